@@ -0,0 +1,17 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by Lesser General Public License that can be found
+// in the LICENSE file.
+
+//! Decode arbitrary bytes with both v3 and v5 decoders.
+//!
+//! Run with `cargo +nightly fuzz run decode_packet` in `codec` directory.
+
+#![no_main]
+
+use hebo_codec::{decode_packet, ProtocolLevel};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ret = decode_packet(ProtocolLevel::V4, data);
+    let _ret = decode_packet(ProtocolLevel::V5, data);
+});
@@ -66,8 +66,7 @@ impl AsMut<Vec<u8>> for BinaryData {
 
 impl DecodePacket for BinaryData {
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
-        let len = ba.read_u16()?;
-        let data = ba.read_bytes(len as usize)?;
+        let data = ba.read_bytes_checked()?;
         Ok(Self(data.to_vec()))
     }
 }
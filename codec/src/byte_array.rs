@@ -61,7 +61,7 @@ impl<'a> ByteArray<'a> {
     /// Returns error if the array has no length bytes.
     pub fn read_byte(&mut self) -> Result<u8, ByteArrayError> {
         let offset = self.offset + 1;
-        if self.offset > self.data.len() {
+        if offset > self.data.len() {
             Err(ByteArrayError::OutOfRangeError)
         } else {
             self.offset = offset;
@@ -102,8 +102,7 @@ impl<'a> ByteArray<'a> {
     ///
     /// Returns error if the array has no length bytes.
     pub fn read_bytes(&mut self, len: usize) -> Result<&[u8], ByteArrayError> {
-        let offset = self.offset + len;
-        if offset > self.data.len() {
+        if len > self.remaining_bytes() {
             log::error!(
                 "read_bytes() reading {} bytes, current offset: {}, data len: {}",
                 len,
@@ -112,11 +111,40 @@ impl<'a> ByteArray<'a> {
             );
             Err(ByteArrayError::OutOfRangeError)
         } else {
-            self.offset = offset;
+            self.offset += len;
             Ok(&self.data[self.offset - len..self.offset])
         }
     }
 
+    /// Read a byte array prefixed with two bytes length from slice.
+    ///
+    /// Length prefix comes from the wire, it is checked against remaining bytes
+    /// before reading. Offset is not changed if the array is truncated.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the array has less bytes than length prefix declares.
+    pub fn read_bytes_checked(&mut self) -> Result<&[u8], ByteArrayError> {
+        let offset = self.offset;
+        let len = self.read_u16()?;
+        if usize::from(len) > self.remaining_bytes() {
+            self.offset = offset;
+            return Err(ByteArrayError::OutOfRangeError);
+        }
+        self.read_bytes(usize::from(len))
+    }
+
+    /// Read an UTF-8 string prefixed with two bytes length from slice.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the array has less bytes than length prefix declares
+    /// or bytes are not valid utf8 string.
+    pub fn read_string_checked(&mut self) -> Result<String, ByteArrayError> {
+        let bytes = self.read_bytes_checked()?;
+        utils::to_utf8_string(bytes).map_err(ByteArrayError::from)
+    }
+
     /// Reset offset value to 0.
     pub fn reset_offset(&mut self) {
         self.offset = 0;
@@ -128,3 +156,40 @@ impl<'a> ByteArray<'a> {
         self.offset
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteArray, ByteArrayError};
+
+    #[test]
+    fn test_read_bytes_out_of_range() {
+        let buf = [1, 2, 3];
+        let mut ba = ByteArray::new(&buf);
+        assert!(matches!(
+            ba.read_bytes(usize::MAX),
+            Err(ByteArrayError::OutOfRangeError)
+        ));
+        assert_eq!(ba.read_bytes(2).unwrap(), [1, 2]);
+        assert!(ba.read_u16().is_err());
+        assert_eq!(ba.remaining_bytes(), 1);
+    }
+
+    #[test]
+    fn test_read_bytes_checked() {
+        let buf = [0, 2, b'h', b'i', 0, 5, b'h'];
+        let mut ba = ByteArray::new(&buf);
+        assert_eq!(ba.read_string_checked().unwrap(), "hi");
+
+        // Length prefix exceeds remaining bytes.
+        assert!(matches!(
+            ba.read_bytes_checked(),
+            Err(ByteArrayError::OutOfRangeError)
+        ));
+        assert_eq!(ba.offset(), 4);
+
+        // Length prefix is truncated.
+        let mut ba = ByteArray::new(&buf[..1]);
+        assert!(ba.read_bytes_checked().is_err());
+        assert_eq!(ba.offset(), 0);
+    }
+}
@@ -40,7 +40,7 @@ pub enum DecodeError {
     InvalidPacketId,
 
     /// Failed to parse variable byte integer.
-    InvalidVarInt,
+    InvalidVarInt(VarIntError),
 
     InvalidBoolData,
 
@@ -110,6 +110,9 @@ pub enum EncodeError {
 
     /// Used in v5 protocol.
     InvalidReasonCode,
+
+    /// Property which may appear at most once is already set, used in v5 protocol.
+    DuplicatedProperty,
 }
 
 impl From<io::Error> for EncodeError {
@@ -158,8 +161,7 @@ impl From<ByteArrayError> for DecodeError {
 }
 
 impl From<VarIntError> for DecodeError {
-    fn from(_err: VarIntError) -> Self {
-        // TODO(Shaohua): Add description
-        Self::InvalidVarInt
+    fn from(err: VarIntError) -> Self {
+        Self::InvalidVarInt(err)
     }
 }
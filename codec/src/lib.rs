@@ -18,6 +18,8 @@ mod connect_flags;
 pub mod error;
 mod header;
 mod keep_alive;
+pub mod packet_decoder;
+pub mod packet_id_pool;
 mod protocol_level;
 mod string_data;
 mod string_pair_data;
@@ -34,6 +36,8 @@ pub use byte_array::ByteArray;
 pub use error::{DecodeError, EncodeError};
 pub use header::{FixedHeader, Packet, PacketType};
 pub use keep_alive::{validate_keep_alive, KeepAlive};
+pub use packet_decoder::{decode_packet, AnyPacket, PacketDecoder, V3Packet, V5Packet};
+pub use packet_id_pool::PacketIdPool;
 pub use protocol_level::ProtocolLevel;
 pub use string_data::StringData;
 pub use string_pair_data::StringPairData;
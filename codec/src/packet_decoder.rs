@@ -0,0 +1,383 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by Lesser General Public License that can be found
+// in the LICENSE file.
+
+//! Split byte stream into complete packets.
+
+#![allow(clippy::module_name_repetitions)]
+
+use crate::{v3, v5, ByteArray, DecodeError, DecodePacket, FixedHeader, PacketType, ProtocolLevel};
+
+/// Get byte length of the first packet in `buf`, including fixed header.
+///
+/// Returns `Ok(None)` if fixed header is incomplete.
+///
+/// # Errors
+///
+/// Returns error if fixed header is malformed.
+pub fn packet_length(buf: &[u8]) -> Result<Option<usize>, DecodeError> {
+    let mut ba = ByteArray::new(buf);
+    match FixedHeader::decode(&mut ba) {
+        Ok(fixed_header) => Ok(Some(ba.offset() + fixed_header.remaining_length())),
+        Err(DecodeError::OutOfRangeError) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Control packets of MQTT v3.1 and v3.1.1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum V3Packet {
+    Connect(v3::ConnectPacket),
+    ConnectAck(v3::ConnectAckPacket),
+    Publish(v3::PublishPacket),
+    PublishAck(v3::PublishAckPacket),
+    PublishReceived(v3::PublishReceivedPacket),
+    PublishRelease(v3::PublishReleasePacket),
+    PublishComplete(v3::PublishCompletePacket),
+    Subscribe(v3::SubscribePacket),
+    SubscribeAck(v3::SubscribeAckPacket),
+    Unsubscribe(v3::UnsubscribePacket),
+    UnsubscribeAck(v3::UnsubscribeAckPacket),
+    PingRequest(v3::PingRequestPacket),
+    PingResponse(v3::PingResponsePacket),
+    Disconnect(v3::DisconnectPacket),
+}
+
+/// Control packets of MQTT v5.0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum V5Packet {
+    Connect(v5::ConnectPacket),
+    ConnectAck(v5::ConnectAckPacket),
+    Publish(v5::PublishPacket),
+    PublishAck(v5::PublishAckPacket),
+    PublishReceived(v5::PublishReceivedPacket),
+    PublishRelease(v5::PublishReleasePacket),
+    PublishComplete(v5::PublishCompletePacket),
+    Subscribe(v5::SubscribePacket),
+    SubscribeAck(v5::SubscribeAckPacket),
+    Unsubscribe(v5::UnsubscribePacket),
+    UnsubscribeAck(v5::UnsubscribeAckPacket),
+    PingRequest(v5::PingRequestPacket),
+    PingResponse(v5::PingResponsePacket),
+    Disconnect(v5::DisconnectPacket),
+    Auth(v5::AuthPacket),
+}
+
+/// Control packet of any protocol level, returned by [`decode_packet`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnyPacket {
+    V3(V3Packet),
+    V5(V5Packet),
+}
+
+/// Decode the first packet in `buf`, with packet type read from its fixed header.
+///
+/// Malformed input, including truncated packets and unknown property types,
+/// is reported as error, this function never panics.
+///
+/// # Errors
+///
+/// Returns error if packet is malformed or its type is not available in `protocol_level`.
+pub fn decode_packet(protocol_level: ProtocolLevel, buf: &[u8]) -> Result<AnyPacket, DecodeError> {
+    let packet_type = FixedHeader::decode(&mut ByteArray::new(buf))?.packet_type();
+    let mut ba = ByteArray::new(buf);
+    match protocol_level {
+        ProtocolLevel::V3 | ProtocolLevel::V4 => decode_v3(packet_type, &mut ba).map(AnyPacket::V3),
+        ProtocolLevel::V5 => decode_v5(packet_type, &mut ba).map(AnyPacket::V5),
+    }
+}
+
+fn decode_v3(packet_type: PacketType, ba: &mut ByteArray) -> Result<V3Packet, DecodeError> {
+    match packet_type {
+        PacketType::Connect => v3::ConnectPacket::decode(ba).map(V3Packet::Connect),
+        PacketType::ConnectAck => v3::ConnectAckPacket::decode(ba).map(V3Packet::ConnectAck),
+        PacketType::Publish { .. } => v3::PublishPacket::decode(ba).map(V3Packet::Publish),
+        PacketType::PublishAck => v3::PublishAckPacket::decode(ba).map(V3Packet::PublishAck),
+        PacketType::PublishReceived => {
+            v3::PublishReceivedPacket::decode(ba).map(V3Packet::PublishReceived)
+        }
+        PacketType::PublishRelease => {
+            v3::PublishReleasePacket::decode(ba).map(V3Packet::PublishRelease)
+        }
+        PacketType::PublishComplete => {
+            v3::PublishCompletePacket::decode(ba).map(V3Packet::PublishComplete)
+        }
+        PacketType::Subscribe => v3::SubscribePacket::decode(ba).map(V3Packet::Subscribe),
+        PacketType::SubscribeAck => v3::SubscribeAckPacket::decode(ba).map(V3Packet::SubscribeAck),
+        PacketType::Unsubscribe => v3::UnsubscribePacket::decode(ba).map(V3Packet::Unsubscribe),
+        PacketType::UnsubscribeAck => {
+            v3::UnsubscribeAckPacket::decode(ba).map(V3Packet::UnsubscribeAck)
+        }
+        PacketType::PingRequest => v3::PingRequestPacket::decode(ba).map(V3Packet::PingRequest),
+        PacketType::PingResponse => v3::PingResponsePacket::decode(ba).map(V3Packet::PingResponse),
+        PacketType::Disconnect => v3::DisconnectPacket::decode(ba).map(V3Packet::Disconnect),
+        // Auth packet is introduced in v5.
+        PacketType::Auth => Err(DecodeError::InvalidPacketType),
+    }
+}
+
+fn decode_v5(packet_type: PacketType, ba: &mut ByteArray) -> Result<V5Packet, DecodeError> {
+    match packet_type {
+        PacketType::Connect => v5::ConnectPacket::decode(ba).map(V5Packet::Connect),
+        PacketType::ConnectAck => v5::ConnectAckPacket::decode(ba).map(V5Packet::ConnectAck),
+        PacketType::Publish { .. } => v5::PublishPacket::decode(ba).map(V5Packet::Publish),
+        PacketType::PublishAck => v5::PublishAckPacket::decode(ba).map(V5Packet::PublishAck),
+        PacketType::PublishReceived => {
+            v5::PublishReceivedPacket::decode(ba).map(V5Packet::PublishReceived)
+        }
+        PacketType::PublishRelease => {
+            v5::PublishReleasePacket::decode(ba).map(V5Packet::PublishRelease)
+        }
+        PacketType::PublishComplete => {
+            v5::PublishCompletePacket::decode(ba).map(V5Packet::PublishComplete)
+        }
+        PacketType::Subscribe => v5::SubscribePacket::decode(ba).map(V5Packet::Subscribe),
+        PacketType::SubscribeAck => v5::SubscribeAckPacket::decode(ba).map(V5Packet::SubscribeAck),
+        PacketType::Unsubscribe => v5::UnsubscribePacket::decode(ba).map(V5Packet::Unsubscribe),
+        PacketType::UnsubscribeAck => {
+            v5::UnsubscribeAckPacket::decode(ba).map(V5Packet::UnsubscribeAck)
+        }
+        PacketType::PingRequest => v5::PingRequestPacket::decode(ba).map(V5Packet::PingRequest),
+        PacketType::PingResponse => v5::PingResponsePacket::decode(ba).map(V5Packet::PingResponse),
+        PacketType::Disconnect => v5::DisconnectPacket::decode(ba).map(V5Packet::Disconnect),
+        PacketType::Auth => v5::AuthPacket::decode(ba).map(V5Packet::Auth),
+    }
+}
+
+/// Accumulates bytes read from network, which may contain a partial packet
+/// or several packets, and pops complete packets one by one.
+///
+/// Popped packets are skipped by advancing a read offset, consumed bytes are
+/// removed from buffer only before more bytes are appended.
+#[derive(Debug, Default, Clone)]
+pub struct PacketDecoder {
+    buf: Vec<u8>,
+
+    /// Offset of the first byte not yet consumed.
+    pos: usize,
+}
+
+impl PacketDecoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+            pos: 0,
+        }
+    }
+
+    /// Get number of buffered bytes not yet consumed.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get mutable reference to inner buffer, so that new bytes can be read into it.
+    ///
+    /// Consumed bytes are removed first.
+    pub fn buffer_mut(&mut self) -> &mut Vec<u8> {
+        self.compact();
+        &mut self.buf
+    }
+
+    /// Append bytes to the end of buffer.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.compact();
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Drop all buffered bytes.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.pos = 0;
+    }
+
+    /// Remove consumed bytes, only bytes of the incomplete packet are moved.
+    fn compact(&mut self) {
+        if self.pos == self.buf.len() {
+            self.buf.clear();
+        } else if self.pos > 0 {
+            self.buf.drain(..self.pos);
+        }
+        self.pos = 0;
+    }
+
+    /// Get byte length of the first packet in buffer, including fixed header.
+    ///
+    /// Returns `Ok(None)` if fixed header is incomplete.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if fixed header is malformed.
+    pub fn packet_length(&self) -> Result<Option<usize>, DecodeError> {
+        packet_length(&self.buf[self.pos..])
+    }
+
+    /// Consume bytes of the first packet in buffer and return them.
+    ///
+    /// Returns `Ok(None)` if more bytes are required to complete that packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if fixed header is malformed.
+    pub fn next_packet_bytes(&mut self) -> Result<Option<&[u8]>, DecodeError> {
+        match self.packet_length()? {
+            Some(len) if len <= self.len() => {
+                let start = self.pos;
+                self.pos += len;
+                Ok(Some(&self.buf[start..self.pos]))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Consume and decode the first packet in buffer.
+    ///
+    /// Returns `Ok(None)` if more bytes are required to complete that packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if packet is malformed or its type is not available in `protocol_level`.
+    pub fn next_packet(
+        &mut self,
+        protocol_level: ProtocolLevel,
+    ) -> Result<Option<AnyPacket>, DecodeError> {
+        self.next_packet_bytes()?
+            .map(|buf| decode_packet(protocol_level, buf))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_packet, AnyPacket, PacketDecoder, V3Packet, V5Packet};
+    use crate::v3::{PingRequestPacket, PublishPacket};
+    use crate::{v5, ByteArray, DecodeError, DecodePacket, EncodePacket, ProtocolLevel, QoS};
+
+    fn publish_bytes(msg: &[u8]) -> Vec<u8> {
+        let packet = PublishPacket::new("hello", QoS::AtMostOnce, msg).unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_one_byte_each_time() {
+        // Remaining length takes two bytes.
+        let msg = vec![b'x'; 200];
+        let buf = publish_bytes(&msg);
+        let mut decoder = PacketDecoder::new();
+        for (index, byte) in buf.iter().enumerate() {
+            decoder.extend_from_slice(&[*byte]);
+            let ret = decoder.next_packet(ProtocolLevel::V4).unwrap();
+            if index + 1 < buf.len() {
+                assert!(ret.is_none());
+            } else {
+                assert!(
+                    matches!(ret, Some(AnyPacket::V3(V3Packet::Publish(packet))) if packet.message() == msg)
+                );
+            }
+        }
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn test_concatenated_packets() {
+        let mut buf = publish_bytes(b"first");
+        PingRequestPacket::new().encode(&mut buf).unwrap();
+        // Partial fixed header of the third packet.
+        let third = publish_bytes(b"third");
+        buf.push(third[0]);
+
+        let mut decoder = PacketDecoder::new();
+        decoder.buffer_mut().extend_from_slice(&buf);
+
+        let packet = decoder.next_packet(ProtocolLevel::V4).unwrap();
+        assert!(
+            matches!(packet, Some(AnyPacket::V3(V3Packet::Publish(packet))) if packet.message() == b"first")
+        );
+        let packet_buf = decoder.next_packet_bytes().unwrap().unwrap();
+        let mut ba = ByteArray::new(packet_buf);
+        assert!(PingRequestPacket::decode(&mut ba).is_ok());
+
+        assert!(decoder.next_packet(ProtocolLevel::V4).unwrap().is_none());
+        assert_eq!(decoder.len(), 1);
+        decoder.extend_from_slice(&third[1..]);
+        // Consumed bytes are removed before appending.
+        assert_eq!(decoder.buffer_mut().len(), third.len());
+        let packet_buf = decoder.next_packet_bytes().unwrap().unwrap();
+        assert_eq!(packet_buf, third);
+        assert!(decoder.is_empty());
+        assert!(decoder.next_packet_bytes().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_malformed_header() {
+        let mut decoder = PacketDecoder::new();
+        decoder.extend_from_slice(&[0x00, 0x00]);
+        assert!(matches!(
+            decoder.next_packet(ProtocolLevel::V4),
+            Err(DecodeError::InvalidPacketType)
+        ));
+
+        let mut decoder = PacketDecoder::new();
+        decoder.extend_from_slice(&[0x30, 0xff, 0xff, 0xff, 0xff]);
+        assert!(matches!(
+            decoder.next_packet_bytes(),
+            Err(DecodeError::InvalidVarInt(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_packet() {
+        let buf = publish_bytes(b"hello");
+        let packet = decode_packet(ProtocolLevel::V4, &buf).unwrap();
+        assert!(
+            matches!(packet, AnyPacket::V3(V3Packet::Publish(packet)) if packet.message() == b"hello")
+        );
+
+        let mut buf = Vec::new();
+        v5::AuthPacket::new().encode(&mut buf).unwrap();
+        assert!(matches!(
+            decode_packet(ProtocolLevel::V5, &buf),
+            Ok(AnyPacket::V5(V5Packet::Auth(_)))
+        ));
+        // Auth packet is not available in v3.
+        assert!(matches!(
+            decode_packet(ProtocolLevel::V4, &buf),
+            Err(DecodeError::InvalidPacketType)
+        ));
+    }
+
+    #[test]
+    fn test_decode_packet_malformed() {
+        // Property length is less than bytes of properties, which used to panic.
+        let inputs: [&[u8]; 2] = [
+            &[0x32, 18, 0, 3, b'a', b'/', b'b', 0, 3, 1, 2, 0, 0, 0, 5],
+            &[0x20, 5, 1, 0, 1, 0x24, 1],
+        ];
+        for input in inputs {
+            assert!(matches!(
+                decode_packet(ProtocolLevel::V5, input),
+                Err(DecodeError::InvalidPropertyValue)
+            ));
+        }
+
+        // Truncated packets.
+        let inputs: [&[u8]; 3] = [&[], &[0x30, 0xff, 0xff, 0xff, 0x7f], &[0x10, 10, 0, 4]];
+        for input in inputs {
+            assert!(decode_packet(ProtocolLevel::V4, input).is_err());
+            assert!(decode_packet(ProtocolLevel::V5, input).is_err());
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by Lesser General Public License that can be found
+// in the LICENSE file.
+
+//! Allocate packet identifiers which are not in flight.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::collections::BTreeSet;
+
+use crate::PacketId;
+
+/// Keeps track of packet ids in use.
+///
+/// New ids are allocated in increasing order and wrap from 65535 back to 1,
+/// skipping ids which are still in use. Packet id 0 is never allocated.
+#[derive(Debug, Default, Clone)]
+pub struct PacketIdPool {
+    last: u16,
+    used: BTreeSet<u16>,
+}
+
+impl PacketIdPool {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            last: 0,
+            used: BTreeSet::new(),
+        }
+    }
+
+    /// Get number of packet ids in use.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// Returns true if all of the 65535 packet ids are in use.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.used.len() >= usize::from(u16::MAX)
+    }
+
+    #[must_use]
+    pub fn contains(&self, packet_id: PacketId) -> bool {
+        self.used.contains(&packet_id.value())
+    }
+
+    /// Allocate the next free packet id after the last allocated one.
+    ///
+    /// Returns `None` if all packet ids are in use.
+    pub fn alloc(&mut self) -> Option<PacketId> {
+        if self.is_full() {
+            return None;
+        }
+        let start = self.last.checked_add(1).unwrap_or(1);
+        let id = first_free(&self.used, start).or_else(|| first_free(&self.used, 1))?;
+        self.used.insert(id);
+        self.last = id;
+        Some(PacketId::new(id))
+    }
+
+    /// Mark `packet_id` as in use, for example when restoring a session.
+    ///
+    /// Returns false if it is 0 or is already in use.
+    pub fn insert(&mut self, packet_id: PacketId) -> bool {
+        packet_id.value() != 0 && self.used.insert(packet_id.value())
+    }
+
+    /// Release `packet_id` so that it can be allocated again.
+    ///
+    /// Returns false if it is not in use.
+    pub fn release(&mut self, packet_id: PacketId) -> bool {
+        self.used.remove(&packet_id.value())
+    }
+
+    /// Release all packet ids.
+    pub fn clear(&mut self) {
+        self.used.clear();
+    }
+}
+
+/// Find the first id not in `used`, in range `start..=u16::MAX`.
+///
+/// Only the run of consecutive used ids beginning at `start` is visited.
+fn first_free(used: &BTreeSet<u16>, start: u16) -> Option<u16> {
+    let mut candidate = start;
+    for &id in used.range(start..) {
+        if id != candidate {
+            break;
+        }
+        candidate = candidate.checked_add(1)?;
+    }
+    Some(candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketIdPool;
+    use crate::PacketId;
+
+    #[test]
+    fn test_alloc_and_release() {
+        let mut pool = PacketIdPool::new();
+        assert_eq!(pool.alloc(), Some(PacketId::new(1)));
+        assert_eq!(pool.alloc(), Some(PacketId::new(2)));
+        assert!(pool.release(PacketId::new(1)));
+        assert!(!pool.release(PacketId::new(1)));
+        // Released ids are not reused until wrapped.
+        assert_eq!(pool.alloc(), Some(PacketId::new(3)));
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.insert(PacketId::new(0)));
+        assert!(!pool.insert(PacketId::new(3)));
+    }
+
+    #[test]
+    fn test_wrap_skips_inflight_ids() {
+        let mut pool = PacketIdPool::new();
+        for id in [1, 2, 3, 5, u16::MAX - 1] {
+            assert!(pool.insert(PacketId::new(id)));
+        }
+        pool.last = u16::MAX - 2;
+
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            let id = pool.alloc().unwrap();
+            assert!(!ids.contains(&id));
+            ids.push(id);
+        }
+        assert_eq!(
+            ids,
+            [
+                PacketId::new(u16::MAX),
+                PacketId::new(4),
+                PacketId::new(6),
+                PacketId::new(7)
+            ]
+        );
+        for id in [1, 2, 3, 5, u16::MAX - 1] {
+            assert!(pool.contains(PacketId::new(id)));
+        }
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let mut pool = PacketIdPool::new();
+        for id in 1..=u16::MAX {
+            assert_eq!(pool.alloc(), Some(PacketId::new(id)));
+        }
+        assert!(pool.is_full());
+        assert_eq!(pool.alloc(), None);
+
+        assert!(pool.release(PacketId::new(100)));
+        assert_eq!(pool.alloc(), Some(PacketId::new(100)));
+        assert_eq!(pool.alloc(), None);
+    }
+}
@@ -12,6 +12,7 @@ use crate::{
 };
 
 /// Text fields within the MQTT Control Packets described later are encoded as UTF-8 strings.
+///
 /// UTF-8 [RFC3629] is an efficient encoding of Unicode [Unicode] characters that
 /// optimizes the encoding of ASCII characters in support of text-based communications.
 ///
@@ -109,8 +110,7 @@ impl AsRef<str> for StringData {
 
 impl DecodePacket for StringData {
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
-        let len = ba.read_u16()?;
-        let s = ba.read_string(len as usize)?;
+        let s = ba.read_string_checked()?;
         Ok(Self(s))
     }
 }
@@ -23,6 +23,7 @@ pub enum TopicError {
     TooManyData,
     InvalidChar,
     ContainsWildChar,
+    InvalidShareName,
 }
 
 impl PartialEq for Topic {
@@ -195,6 +196,21 @@ pub fn validate_pub_topic(topic: &str) -> Result<(), TopicError> {
     }
 }
 
+/// Returns true if topic filter contains wildcard characters `+` or `#`.
+///
+/// # Examples
+///
+/// ```
+/// use hebo_codec::topic;
+/// assert!(topic::is_wildcard_filter("sport/+/player"));
+/// assert!(topic::is_wildcard_filter("$share/group/sport/#"));
+/// assert!(!topic::is_wildcard_filter("sport/tennis/player"));
+/// ```
+#[must_use]
+pub fn is_wildcard_filter(topic: &str) -> bool {
+    topic.split('/').any(|part| part == "+" || part == "#")
+}
+
 // TODO(Shaohua): Impl internal reference to `topic` String.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -220,7 +236,7 @@ pub enum TopicPart {
 
 impl TopicPart {
     fn has_wildcard(s: &str) -> bool {
-        s.contains(|c| c == '#' || c == '+')
+        s.contains(['#', '+'])
     }
 
     /// Returns true if topic is used in broker inner only.
@@ -252,30 +268,67 @@ impl TopicPart {
     }
 }
 
+/// Prefix of shared subscription topic filters, `$share/{ShareName}/{filter}`.
+pub const SHARE_PREFIX: &str = "$share/";
+
 /// Topic/QoS pair.
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SubscribePattern {
     /// Subscribed `topic` contains wildcard characters to match interested topics with patterns.
+    ///
+    /// For shared subscriptions, `$share/{ShareName}/` prefix is not included.
     topic: Topic,
 
     /// Maximum level of `QoS` of packet the Server can send to the Client.
     qos: QoS,
+
+    /// Share name of shared subscription.
+    share_group: Option<String>,
 }
 
 impl SubscribePattern {
+    /// Parse topic filter, including shared subscriptions like `$share/group/sensors/#`.
+    ///
     /// # Errors
     ///
     /// Returns error if `topic` is invalid.
     pub fn parse(topic: &str, qos: QoS) -> Result<Self, TopicError> {
-        let topic = Topic::parse(topic)?;
-        Ok(Self { topic, qos })
+        let Some(shared) = topic.strip_prefix(SHARE_PREFIX) else {
+            let topic = Topic::parse(topic)?;
+            return Ok(Self {
+                topic,
+                qos,
+                share_group: None,
+            });
+        };
+
+        // The ShareName MUST NOT contain the characters "/", "+" or "#",
+        // but MUST be followed by a "/" character. This "/" character MUST be followed
+        // by a Topic Filter [MQTT-4.8.2-2].
+        let (share_group, filter) = shared.split_once('/').ok_or(TopicError::InvalidShareName)?;
+        if share_group.is_empty() || share_group.contains(['+', '#']) {
+            return Err(TopicError::InvalidShareName);
+        }
+        if filter.is_empty() {
+            return Err(TopicError::EmptyTopic);
+        }
+        let topic = Topic::parse(filter)?;
+        Ok(Self {
+            topic,
+            qos,
+            share_group: Some(share_group.to_owned()),
+        })
     }
 
     /// Create a new subscription topic pattern.
     #[must_use]
     #[inline]
     pub const fn new(topic: Topic, qos: QoS) -> Self {
-        Self { topic, qos }
+        Self {
+            topic,
+            qos,
+            share_group: None,
+        }
     }
 
     /// Get topic value.
@@ -291,6 +344,20 @@ impl SubscribePattern {
     pub const fn qos(&self) -> QoS {
         self.qos
     }
+
+    /// Get share name if this is a shared subscription.
+    #[must_use]
+    #[inline]
+    pub fn share_group(&self) -> Option<&str> {
+        self.share_group.as_deref()
+    }
+
+    /// Returns true if this is a shared subscription.
+    #[must_use]
+    #[inline]
+    pub const fn is_shared(&self) -> bool {
+        self.share_group.is_some()
+    }
 }
 
 /// Topic used in publish packet.
@@ -316,6 +383,19 @@ impl PubTopic {
     }
 }
 
+impl PubTopic {
+    /// Decode topic name which may be zero length.
+    ///
+    /// Used by v5 publish packets which refer to topic name with Topic Alias property.
+    pub(crate) fn decode_allow_empty(ba: &mut ByteArray) -> Result<Self, DecodeError> {
+        let s = ba.read_string_checked()?;
+        if !s.is_empty() {
+            validate_pub_topic(&s)?;
+        }
+        Ok(Self(s))
+    }
+}
+
 impl AsRef<str> for PubTopic {
     fn as_ref(&self) -> &str {
         &self.0
@@ -324,8 +404,7 @@ impl AsRef<str> for PubTopic {
 
 impl DecodePacket for PubTopic {
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
-        let len = ba.read_u16()?;
-        let s = ba.read_string(len as usize)?;
+        let s = ba.read_string_checked()?;
         validate_pub_topic(&s)?;
         Ok(Self(s))
     }
@@ -372,8 +451,7 @@ impl AsRef<str> for SubTopic {
 
 impl DecodePacket for SubTopic {
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
-        let len = ba.read_u16()?;
-        let s = ba.read_string(len as usize)?;
+        let s = ba.read_string_checked()?;
         validate_sub_topic(&s)?;
         Ok(Self(s))
     }
@@ -411,4 +489,30 @@ mod tests {
         let t_dev = Topic::parse("dev/#").unwrap();
         assert!(t_dev.is_match("dev/cpu/0"));
     }
+
+    #[test]
+    fn test_parse_shared_subscription() {
+        let pattern = SubscribePattern::parse("$share/groupA/sensors/#", QoS::AtLeastOnce).unwrap();
+        assert!(pattern.is_shared());
+        assert_eq!(pattern.share_group(), Some("groupA"));
+        assert_eq!(pattern.topic().topic(), "sensors/#");
+        assert_eq!(pattern.qos(), QoS::AtLeastOnce);
+
+        let pattern = SubscribePattern::parse("sensors/#", QoS::AtMostOnce).unwrap();
+        assert!(!pattern.is_shared());
+        assert_eq!(pattern.topic().topic(), "sensors/#");
+
+        assert_eq!(
+            SubscribePattern::parse("$share/groupA", QoS::AtMostOnce),
+            Err(TopicError::InvalidShareName)
+        );
+        assert_eq!(
+            SubscribePattern::parse("$share//sensors", QoS::AtMostOnce),
+            Err(TopicError::InvalidShareName)
+        );
+        assert_eq!(
+            SubscribePattern::parse("$share/groupA/", QoS::AtMostOnce),
+            Err(TopicError::EmptyTopic)
+        );
+    }
 }
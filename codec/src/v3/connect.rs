@@ -17,6 +17,7 @@ use crate::{
 /// * `FixedHeader`
 /// * `VariableHeader`
 /// * `Payload`
+///
 /// Note that fixed header part is same in all packets so that we just ignore it.
 ///
 /// Basic struct of `ConnectPacket` is as below:
@@ -463,7 +464,7 @@ impl Packet for ConnectPacket {
 
 #[cfg(test)]
 mod tests {
-    use super::{ByteArray, ConnectPacket, DecodePacket};
+    use super::{ByteArray, ConnectPacket, DecodePacket, EncodePacket};
 
     #[test]
     fn test_decode() {
@@ -476,4 +477,28 @@ mod tests {
         let packet = packet.unwrap();
         assert_eq!(packet.client_id(), "wvPTXcCw");
     }
+
+    #[test]
+    fn test_decode_truncated() {
+        let mut packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        packet.set_username("alice").unwrap();
+        packet.set_password(b"secret").unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        for len in 0..buf.len() {
+            let mut ba = ByteArray::new(&buf[..len]);
+            assert!(ConnectPacket::decode(&mut ba).is_err());
+        }
+
+        // Corrupted length fields shall not panic.
+        for index in 0..buf.len() {
+            for byte in [0x00, 0x01, 0x7f, 0xff] {
+                let mut corrupted = buf.clone();
+                corrupted[index] = byte;
+                let mut ba = ByteArray::new(&corrupted);
+                let _ret = ConnectPacket::decode(&mut ba);
+            }
+        }
+    }
 }
@@ -27,7 +27,7 @@ pub use publish_ack::PublishAckPacket;
 pub use publish_complete::PublishCompletePacket;
 pub use publish_received::PublishReceivedPacket;
 pub use publish_release::PublishReleasePacket;
-pub use subscribe::SubscribePacket;
+pub use subscribe::{SubscribePacket, SubscribeTopic};
 pub use subscribe_ack::{SubscribeAck, SubscribeAckPacket};
 pub use unsubscribe::UnsubscribePacket;
 pub use unsubscribe_ack::UnsubscribeAckPacket;
@@ -189,6 +189,12 @@ impl PublishPacket {
         &self.msg
     }
 
+    /// Replace message payload.
+    pub fn set_message(&mut self, msg: &[u8]) -> &mut Self {
+        self.msg = BytesMut::from(msg);
+        self
+    }
+
     // TODO(Shaohua): Add message related operations.
 
     fn get_fixed_header(&self) -> Result<FixedHeader, VarIntError> {
@@ -219,12 +225,8 @@ impl DecodePacket for PublishPacket {
             return Err(DecodeError::InvalidPacketFlags);
         }
 
-        // In the QoS 1 delivery protocol, the Sender MUST send a PUBLISH Packet
-        // containing this Packet Identifier with QoS=1, DUP=0.
-        // [MQTT-4.3.2-1].
-        if dup && qos == QoS::AtLeastOnce {
-            return Err(DecodeError::InvalidPacketFlags);
-        }
+        // The DUP flag MUST be set to 1 by the Client or Server when it attempts to re-deliver
+        // a PUBLISH Packet [MQTT-3.3.1-1], this also applies to QoS 1 packets.
 
         let topic = PubTopic::decode(ba)?;
         log::info!("topic: {:?}", &topic);
@@ -310,3 +312,70 @@ impl Packet for PublishPacket {
         Ok(fixed_header.bytes() + fixed_header.remaining_length())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PublishPacket;
+    use crate::{ByteArray, DecodeError, DecodePacket, EncodePacket, PacketId, QoS};
+
+    #[test]
+    fn test_decode_header_flags() {
+        let packet = PublishPacket::new("sensors/temperature", QoS::AtMostOnce, b"21").unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        // DUP flag is set in QoS 0 packet.
+        let mut dup_buf = buf.clone();
+        dup_buf[0] |= 0b0000_1000;
+        let mut ba = ByteArray::new(&dup_buf);
+        assert!(matches!(
+            PublishPacket::decode(&mut ba),
+            Err(DecodeError::InvalidPacketFlags)
+        ));
+
+        // Both QoS bits are set.
+        buf[0] |= 0b0000_0110;
+        let mut ba = ByteArray::new(&buf);
+        assert!(matches!(
+            PublishPacket::decode(&mut ba),
+            Err(DecodeError::InvalidPacketFlags)
+        ));
+
+        // DUP flag is set in re-delivered QoS 1 packet.
+        let mut packet =
+            PublishPacket::new("sensors/temperature", QoS::AtLeastOnce, b"21").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        packet.set_dup(true).unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let decoded = PublishPacket::decode(&mut ba).unwrap();
+        assert!(decoded.dup());
+        assert_eq!(decoded.qos(), QoS::AtLeastOnce);
+        assert_eq!(decoded.packet_id(), PacketId::new(1));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let mut packet =
+            PublishPacket::new("sensors/temperature", QoS::AtLeastOnce, b"21").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        for len in 0..buf.len() {
+            let mut ba = ByteArray::new(&buf[..len]);
+            assert!(PublishPacket::decode(&mut ba).is_err());
+        }
+
+        // Corrupted length fields shall not panic.
+        for index in 0..buf.len() {
+            for byte in [0x00, 0x01, 0x7f, 0xff] {
+                let mut corrupted = buf.clone();
+                corrupted[index] = byte;
+                let mut ba = ByteArray::new(&corrupted);
+                let _ret = PublishPacket::decode(&mut ba);
+            }
+        }
+    }
+}
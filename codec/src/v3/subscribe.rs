@@ -32,10 +32,17 @@ impl SubscribeTopic {
     }
 
     /// Get current topic pattern.
+    #[must_use]
     pub fn topic(&self) -> &str {
         self.topic.as_ref()
     }
 
+    /// Update `qos` value.
+    pub fn set_qos(&mut self, qos: QoS) -> &mut Self {
+        self.qos = qos;
+        self
+    }
+
     /// Get current `QoS` value.
     #[must_use]
     pub const fn qos(&self) -> QoS {
@@ -61,7 +61,7 @@ impl SubscribeAckPacket {
 
     /// Create a subscribe ack packet with multiple `acknowledgements`.
     #[must_use]
-    pub fn with_vec(packet_id: PacketId, acknowledgements: Vec<SubscribeAck>) -> Self {
+    pub const fn with_vec(packet_id: PacketId, acknowledgements: Vec<SubscribeAck>) -> Self {
         Self {
             packet_id,
             acknowledgements,
@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet,
@@ -80,7 +79,7 @@ impl EncodePacket for AuthPacket {
         let old_len = buf.len();
 
         let remaining_length = ReasonCode::bytes() + self.properties.bytes();
-        let fixed_header = FixedHeader::new(PacketType::PingRequest, remaining_length)?;
+        let fixed_header = FixedHeader::new(PacketType::Auth, remaining_length)?;
         fixed_header.encode(buf)?;
         self.reason_code.encode(buf)?;
         self.properties.encode(buf)?;
@@ -105,14 +104,7 @@ impl DecodePacket for AuthPacket {
             return Err(DecodeError::InvalidReasonCode);
         }
 
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) = check_property_type_list(properties.props(), AUTH_PROPERTIES) {
-            log::error!(
-                "v5/AuthPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, AUTH_PROPERTIES)?;
 
         Ok(Self {
             reason_code,
@@ -128,7 +120,7 @@ impl Packet for AuthPacket {
 
     fn bytes(&self) -> Result<usize, VarIntError> {
         let remaining_length = ReasonCode::bytes() + self.properties.bytes();
-        let fixed_header = FixedHeader::new(PacketType::PingRequest, remaining_length)?;
+        let fixed_header = FixedHeader::new(PacketType::Auth, remaining_length)?;
 
         Ok(fixed_header.bytes() + remaining_length)
     }
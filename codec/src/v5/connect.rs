@@ -4,7 +4,6 @@
 
 use std::convert::TryFrom;
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType};
 use crate::base::PROTOCOL_NAME;
 use crate::connect_flags::ConnectFlags;
@@ -191,6 +190,7 @@ pub const CONNECT_PROPERTIES: &[PropertyType] = &[
     PropertyType::ReceiveMaximum,
     PropertyType::MaximumPacketSize,
     PropertyType::TopicAliasMaximum,
+    PropertyType::RequestResponseInformation,
     PropertyType::RequestProblemInformation,
     PropertyType::UserProperty,
     PropertyType::AuthenticationMethod,
@@ -220,6 +220,7 @@ impl ConnectPacket {
         let client_id = StringData::from(client_id)?;
         Ok(Self {
             protocol_name,
+            protocol_level: ProtocolLevel::V5,
             keep_alive: KeepAlive::new(60),
             client_id,
             ..Self::default()
@@ -434,11 +435,13 @@ impl ConnectPacket {
             + ProtocolLevel::bytes()
             + ConnectFlags::bytes()
             + KeepAlive::bytes()
+            + self.properties.bytes()
             + self.client_id.bytes();
 
         // Check username/password/topic/message.
         if self.connect_flags.will() {
             assert!(self.will_topic.is_some());
+            remaining_length += self.will_properties.bytes();
             if let Some(will_topic) = &self.will_topic {
                 remaining_length += will_topic.bytes();
             }
@@ -468,12 +471,14 @@ impl EncodePacket for ConnectPacket {
         self.protocol_level.encode(v)?;
         self.connect_flags.encode(v)?;
         self.keep_alive.encode(v)?;
+        self.properties.encode(v)?;
 
         // Write payload
         self.client_id.encode(v)?;
 
         if self.connect_flags.will() {
             assert!(self.will_topic.is_some());
+            self.will_properties.encode(v)?;
             if let Some(will_topic) = &self.will_topic {
                 will_topic.encode(v)?;
             }
@@ -532,22 +537,10 @@ impl DecodePacket for ConnectPacket {
         let keep_alive = KeepAlive::decode(ba)?;
         validate_keep_alive(keep_alive)?;
 
-        let properties = Properties::decode(ba);
-        let properties = match properties {
-            Ok(properties) => properties,
-            Err(err) => {
-                log::error!("err: {:?}", err);
-                return Err(DecodeError::InvalidPropertyType);
-            }
-        };
-        if let Err(property_type) = check_property_type_list(properties.props(), CONNECT_PROPERTIES)
-        {
-            log::error!(
-                "v5/ConnectPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, CONNECT_PROPERTIES).map_err(|err| {
+            log::error!("err: {:?}", err);
+            DecodeError::InvalidPropertyType
+        })?;
 
         let client_id = StringData::decode(ba).map_err(|_err| DecodeError::InvalidClientId)?;
         if client_id.is_empty() && !connect_flags.clean_session() {
@@ -557,19 +550,10 @@ impl DecodePacket for ConnectPacket {
         validate_client_id(client_id.as_ref())?;
 
         let will_properties = if connect_flags.will() {
-            Properties::decode(ba)?
+            Properties::decode_with_types(ba, CONNECT_WILL_PROPERTIES)?
         } else {
             Properties::new()
         };
-        if let Err(property_type) =
-            check_property_type_list(will_properties.props(), CONNECT_WILL_PROPERTIES)
-        {
-            log::error!(
-                "v5/ConnectPacket: property type {:?} cannot be used in will properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
 
         let will_topic = if connect_flags.will() {
             Some(PubTopic::decode(ba)?)
@@ -623,7 +607,9 @@ impl Packet for ConnectPacket {
 
 #[cfg(test)]
 mod tests {
-    use super::{ByteArray, ConnectPacket, DecodePacket};
+    use super::{ByteArray, ConnectPacket, DecodePacket, EncodePacket, Packet, ProtocolLevel};
+    use crate::v5::Property;
+    use crate::{BoolData, DecodeError, StringPairData, U32Data};
 
     #[test]
     fn test_decode() {
@@ -637,4 +623,124 @@ mod tests {
         let packet = packet.unwrap();
         assert_eq!(packet.client_id(), "wvPTXcCw");
     }
+
+    #[test]
+    fn test_encode() {
+        let packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let packet = ConnectPacket::decode(&mut ba).unwrap();
+        assert_eq!(packet.protocol_level(), ProtocolLevel::V5);
+        assert_eq!(packet.client_id(), "wvPTXcCw");
+    }
+
+    #[test]
+    fn test_decode_request_response_information() {
+        let mut packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        packet
+            .properties_mut()
+            .push(Property::RequestResponseInformation(BoolData::new(true)))
+            .unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let packet = ConnectPacket::decode(&mut ba).unwrap();
+        assert_eq!(
+            packet.properties().props(),
+            [Property::RequestResponseInformation(BoolData::new(true))]
+        );
+    }
+
+    fn will_packet(property: Property) -> Vec<u8> {
+        let mut packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        packet.set_will(true);
+        packet.set_will_topic("will/status").unwrap();
+        packet.set_will_message(b"offline").unwrap();
+        packet.will_properties_mut().push(property).unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_decode_will_properties() {
+        let buf = will_packet(Property::WillDelayInterval(U32Data::new(10)));
+        let mut ba = ByteArray::new(&buf);
+        let packet = ConnectPacket::decode(&mut ba).unwrap();
+        assert_eq!(
+            packet.will_properties().props(),
+            [Property::WillDelayInterval(U32Data::new(10))]
+        );
+
+        // Session Expiry Interval is only allowed in connect properties.
+        let buf = will_packet(Property::SessionExpiryInterval(U32Data::new(10)));
+        let mut ba = ByteArray::new(&buf);
+        assert!(matches!(
+            ConnectPacket::decode(&mut ba),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let mut packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        packet.set_username(Some("alice")).unwrap();
+        packet.set_password(Some(b"secret")).unwrap();
+        packet
+            .properties_mut()
+            .push(Property::SessionExpiryInterval(U32Data::new(60)))
+            .unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        for len in 0..buf.len() {
+            let mut ba = ByteArray::new(&buf[..len]);
+            assert!(ConnectPacket::decode(&mut ba).is_err());
+        }
+
+        // Corrupted length fields shall not panic.
+        for index in 0..buf.len() {
+            for byte in [0x00, 0x01, 0x7f, 0xff] {
+                let mut corrupted = buf.clone();
+                corrupted[index] = byte;
+                let mut ba = ByteArray::new(&corrupted);
+                let _ret = ConnectPacket::decode(&mut ba);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_properties() {
+        let mut packet = ConnectPacket::new("wvPTXcCw").unwrap();
+        packet
+            .properties_mut()
+            .push(Property::SessionExpiryInterval(U32Data::new(60)))
+            .unwrap();
+        packet
+            .properties_mut()
+            .push(Property::UserProperty(
+                StringPairData::new("key", "value").unwrap(),
+            ))
+            .unwrap();
+        packet.set_will(true);
+        packet.set_will_topic("will/wvPTXcCw").unwrap();
+        packet.set_will_message(b"offline").unwrap();
+        packet
+            .will_properties_mut()
+            .push(Property::WillDelayInterval(U32Data::new(30)))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let bytes_written = packet.encode(&mut buf).unwrap();
+        assert_eq!(bytes_written, buf.len());
+        assert_eq!(packet.bytes().unwrap(), buf.len());
+        let mut ba = ByteArray::new(&buf);
+        let decoded = ConnectPacket::decode(&mut ba).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(decoded.properties().len(), 2);
+        assert_eq!(decoded.will_properties().len(), 1);
+        assert_eq!(decoded.will_topic(), Some("will/wvPTXcCw"));
+        assert_eq!(decoded.will_message(), b"offline");
+    }
 }
@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet,
@@ -185,17 +184,7 @@ impl DecodePacket for ConnectAckPacket {
             log::error!("Invalid reason code {:?}", reason_code);
             return Err(DecodeError::InvalidReasonCode);
         }
-        let properties = Properties::decode(ba)?;
-
-        if let Err(property_type) =
-            check_property_type_list(properties.props(), CONNECT_ACK_PROPERTIES)
-        {
-            log::error!(
-                "v5/ConnectAckPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, CONNECT_ACK_PROPERTIES)?;
 
         Ok(Self {
             session_present,
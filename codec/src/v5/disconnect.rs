@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet,
@@ -90,6 +89,20 @@ pub const DISCONNECT_REASONS: &[ReasonCode] = &[
     ReasonCode::TopicNameInvalid,
     ReasonCode::ReceiveMaximumExceeded,
     ReasonCode::TopicAliasInvalid,
+    ReasonCode::PacketTooLarge,
+    ReasonCode::MessageRateTooHigh,
+    ReasonCode::QuotaExceeded,
+    ReasonCode::AdministrativeAction,
+    ReasonCode::PayloadFormatInvalid,
+    ReasonCode::RetainNotSupported,
+    ReasonCode::QoSNotSupported,
+    ReasonCode::UseAnotherServer,
+    ReasonCode::ServerMoved,
+    ReasonCode::SharedSubscriptionNotSupported,
+    ReasonCode::ConnectionRateExceeded,
+    ReasonCode::MaximumConnectTime,
+    ReasonCode::SubscriptionIdentifiersNotSupported,
+    ReasonCode::WildcardSubscriptionsNotSupported,
 ];
 
 /// Properties available in disconnect packet.
@@ -134,16 +147,7 @@ impl DecodePacket for DisconnectPacket {
             return Err(DecodeError::InvalidReasonCode);
         }
 
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) =
-            check_property_type_list(properties.props(), DISCONNECT_PROPERTIES)
-        {
-            log::error!(
-                "v5/DisconnectPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, DISCONNECT_PROPERTIES)?;
 
         Ok(Self {
             reason_code,
@@ -39,7 +39,7 @@ pub use publish_release::{
     PublishReleasePacket, PUBLISH_RELEASE_PROPERTIES, PUBLISH_RELEASE_REASONS,
 };
 pub use reason_code::ReasonCode;
-pub use subscribe::SubscribePacket;
+pub use subscribe::{RetainHandling, SubscribePacket, SubscribeTopic};
 pub use subscribe_ack::{SubscribeAckPacket, SUBSCRIBE_ACK_PROPERTIES, SUBSCRIBE_REASONS};
 pub use unsubscribe::{UnsubscribePacket, UNSUBSCRIBE_PROPERTIES};
 pub use unsubscribe_ack::{UnsubscribeAckPacket, UNSUBSCRIBE_ACK_PROPERTIES, UNSUBSCRIBE_REASONS};
@@ -742,6 +742,15 @@ impl Property {
     }
 }
 
+/// Report malformed value of property as `InvalidPropertyValue`, other errors
+/// like truncated bytes are kept as is.
+const fn invalid_value(err: DecodeError) -> DecodeError {
+    match err {
+        DecodeError::InvalidBoolData | DecodeError::InvalidQoS => DecodeError::InvalidPropertyValue,
+        err => err,
+    }
+}
+
 impl DecodePacket for Property {
     #[allow(clippy::too_many_lines)]
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
@@ -762,14 +771,18 @@ impl DecodePacket for Property {
             }
             PropertyType::MaximumPacketSize => {
                 let max = U32Data::decode(ba)?;
+                // It is a Protocol Error for the value to be set to zero.
+                if max.value() == 0 {
+                    return Err(DecodeError::InvalidPropertyValue);
+                }
                 Ok(Self::MaximumPacketSize(max))
             }
             PropertyType::RequestResponseInformation => {
-                let on = BoolData::decode(ba)?;
+                let on = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::RequestResponseInformation(on))
             }
             PropertyType::RequestProblemInformation => {
-                let on = BoolData::decode(ba)?;
+                let on = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::RequestProblemInformation(on))
             }
             PropertyType::UserProperty => {
@@ -789,7 +802,7 @@ impl DecodePacket for Property {
                 Ok(Self::WillDelayInterval(interval))
             }
             PropertyType::PayloadFormatIndicator => {
-                let on = BoolData::decode(ba)?;
+                let on = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::PayloadFormatIndicator(on))
             }
             PropertyType::MessageExpiryInterval => {
@@ -809,14 +822,14 @@ impl DecodePacket for Property {
                 Ok(Self::CorrelationData(data))
             }
             PropertyType::MaximumQoS => {
-                let qos = QoS::decode(ba)?;
+                let qos = QoS::decode(ba).map_err(invalid_value)?;
                 if qos != QoS::AtLeastOnce && qos != QoS::AtMostOnce {
                     return Err(DecodeError::InvalidPropertyValue);
                 }
                 Ok(Self::MaximumQoS(qos))
             }
             PropertyType::RetainAvailable => {
-                let available = BoolData::decode(ba)?;
+                let available = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::RetainAvailable(available))
             }
             PropertyType::AssignedClientIdentifier => {
@@ -825,15 +838,15 @@ impl DecodePacket for Property {
                 Ok(Self::AssignedClientIdentifier(client_id))
             }
             PropertyType::WildcardSubscriptionAvailable => {
-                let available = BoolData::decode(ba)?;
+                let available = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::WildcardSubscriptionAvailable(available))
             }
             PropertyType::SubscriptionIdentifierAvailable => {
-                let available = BoolData::decode(ba)?;
+                let available = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::SubscriptionIdentifierAvailable(available))
             }
             PropertyType::SharedSubscriptionAvailable => {
-                let available = BoolData::decode(ba)?;
+                let available = BoolData::decode(ba).map_err(invalid_value)?;
                 Ok(Self::SharedSubscriptionAvailable(available))
             }
             PropertyType::ServerKeepAlive => {
@@ -852,6 +865,14 @@ impl DecodePacket for Property {
                 let alias = U16Data::decode(ba)?;
                 Ok(Self::TopicAlias(alias))
             }
+            PropertyType::TopicAliasMaximum => {
+                let max = U16Data::decode(ba)?;
+                Ok(Self::TopicAliasMaximum(max))
+            }
+            PropertyType::ReasonString => {
+                let reason = StringData::decode(ba)?;
+                Ok(Self::ReasonString(reason))
+            }
             PropertyType::SubscriptionIdentifier => {
                 let id = VarInt::decode(ba)?;
                 if id.value() == 0 {
@@ -859,7 +880,6 @@ impl DecodePacket for Property {
                 }
                 Ok(Self::SubscriptionIdentifier(id))
             }
-            _ => unimplemented!(),
         }
     }
 }
@@ -918,14 +938,41 @@ impl Properties {
         Self::default()
     }
 
+    /// Decode property list of a packet, which may only contain properties in `types`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidPropertyType` if a property is not in `types`, or it appears
+    /// more than once and is not in `MULTIPLE_PROPERTIES`.
+    pub fn decode_with_types(
+        ba: &mut ByteArray,
+        types: &[PropertyType],
+    ) -> Result<Self, DecodeError> {
+        let properties = Self::decode(ba)?;
+        if let Err(property_type) = check_property_type_list(properties.props(), types) {
+            log::error!(
+                "v5: property type {:?} cannot be used in this packet!",
+                property_type
+            );
+            return Err(DecodeError::InvalidPropertyType);
+        }
+        Ok(properties)
+    }
+
     /// Get byte length of property list.
     ///
     /// # Panics
     /// Raise panic if bytes of properties is larger than 256MB.
     #[must_use]
     pub fn bytes(&self) -> usize {
-        let len = VarInt::from(self.len()).unwrap();
-        len.bytes() + self.0.iter().map(Property::bytes).sum::<usize>()
+        let props_bytes = self.props_bytes();
+        let len = VarInt::from(props_bytes).unwrap();
+        len.bytes() + props_bytes
+    }
+
+    /// Get byte length of properties, without the length prefix.
+    fn props_bytes(&self) -> usize {
+        self.0.iter().map(Property::bytes).sum::<usize>()
     }
 
     /// Get length of property list.
@@ -987,6 +1034,256 @@ impl Properties {
     pub fn remove(&mut self, index: usize) -> Result<Property, EncodeError> {
         Ok(self.0.remove(index))
     }
+
+    /// Retain only the properties specified by the predicate, in their original order.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Property) -> bool,
+    {
+        self.0.retain(f);
+    }
+
+    /// Append a property which may appear at most once in a packet.
+    fn set_once(&mut self, property: Property) -> Result<&mut Self, EncodeError> {
+        let property_type = property.property_type();
+        if self.0.iter().any(|p| p.property_type() == property_type) {
+            return Err(EncodeError::DuplicatedProperty);
+        }
+        self.append(property)
+    }
+
+    /// Append a property if byte length of property list is still in range.
+    fn append(&mut self, property: Property) -> Result<&mut Self, EncodeError> {
+        VarInt::from(self.props_bytes() + property.bytes())?;
+        self.0.push(property);
+        Ok(self)
+    }
+
+    /// Set Payload Format Indicator, true if payload is UTF-8 encoded character data.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_payload_format_indicator(&mut self, utf8: bool) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::PayloadFormatIndicator(BoolData::new(utf8)))
+    }
+
+    /// Set Message Expiry Interval in seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_message_expiry_interval(&mut self, interval: u32) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::MessageExpiryInterval(U32Data::new(interval)))
+    }
+
+    /// Set Content Type of payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `content_type` is too long.
+    pub fn set_content_type(&mut self, content_type: &str) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::ContentType(StringData::from(content_type)?))
+    }
+
+    /// Set Response Topic of request message.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `topic` is invalid.
+    pub fn set_response_topic(&mut self, topic: &str) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::ResponseTopic(PubTopic::new(topic)?))
+    }
+
+    /// Set Correlation Data of request message.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `data` is too long.
+    pub fn set_correlation_data(&mut self, data: &[u8]) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::CorrelationData(BinaryData::from_slice(data)?))
+    }
+
+    /// Add a Subscription Identifier, which is in range 1..=268,435,455.
+    ///
+    /// Subscribe packet accepts only one of it, while publish packet may contain more.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `id` is out of range.
+    pub fn add_subscription_identifier(&mut self, id: usize) -> Result<&mut Self, EncodeError> {
+        if id == 0 {
+            return Err(EncodeError::InvalidData);
+        }
+        self.append(Property::SubscriptionIdentifier(VarInt::from(id)?))
+    }
+
+    /// Set Session Expiry Interval in seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_session_expiry_interval(&mut self, interval: u32) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::SessionExpiryInterval(U32Data::new(interval)))
+    }
+
+    /// Set client id assigned by server.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `client_id` is too long.
+    pub fn set_assigned_client_identifier(
+        &mut self,
+        client_id: &str,
+    ) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::AssignedClientIdentifier(StringData::from(
+            client_id,
+        )?))
+    }
+
+    /// Set Server Keep Alive in seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_server_keep_alive(&mut self, keep_alive: u16) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::ServerKeepAlive(U16Data::new(keep_alive)))
+    }
+
+    /// Set Will Delay Interval in seconds.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_will_delay_interval(&mut self, interval: u32) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::WillDelayInterval(U32Data::new(interval)))
+    }
+
+    /// Set Reason String, a human readable string for diagnostics.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `reason` is too long.
+    pub fn set_reason_string(&mut self, reason: &str) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::ReasonString(StringData::from(reason)?))
+    }
+
+    /// Set Receive Maximum, which is not zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `receive_maximum` is zero.
+    pub fn set_receive_maximum(&mut self, receive_maximum: u16) -> Result<&mut Self, EncodeError> {
+        if receive_maximum == 0 {
+            return Err(EncodeError::InvalidData);
+        }
+        self.set_once(Property::ReceiveMaximum(U16Data::new(receive_maximum)))
+    }
+
+    /// Set Topic Alias Maximum.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_topic_alias_maximum(&mut self, maximum: u16) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::TopicAliasMaximum(U16Data::new(maximum)))
+    }
+
+    /// Set Topic Alias, which is not zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `alias` is zero.
+    pub fn set_topic_alias(&mut self, alias: u16) -> Result<&mut Self, EncodeError> {
+        if alias == 0 {
+            return Err(EncodeError::InvalidData);
+        }
+        self.set_once(Property::TopicAlias(U16Data::new(alias)))
+    }
+
+    /// Set Maximum `QoS`, which is either `QoS` 0 or `QoS` 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `qos` is `QoS` 2.
+    pub fn set_maximum_qos(&mut self, qos: QoS) -> Result<&mut Self, EncodeError> {
+        if qos == QoS::ExactOnce {
+            return Err(EncodeError::InvalidData);
+        }
+        self.set_once(Property::MaximumQoS(qos))
+    }
+
+    /// Set Retain Available.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_retain_available(&mut self, available: bool) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::RetainAvailable(BoolData::new(available)))
+    }
+
+    /// Add a User Property, which may appear more than once.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `key` or `value` is too long.
+    pub fn add_user_property(&mut self, key: &str, value: &str) -> Result<&mut Self, EncodeError> {
+        self.append(Property::UserProperty(StringPairData::new(key, value)?))
+    }
+
+    /// Set Maximum Packet Size, which is not zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set or `size` is zero.
+    pub fn set_maximum_packet_size(&mut self, size: u32) -> Result<&mut Self, EncodeError> {
+        if size == 0 {
+            return Err(EncodeError::InvalidData);
+        }
+        self.set_once(Property::MaximumPacketSize(U32Data::new(size)))
+    }
+
+    /// Set Wildcard Subscription Available.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_wildcard_subscription_available(
+        &mut self,
+        available: bool,
+    ) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::WildcardSubscriptionAvailable(BoolData::new(
+            available,
+        )))
+    }
+
+    /// Set Subscription Identifier Available.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_subscription_identifier_available(
+        &mut self,
+        available: bool,
+    ) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::SubscriptionIdentifierAvailable(BoolData::new(
+            available,
+        )))
+    }
+
+    /// Set Shared Subscription Available.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if this property is already set.
+    pub fn set_shared_subscription_available(
+        &mut self,
+        available: bool,
+    ) -> Result<&mut Self, EncodeError> {
+        self.set_once(Property::SharedSubscriptionAvailable(BoolData::new(
+            available,
+        )))
+    }
 }
 
 impl DecodePacket for Properties {
@@ -1001,7 +1298,10 @@ impl DecodePacket for Properties {
         let mut properties = Vec::new();
         while remaining_length > 0 {
             let property = Property::decode(ba)?;
-            remaining_length -= property.bytes();
+            // Property Length is less than bytes of properties.
+            remaining_length = remaining_length
+                .checked_sub(property.bytes())
+                .ok_or(DecodeError::InvalidPropertyValue)?;
             properties.push(property);
         }
 
@@ -1011,7 +1311,8 @@ impl DecodePacket for Properties {
 
 impl EncodePacket for Properties {
     fn encode(&self, buf: &mut Vec<u8>) -> Result<usize, EncodeError> {
-        let len = VarInt::from(self.len())?;
+        // Property Length is the number of bytes of properties, not the number of properties.
+        let len = VarInt::from(self.props_bytes())?;
         let mut bytes_written = len.bytes();
         len.encode(buf)?;
         for property in &self.0 {
@@ -1021,3 +1322,175 @@ impl EncodePacket for Properties {
         Ok(bytes_written)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{Properties, Property, PropertyType};
+    use crate::v5::publish::PUBLISH_PROPERTIES;
+    use crate::{
+        BinaryData, BoolData, ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket,
+        PubTopic, QoS, StringData, StringPairData, U16Data, U32Data, VarInt,
+    };
+
+    #[test]
+    fn test_decode_all_types() {
+        let properties = [
+            Property::PayloadFormatIndicator(BoolData::new(true)),
+            Property::MessageExpiryInterval(U32Data::new(60)),
+            Property::ContentType(StringData::from("text/plain").unwrap()),
+            Property::ResponseTopic(PubTopic::new("response").unwrap()),
+            Property::CorrelationData(BinaryData::from_slice(b"42").unwrap()),
+            Property::SubscriptionIdentifier(VarInt::from(300).unwrap()),
+            Property::SessionExpiryInterval(U32Data::new(3600)),
+            Property::AssignedClientIdentifier(StringData::from("client-1").unwrap()),
+            Property::ServerKeepAlive(U16Data::new(30)),
+            Property::AuthenticationMethod(StringData::from("SCRAM-SHA-1").unwrap()),
+            Property::AuthenticationData(BinaryData::from_slice(b"data").unwrap()),
+            Property::RequestProblemInformation(BoolData::new(false)),
+            Property::WillDelayInterval(U32Data::new(5)),
+            Property::RequestResponseInformation(BoolData::new(true)),
+            Property::ResponseInformation(StringData::from("info").unwrap()),
+            Property::ServerReference(StringData::from("other-server").unwrap()),
+            Property::ReasonString(StringData::from("reason").unwrap()),
+            Property::ReceiveMaximum(U16Data::new(10)),
+            Property::TopicAliasMaximum(U16Data::new(10)),
+            Property::TopicAlias(U16Data::new(1)),
+            Property::MaximumQoS(QoS::AtLeastOnce),
+            Property::RetainAvailable(BoolData::new(false)),
+            Property::UserProperty(StringPairData::new("key", "value").unwrap()),
+            Property::MaximumPacketSize(U32Data::new(1024)),
+            Property::WildcardSubscriptionAvailable(BoolData::new(false)),
+            Property::SubscriptionIdentifierAvailable(BoolData::new(true)),
+            Property::SharedSubscriptionAvailable(BoolData::new(false)),
+        ];
+
+        // Every property type is covered.
+        let types: Vec<PropertyType> = (0..=u8::MAX)
+            .filter_map(|byte| PropertyType::try_from(byte).ok())
+            .collect();
+        assert_eq!(types.len(), properties.len());
+
+        for property in properties {
+            assert!(types.contains(&property.property_type()));
+            let mut buf = Vec::new();
+            let bytes = property.encode(&mut buf).unwrap();
+            assert_eq!(bytes, property.bytes());
+            let mut ba = ByteArray::new(&buf);
+            assert_eq!(Property::decode(&mut ba).unwrap(), property);
+            assert_eq!(ba.remaining_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_value() {
+        let inputs: [&[u8]; 6] = [
+            &[PropertyType::MaximumQoS as u8, 2],
+            &[PropertyType::MaximumQoS as u8, 3],
+            &[PropertyType::RetainAvailable as u8, 2],
+            &[PropertyType::PayloadFormatIndicator as u8, 0xff],
+            &[PropertyType::MaximumPacketSize as u8, 0, 0, 0, 0],
+            &[PropertyType::SubscriptionIdentifier as u8, 0],
+        ];
+        for input in inputs {
+            let mut ba = ByteArray::new(input);
+            assert!(matches!(
+                Property::decode(&mut ba),
+                Err(DecodeError::InvalidPropertyValue)
+            ));
+        }
+
+        let mut ba = ByteArray::new(&[0x00, 0x01]);
+        assert!(matches!(
+            Property::decode(&mut ba),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+
+        // Truncated value.
+        let mut ba = ByteArray::new(&[PropertyType::TopicAliasMaximum as u8, 0x01]);
+        assert!(matches!(
+            Property::decode(&mut ba),
+            Err(DecodeError::OutOfRangeError)
+        ));
+    }
+
+    fn decode_publish_properties(props: Vec<Property>) -> Result<Properties, DecodeError> {
+        let mut properties = Properties::new();
+        for property in props {
+            properties.push(property).unwrap();
+        }
+        let mut buf = Vec::new();
+        properties.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        Properties::decode_with_types(&mut ba, PUBLISH_PROPERTIES)
+    }
+
+    #[test]
+    fn test_decode_with_types() {
+        let user_property = Property::UserProperty(StringPairData::new("key", "value").unwrap());
+        let expiry = Property::MessageExpiryInterval(U32Data::new(60));
+
+        // User property may appear more than once.
+        let properties =
+            decode_publish_properties(vec![user_property.clone(), user_property, expiry.clone()])
+                .unwrap();
+        assert_eq!(properties.len(), 3);
+
+        assert!(matches!(
+            decode_publish_properties(vec![expiry.clone(), expiry]),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+        assert!(matches!(
+            decode_publish_properties(vec![Property::SessionExpiryInterval(U32Data::new(60))]),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+    }
+
+    #[test]
+    fn test_typed_setters() {
+        let mut properties = Properties::new();
+        properties
+            .set_session_expiry_interval(60)
+            .unwrap()
+            .set_topic_alias(1)
+            .unwrap()
+            .add_user_property("key", "value")
+            .unwrap()
+            .add_user_property("key", "value")
+            .unwrap();
+        assert_eq!(properties.len(), 4);
+        assert_eq!(
+            properties.props()[0],
+            Property::SessionExpiryInterval(U32Data::new(60))
+        );
+
+        // Single-occurrence properties are rejected if already set.
+        assert!(matches!(
+            properties.set_session_expiry_interval(30),
+            Err(EncodeError::DuplicatedProperty)
+        ));
+        assert!(matches!(
+            properties.set_topic_alias(2),
+            Err(EncodeError::DuplicatedProperty)
+        ));
+        assert_eq!(properties.len(), 4);
+
+        // Invalid values.
+        assert!(properties.set_receive_maximum(0).is_err());
+        assert!(properties.set_maximum_qos(QoS::ExactOnce).is_err());
+        assert!(properties.add_subscription_identifier(0).is_err());
+        assert!(properties.set_response_topic("sensors/#").is_err());
+        let long_string = "x".repeat(usize::from(u16::MAX) + 1);
+        assert!(properties.set_reason_string(&long_string).is_err());
+        assert_eq!(properties.len(), 4);
+
+        // Subscription identifiers may appear more than once in publish packets.
+        properties
+            .add_subscription_identifier(1)
+            .unwrap()
+            .add_subscription_identifier(2)
+            .unwrap();
+        assert_eq!(properties.len(), 6);
+    }
+}
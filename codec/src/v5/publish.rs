@@ -4,11 +4,10 @@
 
 use std::io::Write;
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
-    PacketType, PubTopic, QoS, VarIntError,
+    PacketType, PubTopic, QoS, TopicError, VarIntError,
 };
 
 /// `PublishPacket` is used to transport application messages from the Client to the Server,
@@ -300,7 +299,15 @@ impl PublishPacket {
         Ok(self)
     }
 
+    /// Clear topic name, so that topic name is referred by Topic Alias property.
+    pub fn clear_topic(&mut self) -> &mut Self {
+        self.topic = PubTopic::default();
+        self
+    }
+
     /// Get current topic.
+    ///
+    /// Topic name may be empty if Topic Alias property is set.
     #[must_use]
     pub fn topic(&self) -> &str {
         self.topic.as_ref()
@@ -323,11 +330,14 @@ impl PublishPacket {
         &self.msg
     }
 
+    /// Replace message payload.
+    pub fn set_message(&mut self, msg: &[u8]) -> &mut Self {
+        self.msg = msg.to_vec();
+        self
+    }
+
     fn get_fixed_header(&self) -> Result<FixedHeader, VarIntError> {
-        // TODO(Shaohua): Add properties.bytes()
-        let mut remaining_length = self.topic.bytes()
-            //+ self.properties.bytes()
-            + self.msg.len();
+        let mut remaining_length = self.topic.bytes() + self.properties.bytes() + self.msg.len();
         if self.qos != QoS::AtMostOnce {
             remaining_length += PacketId::bytes();
         }
@@ -353,14 +363,11 @@ impl DecodePacket for PublishPacket {
             return Err(DecodeError::InvalidPacketFlags);
         }
 
-        // In the QoS 1 delivery protocol, the Sender MUST send a PUBLISH Packet
-        // containing this Packet Identifier with QoS=1, DUP=0.
-        // [MQTT-4.3.2-1].
-        if dup && qos == QoS::AtLeastOnce {
-            return Err(DecodeError::InvalidPacketFlags);
-        }
+        // The DUP flag MUST be set to 1 by the Client or Server when it attempts to re-deliver
+        // a PUBLISH Packet [MQTT-3.3.1-1], this also applies to QoS 1 packets.
 
-        let topic = PubTopic::decode(ba)?;
+        // Topic name may be empty if Topic Alias property is set, checked after properties.
+        let topic = PubTopic::decode_allow_empty(ba)?;
 
         // Parse packet id.
         //
@@ -379,14 +386,16 @@ impl DecodePacket for PublishPacket {
             packet_id
         };
 
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) = check_property_type_list(properties.props(), PUBLISH_PROPERTIES)
+        let properties = Properties::decode_with_types(ba, PUBLISH_PROPERTIES)?;
+
+        // It is a Protocol Error if the Topic Name is zero length and there is no Topic Alias.
+        if topic.as_ref().is_empty()
+            && !properties
+                .props()
+                .iter()
+                .any(|property| property.property_type() == PropertyType::TopicAlias)
         {
-            log::error!(
-                "v5/PublishPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
+            return Err(DecodeError::InvalidTopic(TopicError::EmptyTopic));
         }
 
         let got_length = if qos == QoS::AtMostOnce {
@@ -456,3 +465,128 @@ impl Packet for PublishPacket {
         Ok(fixed_header.bytes() + fixed_header.remaining_length())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PublishPacket;
+    use crate::v5::Property;
+    use crate::{
+        ByteArray, DecodeError, DecodePacket, EncodePacket, Packet, PacketId, QoS, StringData,
+        TopicError, U16Data, U32Data,
+    };
+
+    #[test]
+    fn test_decode_topic_alias() {
+        let mut packet = PublishPacket::new("sensors/temperature", QoS::AtMostOnce, b"21").unwrap();
+        packet.clear_topic();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        assert!(matches!(
+            PublishPacket::decode(&mut ba),
+            Err(DecodeError::InvalidTopic(TopicError::EmptyTopic))
+        ));
+
+        packet
+            .properties_mut()
+            .push(Property::TopicAlias(U16Data::new(1)))
+            .unwrap();
+        buf.clear();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let decoded = PublishPacket::decode(&mut ba).unwrap();
+        assert!(decoded.topic().is_empty());
+        assert_eq!(decoded.message(), b"21");
+    }
+
+    #[test]
+    fn test_decode_header_flags() {
+        let packet = PublishPacket::new("sensors/temperature", QoS::AtMostOnce, b"21").unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        // DUP flag is set in QoS 0 packet.
+        let mut dup_buf = buf.clone();
+        dup_buf[0] |= 0b0000_1000;
+        let mut ba = ByteArray::new(&dup_buf);
+        assert!(matches!(
+            PublishPacket::decode(&mut ba),
+            Err(DecodeError::InvalidPacketFlags)
+        ));
+
+        // Both QoS bits are set.
+        buf[0] |= 0b0000_0110;
+        let mut ba = ByteArray::new(&buf);
+        assert!(matches!(
+            PublishPacket::decode(&mut ba),
+            Err(DecodeError::InvalidPacketFlags)
+        ));
+
+        // DUP flag is set in re-delivered QoS 1 packet.
+        let mut packet =
+            PublishPacket::new("sensors/temperature", QoS::AtLeastOnce, b"21").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        packet.set_dup(true).unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let decoded = PublishPacket::decode(&mut ba).unwrap();
+        assert!(decoded.dup());
+        assert_eq!(decoded.qos(), QoS::AtLeastOnce);
+        assert_eq!(decoded.packet_id(), PacketId::new(1));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        let mut packet =
+            PublishPacket::new("sensors/temperature", QoS::AtLeastOnce, b"21").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        packet
+            .properties_mut()
+            .push(Property::MessageExpiryInterval(U32Data::new(60)))
+            .unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+
+        for len in 0..buf.len() {
+            let mut ba = ByteArray::new(&buf[..len]);
+            assert!(PublishPacket::decode(&mut ba).is_err());
+        }
+
+        // Corrupted length fields shall not panic.
+        for index in 0..buf.len() {
+            for byte in [0x00, 0x01, 0x7f, 0xff] {
+                let mut corrupted = buf.clone();
+                corrupted[index] = byte;
+                let mut ba = ByteArray::new(&corrupted);
+                let _ret = PublishPacket::decode(&mut ba);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_properties() {
+        let mut packet = PublishPacket::new("sensor/1/temp", QoS::AtLeastOnce, b"20").unwrap();
+        packet.set_packet_id(PacketId::new(7));
+        packet
+            .properties_mut()
+            .push(Property::MessageExpiryInterval(U32Data::new(60)))
+            .unwrap();
+        packet
+            .properties_mut()
+            .push(Property::ContentType(
+                StringData::from("text/plain").unwrap(),
+            ))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let bytes_written = packet.encode(&mut buf).unwrap();
+        assert_eq!(bytes_written, buf.len());
+        assert_eq!(packet.bytes().unwrap(), buf.len());
+        let mut ba = ByteArray::new(&buf);
+        let decoded = PublishPacket::decode(&mut ba).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(decoded.properties().len(), 2);
+        assert_eq!(decoded.message(), b"20");
+    }
+}
@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
@@ -150,17 +149,7 @@ impl DecodePacket for PublishReleasePacket {
         }
 
         let properties = if remaining_length > ReasonCode::bytes() {
-            let properties = Properties::decode(ba)?;
-            if let Err(property_type) =
-                check_property_type_list(properties.props(), PUBLISH_RELEASE_PROPERTIES)
-            {
-                log::error!(
-                    "v5/PublishReleasePacket: property type {:?} cannot be used in properties!",
-                    property_type
-                );
-                return Err(DecodeError::InvalidPropertyType);
-            }
-            properties
+            Properties::decode_with_types(ba, PUBLISH_RELEASE_PROPERTIES)?
         } else {
             Properties::new()
         };
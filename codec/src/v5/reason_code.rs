@@ -398,6 +398,70 @@ impl ReasonCode {
     pub const fn bytes() -> usize {
         1
     }
+
+    /// Returns true if this reason code indicates successful completion of an operation.
+    #[must_use]
+    pub const fn is_success(self) -> bool {
+        (self as u8) < 0x80
+    }
+
+    /// Returns true if this reason code indicates failure.
+    #[must_use]
+    pub const fn is_error(self) -> bool {
+        !self.is_success()
+    }
+
+    /// Get human readable name of reason code defined in spec.
+    ///
+    /// Reason code 0x00 has different names in different packets, "Success" is used here.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Success => "Success",
+            Self::GrantedQoS1 => "Granted QoS 1",
+            Self::GrantedQoS2 => "Granted QoS 2",
+            Self::DisconnectWithWillMessage => "Disconnect with Will Message",
+            Self::NoMatchingSubscribers => "No matching subscribers",
+            Self::NoSubscriptionExisted => "No subscription existed",
+            Self::ContinueAuthentication => "Continue authentication",
+            Self::ReAuthenticate => "Re-authenticate",
+            Self::UnspecifiedError => "Unspecified error",
+            Self::MalformedPacket => "Malformed Packet",
+            Self::ProtocolError => "Protocol Error",
+            Self::ImplementationSpecificError => "Implementation specific error",
+            Self::UnsupportedProtocolVersion => "Unsupported Protocol Version",
+            Self::ClientIdentifierNotValid => "Client Identifier not valid",
+            Self::BadUserNameOrPassword => "Bad User Name or Password",
+            Self::NotAuthorized => "Not authorized",
+            Self::ServerUnavailable => "Server unavailable",
+            Self::ServerBusy => "Server busy",
+            Self::Banned => "Banned",
+            Self::ServerShuttingDown => "Server shutting down",
+            Self::BadAuthenticationMethod => "Bad authentication method",
+            Self::KeepAliveTimeout => "Keep Alive timeout",
+            Self::SessionTakenOver => "Session taken over",
+            Self::TopicFilterInvalid => "Topic Filter invalid",
+            Self::TopicNameInvalid => "Topic Name invalid",
+            Self::PacketIdentifierInUse => "Packet Identifier in use",
+            Self::PacketIdentifierNotFound => "Packet Identifier not found",
+            Self::ReceiveMaximumExceeded => "Receive Maximum exceeded",
+            Self::TopicAliasInvalid => "Topic Alias invalid",
+            Self::PacketTooLarge => "Packet too large",
+            Self::MessageRateTooHigh => "Message rate too high",
+            Self::QuotaExceeded => "Quota exceeded",
+            Self::AdministrativeAction => "Administrative action",
+            Self::PayloadFormatInvalid => "Payload format invalid",
+            Self::RetainNotSupported => "Retain not supported",
+            Self::QoSNotSupported => "QoS not supported",
+            Self::UseAnotherServer => "Use another server",
+            Self::ServerMoved => "Server moved",
+            Self::SharedSubscriptionNotSupported => "Shared Subscriptions not supported",
+            Self::ConnectionRateExceeded => "Connection rate exceeded",
+            Self::MaximumConnectTime => "Maximum connect time",
+            Self::SubscriptionIdentifiersNotSupported => "Subscription Identifiers not supported",
+            Self::WildcardSubscriptionsNotSupported => "Wildcard Subscriptions not supported",
+        }
+    }
 }
 
 impl DecodePacket for ReasonCode {
@@ -414,3 +478,54 @@ impl EncodePacket for ReasonCode {
         Ok(Self::bytes())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ReasonCode;
+
+    #[test]
+    fn test_description() {
+        assert_eq!(ReasonCode::Success.description(), "Success");
+        assert_eq!(ReasonCode::GrantedQoS1.description(), "Granted QoS 1");
+        assert_eq!(
+            ReasonCode::NoMatchingSubscribers.description(),
+            "No matching subscribers"
+        );
+        assert_eq!(ReasonCode::NotAuthorized.description(), "Not authorized");
+        assert_eq!(
+            ReasonCode::BadUserNameOrPassword.description(),
+            "Bad User Name or Password"
+        );
+        assert_eq!(
+            ReasonCode::KeepAliveTimeout.description(),
+            "Keep Alive timeout"
+        );
+        assert_eq!(ReasonCode::PacketTooLarge.description(), "Packet too large");
+        assert_eq!(
+            ReasonCode::WildcardSubscriptionsNotSupported.description(),
+            "Wildcard Subscriptions not supported"
+        );
+    }
+
+    #[test]
+    fn test_is_error() {
+        for code in [
+            ReasonCode::Success,
+            ReasonCode::GrantedQoS2,
+            ReasonCode::DisconnectWithWillMessage,
+            ReasonCode::ReAuthenticate,
+        ] {
+            assert!(code.is_success(), "{code:?}");
+            assert!(!code.is_error(), "{code:?}");
+        }
+        for code in [
+            ReasonCode::UnspecifiedError,
+            ReasonCode::NotAuthorized,
+            ReasonCode::ServerBusy,
+            ReasonCode::WildcardSubscriptionsNotSupported,
+        ] {
+            assert!(code.is_error(), "{code:?}");
+            assert!(!code.is_success(), "{code:?}");
+        }
+    }
+}
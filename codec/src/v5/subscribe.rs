@@ -4,10 +4,7 @@
 
 use std::convert::TryFrom;
 
-use super::{
-    property::check_multiple_subscription_identifiers, property::check_property_type_list,
-    Properties, PropertyType,
-};
+use super::{property::check_multiple_subscription_identifiers, Properties, PropertyType};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
     PacketType, QoS, SubTopic, VarIntError,
@@ -163,6 +160,7 @@ impl SubscribeTopic {
         self.retain_handling
     }
 
+    #[must_use]
     pub fn bytes(&self) -> usize {
         1 + self.topic.bytes()
     }
@@ -341,7 +339,7 @@ impl SubscribePacket {
     }
 
     fn get_fixed_header(&self) -> Result<FixedHeader, VarIntError> {
-        let mut remaining_length = PacketId::bytes();
+        let mut remaining_length = PacketId::bytes() + self.properties.bytes();
         for topic in &self.topics {
             remaining_length += topic.bytes();
         }
@@ -364,16 +362,7 @@ impl DecodePacket for SubscribePacket {
             return Err(DecodeError::InvalidPacketId);
         }
 
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) =
-            check_property_type_list(properties.props(), SUBSCRIBE_PROPERTIES)
-        {
-            log::error!(
-                "v5/SubscribePacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, SUBSCRIBE_PROPERTIES)?;
         if let Err(property_type) = check_multiple_subscription_identifiers(properties.props()) {
             log::error!(
                 "v5/SubscribePacket: property type {:?} cannot be used in properties!",
@@ -415,6 +404,7 @@ impl EncodePacket for SubscribePacket {
 
         // Variable header
         self.packet_id.encode(buf)?;
+        self.properties.encode(buf)?;
 
         // Payload
         for topic in &self.topics {
@@ -435,3 +425,71 @@ impl Packet for SubscribePacket {
         Ok(fixed_header.bytes() + fixed_header.remaining_length())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SubscribePacket;
+    use crate::v5::Property;
+    use crate::{
+        ByteArray, DecodeError, DecodePacket, EncodePacket, Packet, PacketId, QoS, U16Data, VarInt,
+    };
+
+    fn decode(packet: &SubscribePacket) -> Result<SubscribePacket, DecodeError> {
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        SubscribePacket::decode(&mut ba)
+    }
+
+    #[test]
+    fn test_decode_illegal_property() {
+        let mut packet =
+            SubscribePacket::new("sensors/#", QoS::AtLeastOnce, PacketId::new(1)).unwrap();
+        packet
+            .properties_mut()
+            .push(Property::SubscriptionIdentifier(VarInt::from(1).unwrap()))
+            .unwrap();
+        assert!(decode(&packet).is_ok());
+
+        // Subscription identifier appears more than once.
+        let mut duplicated = packet.clone();
+        duplicated
+            .properties_mut()
+            .push(Property::SubscriptionIdentifier(VarInt::from(2).unwrap()))
+            .unwrap();
+        assert!(matches!(
+            decode(&duplicated),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+
+        // Topic alias is only used in publish packets.
+        packet
+            .properties_mut()
+            .push(Property::TopicAlias(U16Data::new(1)))
+            .unwrap();
+        assert!(matches!(
+            decode(&packet),
+            Err(DecodeError::InvalidPropertyType)
+        ));
+    }
+
+    #[test]
+    fn test_encode_properties() {
+        let mut packet =
+            SubscribePacket::new("sensor/+/temp", QoS::AtLeastOnce, PacketId::new(3)).unwrap();
+        packet
+            .properties_mut()
+            .push(Property::SubscriptionIdentifier(VarInt::from(42).unwrap()))
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let bytes_written = packet.encode(&mut buf).unwrap();
+        assert_eq!(bytes_written, buf.len());
+        assert_eq!(packet.bytes().unwrap(), buf.len());
+        let mut ba = ByteArray::new(&buf);
+        let decoded = SubscribePacket::decode(&mut ba).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(decoded.properties().len(), 1);
+        assert_eq!(decoded.topics()[0].topic(), "sensor/+/temp");
+    }
+}
@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
@@ -141,16 +140,7 @@ impl DecodePacket for SubscribeAckPacket {
         }
 
         let packet_id = PacketId::decode(ba)?;
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) =
-            check_property_type_list(properties.props(), SUBSCRIBE_ACK_PROPERTIES)
-        {
-            log::error!(
-                "v5/SubscribeAckPacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, SUBSCRIBE_ACK_PROPERTIES)?;
 
         let mut reasons = Vec::new();
         let mut remaining_length = PacketId::bytes() + properties.bytes();
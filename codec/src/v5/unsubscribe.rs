@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
@@ -10,6 +9,7 @@ use crate::{
 };
 
 /// The Client request to unsubscribe topics from the Server.
+///
 /// When the Server receives this packet, no more Publish packet will be sent to the Client.
 /// Unfinished `QoS` 1 and `QoS` 2 packets will be delivered as usual.
 ///
@@ -171,16 +171,7 @@ impl DecodePacket for UnsubscribePacket {
             return Err(DecodeError::InvalidPacketId);
         }
 
-        let properties = Properties::decode(ba)?;
-        if let Err(property_type) =
-            check_property_type_list(properties.props(), UNSUBSCRIBE_PROPERTIES)
-        {
-            log::error!(
-                "v5/UnsubscribePacket: property type {:?} cannot be used in properties!",
-                property_type
-            );
-            return Err(DecodeError::InvalidPropertyType);
-        }
+        let properties = Properties::decode_with_types(ba, UNSUBSCRIBE_PROPERTIES)?;
 
         let mut remaining_length = PacketId::bytes() + properties.bytes();
         let mut topics = Vec::new();
@@ -2,7 +2,6 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use super::property::check_property_type_list;
 use super::{Properties, PropertyType, ReasonCode};
 use crate::{
     ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, FixedHeader, Packet, PacketId,
@@ -134,17 +133,7 @@ impl DecodePacket for UnsubscribeAckPacket {
 
         let packet_id = PacketId::decode(ba)?;
         let properties = if fixed_header.remaining_length() > PacketId::bytes() {
-            let properties = Properties::decode(ba)?;
-            if let Err(property_type) =
-                check_property_type_list(properties.props(), UNSUBSCRIBE_ACK_PROPERTIES)
-            {
-                log::error!(
-                    "v5/UnsubscribeAckPacket: property type {:?} cannot be used in properties!",
-                    property_type
-                );
-                return Err(DecodeError::InvalidPropertyType);
-            }
-            properties
+            Properties::decode_with_types(ba, UNSUBSCRIBE_ACK_PROPERTIES)?
         } else {
             Properties::new()
         };
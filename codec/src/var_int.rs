@@ -19,12 +19,19 @@ use crate::{ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket};
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct VarInt(usize);
 
-/// 256MB
-pub const MAX_PACKET_LEN: usize = 0x7fff_ffff;
+/// 256MB, maximum value of a four bytes var int.
+pub const MAX_PACKET_LEN: usize = 0x0fff_ffff;
+
+/// Maximum number of bytes of a var int.
+pub const MAX_VAR_INT_BYTES: usize = 4;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VarIntError {
+    /// Value is larger than `MAX_PACKET_LEN`.
     OutOfRange(usize),
+
+    /// Continuation bit of the fourth byte is set.
+    TooLong,
 }
 
 impl VarInt {
@@ -101,9 +108,9 @@ impl VarInt {
     /// Returns number of bytes of this var int object consums.
     #[must_use]
     pub const fn bytes(&self) -> usize {
-        if self.0 > 0x007f_ffff {
+        if self.0 > 0x001f_ffff {
             4
-        } else if self.0 > 0x7fff {
+        } else if self.0 > 0x3fff {
             3
         } else if self.0 > 0x7f {
             2
@@ -121,26 +128,19 @@ impl fmt::Display for VarInt {
 
 impl DecodePacket for VarInt {
     fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
-        let mut byte: usize;
         let mut remaining_length: usize = 0;
-        let mut multiplier = 1;
 
         // Read variant length
-        loop {
-            byte = ba.read_byte()? as usize;
-            remaining_length += (byte & 127) * multiplier;
-            multiplier *= 128;
-
-            if multiplier > 128 * 128 * 128 * 128 {
-                return Err(DecodeError::InvalidVarInt);
-            }
+        for i in 0..MAX_VAR_INT_BYTES {
+            let byte = ba.read_byte()? as usize;
+            remaining_length += (byte & 127) << (7 * i);
 
             if (byte & 128) == 0 {
-                break;
+                return Self::from(remaining_length).map_err(DecodeError::from);
             }
         }
 
-        Ok(Self(remaining_length))
+        Err(VarIntError::TooLong.into())
     }
 }
 
@@ -233,4 +233,54 @@ mod tests {
         let ret = ret.unwrap();
         assert_eq!(ret.0, 268_435_455);
     }
+
+    #[test]
+    fn test_var_int_decode_boundary() {
+        let cases: [(&[u8], usize); 8] = [
+            (&[0x00], 0),
+            (&[0x7f], 127),
+            (&[0x80, 0x01], 128),
+            (&[0xff, 0x7f], 16_383),
+            (&[0x80, 0x80, 0x01], 16_384),
+            (&[0xff, 0xff, 0x7f], 2_097_151),
+            (&[0x80, 0x80, 0x80, 0x01], 2_097_152),
+            (&[0xff, 0xff, 0xff, 0x7f], 268_435_455),
+        ];
+        for (buf, value) in cases {
+            let mut ba = ByteArray::new(buf);
+            let var_int = VarInt::decode(&mut ba).unwrap();
+            assert_eq!(var_int.value(), value);
+            assert_eq!(var_int.bytes(), buf.len());
+            assert_eq!(ba.remaining_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn test_var_int_decode_too_long() {
+        let buf = [0xff, 0xff, 0xff, 0xff, 0x7f];
+        let mut ba = ByteArray::new(&buf);
+        let ret = VarInt::decode(&mut ba);
+        assert!(matches!(
+            ret,
+            Err(DecodeError::InvalidVarInt(VarIntError::TooLong))
+        ));
+
+        // Rejected without reading the fifth byte.
+        let buf = [0x80, 0x80, 0x80, 0x80];
+        let mut ba = ByteArray::new(&buf);
+        let ret = VarInt::decode(&mut ba);
+        assert!(matches!(
+            ret,
+            Err(DecodeError::InvalidVarInt(VarIntError::TooLong))
+        ));
+    }
+
+    #[test]
+    fn test_var_int_from() {
+        assert!(VarInt::from(MAX_PACKET_LEN).is_ok());
+        assert_eq!(
+            VarInt::from(MAX_PACKET_LEN + 1),
+            Err(VarIntError::OutOfRange(MAX_PACKET_LEN + 1))
+        );
+    }
 }
@@ -0,0 +1,56 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Benchmark write throughput of journal backend with and without batching.
+
+use codec::{v3, QoS};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hebo::backends::batch::BatchWriter;
+use hebo::backends::journal::JournalBackend;
+use hebo::session::OutgoingPacket;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+const MESSAGES: usize = 256;
+const BATCH_SIZES: &[usize] = &[1, 16, 128];
+
+async fn write_messages(batch_size: usize) {
+    let path = std::env::temp_dir().join(format!("hebo-bench-journal-{batch_size}.db"));
+    let store = JournalBackend::open(&path).unwrap();
+    let (writer, sender) = BatchWriter::new(store, batch_size, Duration::from_millis(1));
+    let writer_handle = tokio::spawn(writer.run_loop());
+
+    let handles: Vec<_> = (0..MESSAGES)
+        .map(|index| {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let topic = format!("device/{index}/status");
+                let packet = v3::PublishPacket::new(&topic, QoS::AtLeastOnce, b"online").unwrap();
+                sender.write(OutgoingPacket::V3(packet)).await.unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    drop(sender);
+    writer_handle.await.unwrap();
+    let _ret = std::fs::remove_file(path);
+}
+
+fn bench_batch_write(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("journal_write");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    for &batch_size in BATCH_SIZES {
+        group.bench_function(BenchmarkId::new("batch_size", batch_size), |b| {
+            b.iter(|| runtime.block_on(write_messages(batch_size)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_batch_write);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Benchmark wildcard matching of retained messages.
+
+use codec::{v3, QoS};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hebo::dispatcher::RetainTrie;
+
+const TRIE_SIZES: &[usize] = &[1_000, 10_000, 100_000];
+
+fn new_trie(size: usize) -> RetainTrie {
+    let mut trie = RetainTrie::new();
+    for index in 0..size {
+        let topic = format!("device/{}/sensor/{}", index / 100, index % 100);
+        let mut packet = v3::PublishPacket::new(&topic, QoS::AtMostOnce, b"on").unwrap();
+        packet.set_retain(true);
+        trie.retain(&packet);
+    }
+    trie
+}
+
+fn bench_match_filter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_filter");
+    for &size in TRIE_SIZES {
+        let trie = new_trie(size);
+        group.bench_function(BenchmarkId::new("multi_level", size), |b| {
+            b.iter(|| black_box(trie.match_filter("device/5/#")));
+        });
+        group.bench_function(BenchmarkId::new("single_level", size), |b| {
+            b.iter(|| black_box(trie.match_filter("device/5/sensor/+")));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_match_filter);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Benchmark subscribe, match and unsubscribe operations of subscription trie.
+
+use codec::{v3, PacketId, QoS};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use hebo::dispatcher::SubTrie;
+use hebo::types::SessionGid;
+
+const TRIE_SIZES: &[usize] = &[100, 1_000, 10_000];
+
+fn subscribe_packet(index: usize) -> v3::SubscribePacket {
+    let topic = if index % 10 == 0 {
+        format!("device/{}/+/status", index / 10)
+    } else {
+        format!("device/{index}/sensor/temperature")
+    };
+    v3::SubscribePacket::new(&topic, QoS::AtLeastOnce, PacketId::new(1)).unwrap()
+}
+
+fn unsubscribe_packet(index: usize) -> v3::UnsubscribePacket {
+    let topic = if index % 10 == 0 {
+        format!("device/{}/+/status", index / 10)
+    } else {
+        format!("device/{index}/sensor/temperature")
+    };
+    v3::UnsubscribePacket::new(&topic, PacketId::new(2)).unwrap()
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn session_gid(index: usize) -> SessionGid {
+    SessionGid::new((index % 4) as u32, index as u64)
+}
+
+fn new_trie(size: usize) -> SubTrie {
+    let mut trie = SubTrie::new();
+    for index in 0..size {
+        let _ack = trie.subscribe(session_gid(index), &subscribe_packet(index));
+    }
+    trie
+}
+
+fn bench_subscribe(c: &mut Criterion) {
+    let mut group = c.benchmark_group("subscribe");
+    for &size in TRIE_SIZES {
+        let packets: Vec<v3::SubscribePacket> = (0..size).map(subscribe_packet).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &packets, |b, packets| {
+            b.iter(|| {
+                let mut trie = SubTrie::new();
+                for (index, packet) in packets.iter().enumerate() {
+                    black_box(trie.subscribe(session_gid(index), packet));
+                }
+                trie
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_match(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match");
+    let packet = v3::PublishPacket::new("device/5/sensor/status", QoS::AtMostOnce, b"on").unwrap();
+    for &size in TRIE_SIZES {
+        let mut trie = new_trie(size);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| black_box(trie.match_packet(&packet)));
+        });
+    }
+    group.finish();
+}
+
+/// Point-to-point topic with exactly one subscriber, general path versus fast path.
+fn bench_match_single(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_single");
+    let topic = "device/42/sensor/temperature";
+    for &size in TRIE_SIZES {
+        let mut trie = new_trie(size);
+        group.bench_function(BenchmarkId::new("matches", size), |b| {
+            b.iter(|| black_box(trie.matches(black_box(topic))));
+        });
+        group.bench_function(BenchmarkId::new("match_subscribers", size), |b| {
+            b.iter(|| black_box(trie.match_subscribers(black_box(topic))));
+        });
+    }
+    group.finish();
+}
+
+fn bench_unsubscribe(c: &mut Criterion) {
+    let mut group = c.benchmark_group("unsubscribe");
+    for &size in TRIE_SIZES {
+        let packets: Vec<v3::UnsubscribePacket> = (0..size).map(unsubscribe_packet).collect();
+        let trie = new_trie(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &packets, |b, packets| {
+            b.iter_batched(
+                || trie.clone(),
+                |mut trie| {
+                    for (index, packet) in packets.iter().enumerate() {
+                        black_box(trie.unsubscribe(session_gid(index), packet));
+                    }
+                    trie
+                },
+                criterion::BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_subscribe,
+    bench_match,
+    bench_match_single,
+    bench_unsubscribe
+);
+criterion_main!(benches);
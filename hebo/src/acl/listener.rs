@@ -2,150 +2,268 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use codec::{v3, v5};
+use codec::{QoS, SubscribePattern};
+use std::borrow::Cow;
 
 use super::AclApp;
 use crate::commands::{AclToListenerCmd, ListenerToAclCmd};
-use crate::error::Error;
-use crate::types::SessionGid;
+use crate::config::AclPolicy;
+use crate::dispatcher::parse_delayed_topic;
+use crate::error::{Error, ErrorKind};
+use crate::types::{AclRequest, SessionGid};
 
 impl AclApp {
-    pub(super) async fn handle_listener_cmd(&mut self, cmd: ListenerToAclCmd) -> Result<(), Error> {
+    #[allow(clippy::unused_async)]
+    pub(super) async fn handle_listener_cmd(&self, cmd: ListenerToAclCmd) -> Result<(), Error> {
         match cmd {
-            ListenerToAclCmd::Publish(session_gid, packet) => {
-                self.on_listener_publish(session_gid, packet).await
-            }
-            ListenerToAclCmd::PublishV5(session_gid, packet) => {
-                self.on_listener_publish_v5(session_gid, packet).await
-            }
-            ListenerToAclCmd::Subscribe(session_gid, packet) => {
-                self.on_listener_subscribe(session_gid, packet).await
-            }
-            ListenerToAclCmd::SubscribeV5(session_gid, packet) => {
-                self.on_listener_subscribe_v5(session_gid, packet).await
+            ListenerToAclCmd::CheckAcl(session_gid, request) => {
+                self.on_listener_check_acl(session_gid, request)
             }
         }
     }
 
-    async fn on_listener_publish(
-        &mut self,
-        session_gid: SessionGid,
-        packet: v3::PublishPacket,
-    ) -> Result<(), Error> {
-        // TODO(Shaohua): Read acl list from config.
-        let accepted = true;
-        if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
-            let cmd = AclToListenerCmd::PublishAck(session_gid.session_id(), packet, accepted);
-            if let Err(err) = listener_sender.send(cmd).await {
-                log::error!(
-                    "acl: Failed to send publish ack to listener: {:?}, err: {:?}",
-                    session_gid,
-                    err
-                );
-            }
-        } else {
-            log::error!(
-                "acl: Failed to find listener sender with id: {}",
-                session_gid.listener_id()
-            );
-        }
-        // TODO(Shaohua): Return errors
-        Ok(())
+    /// Check each topic in packet against acl rules.
+    ///
+    /// Topics not matched by any rule are handled by policy of listener.
+    fn check_acl(&self, request: &AclRequest) -> Vec<bool> {
+        let write = request.packet.is_publish();
+        request
+            .packet
+            .topics()
+            .into_iter()
+            .map(|topic| {
+                let Some(rules) = &self.rules else {
+                    return true;
+                };
+                let Some(acl_topic) = acl_topic(topic, write) else {
+                    log::warn!("acl: Invalid topic filter {:?} is denied", topic);
+                    return false;
+                };
+                let check = |topic: &str| {
+                    rules
+                        .check(&request.client_id, &request.username, topic, write)
+                        .unwrap_or(request.policy == AclPolicy::Allow)
+                };
+                let mut granted = check(&acl_topic);
+                // Delayed message is published to the real topic later.
+                if granted && write && self.delayed_publish {
+                    if let Some((_interval, real_topic)) = parse_delayed_topic(topic) {
+                        granted = check(real_topic);
+                    }
+                }
+                if !granted {
+                    log::warn!(
+                        "acl: {} of client {:?} to {:?} is denied",
+                        if write { "Publish" } else { "Subscribe" },
+                        request.client_id,
+                        topic
+                    );
+                }
+                granted
+            })
+            .collect()
     }
 
-    async fn on_listener_publish_v5(
-        &mut self,
+    fn on_listener_check_acl(
+        &self,
         session_gid: SessionGid,
-        packet: v5::PublishPacket,
+        request: AclRequest,
     ) -> Result<(), Error> {
-        // TODO(Shaohua): Read acl list from config.
-        let accepted = true;
-        if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
-            let cmd = AclToListenerCmd::PublishAckV5(session_gid.session_id(), packet, accepted);
-            if let Err(err) = listener_sender.send(cmd).await {
-                log::error!(
-                    "acl: Failed to send publish ack to listener: {:?}, err: {:?}",
-                    session_gid,
-                    err
-                );
-            }
-        } else {
-            log::error!(
-                "acl: Failed to find listener sender with id: {}",
-                session_gid.listener_id()
-            );
-        }
-        // TODO(Shaohua): Return errors
-        Ok(())
+        let granted = self.check_acl(&request);
+        let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) else {
+            return Err(Error::from_string(
+                ErrorKind::ChannelError,
+                format!(
+                    "acl: Failed to find listener sender with id: {}",
+                    session_gid.listener_id()
+                ),
+            ));
+        };
+        let cmd = AclToListenerCmd::CheckAclResp(session_gid.session_id(), request.packet, granted);
+        listener_sender.send(cmd).map_err(Into::into)
     }
+}
 
-    async fn on_listener_subscribe(
-        &mut self,
-        session_gid: SessionGid,
-        packet: v3::SubscribePacket,
-    ) -> Result<(), Error> {
-        // TODO(Shaohua): Read acl list from config.
-        let accepted = true;
-        let mut acks = Vec::with_capacity(packet.topics().len());
-        for topic in packet.topics() {
-            // TODO(Shaohua): Check topic patterns.
-            acks.push(v3::SubscribeAck::QoS(topic.qos()));
-        }
+/// Get topic to be checked against acl rules.
+///
+/// Shared subscriptions are checked with their topic filter, without the
+/// `$share/<group>/` prefix. Returns None if topic filter is invalid.
+fn acl_topic(topic: &str, write: bool) -> Option<Cow<'_, str>> {
+    if write {
+        return Some(Cow::Borrowed(topic));
+    }
+    let pattern = SubscribePattern::parse(topic, QoS::AtMostOnce).ok()?;
+    if pattern.is_shared() {
+        Some(Cow::Owned(pattern.topic().topic().clone()))
+    } else {
+        Some(Cow::Borrowed(topic))
+    }
+}
 
-        if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
-            let cmd =
-                AclToListenerCmd::SubscribeAck(session_gid.session_id(), packet, acks, accepted);
-            if let Err(err) = listener_sender.send(cmd).await {
-                log::error!(
-                    "acl: Failed to send subscribe ack to listener: {:?}, err: {:?}",
-                    session_gid,
-                    err
-                );
-            }
-        } else {
-            log::error!(
-                "acl: Failed to find listener sender with id: {}",
-                session_gid.listener_id()
-            );
-        }
-        // TODO(Shaohua): Return errors
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5, PacketId, QoS};
+    use std::collections::HashMap;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    use super::AclApp;
+    use crate::acl::rules::AclRules;
+    use crate::commands::AclToListenerCmd;
+    use crate::config::AclPolicy;
+    use crate::types::{AclPacket, AclRequest, SessionGid};
+
+    fn new_app(rules: Option<&str>) -> (AclApp, UnboundedReceiver<AclToListenerCmd>) {
+        let (listener_sender, receiver) = mpsc::unbounded_channel();
+        let (_sender, listener_receiver) = mpsc::channel(4);
+        let (_sender, server_ctx_receiver) = mpsc::channel(4);
+        let app = AclApp {
+            rules: rules.map(|rules| AclRules::parse(rules).unwrap()),
+            delayed_publish: true,
+            listener_senders: HashMap::from([(1, listener_sender)]),
+            listener_receiver,
+            server_ctx_receiver,
+        };
+        (app, receiver)
     }
 
-    async fn on_listener_subscribe_v5(
-        &mut self,
-        session_gid: SessionGid,
-        packet: v5::SubscribePacket,
-    ) -> Result<(), Error> {
-        // TODO(Shaohua): Read acl list from config.
-        let accepted = true;
-        let mut reasons = Vec::with_capacity(packet.topics().len());
-        for _topic in packet.topics() {
-            // TODO(Shaohua): Check topic patterns.
-            reasons.push(v5::ReasonCode::Success);
-        }
+    async fn check_acl(
+        app: &AclApp,
+        receiver: &mut UnboundedReceiver<AclToListenerCmd>,
+        username: &str,
+        packet: AclPacket,
+    ) -> Vec<bool> {
+        check_acl_with_policy(app, receiver, username, packet, AclPolicy::Deny).await
+    }
 
-        if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
-            let cmd = AclToListenerCmd::SubscribeAckV5(
-                session_gid.session_id(),
-                packet,
-                reasons,
-                accepted,
-            );
-            if let Err(err) = listener_sender.send(cmd).await {
-                log::error!(
-                    "acl: Failed to send subscribe ack to listener: {:?}, err: {:?}",
-                    session_gid,
-                    err
-                );
-            }
-        } else {
-            log::error!(
-                "acl: Failed to find listener sender with id: {}",
-                session_gid.listener_id()
-            );
-        }
-        // TODO(Shaohua): Return errors
-        Ok(())
+    async fn check_acl_with_policy(
+        app: &AclApp,
+        receiver: &mut UnboundedReceiver<AclToListenerCmd>,
+        username: &str,
+        packet: AclPacket,
+        policy: AclPolicy,
+    ) -> Vec<bool> {
+        let request = AclRequest {
+            client_id: "acl-test".to_string(),
+            username: username.to_string(),
+            packet,
+            policy,
+        };
+        app.on_listener_check_acl(SessionGid::new(1, 2), request)
+            .unwrap();
+        let Some(AclToListenerCmd::CheckAclResp(2, _packet, granted)) = receiver.recv().await
+        else {
+            panic!("Expected acl response");
+        };
+        granted
+    }
+
+    fn subscribe_packet(topics: &[&str]) -> AclPacket {
+        let mut packet =
+            v3::SubscribePacket::new(topics[0], QoS::AtMostOnce, PacketId::new(1)).unwrap();
+        let topics: Vec<v3::SubscribeTopic> = topics
+            .iter()
+            .map(|topic| v3::SubscribeTopic::new(topic, QoS::AtMostOnce).unwrap())
+            .collect();
+        packet.set_topics(&topics);
+        AclPacket::Subscribe(packet)
+    }
+
+    #[tokio::test]
+    async fn test_no_acl_file() {
+        let (app, mut receiver) = new_app(None);
+        let packet = subscribe_packet(&["#", "$SYS/#"]);
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", packet).await,
+            [true, true]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_acl() {
+        let rules = "user alice\ntopic read sensor/+/temp\ntopic write cmd/#\n";
+        let (app, mut receiver) = new_app(Some(rules));
+
+        let packet = subscribe_packet(&["sensor/1/temp", "sensor/#", "sensor/+/temp"]);
+        assert_eq!(
+            check_acl(&app, &mut receiver, "alice", packet.clone()).await,
+            [true, false, true]
+        );
+        assert_eq!(
+            check_acl(&app, &mut receiver, "bob", packet).await,
+            [false, false, false]
+        );
+
+        let packet = v3::PublishPacket::new("cmd/light", QoS::AtMostOnce, b"on").unwrap();
+        assert_eq!(
+            check_acl(&app, &mut receiver, "alice", AclPacket::Publish(packet)).await,
+            [true]
+        );
+        let packet = v5::PublishPacket::new("sensor/1/temp", QoS::AtMostOnce, b"20").unwrap();
+        assert_eq!(
+            check_acl(&app, &mut receiver, "alice", AclPacket::PublishV5(packet)).await,
+            [false]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_policy() {
+        let rules = "topic deny sensor/secret/#\npattern read clients/%c/#\n";
+        let (app, mut receiver) = new_app(Some(rules));
+
+        let packet = subscribe_packet(&["sensor/secret/key", "clients/acl-test/inbox", "news"]);
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", packet.clone()).await,
+            [false, true, false]
+        );
+        // Only topics without matching rule are allowed.
+        assert_eq!(
+            check_acl_with_policy(&app, &mut receiver, "", packet, AclPolicy::Allow).await,
+            [false, true, true]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shared_subscription() {
+        let rules = "topic deny sensor/secret/#\npattern read clients/%c/#\n";
+        let (app, mut receiver) = new_app(Some(rules));
+
+        // Shared subscriptions are checked with their topic filter.
+        let packet = subscribe_packet(&[
+            "$share/group/sensor/secret/#",
+            "$share/group/sensor/#",
+            "$share/group/clients/acl-test/inbox",
+            "$share/group/news",
+        ]);
+        assert_eq!(
+            check_acl_with_policy(&app, &mut receiver, "", packet.clone(), AclPolicy::Allow).await,
+            [false, false, true, true]
+        );
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", packet).await,
+            [false, false, true, false]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delayed_publish() {
+        let rules = "topic write $delayed/#\ntopic write public/#\n";
+        let (app, mut receiver) = new_app(Some(rules));
+
+        let publish = |topic: &str| {
+            AclPacket::Publish(v3::PublishPacket::new(topic, QoS::AtMostOnce, b"1").unwrap())
+        };
+        // Both delayed topic and the real topic are checked.
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", publish("$delayed/10/public/news")).await,
+            [true]
+        );
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", publish("$delayed/10/secret/x")).await,
+            [false]
+        );
+        assert_eq!(
+            check_acl(&app, &mut receiver, "", publish("public/news")).await,
+            [true]
+        );
     }
 }
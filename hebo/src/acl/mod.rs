@@ -3,41 +3,72 @@
 // in the LICENSE file.
 
 use std::collections::HashMap;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
 
 use crate::commands::{AclToListenerCmd, ListenerToAclCmd, ServerContextToAclCmd};
+use crate::config::Security;
+use crate::error::Error;
 use crate::types::ListenerId;
 
 mod listener;
+pub mod rules;
 mod server;
 
+use rules::AclRules;
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct AclApp {
-    listener_senders: HashMap<ListenerId, Sender<AclToListenerCmd>>,
+    /// Access of all topics is granted if acl file is not set.
+    rules: Option<AclRules>,
+
+    /// Messages to `$delayed/{seconds}/{topic}` are published to `topic` later,
+    /// so that both topics are checked.
+    delayed_publish: bool,
+
+    /// Unbounded, or acl app and listener may wait for each other when their queues are full.
+    listener_senders: HashMap<ListenerId, UnboundedSender<AclToListenerCmd>>,
     listener_receiver: Receiver<ListenerToAclCmd>,
 
     server_ctx_receiver: Receiver<ServerContextToAclCmd>,
 }
 
 impl AclApp {
-    #[must_use]
+    /// Create an acl app.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read acl file.
     pub fn new(
+        security: &Security,
+        delayed_publish: bool,
         // listeners
-        listener_senders: Vec<(ListenerId, Sender<AclToListenerCmd>)>,
+        listener_senders: Vec<(ListenerId, UnboundedSender<AclToListenerCmd>)>,
         listener_receiver: Receiver<ListenerToAclCmd>,
         // server ctx
         server_ctx_receiver: Receiver<ServerContextToAclCmd>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            rules: Self::new_rules(security)?,
+            delayed_publish,
+
             listener_senders: listener_senders.into_iter().collect(),
             listener_receiver,
 
             server_ctx_receiver,
-        }
+        })
+    }
+
+    /// Load acl rules from acl file in `security`, None if it is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read acl file.
+    pub fn new_rules(security: &Security) -> Result<Option<AclRules>, Error> {
+        security.acl_file().map(AclRules::from_file).transpose()
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         loop {
             tokio::select! {
                 Some(cmd) = self.listener_receiver.recv() => {
@@ -46,6 +77,10 @@ impl AclApp {
                     }
                 },
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToAclCmd::Stop) {
+                        log::info!("acl: Stop app");
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
@@ -0,0 +1,391 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Parse acl file and check topic access of clients.
+
+use codec::topic::validate_sub_topic;
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::error::{Error, ErrorKind};
+
+const CLIENT_ID_PLACEHOLDER: &str = "%c";
+const USERNAME_PLACEHOLDER: &str = "%u";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    ReadWrite,
+    Deny,
+}
+
+impl Access {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "read" => Some(Self::Read),
+            "write" => Some(Self::Write),
+            "readwrite" => Some(Self::ReadWrite),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+
+    /// Publish requires write access, and subscribe requires read access.
+    const fn grants(self, write: bool) -> bool {
+        match self {
+            Self::Read => !write,
+            Self::Write => write,
+            Self::ReadWrite => true,
+            Self::Deny => false,
+        }
+    }
+}
+
+/// Clients a rule applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    /// Clients without username.
+    Anonymous,
+    User(String),
+    /// All clients, with placeholders in topic.
+    Pattern,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    scope: Scope,
+    access: Access,
+    topic: String,
+}
+
+impl Rule {
+    /// Replace placeholders in pattern with client id and username.
+    ///
+    /// Returns None if pattern is not applicable to this client.
+    fn expand<'a>(&'a self, client_id: &str, username: &str) -> Option<Cow<'a, str>> {
+        let mut topic = Cow::Borrowed(self.topic.as_str());
+        for (placeholder, value) in [
+            (CLIENT_ID_PLACEHOLDER, client_id),
+            (USERNAME_PLACEHOLDER, username),
+        ] {
+            if !topic.contains(placeholder) {
+                continue;
+            }
+            // Values with topic separator or wildcards shall not change meaning of pattern.
+            if value.is_empty() || value.contains(['/', '+', '#']) {
+                return None;
+            }
+            topic = Cow::Owned(topic.replace(placeholder, value));
+        }
+        Some(topic)
+    }
+}
+
+/// Returns true if every topic matched by `topic` is also matched by `rule`.
+///
+/// `topic` is a topic name in publish packet or a topic filter in subscribe packet.
+fn is_covered(rule: &str, topic: &str) -> bool {
+    // Wildcards at the first level do not match topics beginning with `$` [MQTT-4.7.2-1].
+    if topic.starts_with('$') && (rule.starts_with('+') || rule.starts_with('#')) {
+        return false;
+    }
+    let mut rule_parts = rule.split('/');
+    let mut topic_parts = topic.split('/');
+    loop {
+        match (rule_parts.next(), topic_parts.next()) {
+            (Some("#"), _) | (None, None) => return true,
+            (Some("+"), Some(part)) if part != "#" => (),
+            (Some(rule_part), Some(part)) if rule_part == part => (),
+            _ => return false,
+        }
+    }
+}
+
+/// Returns true if some topic is matched by both `rule` and `topic`.
+///
+/// Deny rules are checked with this, so that a topic filter is rejected if
+/// any of its topics is denied.
+fn intersects(rule: &str, topic: &str) -> bool {
+    // Wildcards at the first level do not match topics beginning with `$` [MQTT-4.7.2-1].
+    let is_wildcard = |s: &str| s.starts_with('+') || s.starts_with('#');
+    if (topic.starts_with('$') && is_wildcard(rule))
+        || (rule.starts_with('$') && is_wildcard(topic))
+    {
+        return false;
+    }
+    let mut rule_parts = rule.split('/');
+    let mut topic_parts = topic.split('/');
+    loop {
+        match (rule_parts.next(), topic_parts.next()) {
+            (Some("#"), _) | (_, Some("#")) | (None, None) => return true,
+            (Some("+"), Some(_)) | (Some(_), Some("+")) => (),
+            (Some(rule_part), Some(part)) if rule_part == part => (),
+            _ => return false,
+        }
+    }
+}
+
+/// Topic access rules loaded from acl file.
+#[derive(Debug, Default, Clone)]
+pub struct AclRules {
+    /// Rules in the order of acl file.
+    rules: Vec<Rule>,
+}
+
+impl AclRules {
+    /// Read rules from acl file.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read file or file contains invalid rules.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid acl file: {}, err: {err:?}", path.display()),
+            )
+        })
+    }
+
+    /// Parse rules in acl file format.
+    ///
+    /// # Errors
+    ///
+    /// Returns error with line number if any line is not a valid rule.
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut rules = Self::default();
+        let mut scope = Scope::Anonymous;
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_line = || {
+                Error::from_string(
+                    ErrorKind::FormatError,
+                    format!("Invalid acl rule at line {}: {line:?}", index + 1),
+                )
+            };
+
+            let mut words: Vec<&str> = line.split_whitespace().collect();
+            // Username of single line rule, like `user alice topic read sensor/#`.
+            let mut line_user = None;
+            if words[0] == "user" {
+                let username = (*words.get(1).ok_or_else(invalid_line)?).to_string();
+                if words.len() == 2 {
+                    // Following rules apply to this user.
+                    scope = Scope::User(username);
+                    continue;
+                }
+                line_user = Some(username);
+                words.drain(..2);
+            }
+
+            let (access, topic) = match words[..] {
+                [_keyword, access, topic] => {
+                    (Access::parse(access).ok_or_else(invalid_line)?, topic)
+                }
+                [_keyword, topic] => (Access::ReadWrite, topic),
+                _ => return Err(invalid_line()),
+            };
+            validate_sub_topic(topic).map_err(|_err| invalid_line())?;
+            let rule_scope = match (words[0], line_user) {
+                ("topic", Some(username)) => Scope::User(username),
+                ("topic", None) => scope.clone(),
+                ("pattern", None) => Scope::Pattern,
+                _ => return Err(invalid_line()),
+            };
+            rules.rules.push(Rule {
+                scope: rule_scope,
+                access,
+                topic: topic.to_string(),
+            });
+        }
+        Ok(rules)
+    }
+
+    /// Check whether client may publish to topic name (`write` is true),
+    /// or subscribe to topic filter (`write` is false).
+    ///
+    /// Rules are evaluated in order, the first one matching `topic` and access type
+    /// takes effect. A deny rule matches if it shares any topic with `topic`, and an
+    /// allow rule matches only if it covers all of them. Returns None if no rule matches.
+    #[must_use]
+    pub fn check(&self, client_id: &str, username: &str, topic: &str, write: bool) -> Option<bool> {
+        for rule in &self.rules {
+            let rule_topic = match &rule.scope {
+                Scope::Anonymous if username.is_empty() => Cow::Borrowed(rule.topic.as_str()),
+                Scope::User(name) if name == username => Cow::Borrowed(rule.topic.as_str()),
+                Scope::Pattern => match rule.expand(client_id, username) {
+                    Some(rule_topic) => rule_topic,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if rule.access == Access::Deny {
+                if intersects(&rule_topic, topic) {
+                    return Some(false);
+                }
+                continue;
+            }
+            if is_covered(&rule_topic, topic) && rule.access.grants(write) {
+                return Some(true);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{intersects, is_covered, AclRules};
+
+    #[test]
+    fn test_is_covered() {
+        assert!(is_covered("sensor/#", "sensor"));
+        assert!(is_covered("sensor/#", "sensor/1/temp"));
+        assert!(is_covered("sensor/#", "sensor/+/temp"));
+        assert!(is_covered("sensor/+/temp", "sensor/1/temp"));
+        assert!(is_covered("sensor/+/temp", "sensor/+/temp"));
+        assert!(!is_covered("sensor/+/temp", "sensor/#"));
+        assert!(!is_covered("sensor/+", "sensor/1/temp"));
+        assert!(!is_covered("sensor/1", "sensor/+"));
+        assert!(!is_covered("#", "$SYS/uptime"));
+        assert!(is_covered("$SYS/#", "$SYS/uptime"));
+    }
+
+    #[test]
+    fn test_intersects() {
+        assert!(intersects("sensor/secret/#", "sensor/#"));
+        assert!(intersects("sensor/secret/#", "sensor/+/key"));
+        assert!(intersects("sensor/secret/#", "+/secret"));
+        assert!(intersects("sensor/+", "+/temp"));
+        assert!(intersects("sensor/#", "sensor"));
+        assert!(intersects("sensor", "sensor/#"));
+        assert!(!intersects("sensor/+", "sensor"));
+        assert!(!intersects("sensor/secret/#", "sensor/public/#"));
+        assert!(intersects("sensor/secret/#", "sensor/+"));
+        assert!(!intersects("sensor/secret/#", "sensor"));
+        assert!(!intersects("$SYS/#", "#"));
+        assert!(!intersects("#", "$SYS/uptime"));
+    }
+
+    const RULES: &str = r"
+# Anonymous clients.
+topic read public/#
+topic read $SYS/#
+
+user alice
+topic deny sensor/secret/#
+topic sensor/#
+topic write cmd/+/set
+
+user bob
+topic read sensor/+/temp
+user carol topic readwrite sensor/#
+
+pattern readwrite clients/%c/#
+pattern write users/%u/status
+";
+
+    #[test]
+    fn test_check() {
+        let rules = AclRules::parse(RULES).unwrap();
+
+        // Anonymous.
+        assert_eq!(rules.check("c1", "", "public/news", false), Some(true));
+        assert_eq!(rules.check("c1", "", "public/news", true), None);
+        assert_eq!(rules.check("c1", "", "$SYS/uptime", false), Some(true));
+        assert_eq!(rules.check("c1", "", "sensor/1/temp", false), None);
+
+        // Allow rules with wildcards.
+        assert_eq!(
+            rules.check("c2", "alice", "sensor/1/temp", true),
+            Some(true)
+        );
+        assert_eq!(rules.check("c2", "alice", "sensor/#", false), Some(false));
+        assert_eq!(
+            rules.check("c2", "alice", "sensor/+/key", false),
+            Some(false)
+        );
+        assert_eq!(
+            rules.check("c2", "alice", "sensor/public/#", false),
+            Some(true)
+        );
+        assert_eq!(
+            rules.check("c2", "alice", "cmd/light/set", true),
+            Some(true)
+        );
+        assert_eq!(rules.check("c2", "alice", "cmd/light/set", false), None);
+        assert_eq!(rules.check("c2", "alice", "cmd/light/get", true), None);
+        // Deny rules before `sensor/#` take effect first, also for filters
+        // which contain some denied topics.
+        assert_eq!(
+            rules.check("c2", "alice", "sensor/secret/key", false),
+            Some(false)
+        );
+        assert_eq!(
+            rules.check("c2", "alice", "sensor/secret/#", true),
+            Some(false)
+        );
+
+        // Read and write access are distinguished.
+        assert_eq!(rules.check("c3", "bob", "sensor/2/temp", false), Some(true));
+        assert_eq!(rules.check("c3", "bob", "sensor/+/temp", false), Some(true));
+        assert_eq!(rules.check("c3", "bob", "sensor/#", false), None);
+        assert_eq!(rules.check("c3", "bob", "sensor/2/temp", true), None);
+
+        // Single line rule does not change rules of the section.
+        assert_eq!(
+            rules.check("c4", "carol", "sensor/2/temp", true),
+            Some(true)
+        );
+        assert_eq!(rules.check("c4", "bob", "sensor/2/humidity", true), None);
+        // Unknown user.
+        assert_eq!(rules.check("c5", "dave", "public/news", false), None);
+    }
+
+    #[test]
+    fn test_rule_order() {
+        let rules = AclRules::parse("topic deny a/b\ntopic a/#\ntopic deny a/c").unwrap();
+        assert_eq!(rules.check("c1", "", "a/b", false), Some(false));
+        assert_eq!(rules.check("c1", "", "a/c", false), Some(true));
+        // Rule which does not grant this type of access is skipped.
+        let rules = AclRules::parse("topic read a/#\ntopic write a/b").unwrap();
+        assert_eq!(rules.check("c1", "", "a/b", true), Some(true));
+    }
+
+    #[test]
+    fn test_patterns() {
+        let rules = AclRules::parse(RULES).unwrap();
+        assert_eq!(rules.check("c1", "", "clients/c1/inbox", false), Some(true));
+        assert_eq!(rules.check("c1", "", "clients/c2/inbox", false), None);
+        assert_eq!(
+            rules.check("c3", "bob", "users/bob/status", true),
+            Some(true)
+        );
+        assert_eq!(rules.check("c3", "bob", "users/bob/status", false), None);
+        assert_eq!(rules.check("c3", "bob", "users/alice/status", true), None);
+        // Anonymous clients have no username.
+        assert_eq!(rules.check("c1", "", "users//status", true), None);
+        // Wildcards in client id are not expanded.
+        assert_eq!(rules.check("#", "", "clients/c1/inbox", false), None);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(AclRules::parse("topic").is_err());
+        assert!(AclRules::parse("topic readonly sensor/#").is_err());
+        assert!(AclRules::parse("topic sensor/#/temp").is_err());
+        assert!(AclRules::parse("group admin").is_err());
+        assert!(AclRules::parse("user").is_err());
+        assert!(AclRules::parse("user alice pattern clients/%c/#").is_err());
+        assert!(AclRules::parse("topic read a b").is_err());
+
+        let err = AclRules::parse("# comment\n\ntopic read a\ntopic rw b").unwrap_err();
+        assert!(err.to_string().contains("line 4"), "{err}");
+    }
+}
@@ -7,7 +7,23 @@ use crate::commands::ServerContextToAclCmd;
 
 impl AclApp {
     /// Server context handler
+    #[allow(clippy::unused_async)]
     pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToAclCmd) {
-        log::info!("cmd: {:?}", cmd);
+        match cmd {
+            ServerContextToAclCmd::ListenerAdded(listener_id, sender) => {
+                log::info!("acl: Add listener #{}", listener_id);
+                self.listener_senders.insert(listener_id, sender);
+            }
+            ServerContextToAclCmd::ListenerRemoved(listener_id) => {
+                log::info!("acl: Remove listener #{}", listener_id);
+                self.listener_senders.remove(&listener_id);
+            }
+            ServerContextToAclCmd::UpdateSecurity(rules) => {
+                log::info!("acl: Update acl rules");
+                self.rules = rules;
+            }
+            // Handled in run_loop().
+            ServerContextToAclCmd::Stop => (),
+        }
     }
 }
@@ -0,0 +1,52 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Interface of enhanced authentication of v5 clients.
+//!
+//! A v5 client may set Authentication Method in connect packet, then the session
+//! exchanges Authentication Data with client in AUTH packets, until the method
+//! accepts or rejects it. Methods like SCRAM are registered with
+//! `ServerContext::add_auth_method()` before calling `ServerContext::run_loop()`.
+//!
+//! Clients authenticated by an auth method are not checked by `Authenticator`s again.
+//! Instead the identity returned by the method is used as username of client, and
+//! connect packet with another username is rejected.
+
+use futures::future::BoxFuture;
+use std::fmt::Debug;
+
+/// Result of one step of authentication exchange.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthStep {
+    /// Send Authentication Data to client in an AUTH packet, and wait for its response.
+    Continue(Vec<u8>),
+
+    /// Client is authenticated, `(identity, data)` pair.
+    ///
+    /// Identity is the authenticated username, which is passed to ACL.
+    /// Authentication Data is sent to client in connect ack packet if not empty.
+    Success(String, Vec<u8>),
+
+    /// Client is rejected.
+    Failure,
+}
+
+/// State of authentication exchange of a connecting client.
+pub trait AuthExchange: Debug + Send + Sync {
+    /// Handle Authentication Data sent from client, in connect packet or in AUTH packets.
+    ///
+    /// `data` is empty if client does not send Authentication Data.
+    fn step<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, AuthStep>;
+}
+
+/// An enhanced authentication method.
+#[allow(clippy::module_name_repetitions)]
+pub trait AuthMethod: Debug + Send + Sync {
+    /// Name of this method, compared with Authentication Method in connect packet.
+    fn name(&self) -> &str;
+
+    /// Start a new authentication exchange for client with `client_id`.
+    fn start(&self, client_id: &str) -> Box<dyn AuthExchange>;
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Interface of auth backends.
+//!
+//! Besides the built-in password file and database backends, library users can
+//! implement `Authenticator` and register it with `ServerContext::add_authenticator()`
+//! before calling `ServerContext::run_loop()`.
+
+use futures::future::BoxFuture;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+
+use crate::types::ListenerId;
+
+/// Information of a client requesting to connect.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    username: String,
+    password: Vec<u8>,
+    client_id: String,
+    listener_id: ListenerId,
+    remote_address: Option<SocketAddr>,
+    identity: Option<String>,
+}
+
+impl AuthContext {
+    #[must_use]
+    pub fn new(
+        username: &str,
+        password: &[u8],
+        client_id: &str,
+        listener_id: ListenerId,
+        remote_address: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_vec(),
+            client_id: client_id.to_string(),
+            listener_id,
+            remote_address,
+            identity: None,
+        }
+    }
+
+    /// Set identity of client from transport layer.
+    pub fn set_identity(&mut self, identity: Option<String>) -> &mut Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Get username in connect packet, empty for anonymous clients.
+    #[must_use]
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    #[must_use]
+    pub fn password(&self) -> &[u8] {
+        &self.password
+    }
+
+    #[must_use]
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Get id of listener which accepted the connection.
+    #[must_use]
+    pub const fn listener_id(&self) -> ListenerId {
+        self.listener_id
+    }
+
+    /// Get address of remote client, None for unix domain socket.
+    #[must_use]
+    pub const fn remote_address(&self) -> Option<SocketAddr> {
+        self.remote_address
+    }
+
+    /// Get identity of client from transport layer, like common name of client
+    /// certificate, as specified by `identity_source` of listener.
+    #[must_use]
+    pub fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthResult {
+    /// Access is granted, remaining authenticators are skipped.
+    Granted,
+
+    /// Access is denied, remaining authenticators are skipped.
+    Denied,
+
+    /// No record of this client, ask the next authenticator.
+    ///
+    /// If all authenticators ignore a client, it is accepted only if it is anonymous
+    /// and `allow_anonymous` is set.
+    Ignored,
+}
+
+/// Auth backend which checks clients in connect packet.
+///
+/// Authenticators are asked in order of database, password file, then custom ones
+/// in registration order.
+pub trait Authenticator: Debug + Send + Sync {
+    fn authenticate<'a>(&'a self, ctx: &'a AuthContext) -> BoxFuture<'a, AuthResult>;
+}
@@ -4,13 +4,204 @@
 
 //! Interface for auth app database backend.
 
+use futures::future::BoxFuture;
+use std::fmt::Debug;
+
+use super::authenticator::{AuthContext, AuthResult, Authenticator};
+use super::pwd::PasswordHash;
 use crate::error::Error;
 
-pub trait DbAuth {
-    /// Check whether (username, password) is matched in database records.
+#[cfg(feature = "pgsql_conn")]
+use crate::config::PgSQLAuth;
+#[cfg(feature = "pgsql_conn")]
+use crate::connectors::pgsql_conn::PgSQLPool;
+
+pub trait DbAuth: Debug + Send + Sync {
+    /// Get password hash of `username` in database records.
+    ///
+    /// Returns None if user is not found.
     ///
     /// # Errors
     ///
     /// Returns error if failed to access to database.
-    fn is_match(&self, username: &str, password: &[u8]) -> Result<bool, Error>;
+    fn password_hash<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>>;
+}
+
+/// Check whether (username, password) is matched in database records.
+///
+/// # Errors
+///
+/// Returns error if failed to access to database, or password hash in database is invalid.
+pub async fn is_match(
+    db_auth: &dyn DbAuth,
+    username: &str,
+    password: &[u8],
+    allow_plain_text: bool,
+) -> Result<bool, Error> {
+    let Some(password_hash) = db_auth.password_hash(username).await? else {
+        return Ok(false);
+    };
+    let password_hash = PasswordHash::parse_hash(&password_hash, allow_plain_text)?;
+    if matches!(password_hash, PasswordHash::PlainText(_)) {
+        log::warn!("Password of user {:?} is not hashed in database", username);
+    }
+    password_hash.verify(password).await
+}
+
+/// Check clients against password hashes in database.
+#[derive(Debug)]
+pub struct DbAuthenticator {
+    db_auth: Box<dyn DbAuth>,
+    allow_plain_text: bool,
+}
+
+impl DbAuthenticator {
+    /// Create a new authenticator with `db_auth` backend.
+    ///
+    /// Passwords in plain text are rejected unless `allow_plain_text` is true.
+    #[must_use]
+    pub fn new(db_auth: Box<dyn DbAuth>, allow_plain_text: bool) -> Self {
+        Self {
+            db_auth,
+            allow_plain_text,
+        }
+    }
+}
+
+impl Authenticator for DbAuthenticator {
+    fn authenticate<'a>(&'a self, ctx: &'a AuthContext) -> BoxFuture<'a, AuthResult> {
+        Box::pin(async move {
+            if ctx.username().is_empty() {
+                return AuthResult::Ignored;
+            }
+            match is_match(
+                self.db_auth.as_ref(),
+                ctx.username(),
+                ctx.password(),
+                self.allow_plain_text,
+            )
+            .await
+            {
+                Ok(true) => AuthResult::Granted,
+                Ok(false) => AuthResult::Ignored,
+                Err(err) => {
+                    log::error!(
+                        "DbAuth: Failed to check password of user {:?} in database, err: {:?}",
+                        ctx.username(),
+                        err
+                    );
+                    AuthResult::Ignored
+                }
+            }
+        })
+    }
+}
+
+/// Query password hashes from `PgSQL` database.
+///
+/// Connections in pool are shared by all auth requests.
+#[cfg(feature = "pgsql_conn")]
+#[derive(Debug)]
+pub struct PgSQLDbAuth {
+    query: String,
+    pool: PgSQLPool,
+}
+
+#[cfg(feature = "pgsql_conn")]
+impl PgSQLDbAuth {
+    /// Create a new pgsql auth backend.
+    ///
+    /// Connections are not opened until the first auth request.
+    #[must_use]
+    pub fn new(config: &PgSQLAuth) -> Self {
+        Self {
+            query: config.query(),
+            pool: PgSQLPool::new(config.connection()),
+        }
+    }
+}
+
+#[cfg(feature = "pgsql_conn")]
+impl DbAuth for PgSQLDbAuth {
+    fn password_hash<'a>(
+        &'a self,
+        username: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+        Box::pin(async move {
+            let row = self.pool.query_opt(&self.query, &[&username]).await?;
+            row.map(|row| row.try_get(0))
+                .transpose()
+                .map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(all(test, feature = "pgsql_conn"))]
+mod tests {
+    use super::{is_match, PgSQLDbAuth};
+    use crate::auth::pwd::PasswordHash;
+    use crate::config::PgSQLAuth;
+    use crate::connectors::pgsql_conn::PgSQLConn;
+
+    #[test]
+    #[ignore = "requires a running pgsql server"]
+    fn test_pgsql_auth() {
+        let config: PgSQLAuth = toml::from_str(
+            r#"
+        password = "hebo-password"
+        table = "hebo_auth_test"
+        "#,
+        )
+        .unwrap();
+        let db_auth = PgSQLDbAuth::new(&config);
+        let password_hash = bcrypt::hash("password1", 4).unwrap();
+        assert!(PasswordHash::parse_hash(&password_hash, false).is_ok());
+
+        tokio_test::block_on(async {
+            let mut conn = PgSQLConn::connect(config.connection()).await.unwrap();
+            let conn = conn.get_conn();
+            conn.batch_execute(
+                "CREATE TABLE IF NOT EXISTS hebo_auth_test \
+                 (username TEXT PRIMARY KEY, password_hash TEXT NOT NULL)",
+            )
+            .await
+            .unwrap();
+            conn.execute(
+                "INSERT INTO hebo_auth_test VALUES ($1, $2) ON CONFLICT (username) \
+                 DO UPDATE SET password_hash = EXCLUDED.password_hash",
+                &[&"user1", &password_hash],
+            )
+            .await
+            .unwrap();
+
+            assert!(is_match(&db_auth, "user1", b"password1", false)
+                .await
+                .unwrap());
+            assert!(!is_match(&db_auth, "user1", b"password2", false)
+                .await
+                .unwrap());
+            assert!(!is_match(&db_auth, "user2", b"password1", false)
+                .await
+                .unwrap());
+
+            // Malformed record is reported as error instead of panic.
+            conn.execute(
+                "INSERT INTO hebo_auth_test VALUES ($1, $2) ON CONFLICT (username) \
+                 DO UPDATE SET password_hash = EXCLUDED.password_hash",
+                &[&"user3", &"$6$c2hvcnQ=$c2hvcnQ="],
+            )
+            .await
+            .unwrap();
+            assert!(is_match(&db_auth, "user3", b"password1", false)
+                .await
+                .is_err());
+
+            conn.batch_execute("DROP TABLE hebo_auth_test")
+                .await
+                .unwrap();
+        });
+    }
 }
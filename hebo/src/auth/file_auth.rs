@@ -2,33 +2,37 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
+use futures::future::BoxFuture;
 use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 
-use super::pwd::Password;
+use super::authenticator::{AuthContext, AuthResult, Authenticator};
+use super::pwd::{Password, PasswordHash};
 use crate::error::{Error, ErrorKind};
 
 /// `FileAuth` represents records in `password_file`.
 #[derive(Debug)]
-pub struct FileAuth(BTreeMap<String, Password>);
+pub struct FileAuth(BTreeMap<String, PasswordHash>);
 
 impl FileAuth {
     /// Parse `password_file`.
     ///
+    /// Entries with password in plain text are skipped unless `allow_plain_text` is true.
+    ///
     /// # Errors
     ///
     /// Returns error if:
     /// - Failed to read `password_file`
     /// - File format error
-    pub fn new<P: AsRef<Path>>(password_file: P) -> Result<Self, Error> {
+    pub fn new<P: AsRef<Path>>(password_file: P, allow_plain_text: bool) -> Result<Self, Error> {
         let fd = File::open(password_file.as_ref())?;
         let reader = BufReader::new(fd);
         let mut map = BTreeMap::new();
         for line in reader.lines() {
             let line = line?;
-            match Password::parse(&line) {
+            match PasswordHash::parse(&line, allow_plain_text) {
                 Err(err) => {
                     log::error!("err: {:?}, line: {}", err, line);
                 }
@@ -54,6 +58,37 @@ impl FileAuth {
             .get(username)
             .map_or(Ok(false), |p| p.is_match(password))
     }
+
+    /// Same as `is_match()`, but slow password hashes are calculated in a blocking thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to calculate password hash.
+    pub async fn verify(&self, username: &str, password: &[u8]) -> Result<bool, Error> {
+        match self.0.get(username) {
+            Some(p) => p.verify(password).await,
+            None => Ok(false),
+        }
+    }
+}
+
+impl Authenticator for FileAuth {
+    fn authenticate<'a>(&'a self, ctx: &'a AuthContext) -> BoxFuture<'a, AuthResult> {
+        Box::pin(async move {
+            match self.verify(ctx.username(), ctx.password()).await {
+                Ok(true) => AuthResult::Granted,
+                Ok(false) => AuthResult::Ignored,
+                Err(err) => {
+                    log::error!(
+                        "FileAuth: Failed to check password of user {:?}, err: {:?}",
+                        ctx.username(),
+                        err
+                    );
+                    AuthResult::Ignored
+                }
+            }
+        })
+    }
 }
 
 /// Update hash value of all items in `password_file`.
@@ -107,6 +142,7 @@ pub fn add_delete_users<P: AsRef<Path>>(
 ) -> Result<(), Error> {
     let fd = OpenOptions::new()
         .create(true)
+        .truncate(false)
         .read(true)
         .write(true)
         .open(password_file.as_ref())?;
@@ -114,7 +150,7 @@ pub fn add_delete_users<P: AsRef<Path>>(
     let mut users = BTreeMap::new();
     for line in reader.lines() {
         let line = line?;
-        match Password::parse(&line) {
+        match PasswordHash::parse(&line, false) {
             Err(err) => {
                 log::error!("Failed to parse line {:?}, got err: {:?}", line, err);
                 return Err(err);
@@ -140,7 +176,7 @@ pub fn add_delete_users<P: AsRef<Path>>(
                 // continue
             }
             Ok(Some((username, password))) => {
-                users.insert(username.to_string(), password);
+                users.insert(username.to_string(), PasswordHash::Sha512(password));
             }
         }
     }
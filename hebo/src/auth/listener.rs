@@ -3,45 +3,83 @@
 // in the LICENSE file.
 
 use codec::{v3, v5};
+use std::net::SocketAddr;
 
-use super::AuthApp;
+use super::{AuthApp, AuthContext, AuthResult, Authenticator};
 use crate::commands::{AuthToListenerCmd, ListenerToAuthCmd};
 use crate::error::{Error, ErrorKind};
 use crate::types::SessionGid;
 
 impl AuthApp {
-    pub(super) async fn handle_listener_cmd(
-        &mut self,
-        cmd: ListenerToAuthCmd,
-    ) -> Result<(), Error> {
+    /// Ask authenticators in order until one of them grants or denies access.
+    ///
+    /// Clients ignored by all authenticators are accepted only if they are anonymous
+    /// and anonymous access is allowed.
+    async fn check_auth(&self, ctx: &AuthContext) -> bool {
+        let authenticators = self
+            .db_auth
+            .iter()
+            .map(|auth| auth as &dyn Authenticator)
+            .chain(self.file_auth.iter().map(|auth| auth as &dyn Authenticator))
+            .chain(self.authenticators.iter().map(AsRef::as_ref));
+        for authenticator in authenticators {
+            match authenticator.authenticate(ctx).await {
+                AuthResult::Granted => return true,
+                AuthResult::Denied => {
+                    log::warn!(
+                        "AuthApp: Access denied, client id: {:?}, user: {:?}",
+                        ctx.client_id(),
+                        ctx.username()
+                    );
+                    return false;
+                }
+                AuthResult::Ignored => (),
+            }
+        }
+
+        if ctx.username().is_empty() {
+            if !self.allow_anonymous {
+                log::warn!("AuthApp: Anonymous access is not allowed");
+            }
+            return self.allow_anonymous;
+        }
+        log::warn!(
+            "AuthApp: Invalid username or password, user: {:?}",
+            ctx.username()
+        );
+        false
+    }
+
+    pub(super) async fn handle_listener_cmd(&self, cmd: ListenerToAuthCmd) -> Result<(), Error> {
         log::info!("AuthApp::handle_listener_cmd(), cmd: {:?}", cmd);
         match cmd {
-            ListenerToAuthCmd::RequestAuth(session_gid, packet) => {
-                self.on_listener_request_auth(session_gid, packet).await
+            ListenerToAuthCmd::RequestAuth(session_gid, address, identity, packet) => {
+                self.on_listener_request_auth(session_gid, address, identity, packet)
+                    .await
             }
-            ListenerToAuthCmd::RequestAuthV5(session_gid, packet) => {
-                self.on_listener_request_auth_v5(session_gid, packet).await
+            ListenerToAuthCmd::RequestAuthV5(session_gid, address, identity, packet) => {
+                self.on_listener_request_auth_v5(session_gid, address, identity, packet)
+                    .await
             }
         }
     }
 
     async fn on_listener_request_auth(
-        &mut self,
+        &self,
         session_gid: SessionGid,
+        address: Option<SocketAddr>,
+        identity: Option<String>,
         packet: v3::ConnectPacket,
     ) -> Result<(), Error> {
-        let username = packet.username();
-        let password = packet.password();
-        let access_granted = if username.is_empty() {
-            self.allow_anonymous
-        } else if let Some(file_auth) = &self.file_auth {
-            file_auth.is_match(username, password)?
-        } else {
-            false
-        };
-        if !access_granted {
-            log::error!("AuthApp: Check auth failed, {}:{:?}", username, password);
-        }
+        let mut ctx = AuthContext::new(
+            packet.username(),
+            packet.password(),
+            packet.client_id(),
+            session_gid.listener_id(),
+            address,
+        );
+        ctx.set_identity(identity);
+        let access_granted = self.check_auth(&ctx).await;
         for (sender_listener_id, sender) in &self.listener_senders {
             if *sender_listener_id == session_gid.listener_id() {
                 let cmd = AuthToListenerCmd::ResponseAuth(
@@ -49,7 +87,7 @@ impl AuthApp {
                     access_granted,
                     packet,
                 );
-                sender.send(cmd).await?;
+                sender.send(cmd)?;
                 return Ok(());
             }
         }
@@ -64,22 +102,28 @@ impl AuthApp {
     }
 
     async fn on_listener_request_auth_v5(
-        &mut self,
+        &self,
         session_gid: SessionGid,
+        address: Option<SocketAddr>,
+        identity: Option<String>,
         packet: v5::ConnectPacket,
     ) -> Result<(), Error> {
-        let username = packet.username();
-        let password = packet.password();
-        let access_granted = if username.is_empty() {
-            self.allow_anonymous
-        } else if let Some(file_auth) = &self.file_auth {
-            file_auth.is_match(username, password)?
-        } else {
-            false
-        };
-        if !access_granted {
-            log::error!("AuthApp: Check auth failed, {:?}:{:?}", username, password);
-        }
+        let mut ctx = AuthContext::new(
+            packet.username(),
+            packet.password(),
+            packet.client_id(),
+            session_gid.listener_id(),
+            address,
+        );
+        ctx.set_identity(identity);
+        // Sessions forward connect packet with Authentication Method only if the client
+        // is authenticated by that auth method, with username set to the authenticated identity.
+        let has_auth_method = packet
+            .properties()
+            .props()
+            .iter()
+            .any(|property| matches!(property, v5::Property::AuthenticationMethod(_)));
+        let access_granted = has_auth_method || self.check_auth(&ctx).await;
         for (sender_listener_id, sender) in &self.listener_senders {
             if *sender_listener_id == session_gid.listener_id() {
                 let cmd = AuthToListenerCmd::ResponseAuthV5(
@@ -87,7 +131,7 @@ impl AuthApp {
                     access_granted,
                     packet,
                 );
-                sender.send(cmd).await?;
+                sender.send(cmd)?;
                 return Ok(());
             }
         }
@@ -101,3 +145,214 @@ impl AuthApp {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5, StringData};
+    use futures::future::BoxFuture;
+    use std::collections::HashMap;
+    use std::fs;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+    use super::AuthApp;
+    use crate::auth::db_auth::{DbAuth, DbAuthenticator};
+    use crate::auth::file_auth::FileAuth;
+    use crate::auth::pwd::Password;
+    use crate::auth::{AuthContext, AuthResult, Authenticator};
+    use crate::commands::AuthToListenerCmd;
+    use crate::error::{Error, ErrorKind};
+    use crate::types::SessionGid;
+
+    /// Username -> password hash, user `broken` emulates database failure.
+    #[derive(Debug)]
+    struct MockDbAuth(HashMap<String, String>);
+
+    impl DbAuth for MockDbAuth {
+        fn password_hash<'a>(
+            &'a self,
+            username: &'a str,
+        ) -> BoxFuture<'a, Result<Option<String>, Error>> {
+            Box::pin(async move {
+                if username == "broken" {
+                    return Err(Error::new(ErrorKind::PgSQLError, "Connection refused"));
+                }
+                Ok(self.0.get(username).cloned())
+            })
+        }
+    }
+
+    fn new_app(
+        name: &str,
+        allow_anonymous: bool,
+    ) -> (AuthApp, UnboundedReceiver<AuthToListenerCmd>) {
+        let password_file = std::env::temp_dir().join(format!("hebo-auth-{name}.passwd"));
+        let content = format!(
+            "# Test users.\n{}\n{}\n",
+            Password::generate(b"password1").unwrap().dump("user1"),
+            Password::generate(b"password2").unwrap().dump("user2"),
+        );
+        fs::write(&password_file, content).unwrap();
+        let file_auth = FileAuth::new(&password_file, false).unwrap();
+        fs::remove_file(&password_file).unwrap();
+
+        let (listener_sender, receiver) = mpsc::unbounded_channel();
+        let (_sender, listener_receiver) = mpsc::channel(4);
+        let (_sender, server_ctx_receiver) = mpsc::channel(4);
+        let app = AuthApp {
+            allow_anonymous,
+            db_auth: None,
+            file_auth: Some(file_auth),
+            authenticators: Vec::new(),
+            listener_senders: vec![(1, listener_sender)],
+            listener_receiver,
+            server_ctx_receiver,
+        };
+        (app, receiver)
+    }
+
+    async fn request_auth(
+        app: &AuthApp,
+        receiver: &mut UnboundedReceiver<AuthToListenerCmd>,
+        username: &str,
+        password: &[u8],
+    ) -> bool {
+        request_auth_with_client_id(app, receiver, "auth-test", username, password).await
+    }
+
+    async fn request_auth_with_client_id(
+        app: &AuthApp,
+        receiver: &mut UnboundedReceiver<AuthToListenerCmd>,
+        client_id: &str,
+        username: &str,
+        password: &[u8],
+    ) -> bool {
+        let mut packet = v3::ConnectPacket::new(client_id).unwrap();
+        if !username.is_empty() {
+            packet.set_username(username).unwrap();
+            packet.set_password(password).unwrap();
+        }
+        app.on_listener_request_auth(SessionGid::new(1, 2), None, None, packet)
+            .await
+            .unwrap();
+        let Some(AuthToListenerCmd::ResponseAuth(2, access_granted, _packet)) =
+            receiver.recv().await
+        else {
+            panic!("Expected auth response");
+        };
+        access_granted
+    }
+
+    #[tokio::test]
+    async fn test_password_file() {
+        let (app, mut receiver) = new_app("password-file", false);
+        assert!(request_auth(&app, &mut receiver, "user1", b"password1").await);
+        assert!(request_auth(&app, &mut receiver, "user2", b"password2").await);
+        // Wrong password.
+        assert!(!request_auth(&app, &mut receiver, "user1", b"password2").await);
+        assert!(!request_auth(&app, &mut receiver, "user1", b"").await);
+        // Unknown user.
+        assert!(!request_auth(&app, &mut receiver, "user3", b"password1").await);
+        // Anonymous not allowed.
+        assert!(!request_auth(&app, &mut receiver, "", b"").await);
+
+        // Client is authenticated by enhanced auth method in session.
+        let mut packet = v5::ConnectPacket::new("auth-test").unwrap();
+        packet
+            .properties_mut()
+            .push(v5::Property::AuthenticationMethod(
+                StringData::from("SCRAM-SHA-1").unwrap(),
+            ))
+            .unwrap();
+        app.on_listener_request_auth_v5(SessionGid::new(1, 3), None, None, packet)
+            .await
+            .unwrap();
+        assert!(matches!(
+            receiver.recv().await,
+            Some(AuthToListenerCmd::ResponseAuthV5(3, true, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_allow_anonymous() {
+        let (app, mut receiver) = new_app("allow-anonymous", true);
+        assert!(request_auth(&app, &mut receiver, "", b"").await);
+        // Password is still checked if username is set.
+        assert!(!request_auth(&app, &mut receiver, "user1", b"password2").await);
+
+        let mut packet = v5::ConnectPacket::new("auth-test").unwrap();
+        packet.set_username(Some("user1")).unwrap();
+        packet.set_password(Some(b"password1")).unwrap();
+        app.on_listener_request_auth_v5(SessionGid::new(1, 3), None, None, packet)
+            .await
+            .unwrap();
+        assert!(matches!(
+            receiver.recv().await,
+            Some(AuthToListenerCmd::ResponseAuthV5(3, true, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_db_auth() {
+        let (mut app, mut receiver) = new_app("db-auth", false);
+        let mut users = HashMap::new();
+        let entry = Password::generate(b"db-password3").unwrap().dump("user3");
+        let (_username, password_hash) = entry.split_once(':').unwrap();
+        users.insert("user3".to_string(), password_hash.to_string());
+        users.insert("user4".to_string(), "plain-password4".to_string());
+        users.insert("broken".to_string(), password_hash.to_string());
+        app.db_auth = Some(DbAuthenticator::new(
+            Box::new(MockDbAuth(users.clone())),
+            false,
+        ));
+
+        assert!(request_auth(&app, &mut receiver, "user3", b"db-password3").await);
+        assert!(!request_auth(&app, &mut receiver, "user3", b"password1").await);
+        // Plain text password in database is rejected by default.
+        assert!(!request_auth(&app, &mut receiver, "user4", b"plain-password4").await);
+        // Database error is not treated as a match.
+        assert!(!request_auth(&app, &mut receiver, "broken", b"db-password3").await);
+        // Falls back to password file.
+        assert!(request_auth(&app, &mut receiver, "user1", b"password1").await);
+
+        app.db_auth = Some(DbAuthenticator::new(Box::new(MockDbAuth(users)), true));
+        assert!(request_auth(&app, &mut receiver, "user4", b"plain-password4").await);
+    }
+
+    /// Grant only client with `client_id`, ignore anonymous clients.
+    #[derive(Debug)]
+    struct ClientIdAuth {
+        client_id: String,
+    }
+
+    impl Authenticator for ClientIdAuth {
+        fn authenticate<'a>(&'a self, ctx: &'a AuthContext) -> BoxFuture<'a, AuthResult> {
+            Box::pin(async move {
+                if ctx.username().is_empty() {
+                    AuthResult::Ignored
+                } else if ctx.client_id() == self.client_id && ctx.listener_id() == 1 {
+                    AuthResult::Granted
+                } else {
+                    AuthResult::Denied
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_authenticator() {
+        let (mut app, mut receiver) = new_app("custom-authenticator", true);
+        app.authenticators.push(Box::new(ClientIdAuth {
+            client_id: "sensor-1".to_string(),
+        }));
+
+        assert!(request_auth_with_client_id(&app, &mut receiver, "sensor-1", "device", b"").await);
+        assert!(!request_auth_with_client_id(&app, &mut receiver, "sensor-2", "device", b"").await);
+        // Built-in authenticators are asked first.
+        assert!(
+            request_auth_with_client_id(&app, &mut receiver, "sensor-2", "user1", b"password1")
+                .await
+        );
+        // Anonymous clients are ignored by custom authenticator.
+        assert!(request_auth_with_client_id(&app, &mut receiver, "sensor-2", "", b"").await);
+    }
+}
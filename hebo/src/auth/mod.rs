@@ -2,13 +2,15 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::{Receiver, UnboundedSender};
 
 use crate::commands::{AuthToListenerCmd, ListenerToAuthCmd, ServerContextToAuthCmd};
 use crate::config::Security;
 use crate::error::{Error, ErrorKind};
 use crate::types::ListenerId;
 
+pub mod auth_method;
+pub mod authenticator;
 #[allow(clippy::module_name_repetitions)]
 pub mod db_auth;
 #[allow(clippy::module_name_repetitions)]
@@ -17,15 +19,79 @@ mod listener;
 pub mod pwd;
 mod server;
 
+pub use auth_method::{AuthExchange, AuthMethod, AuthStep};
+pub use authenticator::{AuthContext, AuthResult, Authenticator};
+use db_auth::DbAuthenticator;
 use file_auth::FileAuth;
 
+/// Auth settings loaded from security section of config.
+///
+/// Built before being passed to auth app, so that invalid config is rejected on reload
+/// without touching running apps.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub struct AuthSettings {
+    allow_anonymous: bool,
+    db_auth: Option<DbAuthenticator>,
+    file_auth: Option<FileAuth>,
+}
+
+impl AuthSettings {
+    /// Load auth settings from `security`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read password file.
+    pub fn new(security: &Security) -> Result<Self, Error> {
+        Ok(Self {
+            allow_anonymous: security.allow_anonymous(),
+            db_auth: Self::new_db_auth(security),
+            file_auth: Self::new_file_auth(security)?,
+        })
+    }
+
+    fn new_file_auth(security: &Security) -> Result<Option<FileAuth>, Error> {
+        if let Some(password_file) = security.password_file() {
+            let file_auth = FileAuth::new(password_file, security.allow_plain_text_password())
+                .map_err(|err| {
+                    Error::from_string(
+                        ErrorKind::ConfigError,
+                        format!(
+                            "Invalid password file: {}, err: {err:?}",
+                            password_file.display()
+                        ),
+                    )
+                })?;
+            Ok(Some(file_auth))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[allow(unused_variables, clippy::missing_const_for_fn)]
+    fn new_db_auth(security: &Security) -> Option<DbAuthenticator> {
+        #[cfg(feature = "pgsql_conn")]
+        if let Some(pgsql_auth) = security.pgsql_auth() {
+            return Some(DbAuthenticator::new(
+                Box::new(db_auth::PgSQLDbAuth::new(pgsql_auth)),
+                security.allow_plain_text_password(),
+            ));
+        }
+        None
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct AuthApp {
     allow_anonymous: bool,
+    db_auth: Option<DbAuthenticator>,
     file_auth: Option<FileAuth>,
+    /// Authenticators registered by library users, asked after built-in ones.
+    authenticators: Vec<Box<dyn Authenticator>>,
 
-    listener_senders: Vec<(ListenerId, Sender<AuthToListenerCmd>)>,
+    /// Unbounded, or auth app and listener may wait for each other when their queues are full.
+    listener_senders: Vec<(ListenerId, UnboundedSender<AuthToListenerCmd>)>,
     listener_receiver: Receiver<ListenerToAuthCmd>,
 
     server_ctx_receiver: Receiver<ServerContextToAuthCmd>,
@@ -39,27 +105,24 @@ impl AuthApp {
     /// Returns error if failed to read password file.
     pub fn new(
         security: &Security,
+        authenticators: Vec<Box<dyn Authenticator>>,
         // listeners
-        listener_senders: Vec<(ListenerId, Sender<AuthToListenerCmd>)>,
+        listener_senders: Vec<(ListenerId, UnboundedSender<AuthToListenerCmd>)>,
         listener_receiver: Receiver<ListenerToAuthCmd>,
         // server ctx module
         server_ctx_receiver: Receiver<ServerContextToAuthCmd>,
     ) -> Result<Self, Error> {
-        let file_auth = if let Some(password_file) = security.password_file() {
-            let file_auth = FileAuth::new(password_file).map_err(|err| {
-                Error::from_string(
-                    ErrorKind::ConfigError,
-                    format!("Invalid password file: {password_file:?}, err: {err:?}"),
-                )
-            })?;
-            Some(file_auth)
-        } else {
-            None
-        };
+        let AuthSettings {
+            allow_anonymous,
+            db_auth,
+            file_auth,
+        } = AuthSettings::new(security)?;
 
         Ok(Self {
-            allow_anonymous: security.allow_anonymous(),
+            allow_anonymous,
+            db_auth,
             file_auth,
+            authenticators,
 
             listener_senders,
             listener_receiver,
@@ -68,7 +131,7 @@ impl AuthApp {
         })
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         loop {
             tokio::select! {
                 Some(cmd) = self.listener_receiver.recv() => {
@@ -77,6 +140,10 @@ impl AuthApp {
                     }
                 },
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToAuthCmd::Stop) {
+                        log::info!("auth: Stop app");
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
@@ -4,6 +4,8 @@
 
 use base64::Engine;
 use openssl::hash::{Hasher, MessageDigest};
+use openssl::memcmp;
+use openssl::pkcs5;
 use rand::Rng;
 
 use crate::error::{Error, ErrorKind};
@@ -11,6 +13,10 @@ use crate::error::{Error, ErrorKind};
 pub const SALT_LEN: usize = 12;
 pub const HASH_LEN: usize = 64;
 pub const PW_SHA512: i32 = 6;
+/// PBKDF2-SHA512 hash generated by `mosquitto_passwd`.
+pub const PW_SHA512_PBKDF2: i32 = 7;
+/// Prefix of PBKDF2 hash in `PBKDF2$digest$iterations$salt$hash` format.
+pub const PBKDF2_PREFIX: &str = "PBKDF2$";
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct Salt([u8; SALT_LEN]);
@@ -40,14 +46,14 @@ impl Hash {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Password {
     salt: Salt,
-    password_hash: Hash,
+    hash: Hash,
     valid: bool,
 }
 
 impl Password {
     #[must_use]
     pub const fn hash(&self) -> &[u8] {
-        &self.password_hash.0
+        &self.hash.0
     }
 
     #[must_use]
@@ -153,12 +159,15 @@ impl Password {
 
         let b64 = base64::engine::general_purpose::STANDARD;
         let salt = b64.decode(parts[2])?;
-        let salt = Salt::from_slice(&salt);
         let password_hash = b64.decode(parts[3])?;
+        if salt.len() != SALT_LEN || password_hash.len() != HASH_LEN {
+            return err;
+        }
+        let salt = Salt::from_slice(&salt);
         let password_hash = Hash::from_slice(&password_hash);
         Ok(Self {
             salt,
-            password_hash,
+            hash: password_hash,
             valid: true,
         })
     }
@@ -169,7 +178,7 @@ impl Password {
         if self.valid {
             let b64 = base64::engine::general_purpose::STANDARD;
             let salt = b64.encode(self.salt.0);
-            let hash = b64.encode(self.password_hash.0);
+            let hash = b64.encode(self.hash.0);
             format!("{username}:${PW_SHA512}${salt}${hash}")
         } else {
             format!("{username}:")
@@ -186,7 +195,7 @@ impl Password {
         if password.is_empty() {
             return Ok(Self {
                 salt,
-                password_hash: Hash::new(),
+                hash: Hash::new(),
                 valid: false,
             });
         }
@@ -198,7 +207,7 @@ impl Password {
         let password_hash = Hash::from_slice(res.as_ref());
         Ok(Self {
             salt,
-            password_hash,
+            hash: password_hash,
             valid: true,
         })
     }
@@ -214,7 +223,7 @@ impl Password {
         h.update(&self.salt.0)?;
         let res = h.finish()?;
         debug_assert!(res.as_ref().len() == HASH_LEN);
-        self.password_hash.0.copy_from_slice(res.as_ref());
+        self.hash.0.copy_from_slice(res.as_ref());
         Ok(())
     }
 
@@ -228,7 +237,228 @@ impl Password {
         h.update(password)?;
         h.update(&self.salt.0)?;
         let res = h.finish()?;
-        Ok(self.password_hash.0 == res.as_ref())
+        // Compare in constant time to avoid leaking hash prefix through timing.
+        Ok(memcmp::eq(&self.hash.0, res.as_ref()))
+    }
+}
+
+/// Password record in password file, in one of the supported hash formats.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordHash {
+    /// Salted sha512 hash, `$6$salt$hash`, generated by hebo-passwd.
+    Sha512(Password),
+
+    /// PBKDF2 hash, `$7$iterations$salt$hash` or `PBKDF2$digest$iterations$salt$hash`.
+    Pbkdf2(Pbkdf2Hash),
+
+    /// bcrypt hash, `$2b$cost$salt_and_hash`.
+    Bcrypt(String),
+
+    /// Password in plain text, only accepted if `allow_plain_text_password` is enabled.
+    PlainText(String),
+}
+
+/// Digest used in PBKDF2 hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pbkdf2Digest {
+    Sha256,
+    Sha512,
+}
+
+impl Pbkdf2Digest {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    fn message_digest(self) -> MessageDigest {
+        match self {
+            Self::Sha256 => MessageDigest::sha256(),
+            Self::Sha512 => MessageDigest::sha512(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pbkdf2Hash {
+    digest: Pbkdf2Digest,
+    iterations: usize,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+
+    /// Original text in password file, kept when file is rewritten.
+    entry: String,
+}
+
+impl Pbkdf2Hash {
+    /// Parse `$7$iterations$salt$hash` or `PBKDF2$digest$iterations$salt$hash`.
+    fn parse(s: &str) -> Result<Self, Error> {
+        let err = || {
+            Error::from_string(
+                ErrorKind::FormatError,
+                format!("Invalid PBKDF2 password: {s:?}"),
+            )
+        };
+        let parts: Vec<&str> = s.split('$').collect();
+        let (digest, parts) = match parts.as_slice() {
+            ["", hash_type, rest @ ..] if *hash_type == PW_SHA512_PBKDF2.to_string() => {
+                (Pbkdf2Digest::Sha512, rest)
+            }
+            ["PBKDF2", digest, rest @ ..] => (Pbkdf2Digest::parse(digest).ok_or_else(err)?, rest),
+            _ => return Err(err()),
+        };
+        let [iterations, salt, hash] = parts else {
+            return Err(err());
+        };
+        let iterations: usize = iterations.parse().map_err(|_| err())?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let salt = b64.decode(salt)?;
+        let hash = b64.decode(hash)?;
+        if iterations == 0 || hash.is_empty() {
+            return Err(err());
+        }
+        Ok(Self {
+            digest,
+            iterations,
+            salt,
+            hash,
+            entry: s.to_string(),
+        })
+    }
+
+    fn is_match(&self, password: &[u8]) -> Result<bool, Error> {
+        let mut key = vec![0; self.hash.len()];
+        pkcs5::pbkdf2_hmac(
+            password,
+            &self.salt,
+            self.iterations,
+            self.digest.message_digest(),
+            &mut key,
+        )?;
+        Ok(memcmp::eq(&self.hash, &key))
+    }
+}
+
+impl PasswordHash {
+    /// Parse password entry from string, format of password is detected by its prefix.
+    ///
+    /// Password which is not hashed is accepted only if `allow_plain_text` is true.
+    ///
+    /// Returns (username, `PasswordHash`) pair if success.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - String slice contains invalid password record
+    /// - Password is in plain text but `allow_plain_text` is false
+    pub fn parse(s: &str, allow_plain_text: bool) -> Result<Option<(&str, Self)>, Error> {
+        if s.is_empty() || s.starts_with('#') {
+            return Ok(None);
+        }
+        let Some((username, password)) = s.split_once(':') else {
+            return Err(Error::from_string(
+                ErrorKind::FormatError,
+                format!("Invalid password entry: {s:?}"),
+            ));
+        };
+        if username.is_empty() {
+            return Err(Error::from_string(
+                ErrorKind::FormatError,
+                format!("Username is empty in entry: {s:?}"),
+            ));
+        }
+
+        let password_hash = Self::parse_hash(password, allow_plain_text).map_err(|err| {
+            Error::from_string(
+                ErrorKind::FormatError,
+                format!("Invalid password of user: {username:?}, err: {err:?}"),
+            )
+        })?;
+        if matches!(password_hash, Self::PlainText(_)) {
+            log::warn!("Password of user {:?} is not hashed", username);
+        }
+        Ok(Some((username, password_hash)))
+    }
+
+    /// Parse password hash without username, format of password is detected by its prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if password hash is invalid, or password is in plain text
+    /// but `allow_plain_text` is false.
+    pub fn parse_hash(password: &str, allow_plain_text: bool) -> Result<Self, Error> {
+        if password.starts_with(&format!("${PW_SHA512}$")) {
+            Ok(Self::Sha512(Password::parse_password(password)?))
+        } else if password.starts_with(&format!("${PW_SHA512_PBKDF2}$"))
+            || password.starts_with(PBKDF2_PREFIX)
+        {
+            Ok(Self::Pbkdf2(Pbkdf2Hash::parse(password)?))
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| password.starts_with(prefix))
+        {
+            Ok(Self::Bcrypt(password.to_string()))
+        } else if allow_plain_text {
+            Ok(Self::PlainText(password.to_string()))
+        } else {
+            Err(Error::new(
+                ErrorKind::FormatError,
+                "Unsupported password format",
+            ))
+        }
+    }
+
+    /// Generate password entry.
+    #[must_use]
+    pub fn dump(&self, username: &str) -> String {
+        match self {
+            Self::Sha512(password) => password.dump(username),
+            Self::Pbkdf2(hash) => format!("{username}:{}", hash.entry),
+            Self::Bcrypt(hash) => format!("{username}:{hash}"),
+            Self::PlainText(password) => format!("{username}:{password}"),
+        }
+    }
+
+    /// Check whether `password` matches this record, in constant time.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to calculate password hash.
+    pub fn is_match(&self, password: &[u8]) -> Result<bool, Error> {
+        match self {
+            Self::Sha512(p) => p.is_match(password),
+            Self::Pbkdf2(hash) => hash.is_match(password),
+            Self::Bcrypt(hash) => bcrypt::verify(password, hash).map_err(Into::into),
+            Self::PlainText(p) => {
+                Ok(p.len() == password.len() && memcmp::eq(p.as_bytes(), password))
+            }
+        }
+    }
+
+    /// Check whether `password` matches this record, without blocking async runtime.
+    ///
+    /// Bcrypt and PBKDF2 hashes are slow by design, they are calculated in a blocking thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to calculate password hash.
+    pub async fn verify(&self, password: &[u8]) -> Result<bool, Error> {
+        if !matches!(self, Self::Bcrypt(_) | Self::Pbkdf2(_)) {
+            return self.is_match(password);
+        }
+        let hash = self.clone();
+        let password = password.to_vec();
+        tokio::task::spawn_blocking(move || hash.is_match(&password))
+            .await
+            .map_err(|err| {
+                Error::from_string(
+                    ErrorKind::IoError,
+                    format!("Password hash task panicked, err: {err:?}"),
+                )
+            })?
     }
 }
 
@@ -261,5 +491,78 @@ mod tests {
     fn test_is_match() {
         let p = Password::generate(b"password").unwrap();
         assert!(p.is_match(b"password").unwrap());
+        assert!(!p.is_match(b"passwore").unwrap());
+        assert!(!p.is_match(b"").unwrap());
+    }
+
+    #[test]
+    fn test_sha512_invalid_length() {
+        let p = Password::generate(b"password").unwrap();
+        let entry = p.dump("user");
+        assert!(PasswordHash::parse(&entry, false).is_ok());
+
+        // Salt or hash of wrong length is rejected instead of panic.
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let salt = b64.encode(p.salt());
+        let hash = b64.encode(p.hash());
+        let short = b64.encode(b"short");
+        for password in [
+            format!("${PW_SHA512}${short}${hash}"),
+            format!("${PW_SHA512}${salt}${short}"),
+            format!("${PW_SHA512}${salt}$"),
+        ] {
+            let err = PasswordHash::parse_hash(&password, false).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::FormatError));
+        }
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        let entries = [
+            // Generated by mosquitto_passwd.
+            "user:$7$101$aGViby1wYmtkZjIh$b4Ye61kJywCb0eZIO5YJ3FZMg+QL5vCIrmEWAUFZk6ftT/icduS5Uu4nnmFMAKd/25K+CCJSbYUMjEoV5E3u0A==",
+            "user:PBKDF2$sha256$1000$MDEyMzQ1Njc4OWFiY2RlZg==$hRRjgXWkW8ResfIvBP99J/T4vkgEmMRV/0tJTOjR59I=",
+        ];
+        for entry in entries {
+            let (username, p) = PasswordHash::parse(entry, false).unwrap().unwrap();
+            assert_eq!(username, "user");
+            assert!(matches!(p, PasswordHash::Pbkdf2(_)));
+            assert!(p.is_match(b"password").unwrap());
+            assert!(!p.is_match(b"passwore").unwrap());
+            assert_eq!(p.dump(username), entry);
+        }
+        assert!(PasswordHash::parse("user:PBKDF2$md5$1000$MDEy$hRRj", false).is_err());
+        assert!(PasswordHash::parse("user:$7$0$MDEy$hRRj", false).is_err());
+    }
+
+    #[test]
+    fn test_bcrypt() {
+        let entry = "user:$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+        let (username, p) = PasswordHash::parse(entry, false).unwrap().unwrap();
+        assert_eq!(username, "user");
+        assert!(matches!(p, PasswordHash::Bcrypt(_)));
+        assert!(p.is_match(b"U*U").unwrap());
+        assert!(!p.is_match(b"U*V").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let entry = "user:$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+        let (_username, p) = PasswordHash::parse(entry, false).unwrap().unwrap();
+        assert!(p.verify(b"U*U").await.unwrap());
+        assert!(!p.verify(b"U*V").await.unwrap());
+
+        let (_username, p) = PasswordHash::parse("user:password", true).unwrap().unwrap();
+        assert!(p.verify(b"password").await.unwrap());
+        assert!(!p.verify(b"passwore").await.unwrap());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert!(PasswordHash::parse("user:password", false).is_err());
+        let (_username, p) = PasswordHash::parse("user:password", true).unwrap().unwrap();
+        assert!(p.is_match(b"password").unwrap());
+        assert!(!p.is_match(b"passwor").unwrap());
+        assert!(!p.is_match(b"passwore").unwrap());
     }
 }
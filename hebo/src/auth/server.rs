@@ -4,12 +4,35 @@
 
 //! Handles commands from server context.
 
-use super::AuthApp;
+use super::{AuthApp, AuthSettings};
 use crate::commands::ServerContextToAuthCmd;
 
 impl AuthApp {
     #[allow(clippy::unused_async)]
     pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToAuthCmd) {
-        log::info!("cmd: {:?}", cmd);
+        match cmd {
+            ServerContextToAuthCmd::ListenerAdded(listener_id, sender) => {
+                log::info!("auth: Add listener #{}", listener_id);
+                self.listener_senders.push((listener_id, sender));
+            }
+            ServerContextToAuthCmd::ListenerRemoved(listener_id) => {
+                log::info!("auth: Remove listener #{}", listener_id);
+                self.listener_senders
+                    .retain(|(id, _sender)| *id != listener_id);
+            }
+            ServerContextToAuthCmd::UpdateSecurity(settings) => {
+                self.update_security(*settings);
+            }
+            // Handled in run_loop().
+            ServerContextToAuthCmd::Stop => (),
+        }
+    }
+
+    /// Replace auth settings, which are validated by server ctx.
+    fn update_security(&mut self, settings: AuthSettings) {
+        log::info!("auth: Update security config");
+        self.allow_anonymous = settings.allow_anonymous;
+        self.db_auth = settings.db_auth;
+        self.file_auth = settings.file_auth;
     }
 }
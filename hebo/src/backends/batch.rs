@@ -0,0 +1,227 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Group commit of messages written to durable backends.
+//!
+//! Writers send items to `BatchWriter` and wait for result of the batch
+//! which contains them, so that acks to clients are deferred until messages
+//! are durably written.
+
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout_at, Instant};
+
+use crate::error::{Error, ErrorKind};
+
+/// Backend which writes a batch of items durably at once.
+pub trait BatchStore: Send + 'static {
+    type Item: Send + 'static;
+
+    /// Write all `items` durably.
+    ///
+    /// This method may block, it is called in a blocking thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to write items, none of them are acked.
+    fn commit(&mut self, items: &[Self::Item]) -> Result<(), Error>;
+}
+
+type BatchRequest<T> = (T, oneshot::Sender<Result<(), Error>>);
+
+/// Handle to send items to `BatchWriter`.
+#[derive(Debug)]
+pub struct BatchSender<T> {
+    sender: mpsc::Sender<BatchRequest<T>>,
+}
+
+impl<T> Clone for BatchSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> BatchSender<T> {
+    /// Write `item` and wait until the batch containing it is committed.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if batch writer is stopped or the batch failed to commit.
+    pub async fn write(&self, item: T) -> Result<(), Error> {
+        self.enqueue(item).await?.await?
+    }
+
+    /// Send `item` to batch writer without waiting for it to be committed.
+    ///
+    /// Result of the batch containing it is sent to the returned receiver.
+    /// Batches are committed in the same order as items are sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if batch writer is stopped.
+    pub async fn enqueue(&self, item: T) -> Result<oneshot::Receiver<Result<(), Error>>, Error> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.sender.send((item, resp_tx)).await.map_err(|_err| {
+            Error::new(ErrorKind::ChannelError, "backends: Batch writer is stopped")
+        })?;
+        Ok(resp_rx)
+    }
+}
+
+/// Collect items into batches and commit them to store.
+///
+/// A batch is committed when it has `max_size` items, or `window` elapsed
+/// since its first item arrived.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub struct BatchWriter<S: BatchStore> {
+    store: Option<S>,
+    max_size: usize,
+    window: Duration,
+    receiver: mpsc::Receiver<BatchRequest<S::Item>>,
+}
+
+impl<S: BatchStore> BatchWriter<S> {
+    /// Create a new batch writer and its sender handle.
+    #[must_use]
+    pub fn new(store: S, max_size: usize, window: Duration) -> (Self, BatchSender<S::Item>) {
+        let max_size = max_size.max(1);
+        let (sender, receiver) = mpsc::channel(max_size * 2);
+        let writer = Self {
+            store: Some(store),
+            max_size,
+            window,
+            receiver,
+        };
+        (writer, BatchSender { sender })
+    }
+
+    /// Commit batches until all senders are dropped.
+    pub async fn run_loop(mut self) {
+        while let Some(request) = self.receiver.recv().await {
+            let batch = self.collect_batch(request).await;
+            self.commit_batch(batch).await;
+        }
+    }
+
+    async fn collect_batch(&mut self, first: BatchRequest<S::Item>) -> Vec<BatchRequest<S::Item>> {
+        let deadline = Instant::now() + self.window;
+        let mut batch = Vec::with_capacity(self.max_size);
+        batch.push(first);
+        while batch.len() < self.max_size {
+            match timeout_at(deadline, self.receiver.recv()).await {
+                Ok(Some(request)) => batch.push(request),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        batch
+    }
+
+    async fn commit_batch(&mut self, batch: Vec<BatchRequest<S::Item>>) {
+        let (items, waiters): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+        let Some(mut store) = self.store.take() else {
+            return;
+        };
+        let ret = tokio::task::spawn_blocking(move || {
+            let ret = store.commit(&items);
+            (store, ret)
+        })
+        .await;
+        let ret = match ret {
+            Ok((store, ret)) => {
+                self.store = Some(store);
+                ret
+            }
+            Err(err) => {
+                log::error!("backends: Batch store panicked, err: {:?}", err);
+                Err(Error::new(
+                    ErrorKind::IoError,
+                    "backends: Batch store panicked",
+                ))
+            }
+        };
+        if let Err(err) = &ret {
+            log::error!("backends: Failed to commit batch, err: {:?}", err);
+        }
+        for waiter in waiters {
+            // Writer may be cancelled, it is ok to ignore it.
+            let _ret = waiter.send(ret.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{BatchStore, BatchWriter};
+    use crate::error::{Error, ErrorKind};
+
+    /// Record every committed batch.
+    #[derive(Debug, Default, Clone)]
+    struct RecordStore {
+        batches: Arc<Mutex<Vec<Vec<u32>>>>,
+    }
+
+    impl BatchStore for RecordStore {
+        type Item = u32;
+
+        fn commit(&mut self, items: &[u32]) -> Result<(), Error> {
+            // Simulate latency of disk sync.
+            std::thread::sleep(Duration::from_millis(50));
+            self.batches.lock().unwrap().push(items.to_vec());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ack_after_commit() {
+        let store = RecordStore::default();
+        let batches = store.batches.clone();
+        let (writer, sender) = BatchWriter::new(store, 3, Duration::from_millis(100));
+        tokio::spawn(writer.run_loop());
+
+        let mut handles = Vec::new();
+        for item in 0..5 {
+            let sender = sender.clone();
+            let batches = batches.clone();
+            handles.push(tokio::spawn(async move {
+                sender.write(item).await.unwrap();
+                // Item is committed before it is acked.
+                assert!(batches
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|batch| batch.contains(&item)));
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        // First batch is full, second one is committed after window.
+        let sizes: Vec<usize> = batches.lock().unwrap().iter().map(Vec::len).collect();
+        assert_eq!(sizes, [3, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_error() {
+        struct FailedStore;
+
+        impl BatchStore for FailedStore {
+            type Item = u32;
+
+            fn commit(&mut self, _items: &[u32]) -> Result<(), Error> {
+                Err(Error::new(ErrorKind::IoError, "disk full"))
+            }
+        }
+
+        let (writer, sender) = BatchWriter::new(FailedStore, 8, Duration::from_millis(10));
+        tokio::spawn(writer.run_loop());
+        assert!(sender.write(1).await.is_err());
+    }
+}
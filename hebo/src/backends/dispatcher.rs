@@ -7,14 +7,13 @@
 use super::BackendsApp;
 use crate::commands::DispatcherToBackendsCmd;
 use crate::error::Error;
-use crate::types::{ListenerId, SessionId, SessionInfo};
+use crate::types::{ListenerId, PublishRecord, SessionId, SessionInfo};
 
 impl BackendsApp {
     pub(super) async fn handle_dispatcher_cmd(
         &mut self,
         cmd: DispatcherToBackendsCmd,
     ) -> Result<(), Error> {
-        log::info!("cmd: {:?}", cmd);
         match cmd {
             DispatcherToBackendsCmd::SessionAdded(session) => {
                 self.handle_session_added(session).await
@@ -22,18 +21,27 @@ impl BackendsApp {
             DispatcherToBackendsCmd::SessionRemoved(listener_id, session_id) => {
                 self.handle_session_removed(listener_id, session_id).await
             }
+            DispatcherToBackendsCmd::Persist(record) => self.handle_persist(record).await,
+        }
+    }
+
+    async fn handle_persist(&mut self, record: PublishRecord) -> Result<(), Error> {
+        if let Some(message_store) = &mut self.message_store {
+            message_store.write(record).await
+        } else {
+            Ok(())
         }
     }
 
     #[allow(clippy::unused_async)]
-    async fn handle_session_added(&mut self, session: SessionInfo) -> Result<(), Error> {
+    async fn handle_session_added(&self, session: SessionInfo) -> Result<(), Error> {
         log::info!("session added: {}", session.session_id);
         Ok(())
     }
 
     #[allow(clippy::unused_async)]
     async fn handle_session_removed(
-        &mut self,
+        &self,
         listener_id: ListenerId,
         session_id: SessionId,
     ) -> Result<(), Error> {
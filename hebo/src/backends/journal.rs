@@ -0,0 +1,53 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Append messages to a journal file on disk.
+
+use codec::EncodePacket;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::batch::BatchStore;
+use crate::error::Error;
+use crate::session::OutgoingPacket;
+
+/// Append-only journal file, each commit is synced to disk.
+///
+/// Each record is a protocol version tag followed by the publish packet.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub struct JournalBackend {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl JournalBackend {
+    /// Open journal file at `path`, create it if not exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to open file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl BatchStore for JournalBackend {
+    type Item = OutgoingPacket;
+
+    fn commit(&mut self, items: &[OutgoingPacket]) -> Result<(), Error> {
+        self.buf.clear();
+        for packet in items {
+            packet.encode(&mut self.buf)?;
+        }
+        self.file.write_all(&self.buf)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,173 @@
 // Copyright (c) 2021 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
+
+//! Keep offline messages in memory.
+
+use codec::v3;
+use std::collections::{HashMap, VecDeque};
+
+use super::offline::OfflineStore;
+use crate::error::{Error, ErrorKind};
+
+#[derive(Debug, Default)]
+struct ClientQueue {
+    packets: VecDeque<v3::PublishPacket>,
+    /// Total payload bytes of `packets`.
+    bytes: usize,
+}
+
+impl ClientQueue {
+    fn pop_front(&mut self) -> Option<v3::PublishPacket> {
+        let packet = self.packets.pop_front()?;
+        self.bytes -= packet.message().len();
+        Some(packet)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    /// Maximum number of queued messages per client, 0 means no limit.
+    max_messages: usize,
+    /// Maximum payload bytes of queued messages per client, 0 means no limit.
+    max_bytes: usize,
+
+    /// `client_id` -> queued messages.
+    queues: HashMap<String, ClientQueue>,
+}
+
+impl MemoryBackend {
+    #[must_use]
+    pub fn new(max_messages: usize, max_bytes: usize) -> Self {
+        Self {
+            max_messages,
+            max_bytes,
+            queues: HashMap::new(),
+        }
+    }
+
+    fn is_full(&self, queue: &ClientQueue, incoming_bytes: usize) -> bool {
+        (self.max_messages > 0 && queue.packets.len() >= self.max_messages)
+            || (self.max_bytes > 0 && queue.bytes + incoming_bytes > self.max_bytes)
+    }
+}
+
+impl OfflineStore for MemoryBackend {
+    fn enqueue(&mut self, client_id: &str, packet: v3::PublishPacket) -> Result<usize, Error> {
+        let bytes = packet.message().len();
+        if self.max_bytes > 0 && bytes > self.max_bytes {
+            return Err(Error::from_string(
+                ErrorKind::ParameterError,
+                format!("backends: Offline message of {client_id} is too large, got {bytes} bytes"),
+            ));
+        }
+
+        let mut queue = self.queues.remove(client_id).unwrap_or_default();
+        let mut evicted = 0;
+        while self.is_full(&queue, bytes) && queue.pop_front().is_some() {
+            evicted += 1;
+        }
+        queue.bytes += bytes;
+        queue.packets.push_back(packet);
+        self.queues.insert(client_id.to_string(), queue);
+        Ok(evicted)
+    }
+
+    fn drain(&mut self, client_id: &str) -> Vec<v3::PublishPacket> {
+        self.queues
+            .remove(client_id)
+            .map(|queue| queue.packets.into())
+            .unwrap_or_default()
+    }
+
+    fn queued_count(&self, client_id: &str) -> usize {
+        self.queues
+            .get(client_id)
+            .map_or(0, |queue| queue.packets.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, QoS};
+
+    use super::MemoryBackend;
+    use crate::backends::offline::OfflineStore;
+
+    fn new_packet(msg: &[u8]) -> v3::PublishPacket {
+        v3::PublishPacket::new("offline/test", QoS::AtLeastOnce, msg).unwrap()
+    }
+
+    fn messages(packets: &[v3::PublishPacket]) -> Vec<&[u8]> {
+        packets.iter().map(v3::PublishPacket::message).collect()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut backend = MemoryBackend::new(0, 0);
+        for msg in [b"1", b"2", b"3"] {
+            assert_eq!(backend.enqueue("client-1", new_packet(msg)).unwrap(), 0);
+        }
+        assert_eq!(backend.enqueue("client-2", new_packet(b"a")).unwrap(), 0);
+        assert_eq!(backend.queued_count("client-1"), 3);
+        assert_eq!(backend.queued_count("client-2"), 1);
+        assert_eq!(backend.queued_count("client-3"), 0);
+
+        let packets = backend.drain("client-1");
+        assert_eq!(messages(&packets), [b"1", b"2", b"3"]);
+    }
+
+    #[test]
+    fn test_count_limit() {
+        let mut backend = MemoryBackend::new(2, 0);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"1")).unwrap(), 0);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"2")).unwrap(), 0);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"3")).unwrap(), 1);
+        assert_eq!(backend.queued_count("client-1"), 2);
+        // Limit is per client.
+        assert_eq!(backend.enqueue("client-2", new_packet(b"a")).unwrap(), 0);
+
+        let packets = backend.drain("client-1");
+        assert_eq!(messages(&packets), [b"2", b"3"]);
+    }
+
+    #[test]
+    fn test_byte_limit() {
+        let mut backend = MemoryBackend::new(0, 10);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"1234")).unwrap(), 0);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"5678")).unwrap(), 0);
+        // Evict oldest messages until the new one fits.
+        assert_eq!(
+            backend
+                .enqueue("client-1", new_packet(b"abcdefgh"))
+                .unwrap(),
+            2
+        );
+        assert_eq!(backend.enqueue("client-1", new_packet(b"ij")).unwrap(), 0);
+
+        // Message larger than limit is rejected, queue is kept.
+        assert!(backend
+            .enqueue("client-1", new_packet(b"0123456789a"))
+            .is_err());
+        let packets = backend.drain("client-1");
+        assert_eq!(messages(&packets), [&b"abcdefgh"[..], b"ij"]);
+    }
+
+    #[test]
+    fn test_drain_on_reconnect() {
+        let mut backend = MemoryBackend::new(10, 1024);
+        assert_eq!(backend.enqueue("client-1", new_packet(b"1")).unwrap(), 0);
+        assert_eq!(backend.enqueue("client-2", new_packet(b"a")).unwrap(), 0);
+
+        // Client reconnects and receives all queued messages once.
+        assert_eq!(messages(&backend.drain("client-1")), [b"1"]);
+        assert_eq!(backend.queued_count("client-1"), 0);
+        assert!(backend.drain("client-1").is_empty());
+        assert_eq!(backend.queued_count("client-2"), 1);
+
+        // Queue starts over after client goes offline again.
+        assert_eq!(backend.enqueue("client-1", new_packet(b"2")).unwrap(), 0);
+        assert_eq!(messages(&backend.drain("client-1")), [b"2"]);
+    }
+}
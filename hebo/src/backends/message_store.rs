@@ -0,0 +1,288 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Write messages published by clients to message store.
+
+use codec::topic::is_wildcard_filter;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+#[cfg(feature = "redis_conn")]
+use super::redis_store::RedisMessageStore;
+use crate::config::{MessageStoreType, Storage};
+use crate::dispatcher::is_filter_match;
+use crate::error::Error;
+use crate::types::PublishRecord;
+
+/// Keep recent messages of each topic in memory.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default)]
+pub struct MemoryMessageStore {
+    /// Maximum number of messages kept for each topic, 0 means no limit.
+    max_messages: usize,
+
+    /// Maximum number of messages kept for all topics, 0 means no limit.
+    max_total_messages: usize,
+
+    /// topic -> (sequence number, message), oldest first.
+    ///
+    /// Sequence number keeps order of messages in different topics.
+    topics: HashMap<String, VecDeque<(u64, PublishRecord)>>,
+
+    /// sequence number -> topic, of all stored messages.
+    ///
+    /// The oldest message of all topics is found here when `max_total_messages` is reached.
+    seqs: BTreeMap<u64, String>,
+    next_seq: u64,
+}
+
+impl MemoryMessageStore {
+    #[must_use]
+    pub fn new(max_messages: usize, max_total_messages: usize) -> Self {
+        Self {
+            max_messages,
+            max_total_messages,
+            topics: HashMap::new(),
+            seqs: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// Append `record` to messages of its topic.
+    ///
+    /// The oldest message of this topic is removed if limit of topic is reached,
+    /// and the oldest message of all topics is removed if total limit is reached.
+    pub fn write(&mut self, record: PublishRecord) {
+        if self.max_total_messages > 0 && self.seqs.len() >= self.max_total_messages {
+            self.remove_oldest();
+        }
+        let queue = self.topics.entry(record.topic.clone()).or_default();
+        if self.max_messages > 0 && queue.len() >= self.max_messages {
+            if let Some((seq, _record)) = queue.pop_front() {
+                self.seqs.remove(&seq);
+            }
+        }
+        self.seqs.insert(self.next_seq, record.topic.clone());
+        queue.push_back((self.next_seq, record));
+        self.next_seq = self.next_seq.wrapping_add(1);
+    }
+
+    /// Remove the oldest message of all topics.
+    fn remove_oldest(&mut self) {
+        let Some((_seq, topic)) = self.seqs.pop_first() else {
+            return;
+        };
+        if let Some(queue) = self.topics.get_mut(&topic) {
+            queue.pop_front();
+            // Do not keep topics without messages.
+            if queue.is_empty() {
+                self.topics.remove(&topic);
+            }
+        }
+    }
+
+    /// Get stored messages of `topic`, oldest first.
+    pub fn messages(&self, topic: &str) -> impl Iterator<Item = &PublishRecord> {
+        self.topics
+            .get(topic)
+            .into_iter()
+            .flatten()
+            .map(|(_seq, record)| record)
+    }
+
+    /// Get the last `limit` messages of topics matching `topic_filter`, oldest first.
+    #[must_use]
+    pub fn history(&self, topic_filter: &str, limit: usize) -> Vec<PublishRecord> {
+        let mut records: Vec<&(u64, PublishRecord)> = if is_wildcard_filter(topic_filter) {
+            self.topics
+                .iter()
+                .filter(|(topic, _queue)| is_filter_match(topic_filter, topic))
+                .flat_map(|(_topic, queue)| queue)
+                .collect()
+        } else {
+            self.topics
+                .get(topic_filter)
+                .into_iter()
+                .flatten()
+                .collect()
+        };
+        records.sort_unstable_by_key(|(seq, _record)| *seq);
+        let skipped = records.len().saturating_sub(limit);
+        records
+            .into_iter()
+            .skip(skipped)
+            .map(|(_seq, record)| record.clone())
+            .collect()
+    }
+}
+
+/// Backend of message store, selected by `message_store` in storage config.
+#[allow(clippy::module_name_repetitions)]
+pub enum MessageStore {
+    Memory(MemoryMessageStore),
+    #[cfg(feature = "redis_conn")]
+    Redis(Box<RedisMessageStore>),
+}
+
+impl MessageStore {
+    /// Create message store, returns None if it is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if redis uri is invalid.
+    pub fn new(storage: &Storage) -> Result<Option<Self>, Error> {
+        let store = match storage.message_store() {
+            MessageStoreType::None => return Ok(None),
+            MessageStoreType::Memory => Self::Memory(MemoryMessageStore::new(
+                storage.max_stored_messages(),
+                storage.max_total_stored_messages(),
+            )),
+            #[cfg(feature = "redis_conn")]
+            MessageStoreType::Redis => Self::Redis(Box::new(RedisMessageStore::new(
+                storage.redis(),
+                storage.redis_key_prefix(),
+                storage.max_stored_messages(),
+            )?)),
+            // Rejected in config validation.
+            #[cfg(not(feature = "redis_conn"))]
+            MessageStoreType::Redis => return Ok(None),
+        };
+        Ok(Some(store))
+    }
+
+    /// Connect to server of message store.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to connect to server.
+    #[allow(clippy::unused_async)]
+    pub async fn init(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Memory(_store) => Ok(()),
+            #[cfg(feature = "redis_conn")]
+            Self::Redis(store) => store.init().await,
+        }
+    }
+
+    /// Get the last `limit` messages of topics matching `topic_filter`, oldest first.
+    ///
+    /// Returns None if history query is not supported by this store.
+    #[must_use]
+    pub fn history(&self, topic_filter: &str, limit: usize) -> Option<Vec<PublishRecord>> {
+        match self {
+            Self::Memory(store) => Some(store.history(topic_filter, limit)),
+            #[cfg(feature = "redis_conn")]
+            Self::Redis(_store) => None,
+        }
+    }
+
+    /// Write message to store.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to write to server.
+    #[allow(clippy::unused_async)]
+    pub async fn write(&mut self, record: PublishRecord) -> Result<(), Error> {
+        match self {
+            Self::Memory(store) => {
+                store.write(record);
+                Ok(())
+            }
+            #[cfg(feature = "redis_conn")]
+            Self::Redis(store) => store.write(&record).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::QoS;
+
+    use super::MemoryMessageStore;
+    use crate::types::PublishRecord;
+
+    fn payloads(store: &MemoryMessageStore, topic: &str) -> Vec<Vec<u8>> {
+        store
+            .messages(topic)
+            .map(|record| record.payload.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryMessageStore::new(2, 0);
+        for payload in [b"1", b"2", b"3"] {
+            store.write(PublishRecord::new("store/a", payload, QoS::AtLeastOnce));
+        }
+        store.write(PublishRecord::new("store/b", b"x", QoS::AtMostOnce));
+
+        // Limit is per topic, oldest messages are removed first.
+        assert_eq!(payloads(&store, "store/a"), [b"2", b"3"]);
+        assert_eq!(payloads(&store, "store/b"), [b"x"]);
+        assert!(payloads(&store, "store/c").is_empty());
+
+        let record = store.messages("store/a").next().unwrap();
+        assert_eq!(record.qos, QoS::AtLeastOnce);
+        assert!(record.timestamp > 0);
+    }
+
+    #[test]
+    fn test_memory_store_total_limit() {
+        let mut store = MemoryMessageStore::new(2, 3);
+        for (topic, payload) in [
+            ("store/a", b"1"),
+            ("store/b", b"2"),
+            ("store/a", b"3"),
+            ("store/c", b"4"),
+        ] {
+            store.write(PublishRecord::new(topic, payload, QoS::AtMostOnce));
+        }
+        // The oldest message of all topics is removed.
+        assert_eq!(payloads(&store, "store/a"), [b"3"]);
+        assert_eq!(payloads(&store, "store/b"), [b"2"]);
+        assert_eq!(payloads(&store, "store/c"), [b"4"]);
+
+        // Topics are removed with their last message.
+        store.write(PublishRecord::new("store/c", b"5", QoS::AtMostOnce));
+        store.write(PublishRecord::new("store/c", b"6", QoS::AtMostOnce));
+        assert!(payloads(&store, "store/a").is_empty());
+        assert!(payloads(&store, "store/b").is_empty());
+        assert_eq!(payloads(&store, "store/c"), [b"5", b"6"]);
+        assert_eq!(store.topics.len(), 1);
+
+        store.write(PublishRecord::new("store/d", b"7", QoS::AtMostOnce));
+        assert_eq!(payloads(&store, "store/c"), [b"5", b"6"]);
+        assert_eq!(payloads(&store, "store/d"), [b"7"]);
+        store.write(PublishRecord::new("store/d", b"8", QoS::AtMostOnce));
+        assert_eq!(payloads(&store, "store/c"), [b"6"]);
+        assert_eq!(payloads(&store, "store/d"), [b"7", b"8"]);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut store = MemoryMessageStore::new(0, 0);
+        for (topic, payload) in [
+            ("history/a", b"1"),
+            ("history/b", b"2"),
+            ("history/a", b"3"),
+            ("other/a", b"4"),
+            ("history/b", b"5"),
+        ] {
+            store.write(PublishRecord::new(topic, payload, QoS::AtMostOnce));
+        }
+        let history = |filter: &str, limit: usize| -> Vec<Vec<u8>> {
+            store
+                .history(filter, limit)
+                .into_iter()
+                .map(|record| record.payload)
+                .collect()
+        };
+
+        assert_eq!(history("history/a", 10), [b"1", b"3"]);
+        // Messages of matching topics are merged in the order they are received.
+        assert_eq!(history("history/+", 10), [b"1", b"2", b"3", b"5"]);
+        assert_eq!(history("#", 2), [b"4", b"5"]);
+        assert_eq!(history("history/#", 0), Vec::<Vec<u8>>::new());
+        assert!(history("history/c", 10).is_empty());
+    }
+}
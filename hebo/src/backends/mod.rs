@@ -8,13 +8,24 @@ use crate::commands::{
     BackendsToDispatcherCmd, DispatcherToBackendsCmd, ServerContextToBackendsCmd,
 };
 
+pub mod batch;
 mod dispatcher;
+pub mod journal;
 pub mod memory;
+pub mod message_store;
+pub mod offline;
+#[cfg(feature = "redis_conn")]
+pub mod redis_store;
 mod server;
 
+use message_store::MessageStore;
+
 #[allow(dead_code)]
 #[allow(clippy::module_name_repetitions)]
 pub struct BackendsApp {
+    /// Messages published by clients are written to it, disabled if None.
+    message_store: Option<MessageStore>,
+
     dispatcher_sender: Sender<BackendsToDispatcherCmd>,
     dispatcher_receiver: Receiver<DispatcherToBackendsCmd>,
 
@@ -24,6 +35,7 @@ pub struct BackendsApp {
 impl BackendsApp {
     #[must_use]
     pub const fn new(
+        message_store: Option<MessageStore>,
         // dispatcher
         dispatcher_sender: Sender<BackendsToDispatcherCmd>,
         dispatcher_receiver: Receiver<DispatcherToBackendsCmd>,
@@ -31,6 +43,8 @@ impl BackendsApp {
         server_ctx_receiver: Receiver<ServerContextToBackendsCmd>,
     ) -> Self {
         Self {
+            message_store,
+
             dispatcher_sender,
             dispatcher_receiver,
 
@@ -38,7 +52,12 @@ impl BackendsApp {
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
+        if let Some(message_store) = &mut self.message_store {
+            if let Err(err) = message_store.init().await {
+                log::error!("backends: Failed to init message store, err: {:?}", err);
+            }
+        }
         loop {
             tokio::select! {
                 Some(cmd) = self.dispatcher_receiver.recv() => {
@@ -47,6 +66,10 @@ impl BackendsApp {
                     }
                 }
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToBackendsCmd::Stop) {
+                        log::info!("backends: Stop app");
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
@@ -0,0 +1,30 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Interface of storage backends for offline messages.
+
+use codec::v3;
+
+use crate::error::Error;
+
+/// Queue messages of offline clients, and deliver them when clients reconnect.
+///
+/// Messages of each client are kept in the order of `enqueue()`.
+pub trait OfflineStore {
+    /// Append a message to the queue of `client_id`.
+    ///
+    /// If per-client limits are exceeded, the oldest messages are evicted first.
+    /// Returns number of evicted messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if message itself is larger than byte limit, queue is not changed.
+    fn enqueue(&mut self, client_id: &str, packet: v3::PublishPacket) -> Result<usize, Error>;
+
+    /// Remove and return all queued messages of `client_id`, oldest first.
+    fn drain(&mut self, client_id: &str) -> Vec<v3::PublishPacket>;
+
+    /// Get number of queued messages of `client_id`.
+    fn queued_count(&self, client_id: &str) -> usize;
+}
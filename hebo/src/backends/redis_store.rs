@@ -0,0 +1,122 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Append messages to redis streams.
+
+use std::time::Duration;
+
+use crate::connectors::redis_conn::{RedisConn, RedisConnConfig};
+use crate::error::{Error, ErrorKind};
+use crate::types::PublishRecord;
+
+/// Messages of each topic are appended to stream `{key_prefix}{topic}`.
+#[allow(clippy::module_name_repetitions)]
+pub struct RedisMessageStore {
+    conn: RedisConn,
+    query_timeout: Duration,
+    key_prefix: String,
+    /// Approximate maximum length of each stream, 0 means no limit.
+    max_messages: usize,
+}
+
+impl RedisMessageStore {
+    /// Create a new redis message store, call `init()` to connect to server.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if redis uri is invalid.
+    pub fn new(
+        config: &RedisConnConfig,
+        key_prefix: &str,
+        max_messages: usize,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            conn: RedisConn::new(config)?,
+            query_timeout: config.query_timeout(),
+            key_prefix: key_prefix.to_string(),
+            max_messages,
+        })
+    }
+
+    /// Connect to redis server.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to connect to redis server.
+    pub async fn init(&mut self) -> Result<(), Error> {
+        self.conn.init().await
+    }
+
+    fn xadd_cmd(&self, record: &PublishRecord) -> redis::Cmd {
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg(format!("{}{}", self.key_prefix, record.topic));
+        if self.max_messages > 0 {
+            cmd.arg("MAXLEN").arg("~").arg(self.max_messages);
+        }
+        cmd.arg("*")
+            .arg("qos")
+            .arg(record.qos as u8)
+            .arg("timestamp")
+            .arg(record.timestamp)
+            .arg("payload")
+            .arg(record.payload.as_slice());
+        cmd
+    }
+
+    /// Append `record` to stream of its topic.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if not connected, or failed to write to redis server in time.
+    pub async fn write(&self, record: &PublishRecord) -> Result<(), Error> {
+        let Some(mut conn) = self.conn.get_conn() else {
+            return Err(Error::new(
+                ErrorKind::RedisError,
+                "backends: Not connected to redis server",
+            ));
+        };
+        let cmd = self.xadd_cmd(record);
+        tokio::time::timeout(self.query_timeout, cmd.query_async::<_, String>(&mut conn))
+            .await
+            .map_err(|_err| Error::new(ErrorKind::RedisError, "backends: Redis query timeout"))??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::QoS;
+
+    use super::RedisMessageStore;
+    use crate::connectors::redis_conn::RedisConnConfig;
+    use crate::types::PublishRecord;
+
+    #[test]
+    fn test_xadd_cmd() {
+        let store =
+            RedisMessageStore::new(&RedisConnConfig::default(), "hebo:messages:", 100).unwrap();
+        let mut record = PublishRecord::new("store/a", b"hello", QoS::AtLeastOnce);
+        record.timestamp = 1_700_000_000_000;
+        let expected = redis::cmd("XADD")
+            .arg("hebo:messages:store/a")
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(100)
+            .arg("*")
+            .arg("qos")
+            .arg(1)
+            .arg("timestamp")
+            .arg(1_700_000_000_000_u64)
+            .arg("payload")
+            .arg(&b"hello"[..])
+            .get_packed_command();
+        assert_eq!(store.xadd_cmd(&record).get_packed_command(), expected);
+
+        // Streams are not trimmed without limit.
+        let store =
+            RedisMessageStore::new(&RedisConnConfig::default(), "hebo:messages:", 0).unwrap();
+        let packed = store.xadd_cmd(&record).get_packed_command();
+        assert!(!String::from_utf8_lossy(&packed).contains("MAXLEN"));
+    }
+}
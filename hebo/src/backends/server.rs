@@ -9,7 +9,19 @@ use crate::commands::ServerContextToBackendsCmd;
 
 impl BackendsApp {
     #[allow(clippy::unused_async)]
-    pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToBackendsCmd) {
-        log::info!("cmd: {:?}", cmd);
+    pub(super) async fn handle_server_ctx_cmd(&self, cmd: ServerContextToBackendsCmd) {
+        match cmd {
+            ServerContextToBackendsCmd::GetHistory(topic_filter, limit, resp_tx) => {
+                let history = self
+                    .message_store
+                    .as_ref()
+                    .and_then(|message_store| message_store.history(&topic_filter, limit));
+                if resp_tx.send(history).is_err() {
+                    log::warn!("backends: Failed to send history to server ctx");
+                }
+            }
+            // Handled in run_loop().
+            ServerContextToBackendsCmd::Stop => (),
+        }
     }
 }
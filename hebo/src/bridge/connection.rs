@@ -0,0 +1,566 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Outbound connection to remote broker.
+
+use codec::{
+    v3, AnyPacket, ByteArray, DecodePacket, EncodePacket, FixedHeader, PacketDecoder, PacketId,
+    PacketIdPool, PacketType, ProtocolLevel, QoS, V3Packet,
+};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{interval, Interval};
+use tokio_rustls::{rustls, TlsConnector};
+
+use super::mapping::TopicMapping;
+use super::resolver::Resolver;
+use crate::commands::BridgeToDispatcherCmd;
+use crate::config;
+use crate::error::{Error, ErrorKind};
+
+/// Reconnect delay after connection failures.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    retry_interval: Duration,
+    maximum_retry_interval: Duration,
+    tls_retry_interval: Duration,
+    network_failures: u32,
+}
+
+impl Backoff {
+    #[must_use]
+    pub fn new(bridge: &config::Bridge) -> Self {
+        Self {
+            retry_interval: Duration::from_secs(u64::from(bridge.retry_interval())),
+            maximum_retry_interval: Duration::from_secs(u64::from(bridge.maximum_retry_interval())),
+            tls_retry_interval: Duration::from_secs(u64::from(bridge.tls_retry_interval())),
+            network_failures: 0,
+        }
+    }
+
+    /// Reset delay after connection is established.
+    pub fn reset(&mut self) {
+        self.network_failures = 0;
+    }
+
+    /// Get delay before next connection attempt.
+    ///
+    /// TLS failures use a fixed and usually longer delay, as retrying won't help
+    /// until cert of either side is updated. Network failures use exponential backoff.
+    pub fn next_delay(&mut self, err: &Error) -> Duration {
+        if is_tls_error(err) {
+            return self.tls_retry_interval;
+        }
+
+        let delay = self
+            .retry_interval
+            .saturating_mul(2_u32.saturating_pow(self.network_failures));
+        self.network_failures = self.network_failures.saturating_add(1);
+        delay.min(self.maximum_retry_interval)
+    }
+}
+
+/// Keep connection to a remote broker, reconnect after failures.
+#[derive(Debug)]
+pub struct BridgeConnection {
+    bridge: config::Bridge,
+    backoff: Backoff,
+    resolver: Resolver,
+    mappings: Vec<TopicMapping>,
+
+    /// Local messages to publish in remote broker, with remote topic names.
+    receiver: Receiver<v3::PublishPacket>,
+    dispatcher_sender: Sender<BridgeToDispatcherCmd>,
+}
+
+impl BridgeConnection {
+    #[must_use]
+    pub fn new(
+        bridge: config::Bridge,
+        receiver: Receiver<v3::PublishPacket>,
+        dispatcher_sender: Sender<BridgeToDispatcherCmd>,
+    ) -> Self {
+        let backoff = Backoff::new(&bridge);
+        let resolver = Resolver::new(
+            bridge.address().to_string(),
+            Duration::from_secs(u64::from(bridge.dns_cache_ttl())),
+        );
+        let mappings = bridge.topics().iter().map(TopicMapping::new).collect();
+        Self {
+            bridge,
+            backoff,
+            resolver,
+            mappings,
+            receiver,
+            dispatcher_sender,
+        }
+    }
+
+    /// Keep connection until bridge app is stopped.
+    pub async fn run_loop(mut self) {
+        loop {
+            let delay = match self.connect().await {
+                Ok(()) => {
+                    log::info!("bridge {}: Stopped", self.bridge.name());
+                    return;
+                }
+                Err(err) if is_tls_error(&err) => {
+                    let delay = self.backoff.next_delay(&err);
+                    log::error!(
+                        "bridge {}: TLS failure, retry after {:?}, err: {}",
+                        self.bridge.name(),
+                        delay,
+                        err
+                    );
+                    delay
+                }
+                Err(err) => {
+                    let delay = self.backoff.next_delay(&err);
+                    log::warn!(
+                        "bridge {}: Network failure, retry after {:?}, err: {}",
+                        self.bridge.name(),
+                        delay,
+                        err
+                    );
+                    delay
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Connect to remote broker and forward messages until the connection is closed.
+    ///
+    /// Returns Ok if bridge app is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns error with `SSLError` kind if TLS handshake failed, or other kinds
+    /// if network failed or remote broker refused this connection.
+    pub async fn connect(&mut self) -> Result<(), Error> {
+        // Load TLS config before connecting, so that invalid CA file is not reported as network error.
+        let connector = if self.bridge.tls() {
+            Some(new_tls_connector(&self.bridge)?)
+        } else {
+            None
+        };
+        let tcp_stream = self.resolver.connect().await?;
+
+        if let Some(connector) = connector {
+            let server_name =
+                rustls::ServerName::try_from(self.bridge.server_name()).map_err(|err| {
+                    Error::from_string(
+                        ErrorKind::CertError,
+                        format!(
+                            "bridge: Invalid server name {}, err: {err}",
+                            self.bridge.server_name()
+                        ),
+                    )
+                })?;
+            let tls_stream = connector
+                .connect(server_name, tcp_stream)
+                .await
+                .map_err(|err| tls_error(&self.bridge, err))?;
+            self.serve(tls_stream).await
+        } else {
+            self.serve(tcp_stream).await
+        }
+    }
+
+    async fn serve<S>(&mut self, mut stream: S) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let mut decoder = PacketDecoder::new();
+        self.handshake(&mut stream, &mut decoder).await?;
+        self.backoff.reset();
+
+        let mut packet_ids = PacketIdPool::new();
+        self.subscribe(&mut stream, &mut packet_ids).await?;
+
+        let keep_alive = u64::from(self.bridge.keep_alive());
+        let mut ping_timer = (keep_alive > 0).then(|| interval(Duration::from_secs(keep_alive)));
+
+        loop {
+            tokio::select! {
+                n_recv = stream.read_buf(decoder.buffer_mut()) => {
+                    if n_recv? == 0 {
+                        return Err(Error::from_string(
+                            ErrorKind::SocketError,
+                            format!("bridge: Connection to {} closed", self.bridge.address()),
+                        ));
+                    }
+                    while let Some(buf) = decoder.next_packet_bytes()? {
+                        self.handle_remote_packet(&mut stream, &mut packet_ids, buf).await?;
+                    }
+                }
+                packet = self.receiver.recv() => {
+                    let Some(mut packet) = packet else {
+                        return Ok(());
+                    };
+                    if packet.qos() != QoS::AtMostOnce {
+                        let Some(packet_id) = packet_ids.alloc() else {
+                            log::warn!("bridge {}: No packet id available, drop message", self.bridge.name());
+                            continue;
+                        };
+                        packet.set_packet_id(packet_id);
+                    }
+                    write_packet(&mut stream, &packet).await?;
+                }
+                () = ping_tick(&mut ping_timer) => {
+                    write_packet(&mut stream, &v3::PingRequestPacket::new()).await?;
+                }
+            }
+        }
+    }
+
+    /// Send CONNECT packet and wait for CONNACK.
+    async fn handshake<S>(&self, stream: &mut S, decoder: &mut PacketDecoder) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let mut connect_packet = v3::ConnectPacket::new(&self.bridge.client_id())?;
+        connect_packet.set_keep_alive(self.bridge.keep_alive());
+        let mut flags = connect_packet.connect_flags().clone();
+        if let Some(username) = self.bridge.username() {
+            connect_packet.set_username(username)?;
+            flags.set_has_username(true);
+        }
+        if let Some(password) = self.bridge.password() {
+            connect_packet.set_password(password.as_bytes())?;
+            flags.set_has_password(true);
+        }
+        connect_packet.set_connect_flags(flags);
+        write_packet(stream, &connect_packet).await?;
+
+        let packet = loop {
+            if let Some(packet) = decoder.next_packet(ProtocolLevel::V4)? {
+                break packet;
+            }
+            if stream.read_buf(decoder.buffer_mut()).await? == 0 {
+                return Err(Error::from_string(
+                    ErrorKind::SocketError,
+                    format!("bridge: Connection to {} closed", self.bridge.address()),
+                ));
+            }
+        };
+        let AnyPacket::V3(V3Packet::ConnectAck(ack_packet)) = packet else {
+            return Err(Error::from_string(
+                ErrorKind::SocketError,
+                format!(
+                    "bridge: Expected CONNACK from {}, got: {:?}",
+                    self.bridge.address(),
+                    packet
+                ),
+            ));
+        };
+        if ack_packet.return_code() != v3::ConnectReturnCode::Accepted {
+            return Err(Error::from_string(
+                ErrorKind::SocketError,
+                format!(
+                    "bridge: Connection to {} refused, code: {:?}",
+                    self.bridge.address(),
+                    ack_packet.return_code()
+                ),
+            ));
+        }
+        log::info!("bridge: Connected to {}", self.bridge.address());
+        Ok(())
+    }
+
+    /// Subscribe topics forwarded from remote broker.
+    async fn subscribe<S>(&self, stream: &mut S, packet_ids: &mut PacketIdPool) -> Result<(), Error>
+    where
+        S: AsyncWrite + Unpin + Send,
+    {
+        let topics = self
+            .mappings
+            .iter()
+            .filter_map(|mapping| {
+                mapping
+                    .in_filter()
+                    .map(|filter| v3::SubscribeTopic::new(filter, mapping.qos()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(first) = topics.first() else {
+            return Ok(());
+        };
+        let packet_id = packet_ids.alloc().unwrap_or_else(|| PacketId::new(1));
+        let mut packet = v3::SubscribePacket::new(first.topic(), first.qos(), packet_id)?;
+        packet.set_topics(&topics);
+        write_packet(stream, &packet).await
+    }
+
+    async fn handle_remote_packet<S>(
+        &self,
+        stream: &mut S,
+        packet_ids: &mut PacketIdPool,
+        buf: &[u8],
+    ) -> Result<(), Error>
+    where
+        S: AsyncWrite + Unpin + Send,
+    {
+        let mut ba = ByteArray::new(buf);
+        let fixed_header = FixedHeader::decode(&mut ba)?;
+        let mut ba = ByteArray::new(buf);
+        match fixed_header.packet_type() {
+            PacketType::Publish { .. } => {
+                let packet = v3::PublishPacket::decode(&mut ba)?;
+                match packet.qos() {
+                    QoS::AtMostOnce => (),
+                    QoS::AtLeastOnce => {
+                        write_packet(stream, &v3::PublishAckPacket::new(packet.packet_id()))
+                            .await?;
+                    }
+                    QoS::ExactOnce => {
+                        write_packet(stream, &v3::PublishReceivedPacket::new(packet.packet_id()))
+                            .await?;
+                    }
+                }
+                self.forward_to_local(packet).await
+            }
+            PacketType::PublishAck => {
+                let packet = v3::PublishAckPacket::decode(&mut ba)?;
+                packet_ids.release(packet.packet_id());
+                Ok(())
+            }
+            PacketType::PublishReceived => {
+                let packet = v3::PublishReceivedPacket::decode(&mut ba)?;
+                write_packet(stream, &v3::PublishReleasePacket::new(packet.packet_id())).await
+            }
+            PacketType::PublishRelease => {
+                let packet = v3::PublishReleasePacket::decode(&mut ba)?;
+                write_packet(stream, &v3::PublishCompletePacket::new(packet.packet_id())).await
+            }
+            PacketType::PublishComplete => {
+                let packet = v3::PublishCompletePacket::decode(&mut ba)?;
+                packet_ids.release(packet.packet_id());
+                Ok(())
+            }
+            PacketType::SubscribeAck => {
+                let packet = v3::SubscribeAckPacket::decode(&mut ba)?;
+                packet_ids.release(packet.packet_id());
+                if packet
+                    .acknowledgements()
+                    .contains(&v3::SubscribeAck::Failed)
+                {
+                    log::warn!(
+                        "bridge {}: Some topics are rejected by remote broker: {:?}",
+                        self.bridge.name(),
+                        packet.acknowledgements()
+                    );
+                }
+                Ok(())
+            }
+            PacketType::PingResponse => Ok(()),
+            packet_type => {
+                log::warn!(
+                    "bridge {}: Unexpected packet from remote broker: {:?}",
+                    self.bridge.name(),
+                    packet_type
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Rewrite topic of remote message and send it to dispatcher.
+    async fn forward_to_local(&self, mut packet: v3::PublishPacket) -> Result<(), Error> {
+        let Some(topic) = self
+            .mappings
+            .iter()
+            .find_map(|mapping| mapping.to_local(packet.topic()))
+        else {
+            log::warn!(
+                "bridge {}: No topic mapping for remote message: {}",
+                self.bridge.name(),
+                packet.topic()
+            );
+            return Ok(());
+        };
+        packet.set_topic(&topic)?;
+        // Packet id is only valid in connection with remote broker.
+        packet.set_packet_id(PacketId::new(0));
+        self.dispatcher_sender
+            .send(BridgeToDispatcherCmd::Publish(packet))
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Wait for next tick of `timer`, never completes if it is None.
+async fn ping_tick(timer: &mut Option<Interval>) {
+    if let Some(timer) = timer {
+        timer.tick().await;
+    } else {
+        std::future::pending::<()>().await;
+    }
+}
+
+async fn write_packet<S, P>(stream: &mut S, packet: &P) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin + Send,
+    P: EncodePacket + Sync,
+{
+    let mut buf = Vec::new();
+    packet.encode(&mut buf)?;
+    stream.write_all(&buf).await.map_err(Into::into)
+}
+
+/// Returns true if `err` is caused by TLS handshake or TLS config.
+#[must_use]
+pub const fn is_tls_error(err: &Error) -> bool {
+    matches!(err.kind(), ErrorKind::SSLError | ErrorKind::CertError)
+}
+
+fn new_tls_connector(bridge: &config::Bridge) -> Result<TlsConnector, Error> {
+    let mut root_store = rustls::RootCertStore::empty();
+    if let Some(ca_file) = bridge.ca_file() {
+        let certs =
+            rustls_pemfile::certs(&mut BufReader::new(File::open(ca_file)?)).map_err(|err| {
+                Error::from_string(
+                    ErrorKind::CertError,
+                    format!(
+                        "bridge: Failed to load CA file at {}, got: {err:?}",
+                        ca_file.display()
+                    ),
+                )
+            })?;
+        root_store.add_parsable_certificates(&certs);
+    } else {
+        root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+    }
+    let client_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(client_config)))
+}
+
+/// Handshake errors are reported by tokio-rustls as `InvalidData` io error wrapping `rustls::Error`.
+fn tls_error(bridge: &config::Bridge, err: io::Error) -> Error {
+    if matches!(err.get_ref(), Some(inner) if inner.is::<rustls::Error>()) {
+        Error::from_string(
+            ErrorKind::SSLError,
+            format!(
+                "bridge: TLS handshake with {} failed, err: {err}",
+                bridge.address()
+            ),
+        )
+    } else {
+        err.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_rustls::{rustls, TlsAcceptor};
+
+    use super::{is_tls_error, Backoff};
+    use crate::bridge::BridgeConnection;
+    use crate::config;
+
+    fn new_bridge(address: &str, retry_interval: u32, tls_retry_interval: u32) -> config::Bridge {
+        let content = format!(
+            r#"
+            name = "test"
+            address = "{address}"
+            tls = true
+            retry_interval = {retry_interval}
+            maximum_retry_interval = 8
+            tls_retry_interval = {tls_retry_interval}
+            "#
+        );
+        toml::from_str(&content).unwrap()
+    }
+
+    fn new_connection(bridge: config::Bridge) -> BridgeConnection {
+        let (_sender, receiver) = mpsc::channel(1);
+        let (dispatcher_sender, _dispatcher_receiver) = mpsc::channel(1);
+        BridgeConnection::new(bridge, receiver, dispatcher_sender)
+    }
+
+    /// Start a TLS server with self-signed cert, returns its address and number of
+    /// accepted connections.
+    async fn start_tls_server() -> (String, Arc<AtomicUsize>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![rustls::Certificate(cert.serialize_der().unwrap())],
+                rustls::PrivateKey(cert.serialize_private_key_der()),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let accepted_clone = accepted.clone();
+        tokio::spawn(async move {
+            while let Ok((tcp_stream, _address)) = listener.accept().await {
+                accepted_clone.fetch_add(1, Ordering::SeqCst);
+                let _ret = acceptor.accept(tcp_stream).await;
+            }
+        });
+        (address, accepted)
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_cert() {
+        let (address, _accepted) = start_tls_server().await;
+        let bridge = new_bridge(&address, 1, 60);
+        let err = new_connection(bridge.clone()).connect().await.unwrap_err();
+        assert!(is_tls_error(&err));
+
+        let mut backoff = Backoff::new(&bridge);
+        assert_eq!(backoff.next_delay(&err), Duration::from_secs(60));
+        assert_eq!(backoff.next_delay(&err), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_cert_backoff() {
+        let (address, accepted) = start_tls_server().await;
+        let bridge = new_bridge(&address, 1, 60);
+        let handle = tokio::spawn(new_connection(bridge).run_loop());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        handle.abort();
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_network_failure_backoff() {
+        // Get a free port which is not listened.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let bridge = new_bridge(&address, 1, 60);
+        let err = new_connection(bridge.clone()).connect().await.unwrap_err();
+        assert!(!is_tls_error(&err));
+
+        let mut backoff = Backoff::new(&bridge);
+        let delays: Vec<u64> = (0..5)
+            .map(|_i| backoff.next_delay(&err).as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 8]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(&err), Duration::from_secs(1));
+    }
+}
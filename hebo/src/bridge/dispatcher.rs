@@ -2,18 +2,50 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
+use codec::{v3, PacketId};
+use tokio::sync::mpsc::error::TrySendError;
+
 use super::BridgeApp;
 use crate::commands::DispatcherToBridgeCmd;
 use crate::error::Error;
 
 impl BridgeApp {
     #[allow(clippy::unused_async)]
-
     pub(super) async fn handle_dispatcher_cmd(
-        &mut self,
+        &self,
         cmd: DispatcherToBridgeCmd,
     ) -> Result<(), Error> {
-        log::info!("cmd: {:?}", cmd);
+        match cmd {
+            DispatcherToBridgeCmd::Publish(packet) => self.on_dispatcher_publish(&packet),
+        }
+    }
+
+    /// Forward local message to remote brokers with matching topic mapping.
+    fn on_dispatcher_publish(&self, packet: &v3::PublishPacket) -> Result<(), Error> {
+        for remote in &self.remotes {
+            let Some((topic, qos)) = remote.mappings.iter().find_map(|mapping| {
+                mapping
+                    .to_remote(packet.topic())
+                    .map(|topic| (topic, mapping.qos()))
+            }) else {
+                continue;
+            };
+            let mut packet = packet.clone();
+            packet.set_topic(&topic)?;
+            packet.set_qos(qos);
+            packet.set_packet_id(PacketId::new(0));
+
+            // Do not block dispatcher if remote broker is slow or disconnected.
+            match remote.sender.try_send(packet) {
+                Ok(()) => (),
+                Err(TrySendError::Full(_packet)) => {
+                    log::warn!("bridge {}: Queue is full, drop message", remote.name);
+                }
+                Err(TrySendError::Closed(_packet)) => {
+                    log::error!("bridge {}: Connection is stopped", remote.name);
+                }
+            }
+        }
         Ok(())
     }
 }
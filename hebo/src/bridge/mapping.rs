@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Rewrite topics of messages forwarded between local and remote broker.
+
+use codec::QoS;
+
+use crate::config::{BridgeDirection, BridgeTopic};
+
+/// Returns true if topic filter matches topic name.
+#[must_use]
+pub fn is_match(filter: &str, topic: &str) -> bool {
+    // The Server MUST NOT match Topic Filters starting with a wildcard character (# or +)
+    // with Topic Names beginning with a $ character [MQTT-4.7.2-1].
+    if topic.starts_with('$') && (filter.starts_with('#') || filter.starts_with('+')) {
+        return false;
+    }
+
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => (),
+            (level, Some(topic_level)) if level == topic_level => (),
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Topic filters of a bridge topic in both brokers.
+#[derive(Debug, Clone)]
+pub struct TopicMapping {
+    direction: BridgeDirection,
+    qos: QoS,
+    local_prefix: String,
+    remote_prefix: String,
+    local_filter: String,
+    remote_filter: String,
+}
+
+impl TopicMapping {
+    #[must_use]
+    pub fn new(topic: &BridgeTopic) -> Self {
+        Self {
+            direction: topic.direction(),
+            qos: topic.qos(),
+            local_prefix: topic.local_prefix().to_string(),
+            remote_prefix: topic.remote_prefix().to_string(),
+            local_filter: format!("{}{}", topic.local_prefix(), topic.topic()),
+            remote_filter: format!("{}{}", topic.remote_prefix(), topic.topic()),
+        }
+    }
+
+    #[must_use]
+    pub const fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    /// Topic filter in local broker, if local messages are forwarded.
+    #[must_use]
+    pub fn out_filter(&self) -> Option<&str> {
+        (self.direction != BridgeDirection::In).then_some(self.local_filter.as_str())
+    }
+
+    /// Topic filter subscribed in remote broker, if remote messages are forwarded.
+    #[must_use]
+    pub fn in_filter(&self) -> Option<&str> {
+        (self.direction != BridgeDirection::Out).then_some(self.remote_filter.as_str())
+    }
+
+    /// Get topic name in remote broker if local message with `topic` is forwarded.
+    #[must_use]
+    pub fn to_remote(&self, topic: &str) -> Option<String> {
+        let filter = self.out_filter()?;
+        Self::rewrite(filter, topic, &self.local_prefix, &self.remote_prefix)
+    }
+
+    /// Get topic name in local broker if remote message with `topic` is forwarded.
+    #[must_use]
+    pub fn to_local(&self, topic: &str) -> Option<String> {
+        let filter = self.in_filter()?;
+        Self::rewrite(filter, topic, &self.remote_prefix, &self.local_prefix)
+    }
+
+    fn rewrite(filter: &str, topic: &str, from_prefix: &str, to_prefix: &str) -> Option<String> {
+        if !is_match(filter, topic) {
+            return None;
+        }
+        let topic = topic.strip_prefix(from_prefix)?;
+        Some(format!("{to_prefix}{topic}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_match, TopicMapping};
+    use crate::config::BridgeTopic;
+
+    fn new_mapping(content: &str) -> TopicMapping {
+        let topic: BridgeTopic = toml::from_str(content).unwrap();
+        TopicMapping::new(&topic)
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(is_match("sensors/#", "sensors"));
+        assert!(is_match("sensors/#", "sensors/room1/temp"));
+        assert!(is_match("sensors/+/temp", "sensors/room1/temp"));
+        assert!(!is_match("sensors/+/temp", "sensors/room1"));
+        assert!(!is_match("sensors/room1", "sensors"));
+        assert!(!is_match("#", "$SYS/broker/uptime"));
+        assert!(is_match("$SYS/#", "$SYS/broker/uptime"));
+    }
+
+    #[test]
+    fn test_rewrite_prefix() {
+        let mapping = new_mapping(
+            r#"
+            topic = "sensors/#"
+            direction = "both"
+            qos = 1
+            local_prefix = "site1/"
+            remote_prefix = "factory/site1/"
+            "#,
+        );
+        assert_eq!(mapping.out_filter(), Some("site1/sensors/#"));
+        assert_eq!(mapping.in_filter(), Some("factory/site1/sensors/#"));
+        assert_eq!(
+            mapping.to_remote("site1/sensors/temp").as_deref(),
+            Some("factory/site1/sensors/temp")
+        );
+        assert_eq!(
+            mapping.to_local("factory/site1/sensors/temp").as_deref(),
+            Some("site1/sensors/temp")
+        );
+        assert_eq!(mapping.to_remote("site2/sensors/temp"), None);
+    }
+
+    #[test]
+    fn test_direction() {
+        let mapping = new_mapping(r#"topic = "cmd/#""#);
+        assert_eq!(
+            mapping.to_remote("cmd/reboot").as_deref(),
+            Some("cmd/reboot")
+        );
+        assert_eq!(mapping.to_local("cmd/reboot"), None);
+
+        let mapping = new_mapping(
+            r#"
+            topic = "cmd/#"
+            direction = "in"
+            "#,
+        );
+        assert_eq!(mapping.to_remote("cmd/reboot"), None);
+        assert_eq!(
+            mapping.to_local("cmd/reboot").as_deref(),
+            Some("cmd/reboot")
+        );
+    }
+}
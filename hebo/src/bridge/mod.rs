@@ -2,25 +2,52 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use tokio::sync::mpsc::{Receiver, Sender};
+use codec::v3;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
 
 use crate::commands::{BridgeToDispatcherCmd, DispatcherToBridgeCmd, ServerContextToBridgeCmd};
+use crate::config;
 
+mod connection;
 mod dispatcher;
+mod mapping;
+mod resolver;
 mod server;
 
-#[allow(dead_code)]
+pub use connection::{is_tls_error, Backoff, BridgeConnection};
+pub use mapping::{is_match, TopicMapping};
+pub use resolver::Resolver;
+
+/// Maximum number of local messages queued for each remote broker,
+/// newer messages are dropped while the queue is full.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Local side of a connection to remote broker.
+#[derive(Debug)]
+struct RemoteBroker {
+    name: String,
+    mappings: Vec<TopicMapping>,
+    sender: Sender<v3::PublishPacket>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct BridgeApp {
+    bridges: Vec<config::Bridge>,
+    remotes: Vec<RemoteBroker>,
+
     dispatcher_sender: Sender<BridgeToDispatcherCmd>,
     dispatcher_receiver: Receiver<DispatcherToBridgeCmd>,
 
     server_ctx_receiver: Receiver<ServerContextToBridgeCmd>,
+
+    connection_handles: Vec<JoinHandle<()>>,
 }
 
 impl BridgeApp {
     #[must_use]
     pub const fn new(
+        bridges: Vec<config::Bridge>,
         // dispatcher
         dispatcher_sender: Sender<BridgeToDispatcherCmd>,
         dispatcher_receiver: Receiver<DispatcherToBridgeCmd>,
@@ -28,13 +55,31 @@ impl BridgeApp {
         server_ctx_receiver: Receiver<ServerContextToBridgeCmd>,
     ) -> Self {
         Self {
+            bridges,
+            remotes: Vec::new(),
             dispatcher_sender,
             dispatcher_receiver,
             server_ctx_receiver,
+            connection_handles: Vec::new(),
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
+        for bridge in &self.bridges {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            self.remotes.push(RemoteBroker {
+                name: bridge.name().to_string(),
+                mappings: bridge.topics().iter().map(TopicMapping::new).collect(),
+                sender,
+            });
+            let connection =
+                BridgeConnection::new(bridge.clone(), receiver, self.dispatcher_sender.clone());
+            let handle = tokio::spawn(async move {
+                connection.run_loop().await;
+            });
+            self.connection_handles.push(handle);
+        }
+
         loop {
             tokio::select! {
                 Some(cmd) = self.dispatcher_receiver.recv() => {
@@ -43,6 +88,13 @@ impl BridgeApp {
                     }
                 }
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToBridgeCmd::Stop) {
+                        log::info!("bridge: Stop app");
+                        for handle in self.connection_handles.drain(..) {
+                            handle.abort();
+                        }
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
@@ -0,0 +1,154 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Resolve and cache addresses of remote broker.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::Instant;
+
+/// Cache resolved addresses of remote broker and fail over between them.
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    /// Host name and port of remote broker.
+    address: String,
+    ttl: Duration,
+    addresses: Vec<SocketAddr>,
+    resolved_at: Option<Instant>,
+}
+
+impl Resolver {
+    #[must_use]
+    pub const fn new(address: String, ttl: Duration) -> Self {
+        Self {
+            address,
+            ttl,
+            addresses: Vec::new(),
+            resolved_at: None,
+        }
+    }
+
+    /// Get cached addresses, the last connected one comes first.
+    #[must_use]
+    pub fn addresses(&self) -> &[SocketAddr] {
+        &self.addresses
+    }
+
+    fn is_expired(&self) -> bool {
+        self.resolved_at
+            .map_or(true, |resolved_at| resolved_at.elapsed() >= self.ttl)
+    }
+
+    /// Resolve host name if cached addresses are expired.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if host name failed to resolve and no address is cached.
+    pub async fn resolve(&mut self) -> io::Result<&[SocketAddr]> {
+        if !self.is_expired() {
+            return Ok(&self.addresses);
+        }
+
+        match lookup_host(&self.address).await {
+            Ok(addresses) => {
+                let addresses: Vec<SocketAddr> = addresses.collect();
+                if addresses.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No address found for {}", self.address),
+                    ));
+                }
+                // Keep order of cached addresses, so that last connected one is tried first.
+                self.addresses.retain(|address| addresses.contains(address));
+                for address in addresses {
+                    if !self.addresses.contains(&address) {
+                        self.addresses.push(address);
+                    }
+                }
+                self.resolved_at = Some(Instant::now());
+            }
+            Err(err) if self.addresses.is_empty() || self.ttl.is_zero() => return Err(err),
+            Err(err) => {
+                log::warn!(
+                    "bridge: Failed to resolve {}, use cached addresses, err: {err}",
+                    self.address
+                );
+            }
+        }
+        Ok(&self.addresses)
+    }
+
+    /// Connect to each address in order until one succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns error of the last address if none of them is connected.
+    pub async fn connect(&mut self) -> io::Result<TcpStream> {
+        let addresses = self.resolve().await?.to_vec();
+        let mut last_err = None;
+        for (index, address) in addresses.iter().enumerate() {
+            match TcpStream::connect(address).await {
+                Ok(stream) => {
+                    self.addresses[..=index].rotate_right(1);
+                    return Ok(stream);
+                }
+                Err(err) => {
+                    log::warn!("bridge: Failed to connect to {address}, err: {err}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::time::Instant;
+
+    use super::Resolver;
+
+    #[tokio::test]
+    async fn test_failover() {
+        // Get a free port which is not listened.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unreachable = listener.local_addr().unwrap();
+        drop(listener);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+
+        // Host name resolved to two addresses, the first one is down.
+        let mut resolver = Resolver::new("broker.test:1883".to_string(), Duration::from_secs(60));
+        resolver.addresses = vec![unreachable, reachable];
+        resolver.resolved_at = Some(Instant::now());
+
+        let stream = resolver.connect().await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), reachable);
+        assert_eq!(resolver.addresses(), [reachable, unreachable]);
+
+        drop(listener);
+        assert!(resolver.connect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut resolver = Resolver::new(address.to_string(), Duration::from_secs(60));
+        assert_eq!(resolver.resolve().await.unwrap(), [address]);
+        let resolved_at = resolver.resolved_at;
+        assert_eq!(resolver.resolve().await.unwrap(), [address]);
+        assert_eq!(resolver.resolved_at, resolved_at);
+
+        let mut resolver = Resolver::new(address.to_string(), Duration::ZERO);
+        assert!(resolver.connect().await.is_ok());
+        let resolved_at = resolver.resolved_at;
+        assert!(resolver.connect().await.is_ok());
+        assert_ne!(resolver.resolved_at, resolved_at);
+    }
+}
@@ -3,17 +3,37 @@
 // in the LICENSE file.
 
 use codec::{v3, v5, PacketId, ProtocolLevel, QoS};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
 
-use crate::types::{ListenerId, SessionGid, SessionId, SessionInfo, Uptime};
+#[cfg(feature = "acl")]
+use crate::acl::rules::AclRules;
+use crate::auth::AuthSettings;
+use crate::cache_types::SystemMetrics;
+use crate::types::{
+    AclPacket, AclRequest, ClientInfo, ListenerId, PublishRecord, Redirect, SessionGid, SessionId,
+    SessionInfo, SubscriptionInfo, Uptime,
+};
 
-use crate::session::CachedSession;
+use crate::session::{CachedSession, OutgoingPacket};
 
 #[derive(Debug, Clone)]
 pub enum ListenerToAuthCmd {
-    /// `(session_gid, connect_packet)` pair.
-    RequestAuth(SessionGid, v3::ConnectPacket),
-    RequestAuthV5(SessionGid, v5::ConnectPacket),
+    /// `(session_gid, remote_address, identity, connect_packet)` tuple.
+    RequestAuth(
+        SessionGid,
+        Option<SocketAddr>,
+        Option<String>,
+        v3::ConnectPacket,
+    ),
+    RequestAuthV5(
+        SessionGid,
+        Option<SocketAddr>,
+        Option<String>,
+        v5::ConnectPacket,
+    ),
 }
 
 #[derive(Debug, Clone)]
@@ -25,26 +45,19 @@ pub enum AuthToListenerCmd {
 
 #[derive(Debug, Clone)]
 pub enum AclToListenerCmd {
-    /// `(session_id, publish_packet, accepted)` pair.
-    PublishAck(SessionId, v3::PublishPacket, bool),
-    PublishAckV5(SessionId, v5::PublishPacket, bool),
-
-    SubscribeAck(SessionId, v3::SubscribePacket, Vec<v3::SubscribeAck>, bool),
-    SubscribeAckV5(SessionId, v5::SubscribePacket, Vec<v5::ReasonCode>, bool),
+    /// Response to `CheckAcl`, `(session_id, packet, granted)`.
+    ///
+    /// `granted` contains a flag for each topic in packet.
+    CheckAclResp(SessionId, AclPacket, Vec<bool>),
 }
 
 #[derive(Debug, Clone)]
 pub enum ListenerToAclCmd {
-    /// Check publish packet.
-    Publish(SessionGid, v3::PublishPacket),
-    PublishV5(SessionGid, v5::PublishPacket),
-
-    /// Check subscribe packet.
-    Subscribe(SessionGid, v3::SubscribePacket),
-    SubscribeV5(SessionGid, v5::SubscribePacket),
+    /// Check whether client may publish or subscribe to topics in packet.
+    CheckAcl(SessionGid, AclRequest),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum ListenerToSessionCmd {
     /// Accepted or not.
     ConnectAck(v3::ConnectAckPacket, Option<CachedSession>),
@@ -62,9 +75,14 @@ pub enum ListenerToSessionCmd {
     SubscribeAck(v3::SubscribeAckPacket),
     SubscribeAckV5(v5::SubscribeAckPacket),
 
-    /// Disconnect client connection.
-    Disconnect,
-    DisconnectV5,
+    /// Disconnect client connection, reason code is only sent to v5 clients.
+    Disconnect(v5::ReasonCode),
+
+    /// Stop delivering new messages, and redirect client after inflight messages are acked.
+    Drain(Redirect),
+
+    /// Get number of messages sent to client but not acknowledged yet.
+    GetInflightMessages(oneshot::Sender<usize>),
 }
 
 #[derive(Debug, Clone)]
@@ -83,6 +101,15 @@ pub enum SessionToListenerCmd {
 
     Disconnect(SessionId),
     DisconnectV5(SessionId),
+
+    /// Save session state when clean session flag is off.
+    CacheSession(SessionId, CachedSession),
+
+    /// Publish will message after `(session_id, client_id)` is closed for the delay interval.
+    DelayedWillV5(SessionId, String, v5::PublishPacket, Duration),
+
+    /// Will Delay Interval of `(session_id, client_id)` elapsed, sent by timer task.
+    WillDelayElapsed(SessionId, String),
 }
 
 #[derive(Debug, Clone)]
@@ -100,9 +127,11 @@ pub enum DispatcherToListenerCmd {
 pub enum ListenerToDispatcherCmd {
     // `(session_gid, client_id, protocol_level)` pair.
     CheckCachedSession(SessionGid, String, ProtocolLevel),
+    /// Discard cached session of `client_id`, as it connects with clean session flag on.
+    DiscardCachedSession(String),
 
-    Publish(v3::PublishPacket),
-    PublishV5(v5::PublishPacket),
+    Publish(ListenerId, v3::PublishPacket),
+    PublishV5(ListenerId, v5::PublishPacket),
 
     Subscribe(SessionGid, v3::SubscribePacket),
     SubscribeV5(SessionGid, v5::SubscribePacket),
@@ -112,6 +141,13 @@ pub enum ListenerToDispatcherCmd {
 
     SessionAdded(ListenerId),
     SessionRemoved(ListenerId),
+
+    /// Connect request of client is accepted, `(listener_id, client_id)` pair.
+    ClientConnected(ListenerId, String),
+    /// Client is disconnected, and it is not taken over by another session.
+    ClientDisconnected(ListenerId, String),
+
+    CacheSession(SessionGid, CachedSession),
 }
 
 #[derive(Debug, Clone)]
@@ -130,6 +166,8 @@ pub enum DispatcherToMetricsCmd {
     SubscriptionsAdded(ListenerId, usize),
     /// listener id, count
     SubscriptionsRemoved(ListenerId, usize),
+    /// topic filter, number of its subscribers
+    TopicSubscribers(Vec<(String, usize)>),
 
     /// listener id, count, bytes
     RetainedMessageAdded(ListenerId, usize, usize),
@@ -162,16 +200,25 @@ pub enum DispatcherToBackendsCmd {
 
     /// listener id, session id
     SessionRemoved(ListenerId, SessionId),
+
+    /// Write message published by client to message store.
+    Persist(PublishRecord),
 }
 
 #[derive(Debug, Clone)]
 pub enum BackendsToDispatcherCmd {}
 
 #[derive(Debug, Clone)]
-pub enum DispatcherToBridgeCmd {}
+pub enum DispatcherToBridgeCmd {
+    /// Local message matching topics forwarded to remote brokers.
+    Publish(v3::PublishPacket),
+}
 
 #[derive(Debug, Clone)]
-pub enum BridgeToDispatcherCmd {}
+pub enum BridgeToDispatcherCmd {
+    /// Message received from remote broker, with topic name in local broker.
+    Publish(v3::PublishPacket),
+}
 
 #[derive(Debug, Clone)]
 pub enum DispatcherToGatewayCmd {}
@@ -180,37 +227,142 @@ pub enum DispatcherToGatewayCmd {}
 pub enum GatewayToDispatcherCmd {}
 
 #[derive(Debug, Clone)]
-pub enum DispatcherToRuleEngineCmd {}
+pub enum DispatcherToRuleEngineCmd {
+    /// Message received from client, evaluated by rules before it is dispatched.
+    Publish(ListenerId, OutgoingPacket),
+}
 
 #[derive(Debug, Clone)]
-pub enum RuleEngineToDispatcherCmd {}
+pub enum RuleEngineToDispatcherCmd {
+    /// Message transformed or republished by rules, dispatched without evaluating rules again.
+    Publish(ListenerId, OutgoingPacket),
+}
 
 // Server context
 
 #[derive(Debug)]
-pub enum ServerContextToAclCmd {}
+pub enum ServerContextToAclCmd {
+    /// New listener is started after config reloaded.
+    ListenerAdded(ListenerId, UnboundedSender<AclToListenerCmd>),
+    ListenerRemoved(ListenerId),
+
+    /// Replace acl rules after config reloaded.
+    #[cfg(feature = "acl")]
+    UpdateSecurity(Option<AclRules>),
+
+    /// Stop app before server exits.
+    Stop,
+}
 
 #[derive(Debug)]
-pub enum ServerContextToAuthCmd {}
+pub enum ServerContextToAuthCmd {
+    /// New listener is started after config reloaded.
+    ListenerAdded(ListenerId, UnboundedSender<AuthToListenerCmd>),
+    ListenerRemoved(ListenerId),
+
+    /// Apply security section of reloaded config.
+    UpdateSecurity(Box<AuthSettings>),
+
+    Stop,
+}
 
 #[derive(Debug)]
-pub enum ServerContextToBackendsCmd {}
+pub enum ServerContextToBackendsCmd {
+    /// Get the last messages of topics matching topic filter from message store,
+    /// None if message store is disabled or does not support history query.
+    GetHistory(String, usize, oneshot::Sender<Option<Vec<PublishRecord>>>),
+
+    Stop,
+}
 
 #[derive(Debug)]
-pub enum ServerContextToBridgeCmd {}
+pub enum ServerContextToBridgeCmd {
+    Stop,
+}
 
 #[derive(Debug)]
-pub enum ServerContextToGatewayCmd {}
+pub enum ServerContextToDispatcherCmd {
+    /// New listener is started after config reloaded.
+    ///
+    /// `(listener_id, address, sender)` pair.
+    ListenerAdded(ListenerId, String, UnboundedSender<DispatcherToListenerCmd>),
+    ListenerRemoved(ListenerId),
+
+    /// Get number of topic filters subscribed by each session.
+    CountSubscriptions(Vec<SessionGid>, oneshot::Sender<Vec<usize>>),
+
+    /// Get topic filters subscribed by session, sorted by topic filter.
+    GetSubscriptions(SessionGid, oneshot::Sender<Vec<SubscriptionInfo>>),
+
+    Stop,
+}
+
+#[derive(Debug)]
+pub enum ServerContextToGatewayCmd {
+    Stop,
+}
+
+#[derive(Debug)]
+pub enum ServerContextToListenerCmd {
+    /// Disconnect all sessions and stop accepting new connections.
+    Stop,
+
+    /// Same as `Stop`, but listener is removed from config while server keeps running.
+    Remove,
+
+    /// Drain all sessions of this listener.
+    DrainSessions(Redirect),
+
+    /// Drain session with `client_id`, responds whether it is found in this listener.
+    DrainClient(String, Redirect, oneshot::Sender<bool>),
+
+    /// Disconnect session with `client_id`, responds whether it is found in this listener.
+    DisconnectClient(String, oneshot::Sender<bool>),
+
+    /// List connected clients, or only the one with client id if specified.
+    ///
+    /// `subscriptions` of clients are left as 0, which are counted by dispatcher.
+    ListClients(Option<String>, oneshot::Sender<Vec<ClientInfo>>),
+}
 
 #[derive(Debug)]
 pub enum ServerContextToMetricsCmd {
     MetricsGetUptime(oneshot::Sender<Uptime>),
+    /// Get snapshot of broker wide counters.
+    MetricsGetSystem(oneshot::Sender<SystemMetrics>),
+    Stop,
 }
 
 #[derive(Debug)]
-pub enum ServerContextToRuleEngineCmd {}
+pub enum ServerContextToRuleEngineCmd {
+    Stop,
+}
 
 #[derive(Debug)]
 pub enum DashboardToServerContexCmd {
     MetricsGetUptime(oneshot::Sender<Uptime>),
+
+    /// Get snapshot of broker wide counters from metrics app.
+    MetricsGetSystem(oneshot::Sender<SystemMetrics>),
+
+    /// Drain all sessions of listener, responds whether listener is found.
+    DrainListener(ListenerId, Redirect, oneshot::Sender<bool>),
+
+    /// Drain session with client id, responds whether client is online.
+    DrainClient(String, Redirect, oneshot::Sender<bool>),
+
+    /// Disconnect session with client id, responds whether client is online.
+    DisconnectClient(String, oneshot::Sender<bool>),
+
+    /// List connected clients of all listeners.
+    ListClients(oneshot::Sender<Vec<ClientInfo>>),
+
+    /// Get connected client with client id.
+    GetClient(String, oneshot::Sender<Option<ClientInfo>>),
+
+    /// Get topic filters subscribed by client, None if client is offline.
+    GetClientSubscriptions(String, oneshot::Sender<Option<Vec<SubscriptionInfo>>>),
+
+    /// Get the last messages of topics matching topic filter, None if history is not available.
+    GetTopicHistory(String, usize, oneshot::Sender<Option<Vec<PublishRecord>>>),
 }
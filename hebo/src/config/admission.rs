@@ -0,0 +1,99 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use serde::Deserialize;
+
+/// Admission control of new connections when server is overloaded.
+///
+/// When any of the load thresholds is reached, new connections are rejected with
+/// `ServerBusy` reason code, or redirected to another server with `UseAnotherServer`
+/// reason code if `server_reference` is set.
+///
+/// MQTT v3.1.1 clients are rejected with `ServerUnavailable` return code.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Eq)]
+pub struct Admission {
+    /// Reject new connections if number of connections to this listener
+    /// is equal to or larger than this value.
+    ///
+    /// Default is 0, which means no limit.
+    #[serde(default = "Admission::default_busy_connections")]
+    busy_connections: usize,
+
+    /// Reject new connections if memory used by server process in bytes
+    /// is equal to or larger than this value.
+    ///
+    /// Default is 0, which means no limit.
+    #[serde(default = "Admission::default_busy_memory")]
+    busy_memory: u64,
+
+    /// Reject new connections if number of publish messages per second received
+    /// by this listener is equal to or larger than this value.
+    ///
+    /// Default is 0, which means no limit.
+    #[serde(default = "Admission::default_busy_message_rate")]
+    busy_message_rate: u32,
+
+    /// Address of another server which clients are redirected to when server is busy.
+    ///
+    /// Example: `mqtt2.example.com:1883`
+    ///
+    /// Default is None, which means clients are rejected with `ServerBusy`.
+    #[serde(default = "Admission::default_server_reference")]
+    server_reference: Option<String>,
+}
+
+impl Admission {
+    #[inline]
+    #[must_use]
+    pub const fn default_busy_connections() -> usize {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_busy_memory() -> u64 {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_busy_message_rate() -> u32 {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_server_reference() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn busy_connections(&self) -> usize {
+        self.busy_connections
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn busy_memory(&self) -> u64 {
+        self.busy_memory
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn busy_message_rate(&self) -> u32 {
+        self.busy_message_rate
+    }
+
+    #[must_use]
+    pub fn server_reference(&self) -> Option<&str> {
+        self.server_reference.as_deref()
+    }
+
+    /// Returns true if no load threshold is set.
+    #[must_use]
+    pub const fn is_disabled(&self) -> bool {
+        self.busy_connections == 0 && self.busy_memory == 0 && self.busy_message_rate == 0
+    }
+}
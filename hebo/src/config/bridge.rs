@@ -0,0 +1,414 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use codec::topic::{validate_pub_topic, validate_sub_topic};
+use codec::QoS;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use super::validate_file;
+use crate::error::{Error, ErrorKind};
+
+/// Outbound connection to a remote broker.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Bridge {
+    /// Name of this bridge, also used in client id of the connection.
+    name: String,
+
+    /// Address of remote broker.
+    ///
+    /// Example: `mqtt.example.com:8883`
+    address: String,
+
+    /// Client id used to connect to remote broker.
+    ///
+    /// Default is None, which means `hebo-bridge-{name}` is used.
+    #[serde(default = "Bridge::default_client_id")]
+    client_id: Option<String>,
+
+    /// Username used to connect to remote broker.
+    ///
+    /// Default is None.
+    #[serde(default = "Bridge::default_username")]
+    username: Option<String>,
+
+    /// Password used to connect to remote broker.
+    ///
+    /// Default is None.
+    #[serde(default = "Bridge::default_password")]
+    password: Option<String>,
+
+    /// Keep alive seconds of connection to remote broker, set to 0 to disable.
+    ///
+    /// Default is 60s.
+    #[serde(default = "Bridge::default_keep_alive")]
+    keep_alive: u16,
+
+    /// Topics forwarded between local and remote broker.
+    ///
+    /// Default is empty.
+    #[serde(default = "Bridge::default_topics")]
+    topics: Vec<BridgeTopic>,
+
+    /// Connect to remote broker over TLS.
+    ///
+    /// Default is false.
+    #[serde(default = "Bridge::default_tls")]
+    tls: bool,
+
+    /// CA file used to verify cert of remote broker.
+    ///
+    /// Default is None, which means builtin root certificates are used.
+    #[serde(default = "Bridge::default_ca_file")]
+    ca_file: Option<PathBuf>,
+
+    /// Server name used to verify cert of remote broker.
+    ///
+    /// Default is None, which means host part of `address` is used.
+    #[serde(default = "Bridge::default_server_name")]
+    server_name: Option<String>,
+
+    /// Seconds to wait before reconnecting after a network failure.
+    ///
+    /// This interval is doubled on each consecutive failure, up to `maximum_retry_interval`.
+    ///
+    /// Default is 5s.
+    #[serde(default = "Bridge::default_retry_interval")]
+    retry_interval: u32,
+
+    /// Maximum seconds to wait before reconnecting after network failures.
+    ///
+    /// Default is 300s.
+    #[serde(default = "Bridge::default_maximum_retry_interval")]
+    maximum_retry_interval: u32,
+
+    /// Seconds to wait before reconnecting after a TLS handshake failure.
+    ///
+    /// Expired or untrusted cert will not be fixed by retrying immediately,
+    /// so this interval is usually longer.
+    ///
+    /// Default is 300s.
+    #[serde(default = "Bridge::default_tls_retry_interval")]
+    tls_retry_interval: u32,
+
+    /// Seconds to cache resolved addresses of remote broker.
+    ///
+    /// If host name in `address` resolves to multiple addresses, they are tried
+    /// in order until one is connected, and that one is tried first next time.
+    /// Cached addresses are still used if host name fails to resolve after they expire.
+    ///
+    /// Default is 0, which means host name is resolved on each connection attempt.
+    #[serde(default = "Bridge::default_dns_cache_ttl")]
+    dns_cache_ttl: u32,
+}
+
+impl Bridge {
+    #[inline]
+    #[must_use]
+    pub const fn default_client_id() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_username() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_password() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_keep_alive() -> u16 {
+        60
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_topics() -> Vec<BridgeTopic> {
+        Vec::new()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_tls() -> bool {
+        false
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_ca_file() -> Option<PathBuf> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_server_name() -> Option<String> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_retry_interval() -> u32 {
+        5
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_maximum_retry_interval() -> u32 {
+        300
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_tls_retry_interval() -> u32 {
+        300
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_dns_cache_ttl() -> u32 {
+        0
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Get client id used to connect to remote broker.
+    #[must_use]
+    pub fn client_id(&self) -> String {
+        self.client_id
+            .clone()
+            .unwrap_or_else(|| format!("hebo-bridge-{}", self.name))
+    }
+
+    #[must_use]
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    #[must_use]
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn keep_alive(&self) -> u16 {
+        self.keep_alive
+    }
+
+    #[must_use]
+    pub fn topics(&self) -> &[BridgeTopic] {
+        &self.topics
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn tls(&self) -> bool {
+        self.tls
+    }
+
+    #[must_use]
+    pub fn ca_file(&self) -> Option<&Path> {
+        self.ca_file.as_deref()
+    }
+
+    /// Get server name to verify cert of remote broker.
+    #[must_use]
+    pub fn server_name(&self) -> &str {
+        self.server_name.as_deref().unwrap_or_else(|| {
+            self.address
+                .rsplit_once(':')
+                .map_or(self.address.as_str(), |(host, _port)| host)
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn retry_interval(&self) -> u32 {
+        self.retry_interval
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maximum_retry_interval(&self) -> u32 {
+        self.maximum_retry_interval
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn tls_retry_interval(&self) -> u32 {
+        self.tls_retry_interval
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn dns_cache_ttl(&self) -> u32 {
+        self.dns_cache_ttl
+    }
+
+    /// Validate bridge config.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any topic mapping is invalid, or TLS and retry options
+    /// are inconsistent.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (index, topic) in self.topics.iter().enumerate() {
+            topic.validate().map_err(|err| {
+                Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("`topics[{index}]` of bridge {}: {err}", self.name),
+                )
+            })?;
+        }
+
+        if let Some(ca_file) = &self.ca_file {
+            if !self.tls {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!(
+                        "`ca_file` of bridge {} requires `tls` to be true",
+                        self.name
+                    ),
+                ));
+            }
+            validate_file("ca_file", ca_file)?;
+        }
+
+        if self.retry_interval > self.maximum_retry_interval {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`retry_interval` {} of bridge {} is larger than `maximum_retry_interval` {}",
+                    self.retry_interval, self.name, self.maximum_retry_interval
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Direction of messages forwarded by bridge.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BridgeDirection {
+    /// Forward local messages to remote broker.
+    #[default]
+    #[serde(alias = "out")]
+    Out,
+
+    /// Forward messages of remote broker to local subscribers.
+    #[serde(alias = "in")]
+    In,
+
+    /// Forward messages in both directions.
+    #[serde(alias = "both")]
+    Both,
+}
+
+/// Topic mapping of bridge.
+///
+/// Topic filter is `local_prefix + topic` in local broker and `remote_prefix + topic`
+/// in remote broker, topic names of forwarded messages are rewritten by replacing
+/// one prefix with the other.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct BridgeTopic {
+    /// Topic filter, without prefix.
+    topic: String,
+
+    /// Default is out.
+    #[serde(default = "BridgeTopic::default_direction")]
+    direction: BridgeDirection,
+
+    /// `QoS` used to publish and subscribe in remote broker.
+    ///
+    /// Default is 0.
+    #[serde(default = "BridgeTopic::default_qos")]
+    qos: u8,
+
+    /// Prefix of topics in local broker.
+    ///
+    /// Default is empty.
+    #[serde(default = "BridgeTopic::default_prefix")]
+    local_prefix: String,
+
+    /// Prefix of topics in remote broker.
+    ///
+    /// Default is empty.
+    #[serde(default = "BridgeTopic::default_prefix")]
+    remote_prefix: String,
+}
+
+impl BridgeTopic {
+    #[inline]
+    #[must_use]
+    pub const fn default_direction() -> BridgeDirection {
+        BridgeDirection::Out
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_qos() -> u8 {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_prefix() -> String {
+        String::new()
+    }
+
+    #[must_use]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn direction(&self) -> BridgeDirection {
+        self.direction
+    }
+
+    #[must_use]
+    pub fn qos(&self) -> QoS {
+        QoS::try_from(self.qos).unwrap_or(QoS::AtMostOnce)
+    }
+
+    #[must_use]
+    pub fn local_prefix(&self) -> &str {
+        &self.local_prefix
+    }
+
+    #[must_use]
+    pub fn remote_prefix(&self) -> &str {
+        &self.remote_prefix
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if QoS::try_from(self.qos).is_err() {
+            return Err(format!("Invalid qos: {}", self.qos));
+        }
+        for prefix in [&self.local_prefix, &self.remote_prefix] {
+            if !prefix.is_empty() && validate_pub_topic(prefix).is_err() {
+                return Err(format!("Invalid topic prefix: {prefix}"));
+            }
+        }
+        let filter = format!("{}{}", self.local_prefix, self.topic);
+        validate_sub_topic(&filter).map_err(|err| format!("Invalid topic: {filter}, {err:?}"))?;
+        let filter = format!("{}{}", self.remote_prefix, self.topic);
+        validate_sub_topic(&filter).map_err(|err| format!("Invalid topic: {filter}, {err:?}"))
+    }
+}
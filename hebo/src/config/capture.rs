@@ -0,0 +1,70 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Capture raw packets of specific clients to files for offline analysis.
+///
+/// Each packet is written as a line of `<timestamp> <direction> <packet type> <hex bytes>`,
+/// to `<directory>/<client id>.cap`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Capture {
+    /// Client ids whose packets are captured.
+    ///
+    /// Default is empty, which means capture is disabled.
+    #[serde(default = "Capture::default_client_ids")]
+    client_ids: Vec<String>,
+
+    /// Directory to save capture files.
+    ///
+    /// Default is `/var/log/hebo/capture`.
+    #[serde(default = "Capture::default_directory")]
+    directory: PathBuf,
+}
+
+impl Capture {
+    #[inline]
+    #[must_use]
+    pub const fn default_client_ids() -> Vec<String> {
+        Vec::new()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn default_directory() -> PathBuf {
+        PathBuf::from("/var/log/hebo/capture")
+    }
+
+    #[must_use]
+    pub fn client_ids(&self) -> &[String] {
+        &self.client_ids
+    }
+
+    #[must_use]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns true if no client is captured.
+    #[must_use]
+    pub fn is_disabled(&self) -> bool {
+        self.client_ids.is_empty()
+    }
+
+    /// Returns true if packets of `client_id` shall be captured.
+    #[must_use]
+    pub fn contains(&self, client_id: &str) -> bool {
+        self.client_ids.iter().any(|id| id == client_id)
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            client_ids: Self::default_client_ids(),
+            directory: Self::default_directory(),
+        }
+    }
+}
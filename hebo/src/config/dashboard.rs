@@ -4,6 +4,7 @@
 
 use serde::Deserialize;
 use std::net::{TcpListener, ToSocketAddrs};
+use std::time::Duration;
 
 use crate::error::{Error, ErrorKind};
 
@@ -21,6 +22,20 @@ pub struct Dashboard {
     /// Default is `127.0.0.1:18083`.
     #[serde(default = "Dashboard::default_address")]
     address: String,
+
+    /// Close HTTP keep-alive connections which are idle for this many seconds.
+    ///
+    /// Set to 0 to keep idle connections open.
+    ///
+    /// Default is 60.
+    #[serde(default = "Dashboard::default_idle_timeout")]
+    idle_timeout: u64,
+
+    /// Maximum body size in bytes of admin POST requests.
+    ///
+    /// Default is 64KB.
+    #[serde(default = "Dashboard::default_max_body_size")]
+    max_body_size: u64,
 }
 
 impl Dashboard {
@@ -32,6 +47,14 @@ impl Dashboard {
         "127.0.0.1:18083".to_string()
     }
 
+    const fn default_idle_timeout() -> u64 {
+        60
+    }
+
+    const fn default_max_body_size() -> u64 {
+        64 * 1024
+    }
+
     #[must_use]
     pub const fn enable(&self) -> bool {
         self.enable
@@ -42,6 +65,31 @@ impl Dashboard {
         &self.address
     }
 
+    /// Get idle timeout of HTTP connections, returns None if it is disabled.
+    #[must_use]
+    pub const fn idle_timeout(&self) -> Option<Duration> {
+        if self.idle_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.idle_timeout))
+        }
+    }
+
+    #[must_use]
+    pub const fn max_body_size(&self) -> u64 {
+        self.max_body_size
+    }
+
+    pub fn set_enable(&mut self, enable: bool) -> &mut Self {
+        self.enable = enable;
+        self
+    }
+
+    pub fn set_address(&mut self, address: &str) -> &mut Self {
+        self.address = address.to_string();
+        self
+    }
+
     /// Validate dashboard config.
     ///
     /// # Errors
@@ -80,6 +128,8 @@ impl Default for Dashboard {
         Self {
             enable: Self::default_enable(),
             address: Self::default_address(),
+            idle_timeout: Self::default_idle_timeout(),
+            max_body_size: Self::default_max_body_size(),
         }
     }
 }
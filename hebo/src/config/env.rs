@@ -0,0 +1,261 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Override config values with environment variables.
+//!
+//! Name of environment variable is derived from path of the config field:
+//! - starts with `HEBO_`
+//! - followed by section name and field name, in upper case,
+//!   like `HEBO_SECURITY_ALLOW_ANONYMOUS` for `allow_anonymous` in `[security]`
+//! - items of `listeners`, `bridges` and `rules` are selected by index,
+//!   like `HEBO_LISTENERS_0_ADDRESS`, a new item is appended if index equals to length
+//! - fields of nested tables are separated by double underscores,
+//!   like `HEBO_LISTENERS_0_ADMISSION__BUSY_CONNECTIONS`
+//!
+//! Values are parsed as toml values, like `true`, `1883` or `["a", "b"]`,
+//! or taken as strings if they are not valid toml values.
+//! Quote the value to keep it as string, like `HEBO_GENERAL_USER='"1000"'`.
+//!
+//! Variables of unknown sections are ignored.
+
+use toml::{Table, Value};
+
+use crate::error::{Error, ErrorKind};
+
+/// Prefix of environment variables.
+pub const ENV_PREFIX: &str = "HEBO_";
+
+const TABLE_SECTIONS: &[&str] = &["general", "security", "storage", "log", "dashboard"];
+const ARRAY_SECTIONS: &[&str] = &["listeners", "bridges", "rules"];
+
+/// Overlay environment variables on `table`, which is parsed from config file.
+///
+/// Returns names of variables applied.
+///
+/// # Errors
+///
+/// Returns error if name of variable does not match path of config field.
+pub fn apply_env<I>(table: &mut Table, vars: I) -> Result<Vec<String>, Error>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    // Sorted by index of array items, so that they are appended in order.
+    let mut vars: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(name, _value)| name.starts_with(ENV_PREFIX))
+        .collect();
+    vars.sort_by_cached_key(|(name, _value)| sort_key(name));
+
+    let mut names = Vec::new();
+    for (name, value) in vars {
+        let path = name[ENV_PREFIX.len()..].to_lowercase();
+        let value = parse_value(&value);
+        let applied = if let Some((section, field)) = split_section(&path, TABLE_SECTIONS) {
+            let section = get_table(table, section, &name)?;
+            set_field(section, field, value, &name)?;
+            true
+        } else if let Some((section, rest)) = split_section(&path, ARRAY_SECTIONS) {
+            let (index, field) = rest.split_once('_').ok_or_else(|| invalid_name(&name))?;
+            let index: usize = index.parse().map_err(|_err| invalid_name(&name))?;
+            let item = get_array_item(table, section, index, &name)?;
+            set_field(item, field, value, &name)?;
+            true
+        } else {
+            false
+        };
+        if applied {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+/// Get sort key of variable, as (section, index of array item, field).
+///
+/// Indices are compared as numbers, so that `listeners[10]` comes after `listeners[2]`.
+fn sort_key(name: &str) -> (String, Option<usize>, String) {
+    let path = name[ENV_PREFIX.len()..].to_lowercase();
+    if let Some((section, rest)) = split_section(&path, ARRAY_SECTIONS) {
+        if let Some((index, field)) = rest.split_once('_') {
+            if let Ok(index) = index.parse() {
+                return (section.to_string(), Some(index), field.to_string());
+            }
+        }
+    }
+    (path, None, String::new())
+}
+
+/// Split `path` into section name and the remaining field path.
+fn split_section<'a>(path: &'a str, sections: &[&'static str]) -> Option<(&'static str, &'a str)> {
+    sections.iter().find_map(|section| {
+        path.strip_prefix(section)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .map(|field| (*section, field))
+    })
+}
+
+/// Parse `value` as toml value, or take it as string.
+fn parse_value(value: &str) -> Value {
+    format!("value = {value}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+fn invalid_name(name: &str) -> Error {
+    Error::from_string(
+        ErrorKind::ConfigError,
+        format!("Environment variable {name} does not match any config field"),
+    )
+}
+
+fn get_table<'a>(table: &'a mut Table, key: &str, name: &str) -> Result<&'a mut Table, Error> {
+    table
+        .entry(key)
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| invalid_name(name))
+}
+
+fn get_array_item<'a>(
+    table: &'a mut Table,
+    key: &str,
+    index: usize,
+    name: &str,
+) -> Result<&'a mut Table, Error> {
+    let array = table
+        .entry(key)
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| invalid_name(name))?;
+    let len = array.len();
+    if index == len {
+        array.push(Value::Table(Table::new()));
+    }
+    array
+        .get_mut(index)
+        .and_then(Value::as_table_mut)
+        .ok_or_else(|| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "Environment variable {name} refers to {key}[{index}], but there are only {len} items"
+                ),
+            )
+        })
+}
+
+/// Set value of `field` in `table`, nested tables are separated by double underscores.
+fn set_field(table: &mut Table, field: &str, value: Value, name: &str) -> Result<(), Error> {
+    let mut table = table;
+    let mut keys = field.split("__").peekable();
+    while let Some(key) = keys.next() {
+        if key.is_empty() {
+            return Err(invalid_name(name));
+        }
+        if keys.peek().is_none() {
+            table.insert(key.to_string(), value);
+            return Ok(());
+        }
+        table = get_table(table, key, name)?;
+    }
+    Err(invalid_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Table;
+
+    use super::apply_env;
+
+    fn apply(content: &str, vars: &[(&str, &str)]) -> Table {
+        let mut table: Table = content.parse().unwrap();
+        let vars = vars
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()));
+        apply_env(&mut table, vars).unwrap();
+        table
+    }
+
+    #[test]
+    fn test_apply_env() {
+        let table = apply(
+            r#"
+            [security]
+            allow_anonymous = false
+
+            [[listeners]]
+            address = "0.0.0.0:1883"
+            "#,
+            &[
+                ("HEBO_SECURITY_ALLOW_ANONYMOUS", "true"),
+                ("HEBO_DASHBOARD_ADDRESS", "127.0.0.1:18083"),
+                ("HEBO_GENERAL_USER", "\"1000\""),
+                ("HEBO_LISTENERS_1_ADDRESS", "0.0.0.0:1884"),
+                ("HEBO_LISTENERS_0_KEEP_ALIVE", "30"),
+                ("HEBO_LISTENERS_0_ADMISSION__BUSY_CONNECTIONS", "100"),
+                ("HEBO_VERSION", "1"),
+                ("PATH", "/usr/bin"),
+            ],
+        );
+        let expected: Table = r#"
+            [general]
+            user = "1000"
+
+            [security]
+            allow_anonymous = true
+
+            [dashboard]
+            address = "127.0.0.1:18083"
+
+            [[listeners]]
+            address = "0.0.0.0:1883"
+            keep_alive = 30
+            admission = { busy_connections = 100 }
+
+            [[listeners]]
+            address = "0.0.0.0:1884"
+            "#
+        .parse()
+        .unwrap();
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn test_array_index_order() {
+        let vars: Vec<(String, String)> = (0..12)
+            .rev()
+            .map(|index| {
+                (
+                    format!("HEBO_LISTENERS_{index}_ADDRESS"),
+                    format!("\"0.0.0.0:{}\"", 1883 + index),
+                )
+            })
+            .collect();
+        let vars: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let table = apply("", &vars);
+        let listeners = table["listeners"].as_array().unwrap();
+        assert_eq!(listeners.len(), 12);
+        assert_eq!(listeners[2]["address"].as_str(), Some("0.0.0.0:1885"));
+        assert_eq!(listeners[10]["address"].as_str(), Some("0.0.0.0:1893"));
+    }
+
+    #[test]
+    fn test_invalid_env() {
+        let mut table = Table::new();
+        for name in [
+            "HEBO_LISTENERS_1_ADDRESS",
+            "HEBO_LISTENERS_X_ADDRESS",
+            "HEBO_LISTENERS_0",
+            "HEBO_SECURITY_PGSQL_AUTH__",
+        ] {
+            let vars = [(name.to_string(), "value".to_string())];
+            assert!(apply_env(&mut table, vars).is_err(), "{name}");
+        }
+    }
+}
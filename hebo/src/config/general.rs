@@ -4,15 +4,33 @@
 
 #![allow(clippy::unsafe_derive_deserialize)]
 
+use codec::topic::validate_pub_topic;
 use codec::QoS;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 use sysinfo::{System, SystemExt, UserExt};
 
 use crate::error::{Error, ErrorKind};
 
+/// Placeholder in `presence_topic`, replaced with client id.
+pub const PRESENCE_CLIENT_ID: &str = "{client_id}";
+
+/// What to do with new messages when queue of a client is full.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum QueueFullPolicy {
+    /// Drop the oldest queued message to make room for the new one.
+    #[default]
+    #[serde(alias = "drop_oldest")]
+    DropOldest,
+
+    /// Drop new messages until the client reconnects.
+    #[serde(alias = "reject")]
+    Reject,
+}
+
 /// General section in config.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct General {
     /// Time interval to send $SYS messages in seconds.
@@ -72,13 +90,51 @@ pub struct General {
 
     /// Set the maximum `QoS` supported.
     ///
-    /// Clients publishing at a `QoS` higher than specified here will be disconnected.
+    /// Clients publishing at a `QoS` higher than specified here will be disconnected,
+    /// and subscriptions are granted at most this `QoS`. It is sent to v5 clients
+    /// in `ConnectAck` packet if lower than 2.
+    ///
     /// Available values are 0, 1 and 2.
     ///
     /// Default is 2.
-    #[serde(default = "General::default_maximum_qos")]
+    #[serde(
+        default = "General::default_maximum_qos",
+        deserialize_with = "deserialize_qos",
+        serialize_with = "serialize_qos"
+    )]
     maximum_qos: QoS,
 
+    /// Accept subscriptions with wildcard characters `+` and `#` in topic filter.
+    ///
+    /// If disabled, it is sent to v5 clients in `ConnectAck` packet, and wildcard
+    /// topic filters are rejected with `WildcardSubscriptionsNotSupported` reason code
+    /// for v5 clients, and failure return code for v3 clients.
+    ///
+    /// Default is true.
+    #[serde(default = "General::default_wildcard_subscription_available")]
+    wildcard_subscription_available: bool,
+
+    /// Check payload of v5 publish packets with Payload Format Indicator property
+    /// set to 1 is valid UTF-8 string.
+    ///
+    /// Invalid packets are rejected with `PayloadFormatInvalid` reason code,
+    /// in `PublishAck` or `PublishReceived` packet depending on their `QoS`,
+    /// or in `Disconnect` packet if `QoS` is 0.
+    ///
+    /// Default is false.
+    #[serde(default = "General::default_validate_payload_format")]
+    validate_payload_format: bool,
+
+    /// Response Information sent to v5 clients in `ConnectAck` packet, if they
+    /// request it with Request Response Information property.
+    ///
+    /// It is used by clients as base of response topics, `%c` is replaced with client id.
+    /// For example, `responses/%c/`.
+    ///
+    /// Default is empty, which means response information is not sent.
+    #[serde(default = "General::default_response_information")]
+    response_information: String,
+
     /// For MQTT v5 clients, it is possible to have the server send a "maximum packet size" value
     /// that will instruct the client it will not accept MQTT packets with size
     /// greater than `max_packet_size` bytes.
@@ -94,8 +150,70 @@ pub struct General {
     /// Defaults is 0, which means no limit.
     #[serde(default = "General::default_maximum_packet_size")]
     maximum_packet_size: u32,
-    //pub max_queued_messages: usize,
+
+    /// Set maximum size of will message payload in connect packet.
+    ///
+    /// Connect packets with a larger will payload are rejected, so that clients cannot
+    /// make broker hold large will messages while sessions persist.
+    ///
+    /// Default is 0, which means no limit.
+    #[serde(default = "General::default_max_will_payload_size")]
+    max_will_payload_size: u32,
+
+    /// Publish retained presence message of each client to this topic.
+    ///
+    /// `{client_id}` in topic is replaced with client id, for example
+    /// `$SYS/broker/clients/{client_id}/connected`. Payload is `1` when client connects,
+    /// and the retained message is cleared when it disconnects.
+    ///
+    /// Default is None, which disables presence messages.
+    #[serde(default = "General::default_presence_topic")]
+    presence_topic: Option<String>,
+
+    /// Maximum number of `QoS` 1 and `QoS` 2 messages queued for each disconnected client
+    /// with clean session flag off, and for each connected client whose inflight window is full.
+    ///
+    /// `QoS` 0 messages to disconnected clients are always dropped.
+    /// Set to 0 to disable queueing.
+    ///
+    /// Default is 1000.
+    #[serde(default = "General::default_max_queued_messages")]
+    max_queued_messages: usize,
+
+    /// What to do with new messages when queue of a client is full.
+    ///
+    /// Default is `drop_oldest`.
+    #[serde(default = "General::default_queue_full_policy")]
+    queue_full_policy: QueueFullPolicy,
     //pub max_queued_bytes: usize,
+    /// Maximum number of sessions subscribed to a single topic filter.
+    ///
+    /// Subscriptions beyond this limit are rejected, with reason code `QuotaExceeded`
+    /// for v5 clients. Set to 0 to disable this limitation.
+    ///
+    /// Default is 0.
+    #[serde(default = "General::default_max_topic_subscribers")]
+    max_topic_subscribers: usize,
+
+    /// Hold messages published to `$delayed/{seconds}/{topic}` for `seconds`,
+    /// then publish them to `topic`.
+    ///
+    /// Delay interval is at most 4294967295 seconds. ACL rules are checked against
+    /// both the `$delayed/` topic and `topic`, and messages to `topic` starting with `$`
+    /// are dropped. Pending delayed messages are dropped when server stops.
+    ///
+    /// Default is false, `$delayed/` topics are handled as normal topics.
+    #[serde(default = "General::default_delayed_publish")]
+    delayed_publish: bool,
+
+    /// Maximum number of pending delayed messages.
+    ///
+    /// New delayed messages are dropped when this limit is reached.
+    /// Set to 0 to disable this limitation.
+    ///
+    /// Default is 10000.
+    #[serde(default = "General::default_max_delayed_messages")]
+    max_delayed_messages: usize,
 }
 
 impl General {
@@ -141,6 +259,21 @@ impl General {
         QoS::ExactOnce
     }
 
+    #[must_use]
+    pub const fn default_wildcard_subscription_available() -> bool {
+        true
+    }
+
+    #[must_use]
+    pub const fn default_validate_payload_format() -> bool {
+        false
+    }
+
+    #[must_use]
+    pub const fn default_response_information() -> String {
+        String::new()
+    }
+
     #[must_use]
     pub const fn default_maximum_keep_alive() -> u32 {
         65535
@@ -151,6 +284,41 @@ impl General {
         0
     }
 
+    #[must_use]
+    pub const fn default_max_will_payload_size() -> u32 {
+        0
+    }
+
+    #[must_use]
+    pub const fn default_presence_topic() -> Option<String> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_max_queued_messages() -> usize {
+        1000
+    }
+
+    #[must_use]
+    pub const fn default_queue_full_policy() -> QueueFullPolicy {
+        QueueFullPolicy::DropOldest
+    }
+
+    #[must_use]
+    pub const fn default_max_topic_subscribers() -> usize {
+        0
+    }
+
+    #[must_use]
+    pub const fn default_delayed_publish() -> bool {
+        false
+    }
+
+    #[must_use]
+    pub const fn default_max_delayed_messages() -> usize {
+        10000
+    }
+
     #[must_use]
     pub const fn sys_interval(&self) -> Duration {
         Duration::from_secs(self.sys_interval as u64)
@@ -186,25 +354,114 @@ impl General {
         self.maximum_qos
     }
 
+    #[must_use]
+    pub const fn wildcard_subscription_available(&self) -> bool {
+        self.wildcard_subscription_available
+    }
+
+    #[must_use]
+    pub const fn validate_payload_format(&self) -> bool {
+        self.validate_payload_format
+    }
+
+    #[must_use]
+    pub fn response_information(&self) -> &str {
+        &self.response_information
+    }
+
     #[must_use]
     pub const fn maximum_packet_size(&self) -> u32 {
         self.maximum_packet_size
     }
 
+    #[must_use]
+    pub const fn max_will_payload_size(&self) -> u32 {
+        self.max_will_payload_size
+    }
+
+    #[must_use]
+    pub fn presence_topic(&self) -> Option<&str> {
+        self.presence_topic.as_deref()
+    }
+
+    #[must_use]
+    pub const fn max_queued_messages(&self) -> usize {
+        self.max_queued_messages
+    }
+
+    #[must_use]
+    pub const fn queue_full_policy(&self) -> QueueFullPolicy {
+        self.queue_full_policy
+    }
+
+    #[must_use]
+    pub const fn max_topic_subscribers(&self) -> usize {
+        self.max_topic_subscribers
+    }
+
+    #[must_use]
+    pub const fn delayed_publish(&self) -> bool {
+        self.delayed_publish
+    }
+
+    #[must_use]
+    pub const fn max_delayed_messages(&self) -> usize {
+        self.max_delayed_messages
+    }
+
+    /// Set interval to send $SYS messages in seconds, 0 to disable them.
+    pub fn set_sys_interval(&mut self, sys_interval: u32) -> &mut Self {
+        self.sys_interval = sys_interval;
+        self
+    }
+
+    /// Set user to drop privileges to when run as root.
+    pub fn set_user<S: Into<String>>(&mut self, user: S) -> &mut Self {
+        self.user = user.into();
+        self
+    }
+
+    /// Set path to pid file, an empty path means pid file is not written.
+    pub fn set_pid_file<P: Into<PathBuf>>(&mut self, pid_file: P) -> &mut Self {
+        self.pid_file = pid_file.into();
+        self
+    }
+
+    fn validate_presence_topic(&self) -> Result<(), Error> {
+        let Some(presence_topic) = &self.presence_topic else {
+            return Ok(());
+        };
+        if !presence_topic.contains(PRESENCE_CLIENT_ID) {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("presence_topic must contain {PRESENCE_CLIENT_ID}: {presence_topic}"),
+            ));
+        }
+        if let Err(err) = validate_pub_topic(&presence_topic.replace(PRESENCE_CLIENT_ID, "client"))
+        {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid presence_topic: {presence_topic}, err: {err:?}"),
+            ));
+        }
+        Ok(())
+    }
+
     /// Validate config.
     ///
     /// # Errors
     ///
-    /// Returns error if username not found.
+    /// Returns error if `presence_topic` is invalid.
     #[cfg(not(unix))]
     pub fn validate(&self) -> Result<(), Error> {
-        Ok(())
+        self.validate_presence_topic()
     }
 
     #[cfg(unix)]
     /// # Errors
-    /// Returns error if specific user id does not exist.
+    /// Returns error if specific user id does not exist or `presence_topic` is invalid.
     pub fn validate(&self) -> Result<(), Error> {
+        self.validate_presence_topic()?;
         let euid = unsafe { nc::geteuid() };
         if euid == 0 {
             // For root only.
@@ -229,8 +486,30 @@ impl Default for General {
             no_delay: Self::default_no_delay(),
             message_size_limit: Self::default_message_size_limit(),
             maximum_qos: Self::default_maximum_qos(),
+            wildcard_subscription_available: Self::default_wildcard_subscription_available(),
+            validate_payload_format: Self::default_validate_payload_format(),
+            response_information: Self::default_response_information(),
             maximum_keep_alive: Self::default_maximum_keep_alive(),
             maximum_packet_size: Self::default_maximum_packet_size(),
+            max_will_payload_size: Self::default_max_will_payload_size(),
+            presence_topic: Self::default_presence_topic(),
+            max_queued_messages: Self::default_max_queued_messages(),
+            queue_full_policy: Self::default_queue_full_policy(),
+            max_topic_subscribers: Self::default_max_topic_subscribers(),
+            delayed_publish: Self::default_delayed_publish(),
+            max_delayed_messages: Self::default_max_delayed_messages(),
         }
     }
 }
+
+/// `QoS` is written as 0, 1 or 2 in config file.
+fn deserialize_qos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<QoS, D::Error> {
+    let value = u8::deserialize(deserializer)?;
+    QoS::try_from(value)
+        .map_err(|_err| de::Error::custom(format!("invalid QoS {value}, expected 0, 1 or 2")))
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_qos<S: Serializer>(qos: &QoS, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8(*qos as u8)
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Merge config fragments listed in `include` key of main config file.
+//!
+//! ```toml
+//! include = ["conf.d/*.toml"]
+//! ```
+//!
+//! Patterns are relative to directory of main config file. Files matched by each
+//! pattern are sorted by path, and merged in order:
+//! - arrays of tables, like `[[listeners]]`, are appended
+//! - other values override those in main file and previous fragments
+//!
+//! Fragments shall not include other files.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+use crate::error::{Error, ErrorKind};
+
+pub const INCLUDE_KEY: &str = "include";
+
+/// Read config file as toml table.
+pub fn read_table(config_file: &Path) -> Result<Table, Error> {
+    let content = std::fs::read_to_string(config_file).map_err(|err| {
+        Error::from_string(
+            ErrorKind::ConfigError,
+            format!(
+                "Failed to read config file {}, err: {err:?}",
+                config_file.display()
+            ),
+        )
+    })?;
+    content.parse().map_err(|err| {
+        Error::from_string(
+            ErrorKind::ConfigError,
+            format!(
+                "Invalid toml config file {}, err: {err:?}",
+                config_file.display()
+            ),
+        )
+    })
+}
+
+/// Merge files listed in `include` key into `table`, which is read from `config_file`.
+///
+/// Returns fragment files where sections are defined, like `listeners[2]` or `security`.
+pub fn merge_includes(
+    table: &mut Table,
+    config_file: &Path,
+) -> Result<HashMap<String, PathBuf>, Error> {
+    let mut origins = HashMap::new();
+    let Some(patterns) = table.remove(INCLUDE_KEY) else {
+        return Ok(origins);
+    };
+    let invalid_include = || {
+        Error::from_string(
+            ErrorKind::ConfigError,
+            format!(
+                "`{INCLUDE_KEY}` in {} shall be an array of file patterns",
+                config_file.display()
+            ),
+        )
+    };
+    let patterns = patterns.as_array().ok_or_else(invalid_include)?;
+    let base_dir = config_file.parent().unwrap_or_else(|| Path::new(""));
+
+    for pattern in patterns {
+        let pattern = pattern.as_str().ok_or_else(invalid_include)?;
+        for file in expand_pattern(&base_dir.join(pattern))? {
+            let fragment = read_table(&file)?;
+            if fragment.contains_key(INCLUDE_KEY) {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!(
+                        "Nested `{INCLUDE_KEY}` in {} is not supported",
+                        file.display()
+                    ),
+                ));
+            }
+            merge_fragment(table, fragment, &file, &mut origins);
+        }
+    }
+    Ok(origins)
+}
+
+/// Returns files matching `pattern`, sorted by path.
+fn expand_pattern(pattern: &Path) -> Result<Vec<PathBuf>, Error> {
+    let pattern = pattern.to_string_lossy();
+    let paths = glob::glob(&pattern).map_err(|err| {
+        Error::from_string(
+            ErrorKind::ConfigError,
+            format!("Invalid include pattern {pattern}, err: {err}"),
+        )
+    })?;
+    let mut files = paths.collect::<Result<Vec<PathBuf>, _>>().map_err(|err| {
+        Error::from_string(
+            ErrorKind::ConfigError,
+            format!(
+                "Failed to read included file {}, err: {err}",
+                err.path().display()
+            ),
+        )
+    })?;
+    files.sort();
+    Ok(files)
+}
+
+fn merge_fragment(
+    table: &mut Table,
+    fragment: Table,
+    file: &Path,
+    origins: &mut HashMap<String, PathBuf>,
+) {
+    for (key, value) in fragment {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Array(items)), Value::Array(new_items))
+                if is_array_of_tables(&new_items) =>
+            {
+                for index in items.len()..items.len() + new_items.len() {
+                    origins.insert(format!("{key}[{index}]"), file.to_path_buf());
+                }
+                items.extend(new_items);
+            }
+            (_, Value::Array(new_items)) if is_array_of_tables(&new_items) => {
+                for index in 0..new_items.len() {
+                    origins.insert(format!("{key}[{index}]"), file.to_path_buf());
+                }
+                table.insert(key, Value::Array(new_items));
+            }
+            (Some(Value::Table(section)), Value::Table(new_section)) => {
+                merge_table(section, new_section);
+                origins.insert(key, file.to_path_buf());
+            }
+            (_, value) => {
+                origins.insert(key.clone(), file.to_path_buf());
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Merge nested tables recursively, values in `other` take precedence.
+fn merge_table(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(section)), Value::Table(new_section)) => {
+                merge_table(section, new_section);
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+fn is_array_of_tables(items: &[Value]) -> bool {
+    !items.is_empty() && items.iter().all(Value::is_table)
+}
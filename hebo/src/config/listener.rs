@@ -7,9 +7,71 @@ use std::net::{TcpListener, ToSocketAddrs};
 #[cfg(unix)]
 use std::os::unix::net::UnixListener;
 use std::path::{Path, PathBuf};
+use tokio_rustls::rustls::{self, SupportedCipherSuite, SupportedProtocolVersion};
 
+use super::{validate_file, Admission, Capture};
 use crate::error::{Error, ErrorKind};
 
+/// Access of topics not matched by any rule in acl file.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AclPolicy {
+    #[serde(alias = "allow")]
+    Allow,
+
+    #[default]
+    #[serde(alias = "deny")]
+    Deny,
+}
+
+/// What to do with clients publishing messages faster than rate limits of listener.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Stop reading packets from client until its rate drops below the limits.
+    #[default]
+    #[serde(alias = "throttle")]
+    Throttle,
+
+    /// Disconnect client, with `QuotaExceeded` reason code in v5 protocol.
+    #[serde(alias = "disconnect")]
+    Disconnect,
+}
+
+/// Where the identity of clients comes from, which is used to check ACL.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum IdentitySource {
+    /// Username in connect packet.
+    #[default]
+    #[serde(alias = "mqtt-username")]
+    MqttUsername,
+
+    /// Common name of TLS client certificate.
+    #[serde(alias = "tls-cn")]
+    TlsCn,
+
+    /// First subject alternative name of TLS client certificate.
+    #[serde(alias = "tls-san")]
+    TlsSan,
+
+    /// Http header injected by reverse proxy in websocket handshake request.
+    #[serde(alias = "proxy-header")]
+    ProxyHeader,
+
+    /// Uid of peer process of unix domain socket, read with `SO_PEERCRED`.
+    #[serde(alias = "peer-cred")]
+    PeerCred,
+}
+
+/// Minimum TLS protocol version accepted by TLS listeners.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    #[default]
+    #[serde(alias = "1.2")]
+    Tls12,
+
+    #[serde(alias = "1.3")]
+    Tls13,
+}
+
 /// Binding protocol types.
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum Protocol {
@@ -40,7 +102,8 @@ pub enum Protocol {
 }
 
 /// Listener represent an unique ip/port combination and mqtt connection protocol.
-#[derive(Debug, Deserialize, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 pub struct Listener {
     /// Bind the listener to a specific device interface.
     ///
@@ -55,6 +118,9 @@ pub struct Listener {
 
     /// The maximum number of client connections to this listener allowed.
     ///
+    /// Connect packets beyond this limit are rejected, with `QuotaExceeded` reason code
+    /// in v5 protocol, and `ServerUnavailable` return code in v3 protocols.
+    ///
     /// Note that other process limits mean that unlimited connections
     /// are not really possible. Typically the default maximum number of
     /// connections possible is around 1024.
@@ -101,6 +167,39 @@ pub struct Listener {
     #[serde(default = "Listener::default_key_file")]
     key_file: Option<PathBuf>,
 
+    /// Require clients to present a certificate signed by CA in `ca_file`.
+    ///
+    /// Only used by mqtts and wss protocols. Common name or subject alternative
+    /// name of client certificate can be used as identity, see `identity_source`.
+    ///
+    /// Default is false.
+    #[serde(default = "Listener::default_require_certificate")]
+    require_certificate: bool,
+
+    /// Path to CA certificates in PEM format, used to verify client certificates.
+    ///
+    /// Required if `require_certificate` is true.
+    ///
+    /// Default is None.
+    #[serde(default = "Listener::default_ca_file")]
+    ca_file: Option<PathBuf>,
+
+    /// Minimum TLS version accepted by mqtts and wss listeners, "1.2" or "1.3".
+    ///
+    /// Default is "1.2".
+    #[serde(default = "Listener::default_min_tls_version")]
+    min_tls_version: TlsVersion,
+
+    /// Names of cipher suites allowed by mqtts and wss listeners, like
+    /// `TLS13_AES_256_GCM_SHA384` or `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256`.
+    ///
+    /// At least one of them shall support `min_tls_version`.
+    /// Empty list means all cipher suites supported by rustls.
+    ///
+    /// Default is empty.
+    #[serde(default = "Listener::default_cipher_suites")]
+    cipher_suites: Vec<String>,
+
     /// Set `username_as_client_id` to true to replace the client id that a client
     /// connected with with its username.
     ///
@@ -128,7 +227,9 @@ pub struct Listener {
 
     /// Timeout value in seconds before receiving Connect Packet from client.
     ///
-    /// The timer is triggered when client stream is connected.
+    /// The timer is triggered when client stream is connected, and the stream is closed
+    /// if no Connect Packet is received, or enhanced authentication is not completed,
+    /// before timeout. Set to 0 to disable it.
     ///
     /// Default is 60s.
     #[serde(default = "Listener::default_connect_timeout")]
@@ -151,9 +252,133 @@ pub struct Listener {
     /// This includes messages that are partway through handshakes and
     /// those that are being retried.
     ///
+    /// It is also sent to v5 clients as Receive Maximum, clients which send more
+    /// unacknowledged messages than that are disconnected.
+    ///
     /// Defaults to 20.
     #[serde(default = "Listener::default_maximum_inflight_messages")]
     maximum_inflight_messages: u16,
+
+    /// Delay in milliseconds before re-sending unacknowledged messages to a client
+    /// which resumes its session.
+    ///
+    /// Messages are re-sent no faster than `maximum_inflight_messages` and
+    /// Receive Maximum of v5 clients allow, so that a client still initializing
+    /// is not flooded.
+    ///
+    /// Default is 0, which re-sends them right after `ConnectAck` packet.
+    #[serde(default = "Listener::default_retransmit_delay")]
+    retransmit_delay: u32,
+
+    /// The highest value of Topic Alias accepted from v5 clients.
+    ///
+    /// This value is sent to clients in `ConnectAck` packet. Set to 0 to disable topic alias.
+    ///
+    /// Default is 10.
+    #[serde(default = "Listener::default_topic_alias_maximum")]
+    topic_alias_maximum: u16,
+
+    /// Maximum packet size accepted from clients connected to this listener.
+    ///
+    /// Overrides `general.maximum_packet_size` if set. Set to 0 to disable limit
+    /// on this listener.
+    ///
+    /// Default is None, which inherits value in `general` section.
+    #[serde(default = "Listener::default_maximum_packet_size")]
+    maximum_packet_size: Option<u32>,
+
+    /// Maximum number of publish packets per second received from each client.
+    ///
+    /// Bursts up to this number of packets are allowed. Set to 0 to disable limit.
+    ///
+    /// Default is 0.
+    #[serde(default = "Listener::default_max_message_rate")]
+    max_message_rate: u32,
+
+    /// Maximum bytes of publish packets per second received from each client.
+    ///
+    /// It shall be larger than size of messages, or else every message exceeds the limit.
+    /// Set to 0 to disable limit.
+    ///
+    /// Default is 0.
+    #[serde(default = "Listener::default_max_byte_rate")]
+    max_byte_rate: u32,
+
+    /// What to do with clients exceeding `max_message_rate` or `max_byte_rate`.
+    ///
+    /// - `throttle`, stop reading from client for a while
+    /// - `disconnect`, disconnect client
+    ///
+    /// Default is `throttle`.
+    #[serde(default = "RateLimitPolicy::default")]
+    rate_limit_policy: RateLimitPolicy,
+
+    /// Publish and subscribe to topics not matched by any rule in `security.acl_file`
+    /// are allowed or denied by this policy.
+    ///
+    /// Default is deny.
+    #[serde(default = "AclPolicy::default")]
+    acl_policy: AclPolicy,
+
+    /// Source of client identity passed to ACL and authenticators.
+    ///
+    /// - `mqtt-username`, username in connect packet
+    /// - `tls-cn`, common name of client certificate, for mqtts and wss listeners
+    ///   with `require_certificate`
+    /// - `tls-san`, subject alternative name of client certificate, for mqtts and wss listeners
+    ///   with `require_certificate`
+    /// - `proxy-header`, value of `identity_header`, for ws and wss listeners
+    /// - `peer-cred`, uid of peer process, for uds listeners on Linux
+    ///
+    /// Clients without such identity are treated as anonymous.
+    ///
+    /// Default is `mqtt-username`.
+    #[serde(default = "IdentitySource::default")]
+    identity_source: IdentitySource,
+
+    /// Http header in websocket handshake request, used if `identity_source` is `proxy-header`.
+    ///
+    /// Default is `X-Forwarded-User`.
+    #[serde(default = "Listener::default_identity_header")]
+    identity_header: String,
+
+    /// Read PROXY protocol v1 or v2 header sent by load balancer like `HAProxy`,
+    /// so that real address of client is used in auth, ACL and access log.
+    ///
+    /// Connections without a valid header are dropped. Only supported by
+    /// mqtt, mqtts, ws and wss protocols.
+    ///
+    /// Default is false.
+    #[serde(default = "Listener::default_proxy_protocol")]
+    proxy_protocol: bool,
+
+    /// Uids of processes allowed to connect to uds listener.
+    ///
+    /// Credentials of peer process are read with `SO_PEERCRED` when connection
+    /// is accepted, it is allowed if its uid is in `allowed_uids`, or its gid
+    /// is in `allowed_gids`. Only supported by uds protocol on Linux.
+    ///
+    /// Default is empty, processes of all users are allowed.
+    #[serde(default = "Listener::default_allowed_uids")]
+    allowed_uids: Vec<u32>,
+
+    /// Gids of processes allowed to connect to uds listener, see `allowed_uids`.
+    ///
+    /// Default is empty.
+    #[serde(default = "Listener::default_allowed_gids")]
+    allowed_gids: Vec<u32>,
+
+    /// Reject or redirect new connections when server is overloaded.
+    ///
+    /// Default is disabled.
+    #[serde(default = "Admission::default")]
+    admission: Admission,
+
+    /// Capture raw packets of specific clients.
+    ///
+    /// Default is disabled.
+    #[serde(default = "Capture::default")]
+    capture: Capture,
 }
 
 impl Listener {
@@ -206,6 +431,28 @@ impl Listener {
     }
 
     #[inline]
+    #[must_use]
+    pub const fn default_require_certificate() -> bool {
+        false
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_ca_file() -> Option<PathBuf> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_min_tls_version() -> TlsVersion {
+        TlsVersion::Tls12
+    }
+
+    #[must_use]
+    pub const fn default_cipher_suites() -> Vec<String> {
+        Vec::new()
+    }
+
     #[must_use]
     pub const fn default_username_as_client_id() -> bool {
         false
@@ -235,6 +482,58 @@ impl Listener {
         20
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn default_retransmit_delay() -> u32 {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_topic_alias_maximum() -> u16 {
+        10
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn default_identity_header() -> String {
+        "X-Forwarded-User".to_owned()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_proxy_protocol() -> bool {
+        false
+    }
+
+    #[must_use]
+    pub const fn default_allowed_uids() -> Vec<u32> {
+        Vec::new()
+    }
+
+    #[must_use]
+    pub const fn default_allowed_gids() -> Vec<u32> {
+        Vec::new()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_maximum_packet_size() -> Option<u32> {
+        None
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_max_message_rate() -> u32 {
+        0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn default_max_byte_rate() -> u32 {
+        0
+    }
+
     #[inline]
     #[must_use]
     pub fn bind_device(&self) -> &str {
@@ -273,6 +572,84 @@ impl Listener {
         self.key_file.as_deref()
     }
 
+    #[must_use]
+    pub const fn require_certificate(&self) -> bool {
+        self.require_certificate
+    }
+
+    #[must_use]
+    pub fn ca_file(&self) -> Option<&Path> {
+        self.ca_file.as_deref()
+    }
+
+    #[must_use]
+    pub const fn min_tls_version(&self) -> TlsVersion {
+        self.min_tls_version
+    }
+
+    #[must_use]
+    pub fn cipher_suites(&self) -> &[String] {
+        &self.cipher_suites
+    }
+
+    /// Get TLS protocol versions enabled by `min_tls_version`.
+    #[must_use]
+    pub fn tls_versions(&self) -> &'static [&'static SupportedProtocolVersion] {
+        static TLS13_ONLY: &[&SupportedProtocolVersion] = &[&rustls::version::TLS13];
+        match self.min_tls_version {
+            TlsVersion::Tls12 => rustls::ALL_VERSIONS,
+            TlsVersion::Tls13 => TLS13_ONLY,
+        }
+    }
+
+    /// Get cipher suites enabled by `cipher_suites` and `min_tls_version`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a cipher suite name is unknown, or none of them supports
+    /// `min_tls_version`.
+    pub fn tls_cipher_suites(&self) -> Result<Vec<SupportedCipherSuite>, Error> {
+        let suites = if self.cipher_suites.is_empty() {
+            rustls::DEFAULT_CIPHER_SUITES.to_vec()
+        } else {
+            let mut suites = Vec::with_capacity(self.cipher_suites.len());
+            for name in &self.cipher_suites {
+                let suite = rustls::ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|suite| {
+                        suite
+                            .suite()
+                            .as_str()
+                            .map_or(false, |s| s.eq_ignore_ascii_case(name))
+                    })
+                    .ok_or_else(|| {
+                        Error::from_string(
+                            ErrorKind::ConfigError,
+                            format!("Unknown cipher suite {name:?} in `cipher_suites`"),
+                        )
+                    })?;
+                suites.push(*suite);
+            }
+            suites
+        };
+
+        let versions = self.tls_versions();
+        if suites
+            .iter()
+            .any(|suite| versions.contains(&suite.version()))
+        {
+            Ok(suites)
+        } else {
+            Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "None of `cipher_suites` supports `min_tls_version` {:?}",
+                    self.min_tls_version
+                ),
+            ))
+        }
+    }
+
     #[inline]
     #[must_use]
     pub const fn username_as_client_id(&self) -> bool {
@@ -303,13 +680,313 @@ impl Listener {
         self.maximum_inflight_messages
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn retransmit_delay(&self) -> u32 {
+        self.retransmit_delay
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn topic_alias_maximum(&self) -> u16 {
+        self.topic_alias_maximum
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn identity_source(&self) -> IdentitySource {
+        self.identity_source
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn identity_header(&self) -> &str {
+        &self.identity_header
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn proxy_protocol(&self) -> bool {
+        self.proxy_protocol
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn allowed_uids(&self) -> &[u32] {
+        &self.allowed_uids
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn allowed_gids(&self) -> &[u32] {
+        &self.allowed_gids
+    }
+
+    /// Check uid and gid of peer process of uds connection.
+    ///
+    /// Returns true if no allowlist is set. Peers with unknown credentials are
+    /// rejected if either allowlist is set.
+    #[must_use]
+    pub fn is_peer_allowed(&self, cred: Option<(u32, u32)>) -> bool {
+        if self.allowed_uids.is_empty() && self.allowed_gids.is_empty() {
+            return true;
+        }
+        cred.map_or(false, |(uid, gid)| {
+            self.allowed_uids.contains(&uid) || self.allowed_gids.contains(&gid)
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn acl_policy(&self) -> AclPolicy {
+        self.acl_policy
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maximum_packet_size(&self) -> Option<u32> {
+        self.maximum_packet_size
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_message_rate(&self) -> u32 {
+        self.max_message_rate
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_byte_rate(&self) -> u32 {
+        self.max_byte_rate
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn rate_limit_policy(&self) -> RateLimitPolicy {
+        self.rate_limit_policy
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn admission(&self) -> &Admission {
+        &self.admission
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Create a listener with default options.
+    #[must_use]
+    pub fn new(protocol: Protocol, address: &str) -> Self {
+        Self {
+            protocol,
+            address: address.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Create a raw mqtt listener.
+    #[must_use]
+    pub fn tcp(address: &str) -> Self {
+        Self::new(Protocol::Mqtt, address)
+    }
+
+    /// Set url path of websocket listener.
+    pub fn set_path(&mut self, path: Option<String>) -> &mut Self {
+        self.path = path;
+        self
+    }
+
+    /// Set TLS cert file and private key file.
+    pub fn set_cert_file(&mut self, cert_file: PathBuf, key_file: PathBuf) -> &mut Self {
+        self.cert_file = Some(cert_file);
+        self.key_file = Some(key_file);
+        self
+    }
+
+    /// Require client certificates signed by CA in `ca_file`.
+    pub fn set_require_certificate(&mut self, ca_file: Option<PathBuf>) -> &mut Self {
+        self.require_certificate = ca_file.is_some();
+        self.ca_file = ca_file;
+        self
+    }
+
+    /// Set minimum TLS version and allowed cipher suites, empty for all.
+    pub fn set_tls_options(
+        &mut self,
+        min_tls_version: TlsVersion,
+        cipher_suites: Vec<String>,
+    ) -> &mut Self {
+        self.min_tls_version = min_tls_version;
+        self.cipher_suites = cipher_suites;
+        self
+    }
+
+    /// Set timeout in seconds before receiving Connect Packet, 0 to disable it.
+    pub fn set_connect_timeout(&mut self, connect_timeout: u16) -> &mut Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Read PROXY protocol header before mqtt packets.
+    pub fn set_proxy_protocol(&mut self, proxy_protocol: bool) -> &mut Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// Only allow processes with these uids or gids to connect to uds listener.
+    pub fn set_allowed_peers(&mut self, uids: Vec<u32>, gids: Vec<u32>) -> &mut Self {
+        self.allowed_uids = uids;
+        self.allowed_gids = gids;
+        self
+    }
+
+    pub fn set_allow_empty_client_id(&mut self, allow_empty_client_id: bool) -> &mut Self {
+        self.allow_empty_client_id = allow_empty_client_id;
+        self
+    }
+
+    pub fn set_maximum_connections(&mut self, maximum_connections: usize) -> &mut Self {
+        self.maximum_connections = maximum_connections;
+        self
+    }
+
+    /// Check that identity source is available on this protocol.
+    fn validate_identity_source(&self) -> Result<(), Error> {
+        let supported = match self.identity_source {
+            IdentitySource::MqttUsername => true,
+            IdentitySource::TlsCn | IdentitySource::TlsSan => {
+                matches!(self.protocol, Protocol::Mqtts | Protocol::Wss)
+            }
+            IdentitySource::ProxyHeader => matches!(self.protocol, Protocol::Ws | Protocol::Wss),
+            IdentitySource::PeerCred => self.protocol == Protocol::Uds && cfg!(target_os = "linux"),
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`identity_source` {:?} is not supported by {:?} protocol",
+                    self.identity_source, self.protocol
+                ),
+            ))
+        }
+    }
+
+    /// Check options which depend on protocol.
+    fn validate_protocol_options(&self) -> Result<(), Error> {
+        self.validate_identity_source()?;
+
+        let requires_cert = matches!(
+            self.protocol,
+            Protocol::Mqtts | Protocol::Wss | Protocol::Quic
+        );
+        if requires_cert {
+            for (field, file) in [("cert_file", &self.cert_file), ("key_file", &self.key_file)] {
+                let Some(file) = file else {
+                    return Err(Error::from_string(
+                        ErrorKind::ConfigError,
+                        format!("`{field}` is required by {:?} protocol", self.protocol),
+                    ));
+                };
+                validate_file(field, file)?;
+            }
+        } else if self.cert_file.is_some() || self.key_file.is_some() {
+            log::warn!(
+                "`cert_file` and `key_file` are ignored by {:?} protocol",
+                self.protocol
+            );
+        }
+
+        if self.require_certificate {
+            if !matches!(self.protocol, Protocol::Mqtts | Protocol::Wss) {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!(
+                        "`require_certificate` is only supported by Mqtts and Wss protocols, got {:?}",
+                        self.protocol
+                    ),
+                ));
+            }
+            let Some(ca_file) = &self.ca_file else {
+                return Err(Error::new(
+                    ErrorKind::ConfigError,
+                    "`ca_file` is required by `require_certificate`",
+                ));
+            };
+            validate_file("ca_file", ca_file)?;
+        } else if self.ca_file.is_some() {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "`ca_file` requires `require_certificate` to be true",
+            ));
+        }
+
+        if matches!(self.protocol, Protocol::Mqtts | Protocol::Wss) {
+            self.tls_cipher_suites()?;
+        } else if self.min_tls_version != Self::default_min_tls_version()
+            || !self.cipher_suites.is_empty()
+        {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`min_tls_version` and `cipher_suites` are only supported by Mqtts and Wss protocols, got {:?}",
+                    self.protocol
+                ),
+            ));
+        }
+
+        if self.path.is_some() && !matches!(self.protocol, Protocol::Ws | Protocol::Wss) {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`path` is only supported by Ws and Wss protocols, got {:?}",
+                    self.protocol
+                ),
+            ));
+        }
+
+        if self.proxy_protocol
+            && !matches!(
+                self.protocol,
+                Protocol::Mqtt | Protocol::Mqtts | Protocol::Ws | Protocol::Wss
+            )
+        {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`proxy_protocol` is only supported by tcp based protocols, got {:?}",
+                    self.protocol
+                ),
+            ));
+        }
+
+        let has_allowed_peers = !self.allowed_uids.is_empty() || !self.allowed_gids.is_empty();
+        if has_allowed_peers && (self.protocol != Protocol::Uds || !cfg!(target_os = "linux")) {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`allowed_uids` and `allowed_gids` are only supported by Uds protocol on Linux, got {:?}",
+                    self.protocol
+                ),
+            ));
+        }
+        Ok(())
+    }
+
     #[cfg(not(unix))]
     /// Validate config.
     ///
     /// # Errors
     ///
-    /// Returns error if socket address is invalid or already in use.
+    /// Returns error if socket address is invalid or already in use,
+    /// or options are not consistent with protocol.
     pub fn validate(&self, bind_address: bool) -> Result<(), Error> {
+        self.validate_protocol_options()?;
         if bind_address {
             let _socket = TcpListener::bind(&self.address).map_err(|err| {
                 Error::from_string(
@@ -335,9 +1012,11 @@ impl Listener {
     ///
     /// # Errors
     ///
-    /// Returns error if socket address is invalid or already in use.
+    /// Returns error if socket address is invalid or already in use,
+    /// or options are not consistent with protocol.
     #[cfg(unix)]
     pub fn validate(&self, bind_address: bool) -> Result<(), Error> {
+        self.validate_protocol_options()?;
         if bind_address {
             if self.protocol() == Protocol::Uds {
                 let listener = UnixListener::bind(&self.address).map_err(|err| {
@@ -372,7 +1051,6 @@ impl Listener {
             })?;
         }
 
-        // TODO(Shaohua): Validate cert and key files.
         Ok(())
     }
 }
@@ -387,11 +1065,29 @@ impl Default for Listener {
             path: Self::default_path(),
             cert_file: Self::default_cert_file(),
             key_file: Self::default_key_file(),
+            require_certificate: Self::default_require_certificate(),
+            ca_file: Self::default_ca_file(),
+            min_tls_version: Self::default_min_tls_version(),
+            cipher_suites: Self::default_cipher_suites(),
             username_as_client_id: Self::default_username_as_client_id(),
             keep_alive: Self::default_keep_alive(),
             connect_timeout: Self::default_connect_timeout(),
             allow_empty_client_id: Self::default_allow_empty_client_id(),
             maximum_inflight_messages: Self::default_maximum_inflight_messages(),
+            retransmit_delay: Self::default_retransmit_delay(),
+            topic_alias_maximum: Self::default_topic_alias_maximum(),
+            maximum_packet_size: Self::default_maximum_packet_size(),
+            max_message_rate: Self::default_max_message_rate(),
+            max_byte_rate: Self::default_max_byte_rate(),
+            rate_limit_policy: RateLimitPolicy::default(),
+            identity_source: IdentitySource::default(),
+            identity_header: Self::default_identity_header(),
+            proxy_protocol: Self::default_proxy_protocol(),
+            allowed_uids: Self::default_allowed_uids(),
+            allowed_gids: Self::default_allowed_gids(),
+            acl_policy: AclPolicy::default(),
+            admission: Admission::default(),
+            capture: Capture::default(),
         }
     }
 }
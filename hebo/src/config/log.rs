@@ -8,6 +8,8 @@ use std::path::Path;
 
 use crate::error::{Error, ErrorKind};
 
+/// Field names are keys in config file.
+#[allow(clippy::struct_field_names)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct Log {
     /// Alaso print log to console.
@@ -35,6 +37,35 @@ pub struct Log {
     /// Default is None.
     #[serde(default = "Log::default_log_file")]
     log_file: Option<String>,
+
+    /// Format of log records, both in console and in log file.
+    ///
+    /// Avaliable values are:
+    /// - text, human readable lines
+    /// - json, one json object per line, with timestamp, level, target and message fields
+    ///
+    /// Default is "text".
+    #[serde(default = "Log::default_format")]
+    format: LogFormat,
+
+    /// Path to access log file.
+    ///
+    /// Connect and disconnect events of clients are recorded in this file for auditing,
+    /// one line per event. It is independent of `log_level`.
+    ///
+    /// Default is None, access log is disabled.
+    #[serde(default = "Log::default_access_log_file")]
+    access_log_file: Option<String>,
+
+    /// Format of access log lines.
+    ///
+    /// Avaliable values are:
+    /// - text, space separated `key=value` pairs
+    /// - json, one json object per line
+    ///
+    /// Default is "text".
+    #[serde(default = "Log::default_access_log_format")]
+    access_log_format: LogFormat,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -59,6 +90,17 @@ pub enum LogLevel {
     Trace,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    #[serde(alias = "text")]
+    Text,
+
+    #[serde(alias = "json")]
+    Json,
+}
+
 impl Log {
     #[must_use]
     pub const fn default_console_log() -> bool {
@@ -76,6 +118,21 @@ impl Log {
         None
     }
 
+    #[must_use]
+    pub const fn default_format() -> LogFormat {
+        LogFormat::Text
+    }
+
+    #[must_use]
+    pub const fn default_access_log_file() -> Option<String> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_access_log_format() -> LogFormat {
+        LogFormat::Text
+    }
+
     #[must_use]
     pub const fn console_log(&self) -> bool {
         self.console_log
@@ -91,13 +148,61 @@ impl Log {
         self.log_file.as_ref()
     }
 
+    #[must_use]
+    pub const fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    #[must_use]
+    pub const fn access_log_file(&self) -> Option<&String> {
+        self.access_log_file.as_ref()
+    }
+
+    #[must_use]
+    pub const fn access_log_format(&self) -> LogFormat {
+        self.access_log_format
+    }
+
+    pub fn set_console_log(&mut self, console_log: bool) -> &mut Self {
+        self.console_log = console_log;
+        self
+    }
+
+    pub fn set_log_level(&mut self, log_level: LogLevel) -> &mut Self {
+        self.log_level = log_level;
+        self
+    }
+
+    pub fn set_log_file(&mut self, log_file: Option<String>) -> &mut Self {
+        self.log_file = log_file;
+        self
+    }
+
+    pub fn set_format(&mut self, format: LogFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    pub fn set_access_log_file(&mut self, access_log_file: Option<String>) -> &mut Self {
+        self.access_log_file = access_log_file;
+        self
+    }
+
+    pub fn set_access_log_format(&mut self, access_log_format: LogFormat) -> &mut Self {
+        self.access_log_format = access_log_format;
+        self
+    }
+
     /// Validate config.
     ///
     /// # Errors
     ///
-    /// Returns error if failed to create log file or its parent directory.
+    /// Returns error if failed to create log files or their parent directories.
     pub fn validate(&self) -> Result<(), Error> {
-        if let Some(log_file) = &self.log_file {
+        for log_file in [&self.log_file, &self.access_log_file]
+            .into_iter()
+            .flatten()
+        {
             let path = Path::new(log_file);
             if let Some(parent) = path.parent() {
                 fs::create_dir_all(parent).map_err(|err| {
@@ -130,6 +235,9 @@ impl Default for Log {
             console_log: Self::default_console_log(),
             log_level: Self::default_log_level(),
             log_file: Self::default_log_file(),
+            format: Self::default_format(),
+            access_log_file: Self::default_access_log_file(),
+            access_log_format: Self::default_access_log_format(),
         }
     }
 }
@@ -3,22 +3,45 @@
 // in the LICENSE file.
 
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 
+mod admission;
+mod bridge;
+mod capture;
 mod dashboard;
+mod env;
 mod general;
+mod include;
 mod listener;
 mod log;
+#[cfg(feature = "pgsql_conn")]
+mod pgsql_auth;
+mod rule;
 mod security;
+#[cfg(feature = "pgsql_conn")]
+mod sql_output;
 mod storage;
+mod webhook_output;
 
-pub use self::log::{Log, LogLevel};
+pub use self::log::{Log, LogFormat, LogLevel};
+pub use admission::Admission;
+pub use bridge::{Bridge, BridgeDirection, BridgeTopic};
+pub use capture::Capture;
 pub use dashboard::Dashboard;
-pub use general::General;
-pub use listener::{Listener, Protocol};
+pub use env::ENV_PREFIX;
+pub use general::{General, QueueFullPolicy, PRESENCE_CLIENT_ID};
+pub use listener::{AclPolicy, IdentitySource, Listener, Protocol, RateLimitPolicy, TlsVersion};
+#[cfg(feature = "pgsql_conn")]
+pub use pgsql_auth::PgSQLAuth;
+pub use rule::{Rule, RuleAction};
 pub use security::Security;
-pub use storage::Storage;
+#[cfg(feature = "pgsql_conn")]
+pub use sql_output::{SqlColumns, SqlOutput};
+pub use storage::{MessageQueuePolicy, MessageStoreType, Storage};
+pub use webhook_output::WebhookOutput;
 
 /// Server main config.
 #[derive(Debug, Default, Clone, Deserialize)]
@@ -40,9 +63,99 @@ pub struct Config {
 
     #[serde(default = "Dashboard::default")]
     dashboard: Dashboard,
+
+    #[serde(default = "Vec::new")]
+    bridges: Vec<Bridge>,
+
+    /// Rules of rule engine, evaluated in order.
+    #[serde(default = "Vec::new")]
+    rules: Vec<Rule>,
+
+    /// Included files where sections are defined, like `listeners[2]`.
+    #[serde(skip)]
+    origins: HashMap<String, PathBuf>,
 }
 
 impl Config {
+    /// Read and parse config file.
+    ///
+    /// Files listed in `include` key are merged into main config file, see [`Self::load`].
+    /// Values in config file are overridden by environment variables, see [`Self::from_env`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read config file or it is not a valid toml file,
+    /// or environment variables contain invalid values.
+    pub fn from_file<P: AsRef<Path>>(config_file: P) -> Result<Self, Error> {
+        Self::load(config_file.as_ref(), std::env::vars())
+    }
+
+    /// Read `config_file` and files included by it, and overlay `vars` on them.
+    ///
+    /// Config fragments are included by glob patterns relative to directory of main file,
+    /// like `include = ["conf.d/*.toml"]`. Matched files are merged in order of path,
+    /// arrays of tables like `[[listeners]]` are appended, and other values are overridden.
+    fn load<I>(config_file: &Path, vars: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut table = include::read_table(config_file)?;
+        let origins = include::merge_includes(&mut table, config_file)?;
+        let mut config = Self::from_table(table, vars).map_err(|err| {
+            Error::from_string(
+                err.kind().clone(),
+                format!("{}: {}", config_file.display(), err.message()),
+            )
+        })?;
+        config.origins = origins;
+        Ok(config)
+    }
+
+    /// Create config with default values, overridden by environment variables.
+    ///
+    /// Names of environment variables are derived from path of config fields,
+    /// like `HEBO_SECURITY_ALLOW_ANONYMOUS` for `allow_anonymous` in `[security]`
+    /// section, and `HEBO_LISTENERS_0_ADDRESS` for `address` of the first listener.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if environment variables contain invalid values.
+    pub fn from_env() -> Result<Self, Error> {
+        Self::parse("", std::env::vars())
+    }
+
+    /// Parse toml `content` and overlay `vars` on it.
+    fn parse<I>(content: &str, vars: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let table: toml::Table = content.parse().map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid toml config, err: {err:?}"),
+            )
+        })?;
+        Self::from_table(table, vars)
+    }
+
+    fn from_table<I>(mut table: toml::Table, vars: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let names = env::apply_env(&mut table, vars)?;
+        toml::Value::Table(table).try_into().map_err(|err| {
+            let message = if names.is_empty() {
+                format!("Invalid config, err: {err:?}")
+            } else {
+                format!(
+                    "Invalid config with environment variables {}, err: {err:?}",
+                    names.join(", ")
+                )
+            };
+            Error::from_string(ErrorKind::ConfigError, message)
+        })
+    }
+
     #[must_use]
     pub const fn general(&self) -> &General {
         &self.general
@@ -73,21 +186,625 @@ impl Config {
         &self.dashboard
     }
 
+    #[must_use]
+    pub fn bridges(&self) -> &[Bridge] {
+        &self.bridges
+    }
+
+    #[must_use]
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn set_general(&mut self, general: General) -> &mut Self {
+        self.general = general;
+        self
+    }
+
+    pub fn set_listeners(&mut self, listeners: Vec<Listener>) -> &mut Self {
+        self.listeners = listeners;
+        self
+    }
+
+    pub fn add_listener(&mut self, listener: Listener) -> &mut Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    pub fn set_security(&mut self, security: Security) -> &mut Self {
+        self.security = security;
+        self
+    }
+
+    pub fn set_storage(&mut self, storage: Storage) -> &mut Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn set_log(&mut self, log: Log) -> &mut Self {
+        self.log = log;
+        self
+    }
+
+    pub fn set_dashboard(&mut self, dashboard: Dashboard) -> &mut Self {
+        self.dashboard = dashboard;
+        self
+    }
+
+    pub fn add_bridge(&mut self, bridge: Bridge) -> &mut Self {
+        self.bridges.push(bridge);
+        self
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
     /// Validate config.
     ///
+    /// Error message starts with section of the invalid option, like `listeners[1]`.
+    ///
     /// # Errors
     ///
     /// Returns error if some options in config is invalid.
     pub fn validate(&self, bind_address: bool) -> Result<(), Error> {
-        self.general.validate()?;
+        self.general
+            .validate()
+            .map_err(section_error(&self.section_name("general")))?;
+
+        for (index, listener) in self.listeners.iter().enumerate() {
+            listener.validate(bind_address).map_err(section_error(
+                &self.section_name(&format!("listeners[{index}]")),
+            ))?;
+        }
+
+        for (index, bridge) in self.bridges.iter().enumerate() {
+            bridge.validate().map_err(section_error(
+                &self.section_name(&format!("bridges[{index}]")),
+            ))?;
+        }
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate().map_err(section_error(
+                &self.section_name(&format!("rules[{index}]")),
+            ))?;
+        }
+        if !self.rules.is_empty() && !cfg!(feature = "rule_engine") {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "rules: rules require rule_engine feature",
+            ));
+        }
+
+        self.security
+            .validate()
+            .map_err(section_error(&self.section_name("security")))?;
+        self.storage
+            .validate()
+            .map_err(section_error(&self.section_name("storage")))?;
+        self.log
+            .validate()
+            .map_err(section_error(&self.section_name("log")))?;
+        self.dashboard
+            .validate(bind_address)
+            .map_err(section_error(&self.section_name("dashboard")))
+    }
+
+    /// Append included file to `section` if it is defined there.
+    fn section_name(&self, section: &str) -> String {
+        self.origins.get(section).map_or_else(
+            || section.to_string(),
+            |file| format!("{section} in {}", file.display()),
+        )
+    }
+}
+
+/// Prefix error message with name of config section.
+fn section_error(section: &str) -> impl FnOnce(Error) -> Error + '_ {
+    move |err| Error::from_string(err.kind().clone(), format!("{section}: {}", err.message()))
+}
+
+/// Check that file in option `field` exists.
+fn validate_file(field: &str, path: &Path) -> Result<(), Error> {
+    if path.is_file() {
+        Ok(())
+    } else {
+        Err(Error::from_string(
+            ErrorKind::ConfigError,
+            format!("`{field}` {} is not a file", path.display()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Listener, MessageQueuePolicy, MessageStoreType};
+    use crate::error::ErrorKind;
+
+    /// Validate config and returns error message.
+    fn validate(content: &str) -> String {
+        let mut config: Config = toml::from_str(content).unwrap();
+        set_root_user(&mut config);
+        config.validate(false).unwrap_err().message().to_string()
+    }
+
+    /// Use a user which exists on every host, so that validation of `general`
+    /// section does not depend on users of host.
+    fn set_root_user(config: &mut Config) {
+        let mut general = config.general().clone();
+        general.set_user("root");
+        config.set_general(general);
+    }
+
+    #[test]
+    fn test_env_overlay() {
+        let content = r#"
+            [security]
+            allow_anonymous = false
+
+            [dashboard]
+            address = "127.0.0.1:18083"
+            "#;
+        let config = Config::parse(content, []).unwrap();
+        assert!(!config.security().allow_anonymous());
+
+        let vars = [
+            ("HEBO_SECURITY_ALLOW_ANONYMOUS", "true"),
+            ("HEBO_DASHBOARD_ADDRESS", "0.0.0.0:18083"),
+            ("HEBO_LISTENERS_0_ADDRESS", "0.0.0.0:1884"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let config = Config::parse(content, vars).unwrap();
+        assert!(config.security().allow_anonymous());
+        assert_eq!(config.dashboard().address(), "0.0.0.0:18083");
+        assert_eq!(config.listeners()[0].address(), "0.0.0.0:1884");
+
+        let vars = [(
+            "HEBO_SECURITY_ALLOW_ANONYMOUS".to_string(),
+            "maybe".to_string(),
+        )];
+        let err = Config::parse(content, vars).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::ConfigError));
+        assert!(err.message().starts_with(
+            "Invalid config with environment variables HEBO_SECURITY_ALLOW_ANONYMOUS"
+        ));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join("hebo-test-config-include");
+        let conf_dir = dir.join("conf.d");
+        std::fs::create_dir_all(&conf_dir).unwrap();
+        let main_file = dir.join("hebo.toml");
+        std::fs::write(
+            &main_file,
+            r#"
+            include = ["conf.d/*.toml"]
+
+            [general]
+            maximum_keep_alive = 60
+
+            [security]
+            allow_anonymous = false
+
+            [[listeners]]
+            address = "127.0.0.1:1883"
+            "#,
+        )
+        .unwrap();
+        // Fragments are merged in order of file name.
+        std::fs::write(
+            conf_dir.join("20-ws.toml"),
+            r#"
+            [security]
+            allow_anonymous = true
+
+            [[listeners]]
+            protocol = "ws"
+            address = "127.0.0.1:8083"
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            conf_dir.join("10-mqtts.toml"),
+            r#"
+            [general]
+            maximum_keep_alive = 30
+
+            [[listeners]]
+            protocol = "mqtts"
+            address = "127.0.0.1:8883"
+            "#,
+        )
+        .unwrap();
+
+        let mut config = Config::load(&main_file, []).unwrap();
+        let addresses: Vec<&str> = config.listeners().iter().map(Listener::address).collect();
+        assert_eq!(
+            addresses,
+            ["127.0.0.1:1883", "127.0.0.1:8883", "127.0.0.1:8083"]
+        );
+        assert_eq!(config.general().maximum_keep_alive(), 30);
+        assert!(config.security().allow_anonymous());
+
+        // Validation error reports file where the invalid listener is defined.
+        set_root_user(&mut config);
+        let msg = config.validate(false).unwrap_err().message().to_string();
+        assert_eq!(
+            msg,
+            format!(
+                "listeners[1] in {}: `cert_file` is required by Mqtts protocol",
+                conf_dir.join("10-mqtts.toml").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_listener_options() {
+        let msg = validate(
+            r#"
+            [[listeners]]
+            address = "127.0.0.1:1883"
+
+            [[listeners]]
+            protocol = "mqtts"
+            address = "127.0.0.1:8883"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[1]: `cert_file` is required by Mqtts protocol"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "quic"
+            address = "127.0.0.1:8993"
+            cert_file = "Cargo.toml"
+            "#,
+        );
+        assert_eq!(msg, "listeners[0]: `key_file` is required by Quic protocol");
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "wss"
+            address = "127.0.0.1:8084"
+            cert_file = "/nonexistent/cert.pem"
+            key_file = "Cargo.toml"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `cert_file` /nonexistent/cert.pem is not a file"
+        );
 
-        for listener in &self.listeners {
-            listener.validate(bind_address)?;
+        let msg = validate(
+            r#"
+            [[listeners]]
+            address = "127.0.0.1:1883"
+            path = "/mqtt"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `path` is only supported by Ws and Wss protocols, got Mqtt"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            address = "127.0.0.1:1883"
+            identity_source = "tls-cn"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `identity_source` TlsCn is not supported by Mqtt protocol"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "uds"
+            address = "/tmp/hebo-proxy-protocol.sock"
+            proxy_protocol = true
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `proxy_protocol` is only supported by tcp based protocols, got Uds"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "mqtts"
+            address = "127.0.0.1:8883"
+            cert_file = "Cargo.toml"
+            key_file = "Cargo.toml"
+            require_certificate = true
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `ca_file` is required by `require_certificate`"
+        );
+    }
+
+    #[test]
+    fn test_allowed_peers() {
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "mqtt"
+            address = "127.0.0.1:1883"
+            allowed_uids = [1000]
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `allowed_uids` and `allowed_gids` are only supported by Uds protocol on Linux, got Mqtt"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "mqtt"
+            address = "127.0.0.1:1883"
+            identity_source = "peer-cred"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `identity_source` PeerCred is not supported by Mqtt protocol"
+        );
+    }
+
+    #[test]
+    fn test_listener_tls_options() {
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "mqtts"
+            address = "127.0.0.1:8883"
+            cert_file = "Cargo.toml"
+            key_file = "Cargo.toml"
+            cipher_suites = ["TLS13_AES_256_GCM_SHA384", "TLS_RSA_WITH_RC4_128_SHA"]
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: Unknown cipher suite \"TLS_RSA_WITH_RC4_128_SHA\" in `cipher_suites`"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            protocol = "mqtts"
+            address = "127.0.0.1:8883"
+            cert_file = "Cargo.toml"
+            key_file = "Cargo.toml"
+            min_tls_version = "1.3"
+            cipher_suites = ["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"]
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: None of `cipher_suites` supports `min_tls_version` Tls13"
+        );
+
+        let msg = validate(
+            r#"
+            [[listeners]]
+            address = "127.0.0.1:1883"
+            min_tls_version = "1.3"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "listeners[0]: `min_tls_version` and `cipher_suites` are only supported by Mqtts and Wss protocols, got Mqtt"
+        );
+    }
+
+    #[test]
+    fn test_bridge_options() {
+        let msg = validate(
+            r#"
+            [[bridges]]
+            name = "cloud"
+            address = "127.0.0.1:1883"
+            ca_file = "Cargo.toml"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "bridges[0]: `ca_file` of bridge cloud requires `tls` to be true"
+        );
+
+        let msg = validate(
+            r#"
+            [[bridges]]
+            name = "cloud"
+            address = "127.0.0.1:1883"
+            retry_interval = 60
+            maximum_retry_interval = 30
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "bridges[0]: `retry_interval` 60 of bridge cloud is larger than `maximum_retry_interval` 30"
+        );
+    }
+
+    #[test]
+    fn test_rule_options() {
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/+/temp"
+            action = "republish"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: `target` is required by republish rule sensors/+/temp"
+        );
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/+/temp"
+            action = "republish"
+            target = "agg/{1}/{2}"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: `target` of rule sensors/+/temp refers to {2}, but only 1 levels are captured"
+        );
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#/temp"
+            action = "drop"
+            "#,
+        );
+        assert!(msg.starts_with("rules[0]: Invalid `match` sensors/#/temp"));
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "sql"
+            "#,
+        );
+        if cfg!(feature = "pgsql_conn") {
+            assert_eq!(msg, "rules[0]: `sql` is required by sql rule sensors/#");
+        } else {
+            assert_eq!(
+                msg,
+                "rules[0]: sql rule sensors/# requires pgsql_conn feature"
+            );
         }
+    }
+
+    #[test]
+    fn test_rule_webhook_options() {
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "webhook"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: `webhook` is required by webhook rule sensors/#"
+        );
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "webhook"
+            [rules.webhook]
+            url = "ftp://127.0.0.1/messages"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: Invalid `url` of webhook output: \"ftp://127.0.0.1/messages\""
+        );
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "webhook"
+            [rules.webhook]
+            url = "https://example.com/messages"
+            headers = { "Bad Header" = "1" }
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: Invalid header of webhook output: \"Bad Header\""
+        );
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    #[test]
+    fn test_rule_sql_options() {
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "sql"
+            [rules.sql]
+            table = "messages; DROP TABLE messages"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: Invalid table or column name in sql output: \"messages; DROP TABLE messages\""
+        );
+
+        let msg = validate(
+            r#"
+            [[rules]]
+            match = "sensors/#"
+            action = "sql"
+            [rules.sql]
+            batch_size = 20000
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "rules[0]: `batch_size` of sql output must be in range 1..=16383, got 20000"
+        );
+    }
+
+    #[test]
+    fn test_security_options() {
+        let msg = validate(
+            r#"
+            [security]
+            password_file = "/nonexistent/passwd"
+            "#,
+        );
+        assert_eq!(
+            msg,
+            "security: `password_file` /nonexistent/passwd is not a file"
+        );
+    }
+
+    #[test]
+    fn test_storage_options() {
+        let msg = validate(
+            r#"
+            [storage]
+            message_store = "memory"
+            message_queue_size = 0
+            "#,
+        );
+        assert_eq!(msg, "storage: message_queue_size must be greater than 0");
 
-        self.security.validate()?;
-        self.storage.validate()?;
-        self.log.validate()?;
-        self.dashboard.validate(bind_address)
+        let config: Config = toml::from_str(
+            r#"
+            [storage]
+            message_store = "memory"
+            message_queue_policy = "block"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.storage().message_store(), MessageStoreType::Memory);
+        assert_eq!(
+            config.storage().message_queue_policy(),
+            MessageQueuePolicy::Block
+        );
     }
 }
@@ -0,0 +1,152 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use serde::Deserialize;
+
+use crate::connectors::pgsql_conn::PgSQLConnConfig;
+use crate::error::{Error, ErrorKind};
+
+/// Authenticate clients with password hashes stored in `PgSQL` database.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Deserialize, Clone)]
+pub struct PgSQLAuth {
+    /// Connection to `PgSQL` server, `host`, `port`, `pool_size` and so on.
+    #[serde(flatten)]
+    connection: PgSQLConnConfig,
+
+    /// Table which contains user records.
+    ///
+    /// Default is `mqtt_users`.
+    #[serde(default = "PgSQLAuth::default_table")]
+    table: String,
+
+    /// Column of username.
+    ///
+    /// Default is `username`.
+    #[serde(default = "PgSQLAuth::default_username_column")]
+    username_column: String,
+
+    /// Column of password hash, in the same formats as password file.
+    ///
+    /// Default is `password_hash`.
+    #[serde(default = "PgSQLAuth::default_password_column")]
+    password_column: String,
+
+    /// Custom query to get password hash of a user, `$1` is replaced with username.
+    ///
+    /// Overrides `table`, `username_column` and `password_column` if set,
+    /// for example `SELECT password_hash FROM users WHERE username = $1 AND enabled`.
+    ///
+    /// Default is None.
+    #[serde(default = "PgSQLAuth::default_query")]
+    query: Option<String>,
+}
+
+impl PgSQLAuth {
+    #[must_use]
+    pub fn default_table() -> String {
+        "mqtt_users".to_string()
+    }
+
+    #[must_use]
+    pub fn default_username_column() -> String {
+        "username".to_string()
+    }
+
+    #[must_use]
+    pub fn default_password_column() -> String {
+        "password_hash".to_string()
+    }
+
+    #[must_use]
+    pub const fn default_query() -> Option<String> {
+        None
+    }
+
+    #[must_use]
+    pub const fn connection(&self) -> &PgSQLConnConfig {
+        &self.connection
+    }
+
+    /// Get sql statement to query password hash of a user.
+    #[must_use]
+    pub fn query(&self) -> String {
+        self.query.clone().unwrap_or_else(|| {
+            format!(
+                "SELECT {} FROM {} WHERE {} = $1",
+                self.password_column, self.table, self.username_column
+            )
+        })
+    }
+
+    /// Validate table and column names, and custom query.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if names are not valid sql identifiers, or `$1` is not found in query.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(query) = &self.query {
+            if !query.contains("$1") {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("security: No username parameter $1 in pgsql auth query: {query:?}"),
+                ));
+            }
+            return Ok(());
+        }
+        for name in [&self.table, &self.username_column, &self.password_column] {
+            if !is_identifier(name) {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("security: Invalid table or column name in pgsql auth: {name:?}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check `name` is a plain sql identifier, optionally prefixed with schema name.
+pub(super) fn is_identifier(name: &str) -> bool {
+    name.split('.').all(|part| {
+        part.chars()
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PgSQLAuth;
+
+    #[test]
+    fn test_query() {
+        let config: PgSQLAuth = toml::from_str(
+            r#"
+        host = "127.0.0.1"
+        table = "auth.users"
+        password_column = "pw"
+        "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.query(),
+            "SELECT pw FROM auth.users WHERE username = $1"
+        );
+
+        let config: PgSQLAuth = toml::from_str(r#"table = "users; DROP TABLE users""#).unwrap();
+        assert!(config.validate().is_err());
+
+        let config: PgSQLAuth =
+            toml::from_str(r#"query = "SELECT pw FROM users WHERE name = $1 AND enabled""#)
+                .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.query(),
+            "SELECT pw FROM users WHERE name = $1 AND enabled"
+        );
+    }
+}
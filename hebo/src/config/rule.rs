@@ -0,0 +1,291 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use codec::topic::{validate_sub_topic, SHARE_PREFIX};
+use serde::Deserialize;
+
+#[cfg(feature = "pgsql_conn")]
+use super::SqlOutput;
+use super::WebhookOutput;
+use crate::error::{Error, ErrorKind};
+
+/// What rule engine does with messages matching a rule.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum RuleAction {
+    /// Publish a new message to `target` topic, original message is still delivered.
+    ///
+    /// Payload of new message is `template` if set, or payload of original message.
+    #[default]
+    #[serde(alias = "republish")]
+    Republish,
+
+    /// Replace payload of original message with `template`.
+    #[serde(alias = "rewrite")]
+    Rewrite,
+
+    /// Drop original message, rules after it are not evaluated.
+    #[serde(alias = "drop")]
+    Drop,
+
+    /// Insert message into table of `sql` output, original message is still delivered.
+    ///
+    /// Requires `pgsql_conn` feature.
+    #[serde(alias = "sql")]
+    Sql,
+
+    /// Post message to http endpoint of `webhook` output, original message is still delivered.
+    #[serde(alias = "webhook")]
+    Webhook,
+}
+
+/// Rule evaluated by rule engine against messages published by clients.
+///
+/// Rules are evaluated in order, and all matching rules apply.
+///
+/// `{1}`, `{2}`, ... in `target` and `template` are replaced with topic levels
+/// captured by wildcards in `match`, `#` captures all remaining levels.
+/// `{topic}` and `{payload}` are replaced with topic and payload of original message.
+///
+/// Example: `{ match = "sensors/+/temp", action = "republish", target = "agg/{1}/temp" }`
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Rule {
+    /// Topic filter of messages handled by this rule.
+    #[serde(rename = "match")]
+    topic_filter: String,
+
+    /// Default is `republish`.
+    #[serde(default = "Rule::default_action")]
+    action: RuleAction,
+
+    /// Topic template of republished messages, required by `republish` action.
+    ///
+    /// Default is None.
+    #[serde(default = "Rule::default_target")]
+    target: Option<String>,
+
+    /// Payload template, required by `rewrite` action.
+    ///
+    /// Default is None.
+    #[serde(default = "Rule::default_template")]
+    template: Option<String>,
+
+    /// Table and connection of `sql` action.
+    ///
+    /// Default is None.
+    #[cfg(feature = "pgsql_conn")]
+    #[serde(default = "Rule::default_sql")]
+    sql: Option<SqlOutput>,
+
+    /// Http endpoint of `webhook` action.
+    ///
+    /// Default is None.
+    #[serde(default = "Rule::default_webhook")]
+    webhook: Option<WebhookOutput>,
+}
+
+impl Rule {
+    #[must_use]
+    pub fn new(topic_filter: &str, action: RuleAction) -> Self {
+        Self {
+            topic_filter: topic_filter.to_string(),
+            action,
+            target: None,
+            template: None,
+            #[cfg(feature = "pgsql_conn")]
+            sql: None,
+            webhook: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn default_action() -> RuleAction {
+        RuleAction::Republish
+    }
+
+    #[must_use]
+    pub const fn default_target() -> Option<String> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_template() -> Option<String> {
+        None
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    #[must_use]
+    pub const fn default_sql() -> Option<SqlOutput> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_webhook() -> Option<WebhookOutput> {
+        None
+    }
+
+    #[must_use]
+    pub fn topic_filter(&self) -> &str {
+        &self.topic_filter
+    }
+
+    #[must_use]
+    pub const fn action(&self) -> RuleAction {
+        self.action
+    }
+
+    #[must_use]
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    #[must_use]
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    #[must_use]
+    pub const fn sql(&self) -> Option<&SqlOutput> {
+        self.sql.as_ref()
+    }
+
+    #[must_use]
+    pub const fn webhook(&self) -> Option<&WebhookOutput> {
+        self.webhook.as_ref()
+    }
+
+    pub fn set_target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn set_template(&mut self, template: &str) -> &mut Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Number of levels captured by wildcards in topic filter.
+    fn captures(&self) -> usize {
+        self.topic_filter
+            .split('/')
+            .filter(|level| *level == "+" || *level == "#")
+            .count()
+    }
+
+    /// Check `{n}` placeholders in `template` refer to captured levels.
+    fn validate_placeholders(&self, field: &str, template: &str) -> Result<(), Error> {
+        let captures = self.captures();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            rest = &rest[start + 1..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            if rest[..end].contains('{') {
+                continue;
+            }
+            if let Ok(index) = rest[..end].parse::<usize>() {
+                if index == 0 || index > captures {
+                    return Err(Error::from_string(
+                        ErrorKind::ConfigError,
+                        format!(
+                            "`{field}` of rule {} refers to {{{index}}}, but only {captures} levels are captured",
+                            self.topic_filter
+                        ),
+                    ));
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    fn validate_sql(&self) -> Result<(), Error> {
+        self.sql.as_ref().map_or_else(
+            || {
+                Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("`sql` is required by sql rule {}", self.topic_filter),
+                ))
+            },
+            SqlOutput::validate,
+        )
+    }
+
+    #[cfg(not(feature = "pgsql_conn"))]
+    fn validate_sql(&self) -> Result<(), Error> {
+        Err(Error::from_string(
+            ErrorKind::ConfigError,
+            format!("sql rule {} requires pgsql_conn feature", self.topic_filter),
+        ))
+    }
+
+    /// Validate rule.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if topic filter is invalid, required `target`, `template`, `sql`
+    /// or `webhook` is missing, or they refer to levels not captured.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Err(err) = validate_sub_topic(&self.topic_filter) {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid `match` {}, err: {err:?}", self.topic_filter),
+            ));
+        }
+        if self.topic_filter.starts_with(SHARE_PREFIX) {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "Shared subscription is not allowed in `match`: {}",
+                    self.topic_filter
+                ),
+            ));
+        }
+
+        match self.action {
+            RuleAction::Republish if self.target.is_none() => {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!(
+                        "`target` is required by republish rule {}",
+                        self.topic_filter
+                    ),
+                ));
+            }
+            RuleAction::Rewrite if self.template.is_none() => {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!(
+                        "`template` is required by rewrite rule {}",
+                        self.topic_filter
+                    ),
+                ));
+            }
+            RuleAction::Sql => self.validate_sql()?,
+            RuleAction::Webhook => match &self.webhook {
+                Some(webhook) => webhook.validate()?,
+                None => {
+                    return Err(Error::from_string(
+                        ErrorKind::ConfigError,
+                        format!(
+                            "`webhook` is required by webhook rule {}",
+                            self.topic_filter
+                        ),
+                    ));
+                }
+            },
+            _ => (),
+        }
+
+        if let Some(target) = &self.target {
+            self.validate_placeholders("target", target)?;
+        }
+        if let Some(template) = &self.template {
+            self.validate_placeholders("template", template)?;
+        }
+        Ok(())
+    }
+}
@@ -5,8 +5,12 @@
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
 
+use super::validate_file;
 use crate::error::Error;
 
+#[cfg(feature = "pgsql_conn")]
+use super::PgSQLAuth;
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct Security {
@@ -30,6 +34,44 @@ pub struct Security {
     /// Default is None.
     #[serde(default = "Security::default_password_file")]
     password_file: Option<PathBuf>,
+
+    /// Accept entries with password in plain text in `password_file`.
+    ///
+    /// Hashed passwords in sha512, PBKDF2 and bcrypt formats are always accepted.
+    /// This is only intended for migrating an old password file, hash it with
+    /// hebo-passwd as soon as possible.
+    ///
+    /// Default is false.
+    #[serde(default = "Security::default_allow_plain_text_password")]
+    allow_plain_text_password: bool,
+
+    /// Control topic access of clients using an acl file, requires `acl` feature.
+    ///
+    /// Each line of the file is one of:
+    /// - `user <username>`, following `topic` rules only apply to this user.
+    ///   Rules before any `user` line apply to anonymous clients.
+    /// - `topic [read|write|readwrite|deny] <topic>`, access defaults to `readwrite`.
+    /// - `user <username> topic [read|write|readwrite|deny] <topic>`, a single rule
+    ///   of this user.
+    /// - `pattern [read|write|readwrite|deny] <topic>`, applies to all clients,
+    ///   `%c` is replaced with client id and `%u` with username.
+    ///
+    /// Wildcards are allowed in topics. Rules are evaluated in order, the first rule
+    /// matching the topic and access type takes effect. If no rule matches,
+    /// `acl_policy` of the listener is used.
+    ///
+    /// Default is None, which allows all clients to access all topics.
+    #[serde(default = "Security::default_acl_file")]
+    acl_file: Option<PathBuf>,
+
+    /// Check username and password against records in `PgSQL` database.
+    ///
+    /// Database is checked before `password_file`.
+    ///
+    /// Default is None.
+    #[cfg(feature = "pgsql_conn")]
+    #[serde(default = "Security::default_pgsql_auth")]
+    pgsql_auth: Option<PgSQLAuth>,
 }
 
 impl Security {
@@ -43,6 +85,22 @@ impl Security {
         None
     }
 
+    #[must_use]
+    pub const fn default_allow_plain_text_password() -> bool {
+        false
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    #[must_use]
+    pub const fn default_pgsql_auth() -> Option<PgSQLAuth> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_acl_file() -> Option<PathBuf> {
+        None
+    }
+
     #[must_use]
     pub const fn allow_anonymous(&self) -> bool {
         self.allow_anonymous
@@ -53,13 +111,60 @@ impl Security {
         self.password_file.as_deref()
     }
 
+    #[must_use]
+    pub const fn allow_plain_text_password(&self) -> bool {
+        self.allow_plain_text_password
+    }
+
+    #[must_use]
+    pub fn acl_file(&self) -> Option<&Path> {
+        self.acl_file.as_deref()
+    }
+
+    #[cfg(feature = "pgsql_conn")]
+    #[must_use]
+    pub const fn pgsql_auth(&self) -> Option<&PgSQLAuth> {
+        self.pgsql_auth.as_ref()
+    }
+
+    pub fn set_allow_anonymous(&mut self, allow_anonymous: bool) -> &mut Self {
+        self.allow_anonymous = allow_anonymous;
+        self
+    }
+
+    pub fn set_password_file(&mut self, password_file: Option<PathBuf>) -> &mut Self {
+        self.password_file = password_file;
+        self
+    }
+
+    pub fn set_allow_plain_text_password(&mut self, allow_plain_text_password: bool) -> &mut Self {
+        self.allow_plain_text_password = allow_plain_text_password;
+        self
+    }
+
+    pub fn set_acl_file(&mut self, acl_file: Option<PathBuf>) -> &mut Self {
+        self.acl_file = acl_file;
+        self
+    }
+
     /// Validate security config.
     ///
     /// # Errors
     ///
-    /// Does nothing.
-    pub const fn validate(&self) -> Result<(), Error> {
+    /// Returns error if password file or acl file does not exist, or database
+    /// auth config is invalid.
+    pub fn validate(&self) -> Result<(), Error> {
         // TODO(Shaohua): Validate password file entry
+        if let Some(password_file) = &self.password_file {
+            validate_file("password_file", password_file)?;
+        }
+        if let Some(acl_file) = &self.acl_file {
+            validate_file("acl_file", acl_file)?;
+        }
+        #[cfg(feature = "pgsql_conn")]
+        if let Some(pgsql_auth) = &self.pgsql_auth {
+            pgsql_auth.validate()?;
+        }
         Ok(())
     }
 }
@@ -69,6 +174,10 @@ impl Default for Security {
         Self {
             allow_anonymous: Self::default_allow_anonymous(),
             password_file: Self::default_password_file(),
+            allow_plain_text_password: Self::default_allow_plain_text_password(),
+            acl_file: Self::default_acl_file(),
+            #[cfg(feature = "pgsql_conn")]
+            pgsql_auth: Self::default_pgsql_auth(),
         }
     }
 }
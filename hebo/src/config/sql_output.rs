@@ -0,0 +1,242 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use serde::Deserialize;
+use std::time::Duration;
+
+use super::pgsql_auth::is_identifier;
+use crate::connectors::pgsql_conn::PgSQLConnConfig;
+use crate::error::{Error, ErrorKind};
+
+/// Maximum number of parameters in a `PgSQL` statement.
+const MAX_PARAMETERS: usize = u16::MAX as usize;
+
+/// Columns where fields of messages are written to, empty name means field is not written.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SqlColumns {
+    /// Topic name, of `TEXT` type.
+    ///
+    /// Default is `topic`.
+    #[serde(default = "SqlColumns::default_topic")]
+    topic: String,
+
+    /// Message payload, of `BYTEA` type.
+    ///
+    /// Default is `payload`.
+    #[serde(default = "SqlColumns::default_payload")]
+    payload: String,
+
+    /// `QoS` of message, of `SMALLINT` type.
+    ///
+    /// Default is `qos`.
+    #[serde(default = "SqlColumns::default_qos")]
+    qos: String,
+
+    /// Milliseconds since unix epoch when message is received, of `BIGINT` type.
+    ///
+    /// Default is `ts`.
+    #[serde(default = "SqlColumns::default_timestamp")]
+    timestamp: String,
+}
+
+impl SqlColumns {
+    #[must_use]
+    pub fn default_topic() -> String {
+        "topic".to_string()
+    }
+
+    #[must_use]
+    pub fn default_payload() -> String {
+        "payload".to_string()
+    }
+
+    #[must_use]
+    pub fn default_qos() -> String {
+        "qos".to_string()
+    }
+
+    #[must_use]
+    pub fn default_timestamp() -> String {
+        "ts".to_string()
+    }
+
+    #[must_use]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    #[must_use]
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    #[must_use]
+    pub fn qos(&self) -> &str {
+        &self.qos
+    }
+
+    #[must_use]
+    pub fn timestamp(&self) -> &str {
+        &self.timestamp
+    }
+
+    /// Number of fields written to table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names().count()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn names(&self) -> impl Iterator<Item = &String> {
+        [&self.topic, &self.payload, &self.qos, &self.timestamp]
+            .into_iter()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+impl Default for SqlColumns {
+    fn default() -> Self {
+        Self {
+            topic: Self::default_topic(),
+            payload: Self::default_payload(),
+            qos: Self::default_qos(),
+            timestamp: Self::default_timestamp(),
+        }
+    }
+}
+
+/// Insert messages matching a rule into `PgSQL` table.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SqlOutput {
+    /// Connection to `PgSQL` server, `host`, `port`, `pool_size` and so on.
+    #[serde(flatten)]
+    connection: PgSQLConnConfig,
+
+    /// Table where messages are inserted to.
+    ///
+    /// Default is `mqtt_messages`.
+    #[serde(default = "SqlOutput::default_table")]
+    table: String,
+
+    #[serde(default = "SqlColumns::default")]
+    columns: SqlColumns,
+
+    /// Maximum number of messages inserted in one statement.
+    ///
+    /// Default is 100.
+    #[serde(default = "SqlOutput::default_batch_size")]
+    batch_size: usize,
+
+    /// Pending messages are inserted after this interval in milliseconds,
+    /// even if batch is not full.
+    ///
+    /// Default is 1000.
+    #[serde(default = "SqlOutput::default_flush_interval")]
+    flush_interval: u64,
+
+    /// Maximum number of messages waiting to be inserted.
+    ///
+    /// New messages are dropped while queue is full, so that a slow output does not
+    /// hold back messages of other rules and clients.
+    ///
+    /// Default is 1024.
+    #[serde(default = "SqlOutput::default_queue_size")]
+    queue_size: usize,
+}
+
+impl SqlOutput {
+    #[must_use]
+    pub fn default_table() -> String {
+        "mqtt_messages".to_string()
+    }
+
+    #[must_use]
+    pub const fn default_batch_size() -> usize {
+        100
+    }
+
+    #[must_use]
+    pub const fn default_flush_interval() -> u64 {
+        1000
+    }
+
+    #[must_use]
+    pub const fn default_queue_size() -> usize {
+        1024
+    }
+
+    #[must_use]
+    pub const fn connection(&self) -> &PgSQLConnConfig {
+        &self.connection
+    }
+
+    #[must_use]
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    #[must_use]
+    pub const fn columns(&self) -> &SqlColumns {
+        &self.columns
+    }
+
+    #[must_use]
+    pub const fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    #[must_use]
+    pub const fn flush_interval(&self) -> Duration {
+        Duration::from_millis(self.flush_interval)
+    }
+
+    #[must_use]
+    pub const fn queue_size(&self) -> usize {
+        self.queue_size
+    }
+
+    /// Validate table and column names, and batch options.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if names are not valid sql identifiers, no column is set,
+    /// or `batch_size` or `queue_size` is out of range.
+    pub fn validate(&self) -> Result<(), Error> {
+        for name in std::iter::once(&self.table).chain(self.columns.names()) {
+            if !is_identifier(name) {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("Invalid table or column name in sql output: {name:?}"),
+                ));
+            }
+        }
+        if self.columns.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "No column is set in sql output",
+            ));
+        }
+        let max_batch_size = MAX_PARAMETERS / self.columns.len();
+        if self.batch_size == 0 || self.batch_size > max_batch_size {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "`batch_size` of sql output must be in range 1..={max_batch_size}, got {}",
+                    self.batch_size
+                ),
+            ));
+        }
+        if self.queue_size == 0 {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "`queue_size` of sql output must be greater than 0",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -6,17 +6,50 @@ use serde::Deserialize;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use crate::error::Error;
+#[cfg(feature = "redis_conn")]
+use crate::connectors::redis_conn::RedisConnConfig;
+use crate::error::{Error, ErrorKind};
+
+/// Where messages published by clients are written to.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MessageStoreType {
+    /// Messages are not stored.
+    #[default]
+    #[serde(alias = "none")]
+    None,
+
+    /// Keep recent messages in memory, they are lost when server stops.
+    #[serde(alias = "memory")]
+    Memory,
+
+    /// Append messages to redis streams, requires `redis_conn` feature.
+    #[serde(alias = "redis")]
+    Redis,
+}
+
+/// What dispatcher does when queue of messages waiting to be stored is full.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MessageQueuePolicy {
+    /// Drop new messages, they are still delivered to subscribers.
+    #[default]
+    #[serde(alias = "drop")]
+    Drop,
+
+    /// Wait until message store catches up, this slows down delivery of all messages.
+    #[serde(alias = "block")]
+    Block,
+}
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct Storage {
     /// Save persistent message data to disk.
     ///
-    /// This saves information about all messages, including subscriptions, currently in-flight messages
-    /// and retained messages.
+    /// State of disconnected persistent sessions, including their subscriptions,
+    /// in-flight messages and queued messages, is saved to `db_path` when hebo exits,
+    /// and restored on next start.
     ///
-    /// Default is true.
+    /// Default is false.
     #[serde(default = "Storage::default_persistence")]
     persistence: bool,
 
@@ -43,12 +76,87 @@ pub struct Storage {
     /// Default is None.
     #[serde(default = "Storage::default_auto_save_on_change")]
     auto_save_on_change: Option<u64>,
+
+    /// Append every qos=1 and qos=2 message published by clients to this journal file.
+    ///
+    /// Messages are written in batches and synced to disk, they are acked to clients
+    /// only after their batch is written.
+    ///
+    /// Default is None, journal is disabled.
+    #[serde(default = "Storage::default_journal_path")]
+    journal_path: Option<PathBuf>,
+
+    /// Maximum number of messages written to journal in one batch.
+    ///
+    /// Default is 128.
+    #[serde(default = "Storage::default_batch_size")]
+    batch_size: usize,
+
+    /// Maximum time in milliseconds to wait for more messages before writing a batch
+    /// to journal.
+    ///
+    /// Set to 0 to write messages as soon as they arrive.
+    ///
+    /// Default is 5.
+    #[serde(default = "Storage::default_batch_window")]
+    batch_window: u64,
+
+    /// Write every message published by clients, with its topic, payload, `QoS`
+    /// and timestamp, to this store.
+    ///
+    /// Available values are `none`, `memory` and `redis`.
+    ///
+    /// Default is `none`.
+    #[serde(default = "Storage::default_message_store")]
+    message_store: MessageStoreType,
+
+    /// Maximum number of messages kept for each topic in message store.
+    ///
+    /// Older messages are removed when this limit is reached. Set to 0 to disable
+    /// this limitation.
+    ///
+    /// Default is 1000.
+    #[serde(default = "Storage::default_max_stored_messages")]
+    max_stored_messages: usize,
+
+    /// Maximum number of messages of all topics kept in `memory` message store.
+    ///
+    /// The oldest messages of any topic are removed when this limit is reached.
+    /// Set to 0 to disable this limitation.
+    ///
+    /// Default is 100000.
+    #[serde(default = "Storage::default_max_total_stored_messages")]
+    max_total_stored_messages: usize,
+
+    /// Maximum number of messages waiting to be written to message store.
+    ///
+    /// Default is 1024.
+    #[serde(default = "Storage::default_message_queue_size")]
+    message_queue_size: usize,
+
+    /// What to do with new messages when queue of message store is full.
+    ///
+    /// Default is `drop`.
+    #[serde(default = "Storage::default_message_queue_policy")]
+    message_queue_policy: MessageQueuePolicy,
+
+    /// Connection to redis server used by `redis` message store.
+    #[cfg(feature = "redis_conn")]
+    #[serde(default = "Storage::default_redis")]
+    redis: RedisConnConfig,
+
+    /// Prefix of redis stream keys, messages of each topic are appended to
+    /// stream `{redis_key_prefix}{topic}`.
+    ///
+    /// Default is `hebo:messages:`.
+    #[serde(default = "Storage::default_redis_key_prefix")]
+    redis_key_prefix: String,
 }
 
 impl Storage {
     #[must_use]
     pub const fn default_persistence() -> bool {
-        true
+        false
     }
 
     #[must_use]
@@ -66,6 +174,57 @@ impl Storage {
         None
     }
 
+    #[must_use]
+    pub const fn default_journal_path() -> Option<PathBuf> {
+        None
+    }
+
+    #[must_use]
+    pub const fn default_batch_size() -> usize {
+        128
+    }
+
+    #[must_use]
+    pub const fn default_batch_window() -> u64 {
+        5
+    }
+
+    #[must_use]
+    pub const fn default_message_store() -> MessageStoreType {
+        MessageStoreType::None
+    }
+
+    #[must_use]
+    pub const fn default_max_stored_messages() -> usize {
+        1000
+    }
+
+    #[must_use]
+    pub const fn default_max_total_stored_messages() -> usize {
+        100_000
+    }
+
+    #[must_use]
+    pub const fn default_message_queue_size() -> usize {
+        1024
+    }
+
+    #[must_use]
+    pub const fn default_message_queue_policy() -> MessageQueuePolicy {
+        MessageQueuePolicy::Drop
+    }
+
+    #[cfg(feature = "redis_conn")]
+    #[must_use]
+    pub fn default_redis() -> RedisConnConfig {
+        RedisConnConfig::default()
+    }
+
+    #[must_use]
+    pub fn default_redis_key_prefix() -> String {
+        "hebo:messages:".to_string()
+    }
+
     #[must_use]
     pub const fn persistence(&self) -> bool {
         self.persistence
@@ -86,13 +245,98 @@ impl Storage {
         self.auto_save_on_change.map(Duration::from_secs)
     }
 
+    #[must_use]
+    pub fn journal_path(&self) -> Option<&Path> {
+        self.journal_path.as_deref()
+    }
+
+    #[must_use]
+    pub const fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    #[must_use]
+    pub const fn batch_window(&self) -> Duration {
+        Duration::from_millis(self.batch_window)
+    }
+
+    #[must_use]
+    pub const fn message_store(&self) -> MessageStoreType {
+        self.message_store
+    }
+
+    #[must_use]
+    pub const fn max_stored_messages(&self) -> usize {
+        self.max_stored_messages
+    }
+
+    #[must_use]
+    pub const fn max_total_stored_messages(&self) -> usize {
+        self.max_total_stored_messages
+    }
+
+    #[must_use]
+    pub const fn message_queue_size(&self) -> usize {
+        self.message_queue_size
+    }
+
+    #[must_use]
+    pub const fn message_queue_policy(&self) -> MessageQueuePolicy {
+        self.message_queue_policy
+    }
+
+    #[cfg(feature = "redis_conn")]
+    #[must_use]
+    pub const fn redis(&self) -> &RedisConnConfig {
+        &self.redis
+    }
+
+    #[must_use]
+    pub fn redis_key_prefix(&self) -> &str {
+        &self.redis_key_prefix
+    }
+
+    pub fn set_message_store(&mut self, message_store: MessageStoreType) -> &mut Self {
+        self.message_store = message_store;
+        self
+    }
+
+    pub fn set_persistence(&mut self, persistence: bool) -> &mut Self {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn set_db_path(&mut self, db_path: PathBuf) -> &mut Self {
+        self.db_path = db_path;
+        self
+    }
+
     /// Validate storage config.
     ///
     /// # Errors
     ///
-    /// Does nothing.
-    pub const fn validate(&self) -> Result<(), Error> {
+    /// Returns error if `batch_size` or `message_queue_size` is 0, or `redis` message store
+    /// is used without `redis_conn` feature.
+    pub fn validate(&self) -> Result<(), Error> {
         // TODO(Shaohua): check storage file permission
+        if self.batch_size == 0 {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "batch_size must be greater than 0",
+            ));
+        }
+        if self.message_queue_size == 0 {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "message_queue_size must be greater than 0",
+            ));
+        }
+        if self.message_store == MessageStoreType::Redis && !cfg!(feature = "redis_conn") {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "redis message_store requires redis_conn feature",
+            ));
+        }
         Ok(())
     }
 }
@@ -104,6 +348,17 @@ impl Default for Storage {
             db_path: Self::default_db_path(),
             auto_save_interval: Self::default_auto_save_interval(),
             auto_save_on_change: Self::default_auto_save_on_change(),
+            journal_path: Self::default_journal_path(),
+            batch_size: Self::default_batch_size(),
+            batch_window: Self::default_batch_window(),
+            message_store: Self::default_message_store(),
+            max_stored_messages: Self::default_max_stored_messages(),
+            max_total_stored_messages: Self::default_max_total_stored_messages(),
+            message_queue_size: Self::default_message_queue_size(),
+            message_queue_policy: Self::default_message_queue_policy(),
+            #[cfg(feature = "redis_conn")]
+            redis: Self::default_redis(),
+            redis_key_prefix: Self::default_redis_key_prefix(),
         }
     }
 }
@@ -0,0 +1,161 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use http::header::{HeaderName, HeaderValue};
+use http::Uri;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::error::{Error, ErrorKind};
+
+/// Post messages matching a rule to http endpoint as json.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookOutput {
+    /// Url of http endpoint, like `https://example.com/mqtt/messages`.
+    url: String,
+
+    /// Extra headers of requests, like `Authorization`.
+    ///
+    /// Default is empty.
+    #[serde(default = "BTreeMap::new")]
+    headers: BTreeMap<String, String>,
+
+    /// Timeout of each request in seconds.
+    ///
+    /// Default is 5s.
+    #[serde(default = "WebhookOutput::default_timeout")]
+    timeout: u32,
+
+    /// Retry times of failed requests, message is dropped after the last retry.
+    ///
+    /// Requests failed with connection error, timeout, or non-2xx status code
+    /// are retried.
+    ///
+    /// Default is 3.
+    #[serde(default = "WebhookOutput::default_max_retries")]
+    max_retries: u32,
+
+    /// Interval in milliseconds before the first retry, doubled for each retry.
+    ///
+    /// Default is 1000.
+    #[serde(default = "WebhookOutput::default_retry_interval")]
+    retry_interval: u64,
+
+    /// Maximum number of requests sent at the same time, including ones waiting to retry.
+    ///
+    /// Default is 4.
+    #[serde(default = "WebhookOutput::default_max_concurrency")]
+    max_concurrency: usize,
+
+    /// Maximum number of messages waiting to be posted.
+    ///
+    /// New messages are dropped while queue is full, so that a slow output does not
+    /// hold back messages of other rules and clients.
+    ///
+    /// Default is 1024.
+    #[serde(default = "WebhookOutput::default_queue_size")]
+    queue_size: usize,
+}
+
+impl WebhookOutput {
+    #[must_use]
+    pub const fn default_timeout() -> u32 {
+        5
+    }
+
+    #[must_use]
+    pub const fn default_max_retries() -> u32 {
+        3
+    }
+
+    #[must_use]
+    pub const fn default_retry_interval() -> u64 {
+        1000
+    }
+
+    #[must_use]
+    pub const fn default_max_concurrency() -> usize {
+        4
+    }
+
+    #[must_use]
+    pub const fn default_queue_size() -> usize {
+        1024
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    #[must_use]
+    pub const fn headers(&self) -> &BTreeMap<String, String> {
+        &self.headers
+    }
+
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(u64::from(self.timeout))
+    }
+
+    #[must_use]
+    pub const fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    #[must_use]
+    pub const fn retry_interval(&self) -> Duration {
+        Duration::from_millis(self.retry_interval)
+    }
+
+    #[must_use]
+    pub const fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    #[must_use]
+    pub const fn queue_size(&self) -> usize {
+        self.queue_size
+    }
+
+    /// Validate url and headers, and queue options.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if url is not a http or https url, headers are invalid,
+    /// or `max_concurrency` or `queue_size` is 0.
+    pub fn validate(&self) -> Result<(), Error> {
+        let is_valid_url = self.url.parse::<Uri>().map_or(false, |uri| {
+            uri.host().is_some() && matches!(uri.scheme_str(), Some("http" | "https"))
+        });
+        if !is_valid_url {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid `url` of webhook output: {:?}", self.url),
+            ));
+        }
+        for (name, value) in &self.headers {
+            if HeaderName::try_from(name).is_err() || HeaderValue::try_from(value).is_err() {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("Invalid header of webhook output: {name:?}"),
+                ));
+            }
+        }
+        if self.max_concurrency == 0 {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "`max_concurrency` of webhook output must be greater than 0",
+            ));
+        }
+        if self.queue_size == 0 {
+            return Err(Error::new(
+                ErrorKind::ConfigError,
+                "`queue_size` of webhook output must be greater than 0",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -3,11 +3,14 @@
 // in the LICENSE file.
 
 use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
+use tokio::sync::Mutex;
 use tokio_postgres::config::{Config, SslMode};
-use tokio_postgres::NoTls;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{NoTls, Row};
 
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 
 /// Configuration for connection to pgsql server.
 #[allow(clippy::module_name_repetitions)]
@@ -180,6 +183,100 @@ impl PgSQLConn {
     }
 }
 
+/// A fixed size pool of connections to pgsql server.
+///
+/// Connections are opened on first use and reused by later queries.
+/// Closed connections are opened again on next query.
+pub struct PgSQLPool {
+    config: PgSQLConnConfig,
+    conns: Vec<Mutex<Option<PgSQLConn>>>,
+    next_conn: AtomicUsize,
+}
+
+impl std::fmt::Debug for PgSQLPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgSQLPool")
+            .field("host", &self.config.host)
+            .field("port", &self.config.port)
+            .field("database", &self.config.database)
+            .field("pool_size", &self.conns.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl PgSQLPool {
+    #[must_use]
+    pub fn new(config: &PgSQLConnConfig) -> Self {
+        let conns = (0..config.pool_size.max(1))
+            .map(|_| Mutex::new(None))
+            .collect();
+        Self {
+            config: config.clone(),
+            conns,
+            next_conn: AtomicUsize::new(0),
+        }
+    }
+
+    /// Execute a statement which returns zero or one row, with connections in pool.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to connect to db, query timeout, or query returns
+    /// more than one row.
+    // Connection is locked until query finishes.
+    #[allow(clippy::significant_drop_tightening)]
+    pub async fn query_opt(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, Error> {
+        let index = self.next_conn.fetch_add(1, Ordering::Relaxed) % self.conns.len();
+        let mut slot = self.conns[index].lock().await;
+        let conn = match slot.take().filter(|conn| !conn.client.is_closed()) {
+            Some(conn) => conn,
+            None => PgSQLConn::connect(&self.config).await?,
+        };
+        let conn = slot.insert(conn);
+
+        tokio::time::timeout(
+            self.config.query_timeout(),
+            conn.get_conn().query_opt(statement, params),
+        )
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::PgSQLError, "pgsql: Query timeout"))?
+        .map_err(Into::into)
+    }
+
+    /// Execute a statement with connections in pool, returns number of rows modified.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to connect to db, or query failed or timeout.
+    // Connection is locked until query finishes.
+    #[allow(clippy::significant_drop_tightening)]
+    pub async fn execute(
+        &self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, Error> {
+        let index = self.next_conn.fetch_add(1, Ordering::Relaxed) % self.conns.len();
+        let mut slot = self.conns[index].lock().await;
+        let conn = match slot.take().filter(|conn| !conn.client.is_closed()) {
+            Some(conn) => conn,
+            None => PgSQLConn::connect(&self.config).await?,
+        };
+        let conn = slot.insert(conn);
+
+        tokio::time::timeout(
+            self.config.query_timeout(),
+            conn.get_conn().execute(statement, params),
+        )
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::PgSQLError, "pgsql: Query timeout"))?
+        .map_err(Into::into)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -201,7 +298,7 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
+    #[ignore = "requires a running pgsql server"]
     fn test_pgsql_conn() {
         let config = PgSQLConnConfig {
             password: "hebo-password".to_string(),
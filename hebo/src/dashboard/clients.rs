@@ -0,0 +1,100 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use tokio::sync::oneshot;
+use warp::http::StatusCode;
+use warp::reply::{Reply, Response};
+
+use super::types::DashboardSender;
+use crate::commands::DashboardToServerContexCmd;
+
+/// List connected clients of all listeners.
+pub async fn list_clients(sender: DashboardSender) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::list_clients()");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::ListClients(resp_tx);
+    let reply = send_cmd(&sender, cmd, resp_rx)
+        .await
+        .map_or_else(internal_error, |clients| {
+            warp::reply::json(&clients).into_response()
+        });
+    Ok(reply)
+}
+
+/// Get connected client with `client_id`.
+pub async fn get_client(
+    client_id: String,
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::get_client({client_id})");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::GetClient(client_id, resp_tx);
+    let reply = match send_cmd(&sender, cmd, resp_rx).await {
+        Some(Some(client)) => warp::reply::json(&client).into_response(),
+        Some(None) => {
+            warp::reply::with_status("Client is offline", StatusCode::NOT_FOUND).into_response()
+        }
+        None => internal_error(),
+    };
+    Ok(reply)
+}
+
+/// Get topic filters subscribed by client with `client_id`.
+pub async fn get_client_subscriptions(
+    client_id: String,
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::get_client_subscriptions({client_id})");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::GetClientSubscriptions(client_id, resp_tx);
+    let reply = match send_cmd(&sender, cmd, resp_rx).await {
+        Some(Some(subscriptions)) => warp::reply::json(&subscriptions).into_response(),
+        Some(None) => {
+            warp::reply::with_status("Client is offline", StatusCode::NOT_FOUND).into_response()
+        }
+        None => internal_error(),
+    };
+    Ok(reply)
+}
+
+/// Disconnect client with `client_id`.
+pub async fn disconnect_client(
+    client_id: String,
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::disconnect_client({client_id})");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::DisconnectClient(client_id, resp_tx);
+    let reply = match send_cmd(&sender, cmd, resp_rx).await {
+        Some(true) => warp::reply::with_status("OK", StatusCode::OK).into_response(),
+        Some(false) => {
+            warp::reply::with_status("Client is offline", StatusCode::NOT_FOUND).into_response()
+        }
+        None => internal_error(),
+    };
+    Ok(reply)
+}
+
+async fn send_cmd<T>(
+    sender: &DashboardSender,
+    cmd: DashboardToServerContexCmd,
+    resp_rx: oneshot::Receiver<T>,
+) -> Option<T> {
+    if let Err(err) = sender.send(cmd).await {
+        log::error!("Failed to send cmd to server ctx, err: {err:?}");
+        return None;
+    }
+    match resp_rx.await {
+        Ok(resp) => Some(resp),
+        Err(err) => {
+            log::info!("clients response err: {err:?}");
+            None
+        }
+    }
+}
+
+fn internal_error() -> Response {
+    warp::reply::with_status("Internal server error", StatusCode::INTERNAL_SERVER_ERROR)
+        .into_response()
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use tokio::sync::oneshot;
+use warp::http::StatusCode;
+
+use super::types::{DashboardSender, DrainQuery};
+use crate::commands::DashboardToServerContexCmd;
+use crate::types::{ListenerId, Redirect};
+
+/// Drain session of client, and redirect it to another server.
+pub async fn drain_client(
+    client_id: String,
+    query: DrainQuery,
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::drain_client({client_id})");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::DrainClient(client_id, query.into(), resp_tx);
+    Ok(send_drain_cmd(&sender, cmd, resp_rx, "Client is offline").await)
+}
+
+/// Drain all sessions of listener.
+pub async fn drain_listener(
+    listener_id: ListenerId,
+    query: DrainQuery,
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Dashboard::drain_listener({listener_id})");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::DrainListener(listener_id, query.into(), resp_tx);
+    Ok(send_drain_cmd(&sender, cmd, resp_rx, "Listener not found").await)
+}
+
+async fn send_drain_cmd(
+    sender: &DashboardSender,
+    cmd: DashboardToServerContexCmd,
+    resp_rx: oneshot::Receiver<bool>,
+    not_found: &str,
+) -> warp::reply::WithStatus<String> {
+    if let Err(err) = sender.send(cmd).await {
+        log::error!("Failed to send cmd to server ctx, err: {err:?}");
+    } else {
+        match resp_rx.await {
+            Ok(true) => return warp::reply::with_status("OK".to_string(), StatusCode::OK),
+            Ok(false) => {
+                return warp::reply::with_status(not_found.to_string(), StatusCode::NOT_FOUND);
+            }
+            Err(err) => {
+                log::info!("drain response err: {err:?}");
+            }
+        }
+    }
+
+    warp::reply::with_status(
+        "Internal server error".to_string(),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    )
+}
+
+impl From<DrainQuery> for Redirect {
+    fn from(query: DrainQuery) -> Self {
+        Self::new(query.server_reference, query.permanent)
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Resource limits of dashboard connections and requests.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, Instant, Sleep};
+use warp::http::StatusCode;
+use warp::reject::Reject;
+use warp::{Filter, Rejection, Reply};
+
+/// Request body is larger than `max_body_size` in config.
+#[derive(Debug)]
+pub struct PayloadTooLarge;
+
+impl Reject for PayloadTooLarge {}
+
+/// Reject requests with body larger than `max_size` bytes.
+///
+/// Unlike `warp::body::content_length_limit()`, requests without `content-length`
+/// header are accepted, as admin apis which take no body are called without it.
+pub fn body_limit(max_size: u64) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and_then(move |length: Option<u64>| async move {
+            match length {
+                Some(length) if length > max_size => Err(warp::reject::custom(PayloadTooLarge)),
+                _ => Ok(()),
+            }
+        })
+        .untuple_one()
+}
+
+/// Convert rejection of resource limits to response, others are handled by warp.
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    if rejection.find::<PayloadTooLarge>().is_some() {
+        Ok(warp::reply::with_status(
+            "Payload too large",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ))
+    } else {
+        Err(rejection)
+    }
+}
+
+/// Tcp stream which is closed if no data is transferred within `timeout`.
+#[derive(Debug)]
+pub struct IdleTimeoutStream {
+    stream: TcpStream,
+    timeout: Option<Duration>,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl IdleTimeoutStream {
+    #[must_use]
+    pub fn new(stream: TcpStream, timeout: Option<Duration>) -> Self {
+        let deadline = Box::pin(sleep(timeout.unwrap_or_default()));
+        Self {
+            stream,
+            timeout,
+            deadline,
+        }
+    }
+
+    fn reset_deadline(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+}
+
+impl AsyncRead for IdleTimeoutStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.stream).poll_read(cx, buf) {
+            Poll::Ready(ret) => {
+                this.reset_deadline();
+                Poll::Ready(ret)
+            }
+            Poll::Pending => {
+                if this.timeout.is_some() && this.deadline.as_mut().poll(cx).is_ready() {
+                    Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "Connection is idle",
+                    )))
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for IdleTimeoutStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let ret = Pin::new(&mut this.stream).poll_write(cx, buf);
+        if ret.is_ready() {
+            this.reset_deadline();
+        }
+        ret
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Accept connections from `listener`, close them after `idle_timeout`.
+pub async fn serve<F>(listener: tokio::net::TcpListener, routes: F, idle_timeout: Option<Duration>)
+where
+    F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let incoming = futures::stream::unfold(listener, move |listener| async move {
+        let stream = listener
+            .accept()
+            .await
+            .map(|(stream, _address)| IdleTimeoutStream::new(stream, idle_timeout));
+        Some((stream, listener))
+    });
+    warp::serve(routes).run_incoming(incoming).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    use super::{body_limit, handle_rejection, serve};
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let routes = warp::post()
+            .and(body_limit(16))
+            .map(|| "OK")
+            .recover(handle_rejection);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .body(vec![b'a'; 17])
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = warp::test::request()
+            .method("POST")
+            .body(vec![b'a'; 16])
+            .reply(&routes)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // No content-length header.
+        let resp = warp::test::request().method("POST").reply(&routes).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let routes = warp::get().map(|| "OK");
+        tokio::spawn(serve(listener, routes, Some(Duration::from_millis(500))));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 1024];
+        let n_read = stream.read(&mut buf).await.unwrap();
+        assert!(buf[..n_read].starts_with(b"HTTP/1.1 200 OK"));
+
+        // Keep-alive connection is closed by server.
+        let n_read = tokio::time::timeout(Duration::from_secs(3), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n_read, 0);
+    }
+}
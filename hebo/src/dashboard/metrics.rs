@@ -2,12 +2,17 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
+use std::fmt::Write as _;
 use tokio::sync::oneshot;
 use warp::http::StatusCode;
 
 use super::types::DashboardSender;
+use crate::cache_types::SystemMetrics;
 use crate::commands::DashboardToServerContexCmd;
 
+/// Content type of Prometheus text exposition format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
 /// metrics api
 pub async fn get_uptime(sender: DashboardSender) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("Dashboard::get_uptime()");
@@ -36,3 +41,169 @@ pub async fn get_uptime(sender: DashboardSender) -> Result<impl warp::Reply, war
         StatusCode::INTERNAL_SERVER_ERROR,
     ))
 }
+
+/// Broker counters in Prometheus text exposition format.
+pub async fn get_prometheus_metrics(
+    sender: DashboardSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::debug!("Dashboard::get_prometheus_metrics()");
+    let (resp_tx, resp_rx) = oneshot::channel();
+    if let Err(err) = sender
+        .send(DashboardToServerContexCmd::MetricsGetSystem(resp_tx))
+        .await
+    {
+        log::error!("Failed to send cmd to server ctx, err: {err:?}");
+    } else {
+        match resp_rx.await {
+            Ok(system) => {
+                let reply = warp::reply::with_status(to_prometheus(&system), StatusCode::OK);
+                return Ok(warp::reply::with_header(
+                    reply,
+                    "content-type",
+                    PROMETHEUS_CONTENT_TYPE,
+                ));
+            }
+            Err(err) => {
+                log::info!("metrics response err: {err:?}");
+            }
+        }
+    }
+
+    let reply = warp::reply::with_status(
+        "Internal server error".to_string(),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    );
+    Ok(warp::reply::with_header(
+        reply,
+        "content-type",
+        PROMETHEUS_CONTENT_TYPE,
+    ))
+}
+
+fn to_prometheus(system: &SystemMetrics) -> String {
+    let metrics = [
+        (
+            "hebo_clients_connected",
+            "gauge",
+            "Number of connected clients.",
+            system.sessions,
+        ),
+        (
+            "hebo_subscriptions",
+            "gauge",
+            "Number of active subscriptions.",
+            system.subscriptions,
+        ),
+        (
+            "hebo_retained_messages",
+            "gauge",
+            "Number of retained messages.",
+            system.retained_messages,
+        ),
+        (
+            "hebo_messages_received_total",
+            "counter",
+            "Publish messages received from clients.",
+            system.publish_messages_received,
+        ),
+        (
+            "hebo_messages_sent_total",
+            "counter",
+            "Publish messages sent to clients.",
+            system.publish_messages_sent,
+        ),
+        (
+            "hebo_bytes_received_total",
+            "counter",
+            "Bytes of publish messages received from clients.",
+            system.publish_bytes_received,
+        ),
+        (
+            "hebo_bytes_sent_total",
+            "counter",
+            "Bytes of publish messages sent to clients.",
+            system.publish_bytes_sent,
+        ),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(body, "# HELP {name} {help}");
+        let _ = writeln!(body, "# TYPE {name} {kind}");
+        let _ = writeln!(body, "{name} {value}");
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::to_prometheus;
+    use crate::cache_types::SystemMetrics;
+
+    fn is_metric_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+    }
+
+    /// Parse text exposition format, returns samples and type of each metric family.
+    fn parse(body: &str) -> (HashMap<String, String>, HashMap<String, String>) {
+        let mut samples = HashMap::new();
+        let mut types = HashMap::new();
+        for line in body.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut parts = comment.splitn(3, ' ');
+                let keyword = parts.next().unwrap();
+                let name = parts.next().unwrap();
+                assert!(is_metric_name(name), "invalid name in: {line}");
+                if keyword == "TYPE" {
+                    let kind = parts.next().unwrap();
+                    assert!(["counter", "gauge"].contains(&kind), "invalid type: {line}");
+                    assert!(!samples.contains_key(name), "TYPE after sample: {line}");
+                    types.insert(name.to_string(), kind.to_string());
+                } else {
+                    assert_eq!(keyword, "HELP");
+                }
+            } else {
+                let (name, value) = line.split_once(' ').unwrap();
+                assert!(is_metric_name(name), "invalid name in: {line}");
+                assert!(value.parse::<f64>().is_ok(), "invalid value in: {line}");
+                assert!(samples
+                    .insert(name.to_string(), value.to_string())
+                    .is_none());
+            }
+        }
+        (samples, types)
+    }
+
+    #[test]
+    fn test_to_prometheus() {
+        let system = SystemMetrics {
+            sessions: 3,
+            subscriptions: 5,
+            retained_messages: 2,
+            publish_messages_received: 10,
+            publish_messages_sent: 20,
+            publish_bytes_received: 1000,
+            publish_bytes_sent: 2000,
+            ..SystemMetrics::default()
+        };
+        let body = to_prometheus(&system);
+        assert!(body.ends_with('\n'));
+        let (samples, types) = parse(&body);
+        assert_eq!(samples.len(), 7);
+        assert_eq!(samples["hebo_clients_connected"], "3");
+        assert_eq!(samples["hebo_subscriptions"], "5");
+        assert_eq!(samples["hebo_retained_messages"], "2");
+        assert_eq!(samples["hebo_messages_received_total"], "10");
+        assert_eq!(samples["hebo_messages_sent_total"], "20");
+        assert_eq!(samples["hebo_bytes_received_total"], "1000");
+        assert_eq!(samples["hebo_bytes_sent_total"], "2000");
+        assert_eq!(types["hebo_clients_connected"], "gauge");
+        assert_eq!(types["hebo_bytes_sent_total"], "counter");
+    }
+}
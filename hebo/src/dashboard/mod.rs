@@ -7,21 +7,31 @@
 //! Web ui part is located in `/dashboard`.
 
 use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
 use tokio::sync::mpsc::Sender;
 use warp::Filter;
 
 use crate::commands::DashboardToServerContexCmd;
 use crate::config;
 use crate::error::Error;
+use crate::types::ListenerId;
+use types::{DrainQuery, HistoryQuery};
 
+mod clients;
+mod drain;
 mod error_code;
+mod limit;
 mod metrics;
+mod topics;
 mod types;
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct DashboardApp {
     addr: SocketAddr,
+    idle_timeout: Option<Duration>,
+    max_body_size: u64,
 
     server_ctx_sender: Sender<DashboardToServerContexCmd>,
 }
@@ -39,6 +49,8 @@ impl DashboardApp {
         let addr = config.address().parse()?;
         Ok(Self {
             addr,
+            idle_timeout: config.idle_timeout(),
+            max_body_size: config.max_body_size(),
             server_ctx_sender,
         })
     }
@@ -47,15 +59,84 @@ impl DashboardApp {
         let sender = self.server_ctx_sender.clone();
         let sender_filter = warp::any().map(move || sender.clone());
 
-        let routes = warp::get()
+        let uptime = warp::get()
             .and(warp::path("api"))
             .and(warp::path("v1"))
             .and(warp::path("metrics"))
             .and(warp::path("uptime"))
             .and(warp::path::end())
-            .and(sender_filter)
+            .and(sender_filter.clone())
             .and_then(metrics::get_uptime);
 
-        warp::serve(routes).run(self.addr).await;
+        let prometheus = warp::get()
+            .and(warp::path("metrics"))
+            .and(warp::path::end())
+            .and(sender_filter.clone())
+            .and_then(metrics::get_prometheus_metrics);
+
+        let list_clients = warp::get()
+            .and(warp::path!("api" / "v1" / "clients"))
+            .and(sender_filter.clone())
+            .and_then(clients::list_clients);
+
+        let get_client = warp::get()
+            .and(warp::path!("api" / "v1" / "clients" / String))
+            .and(sender_filter.clone())
+            .and_then(clients::get_client);
+
+        let get_client_subscriptions = warp::get()
+            .and(warp::path!(
+                "api" / "v1" / "clients" / String / "subscriptions"
+            ))
+            .and(sender_filter.clone())
+            .and_then(clients::get_client_subscriptions);
+
+        let disconnect_client = warp::post()
+            .and(limit::body_limit(self.max_body_size))
+            .and(warp::path!(
+                "api" / "v1" / "clients" / String / "disconnect"
+            ))
+            .and(sender_filter.clone())
+            .and_then(clients::disconnect_client);
+
+        let drain_client = warp::post()
+            .and(limit::body_limit(self.max_body_size))
+            .and(warp::path!("api" / "v1" / "clients" / String / "drain"))
+            .and(warp::query::<DrainQuery>())
+            .and(sender_filter.clone())
+            .and_then(drain::drain_client);
+
+        let drain_listener = warp::post()
+            .and(limit::body_limit(self.max_body_size))
+            .and(warp::path!(
+                "api" / "v1" / "listeners" / ListenerId / "drain"
+            ))
+            .and(warp::query::<DrainQuery>())
+            .and(sender_filter.clone())
+            .and_then(drain::drain_listener);
+
+        // Topic filter may contain `/`, so it is the path tail before `/history`.
+        let get_topic_history = warp::get()
+            .and(warp::path!("api" / "v1" / "topics" / ..))
+            .and(warp::path::tail())
+            .and(warp::query::<HistoryQuery>())
+            .and(sender_filter)
+            .and_then(topics::get_topic_history);
+
+        let routes = uptime
+            .or(prometheus)
+            .or(list_clients)
+            .or(get_client)
+            .or(get_client_subscriptions)
+            .or(disconnect_client)
+            .or(drain_client)
+            .or(drain_listener)
+            .or(get_topic_history)
+            .recover(limit::handle_rejection);
+
+        match TcpListener::bind(self.addr).await {
+            Ok(listener) => limit::serve(listener, routes, self.idle_timeout).await,
+            Err(err) => log::error!("dashboard: Failed to bind {}, err: {:?}", self.addr, err),
+        }
     }
 }
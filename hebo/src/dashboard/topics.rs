@@ -0,0 +1,90 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use codec::topic::validate_sub_topic;
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use tokio::sync::oneshot;
+use warp::http::StatusCode;
+use warp::path::Tail;
+use warp::reply::{Reply, Response};
+
+use super::types::{DashboardSender, HistoryQuery};
+use crate::commands::DashboardToServerContexCmd;
+
+/// Suffix of topic history api path, after topic filter.
+const HISTORY_SUFFIX: &str = "/history";
+
+/// Get the last messages of topics matching topic filter from message store.
+///
+/// Topic filter is percent-encoded in path, for example `/api/v1/topics/sensors/%23/history`.
+pub async fn get_topic_history(
+    tail: Tail,
+    query: HistoryQuery,
+    sender: DashboardSender,
+) -> Result<Response, warp::Rejection> {
+    let Some(topic_filter) = parse_topic_filter(tail.as_str()) else {
+        return Err(warp::reject::not_found());
+    };
+    log::info!("Dashboard::get_topic_history({topic_filter})");
+    if validate_sub_topic(&topic_filter).is_err() {
+        return Ok(
+            warp::reply::with_status("Invalid topic filter", StatusCode::BAD_REQUEST)
+                .into_response(),
+        );
+    }
+
+    let (resp_tx, resp_rx) = oneshot::channel();
+    let cmd = DashboardToServerContexCmd::GetTopicHistory(topic_filter, query.limit, resp_tx);
+    if let Err(err) = sender.send(cmd).await {
+        log::error!("Failed to send cmd to server ctx, err: {err:?}");
+        return Ok(internal_error());
+    }
+    let reply = match resp_rx.await {
+        Ok(Some(history)) => warp::reply::json(&history).into_response(),
+        Ok(None) => {
+            warp::reply::with_status("Message history is not available", StatusCode::NOT_FOUND)
+                .into_response()
+        }
+        Err(err) => {
+            log::info!("topic history response err: {err:?}");
+            internal_error()
+        }
+    };
+    Ok(reply)
+}
+
+/// Get percent-decoded topic filter in path tail `{topic_filter}/history`.
+fn parse_topic_filter(tail: &str) -> Option<String> {
+    let topic_filter = tail.strip_suffix(HISTORY_SUFFIX)?;
+    percent_decode_str(topic_filter)
+        .decode_utf8()
+        .ok()
+        .map(Cow::into_owned)
+}
+
+fn internal_error() -> Response {
+    warp::reply::with_status("Internal server error", StatusCode::INTERNAL_SERVER_ERROR)
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_topic_filter;
+
+    #[test]
+    fn test_parse_topic_filter() {
+        assert_eq!(
+            parse_topic_filter("sensors/+/temp/history").as_deref(),
+            Some("sensors/+/temp")
+        );
+        assert_eq!(
+            parse_topic_filter("sensors/%23/history").as_deref(),
+            Some("sensors/#")
+        );
+        assert_eq!(parse_topic_filter("a%20b/history").as_deref(), Some("a b"));
+        assert_eq!(parse_topic_filter("sensors/temp"), None);
+        assert_eq!(parse_topic_filter("history"), None);
+    }
+}
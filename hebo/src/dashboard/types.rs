@@ -3,6 +3,34 @@
 // in the LICENSE file.
 
 use crate::commands::DashboardToServerContexCmd;
+use serde::Deserialize;
 use tokio::sync::mpsc::Sender;
 
 pub type DashboardSender = Sender<DashboardToServerContexCmd>;
+
+/// Query parameters of drain api.
+#[derive(Debug, Default, Deserialize)]
+pub struct DrainQuery {
+    /// Address of server which clients are redirected to.
+    #[serde(default)]
+    pub server_reference: Option<String>,
+
+    /// Server is moved permanently, or just use another server temporarily.
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+/// Query parameters of topic history api.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Maximum number of messages returned.
+    #[serde(default = "HistoryQuery::default_limit")]
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    #[must_use]
+    pub const fn default_limit() -> usize {
+        100
+    }
+}
@@ -6,16 +6,62 @@
 
 #![allow(clippy::unused_async)]
 
-use codec::{v3, v5};
+use codec::{v3, v5, QoS};
+use tokio::sync::mpsc::error::TrySendError;
 
 use super::Dispatcher;
-use crate::commands::BackendsToDispatcherCmd;
+use crate::commands::{BackendsToDispatcherCmd, DispatcherToBackendsCmd};
+use crate::config::MessageQueuePolicy;
+use crate::log::LogLimiter;
+use crate::types::PublishRecord;
+
+/// Messages are dropped on every publish while message store is slow, log them less frequently.
+static DROPPED_LOGS: LogLimiter = LogLimiter::new();
 
 impl Dispatcher {
     /// Send packet to backends.
-    pub(super) async fn backends_store_packet(&mut self, _: &v3::PublishPacket) {}
+    pub(super) async fn backends_store_packet(&self, packet: &v3::PublishPacket) {
+        self.backends_persist(packet.topic(), packet.message(), packet.qos())
+            .await;
+    }
+
+    pub(super) async fn backends_store_packet_v5(&self, packet: &v5::PublishPacket) {
+        self.backends_persist(packet.topic(), packet.message(), packet.qos())
+            .await;
+    }
 
-    pub(super) async fn backends_store_packet_v5(&mut self, _: &v5::PublishPacket) {}
+    /// Write message to message store of backends app, if it is enabled.
+    async fn backends_persist(&self, topic: &str, payload: &[u8], qos: QoS) {
+        let Some(policy) = self.message_queue_policy else {
+            return;
+        };
+        let cmd = DispatcherToBackendsCmd::Persist(PublishRecord::new(topic, payload, qos));
+        match policy {
+            MessageQueuePolicy::Drop => match self.backends_sender.try_send(cmd) {
+                Ok(()) => (),
+                Err(TrySendError::Full(_cmd)) => {
+                    if let Some(suppressed) = DROPPED_LOGS.check() {
+                        log::warn!(
+                            "dispatcher: Message store queue is full, drop message to {:?}{}",
+                            topic,
+                            suppressed
+                        );
+                    }
+                }
+                Err(TrySendError::Closed(_cmd)) => {
+                    log::error!("dispatcher: Failed to send message to backends, app is stopped");
+                }
+            },
+            MessageQueuePolicy::Block => {
+                if let Err(err) = self.backends_sender.send(cmd).await {
+                    log::error!(
+                        "dispatcher: Failed to send message to backends, err: {:?}",
+                        err
+                    );
+                }
+            }
+        }
+    }
 
-    pub(super) async fn handle_backends_cmd(&mut self, _: BackendsToDispatcherCmd) {}
+    pub(super) async fn handle_backends_cmd(&self, _: BackendsToDispatcherCmd) {}
 }
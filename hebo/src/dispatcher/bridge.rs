@@ -4,12 +4,65 @@
 
 //! Bridge app handlers
 
+use codec::{v3, v5};
+
 use super::Dispatcher;
-use crate::commands::BridgeToDispatcherCmd;
+use crate::bridge::is_match;
+use crate::commands::{BridgeToDispatcherCmd, DispatcherToBridgeCmd};
 
 impl Dispatcher {
-    #[allow(clippy::unused_async)]
     pub(super) async fn handle_bridge_cmd(&mut self, cmd: BridgeToDispatcherCmd) {
-        log::info!("cmd: {:?}", cmd);
+        match cmd {
+            BridgeToDispatcherCmd::Publish(packet) => {
+                // Messages from remote broker are delivered to local subscribers only,
+                // they are neither forwarded back to bridge nor stored as retained messages.
+                self.publish_packet_to_sub_trie(&packet).await;
+            }
+        }
+    }
+
+    fn is_bridged(&self, topic: &str) -> bool {
+        self.bridge_filters
+            .iter()
+            .any(|filter| is_match(filter, topic))
+    }
+
+    /// Forward local message to bridge app if it matches any of bridge topics.
+    pub(super) async fn bridge_publish(&self, packet: &v3::PublishPacket) {
+        if self.is_bridged(packet.topic()) {
+            self.send_to_bridge(packet.clone()).await;
+        }
+    }
+
+    pub(super) async fn bridge_publish_v5(&self, packet: &v5::PublishPacket) {
+        if !self.is_bridged(packet.topic()) {
+            return;
+        }
+        // Connections to remote brokers use v3 protocol.
+        match v3::PublishPacket::new(packet.topic(), packet.qos(), packet.message()) {
+            Ok(mut v3_packet) => {
+                v3_packet.set_retain(packet.retain());
+                self.send_to_bridge(v3_packet).await;
+            }
+            Err(err) => {
+                log::error!(
+                    "dispatcher: Failed to convert v5 publish packet, err: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    async fn send_to_bridge(&self, packet: v3::PublishPacket) {
+        if let Err(err) = self
+            .bridge_sender
+            .send(DispatcherToBridgeCmd::Publish(packet))
+            .await
+        {
+            log::error!(
+                "dispatcher: Failed to send publish to bridge, err: {:?}",
+                err
+            );
+        }
     }
 }
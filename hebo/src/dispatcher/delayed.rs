@@ -0,0 +1,251 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Hold messages published to `$delayed/{seconds}/{topic}` until their delay interval passes.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::Dispatcher;
+use crate::session::OutgoingPacket;
+use crate::types::ListenerId;
+
+/// Topic prefix of delayed messages.
+pub const DELAYED_PREFIX: &str = "$delayed/";
+
+/// Parse `$delayed/{seconds}/{topic}`, returns delay interval and the real topic.
+///
+/// Returns None if `topic` is malformed, or the real topic starts with `$`,
+/// which is reserved for server.
+#[must_use]
+pub fn parse_delayed_topic(topic: &str) -> Option<(Duration, &str)> {
+    let (seconds, topic) = topic.strip_prefix(DELAYED_PREFIX)?.split_once('/')?;
+    let seconds: u32 = seconds.parse().ok()?;
+    if topic.is_empty() || topic.starts_with('$') {
+        return None;
+    }
+    Some((Duration::from_secs(u64::from(seconds)), topic))
+}
+
+#[derive(Debug)]
+struct DelayedMessage {
+    listener_id: ListenerId,
+    packet: OutgoingPacket,
+    received_at: Instant,
+}
+
+/// Pending delayed messages, ordered by the time they are published.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug)]
+pub struct DelayedMessages {
+    /// (publish time, sequence number) -> message.
+    ///
+    /// Sequence number keeps order of messages with the same publish time.
+    queue: BTreeMap<(Instant, u64), DelayedMessage>,
+    next_seq: u64,
+    max_messages: usize,
+}
+
+impl DelayedMessages {
+    pub const fn new(max_messages: usize) -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            next_seq: 0,
+            max_messages,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Hold `packet` until `publish_at`.
+    ///
+    /// Returns false if queue is full and message is dropped.
+    pub fn push(
+        &mut self,
+        publish_at: Instant,
+        listener_id: ListenerId,
+        packet: OutgoingPacket,
+    ) -> bool {
+        if self.max_messages > 0 && self.queue.len() >= self.max_messages {
+            return false;
+        }
+        let message = DelayedMessage {
+            listener_id,
+            packet,
+            received_at: Instant::now(),
+        };
+        self.queue.insert((publish_at, self.next_seq), message);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        true
+    }
+
+    /// Get the earliest time when a delayed message shall be published.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue
+            .first_key_value()
+            .map(|((publish_at, _seq), _)| *publish_at)
+    }
+
+    /// Remove messages due before `now`.
+    ///
+    /// Message Expiry Interval of v5 messages is reduced by the time they are held,
+    /// expired messages are dropped.
+    pub fn pop_due(&mut self, now: Instant) -> Vec<(ListenerId, OutgoingPacket)> {
+        let mut due = Vec::new();
+        while let Some(entry) = self.queue.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let mut message = entry.remove();
+            if message
+                .packet
+                .update_message_expiry(now.saturating_duration_since(message.received_at))
+            {
+                due.push((message.listener_id, message.packet));
+            }
+        }
+        due
+    }
+}
+
+impl Dispatcher {
+    /// Returns true if message to `topic` shall be held as delayed message.
+    pub(super) fn is_delayed_topic(&self, topic: &str) -> bool {
+        self.delayed_messages.is_some() && topic.starts_with(DELAYED_PREFIX)
+    }
+
+    /// Get the earliest time when a delayed message shall be published.
+    pub(super) fn next_delayed_publish(&self) -> Option<Instant> {
+        self.delayed_messages
+            .as_ref()
+            .and_then(DelayedMessages::next_deadline)
+    }
+
+    /// Hold message to `$delayed/{seconds}/{topic}`, it is published to `topic` later.
+    ///
+    /// Malformed delayed messages are dropped.
+    pub(super) fn delay_publish(&mut self, listener_id: ListenerId, mut packet: OutgoingPacket) {
+        let Some(delayed_messages) = &mut self.delayed_messages else {
+            return;
+        };
+        let delayed_topic = match &packet {
+            OutgoingPacket::V3(packet) => packet.topic().to_string(),
+            OutgoingPacket::V5(packet) => packet.topic().to_string(),
+        };
+        let Some((interval, topic)) = parse_delayed_topic(&delayed_topic) else {
+            log::warn!(
+                "dispatcher: Invalid delayed topic: {:?}, drop message",
+                delayed_topic
+            );
+            return;
+        };
+        let ret = match &mut packet {
+            OutgoingPacket::V3(packet) => packet.set_topic(topic).map(drop),
+            OutgoingPacket::V5(packet) => packet.set_topic(topic).map(drop),
+        };
+        if let Err(err) = ret {
+            log::warn!(
+                "dispatcher: Invalid delayed topic: {:?}, err: {:?}",
+                delayed_topic,
+                err
+            );
+            return;
+        }
+        let Some(publish_at) = Instant::now().checked_add(interval) else {
+            return;
+        };
+        if !delayed_messages.push(publish_at, listener_id, packet) {
+            log::warn!(
+                "dispatcher: Too many delayed messages, drop message to {:?}",
+                delayed_topic
+            );
+        }
+    }
+
+    /// Publish delayed messages whose delay interval has passed.
+    pub(super) async fn publish_delayed_messages(&mut self) {
+        let Some(delayed_messages) = &mut self.delayed_messages else {
+            return;
+        };
+        for (listener_id, packet) in delayed_messages.pop_due(Instant::now()) {
+            self.route_publish(listener_id, packet).await;
+        }
+    }
+
+    /// Pending delayed messages are not persisted, they are dropped when server stops.
+    pub(super) fn drop_delayed_messages(&mut self) {
+        if let Some(delayed_messages) = self.delayed_messages.take() {
+            if delayed_messages.len() > 0 {
+                log::warn!(
+                    "dispatcher: Drop {} pending delayed messages",
+                    delayed_messages.len()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, QoS};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    use super::{parse_delayed_topic, DelayedMessages};
+    use crate::session::OutgoingPacket;
+
+    fn new_packet(topic: &str) -> OutgoingPacket {
+        OutgoingPacket::V3(v3::PublishPacket::new(topic, QoS::AtMostOnce, b"1").unwrap())
+    }
+
+    fn topic(packet: &OutgoingPacket) -> &str {
+        match packet {
+            OutgoingPacket::V3(packet) => packet.topic(),
+            OutgoingPacket::V5(packet) => packet.topic(),
+        }
+    }
+
+    #[test]
+    fn test_parse_delayed_topic() {
+        assert_eq!(
+            parse_delayed_topic("$delayed/60/actual/topic"),
+            Some((Duration::from_secs(60), "actual/topic"))
+        );
+        assert_eq!(
+            parse_delayed_topic("$delayed/0/a"),
+            Some((Duration::ZERO, "a"))
+        );
+        assert_eq!(parse_delayed_topic("$delayed/60/"), None);
+        assert_eq!(parse_delayed_topic("$delayed/60"), None);
+        assert_eq!(parse_delayed_topic("$delayed/-1/a"), None);
+        assert_eq!(parse_delayed_topic("$delayed/soon/a"), None);
+        assert_eq!(parse_delayed_topic("$delayed/0/$SYS/broker/uptime"), None);
+        assert_eq!(parse_delayed_topic("actual/topic"), None);
+    }
+
+    #[test]
+    fn test_pop_due() {
+        let mut messages = DelayedMessages::new(2);
+        let now = Instant::now();
+        assert!(messages.push(now + Duration::from_secs(20), 1, new_packet("b")));
+        assert!(messages.push(now + Duration::from_secs(10), 1, new_packet("a")));
+        assert!(!messages.push(now, 1, new_packet("c")));
+        assert_eq!(
+            messages.next_deadline(),
+            Some(now + Duration::from_secs(10))
+        );
+
+        assert!(messages.pop_due(now).is_empty());
+        let due = messages.pop_due(now + Duration::from_secs(15));
+        assert_eq!(due.len(), 1);
+        assert_eq!(topic(&due[0].1), "a");
+        let due = messages.pop_due(now + Duration::from_secs(20));
+        assert_eq!(due.len(), 1);
+        assert_eq!(topic(&due[0].1), "b");
+        assert!(messages.next_deadline().is_none());
+    }
+}
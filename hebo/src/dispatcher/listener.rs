@@ -2,11 +2,13 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use codec::{v3, v5, ProtocolLevel};
+use codec::topic::SHARE_PREFIX;
+use codec::{v3, v5, Packet, ProtocolLevel};
 
-use super::Dispatcher;
+use super::{trie, Dispatcher};
 use crate::commands::{DispatcherToListenerCmd, ListenerToDispatcherCmd};
-use crate::types::SessionGid;
+use crate::session::OutgoingPacket;
+use crate::types::{ListenerId, SessionGid};
 
 impl Dispatcher {
     pub(super) async fn handle_listener_cmd(&mut self, cmd: ListenerToDispatcherCmd) {
@@ -15,13 +17,34 @@ impl Dispatcher {
                 self.on_listener_check_cached_session(session_gid, client_id, protocol_level)
                     .await;
             }
-            ListenerToDispatcherCmd::Publish(packet) => {
-                self.backends_store_packet(&packet).await;
-                self.on_listener_publish(&packet).await;
+            ListenerToDispatcherCmd::DiscardCachedSession(client_id) => {
+                self.discard_cached_session(&client_id).await;
             }
-            ListenerToDispatcherCmd::PublishV5(packet) => {
-                self.backends_store_packet_v5(&packet).await;
-                self.on_listener_publish_v5(&packet).await;
+            ListenerToDispatcherCmd::Publish(listener_id, packet) => {
+                self.metrics_publish_packet_received(
+                    listener_id,
+                    packet.bytes().unwrap_or_default(),
+                )
+                .await;
+                if self.is_delayed_topic(packet.topic()) {
+                    self.delay_publish(listener_id, OutgoingPacket::V3(packet));
+                } else {
+                    self.route_publish(listener_id, OutgoingPacket::V3(packet))
+                        .await;
+                }
+            }
+            ListenerToDispatcherCmd::PublishV5(listener_id, packet) => {
+                self.metrics_publish_packet_received(
+                    listener_id,
+                    packet.bytes().unwrap_or_default(),
+                )
+                .await;
+                if self.is_delayed_topic(packet.topic()) {
+                    self.delay_publish(listener_id, OutgoingPacket::V5(packet));
+                } else {
+                    self.route_publish(listener_id, OutgoingPacket::V5(packet))
+                        .await;
+                }
             }
             ListenerToDispatcherCmd::Subscribe(session_gid, packet) => {
                 self.on_listener_subscribe(session_gid, packet).await;
@@ -41,6 +64,15 @@ impl Dispatcher {
             ListenerToDispatcherCmd::SessionRemoved(listener_id) => {
                 self.metrics_on_session_removed(listener_id).await;
             }
+            ListenerToDispatcherCmd::ClientConnected(listener_id, client_id) => {
+                self.publish_presence(listener_id, &client_id, true).await;
+            }
+            ListenerToDispatcherCmd::ClientDisconnected(listener_id, client_id) => {
+                self.publish_presence(listener_id, &client_id, false).await;
+            }
+            ListenerToDispatcherCmd::CacheSession(session_gid, cached_session) => {
+                self.cache_session(session_gid, cached_session).await;
+            }
         }
     }
 
@@ -50,14 +82,23 @@ impl Dispatcher {
         client_id: String,
         protocol_level: ProtocolLevel,
     ) {
-        let cached_session = self.cached_sessions.pop(&client_id);
+        // Expired sessions are not resumed, even if expiry timer has not fired yet.
+        self.remove_expired_sessions().await;
+        let cached_session =
+            self.cached_sessions
+                .pop(&client_id)
+                .map(|(old_gid, cached_session)| {
+                    // Subscriptions are part of session state.
+                    self.sub_trie.move_session(old_gid, session_gid);
+                    cached_session
+                });
         if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
             let cmd = DispatcherToListenerCmd::CheckCachedSessionResp(
                 session_gid.session_id(),
                 protocol_level,
                 cached_session,
             );
-            if let Err(err) = listener_sender.send(cmd).await {
+            if let Err(err) = listener_sender.send(cmd) {
                 log::error!(
                     "dispatcher: Failed to send check cached session to listener: {:?}, err: {:?}",
                     session_gid,
@@ -72,11 +113,49 @@ impl Dispatcher {
         }
     }
 
-    pub(super) async fn on_listener_publish(&mut self, packet: &v3::PublishPacket) {
+    /// Store, bridge and publish message received from client.
+    pub(super) async fn dispatch_publish(
+        &mut self,
+        listener_id: ListenerId,
+        packet: &v3::PublishPacket,
+    ) {
+        self.backends_store_packet(packet).await;
+        self.bridge_publish(packet).await;
+        self.on_listener_publish(listener_id, packet).await;
+    }
+
+    /// Store, bridge and publish message received from v5 client.
+    pub(super) async fn dispatch_publish_v5(
+        &mut self,
+        listener_id: ListenerId,
+        packet: &v5::PublishPacket,
+    ) {
+        self.backends_store_packet_v5(packet).await;
+        self.bridge_publish_v5(packet).await;
+        self.on_listener_publish_v5(listener_id, packet).await;
+    }
+
+    pub(super) async fn on_listener_publish(
+        &mut self,
+        listener_id: ListenerId,
+        packet: &v3::PublishPacket,
+    ) {
+        let retained = self.retain_trie.len();
+        self.retain_trie.retain(packet);
+        self.metrics_on_retained_changed(listener_id, retained)
+            .await;
         self.publish_packet_to_sub_trie(packet).await;
     }
 
-    pub(super) async fn on_listener_publish_v5(&mut self, packet: &v5::PublishPacket) {
+    pub(super) async fn on_listener_publish_v5(
+        &mut self,
+        listener_id: ListenerId,
+        packet: &v5::PublishPacket,
+    ) {
+        let retained = self.retain_trie.len();
+        self.retain_trie.retain_v5(packet);
+        self.metrics_on_retained_changed(listener_id, retained)
+            .await;
         self.publish_packet_to_sub_trie_v5(packet).await;
     }
 
@@ -89,11 +168,16 @@ impl Dispatcher {
 
         self.metrics_on_subscription_added(session_gid.listener_id(), n_subscribed)
             .await;
+        let counts = self
+            .sub_trie
+            .subscriber_counts(packet.topics().iter().map(v3::SubscribeTopic::topic));
+        self.metrics_on_topic_subscribers_changed(counts).await;
 
+        let ack_vec = sub_ack_packet.acknowledgements().to_vec();
         if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
             let cmd =
                 DispatcherToListenerCmd::SubscribeAck(session_gid.session_id(), sub_ack_packet);
-            if let Err(err) = listener_sender.send(cmd).await {
+            if let Err(err) = listener_sender.send(cmd) {
                 log::error!(
                     "dispatcher: Failed to send subscribe ack to listener: {:?}, err: {:?}",
                     session_gid,
@@ -106,6 +190,16 @@ impl Dispatcher {
                 session_gid.listener_id()
             );
         }
+
+        // When a new subscription is established, the last retained message, if any,
+        // on each matching topic name MUST be sent to the subscriber [MQTT-3.3.1-6].
+        // Retained messages are not sent for shared subscriptions.
+        for (topic, ack) in packet.topics().iter().zip(ack_vec) {
+            if ack != v3::SubscribeAck::Failed && !topic.topic().starts_with(SHARE_PREFIX) {
+                self.send_retained_messages(session_gid, topic.topic(), None)
+                    .await;
+            }
+        }
     }
 
     async fn on_listener_subscribe_v5(
@@ -117,11 +211,16 @@ impl Dispatcher {
 
         self.metrics_on_subscription_added(session_gid.listener_id(), n_subscribed)
             .await;
+        let counts = self
+            .sub_trie
+            .subscriber_counts(packet.topics().iter().map(v5::SubscribeTopic::topic));
+        self.metrics_on_topic_subscribers_changed(counts).await;
 
+        let reasons = sub_ack_packet.reasons().to_vec();
         if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
             let cmd =
                 DispatcherToListenerCmd::SubscribeAckV5(session_gid.session_id(), sub_ack_packet);
-            if let Err(err) = listener_sender.send(cmd).await {
+            if let Err(err) = listener_sender.send(cmd) {
                 log::error!(
                     "dispatcher: Failed to send subscribe ack to listener: {:?}, err: {:?}",
                     session_gid,
@@ -134,6 +233,18 @@ impl Dispatcher {
                 session_gid.listener_id()
             );
         }
+
+        // TODO(Shaohua): Support RetainHandling::SendFirst.
+        let subscription_id = trie::subscription_id(&packet);
+        for (topic, reason) in packet.topics().iter().zip(reasons) {
+            if !reason.is_error()
+                && topic.retain_handling() != v5::RetainHandling::NoSend
+                && !topic.topic().starts_with(SHARE_PREFIX)
+            {
+                self.send_retained_messages(session_gid, topic.topic(), subscription_id)
+                    .await;
+            }
+        }
     }
 
     async fn on_listener_unsubscribe(
@@ -144,6 +255,10 @@ impl Dispatcher {
         let n_unsubscribed = self.sub_trie.unsubscribe(session_gid, &packet);
         self.metrics_on_subscription_removed(session_gid.listener_id(), n_unsubscribed)
             .await;
+        let counts = self
+            .sub_trie
+            .subscriber_counts(packet.topics().iter().map(AsRef::as_ref));
+        self.metrics_on_topic_subscribers_changed(counts).await;
     }
 
     async fn on_listener_unsubscribe_v5(
@@ -154,5 +269,9 @@ impl Dispatcher {
         let n_unsubscribed = self.sub_trie.unsubscribe_v5(session_gid, &packet);
         self.metrics_on_subscription_removed(session_gid.listener_id(), n_unsubscribed)
             .await;
+        let counts = self
+            .sub_trie
+            .subscriber_counts(packet.topics().iter().map(AsRef::as_ref));
+        self.metrics_on_topic_subscribers_changed(counts).await;
     }
 }
@@ -4,6 +4,8 @@
 
 //! Metrics app handler
 
+use std::cmp::Ordering;
+
 use super::Dispatcher;
 use crate::commands::{DispatcherToMetricsCmd, MetricsToDispatcherCmd};
 use crate::types::ListenerId;
@@ -20,9 +22,29 @@ impl Dispatcher {
         }
     }
 
-    #[allow(dead_code)]
+    pub(super) async fn metrics_publish_packet_received(
+        &self,
+        listener_id: ListenerId,
+        bytes: usize,
+    ) {
+        if let Err(err) = self
+            .metrics_sender
+            .send(DispatcherToMetricsCmd::PublishPacketReceived(
+                listener_id,
+                1,
+                bytes,
+            ))
+            .await
+        {
+            log::error!(
+                "Dispatcher: Failed to send PublishPacketReceived, err: {:?}",
+                err
+            );
+        }
+    }
+
     pub(super) async fn metrics_publish_packet_sent(
-        &mut self,
+        &self,
         listener_id: ListenerId,
         count: usize,
         bytes: usize,
@@ -43,7 +65,51 @@ impl Dispatcher {
         }
     }
 
-    pub(super) async fn metrics_on_session_added(&mut self, listener_id: ListenerId) {
+    /// Report change of retained messages, `old_len` is number of them before update.
+    pub(super) async fn metrics_on_retained_changed(
+        &self,
+        listener_id: ListenerId,
+        old_len: usize,
+    ) {
+        let new_len = self.retain_trie.len();
+        let cmd = match new_len.cmp(&old_len) {
+            Ordering::Greater => {
+                DispatcherToMetricsCmd::RetainedMessageAdded(listener_id, new_len - old_len, 0)
+            }
+            Ordering::Less => {
+                DispatcherToMetricsCmd::RetainedMessageRemoved(listener_id, old_len - new_len, 0)
+            }
+            Ordering::Equal => return,
+        };
+        if let Err(err) = self.metrics_sender.send(cmd).await {
+            log::error!(
+                "Dispatcher: Failed to send retained message cmd, err: {:?}",
+                err
+            );
+        }
+    }
+
+    pub(super) async fn metrics_on_listener_added(&self, listener_id: ListenerId, address: String) {
+        if let Err(err) = self
+            .metrics_sender
+            .send(DispatcherToMetricsCmd::ListenerAdded(listener_id, address))
+            .await
+        {
+            log::error!("Dispatcher: Failed to send ListenerAdded, err: {:?}", err);
+        }
+    }
+
+    pub(super) async fn metrics_on_listener_removed(&self, listener_id: ListenerId) {
+        if let Err(err) = self
+            .metrics_sender
+            .send(DispatcherToMetricsCmd::ListenerRemoved(listener_id))
+            .await
+        {
+            log::error!("Dispatcher: Failed to send ListenerRemoved, err: {:?}", err);
+        }
+    }
+
+    pub(super) async fn metrics_on_session_added(&self, listener_id: ListenerId) {
         if let Err(err) = self
             .metrics_sender
             .send(DispatcherToMetricsCmd::SessionAdded(listener_id, 1))
@@ -56,7 +122,7 @@ impl Dispatcher {
         }
     }
 
-    pub(super) async fn metrics_on_session_removed(&mut self, listener_id: ListenerId) {
+    pub(super) async fn metrics_on_session_removed(&self, listener_id: ListenerId) {
         if let Err(err) = self
             .metrics_sender
             .send(DispatcherToMetricsCmd::SessionRemoved(listener_id, 1))
@@ -69,11 +135,7 @@ impl Dispatcher {
         }
     }
 
-    pub(super) async fn metrics_on_subscription_added(
-        &mut self,
-        listener_id: ListenerId,
-        n: usize,
-    ) {
+    pub(super) async fn metrics_on_subscription_added(&self, listener_id: ListenerId, n: usize) {
         if let Err(err) = self
             .metrics_sender
             .send(DispatcherToMetricsCmd::SubscriptionsAdded(listener_id, n))
@@ -86,11 +148,7 @@ impl Dispatcher {
         }
     }
 
-    pub(super) async fn metrics_on_subscription_removed(
-        &mut self,
-        listener_id: ListenerId,
-        n: usize,
-    ) {
+    pub(super) async fn metrics_on_subscription_removed(&self, listener_id: ListenerId, n: usize) {
         if let Err(err) = self
             .metrics_sender
             .send(DispatcherToMetricsCmd::SubscriptionsRemoved(listener_id, n))
@@ -102,4 +160,21 @@ impl Dispatcher {
             );
         }
     }
+
+    /// Report number of subscribers of topic filters after their subscriptions changed.
+    pub(super) async fn metrics_on_topic_subscribers_changed(&self, counts: Vec<(String, usize)>) {
+        if counts.is_empty() {
+            return;
+        }
+        if let Err(err) = self
+            .metrics_sender
+            .send(DispatcherToMetricsCmd::TopicSubscribers(counts))
+            .await
+        {
+            log::error!(
+                "Dispatcher: Failed to send TopicSubscribers cmd, err: {:?}",
+                err
+            );
+        }
+    }
 }
@@ -3,35 +3,62 @@
 // in the LICENSE file.
 
 use std::collections::HashMap;
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::path::PathBuf;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
 
 use crate::commands::{
     BackendsToDispatcherCmd, BridgeToDispatcherCmd, DispatcherToBackendsCmd, DispatcherToBridgeCmd,
     DispatcherToGatewayCmd, DispatcherToListenerCmd, DispatcherToMetricsCmd,
     DispatcherToRuleEngineCmd, GatewayToDispatcherCmd, ListenerToDispatcherCmd,
-    MetricsToDispatcherCmd, RuleEngineToDispatcherCmd,
+    MetricsToDispatcherCmd, RuleEngineToDispatcherCmd, ServerContextToDispatcherCmd,
 };
+use crate::config::{MessageQueuePolicy, QueueFullPolicy};
 use crate::types::ListenerId;
 
 mod backends;
 mod bridge;
+mod delayed;
 mod gateway;
 mod listener;
 mod metrics;
+mod presence;
+mod retain;
 mod rule_engine;
+mod server;
 mod sessions;
 mod trie;
 
+pub use delayed::{parse_delayed_topic, DELAYED_PREFIX};
+pub use retain::{RetainTrie, RetainedMessage};
+pub use trie::{is_filter_match, SubTrie, Subscribers};
+
 /// Dispatcher is a message router.
 #[allow(dead_code)]
 pub struct Dispatcher {
     sub_trie: trie::SubTrie,
 
+    retain_trie: retain::RetainTrie,
+
     cached_sessions: sessions::CachedSessions,
 
+    /// Cached sessions are saved to this file on stop, persistence is disabled if None.
+    sessions_file: Option<PathBuf>,
+
+    /// Topic template of presence messages, disabled if None.
+    presence_topic: Option<String>,
+
+    /// Pending messages to `$delayed/` topics, delayed publish is disabled if None.
+    delayed_messages: Option<delayed::DelayedMessages>,
+
+    /// Messages are written to message store of backends app with this policy,
+    /// disabled if None.
+    message_queue_policy: Option<MessageQueuePolicy>,
     backends_sender: Sender<DispatcherToBackendsCmd>,
     backends_receiver: Receiver<BackendsToDispatcherCmd>,
 
+    /// Topic filters of local messages forwarded to remote brokers.
+    bridge_filters: Vec<String>,
     bridge_sender: Sender<DispatcherToBridgeCmd>,
     bridge_receiver: Receiver<BridgeToDispatcherCmd>,
 
@@ -41,20 +68,36 @@ pub struct Dispatcher {
     metrics_sender: Sender<DispatcherToMetricsCmd>,
     metrics_receiver: Receiver<MetricsToDispatcherCmd>,
 
-    listener_senders: HashMap<ListenerId, Sender<DispatcherToListenerCmd>>,
+    /// Unbounded, or dispatcher and listener may wait for each other when their queues are full.
+    listener_senders: HashMap<ListenerId, UnboundedSender<DispatcherToListenerCmd>>,
     listener_receiver: Receiver<ListenerToDispatcherCmd>,
 
+    /// Messages received from clients are evaluated by rule engine before dispatched.
+    rule_engine_enabled: bool,
     rule_engine_sender: Sender<DispatcherToRuleEngineCmd>,
-    rule_engine_receiver: Receiver<RuleEngineToDispatcherCmd>,
+    /// Rule engine never waits for dispatcher, or both of them may wait for each other
+    /// when their queues are full.
+    rule_engine_receiver: UnboundedReceiver<RuleEngineToDispatcherCmd>,
+
+    server_ctx_receiver: Receiver<ServerContextToDispatcherCmd>,
 }
 
 impl Dispatcher {
     #[allow(clippy::too_many_arguments)]
     #[must_use]
     pub fn new(
+        presence_topic: Option<String>,
+        max_queued_messages: usize,
+        queue_full_policy: QueueFullPolicy,
+        max_topic_subscribers: usize,
+        delayed_publish: bool,
+        max_delayed_messages: usize,
+
+        message_queue_policy: Option<MessageQueuePolicy>,
         backends_sender: Sender<DispatcherToBackendsCmd>,
         backends_receiver: Receiver<BackendsToDispatcherCmd>,
 
+        bridge_filters: Vec<String>,
         bridge_sender: Sender<DispatcherToBridgeCmd>,
         bridge_receiver: Receiver<BridgeToDispatcherCmd>,
 
@@ -64,20 +107,35 @@ impl Dispatcher {
         metrics_sender: Sender<DispatcherToMetricsCmd>,
         metrics_receiver: Receiver<MetricsToDispatcherCmd>,
 
-        listener_senders: Vec<(ListenerId, Sender<DispatcherToListenerCmd>)>,
+        listener_senders: Vec<(ListenerId, UnboundedSender<DispatcherToListenerCmd>)>,
         listener_receiver: Receiver<ListenerToDispatcherCmd>,
 
+        rule_engine_enabled: bool,
         rule_engine_sender: Sender<DispatcherToRuleEngineCmd>,
-        rule_engine_receiver: Receiver<RuleEngineToDispatcherCmd>,
+        rule_engine_receiver: UnboundedReceiver<RuleEngineToDispatcherCmd>,
+
+        server_ctx_receiver: Receiver<ServerContextToDispatcherCmd>,
     ) -> Self {
+        let mut sub_trie = trie::SubTrie::new();
+        sub_trie.set_max_subscribers(max_topic_subscribers);
         Self {
-            sub_trie: trie::SubTrie::new(),
+            sub_trie,
+
+            retain_trie: retain::RetainTrie::new(),
+
+            cached_sessions: sessions::CachedSessions::new(max_queued_messages, queue_full_policy),
+            sessions_file: None,
 
-            cached_sessions: sessions::CachedSessions::new(),
+            presence_topic,
 
+            delayed_messages: delayed_publish
+                .then(|| delayed::DelayedMessages::new(max_delayed_messages)),
+
+            message_queue_policy,
             backends_sender,
             backends_receiver,
 
+            bridge_filters,
             bridge_sender,
             bridge_receiver,
 
@@ -90,13 +148,17 @@ impl Dispatcher {
             listener_senders: listener_senders.into_iter().collect(),
             listener_receiver,
 
+            rule_engine_enabled,
             rule_engine_sender,
             rule_engine_receiver,
+
+            server_ctx_receiver,
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         loop {
+            let next_delayed_publish = self.next_delayed_publish();
             tokio::select! {
                 Some(cmd) = self.backends_receiver.recv() => {
                     self.handle_backends_cmd(cmd).await;
@@ -116,7 +178,36 @@ impl Dispatcher {
                 Some(cmd) = self.rule_engine_receiver.recv() => {
                     self.handle_rule_engine_cmd(cmd).await;
                 },
+                () = deadline_timer(self.cached_sessions.next_expiry()) => {
+                    self.remove_expired_sessions().await;
+                },
+                () = deadline_timer(next_delayed_publish) => {
+                    self.publish_delayed_messages().await;
+                },
+                Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToDispatcherCmd::Stop) {
+                        log::info!("dispatcher: Stop app");
+                        // Listeners have stopped, handle sessions cached by them
+                        // before saving.
+                        while let Ok(cmd) = self.listener_receiver.try_recv() {
+                            self.handle_listener_cmd(cmd).await;
+                        }
+                        self.save_sessions();
+                        self.drop_delayed_messages();
+                        break;
+                    }
+                    self.handle_server_ctx_cmd(cmd).await;
+                },
             }
         }
     }
 }
+
+/// Wait until `deadline`, never completes if it is None.
+async fn deadline_timer(deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline).await;
+    } else {
+        std::future::pending::<()>().await;
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Publish presence messages when clients connect and disconnect.
+
+use codec::{v3, QoS};
+
+use super::Dispatcher;
+use crate::config::PRESENCE_CLIENT_ID;
+use crate::types::ListenerId;
+
+/// Payload of presence message when client is connected.
+const CONNECTED_PAYLOAD: &[u8] = b"1";
+
+impl Dispatcher {
+    /// Publish retained presence message of `client_id`.
+    ///
+    /// Retained message is cleared with an empty payload if client is disconnected.
+    pub(super) async fn publish_presence(
+        &mut self,
+        listener_id: ListenerId,
+        client_id: &str,
+        connected: bool,
+    ) {
+        let Some(presence_topic) = &self.presence_topic else {
+            return;
+        };
+        let topic = presence_topic.replace(PRESENCE_CLIENT_ID, client_id);
+        let payload = if connected { CONNECTED_PAYLOAD } else { &[] };
+        let mut packet = match v3::PublishPacket::new(&topic, QoS::AtMostOnce, payload) {
+            Ok(packet) => packet,
+            Err(err) => {
+                // Client id may contain wildcard characters.
+                log::warn!(
+                    "dispatcher: Invalid presence topic: {:?}, err: {:?}",
+                    topic,
+                    err
+                );
+                return;
+            }
+        };
+        packet.set_retain(true);
+        self.on_listener_publish(listener_id, &packet).await;
+    }
+}
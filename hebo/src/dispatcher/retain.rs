@@ -0,0 +1,429 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Manage retained messages.
+
+use codec::{v3, v5, VarInt};
+use std::collections::HashMap;
+use tokio::time::Instant;
+
+use super::Dispatcher;
+use crate::commands::DispatcherToListenerCmd;
+use crate::session::update_message_expiry;
+use crate::types::SessionGid;
+
+#[derive(Debug, Clone)]
+pub enum RetainedMessage {
+    V3(v3::PublishPacket),
+    V5(v5::PublishPacket),
+}
+
+impl RetainedMessage {
+    fn topic(&self) -> &str {
+        match self {
+            Self::V3(packet) => packet.topic(),
+            Self::V5(packet) => packet.topic(),
+        }
+    }
+}
+
+/// Retained message with the time it is stored.
+#[derive(Debug, Clone)]
+struct StoredMessage {
+    message: RetainedMessage,
+    retained_at: Instant,
+}
+
+impl StoredMessage {
+    /// Returns message to be delivered, or None if it has expired.
+    fn take(&self) -> Option<RetainedMessage> {
+        match &self.message {
+            RetainedMessage::V3(packet) => Some(RetainedMessage::V3(packet.clone())),
+            RetainedMessage::V5(packet) => {
+                let mut packet = packet.clone();
+                update_message_expiry(&mut packet, self.retained_at.elapsed())
+                    .then_some(RetainedMessage::V5(packet))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct RetainNode {
+    message: Option<StoredMessage>,
+    /// Topic levels are sent by clients, so `SipHash` is used to resist hash flooding.
+    children: HashMap<String, Self>,
+}
+
+impl RetainNode {
+    fn is_empty(&self) -> bool {
+        self.message.is_none() && self.children.is_empty()
+    }
+}
+
+/// Retained messages indexed by topic levels.
+///
+/// A topic filter only walks the branches it matches, so that new wildcard subscriptions
+/// do not need to scan all of retained messages.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Default, Clone)]
+pub struct RetainTrie {
+    root: RetainNode,
+    len: usize,
+}
+
+impl RetainTrie {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get number of retained messages.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Store or remove retained message if `retain` flag of packet is set.
+    pub fn retain(&mut self, packet: &v3::PublishPacket) {
+        if !packet.retain() {
+            return;
+        }
+        if packet.message().is_empty() {
+            self.remove(packet.topic());
+        } else {
+            self.insert(packet.topic(), RetainedMessage::V3(packet.clone()));
+        }
+    }
+
+    /// Store or remove retained message if `retain` flag of packet is set.
+    pub fn retain_v5(&mut self, packet: &v5::PublishPacket) {
+        if !packet.retain() {
+            return;
+        }
+        if packet.message().is_empty() {
+            self.remove(packet.topic());
+        } else {
+            self.insert(packet.topic(), RetainedMessage::V5(packet.clone()));
+        }
+    }
+
+    /// Replace any existing retained message for this topic [MQTT-3.3.1-5].
+    fn insert(&mut self, topic: &str, message: RetainedMessage) {
+        let mut node = &mut self.root;
+        for level in topic.split('/') {
+            node = node.children.entry(level.to_string()).or_default();
+        }
+        let message = StoredMessage {
+            message,
+            retained_at: Instant::now(),
+        };
+        if node.message.replace(message).is_none() {
+            self.len += 1;
+        }
+    }
+
+    /// A PUBLISH packet with a RETAIN flag set to 1 and a payload containing zero bytes
+    /// removes any existing retained message with the same topic name [MQTT-3.3.1-10].
+    fn remove(&mut self, topic: &str) {
+        let levels: Vec<&str> = topic.split('/').collect();
+        if Self::remove_node(&mut self.root, &levels).is_some() {
+            self.len -= 1;
+        }
+    }
+
+    fn remove_node(node: &mut RetainNode, levels: &[&str]) -> Option<StoredMessage> {
+        let Some((level, rest)) = levels.split_first() else {
+            return node.message.take();
+        };
+        let child = node.children.get_mut(*level)?;
+        let message = Self::remove_node(child, rest);
+        // Release empty branches.
+        if child.is_empty() {
+            node.children.remove(*level);
+        }
+        message
+    }
+
+    /// Get retained messages matching `filter`.
+    ///
+    /// `filter` shall be a valid topic filter.
+    #[must_use]
+    pub fn match_filter(&self, filter: &str) -> Vec<&RetainedMessage> {
+        self.match_stored(filter)
+            .into_iter()
+            .map(|stored| &stored.message)
+            .collect()
+    }
+
+    /// Get retained messages matching `filter` to be sent to subscriber.
+    ///
+    /// Message Expiry Interval of v5 messages is reduced by the time they have been
+    /// retained, and expired messages are removed instead.
+    pub fn take_matched(&mut self, filter: &str) -> Vec<RetainedMessage> {
+        let mut messages = Vec::new();
+        let mut expired_topics = Vec::new();
+        for stored in self.match_stored(filter) {
+            if let Some(message) = stored.take() {
+                messages.push(message);
+            } else {
+                expired_topics.push(stored.message.topic().to_string());
+            }
+        }
+        for topic in expired_topics {
+            self.remove(&topic);
+        }
+        messages
+    }
+
+    fn match_stored(&self, filter: &str) -> Vec<&StoredMessage> {
+        let levels: Vec<&str> = filter.split('/').collect();
+        let mut messages = Vec::new();
+        Self::match_node(&self.root, &levels, true, &mut messages);
+        messages
+    }
+
+    fn match_node<'a>(
+        node: &'a RetainNode,
+        levels: &[&str],
+        is_root: bool,
+        messages: &mut Vec<&'a StoredMessage>,
+    ) {
+        let Some((level, rest)) = levels.split_first() else {
+            messages.extend(node.message.as_ref());
+            return;
+        };
+
+        // The Server MUST NOT match Topic Filters starting with a wildcard character (# or +)
+        // with Topic Names beginning with a $ character [MQTT-4.7.2-1].
+        let wildcard_children = node
+            .children
+            .iter()
+            .filter(|(name, _child)| !(is_root && name.starts_with('$')));
+
+        match *level {
+            "#" => {
+                // Multi-level wildcard also matches the parent level.
+                messages.extend(node.message.as_ref());
+                for (_name, child) in wildcard_children {
+                    Self::collect_all(child, messages);
+                }
+            }
+            "+" => {
+                for (_name, child) in wildcard_children {
+                    Self::match_node(child, rest, false, messages);
+                }
+            }
+            _ => {
+                if let Some(child) = node.children.get(*level) {
+                    Self::match_node(child, rest, false, messages);
+                }
+            }
+        }
+    }
+
+    fn collect_all<'a>(node: &'a RetainNode, messages: &mut Vec<&'a StoredMessage>) {
+        messages.extend(node.message.as_ref());
+        for child in node.children.values() {
+            Self::collect_all(child, messages);
+        }
+    }
+}
+
+impl Dispatcher {
+    /// Send retained messages matching `filter` to newly subscribed session.
+    ///
+    /// `subscription_id` of v5 subscribe packet is added to v5 messages, and expired
+    /// messages are discarded.
+    pub(super) async fn send_retained_messages(
+        &mut self,
+        session_gid: SessionGid,
+        filter: &str,
+        subscription_id: Option<VarInt>,
+    ) {
+        let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) else {
+            log::error!(
+                "dispatcher: Failed to get listener sender with id: {}",
+                session_gid.listener_id()
+            );
+            return;
+        };
+
+        let retained = self.retain_trie.len();
+        let messages = self.retain_trie.take_matched(filter);
+        for message in messages {
+            let cmd = match message {
+                RetainedMessage::V3(packet) => {
+                    DispatcherToListenerCmd::Publish(session_gid.session_id(), packet)
+                }
+                RetainedMessage::V5(mut packet) => {
+                    if let Some(id) = subscription_id {
+                        if let Err(err) = packet
+                            .properties_mut()
+                            .push(v5::Property::SubscriptionIdentifier(id))
+                        {
+                            log::error!(
+                                "dispatcher: Failed to add subscription identifier, err: {:?}",
+                                err
+                            );
+                        }
+                    }
+                    DispatcherToListenerCmd::PublishV5(session_gid.session_id(), packet)
+                }
+            };
+            if let Err(err) = listener_sender.send(cmd) {
+                log::error!(
+                    "dispatcher: Failed to send retained message to listener: {}, err: {:?}",
+                    session_gid.listener_id(),
+                    err
+                );
+            }
+        }
+        // Expired messages are removed.
+        self.metrics_on_retained_changed(session_gid.listener_id(), retained)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5, QoS, U32Data};
+    use std::time::Duration;
+
+    use super::{RetainTrie, RetainedMessage};
+
+    fn retain(trie: &mut RetainTrie, topic: &str, message: &[u8]) {
+        let mut packet = v3::PublishPacket::new(topic, QoS::AtMostOnce, message).unwrap();
+        packet.set_retain(true);
+        trie.retain(&packet);
+    }
+
+    fn match_topics(trie: &RetainTrie, filter: &str) -> Vec<String> {
+        let mut topics: Vec<String> = trie
+            .match_filter(filter)
+            .into_iter()
+            .map(|message| match message {
+                RetainedMessage::V3(packet) => packet.topic().to_string(),
+                RetainedMessage::V5(packet) => packet.topic().to_string(),
+            })
+            .collect();
+        topics.sort();
+        topics
+    }
+
+    #[test]
+    fn test_retain_and_remove() {
+        let mut trie = RetainTrie::new();
+        retain(&mut trie, "sport/tennis", b"1");
+        retain(&mut trie, "sport/tennis", b"2");
+        assert_eq!(trie.len(), 1);
+
+        let packet = v3::PublishPacket::new("sport/golf", QoS::AtMostOnce, b"3").unwrap();
+        trie.retain(&packet);
+        assert_eq!(trie.len(), 1);
+
+        retain(&mut trie, "sport/tennis", b"");
+        assert!(trie.is_empty());
+        assert!(trie.root.is_empty());
+    }
+
+    #[test]
+    fn test_match_filter() {
+        let mut trie = RetainTrie::new();
+        for topic in [
+            "sport",
+            "sport/tennis",
+            "sport/tennis/player1",
+            "sport/golf",
+            "news/sport",
+            "/sport",
+            "$SYS/uptime",
+        ] {
+            retain(&mut trie, topic, b"on");
+        }
+
+        assert_eq!(
+            match_topics(&trie, "sport/#"),
+            [
+                "sport",
+                "sport/golf",
+                "sport/tennis",
+                "sport/tennis/player1"
+            ]
+        );
+        assert_eq!(
+            match_topics(&trie, "sport/+"),
+            ["sport/golf", "sport/tennis"]
+        );
+        assert_eq!(match_topics(&trie, "+/sport"), ["/sport", "news/sport"]);
+        assert_eq!(match_topics(&trie, "sport/tennis"), ["sport/tennis"]);
+        assert_eq!(match_topics(&trie, "#").len(), 6);
+        assert_eq!(match_topics(&trie, "$SYS/#"), ["$SYS/uptime"]);
+        assert!(match_topics(&trie, "sport/hockey").is_empty());
+    }
+
+    #[test]
+    fn test_match_many_topics() {
+        let mut trie = RetainTrie::new();
+        for device in 0..100 {
+            for sensor in 0..100 {
+                retain(
+                    &mut trie,
+                    &format!("device/{device}/sensor/{sensor}"),
+                    b"on",
+                );
+            }
+        }
+        assert_eq!(trie.len(), 10_000);
+
+        let topics = match_topics(&trie, "device/42/#");
+        assert_eq!(topics.len(), 100);
+        assert!(topics.iter().all(|topic| topic.starts_with("device/42/")));
+        assert_eq!(match_topics(&trie, "device/+/sensor/7").len(), 100);
+    }
+
+    fn retain_v5(trie: &mut RetainTrie, topic: &str, expiry_interval: u32) {
+        let mut packet = v5::PublishPacket::new(topic, QoS::AtMostOnce, b"on").unwrap();
+        packet.set_retain(true);
+        packet
+            .properties_mut()
+            .push(v5::Property::MessageExpiryInterval(U32Data::new(
+                expiry_interval,
+            )))
+            .unwrap();
+        trie.retain_v5(&packet);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_message_expiry() {
+        let mut trie = RetainTrie::new();
+        retain_v5(&mut trie, "sensor/1", 1);
+        retain_v5(&mut trie, "sensor/2", 60);
+        retain(&mut trie, "sensor/3", b"on");
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        let messages = trie.take_matched("sensor/+");
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            match message {
+                RetainedMessage::V3(packet) => assert_eq!(packet.topic(), "sensor/3"),
+                RetainedMessage::V5(packet) => {
+                    assert_eq!(packet.topic(), "sensor/2");
+                    assert_eq!(
+                        packet.properties().props(),
+                        [v5::Property::MessageExpiryInterval(U32Data::new(55))]
+                    );
+                }
+            }
+        }
+        // Expired message is removed from store.
+        assert_eq!(trie.len(), 2);
+        assert_eq!(match_topics(&trie, "sensor/#"), ["sensor/2", "sensor/3"]);
+    }
+}
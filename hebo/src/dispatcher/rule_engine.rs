@@ -5,11 +5,40 @@
 //! `RuleEngine` app handler
 
 use super::Dispatcher;
+use crate::commands::DispatcherToRuleEngineCmd;
 use crate::dispatcher::RuleEngineToDispatcherCmd;
+use crate::session::OutgoingPacket;
+use crate::types::ListenerId;
 
 impl Dispatcher {
-    #[allow(clippy::unused_async)]
     pub(super) async fn handle_rule_engine_cmd(&mut self, cmd: RuleEngineToDispatcherCmd) {
-        log::info!("cmd: {:?}", cmd);
+        match cmd {
+            RuleEngineToDispatcherCmd::Publish(listener_id, packet) => {
+                self.dispatch_outgoing_packet(listener_id, packet).await;
+            }
+        }
+    }
+
+    /// Send message received from client to rule engine if rules are set,
+    /// or dispatch it directly.
+    pub(super) async fn route_publish(&mut self, listener_id: ListenerId, packet: OutgoingPacket) {
+        if !self.rule_engine_enabled {
+            self.dispatch_outgoing_packet(listener_id, packet).await;
+            return;
+        }
+        let cmd = DispatcherToRuleEngineCmd::Publish(listener_id, packet);
+        if let Err(err) = self.rule_engine_sender.send(cmd).await {
+            log::error!(
+                "dispatcher: Failed to send publish packet to rule engine, err: {:?}",
+                err
+            );
+        }
+    }
+
+    async fn dispatch_outgoing_packet(&mut self, listener_id: ListenerId, packet: OutgoingPacket) {
+        match packet {
+            OutgoingPacket::V3(packet) => self.dispatch_publish(listener_id, &packet).await,
+            OutgoingPacket::V5(packet) => self.dispatch_publish_v5(listener_id, &packet).await,
+        }
     }
 }
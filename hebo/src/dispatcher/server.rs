@@ -0,0 +1,53 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Handles commands from server context.
+
+use super::Dispatcher;
+use crate::commands::ServerContextToDispatcherCmd;
+use crate::types::SubscriptionInfo;
+
+impl Dispatcher {
+    pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToDispatcherCmd) {
+        match cmd {
+            ServerContextToDispatcherCmd::ListenerAdded(listener_id, address, sender) => {
+                log::info!("dispatcher: Add listener #{}, {}", listener_id, address);
+                self.listener_senders.insert(listener_id, sender);
+                self.metrics_on_listener_added(listener_id, address).await;
+            }
+            ServerContextToDispatcherCmd::ListenerRemoved(listener_id) => {
+                log::info!("dispatcher: Remove listener #{}", listener_id);
+                if self.listener_senders.remove(&listener_id).is_some() {
+                    self.metrics_on_listener_removed(listener_id).await;
+                }
+            }
+            ServerContextToDispatcherCmd::CountSubscriptions(session_gids, resp_tx) => {
+                let counts = session_gids
+                    .into_iter()
+                    .map(|session_gid| self.sub_trie.session_subscription_count(session_gid))
+                    .collect();
+                if resp_tx.send(counts).is_err() {
+                    log::warn!("dispatcher: Failed to send subscription counts to server ctx");
+                }
+            }
+            ServerContextToDispatcherCmd::GetSubscriptions(session_gid, resp_tx) => {
+                let mut subscriptions: Vec<SubscriptionInfo> = self
+                    .sub_trie
+                    .session_subscriptions(session_gid)
+                    .into_iter()
+                    .map(|(topic, qos)| SubscriptionInfo {
+                        topic,
+                        qos: qos as u8,
+                    })
+                    .collect();
+                subscriptions.sort_by(|a, b| a.topic.cmp(&b.topic));
+                if resp_tx.send(subscriptions).is_err() {
+                    log::warn!("dispatcher: Failed to send subscriptions to server ctx");
+                }
+            }
+            // Handled in run_loop().
+            ServerContextToDispatcherCmd::Stop => (),
+        }
+    }
+}
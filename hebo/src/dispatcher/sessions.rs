@@ -2,24 +2,454 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use std::collections::HashMap;
+use codec::{
+    ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, QoS, StringData, U32Data,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use tokio::time::Instant;
 
-use crate::session::CachedSession;
+use super::{trie::SubTrie, Dispatcher};
+use crate::config::QueueFullPolicy;
+use crate::error::{Error, ErrorKind};
+use crate::session::{CachedSession, OutgoingPacket};
+use crate::types::{ListenerId, SessionGid};
 
+/// Listener id of sessions restored from disk, which belong to no listener.
+const RESTORED_LISTENER_ID: ListenerId = ListenerId::MAX;
+
+/// Version of cached sessions file format.
+const SESSIONS_FILE_VERSION: u8 = 1;
+
+/// Sessions of disconnected clients with clean session flag off.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug)]
 pub struct CachedSessions {
-    map: HashMap<String, CachedSession>,
+    /// `client_id` -> (gid of disconnected session, session state).
+    map: HashMap<String, (SessionGid, CachedSession)>,
+
+    /// Subscriptions of disconnected sessions are kept in sub trie with their old gid.
+    gids: HashMap<SessionGid, String>,
+
+    /// Sessions with expiry interval, ordered by expiry time.
+    expiry: BTreeSet<(Instant, SessionGid)>,
+
+    max_queued_messages: usize,
+    queue_full_policy: QueueFullPolicy,
 }
 
 impl CachedSessions {
-    pub fn new() -> Self {
+    pub fn new(max_queued_messages: usize, queue_full_policy: QueueFullPolicy) -> Self {
         Self {
             map: HashMap::new(),
+            gids: HashMap::new(),
+            expiry: BTreeSet::new(),
+            max_queued_messages,
+            queue_full_policy,
+        }
+    }
+
+    /// Cache state of disconnected session.
+    ///
+    /// Returns gid of the discarded session, either `session_gid` if its expiry interval
+    /// is 0, or an older session of the same client. Its subscriptions shall be removed.
+    pub fn insert(
+        &mut self,
+        session_gid: SessionGid,
+        cached_session: CachedSession,
+    ) -> Option<SessionGid> {
+        let expire_at = match cached_session.expiry_interval() {
+            Some(interval) if interval.is_zero() => return Some(session_gid),
+            Some(interval) => Instant::now().checked_add(interval),
+            None => None,
+        };
+        if let Some(expire_at) = expire_at {
+            self.expiry.insert((expire_at, session_gid));
+        }
+
+        let client_id = cached_session.client_id().to_string();
+        self.gids.insert(session_gid, client_id.clone());
+        let (old_gid, _old_session) = self.map.insert(client_id, (session_gid, cached_session))?;
+        if old_gid == session_gid {
+            None
+        } else {
+            self.gids.remove(&old_gid);
+            Some(old_gid)
+        }
+    }
+
+    /// Remove cached session of `client_id`, returns its old gid and session state.
+    pub fn pop(&mut self, client_id: &str) -> Option<(SessionGid, CachedSession)> {
+        let (session_gid, cached_session) = self.map.remove(client_id)?;
+        self.gids.remove(&session_gid);
+        Some((session_gid, cached_session))
+    }
+
+    /// Get the earliest time when a cached session expires.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.expiry
+            .first()
+            .map(|(expire_at, _session_gid)| *expire_at)
+    }
+
+    /// Remove sessions expired before `now`, returns their gids.
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<SessionGid> {
+        let mut expired = Vec::new();
+        while let Some(&(expire_at, session_gid)) = self.expiry.first() {
+            if expire_at > now {
+                break;
+            }
+            self.expiry.pop_first();
+            // Session may be resumed or replaced already.
+            if let Some(client_id) = self.gids.remove(&session_gid) {
+                self.map.remove(&client_id);
+                expired.push(session_gid);
+            }
+        }
+        expired
+    }
+
+    /// Get all cached sessions with their gids.
+    pub fn iter(&self) -> impl Iterator<Item = (SessionGid, &CachedSession)> {
+        self.map
+            .values()
+            .map(|(session_gid, cached_session)| (*session_gid, cached_session))
+    }
+
+    /// Serialize cached sessions and their subscriptions in `sub_trie`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any session failed to encode.
+    pub fn encode(&self, sub_trie: &SubTrie) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![SESSIONS_FILE_VERSION];
+        let len = u32::try_from(self.map.len())
+            .map_err(|_err| Error::new(ErrorKind::EncodeError, "Too many cached sessions"))?;
+        U32Data::new(len).encode(&mut buf)?;
+        for (session_gid, cached_session) in self.iter() {
+            let subscriptions = sub_trie.session_subscriptions(session_gid);
+            let len = u32::try_from(subscriptions.len())
+                .map_err(|_err| Error::new(ErrorKind::EncodeError, "Too many subscriptions"))?;
+            U32Data::new(len).encode(&mut buf)?;
+            for (filter, qos) in subscriptions {
+                StringData::from(&filter)
+                    .map_err(EncodeError::from)?
+                    .encode(&mut buf)?;
+                qos.encode(&mut buf)?;
+            }
+            cached_session.encode(&mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    /// Restore cached sessions serialized by `encode()`, and their subscriptions into `sub_trie`.
+    ///
+    /// Restored sessions are assigned new gids, as old listeners and sessions are gone.
+    /// Returns number of restored sessions.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `buf` is malformed.
+    pub fn decode(&mut self, buf: &[u8], sub_trie: &mut SubTrie) -> Result<usize, Error> {
+        let mut ba = ByteArray::new(buf);
+        if ba.read_byte().map_err(DecodeError::from)? != SESSIONS_FILE_VERSION {
+            return Err(Error::new(
+                ErrorKind::DecodeError,
+                "Unsupported cached sessions file version",
+            ));
+        }
+        let len = U32Data::decode(&mut ba)?.value();
+        for session_id in 0..len {
+            let n_subscriptions = U32Data::decode(&mut ba)?.value();
+            let mut subscriptions = Vec::new();
+            for _i in 0..n_subscriptions {
+                let filter = StringData::decode(&mut ba)?;
+                let qos = QoS::try_from(ba.read_byte().map_err(DecodeError::from)?)?;
+                subscriptions.push((filter.as_ref().to_string(), qos));
+            }
+            let cached_session = CachedSession::decode(&mut ba)?;
+
+            let session_gid = SessionGid::new(RESTORED_LISTENER_ID, u64::from(session_id));
+            sub_trie.restore_session(session_gid, &subscriptions);
+            if let Some(discarded_gid) = self.insert(session_gid, cached_session) {
+                sub_trie.remove_session(discarded_gid);
+            }
+        }
+        if ba.remaining_bytes() > 0 {
+            return Err(DecodeError::InvalidRemainingLength.into());
+        }
+        Ok(len as usize)
+    }
+
+    /// Returns true if `session_gid` belongs to a disconnected session.
+    pub fn contains(&self, session_gid: SessionGid) -> bool {
+        self.gids.contains_key(&session_gid)
+    }
+
+    /// Queue message to disconnected session.
+    ///
+    /// Returns false if message is dropped.
+    pub fn enqueue(&mut self, session_gid: SessionGid, packet: OutgoingPacket) -> bool {
+        let Some(client_id) = self.gids.get(&session_gid) else {
+            return false;
+        };
+        let Some((_gid, cached_session)) = self.map.get_mut(client_id) else {
+            return false;
+        };
+        cached_session.enqueue(packet, self.max_queued_messages, self.queue_full_policy)
+    }
+}
+
+impl Dispatcher {
+    pub(super) async fn cache_session(
+        &mut self,
+        session_gid: SessionGid,
+        cached_session: CachedSession,
+    ) {
+        if let Some(discarded_gid) = self.cached_sessions.insert(session_gid, cached_session) {
+            self.remove_session_subscriptions(discarded_gid).await;
+        }
+    }
+
+    /// Discard state of session of `client_id`, including its subscriptions and queued messages.
+    pub(super) async fn discard_cached_session(&mut self, client_id: &str) {
+        if let Some((session_gid, _cached_session)) = self.cached_sessions.pop(client_id) {
+            log::info!("dispatcher: Discard cached session of {}", client_id);
+            self.remove_session_subscriptions(session_gid).await;
+        }
+    }
+
+    /// Discard state of expired sessions, including their subscriptions and queued messages.
+    pub(super) async fn remove_expired_sessions(&mut self) {
+        for session_gid in self.cached_sessions.remove_expired(Instant::now()) {
+            log::info!("dispatcher: Session {:?} expired", session_gid);
+            self.remove_session_subscriptions(session_gid).await;
+        }
+    }
+
+    async fn remove_session_subscriptions(&mut self, session_gid: SessionGid) {
+        let filters = self.sub_trie.remove_session(session_gid);
+        if !filters.is_empty() {
+            // Subscriptions of restored sessions are not counted in metrics of any listener.
+            if session_gid.listener_id() != RESTORED_LISTENER_ID {
+                self.metrics_on_subscription_removed(session_gid.listener_id(), filters.len())
+                    .await;
+            }
+            let counts = self
+                .sub_trie
+                .subscriber_counts(filters.iter().map(String::as_str));
+            self.metrics_on_topic_subscribers_changed(counts).await;
+        }
+    }
+
+    /// Restore cached sessions saved in `path`, and save them back to it when dispatcher stops.
+    ///
+    /// Sessions still connected when server stops are saved too, if they are not
+    /// clean sessions.
+    pub fn load_sessions(&mut self, path: &Path) {
+        self.sessions_file = Some(path.to_path_buf());
+        let buf = match fs::read(path) {
+            Ok(buf) => buf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+            Err(err) => {
+                log::error!(
+                    "dispatcher: Failed to read sessions file {:?}, err: {:?}",
+                    path,
+                    err
+                );
+                return;
+            }
+        };
+        match self.cached_sessions.decode(&buf, &mut self.sub_trie) {
+            Ok(n_sessions) => log::info!("dispatcher: Restored {} cached sessions", n_sessions),
+            Err(err) => log::error!(
+                "dispatcher: Failed to restore sessions from {:?}, err: {:?}",
+                path,
+                err
+            ),
+        }
+    }
+
+    /// Save cached sessions to sessions file if persistence is enabled.
+    pub(super) fn save_sessions(&self) {
+        let Some(path) = &self.sessions_file else {
+            return;
+        };
+        if let Err(err) = self.write_sessions_file(path) {
+            log::error!(
+                "dispatcher: Failed to save sessions to {:?}, err: {:?}",
+                path,
+                err
+            );
+        }
+    }
+
+    fn write_sessions_file(&self, path: &Path) -> Result<(), Error> {
+        let buf = self.cached_sessions.encode(&self.sub_trie)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Replace old file atomically, so that it is never left half written.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, buf)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Queue message sent to a disconnected session, it is delivered after client reconnects.
+    ///
+    /// `QoS` 0 messages are dropped. Returns false if the session is online.
+    pub(super) fn queue_offline_message<F>(
+        &mut self,
+        session_gid: SessionGid,
+        qos: QoS,
+        packet: F,
+    ) -> bool
+    where
+        F: FnOnce() -> OutgoingPacket,
+    {
+        if !self.cached_sessions.contains(session_gid) {
+            return false;
+        }
+        if qos != QoS::AtMostOnce && !self.cached_sessions.enqueue(session_gid, packet()) {
+            log::warn!(
+                "dispatcher: Offline queue of {:?} is full, drop message",
+                session_gid
+            );
         }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, PacketId, QoS};
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    use super::{CachedSessions, RESTORED_LISTENER_ID};
+    use crate::config::QueueFullPolicy;
+    use crate::dispatcher::SubTrie;
+    use crate::session::{CachedSession, InflightMessages, OutgoingPacket};
+    use crate::types::SessionGid;
+
+    fn new_packet() -> OutgoingPacket {
+        OutgoingPacket::V3(v3::PublishPacket::new("offline/test", QoS::AtLeastOnce, b"1").unwrap())
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let old_gid = SessionGid::new(1, 1);
+        let session = CachedSession::new("client-1".to_string(), InflightMessages::new(0));
+        sessions.insert(old_gid, session);
+        assert!(sessions.contains(old_gid));
+        assert!(sessions.enqueue(old_gid, new_packet()));
+        assert!(!sessions.enqueue(SessionGid::new(1, 2), new_packet()));
+
+        let (gid, session) = sessions.pop("client-1").unwrap();
+        assert_eq!(gid, old_gid);
+        assert_eq!(session.queued_len(), 1);
+        assert!(!sessions.contains(old_gid));
+        assert!(sessions.pop("client-1").is_none());
+    }
+
+    fn new_session(expiry_interval: Option<Duration>) -> CachedSession {
+        let mut session = CachedSession::new("client-1".to_string(), InflightMessages::new(0));
+        session.set_expiry_interval(expiry_interval);
+        session
+    }
+
+    #[test]
+    fn test_expire_after_interval() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let gid = SessionGid::new(1, 1);
+        let now = Instant::now();
+        assert!(sessions
+            .insert(gid, new_session(Some(Duration::from_secs(10))))
+            .is_none());
+        assert!(sessions.next_expiry().unwrap() >= now + Duration::from_secs(10));
+
+        assert!(sessions.remove_expired(now).is_empty());
+        assert!(sessions.contains(gid));
+        assert_eq!(
+            sessions.remove_expired(now + Duration::from_secs(11)),
+            [gid]
+        );
+        assert!(!sessions.contains(gid));
+        assert!(sessions.pop("client-1").is_none());
+        assert!(sessions.next_expiry().is_none());
+    }
+
+    #[test]
+    fn test_never_expire() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let gid = SessionGid::new(1, 1);
+        assert!(sessions.insert(gid, new_session(None)).is_none());
+        assert!(sessions.next_expiry().is_none());
+        let far_future = Instant::now() + Duration::from_secs(u64::from(u32::MAX));
+        assert!(sessions.remove_expired(far_future).is_empty());
+        assert!(sessions.contains(gid));
+    }
+
+    #[test]
+    fn test_expire_immediately() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let gid = SessionGid::new(1, 1);
+        assert_eq!(
+            sessions.insert(gid, new_session(Some(Duration::ZERO))),
+            Some(gid)
+        );
+        assert!(!sessions.contains(gid));
+        assert!(sessions.pop("client-1").is_none());
     }
 
-    pub fn pop(&mut self, client_id: &str) -> Option<CachedSession> {
-        self.map.remove(client_id)
+    #[test]
+    fn test_resumed_session_not_expired() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let old_gid = SessionGid::new(1, 1);
+        let interval = Some(Duration::from_secs(10));
+        assert!(sessions.insert(old_gid, new_session(interval)).is_none());
+        // Client reconnects and disconnects again before old session expires.
+        assert!(sessions.pop("client-1").is_some());
+        let new_gid = SessionGid::new(1, 2);
+        assert!(sessions.insert(new_gid, new_session(None)).is_none());
+
+        let later = Instant::now() + Duration::from_secs(11);
+        assert!(sessions.remove_expired(later).is_empty());
+        assert!(sessions.contains(new_gid));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut sessions = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let mut sub_trie = SubTrie::new();
+        let gid = SessionGid::new(1, 1);
+        let packet =
+            v3::SubscribePacket::new("offline/#", QoS::ExactOnce, PacketId::new(1)).unwrap();
+        sub_trie.subscribe(gid, &packet);
+        sessions.insert(gid, new_session(Some(Duration::from_secs(10))));
+        assert!(sessions.enqueue(gid, new_packet()));
+        let buf = sessions.encode(&sub_trie).unwrap();
+
+        let mut restored = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        let mut restored_trie = SubTrie::new();
+        assert_eq!(restored.decode(&buf, &mut restored_trie).unwrap(), 1);
+        let (new_gid, session) = restored.pop("client-1").unwrap();
+        assert_eq!(new_gid.listener_id(), RESTORED_LISTENER_ID);
+        assert_eq!(session.expiry_interval(), Some(Duration::from_secs(10)));
+        assert_eq!(session.queued_len(), 1);
+        assert_eq!(restored_trie.matches("offline/status"), [new_gid]);
+        assert_eq!(
+            restored_trie.session_subscriptions(new_gid),
+            [("offline/#".to_string(), QoS::ExactOnce)]
+        );
+
+        // Truncated data is rejected.
+        let mut restored = CachedSessions::new(10, QueueFullPolicy::DropOldest);
+        assert!(restored
+            .decode(&buf[..buf.len() - 1], &mut SubTrie::new())
+            .is_err());
     }
 }
@@ -4,24 +4,223 @@
 
 //! Manage subscription trie.
 
-use codec::{v3, v5, SubscribePattern};
+use codec::{v3, v5, Packet, QoS, SubTopic, SubscribePattern, VarInt};
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::HashMap;
+use std::str::Split;
 
 use super::Dispatcher;
 use crate::commands::DispatcherToListenerCmd;
-use crate::types::SessionGid;
+use crate::session::OutgoingPacket;
+use crate::types::{ListenerId, SessionGid};
 
+/// Members of a shared subscription, messages are delivered to them in turn.
+#[derive(Debug, Default, Clone)]
+struct ShareGroup {
+    members: Vec<SessionGid>,
+    next: usize,
+}
+
+impl ShareGroup {
+    fn add(&mut self, session_gid: SessionGid) {
+        if !self.members.contains(&session_gid) {
+            self.members.push(session_gid);
+        }
+    }
+
+    fn remove(&mut self, session_gid: SessionGid) {
+        if let Some(index) = self.members.iter().position(|gid| *gid == session_gid) {
+            self.members.remove(index);
+            if index < self.next {
+                self.next -= 1;
+            }
+        }
+    }
+
+    /// Select next member with round-robin.
+    fn next_member(&mut self) -> Option<SessionGid> {
+        if self.members.is_empty() {
+            return None;
+        }
+        if self.next >= self.members.len() {
+            self.next = 0;
+        }
+        let session_gid = self.members[self.next];
+        self.next += 1;
+        Some(session_gid)
+    }
+}
+
+/// Topic filter subscribed by a session.
+#[derive(Debug, Clone)]
+struct Subscription {
+    pattern: SubscribePattern,
+
+    /// Subscription identifier in v5 subscribe packet, sent back in matching messages.
+    id: Option<VarInt>,
+}
+
+/// Returns true if topic filter matches topic name, both shall be valid.
+#[must_use]
+pub fn is_filter_match(filter: &str, topic: &str) -> bool {
+    // The Server MUST NOT match Topic Filters starting with a wildcard character (# or +)
+    // with Topic Names beginning with a $ character [MQTT-4.7.2-1].
+    if topic.starts_with('$') && (filter.starts_with('#') || filter.starts_with('+')) {
+        return false;
+    }
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => (),
+            (level, Some(topic_level)) if level == topic_level => (),
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Get subscription identifier in properties of subscribe packet.
+pub fn subscription_id(packet: &v5::SubscribePacket) -> Option<VarInt> {
+    packet
+        .properties()
+        .props()
+        .iter()
+        .find_map(|property| match property {
+            v5::Property::SubscriptionIdentifier(id) => Some(*id),
+            _ => None,
+        })
+}
+
+/// Sessions matching a topic name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subscribers {
+    Empty,
+
+    /// Point-to-point topic, found without allocation.
+    Single(SessionGid),
+
+    /// More than one session, or any shared subscription, matches.
+    Multiple(Vec<SessionGid>),
+}
+
+#[derive(Debug, Default, Clone)]
+struct SubNode {
+    /// Sessions subscribed to the topic filter ending at this node.
+    sessions: FxHashSet<SessionGid>,
+
+    /// Shared subscriptions to the topic filter ending at this node, indexed by share name.
+    groups: HashMap<String, ShareGroup>,
+
+    /// Child levels, `+` and `#` wildcards are stored as normal levels.
+    ///
+    /// Levels and share names are sent by clients, so `SipHash` is used to resist hash flooding.
+    children: HashMap<String, Self>,
+}
+
+impl SubNode {
+    fn is_empty(&self) -> bool {
+        self.sessions.is_empty() && self.groups.is_empty() && self.children.is_empty()
+    }
+
+    /// Number of sessions subscribed to the topic filter ending at this node,
+    /// including members of share groups.
+    fn subscribers(&self) -> usize {
+        self.sessions.len()
+            + self
+                .groups
+                .values()
+                .map(|group| group.members.len())
+                .sum::<usize>()
+    }
+
+    /// Collect subscribers of the topic filter ending at this node.
+    fn collect(&mut self, sessions: &mut FxHashSet<SessionGid>, shared: &mut Vec<SessionGid>) {
+        sessions.extend(&self.sessions);
+        shared.extend(self.groups.values_mut().filter_map(ShareGroup::next_member));
+    }
+
+    /// Record subscriber of the topic filter ending at this node into `found`.
+    ///
+    /// Returns false if more than one session is found, or any shared subscription exists.
+    fn collect_single(&self, found: &mut Option<SessionGid>) -> bool {
+        if !self.groups.is_empty() {
+            return false;
+        }
+        for session_gid in &self.sessions {
+            if found.replace(*session_gid).is_some() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Subscriptions indexed by topic levels.
+///
+/// Matching a topic name only walks the exact, `+` and `#` branches of each level,
+/// so its cost depends on number of topic levels instead of number of subscriptions.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Default, Clone)]
 pub struct SubTrie {
-    map: HashMap<SessionGid, HashMap<String, SubscribePattern>>,
+    root: SubNode,
+
+    /// Subscriptions of each session, indexed by topic filter.
+    ///
+    /// Topic filters are sent by clients, so they are hashed with the randomly keyed
+    /// `SipHash` to resist hash flooding. `FxHashMap` is only used for keys assigned by broker.
+    map: FxHashMap<SessionGid, HashMap<String, Subscription>>,
+
+    /// Maximum number of subscribers of a topic filter, 0 means no limit.
+    max_subscribers: usize,
 }
 
 impl SubTrie {
+    #[must_use]
     pub fn new() -> Self {
-        Self {
-            map: HashMap::new(),
+        Self::default()
+    }
+
+    /// Set maximum number of sessions subscribed to a single topic filter.
+    ///
+    /// Set to 0 to disable this limitation.
+    pub fn set_max_subscribers(&mut self, max_subscribers: usize) -> &mut Self {
+        self.max_subscribers = max_subscribers;
+        self
+    }
+
+    /// Returns true if topic filter of `pattern` has reached maximum number of subscribers.
+    fn is_full(root: &SubNode, max_subscribers: usize, pattern: &SubscribePattern) -> bool {
+        max_subscribers > 0
+            && Self::find_node(root, pattern.topic().topic()).map_or(0, SubNode::subscribers)
+                >= max_subscribers
+    }
+
+    fn find_node<'a>(root: &'a SubNode, topic: &str) -> Option<&'a SubNode> {
+        let mut node = root;
+        for level in topic.split('/') {
+            node = node.children.get(level)?;
         }
+        Some(node)
+    }
+
+    /// Get number of subscribers of each topic filter in `filters`.
+    ///
+    /// Share groups of the same topic filter are counted together, and invalid
+    /// topic filters are skipped.
+    pub fn subscriber_counts<'a, I>(&self, filters: I) -> Vec<(String, usize)>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        filters
+            .into_iter()
+            .filter_map(|filter| SubscribePattern::parse(filter, QoS::AtMostOnce).ok())
+            .map(|pattern| {
+                let topic = pattern.topic().topic();
+                let count = Self::find_node(&self.root, topic).map_or(0, SubNode::subscribers);
+                (topic.clone(), count)
+            })
+            .collect()
     }
 
     pub fn subscribe(
@@ -29,13 +228,7 @@ impl SubTrie {
         session_gid: SessionGid,
         packet: &v3::SubscribePacket,
     ) -> (v3::SubscribeAckPacket, usize) {
-        let patterns = if let Some(patterns) = self.map.get_mut(&session_gid) {
-            patterns
-        } else {
-            let patterns = HashMap::new();
-            self.map.insert(session_gid, patterns);
-            self.map.get_mut(&session_gid).unwrap()
-        };
+        let patterns = self.map.entry(session_gid).or_default();
 
         // If a Server receives a SUBSCRIBE packet that contains multiple Topic Filters
         // it MUST handle that packet as if it had received a sequence of multiple SUBSCRIBE packets,
@@ -43,12 +236,24 @@ impl SubTrie {
         let mut ack_vec = vec![];
         let mut pattern_added = 0;
         for topic in packet.topics() {
-            // TODO(Shaohua): Send retained messages.
-            // TODO(Shaohua): Check topic filter has been subscribed.
-            // TODO(Shaohua): Update qos in SubscribeAck.
             match SubscribePattern::parse(topic.topic(), topic.qos()) {
+                Ok(pattern)
+                    if !patterns.contains_key(topic.topic())
+                        && Self::is_full(&self.root, self.max_subscribers, &pattern) =>
+                {
+                    log::warn!(
+                        "trie: Too many subscribers of topic: {}, reject {:?}",
+                        topic.topic(),
+                        session_gid
+                    );
+                    ack_vec.push(v3::SubscribeAck::Failed);
+                }
                 Ok(pattern) => {
-                    patterns.insert(topic.topic().to_string(), pattern);
+                    Self::insert(&mut self.root, &pattern, session_gid);
+                    patterns.insert(
+                        topic.topic().to_string(),
+                        Subscription { pattern, id: None },
+                    );
                     ack_vec.push(v3::SubscribeAck::QoS(topic.qos()));
                     pattern_added += 1;
                 }
@@ -74,25 +279,36 @@ impl SubTrie {
         session_gid: SessionGid,
         packet: &v5::SubscribePacket,
     ) -> (v5::SubscribeAckPacket, usize) {
-        let patterns = if let Some(patterns) = self.map.get_mut(&session_gid) {
-            patterns
-        } else {
-            let patterns = HashMap::new();
-            self.map.insert(session_gid, patterns);
-            self.map.get_mut(&session_gid).unwrap()
-        };
+        let patterns = self.map.entry(session_gid).or_default();
+        // Subscription identifier is associated with all topic filters of this packet,
+        // and replaces the one of existing subscription.
+        let id = subscription_id(packet);
 
-        // TODO(Shaohua): Add comments
+        // Topic filters are handled one by one, and their reason codes are combined
+        // into a single SUBACK packet.
         let mut reasons = vec![];
         let mut pattern_added = 0;
         for topic in packet.topics() {
-            // TODO(Shaohua): Send retained messages.
-            // TODO(Shaohua): Check topic filter has been subscribed.
-            // TODO(Shaohua): Update qos in SubscribeAck.
             match SubscribePattern::parse(topic.topic(), topic.qos()) {
+                Ok(pattern)
+                    if !patterns.contains_key(topic.topic())
+                        && Self::is_full(&self.root, self.max_subscribers, &pattern) =>
+                {
+                    log::warn!(
+                        "trie: Too many subscribers of topic: {}, reject {:?}",
+                        topic.topic(),
+                        session_gid
+                    );
+                    reasons.push(v5::ReasonCode::QuotaExceeded);
+                }
                 Ok(pattern) => {
-                    patterns.insert(topic.topic().to_string(), pattern);
-                    reasons.push(v5::ReasonCode::Success);
+                    Self::insert(&mut self.root, &pattern, session_gid);
+                    patterns.insert(topic.topic().to_string(), Subscription { pattern, id });
+                    reasons.push(match topic.qos() {
+                        QoS::AtMostOnce => v5::ReasonCode::Success,
+                        QoS::AtLeastOnce => v5::ReasonCode::GrantedQoS1,
+                        QoS::ExactOnce => v5::ReasonCode::GrantedQoS2,
+                    });
                     pattern_added += 1;
                 }
                 Err(err) => {
@@ -117,25 +333,7 @@ impl SubTrie {
         session_gid: SessionGid,
         packet: &v3::UnsubscribePacket,
     ) -> usize {
-        self.map.get_mut(&session_gid).map_or_else(
-            || {
-                log::error!("trie: No subscription for gid: {:?}", session_gid);
-                0
-            },
-            |set| {
-                let to_be_removed: Vec<String> = packet
-                    .topics()
-                    .iter()
-                    .filter(|topic| set.contains_key(topic.as_ref()))
-                    .map(|topic| topic.as_ref().to_string())
-                    .collect();
-                for p in &to_be_removed {
-                    set.remove(p);
-                }
-
-                to_be_removed.len()
-            },
-        )
+        self.remove_topics(session_gid, packet.topics())
     }
 
     pub fn unsubscribe_v5(
@@ -143,100 +341,766 @@ impl SubTrie {
         session_gid: SessionGid,
         packet: &v5::UnsubscribePacket,
     ) -> usize {
-        self.map.get_mut(&session_gid).map_or_else(
-            || {
-                log::error!("trie: No subscription for gid: {:?}", session_gid);
-                0
-            },
-            |set| {
-                let to_be_removed: Vec<String> = packet
-                    .topics()
+        self.remove_topics(session_gid, packet.topics())
+    }
+
+    fn remove_topics(&mut self, session_gid: SessionGid, topics: &[SubTopic]) -> usize {
+        let Some(patterns) = self.map.get_mut(&session_gid) else {
+            log::error!("trie: No subscription for gid: {:?}", session_gid);
+            return 0;
+        };
+        let mut n_removed = 0;
+        for topic in topics {
+            if let Some(Subscription { pattern, .. }) = patterns.remove(topic.as_ref()) {
+                let levels: Vec<&str> = pattern.topic().topic().split('/').collect();
+                Self::remove_node(&mut self.root, &levels, &pattern, session_gid);
+                n_removed += 1;
+            }
+        }
+
+        // Release memory of sessions without any subscriptions.
+        if patterns.is_empty() {
+            self.map.remove(&session_gid);
+        }
+        n_removed
+    }
+
+    /// Remove all subscriptions of `session_gid`, when its session state is discarded.
+    ///
+    /// Returns removed topic filters.
+    pub fn remove_session(&mut self, session_gid: SessionGid) -> Vec<String> {
+        let Some(patterns) = self.map.remove(&session_gid) else {
+            return Vec::new();
+        };
+        patterns
+            .into_iter()
+            .map(|(filter, Subscription { pattern, .. })| {
+                let levels: Vec<&str> = pattern.topic().topic().split('/').collect();
+                Self::remove_node(&mut self.root, &levels, &pattern, session_gid);
+                filter
+            })
+            .collect()
+    }
+
+    /// Move subscriptions of `old_gid` to `new_gid`, when client of a persistent session
+    /// reconnects.
+    ///
+    /// Returns number of moved topic filters.
+    pub fn move_session(&mut self, old_gid: SessionGid, new_gid: SessionGid) -> usize {
+        let Some(old_patterns) = self.map.remove(&old_gid) else {
+            return 0;
+        };
+        let n_moved = old_patterns.len();
+        let patterns = self.map.entry(new_gid).or_default();
+        for (topic, subscription) in old_patterns {
+            let pattern = &subscription.pattern;
+            let levels: Vec<&str> = pattern.topic().topic().split('/').collect();
+            Self::remove_node(&mut self.root, &levels, pattern, old_gid);
+            Self::insert(&mut self.root, pattern, new_gid);
+            patterns.insert(topic, subscription);
+        }
+        n_moved
+    }
+
+    /// Get topic filters and their `QoS` subscribed by `session_gid`.
+    #[must_use]
+    pub fn session_subscriptions(&self, session_gid: SessionGid) -> Vec<(String, QoS)> {
+        self.map
+            .get(&session_gid)
+            .map_or_else(Vec::new, |patterns| {
+                patterns
                     .iter()
-                    .filter(|topic| set.contains_key(topic.as_ref()))
-                    .map(|topic| topic.as_ref().to_string())
-                    .collect();
-                for p in &to_be_removed {
-                    set.remove(p);
+                    .map(|(filter, subscription)| (filter.clone(), subscription.pattern.qos()))
+                    .collect()
+            })
+    }
+
+    /// Get number of topic filters subscribed by `session_gid`.
+    #[must_use]
+    pub fn session_subscription_count(&self, session_gid: SessionGid) -> usize {
+        self.map.get(&session_gid).map_or(0, HashMap::len)
+    }
+
+    /// Restore subscriptions of `session_gid` saved by `session_subscriptions()`.
+    ///
+    /// Subscriber limit is not checked, as these topic filters were accepted before.
+    /// Returns number of restored topic filters.
+    pub fn restore_session(
+        &mut self,
+        session_gid: SessionGid,
+        subscriptions: &[(String, QoS)],
+    ) -> usize {
+        let patterns = self.map.entry(session_gid).or_default();
+        let mut n_restored = 0;
+        for (filter, qos) in subscriptions {
+            match SubscribePattern::parse(filter, *qos) {
+                Ok(pattern) => {
+                    Self::insert(&mut self.root, &pattern, session_gid);
+                    patterns.insert(filter.clone(), Subscription { pattern, id: None });
+                    n_restored += 1;
+                }
+                Err(err) => {
+                    log::error!("trie: Invalid saved topic: {}, err: {:?}", filter, err);
                 }
+            }
+        }
+        if patterns.is_empty() {
+            self.map.remove(&session_gid);
+        }
+        n_restored
+    }
 
-                to_be_removed.len()
-            },
-        )
+    fn insert(root: &mut SubNode, pattern: &SubscribePattern, session_gid: SessionGid) {
+        let mut node = root;
+        for level in pattern.topic().topic().split('/') {
+            node = node.children.entry(level.to_string()).or_default();
+        }
+        if let Some(share_group) = pattern.share_group() {
+            node.groups
+                .entry(share_group.to_string())
+                .or_default()
+                .add(session_gid);
+        } else {
+            node.sessions.insert(session_gid);
+        }
     }
 
-    pub fn match_packet(&mut self, packet: &v3::PublishPacket) -> Vec<SessionGid> {
-        let mut vec = vec![];
-        let topic = packet.topic();
-        for (session_gid, topic_patterns) in &self.map {
-            for topic_pattern in topic_patterns.values() {
-                if topic_pattern.topic().is_match(topic) {
-                    vec.push(*session_gid);
-                    break;
+    fn remove_node(
+        node: &mut SubNode,
+        levels: &[&str],
+        pattern: &SubscribePattern,
+        session_gid: SessionGid,
+    ) {
+        let Some((level, rest)) = levels.split_first() else {
+            if let Some(share_group) = pattern.share_group() {
+                if let Some(group) = node.groups.get_mut(share_group) {
+                    group.remove(session_gid);
+                    if group.members.is_empty() {
+                        node.groups.remove(share_group);
+                    }
                 }
+            } else {
+                node.sessions.remove(&session_gid);
+            }
+            return;
+        };
+        if let Some(child) = node.children.get_mut(*level) {
+            Self::remove_node(child, rest, pattern, session_gid);
+            // Release empty branches.
+            if child.is_empty() {
+                node.children.remove(*level);
             }
         }
-        vec
     }
 
-    pub fn match_packet_v5(&mut self, packet: &v5::PublishPacket) -> Vec<SessionGid> {
-        let mut vec = vec![];
-        let topic = packet.topic();
-        for (session_gid, topic_patterns) in &self.map {
-            for topic_pattern in topic_patterns.values() {
-                if topic_pattern.topic().is_match(topic) {
-                    vec.push(*session_gid);
-                    break;
+    /// Get sessions with any topic filter matching `topic`.
+    ///
+    /// `topic` shall be a valid topic name. Each session is returned only once even if
+    /// more than one of its topic filters match. For each matching shared subscription,
+    /// only one member of the share group is returned, selected with round-robin.
+    pub fn matches(&mut self, topic: &str) -> Vec<SessionGid> {
+        let levels: Vec<&str> = topic.split('/').collect();
+        let mut sessions = FxHashSet::default();
+        let mut shared = Vec::new();
+        Self::match_node(&mut self.root, &levels, true, &mut sessions, &mut shared);
+        let mut sessions: Vec<SessionGid> = sessions.into_iter().collect();
+        sessions.extend(shared);
+        sessions
+    }
+
+    /// Get sessions with any topic filter matching `topic`, same as `matches()`.
+    ///
+    /// Topics with zero or one subscriber are resolved by walking the trie without
+    /// allocation, others fall back to `matches()` after the walk stops at the second one.
+    pub fn match_subscribers(&mut self, topic: &str) -> Subscribers {
+        let mut found = None;
+        if Self::match_single_node(&self.root, topic.split('/'), true, &mut found) {
+            found.map_or(Subscribers::Empty, Subscribers::Single)
+        } else {
+            Subscribers::Multiple(self.matches(topic))
+        }
+    }
+
+    /// Walk the same branches as `match_node()`, returns false once a second subscriber
+    /// is found.
+    fn match_single_node(
+        node: &SubNode,
+        mut levels: Split<'_, char>,
+        is_root: bool,
+        found: &mut Option<SessionGid>,
+    ) -> bool {
+        let level = levels.next();
+        let match_wildcards = !(is_root && level.map_or(false, |l| l.starts_with('$')));
+
+        if match_wildcards {
+            if let Some(child) = node.children.get("#") {
+                if !child.collect_single(found) {
+                    return false;
+                }
+            }
+        }
+
+        let Some(level) = level else {
+            return node.collect_single(found);
+        };
+
+        if match_wildcards {
+            if let Some(child) = node.children.get("+") {
+                if !Self::match_single_node(child, levels.clone(), false, found) {
+                    return false;
                 }
             }
         }
-        vec
+        node.children.get(level).map_or(true, |child| {
+            Self::match_single_node(child, levels, false, found)
+        })
+    }
+
+    fn match_node(
+        node: &mut SubNode,
+        levels: &[&str],
+        is_root: bool,
+        sessions: &mut FxHashSet<SessionGid>,
+        shared: &mut Vec<SessionGid>,
+    ) {
+        // The Server MUST NOT match Topic Filters starting with a wildcard character (# or +)
+        // with Topic Names beginning with a $ character [MQTT-4.7.2-1].
+        let match_wildcards = !(is_root && levels.first().map_or(false, |l| l.starts_with('$')));
+
+        // Multi-level wildcard matches all of remaining levels, including the parent level.
+        if match_wildcards {
+            if let Some(child) = node.children.get_mut("#") {
+                child.collect(sessions, shared);
+            }
+        }
+
+        let Some((level, rest)) = levels.split_first() else {
+            node.collect(sessions, shared);
+            return;
+        };
+
+        if match_wildcards {
+            if let Some(child) = node.children.get_mut("+") {
+                Self::match_node(child, rest, false, sessions, shared);
+            }
+        }
+        if let Some(child) = node.children.get_mut(*level) {
+            Self::match_node(child, rest, false, sessions, shared);
+        }
+    }
+
+    /// Get subscription identifiers of all subscriptions of `session_gid` matching `topic`.
+    #[must_use]
+    pub fn subscription_ids(&self, session_gid: SessionGid, topic: &str) -> Vec<VarInt> {
+        self.map
+            .get(&session_gid)
+            .map_or_else(Vec::new, |patterns| {
+                patterns
+                    .values()
+                    .filter_map(|subscription| {
+                        subscription.id.filter(|_id| {
+                            is_filter_match(subscription.pattern.topic().topic(), topic)
+                        })
+                    })
+                    .collect()
+            })
+    }
+
+    /// Copy `packet` with subscription identifiers of `session_gid` matching its topic.
+    #[must_use]
+    pub fn packet_for_session(
+        &self,
+        session_gid: SessionGid,
+        packet: &v5::PublishPacket,
+    ) -> v5::PublishPacket {
+        let mut packet = packet.clone();
+        for id in self.subscription_ids(session_gid, packet.topic()) {
+            if let Err(err) = packet
+                .properties_mut()
+                .push(v5::Property::SubscriptionIdentifier(id))
+            {
+                log::error!(
+                    "trie: Failed to add subscription identifier, err: {:?}",
+                    err
+                );
+            }
+        }
+        packet
+    }
+
+    pub fn match_packet(&mut self, packet: &v3::PublishPacket) -> Vec<SessionGid> {
+        self.matches(packet.topic())
+    }
+
+    pub fn match_packet_v5(&mut self, packet: &v5::PublishPacket) -> Vec<SessionGid> {
+        self.matches(packet.topic())
     }
 }
 
 impl Dispatcher {
     pub(super) async fn publish_packet_to_sub_trie(&mut self, packet: &v3::PublishPacket) {
-        // match topic in trie
-        for session_gid in self.sub_trie.match_packet(packet) {
-            // send packet to listener
-            if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
+        let bytes = packet.bytes().unwrap_or_default();
+        match self.sub_trie.match_subscribers(packet.topic()) {
+            Subscribers::Empty => (),
+            Subscribers::Single(session_gid) => {
+                if self.queue_offline_message(session_gid, packet.qos(), || {
+                    OutgoingPacket::V3(packet.clone())
+                }) {
+                    return;
+                }
                 let cmd =
                     DispatcherToListenerCmd::Publish(session_gid.session_id(), packet.clone());
-                if let Err(err) = listener_sender.send(cmd).await {
-                    log::error!(
-                        "dispatcher: Failed to send publish packet to listener: {}, err: {:?}",
-                        session_gid.listener_id(),
-                        err
-                    );
+                if self.send_to_listener(session_gid, cmd) {
+                    self.metrics_publish_packet_sent(session_gid.listener_id(), 1, bytes)
+                        .await;
+                }
+            }
+            Subscribers::Multiple(sessions) => {
+                // Number of packets sent to each listener.
+                let mut sent: FxHashMap<ListenerId, usize> = FxHashMap::default();
+                for session_gid in sessions {
+                    if self.queue_offline_message(session_gid, packet.qos(), || {
+                        OutgoingPacket::V3(packet.clone())
+                    }) {
+                        continue;
+                    }
+                    let cmd =
+                        DispatcherToListenerCmd::Publish(session_gid.session_id(), packet.clone());
+                    if self.send_to_listener(session_gid, cmd) {
+                        *sent.entry(session_gid.listener_id()).or_default() += 1;
+                    }
+                }
+                for (listener_id, count) in sent {
+                    self.metrics_publish_packet_sent(listener_id, count, count * bytes)
+                        .await;
                 }
-            } else {
-                log::error!(
-                    "dispatcher: Failed to get listener sender with id: {}",
-                    session_gid.listener_id()
-                );
             }
         }
     }
 
     pub(super) async fn publish_packet_to_sub_trie_v5(&mut self, packet: &v5::PublishPacket) {
-        // match topic in trie
-        for session_gid in self.sub_trie.match_packet_v5(packet) {
-            // send packet to listener
-            if let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) {
-                let cmd =
-                    DispatcherToListenerCmd::PublishV5(session_gid.session_id(), packet.clone());
-                if let Err(err) = listener_sender.send(cmd).await {
-                    log::error!(
-                        "dispatcher: Failed to send publish packet to listener: {}, err: {:?}",
-                        session_gid.listener_id(),
-                        err
-                    );
+        let bytes = packet.bytes().unwrap_or_default();
+        match self.sub_trie.match_subscribers(packet.topic()) {
+            Subscribers::Empty => (),
+            Subscribers::Single(session_gid) => {
+                let packet = self.sub_trie.packet_for_session(session_gid, packet);
+                if self.queue_offline_message(session_gid, packet.qos(), || {
+                    OutgoingPacket::V5(packet.clone())
+                }) {
+                    return;
+                }
+                let cmd = DispatcherToListenerCmd::PublishV5(session_gid.session_id(), packet);
+                if self.send_to_listener(session_gid, cmd) {
+                    self.metrics_publish_packet_sent(session_gid.listener_id(), 1, bytes)
+                        .await;
+                }
+            }
+            Subscribers::Multiple(sessions) => {
+                // Number of packets sent to each listener.
+                let mut sent: FxHashMap<ListenerId, usize> = FxHashMap::default();
+                for session_gid in sessions {
+                    let packet = self.sub_trie.packet_for_session(session_gid, packet);
+                    if self.queue_offline_message(session_gid, packet.qos(), || {
+                        OutgoingPacket::V5(packet.clone())
+                    }) {
+                        continue;
+                    }
+                    let cmd = DispatcherToListenerCmd::PublishV5(session_gid.session_id(), packet);
+                    if self.send_to_listener(session_gid, cmd) {
+                        *sent.entry(session_gid.listener_id()).or_default() += 1;
+                    }
+                }
+                for (listener_id, count) in sent {
+                    self.metrics_publish_packet_sent(listener_id, count, count * bytes)
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Send publish packet to listener of session, returns true if it is sent.
+    fn send_to_listener(&self, session_gid: SessionGid, cmd: DispatcherToListenerCmd) -> bool {
+        let Some(listener_sender) = self.listener_senders.get(&session_gid.listener_id()) else {
+            log::error!(
+                "dispatcher: Failed to get listener sender with id: {}",
+                session_gid.listener_id()
+            );
+            return false;
+        };
+        if let Err(err) = listener_sender.send(cmd) {
+            log::error!(
+                "dispatcher: Failed to send publish packet to listener: {}, err: {:?}",
+                session_gid.listener_id(),
+                err
+            );
+            false
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5, PacketId, QoS, VarInt};
+
+    use super::{is_filter_match, SubTrie, Subscribers};
+    use crate::types::SessionGid;
+
+    fn subscribe(trie: &mut SubTrie, gid: SessionGid, filter: &str) {
+        let packet = v3::SubscribePacket::new(filter, QoS::AtMostOnce, PacketId::new(1)).unwrap();
+        let (_ack, n_subscribed) = trie.subscribe(gid, &packet);
+        assert_eq!(n_subscribed, 1);
+    }
+
+    fn sorted_matches(trie: &mut SubTrie, topic: &str) -> Vec<SessionGid> {
+        let mut sessions = trie.matches(topic);
+        sessions.sort_by_key(|gid| (gid.listener_id(), gid.session_id()));
+        sessions
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe() {
+        let mut trie = SubTrie::new();
+        let gid = SessionGid::new(1, 1);
+        let packet =
+            v3::SubscribePacket::new("dev/+/status", QoS::AtMostOnce, PacketId::new(1)).unwrap();
+        let (_ack, n_subscribed) = trie.subscribe(gid, &packet);
+        assert_eq!(n_subscribed, 1);
+
+        let publish = v3::PublishPacket::new("dev/1/status", QoS::AtMostOnce, b"on").unwrap();
+        assert_eq!(trie.match_packet(&publish), vec![gid]);
+
+        let packet = v3::UnsubscribePacket::new("dev/+/status", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(gid, &packet), 1);
+        assert_eq!(trie.unsubscribe(gid, &packet), 0);
+        assert!(trie.match_packet(&publish).is_empty());
+    }
+
+    #[test]
+    fn test_match_wildcards() {
+        let mut trie = SubTrie::new();
+        let filters = [
+            "sport/tennis/#",
+            "sport/+",
+            "+/+",
+            "#",
+            "sport/tennis/player1",
+            "$SYS/#",
+        ];
+        for (index, filter) in filters.iter().enumerate() {
+            subscribe(&mut trie, SessionGid::new(0, index as u64), filter);
+        }
+        let gids = |indices: &[u64]| -> Vec<SessionGid> {
+            indices.iter().map(|i| SessionGid::new(0, *i)).collect()
+        };
+
+        assert_eq!(
+            sorted_matches(&mut trie, "sport/tennis"),
+            gids(&[0, 1, 2, 3])
+        );
+        assert_eq!(
+            sorted_matches(&mut trie, "sport/tennis/player1"),
+            gids(&[0, 3, 4])
+        );
+        assert_eq!(sorted_matches(&mut trie, "sport"), gids(&[3]));
+        assert_eq!(sorted_matches(&mut trie, "/finance"), gids(&[2, 3]));
+        assert_eq!(sorted_matches(&mut trie, "$SYS/uptime"), gids(&[5]));
+        assert_eq!(sorted_matches(&mut trie, "$SYS"), gids(&[5]));
+    }
+
+    #[test]
+    fn test_match_once_per_session() {
+        let mut trie = SubTrie::new();
+        let gid = SessionGid::new(1, 1);
+        subscribe(&mut trie, gid, "dev/#");
+        subscribe(&mut trie, gid, "dev/+/status");
+        let publish = v3::PublishPacket::new("dev/1/status", QoS::AtMostOnce, b"on").unwrap();
+        assert_eq!(trie.match_packet(&publish), vec![gid]);
+
+        let packet = v3::UnsubscribePacket::new("dev/#", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(gid, &packet), 1);
+        assert_eq!(trie.match_packet(&publish), vec![gid]);
+        let packet = v3::UnsubscribePacket::new("dev/+/status", PacketId::new(3)).unwrap();
+        assert_eq!(trie.unsubscribe(gid, &packet), 1);
+        assert!(trie.root.is_empty());
+    }
+
+    #[test]
+    fn test_match_many_subscriptions() {
+        let filter = |index: u64| match index % 4 {
+            0 => format!("device/{}/sensor/{}", index % 100, index % 7),
+            1 => format!("device/+/sensor/{}", index % 7),
+            2 => format!("device/{}/#", index % 100),
+            _ => format!("+/{}/+/{}", index % 100, index % 7),
+        };
+
+        let mut trie = SubTrie::new();
+        let mut filters = Vec::new();
+        for index in 0..10_000 {
+            let gid = SessionGid::new((index % 4) as u32, index);
+            subscribe(&mut trie, gid, &filter(index));
+            filters.push((gid, filter(index)));
+        }
+
+        for topic in [
+            "device/42/sensor/3",
+            "device/7/sensor/0",
+            "device/42",
+            "device/42/status",
+            "room/42/light/3",
+            "device",
+        ] {
+            let mut expected: Vec<SessionGid> = filters
+                .iter()
+                .filter(|(_gid, filter)| is_filter_match(filter, topic))
+                .map(|(gid, _filter)| *gid)
+                .collect();
+            expected.sort_by_key(|gid| (gid.listener_id(), gid.session_id()));
+            assert_eq!(sorted_matches(&mut trie, topic), expected, "topic: {topic}");
+            match trie.match_subscribers(topic) {
+                Subscribers::Empty => assert!(expected.is_empty()),
+                Subscribers::Single(gid) => assert_eq!(expected, [gid]),
+                Subscribers::Multiple(mut sessions) => {
+                    sessions.sort_by_key(|gid| (gid.listener_id(), gid.session_id()));
+                    assert_eq!(sessions, expected);
                 }
-            } else {
-                log::error!(
-                    "dispatcher: Failed to get listener sender with id: {}",
-                    session_gid.listener_id()
-                );
             }
         }
+        assert!(!trie.matches("device/42/sensor/3").is_empty());
+    }
+
+    #[test]
+    fn test_match_shared_subscription() {
+        let mut trie = SubTrie::new();
+        let member1 = SessionGid::new(0, 1);
+        let member2 = SessionGid::new(1, 2);
+        let normal = SessionGid::new(0, 3);
+        subscribe(&mut trie, member1, "$share/groupA/sensors/#");
+        subscribe(&mut trie, member2, "$share/groupA/sensors/#");
+        subscribe(&mut trie, normal, "sensors/#");
+
+        // Messages are delivered to members of share group in turn, and non-shared
+        // subscribers receive all of them.
+        let mut delivered = Vec::new();
+        for _i in 0..4 {
+            let sessions = trie.matches("sensors/temperature");
+            assert_eq!(sessions.len(), 2);
+            assert!(sessions.contains(&normal));
+            delivered.extend(sessions.into_iter().filter(|gid| *gid != normal));
+        }
+        assert_eq!(delivered, [member1, member2, member1, member2]);
+
+        let packet =
+            v3::UnsubscribePacket::new("$share/groupA/sensors/#", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(member1, &packet), 1);
+        assert_eq!(
+            sorted_matches(&mut trie, "sensors/humidity"),
+            [normal, member2]
+        );
+        assert_eq!(
+            sorted_matches(&mut trie, "sensors/humidity"),
+            [normal, member2]
+        );
+
+        assert_eq!(trie.unsubscribe(member2, &packet), 1);
+        assert_eq!(sorted_matches(&mut trie, "sensors/humidity"), [normal]);
+        assert!(trie.root.children["sensors"].children["#"]
+            .groups
+            .is_empty());
+    }
+
+    #[test]
+    fn test_match_single_subscriber() {
+        let mut trie = SubTrie::new();
+        let gid1 = SessionGid::new(0, 1);
+        let gid2 = SessionGid::new(1, 2);
+        subscribe(&mut trie, gid1, "rpc/1/request");
+        subscribe(&mut trie, gid1, "rpc/+/response");
+        subscribe(&mut trie, gid2, "rpc/2/#");
+        subscribe(&mut trie, gid2, "#");
+
+        assert_eq!(trie.match_subscribers("$SYS/rpc"), Subscribers::Empty);
+        assert_eq!(trie.match_subscribers("rpc"), Subscribers::Single(gid2));
+        let Subscribers::Multiple(sessions) = trie.match_subscribers("rpc/1/request") else {
+            panic!("Expected more than one subscriber");
+        };
+        assert_eq!(sessions.len(), 2);
+
+        let packet = v3::UnsubscribePacket::new("#", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(gid2, &packet), 1);
+        assert_eq!(
+            trie.match_subscribers("rpc/1/request"),
+            Subscribers::Single(gid1)
+        );
+        assert_eq!(
+            trie.match_subscribers("rpc/3/response"),
+            Subscribers::Single(gid1)
+        );
+        assert_eq!(trie.match_subscribers("rpc/2"), Subscribers::Single(gid2));
+        assert_eq!(trie.match_subscribers("rpc/1/status"), Subscribers::Empty);
+
+        // Session matched by two topic filters is still delivered once.
+        subscribe(&mut trie, gid1, "rpc/1/#");
+        assert_eq!(
+            trie.match_subscribers("rpc/1/request"),
+            Subscribers::Multiple(vec![gid1])
+        );
+
+        // Shared subscriptions take the general path to rotate members.
+        subscribe(&mut trie, gid1, "$share/workers/jobs");
+        assert_eq!(
+            trie.match_subscribers("jobs"),
+            Subscribers::Multiple(vec![gid1])
+        );
+    }
+
+    #[test]
+    fn test_move_session() {
+        let mut trie = SubTrie::new();
+        let old_gid = SessionGid::new(0, 1);
+        let new_gid = SessionGid::new(0, 2);
+        subscribe(&mut trie, old_gid, "offline/#");
+        subscribe(&mut trie, old_gid, "$share/workers/jobs");
+
+        assert_eq!(trie.move_session(old_gid, new_gid), 2);
+        assert_eq!(
+            trie.match_subscribers("offline/1"),
+            Subscribers::Single(new_gid)
+        );
+        assert_eq!(trie.matches("jobs"), [new_gid]);
+        assert_eq!(trie.move_session(old_gid, new_gid), 0);
+
+        let packet = v3::UnsubscribePacket::new("offline/#", PacketId::new(1)).unwrap();
+        assert_eq!(trie.unsubscribe(new_gid, &packet), 1);
+        assert_eq!(trie.match_subscribers("offline/1"), Subscribers::Empty);
+    }
+
+    #[test]
+    fn test_session_subscriptions() {
+        let mut trie = SubTrie::new();
+        let gid = SessionGid::new(0, 1);
+        subscribe(&mut trie, gid, "sensors/#");
+        let packet =
+            v3::SubscribePacket::new("alerts", QoS::AtLeastOnce, PacketId::new(2)).unwrap();
+        trie.subscribe(gid, &packet);
+
+        let mut subscriptions = trie.session_subscriptions(gid);
+        subscriptions.sort();
+        assert_eq!(
+            subscriptions,
+            [
+                ("alerts".to_string(), QoS::AtLeastOnce),
+                ("sensors/#".to_string(), QoS::AtMostOnce),
+            ]
+        );
+        assert!(trie.session_subscriptions(SessionGid::new(0, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_remove_session() {
+        let mut trie = SubTrie::new();
+        let gid = SessionGid::new(0, 1);
+        let other_gid = SessionGid::new(0, 2);
+        subscribe(&mut trie, gid, "expiry/#");
+        subscribe(&mut trie, gid, "$share/workers/jobs");
+        subscribe(&mut trie, other_gid, "expiry/1");
+
+        assert_eq!(trie.remove_session(gid).len(), 2);
+        assert_eq!(
+            trie.match_subscribers("expiry/1"),
+            Subscribers::Single(other_gid)
+        );
+        assert!(trie.matches("jobs").is_empty());
+        assert!(trie.remove_session(gid).is_empty());
+    }
+
+    #[test]
+    fn test_max_subscribers() {
+        let mut trie = SubTrie::new();
+        trie.set_max_subscribers(2);
+        subscribe(&mut trie, SessionGid::new(0, 1), "fan-out/+");
+        subscribe(&mut trie, SessionGid::new(0, 2), "$share/workers/fan-out/+");
+        // Other topic filters are not affected.
+        subscribe(&mut trie, SessionGid::new(0, 3), "fan-out/#");
+
+        let packet =
+            v3::SubscribePacket::new("fan-out/+", QoS::AtMostOnce, PacketId::new(1)).unwrap();
+        let (ack, n_subscribed) = trie.subscribe(SessionGid::new(0, 3), &packet);
+        assert_eq!(n_subscribed, 0);
+        assert_eq!(ack.acknowledgements(), [v3::SubscribeAck::Failed]);
+
+        let packet =
+            v5::SubscribePacket::new("fan-out/+", QoS::AtMostOnce, PacketId::new(1)).unwrap();
+        let (ack, n_subscribed) = trie.subscribe_v5(SessionGid::new(0, 4), &packet);
+        assert_eq!(n_subscribed, 0);
+        assert_eq!(ack.reasons(), [v5::ReasonCode::QuotaExceeded]);
+
+        // Subscribing again to the same topic filter is allowed.
+        subscribe(&mut trie, SessionGid::new(0, 1), "fan-out/+");
+        assert_eq!(trie.matches("fan-out/1").len(), 3);
+        assert_eq!(
+            trie.subscriber_counts(["fan-out/+", "$share/other/fan-out/#", "fan-out/none"]),
+            [
+                ("fan-out/+".to_string(), 2),
+                ("fan-out/#".to_string(), 1),
+                ("fan-out/none".to_string(), 0)
+            ]
+        );
+
+        // Subscription is accepted after another subscriber leaves.
+        let packet = v3::UnsubscribePacket::new("fan-out/+", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(SessionGid::new(0, 1), &packet), 1);
+        subscribe(&mut trie, SessionGid::new(0, 3), "fan-out/+");
+    }
+
+    #[test]
+    fn test_subscription_ids() {
+        let mut trie = SubTrie::new();
+        let gid = SessionGid::new(0, 1);
+        let new_gid = SessionGid::new(0, 2);
+        for (filter, id) in [("dev/+/status", 1), ("dev/1/#", 2), ("#", 3)] {
+            let mut packet =
+                v5::SubscribePacket::new(filter, QoS::AtMostOnce, PacketId::new(1)).unwrap();
+            packet
+                .properties_mut()
+                .push(v5::Property::SubscriptionIdentifier(
+                    VarInt::from(id).unwrap(),
+                ))
+                .unwrap();
+            let (_ack, n_subscribed) = trie.subscribe_v5(gid, &packet);
+            assert_eq!(n_subscribed, 1);
+        }
+        subscribe(&mut trie, gid, "dev/2/status");
+
+        let ids = |trie: &SubTrie, gid: SessionGid, topic: &str| -> Vec<usize> {
+            let publish = v5::PublishPacket::new(topic, QoS::AtMostOnce, b"on").unwrap();
+            let mut ids: Vec<usize> = trie
+                .packet_for_session(gid, &publish)
+                .properties()
+                .props()
+                .iter()
+                .filter_map(|property| match property {
+                    v5::Property::SubscriptionIdentifier(id) => Some(id.value()),
+                    _ => None,
+                })
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(ids(&trie, gid, "dev/3/status"), [1, 3]);
+        assert_eq!(ids(&trie, gid, "dev/1/status"), [1, 2, 3]);
+        assert_eq!(ids(&trie, gid, "dev/1"), [2, 3]);
+        // Wildcards do not match topics starting with `$`.
+        assert!(ids(&trie, gid, "$SYS/dev").is_empty());
+        // Unsubscribed filters and subscriptions of v3 packets have no identifier.
+        let packet = v3::UnsubscribePacket::new("#", PacketId::new(2)).unwrap();
+        assert_eq!(trie.unsubscribe(gid, &packet), 1);
+        assert_eq!(ids(&trie, gid, "dev/2/status"), [1]);
+
+        // Identifiers are kept when session is taken over.
+        assert_eq!(trie.move_session(gid, new_gid), 3);
+        assert_eq!(ids(&trie, new_gid, "dev/1/status"), [1, 2]);
+        assert!(ids(&trie, gid, "dev/1/status").is_empty());
     }
 }
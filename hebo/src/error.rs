@@ -9,9 +9,11 @@ use tokio::sync::{mpsc, oneshot};
 use tokio_tungstenite::tungstenite;
 
 use crate::commands::{
-    AuthToListenerCmd, DispatcherToMetricsCmd, ListenerToAclCmd, ListenerToAuthCmd,
-    ListenerToDispatcherCmd, ListenerToSessionCmd, MetricsToDispatcherCmd,
-    ServerContextToMetricsCmd, SessionToListenerCmd,
+    AclToListenerCmd, AuthToListenerCmd, BridgeToDispatcherCmd, DispatcherToMetricsCmd,
+    ListenerToAclCmd, ListenerToAuthCmd, ListenerToDispatcherCmd, ListenerToSessionCmd,
+    MetricsToDispatcherCmd, RuleEngineToDispatcherCmd, ServerContextToAclCmd,
+    ServerContextToAuthCmd, ServerContextToBackendsCmd, ServerContextToDispatcherCmd,
+    ServerContextToListenerCmd, ServerContextToMetricsCmd, SessionToListenerCmd,
 };
 use crate::types::SessionId;
 
@@ -69,6 +71,9 @@ pub enum ErrorKind {
     MySQLError,
     PgSQLError,
     MongoError,
+
+    /// Http request error.
+    HttpError,
 }
 
 #[derive(Clone, Debug)]
@@ -93,6 +98,16 @@ impl Error {
     pub const fn from_string(kind: ErrorKind, message: String) -> Self {
         Self { kind, message }
     }
+
+    #[must_use]
+    pub const fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Error {
@@ -179,6 +194,12 @@ impl From<base64::DecodeError> for Error {
     }
 }
 
+impl From<bcrypt::BcryptError> for Error {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        Self::from_string(ErrorKind::FormatError, format!("{err:?}"))
+    }
+}
+
 #[cfg(feature = "redis_conn")]
 impl From<redis::RedisError> for Error {
     fn from(err: redis::RedisError) -> Self {
@@ -207,6 +228,20 @@ impl From<mongodb::error::Error> for Error {
     }
 }
 
+#[cfg(feature = "rule_engine")]
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Self::from_string(ErrorKind::HttpError, format!("{err:?}"))
+    }
+}
+
+#[cfg(feature = "rule_engine")]
+impl From<http::Error> for Error {
+    fn from(err: http::Error) -> Self {
+        Self::from_string(ErrorKind::HttpError, format!("{err:?}"))
+    }
+}
+
 impl From<toml::de::Error> for Error {
     fn from(err: toml::de::Error) -> Self {
         Self::from_string(ErrorKind::ConfigError, format!("{err:?}"))
@@ -248,12 +283,20 @@ macro_rules! convert_send_error {
     };
 }
 
+convert_send_error!(AclToListenerCmd);
 convert_send_error!(AuthToListenerCmd);
+convert_send_error!(BridgeToDispatcherCmd);
 convert_send_error!(DispatcherToMetricsCmd);
 convert_send_error!(ListenerToAclCmd);
 convert_send_error!(ListenerToAuthCmd);
 convert_send_error!(ListenerToDispatcherCmd);
 convert_send_error!(ListenerToSessionCmd);
 convert_send_error!(MetricsToDispatcherCmd);
+convert_send_error!(RuleEngineToDispatcherCmd);
+convert_send_error!(ServerContextToAclCmd);
+convert_send_error!(ServerContextToAuthCmd);
+convert_send_error!(ServerContextToBackendsCmd);
+convert_send_error!(ServerContextToDispatcherCmd);
+convert_send_error!(ServerContextToListenerCmd);
 convert_send_error!(ServerContextToMetricsCmd);
 convert_send_error!(SessionToListenerCmd);
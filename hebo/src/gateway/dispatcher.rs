@@ -9,7 +9,7 @@ use crate::error::Error;
 impl GatewayApp {
     #[allow(clippy::unused_async)]
     pub(super) async fn handle_dispatcher_cmd(
-        &mut self,
+        &self,
         cmd: DispatcherToGatewayCmd,
     ) -> Result<(), Error> {
         log::info!("cmd: {:?}", cmd);
@@ -35,7 +35,7 @@ impl GatewayApp {
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         loop {
             tokio::select! {
                 Some(cmd) = self.dispatcher_receiver.recv() => {
@@ -45,6 +45,10 @@ impl GatewayApp {
                 }
 
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToGatewayCmd::Stop) {
+                        log::info!("gateway: Stop app");
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
@@ -9,7 +9,7 @@ use crate::commands::ServerContextToGatewayCmd;
 
 impl GatewayApp {
     #[allow(clippy::unused_async)]
-    pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToGatewayCmd) {
+    pub(super) async fn handle_server_ctx_cmd(&self, cmd: ServerContextToGatewayCmd) {
         log::info!("cmd: {:?}", cmd);
     }
 }
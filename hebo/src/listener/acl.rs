@@ -4,73 +4,136 @@
 
 //! Acl cmd handler.
 
-use codec::{v3, v5};
+use codec::{v3, v5, QoS};
 
 use super::Listener;
-use crate::commands::{AclToListenerCmd, ListenerToDispatcherCmd, ListenerToSessionCmd};
+use crate::commands::{
+    AclToListenerCmd, ListenerToAclCmd, ListenerToDispatcherCmd, ListenerToSessionCmd,
+};
 use crate::error::Error;
-use crate::types::{SessionGid, SessionId};
+use crate::session::OutgoingPacket;
+use crate::types::{AclPacket, AclRequest, SessionGid, SessionId};
 
 impl Listener {
     pub(super) async fn handle_acl_cmd(&mut self, cmd: AclToListenerCmd) -> Result<(), Error> {
         match cmd {
-            AclToListenerCmd::PublishAck(session_id, packet, accepted) => {
+            AclToListenerCmd::CheckAclResp(session_id, packet, granted) => {
+                self.on_acl_check_resp(session_id, packet, &granted).await
+            }
+        }
+    }
+
+    /// Ask acl app whether this client may publish or subscribe.
+    ///
+    /// Packet is denied if acl app is not running.
+    pub(super) async fn check_acl(
+        &mut self,
+        session_id: SessionId,
+        packet: AclPacket,
+    ) -> Result<(), Error> {
+        let request = AclRequest {
+            client_id: self
+                .session_client_ids
+                .get(&session_id)
+                .cloned()
+                .unwrap_or_default(),
+            username: self
+                .session_usernames
+                .get(&session_id)
+                .cloned()
+                .unwrap_or_default(),
+            packet,
+            policy: self.config.acl_policy(),
+        };
+        let cmd = ListenerToAclCmd::CheckAcl(SessionGid::new(self.id, session_id), request);
+        let Err(err) = self.acl_sender.send(cmd).await else {
+            return Ok(());
+        };
+        let ListenerToAclCmd::CheckAcl(_session_gid, request) = err.0;
+        log::error!(
+            "listener: Acl app is not running, deny topics: {:?}",
+            request.packet.topics()
+        );
+        let granted = vec![false; request.packet.topics().len()];
+        self.on_acl_check_resp(session_id, request.packet, &granted)
+            .await
+    }
+
+    async fn on_acl_check_resp(
+        &mut self,
+        session_id: SessionId,
+        packet: AclPacket,
+        granted: &[bool],
+    ) -> Result<(), Error> {
+        // Messages denied by ACL are dropped.
+        let accepted = granted.iter().all(|granted| *granted);
+        match packet {
+            AclPacket::Publish(packet) => {
                 self.on_acl_publish_ack(session_id, packet, accepted).await
             }
-            AclToListenerCmd::PublishAckV5(session_id, packet, accepted) => {
+            AclPacket::PublishV5(packet) => {
                 self.on_acl_publish_ack_v5(session_id, packet, accepted)
                     .await
             }
-            AclToListenerCmd::SubscribeAck(session_id, packet, acks, accepted) => {
-                self.on_acl_subscribe_ack(session_id, packet, acks, accepted)
-                    .await
+            AclPacket::Subscribe(packet) => {
+                self.on_acl_subscribe_ack(session_id, packet, granted).await
             }
-            AclToListenerCmd::SubscribeAckV5(session_id, packet, reason, accepted) => {
-                self.on_acl_subscribe_ack_v5(session_id, packet, reason, accepted)
+            AclPacket::SubscribeV5(packet) => {
+                self.on_acl_subscribe_ack_v5(session_id, packet, granted)
                     .await
             }
         }
     }
 
-    async fn on_acl_publish_ack(
-        &mut self,
+    pub(super) async fn on_acl_publish_ack(
+        &self,
         session_id: SessionId,
         packet: v3::PublishPacket,
         accepted: bool,
     ) -> Result<(), Error> {
-        if let Some(session_sender) = self.session_senders.get(&session_id) {
-            let cmd = ListenerToSessionCmd::PublishAck(packet.packet_id(), packet.qos(), accepted);
-            if let Err(err) = session_sender.send(cmd).await {
-                log::error!(
-                    "listener: Failed to send publish ack to session: {:?}, err: {:?}",
-                    session_id,
-                    err
-                );
-            }
+        // Denied publish is acked as usual and then dropped, instead of closing
+        // the network connection [MQTT-3.3.5-2].
+        let cmd = ListenerToSessionCmd::PublishAck(packet.packet_id(), packet.qos(), true);
+        if accepted && packet.qos() != QoS::AtMostOnce && self.has_journal() {
+            self.write_journal(session_id, OutgoingPacket::V3(packet.clone()), cmd)
+                .await;
         } else {
-            log::error!(
-                "listener: Failed to find session sender with id: {}",
-                session_id
-            );
+            self.send_publish_ack(session_id, cmd).await;
         }
 
         // If ACL passed, send publish packet to dispatcher layer.
         if accepted {
-            let cmd = ListenerToDispatcherCmd::Publish(packet);
+            let cmd = ListenerToDispatcherCmd::Publish(self.id, packet);
             self.dispatcher_sender.send(cmd).await?;
         }
         Ok(())
     }
 
-    async fn on_acl_publish_ack_v5(
-        &mut self,
+    pub(super) async fn on_acl_publish_ack_v5(
+        &self,
         session_id: SessionId,
         packet: v5::PublishPacket,
         accepted: bool,
     ) -> Result<(), Error> {
+        // Denied publish is acked as usual and then dropped [MQTT-3.3.5-2].
+        let cmd = ListenerToSessionCmd::PublishAckV5(packet.packet_id(), packet.qos(), true);
+        if accepted && packet.qos() != QoS::AtMostOnce && self.has_journal() {
+            self.write_journal(session_id, OutgoingPacket::V5(packet.clone()), cmd)
+                .await;
+        } else {
+            self.send_publish_ack(session_id, cmd).await;
+        }
+
+        // If ACL passed, send publish packet to dispatcher layer.
+        if accepted {
+            let cmd = ListenerToDispatcherCmd::PublishV5(self.id, packet);
+            self.dispatcher_sender.send(cmd).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_publish_ack(&self, session_id: SessionId, cmd: ListenerToSessionCmd) {
         if let Some(session_sender) = self.session_senders.get(&session_id) {
-            let cmd =
-                ListenerToSessionCmd::PublishAckV5(packet.packet_id(), packet.qos(), accepted);
             if let Err(err) = session_sender.send(cmd).await {
                 log::error!(
                     "listener: Failed to send publish ack to session: {:?}, err: {:?}",
@@ -84,57 +147,76 @@ impl Listener {
                 session_id
             );
         }
+    }
 
-        // If ACL passed, send publish packet to dispatcher layer.
-        if accepted {
-            let cmd = ListenerToDispatcherCmd::PublishV5(packet);
-            self.dispatcher_sender.send(cmd).await?;
-        }
-        Ok(())
+    /// Index of topic filters rejected by ACL.
+    fn denied_indices(granted: &[bool]) -> Vec<usize> {
+        granted
+            .iter()
+            .enumerate()
+            .filter_map(|(index, granted)| (!granted).then_some(index))
+            .collect()
     }
 
-    async fn on_acl_subscribe_ack(
+    /// Keep topics granted by ACL.
+    fn retain_granted<T>(topics: &mut Vec<T>, granted: &[bool]) {
+        let mut granted = granted.iter();
+        topics.retain(|_topic| granted.next().copied().unwrap_or(false));
+    }
+
+    pub(super) async fn on_acl_subscribe_ack(
         &mut self,
         session_id: SessionId,
-        packet: v3::SubscribePacket,
-        acks: Vec<v3::SubscribeAck>,
-        accepted: bool,
+        mut packet: v3::SubscribePacket,
+        granted: &[bool],
     ) -> Result<(), Error> {
-        // If ACL passed, send publish packet to dispatcher layer.
-        if accepted {
-            // Can accept part of subscribe packet.
-            let id = SessionGid::new(self.id, session_id);
-            self.dispatcher_sender
-                .send(ListenerToDispatcherCmd::Subscribe(id, packet))
-                .await
-                .map_err(Into::into)
-        } else {
+        let denied = Self::denied_indices(granted);
+        if denied.len() == packet.topics().len() {
             // All of topic filters are rejected.
+            let acks = vec![v3::SubscribeAck::Failed; denied.len()];
             let ack_packet = v3::SubscribeAckPacket::with_vec(packet.packet_id(), acks);
-            self.session_send_publish_ack(session_id, ack_packet).await
+            return self.session_send_publish_ack(session_id, ack_packet).await;
+        }
+        if !denied.is_empty() {
+            // Accept part of subscribe packet, failure codes are inserted back
+            // after the others are subscribed.
+            Self::retain_granted(packet.mut_topics(), granted);
+            self.denied_subscriptions
+                .insert((session_id, packet.packet_id()), denied);
         }
+
+        let id = SessionGid::new(self.id, session_id);
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::Subscribe(id, packet))
+            .await
+            .map_err(Into::into)
     }
 
-    async fn on_acl_subscribe_ack_v5(
+    pub(super) async fn on_acl_subscribe_ack_v5(
         &mut self,
         session_id: SessionId,
-        packet: v5::SubscribePacket,
-        reasons: Vec<v5::ReasonCode>,
-        accepted: bool,
+        mut packet: v5::SubscribePacket,
+        granted: &[bool],
     ) -> Result<(), Error> {
-        // If ACL passed, send publish packet to dispatcher layer.
-        if accepted {
-            // Can accept part of subscribe packet.
-            let id = SessionGid::new(self.id, session_id);
-            self.dispatcher_sender
-                .send(ListenerToDispatcherCmd::SubscribeV5(id, packet))
-                .await
-                .map_err(Into::into)
-        } else {
+        let denied = Self::denied_indices(granted);
+        if denied.len() == packet.topics().len() {
             // All of topic filters are rejected.
+            let reasons = vec![v5::ReasonCode::NotAuthorized; denied.len()];
             let ack_packet = v5::SubscribeAckPacket::with_vec(packet.packet_id(), reasons);
-            self.session_send_publish_ack_v5(session_id, ack_packet)
-                .await
+            return self
+                .session_send_publish_ack_v5(session_id, ack_packet)
+                .await;
+        }
+        if !denied.is_empty() {
+            Self::retain_granted(packet.mut_topics(), granted);
+            self.denied_subscriptions
+                .insert((session_id, packet.packet_id()), denied);
         }
+
+        let id = SessionGid::new(self.id, session_id);
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::SubscribeV5(id, packet))
+            .await
+            .map_err(Into::into)
     }
 }
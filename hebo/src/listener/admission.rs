@@ -0,0 +1,294 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Admission control of new connections.
+
+use codec::{v3, v5, EncodeError, StringData};
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessExt, ProcessRefreshKind, System, SystemExt};
+
+use super::Listener;
+use crate::commands::ListenerToSessionCmd;
+use crate::config;
+use crate::error::Error;
+use crate::log::LogLimiter;
+use crate::types::SessionId;
+
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Busy server is reported by every rejected connection, log them less frequently.
+static BUSY_LOGS: LogLimiter = LogLimiter::new();
+
+/// Result of admission check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+
+    /// Reject with `ServerBusy` reason code.
+    ServerBusy,
+
+    /// Redirect client to another server.
+    UseAnotherServer(String),
+
+    /// Reject with `QuotaExceeded` reason code, as `maximum_connections` of listener
+    /// is reached.
+    QuotaExceeded,
+}
+
+/// Check server load before accepting new connections.
+#[derive(Debug)]
+pub struct AdmissionPolicy {
+    config: config::Admission,
+
+    /// Number of publish messages received in current interval.
+    messages: u32,
+    /// Number of publish messages received in last interval.
+    last_messages: u32,
+    interval_start: Instant,
+
+    pid: Option<Pid>,
+    system: System,
+}
+
+impl AdmissionPolicy {
+    #[must_use]
+    pub fn new(config: config::Admission) -> Self {
+        let pid = if config.busy_memory() > 0 {
+            sysinfo::get_current_pid()
+                .map_err(|err| log::error!("admission: Failed to get current pid, err: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        Self {
+            config,
+            messages: 0,
+            last_messages: 0,
+            interval_start: Instant::now(),
+            pid,
+            system: System::new(),
+        }
+    }
+
+    /// Update message rate when a publish message is received.
+    pub fn on_message(&mut self) {
+        self.update_interval();
+        self.messages = self.messages.saturating_add(1);
+    }
+
+    fn update_interval(&mut self) {
+        let elapsed = self.interval_start.elapsed();
+        if elapsed >= RATE_INTERVAL {
+            // Message rate is zero if no message received in last interval.
+            self.last_messages = if elapsed >= RATE_INTERVAL * 2 {
+                0
+            } else {
+                self.messages
+            };
+            self.messages = 0;
+            self.interval_start = Instant::now();
+        }
+    }
+
+    /// Get number of publish messages received per second.
+    fn message_rate(&mut self) -> u32 {
+        self.update_interval();
+        self.last_messages.max(self.messages)
+    }
+
+    /// Get memory used by server process in bytes.
+    fn memory_usage(&mut self) -> u64 {
+        let Some(pid) = self.pid else {
+            return 0;
+        };
+        if self
+            .system
+            .refresh_process_specifics(pid, ProcessRefreshKind::new())
+        {
+            self.system.process(pid).map_or(0, ProcessExt::memory)
+        } else {
+            0
+        }
+    }
+
+    /// Check whether a new connection is acceptable.
+    ///
+    /// `connections` is number of clients currently connected to this listener.
+    pub fn check(&mut self, connections: usize) -> Admission {
+        if self.config.is_disabled() || !self.is_busy(connections) {
+            return Admission::Accept;
+        }
+        self.config
+            .server_reference()
+            .map_or(Admission::ServerBusy, |reference| {
+                Admission::UseAnotherServer(reference.to_string())
+            })
+    }
+
+    fn is_busy(&mut self, connections: usize) -> bool {
+        let busy_connections = self.config.busy_connections();
+        if busy_connections > 0 && connections >= busy_connections {
+            if let Some(suppressed) = BUSY_LOGS.check() {
+                log::warn!(
+                    "admission: Too many connections: {}{}",
+                    connections,
+                    suppressed
+                );
+            }
+            return true;
+        }
+
+        let busy_message_rate = self.config.busy_message_rate();
+        if busy_message_rate > 0 {
+            let rate = self.message_rate();
+            if rate >= busy_message_rate {
+                if let Some(suppressed) = BUSY_LOGS.check() {
+                    log::warn!(
+                        "admission: Message rate is too high: {}{}",
+                        rate,
+                        suppressed
+                    );
+                }
+                return true;
+            }
+        }
+
+        let busy_memory = self.config.busy_memory();
+        if busy_memory > 0 {
+            let memory = self.memory_usage();
+            if memory >= busy_memory {
+                if let Some(suppressed) = BUSY_LOGS.check() {
+                    log::warn!(
+                        "admission: Memory usage is too high: {}{}",
+                        memory,
+                        suppressed
+                    );
+                }
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+impl Listener {
+    /// Check connection limit of this listener and server load.
+    fn admit(&mut self) -> Admission {
+        // Current session is not counted in.
+        let connections = self.session_senders.len().saturating_sub(1);
+        let maximum_connections = self.config.maximum_connections();
+        if maximum_connections > 0 && connections >= maximum_connections {
+            if let Some(suppressed) = BUSY_LOGS.check() {
+                log::warn!(
+                    "admission: Listener {} reaches maximum connections: {}{}",
+                    self.id,
+                    maximum_connections,
+                    suppressed
+                );
+            }
+            return Admission::QuotaExceeded;
+        }
+        self.admission.check(connections)
+    }
+
+    /// Check server load before handling connect packet.
+    ///
+    /// Returns false if this session is rejected.
+    pub(super) async fn check_admission(&mut self, session_id: SessionId) -> Result<bool, Error> {
+        match self.admit() {
+            Admission::Accept => Ok(true),
+            Admission::ServerBusy | Admission::UseAnotherServer(_) | Admission::QuotaExceeded => {
+                self.session_send_connect_ack(
+                    session_id,
+                    v3::ConnectReturnCode::ServerUnavailable,
+                    None,
+                )
+                .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Check server load before handling connect packet of v5 protocol.
+    ///
+    /// Returns false if this session is rejected or redirected to another server.
+    pub(super) async fn check_admission_v5(
+        &mut self,
+        session_id: SessionId,
+    ) -> Result<bool, Error> {
+        let ack_packet = match self.admit() {
+            Admission::Accept => return Ok(true),
+            Admission::ServerBusy => v5::ConnectAckPacket::new(false, v5::ReasonCode::ServerBusy),
+            Admission::QuotaExceeded => {
+                v5::ConnectAckPacket::new(false, v5::ReasonCode::QuotaExceeded)
+            }
+            Admission::UseAnotherServer(reference) => {
+                let mut packet = v5::ConnectAckPacket::new(false, v5::ReasonCode::UseAnotherServer);
+                let reference = StringData::from(&reference).map_err(EncodeError::from)?;
+                packet
+                    .properties_mut()
+                    .push(v5::Property::ServerReference(reference))?;
+                packet
+            }
+        };
+
+        let cmd = ListenerToSessionCmd::ConnectAckV5(ack_packet, None);
+        if let Some(session_sender) = self.session_senders.get(&session_id) {
+            session_sender.send(cmd).await?;
+            Ok(false)
+        } else {
+            Err(Error::session_error(session_id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Admission, AdmissionPolicy};
+    use crate::config;
+
+    fn new_policy(s: &str) -> AdmissionPolicy {
+        let config: config::Admission = toml::from_str(s).unwrap();
+        AdmissionPolicy::new(config)
+    }
+
+    #[test]
+    fn test_busy_connections() {
+        let mut policy = new_policy("busy_connections = 2");
+        assert_eq!(policy.check(1), Admission::Accept);
+        assert_eq!(policy.check(2), Admission::ServerBusy);
+
+        let mut policy = new_policy(
+            r#"
+busy_connections = 2
+server_reference = "mqtt2.example.com:1883"
+"#,
+        );
+        assert_eq!(policy.check(0), Admission::Accept);
+        assert_eq!(
+            policy.check(3),
+            Admission::UseAnotherServer("mqtt2.example.com:1883".to_string())
+        );
+    }
+
+    #[test]
+    fn test_busy_message_rate() {
+        let mut policy = new_policy("busy_message_rate = 3");
+        policy.on_message();
+        policy.on_message();
+        assert_eq!(policy.check(0), Admission::Accept);
+        policy.on_message();
+        assert_eq!(policy.check(0), Admission::ServerBusy);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut policy = new_policy("");
+        for _ in 0..100 {
+            policy.on_message();
+        }
+        assert_eq!(policy.check(10_000), Admission::Accept);
+    }
+}
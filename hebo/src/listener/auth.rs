@@ -44,24 +44,40 @@ impl Listener {
         // If a server sends a CONNACK packet containing a non-zero return code
         // it MUST set Session Present to 0 [MQTT-3.2.2-4].
 
-        self.connecting_sessions.remove(&session_id);
+        let Some((client_id, taken_over)) = self.connecting_sessions.remove(&session_id) else {
+            log::info!("listener: Session {} is closed or taken over", session_id);
+            return Ok(());
+        };
 
         // If not granted, reject this session here.
         if !access_granted {
+            self.on_connect_aborted(client_id, taken_over).await?;
             return self
                 .session_send_connect_ack(session_id, v3::ConnectReturnCode::Unauthorized, None)
                 .await;
         }
 
-        // Clean session flag is on.
+        self.on_client_connected(
+            session_id,
+            packet.client_id(),
+            packet.username(),
+            packet.protocol_level(),
+            packet.connect_flags().clean_session(),
+        )
+        .await?;
+
+        // Clean session flag is on, previous session state is discarded.
         if packet.connect_flags().clean_session() {
+            self.publish_delayed_will(packet.client_id()).await?;
+            let cmd = ListenerToDispatcherCmd::DiscardCachedSession(packet.client_id().to_string());
+            self.dispatcher_sender.send(cmd).await?;
             return self
                 .session_send_connect_ack(session_id, v3::ConnectReturnCode::Accepted, None)
                 .await;
         }
 
-        self.client_ids
-            .insert(packet.client_id().to_string(), session_id);
+        // Previous session is resumed.
+        self.cancel_delayed_will(packet.client_id());
 
         // Check cached session store and update session_present flag.
         let cmd = ListenerToDispatcherCmd::CheckCachedSession(
@@ -79,24 +95,40 @@ impl Listener {
         packet: v5::ConnectPacket,
     ) -> Result<(), Error> {
         // TODO(Shaohua): Add comments
-        self.connecting_sessions.remove(&session_id);
+        let Some((client_id, taken_over)) = self.connecting_sessions.remove(&session_id) else {
+            log::info!("listener: Session {} is closed or taken over", session_id);
+            return Ok(());
+        };
 
         // If not granted, reject this session here.
         if !access_granted {
+            self.on_connect_aborted(client_id, taken_over).await?;
             return self
                 .session_send_connect_ack_v5(session_id, v5::ReasonCode::NotAuthorized, None)
                 .await;
         }
 
-        // Clean session flag is on.
+        self.on_client_connected(
+            session_id,
+            packet.client_id(),
+            packet.username(),
+            packet.protocol_level(),
+            packet.connect_flags().clean_session(),
+        )
+        .await?;
+
+        // Clean session flag is on, previous session state is discarded.
         if packet.connect_flags().clean_session() {
+            self.publish_delayed_will(packet.client_id()).await?;
+            let cmd = ListenerToDispatcherCmd::DiscardCachedSession(packet.client_id().to_string());
+            self.dispatcher_sender.send(cmd).await?;
             return self
                 .session_send_connect_ack_v5(session_id, v5::ReasonCode::Success, None)
                 .await;
         }
 
-        self.client_ids
-            .insert(packet.client_id().to_string(), session_id);
+        // Previous session is resumed.
+        self.cancel_delayed_will(packet.client_id());
 
         // Check cached session store and update session_present flag.
         let cmd = ListenerToDispatcherCmd::CheckCachedSession(
@@ -43,7 +43,7 @@ impl Listener {
     }
 
     async fn on_dispatcher_check_cached_session(
-        &mut self,
+        &self,
         session_id: SessionId,
         protocol_level: ProtocolLevel,
         cached_session: Option<CachedSession>,
@@ -62,7 +62,7 @@ impl Listener {
     }
 
     async fn on_dispatcher_publish(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v3::PublishPacket,
     ) -> Result<(), Error> {
@@ -75,7 +75,7 @@ impl Listener {
     }
 
     async fn on_dispatcher_publish_v5(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v5::PublishPacket,
     ) -> Result<(), Error> {
@@ -90,16 +90,35 @@ impl Listener {
     async fn on_dispatcher_subscribe_ack(
         &mut self,
         session_id: SessionId,
-        packet: v3::SubscribeAckPacket,
+        mut packet: v3::SubscribeAckPacket,
     ) -> Result<(), Error> {
+        if let Some(denied) = self
+            .denied_subscriptions
+            .remove(&(session_id, packet.packet_id()))
+        {
+            let mut acks = packet.acknowledgements().to_vec();
+            for index in denied {
+                acks.insert(index, v3::SubscribeAck::Failed);
+            }
+            packet.set_ack(&acks);
+        }
         self.session_send_publish_ack(session_id, packet).await
     }
 
     async fn on_dispatcher_subscribe_ack_v5(
         &mut self,
         session_id: SessionId,
-        packet: v5::SubscribeAckPacket,
+        mut packet: v5::SubscribeAckPacket,
     ) -> Result<(), Error> {
+        if let Some(denied) = self
+            .denied_subscriptions
+            .remove(&(session_id, packet.packet_id()))
+        {
+            let reasons = packet.reasons_mut();
+            for index in denied {
+                reasons.insert(index, v5::ReasonCode::NotAuthorized);
+            }
+        }
         self.session_send_publish_ack_v5(session_id, packet).await
     }
 }
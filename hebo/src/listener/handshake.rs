@@ -0,0 +1,137 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Finish handshake of accepted connections in background tasks.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::Sender;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::server as ws_server;
+use tokio_tungstenite::WebSocketStream;
+
+use super::{identity, proxy_protocol, websocket};
+use crate::config::{self, IdentitySource};
+use crate::error::{Error, ErrorKind};
+use crate::stream::Stream;
+
+/// Stream of new connection, identity of client extracted from transport layer,
+/// and address of client in PROXY protocol header.
+pub type Accepted = (Stream, Option<String>, Option<SocketAddr>);
+
+/// Options of listener used to finish handshake of new connections.
+#[derive(Debug, Clone)]
+pub struct HandshakeOptions {
+    proxy_protocol: bool,
+    path: Option<String>,
+    identity_source: IdentitySource,
+    identity_header: String,
+    /// None if timeout is disabled.
+    timeout: Option<Duration>,
+}
+
+impl HandshakeOptions {
+    pub fn new(config: &config::Listener) -> Self {
+        let timeout = config.connect_timeout();
+        Self {
+            proxy_protocol: config.proxy_protocol(),
+            path: config.path().map(ToString::to_string),
+            identity_source: config.identity_source(),
+            identity_header: config.identity_header().to_string(),
+            timeout: (timeout > 0).then(|| Duration::from_secs(u64::from(timeout))),
+        }
+    }
+}
+
+/// Run `handshake` in a new task, and send result to `sender`.
+///
+/// Handshake is limited by `connect_timeout` of listener.
+pub fn spawn<F>(sender: Sender<Result<Accepted, Error>>, options: &HandshakeOptions, handshake: F)
+where
+    F: Future<Output = Result<Accepted, Error>> + Send + 'static,
+{
+    let timeout = options.timeout;
+    tokio::spawn(async move {
+        let ret = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, handshake)
+                .await
+                .unwrap_or_else(|_elapsed| {
+                    Err(Error::new(ErrorKind::SocketError, "Handshake timeout"))
+                }),
+            None => handshake.await,
+        };
+        // Channel is closed if listener has stopped, and connection is dropped.
+        let _ret = sender.send(ret).await;
+    });
+}
+
+/// Read PROXY protocol header if it is enabled, then finish TLS handshake if `acceptor`
+/// is set, and websocket handshake if `websocket` is true.
+pub async fn tcp(
+    mut tcp_stream: TcpStream,
+    acceptor: Option<TlsAcceptor>,
+    websocket: bool,
+    options: HandshakeOptions,
+) -> Result<Accepted, Error> {
+    let proxy_address = if options.proxy_protocol {
+        proxy_protocol::read_header(&mut tcp_stream).await?
+    } else {
+        None
+    };
+
+    match (acceptor, websocket) {
+        (None, false) => Ok((Stream::Mqtt(tcp_stream), None, proxy_address)),
+        (Some(acceptor), false) => {
+            let tls_stream = acceptor.accept(tcp_stream).await?;
+            let identity = identity::tls_identity(tls_stream.get_ref().1, options.identity_source);
+            Ok((Stream::Mqtts(Box::new(tls_stream)), identity, proxy_address))
+        }
+        (None, true) => {
+            let (ws_stream, identity) = ws_accept(tcp_stream, &options).await?;
+            Ok((Stream::Ws(Box::new(ws_stream)), identity, proxy_address))
+        }
+        (Some(acceptor), true) => {
+            let tls_stream = acceptor.accept(tcp_stream).await?;
+            let tls_identity =
+                identity::tls_identity(tls_stream.get_ref().1, options.identity_source);
+            let (ws_stream, header_identity) = ws_accept(tls_stream, &options).await?;
+            Ok((
+                Stream::Wss(Box::new(ws_stream)),
+                tls_identity.or(header_identity),
+                proxy_address,
+            ))
+        }
+    }
+}
+
+/// Finish websocket handshake, and returns identity of client in http header.
+async fn ws_accept<S>(
+    stream: S,
+    options: &HandshakeOptions,
+) -> Result<(WebSocketStream<S>, Option<String>), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut header_identity = None;
+    #[allow(clippy::result_large_err)]
+    let check_request = |request: &ws_server::Request,
+                         response: ws_server::Response|
+     -> Result<ws_server::Response, ws_server::ErrorResponse> {
+        if options.identity_source == IdentitySource::ProxyHeader {
+            header_identity = identity::header_identity(request, &options.identity_header);
+        }
+        websocket::check_request(request, response, options.path.as_deref())
+    };
+    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, check_request).await?;
+    Ok((ws_stream, header_identity))
+}
+
+/// Wait for quic connection to be established.
+pub async fn quic(connecting: quinn::Connecting) -> Result<Accepted, Error> {
+    let connection = connecting.await?;
+    Ok((Stream::Quic(connection), None, None))
+}
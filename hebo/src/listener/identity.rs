@@ -0,0 +1,213 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Extract identity of clients from transport layer.
+
+use openssl::nid::Nid;
+use openssl::x509::X509;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio_rustls::rustls::ServerConnection;
+
+use crate::config::IdentitySource;
+
+/// Get identity of client from TLS connection.
+pub fn tls_identity(conn: &ServerConnection, source: IdentitySource) -> Option<String> {
+    match source {
+        IdentitySource::TlsCn | IdentitySource::TlsSan => {
+            let cert = conn.peer_certificates()?.first()?;
+            cert_identity(&cert.0, source)
+        }
+        IdentitySource::MqttUsername | IdentitySource::ProxyHeader | IdentitySource::PeerCred => {
+            None
+        }
+    }
+}
+
+/// Get common name, or the first DNS, email or URI subject alternative name of certificate.
+fn cert_identity(der: &[u8], source: IdentitySource) -> Option<String> {
+    let cert = X509::from_der(der)
+        .map_err(|err| log::warn!("listener: Invalid client certificate, err: {:?}", err))
+        .ok()?;
+    match source {
+        IdentitySource::TlsCn => {
+            let entry = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next()?;
+            entry.data().as_utf8().ok().map(|name| name.to_string())
+        }
+        IdentitySource::TlsSan => cert.subject_alt_names()?.iter().find_map(|name| {
+            name.dnsname()
+                .or_else(|| name.email())
+                .or_else(|| name.uri())
+                .map(ToString::to_string)
+        }),
+        _ => None,
+    }
+}
+
+/// Get value of `header` in websocket handshake request.
+pub fn header_identity<T>(request: &http::Request<T>, header: &str) -> Option<String> {
+    let value = request.headers().get(header)?.to_str().ok()?.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Get uid and gid of peer process of unix domain socket.
+#[cfg(target_os = "linux")]
+pub fn peer_cred(stream: &UnixStream) -> Option<(u32, u32)> {
+    stream
+        .peer_cred()
+        .map(|cred| (cred.uid(), cred.gid()))
+        .map_err(|err| log::warn!("listener: Failed to read peer credentials, err: {:?}", err))
+        .ok()
+}
+
+/// Peer credentials are only read on Linux.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub const fn peer_cred(_stream: &UnixStream) -> Option<(u32, u32)> {
+    None
+}
+
+/// Get identity of client passed to ACL.
+///
+/// Returns empty string for anonymous client.
+#[must_use]
+pub fn acl_identity(
+    source: IdentitySource,
+    username: &str,
+    transport_identity: Option<&str>,
+) -> String {
+    match source {
+        IdentitySource::MqttUsername => username.to_string(),
+        _ => transport_identity.unwrap_or_default().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+    use std::sync::Arc;
+    use tokio_rustls::rustls::{self, ClientConfig, ClientConnection, ServerConnection};
+
+    use super::{acl_identity, cert_identity, header_identity, tls_identity};
+    use crate::config::IdentitySource;
+
+    fn new_cert(common_name: &str, san: Option<&str>) -> Vec<u8> {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if let Some(san) = san {
+            let ext = SubjectAlternativeName::new()
+                .dns(san)
+                .build(&builder.x509v3_context(None, None))
+                .unwrap();
+            builder.append_extension(ext).unwrap();
+        }
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build().to_der().unwrap()
+    }
+
+    #[test]
+    fn test_cert_identity() {
+        let cert = new_cert("device-1", Some("device-1.example.com"));
+        assert_eq!(
+            cert_identity(&cert, IdentitySource::TlsCn).as_deref(),
+            Some("device-1")
+        );
+        assert_eq!(
+            cert_identity(&cert, IdentitySource::TlsSan).as_deref(),
+            Some("device-1.example.com")
+        );
+
+        let cert = new_cert("device-2", None);
+        assert_eq!(cert_identity(&cert, IdentitySource::TlsSan), None);
+        assert_eq!(cert_identity(b"invalid", IdentitySource::TlsCn), None);
+    }
+
+    #[test]
+    fn test_tls_identity_without_certificate() {
+        let server_config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(rustls::server::ResolvesServerCertUsingSni::new()));
+        let mut server = ServerConnection::new(Arc::new(server_config)).unwrap();
+        let client_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let server_name = "broker.example.com".try_into().unwrap();
+        let mut client = ClientConnection::new(Arc::new(client_config), server_name).unwrap();
+
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+        server.read_tls(&mut buf.as_slice()).unwrap();
+        let _ret = server.process_new_packets();
+        assert_eq!(tls_identity(&server, IdentitySource::TlsCn), None);
+        assert_eq!(tls_identity(&server, IdentitySource::TlsSan), None);
+    }
+
+    #[test]
+    fn test_header_identity() {
+        let request = http::Request::builder()
+            .header("X-Forwarded-User", "alice")
+            .header("X-Empty", " ")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            header_identity(&request, "x-forwarded-user").as_deref(),
+            Some("alice")
+        );
+        assert_eq!(header_identity(&request, "X-Empty"), None);
+        assert_eq!(header_identity(&request, "X-Client-Id"), None);
+    }
+
+    #[test]
+    fn test_acl_identity() {
+        let source = IdentitySource::MqttUsername;
+        assert_eq!(acl_identity(source, "bob", Some("alice")), "bob");
+        for source in [
+            IdentitySource::TlsCn,
+            IdentitySource::TlsSan,
+            IdentitySource::ProxyHeader,
+            IdentitySource::PeerCred,
+        ] {
+            assert_eq!(acl_identity(source, "bob", Some("alice")), "alice");
+            // Client without transport identity is anonymous.
+            assert_eq!(acl_identity(source, "bob", None), "");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_peer_cred() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (stream, _peer) = tokio::net::UnixStream::pair().unwrap();
+        let metadata = std::fs::metadata("/proc/self").unwrap();
+        assert_eq!(
+            super::peer_cred(&stream),
+            Some((metadata.uid(), metadata.gid()))
+        );
+    }
+}
@@ -4,7 +4,8 @@
 
 //! Initialize Listener
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use codec::QoS;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::net::SocketAddr;
@@ -12,18 +13,26 @@ use std::path::Path;
 use std::sync::Arc;
 #[cfg(unix)]
 use tokio::net::UnixListener;
-use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::mpsc::{self, Receiver, Sender, UnboundedReceiver};
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
 use tokio_rustls::{rustls, TlsAcceptor};
 
+use super::handshake::{self, HandshakeOptions};
+use super::identity;
+use super::AdmissionPolicy;
+use super::Journal;
 use super::Listener;
 use super::Protocol;
 use super::CHANNEL_CAPACITY;
+use crate::auth::AuthMethod;
+use crate::backends::batch::BatchSender;
 use crate::commands::{
     AclToListenerCmd, AuthToListenerCmd, DispatcherToListenerCmd, ListenerToAclCmd,
-    ListenerToAuthCmd, ListenerToDispatcherCmd,
+    ListenerToAuthCmd, ListenerToDispatcherCmd, ServerContextToListenerCmd,
 };
-use crate::config;
+use crate::config::{self, IdentitySource, QueueFullPolicy};
 use crate::error::{Error, ErrorKind};
+use crate::session::OutgoingPacket;
 use crate::socket::new_tcp_listener;
 use crate::stream::Stream;
 use crate::types::ListenerId;
@@ -36,29 +45,55 @@ impl Listener {
         listener_config: config::Listener,
         // dispatcher module
         dispatcher_sender: Sender<ListenerToDispatcherCmd>,
-        dispatcher_receiver: Receiver<DispatcherToListenerCmd>,
+        dispatcher_receiver: UnboundedReceiver<DispatcherToListenerCmd>,
         // auth module
         auth_sender: Sender<ListenerToAuthCmd>,
-        auth_receiver: Receiver<AuthToListenerCmd>,
+        auth_receiver: UnboundedReceiver<AuthToListenerCmd>,
         // acl module
         acl_sender: Sender<ListenerToAclCmd>,
-        acl_receiver: Receiver<AclToListenerCmd>,
+        acl_receiver: UnboundedReceiver<AclToListenerCmd>,
+        // server ctx
+        server_ctx_receiver: Receiver<ServerContextToListenerCmd>,
     ) -> Self {
         let (session_sender, session_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let (handshake_sender, handshake_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let admission = AdmissionPolicy::new(listener_config.admission().clone());
         Self {
             id,
             protocol,
             config: listener_config,
+            maximum_packet_size: 0,
+            max_will_payload_size: 0,
+            maximum_qos: QoS::ExactOnce,
+            max_queued_messages: config::General::default_max_queued_messages(),
+            queue_full_policy: QueueFullPolicy::DropOldest,
+            wildcard_subscription_available: true,
+            validate_payload_format: false,
+            response_information: String::new(),
+            auth_methods: Vec::new(),
             current_session_id: 0,
 
             session_senders: HashMap::new(),
             client_ids: BTreeMap::new(),
+            session_client_ids: HashMap::new(),
+            session_usernames: HashMap::new(),
+            session_identities: HashMap::new(),
+            session_addresses: HashMap::new(),
+            denied_subscriptions: HashMap::new(),
+            access_records: HashMap::new(),
 
-            connecting_sessions: HashSet::new(),
+            connecting_sessions: HashMap::new(),
+
+            admission,
+            delayed_wills: HashMap::new(),
+            journal: None,
 
             session_sender,
             session_receiver: Some(session_receiver),
 
+            handshake_sender,
+            handshake_receiver: Some(handshake_receiver),
+
             dispatcher_sender,
             dispatcher_receiver: Some(dispatcher_receiver),
 
@@ -67,6 +102,8 @@ impl Listener {
 
             acl_sender,
             acl_receiver: Some(acl_receiver),
+
+            server_ctx_receiver: Some(server_ctx_receiver),
         }
     }
 
@@ -75,7 +112,10 @@ impl Listener {
             rustls_pemfile::certs(&mut BufReader::new(File::open(path)?)).map_err(|err| {
                 Error::from_string(
                     ErrorKind::CertError,
-                    format!("Failed to load cert file at {path:?}, got: {err:?}"),
+                    format!(
+                        "Failed to load cert file at {}, got: {err:?}",
+                        path.display()
+                    ),
                 )
             })?;
         Ok(items.into_iter().map(rustls::Certificate).collect())
@@ -96,10 +136,24 @@ impl Listener {
 
         Err(Error::from_string(
             ErrorKind::CertError,
-            format!("Failed to load key file at {path:?}"),
+            format!("Failed to load key file at {}", path.display()),
         ))
     }
 
+    /// Load CA certificates used to verify client certificates.
+    fn load_client_roots(path: &Path) -> Result<rustls::RootCertStore, Error> {
+        let certs = Self::load_certs(path)?;
+        let mut roots = rustls::RootCertStore::empty();
+        let (added, _ignored) = roots.add_parsable_certificates(&certs);
+        if added == 0 {
+            return Err(Error::from_string(
+                ErrorKind::CertError,
+                format!("No valid CA certificate found in {}", path.display()),
+            ));
+        }
+        Ok(roots)
+    }
+
     fn get_cert_config(listener_config: &config::Listener) -> Result<rustls::ServerConfig, Error> {
         let cert_file = listener_config
             .cert_file()
@@ -111,17 +165,27 @@ impl Listener {
         let certs = Self::load_certs(cert_file)?;
         let mut keys = Self::load_keys(key_file)?;
 
-        rustls::ServerConfig::builder()
-            .with_safe_default_cipher_suites()
+        let cipher_suites = listener_config.tls_cipher_suites()?;
+        let builder = rustls::ServerConfig::builder()
+            .with_cipher_suites(&cipher_suites)
             .with_safe_default_kx_groups()
-            .with_protocol_versions(rustls::ALL_VERSIONS)
+            .with_protocol_versions(listener_config.tls_versions())
             .map_err(|err| {
                 Error::from_string(
                     ErrorKind::CertError,
                     format!("Failed to init ConfigBuilder, got {err:?}"),
                 )
-            })?
-            .with_no_client_auth()
+            })?;
+        let builder = if listener_config.require_certificate() {
+            let ca_file = listener_config
+                .ca_file()
+                .ok_or_else(|| Error::new(ErrorKind::CertError, "ca_file is required"))?;
+            let roots = Self::load_client_roots(ca_file)?;
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        } else {
+            builder.with_no_client_auth()
+        };
+        builder
             .with_single_cert(certs, keys.remove(0))
             .map_err(|err| {
                 Error::from_string(
@@ -131,6 +195,60 @@ impl Listener {
             })
     }
 
+    /// Set maximum packet size accepted by broker.
+    ///
+    /// Clients sending packets larger than this value will be disconnected.
+    pub fn set_maximum_packet_size(&mut self, maximum_packet_size: u32) {
+        self.maximum_packet_size = maximum_packet_size as usize;
+    }
+
+    /// Set maximum size of will payload in connect packet.
+    ///
+    /// Connect packets with larger will payload are rejected.
+    pub fn set_max_will_payload_size(&mut self, max_will_payload_size: u32) {
+        self.max_will_payload_size = max_will_payload_size as usize;
+    }
+
+    /// Set maximum `QoS` supported by broker.
+    ///
+    /// Subscriptions are downgraded to this `QoS`, and clients publishing
+    /// at a higher `QoS` are disconnected.
+    pub fn set_maximum_qos(&mut self, maximum_qos: QoS) {
+        self.maximum_qos = maximum_qos;
+    }
+
+    /// Set maximum number of messages queued for each client when its inflight window
+    /// is full, and what to do with new messages when the queue is full.
+    pub fn set_queue_limit(&mut self, max_queued_messages: usize, policy: QueueFullPolicy) {
+        self.max_queued_messages = max_queued_messages;
+        self.queue_full_policy = policy;
+    }
+
+    /// Set whether topic filters with wildcard characters are accepted.
+    pub fn set_wildcard_subscription_available(&mut self, available: bool) {
+        self.wildcard_subscription_available = available;
+    }
+
+    /// Set whether payload of publish packets declared as UTF-8 string is validated.
+    pub fn set_validate_payload_format(&mut self, validate: bool) {
+        self.validate_payload_format = validate;
+    }
+
+    /// Set response information sent to v5 clients which request it.
+    pub fn set_response_information(&mut self, response_information: &str) {
+        self.response_information = response_information.to_string();
+    }
+
+    /// Set enhanced auth methods of v5 clients.
+    pub fn set_auth_methods(&mut self, auth_methods: &[Arc<dyn AuthMethod>]) {
+        self.auth_methods = auth_methods.to_vec();
+    }
+
+    /// Defer acks of qos=1 and qos=2 messages until they are written to journal.
+    pub fn set_journal(&mut self, writer: BatchSender<OutgoingPacket>) {
+        self.journal = Some(Journal::new(writer));
+    }
+
     /// Bind to specific socket address.
     ///
     /// # Errors
@@ -143,13 +261,15 @@ impl Listener {
         listener_config: config::Listener,
         // dispatcher
         dispatcher_sender: Sender<ListenerToDispatcherCmd>,
-        dispatcher_receiver: Receiver<DispatcherToListenerCmd>,
+        dispatcher_receiver: UnboundedReceiver<DispatcherToListenerCmd>,
         // auth
         auth_sender: Sender<ListenerToAuthCmd>,
-        auth_receiver: Receiver<AuthToListenerCmd>,
+        auth_receiver: UnboundedReceiver<AuthToListenerCmd>,
         // acl
         acl_sender: Sender<ListenerToAclCmd>,
-        acl_receiver: Receiver<AclToListenerCmd>,
+        acl_receiver: UnboundedReceiver<AclToListenerCmd>,
+        // server ctx
+        server_ctx_receiver: Receiver<ServerContextToListenerCmd>,
     ) -> Result<Self, Error> {
         let device = listener_config.bind_device();
         let address = listener_config.address();
@@ -165,6 +285,7 @@ impl Listener {
                 auth_receiver,
                 acl_sender,
                 acl_receiver,
+                server_ctx_receiver,
             ))
         };
         match listener_config.protocol() {
@@ -231,67 +352,249 @@ impl Listener {
         }
     }
 
-    pub(super) async fn accept(&mut self) -> Result<Stream, Error> {
-        use tokio_tungstenite::tungstenite::handshake::server as ws_server;
-        let listener_path = self.config.path();
-        let check_ws_path = |request: &ws_server::Request,
-                             response: ws_server::Response|
-         -> Result<ws_server::Response, ws_server::ErrorResponse> {
-            let path = request.uri().path();
-            if listener_path.is_none() || path == listener_path.unwrap() {
-                return Ok(response);
-            }
-            let builder = http::Response::builder().status(http::StatusCode::NOT_FOUND);
-            let resp = builder.body(None);
-            // TODO(Shaohua): Remove unwrap()
-            let resp = resp.unwrap();
-            Err(resp)
-        };
+    /// Get socket address this listener is bound to.
+    ///
+    /// If port in config is 0, the port picked by system is returned.
+    /// Returns None for unix domain socket.
+    #[must_use]
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.protocol.local_addr()
+    }
+
+    /// Accept a new connection.
+    ///
+    /// Handshake of the connection is finished in a new task, and the stream is sent
+    /// back to `handshake_receiver`, so that slow clients never block this listener.
+    pub(super) async fn accept(&mut self) -> Result<(), Error> {
+        let sender = self.handshake_sender.clone();
+        let options = HandshakeOptions::new(&self.config);
 
         match &mut self.protocol {
             Protocol::Mqtt(listener) => {
                 let (tcp_stream, _address) = listener.accept().await?;
-                Ok(Stream::Mqtt(tcp_stream))
+                handshake::spawn(
+                    sender,
+                    &options,
+                    handshake::tcp(tcp_stream, None, false, options.clone()),
+                );
             }
             Protocol::Mqtts(listener, acceptor) => {
                 let (tcp_stream, _address) = listener.accept().await?;
-                let tls_stream = acceptor.accept(tcp_stream).await?;
-                Ok(Stream::Mqtts(Box::new(tls_stream)))
+                let acceptor = Some(acceptor.clone());
+                handshake::spawn(
+                    sender,
+                    &options,
+                    handshake::tcp(tcp_stream, acceptor, false, options.clone()),
+                );
             }
             Protocol::Ws(listener) => {
                 let (tcp_stream, _address) = listener.accept().await?;
-                let ws_stream = if listener_path.is_none() {
-                    tokio_tungstenite::accept_async(tcp_stream).await?
-                } else {
-                    tokio_tungstenite::accept_hdr_async(tcp_stream, check_ws_path).await?
-                };
-                Ok(Stream::Ws(Box::new(ws_stream)))
+                handshake::spawn(
+                    sender,
+                    &options,
+                    handshake::tcp(tcp_stream, None, true, options.clone()),
+                );
             }
             Protocol::Wss(listener, acceptor) => {
                 let (tcp_stream, _address) = listener.accept().await?;
-                let tls_stream = acceptor.accept(tcp_stream).await?;
-                let ws_stream = if listener_path.is_none() {
-                    tokio_tungstenite::accept_async(tls_stream).await?
-                } else {
-                    tokio_tungstenite::accept_hdr_async(tls_stream, check_ws_path).await?
-                };
-                Ok(Stream::Wss(Box::new(ws_stream)))
+                let acceptor = Some(acceptor.clone());
+                handshake::spawn(
+                    sender,
+                    &options,
+                    handshake::tcp(tcp_stream, acceptor, true, options.clone()),
+                );
             }
             #[cfg(unix)]
             Protocol::Uds(listener) => {
                 let (uds_stream, _address) = listener.accept().await?;
-                Ok(Stream::Uds(uds_stream))
+                let cred = identity::peer_cred(&uds_stream);
+                if !self.config.is_peer_allowed(cred) {
+                    return Err(Error::from_string(
+                        ErrorKind::SocketError,
+                        format!("Peer process is not allowed, uid and gid: {cred:?}"),
+                    ));
+                }
+                let identity = if self.config.identity_source() == IdentitySource::PeerCred {
+                    cred.map(|(uid, _gid)| uid.to_string())
+                } else {
+                    None
+                };
+                handshake::spawn(sender, &options, async move {
+                    Ok((Stream::Uds(uds_stream), identity, None))
+                });
             }
             Protocol::Quic(endpoint) => {
-                if let Some(conn) = endpoint.accept().await {
-                    let connection: quinn::Connection = conn.await?;
-                    return Ok(Stream::Quic(connection));
-                }
-                Err(Error::new(
-                    ErrorKind::SocketError,
-                    "Failed to accept new quic connection",
-                ))
+                let connecting = endpoint.accept().await.ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::SocketError,
+                        "Failed to accept new quic connection",
+                    )
+                })?;
+                handshake::spawn(sender, &options, handshake::quic(connecting));
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::{self, ClientConfig};
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    use super::Listener;
+    use crate::config::{self, IdentitySource, Protocol, TlsVersion};
+    use crate::listener::identity::tls_identity;
+
+    fn new_cert(common_name: &str, is_ca: bool) -> Certificate {
+        let mut params = CertificateParams::new(vec![common_name.to_string()]);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        if is_ca {
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        }
+        Certificate::from_params(params).unwrap()
+    }
+
+    fn write_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn new_roots(ca: &Certificate) -> rustls::RootCertStore {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+            .unwrap();
+        roots
+    }
+
+    /// Returns common name of client certificate if server accepts the connection,
+    /// or an empty string if client certificate is not required.
+    async fn connect(acceptor: TlsAcceptor, client_config: ClientConfig) -> Option<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (tcp_stream, _address) = listener.accept().await.unwrap();
+            let tls_stream = acceptor.accept(tcp_stream).await.ok()?;
+            Some(tls_identity(tls_stream.get_ref().1, IdentitySource::TlsCn).unwrap_or_default())
+        });
+
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let tcp_stream = TcpStream::connect(address).await.unwrap();
+        let _ret = connector
+            .connect("localhost".try_into().unwrap(), tcp_stream)
+            .await;
+        server.await.unwrap()
+    }
+
+    /// Connect to server with `client` certificate signed by `signer`.
+    async fn handshake(
+        acceptor: TlsAcceptor,
+        ca: &Certificate,
+        client: &Certificate,
+        signer: &Certificate,
+    ) -> Option<String> {
+        let client_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(new_roots(ca))
+            .with_client_auth_cert(
+                vec![rustls::Certificate(
+                    client.serialize_der_with_signer(signer).unwrap(),
+                )],
+                rustls::PrivateKey(client.serialize_private_key_der()),
+            )
+            .unwrap();
+        connect(acceptor, client_config).await
+    }
+
+    /// Connect to server with only `version` enabled on client side.
+    async fn handshake_with_version(
+        acceptor: TlsAcceptor,
+        ca: &Certificate,
+        version: &'static rustls::SupportedProtocolVersion,
+    ) -> bool {
+        let client_config = ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[version])
+            .unwrap()
+            .with_root_certificates(new_roots(ca))
+            .with_no_client_auth();
+        connect(acceptor, client_config).await.is_some()
+    }
+
+    #[tokio::test]
+    async fn test_client_certificate() {
+        let dir = std::env::temp_dir().join("hebo-listener-mtls");
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca = new_cert("hebo-ca", true);
+        let server = new_cert("localhost", false);
+        let ca_file = write_file(&dir, "ca.pem", &ca.serialize_pem().unwrap());
+        let cert_file = write_file(
+            &dir,
+            "cert.pem",
+            &server.serialize_pem_with_signer(&ca).unwrap(),
+        );
+        let key_file = write_file(&dir, "key.pem", &server.serialize_private_key_pem());
+
+        let mut listener_config = config::Listener::new(Protocol::Mqtts, "127.0.0.1:0");
+        listener_config
+            .set_cert_file(cert_file, key_file)
+            .set_require_certificate(Some(ca_file));
+        let server_config = Listener::get_cert_config(&listener_config).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+        let client = new_cert("device-1", false);
+        let identity = handshake(acceptor.clone(), &ca, &client, &ca).await;
+        assert_eq!(identity.as_deref(), Some("device-1"));
+
+        // Signed by untrusted CA.
+        let other_ca = new_cert("other-ca", true);
+        let client = new_cert("device-2", false);
+        let identity = handshake(acceptor, &ca, &client, &other_ca).await;
+        assert_eq!(identity, None);
+    }
+
+    #[tokio::test]
+    async fn test_min_tls_version() {
+        let dir = std::env::temp_dir().join("hebo-listener-tls-version");
+        std::fs::create_dir_all(&dir).unwrap();
+        let ca = new_cert("hebo-ca", true);
+        let server = new_cert("localhost", false);
+        let cert_file = write_file(
+            &dir,
+            "cert.pem",
+            &server.serialize_pem_with_signer(&ca).unwrap(),
+        );
+        let key_file = write_file(&dir, "key.pem", &server.serialize_private_key_pem());
+
+        let mut listener_config = config::Listener::new(Protocol::Mqtts, "127.0.0.1:0");
+        listener_config.set_cert_file(cert_file, key_file);
+        let server_config = Listener::get_cert_config(&listener_config).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        assert!(handshake_with_version(acceptor, &ca, &rustls::version::TLS12).await);
+
+        listener_config.set_tls_options(TlsVersion::Tls13, Vec::new());
+        let server_config = Listener::get_cert_config(&listener_config).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        assert!(!handshake_with_version(acceptor.clone(), &ca, &rustls::version::TLS12).await);
+        assert!(handshake_with_version(acceptor, &ca, &rustls::version::TLS13).await);
+
+        // Only cipher suites in allowlist are negotiated.
+        listener_config.set_tls_options(
+            TlsVersion::Tls12,
+            vec!["TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256".to_string()],
+        );
+        let server_config = Listener::get_cert_config(&listener_config).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        assert!(handshake_with_version(acceptor.clone(), &ca, &rustls::version::TLS12).await);
+        assert!(!handshake_with_version(acceptor, &ca, &rustls::version::TLS13).await);
     }
 }
@@ -0,0 +1,114 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Defer publish acks until messages are written to journal.
+
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::oneshot;
+
+use super::Listener;
+use crate::backends::batch::BatchSender;
+use crate::commands::ListenerToSessionCmd;
+use crate::error::Error;
+use crate::session::OutgoingPacket;
+use crate::types::SessionId;
+
+/// Max number of acks waiting for their messages to be written.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Ack sent to session after its message is written to journal.
+#[derive(Debug)]
+struct PendingAck {
+    committed: oneshot::Receiver<Result<(), Error>>,
+    session_id: SessionId,
+    session_sender: Sender<ListenerToSessionCmd>,
+    cmd: ListenerToSessionCmd,
+}
+
+/// Journal writer of a listener.
+#[derive(Debug)]
+pub struct Journal {
+    writer: BatchSender<OutgoingPacket>,
+    acks: Sender<PendingAck>,
+}
+
+impl Journal {
+    /// Create journal handle, and start a task to send acks in order.
+    #[must_use]
+    pub fn new(writer: BatchSender<OutgoingPacket>) -> Self {
+        let (acks, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(send_acks(receiver));
+        Self { writer, acks }
+    }
+}
+
+/// Send acks in the same order as messages are received [MQTT-4.6.0-2].
+///
+/// Batches are committed in order, so waiting for them one by one does not
+/// delay any ack.
+async fn send_acks(mut receiver: Receiver<PendingAck>) {
+    while let Some(ack) = receiver.recv().await {
+        match ack.committed.await {
+            Ok(Ok(())) => {
+                if let Err(err) = ack.session_sender.send(ack.cmd).await {
+                    log::error!(
+                        "listener: Failed to send publish ack to session: {:?}, err: {:?}",
+                        ack.session_id,
+                        err
+                    );
+                }
+            }
+            // Message is not acked, client will send it again.
+            Ok(Err(err)) => log::error!(
+                "listener: Failed to write message of session {} to journal, err: {:?}",
+                ack.session_id,
+                err
+            ),
+            Err(err) => log::error!("listener: Journal writer is stopped, err: {:?}", err),
+        }
+    }
+}
+
+impl Listener {
+    /// Returns true if accepted qos=1 and qos=2 messages shall be written to journal
+    /// before they are acked.
+    pub(super) const fn has_journal(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Write `packet` to journal, and send `cmd` to session after it is written.
+    pub(super) async fn write_journal(
+        &self,
+        session_id: SessionId,
+        packet: OutgoingPacket,
+        cmd: ListenerToSessionCmd,
+    ) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let Some(session_sender) = self.session_senders.get(&session_id) else {
+            log::error!(
+                "listener: Failed to find session sender with id: {}",
+                session_id
+            );
+            return;
+        };
+        let committed = match journal.writer.enqueue(packet).await {
+            Ok(committed) => committed,
+            Err(err) => {
+                log::error!("listener: Failed to write journal, err: {:?}", err);
+                return;
+            }
+        };
+        let ack = PendingAck {
+            committed,
+            session_id,
+            session_sender: session_sender.clone(),
+            cmd,
+        };
+        if let Err(err) = journal.acks.send(ack).await {
+            log::error!("listener: Failed to queue publish ack, err: {:?}", err);
+        }
+    }
+}
@@ -2,26 +2,45 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use codec::{PacketId, QoS};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver};
 
+use crate::auth::AuthMethod;
 use crate::commands::{
     AclToListenerCmd, AuthToListenerCmd, DispatcherToListenerCmd, ListenerToAclCmd,
-    ListenerToAuthCmd, ListenerToDispatcherCmd, ListenerToSessionCmd, SessionToListenerCmd,
+    ListenerToAuthCmd, ListenerToDispatcherCmd, ListenerToSessionCmd, ServerContextToListenerCmd,
+    SessionToListenerCmd,
 };
-use crate::config;
+use crate::config::{self, QueueFullPolicy};
+use crate::error::Error;
+use crate::log::AccessRecord;
 use crate::types::{ListenerId, SessionId};
 
 mod acl;
+mod admission;
 mod auth;
 mod dispatcher;
+mod handshake;
+mod identity;
 mod init;
+mod journal;
 mod protocol;
+mod proxy_protocol;
 mod run;
+mod server;
 mod session;
+mod websocket;
+mod will;
 
+use admission::AdmissionPolicy;
+use handshake::Accepted;
+use journal::Journal;
 use protocol::Protocol;
+use will::DelayedWill;
 
 const CHANNEL_CAPACITY: usize = 16;
 
@@ -30,25 +49,74 @@ pub struct Listener {
     id: ListenerId,
     protocol: Protocol,
     config: config::Listener,
+    /// Maximum packet size accepted by broker, 0 means no limit.
+    maximum_packet_size: usize,
+    /// Maximum will payload size in connect packet, 0 means no limit.
+    max_will_payload_size: usize,
+    /// Maximum `QoS` of publish packets and subscriptions accepted by broker.
+    maximum_qos: QoS,
+    /// Maximum number of messages queued for each client when its inflight window is full.
+    max_queued_messages: usize,
+    queue_full_policy: QueueFullPolicy,
+    /// Accept topic filters with wildcard characters.
+    wildcard_subscription_available: bool,
+    /// Validate payload of publish packets declared as UTF-8 string.
+    validate_payload_format: bool,
+    /// Response information sent to v5 clients, empty if disabled.
+    response_information: String,
+    /// Enhanced auth methods of v5 clients.
+    auth_methods: Vec<Arc<dyn AuthMethod>>,
     current_session_id: SessionId,
 
     session_senders: HashMap<SessionId, Sender<ListenerToSessionCmd>>,
+    /// `client_id` -> session of connected client.
     client_ids: BTreeMap<String, SessionId>,
+    /// `session_id` -> `client_id`, reverse index of `client_ids`.
+    session_client_ids: HashMap<SessionId, String>,
+    /// `session_id` -> identity of client used to check ACL, see `identity_source`.
+    session_usernames: HashMap<SessionId, String>,
+    /// `session_id` -> identity of client extracted from TLS connection or http header.
+    session_identities: HashMap<SessionId, String>,
+    /// `session_id` -> address of remote client, passed to auth app.
+    session_addresses: HashMap<SessionId, SocketAddr>,
+    /// `(session_id, packet_id)` -> index of topic filters rejected by ACL,
+    /// which are inserted back to subscribe ack packet.
+    denied_subscriptions: HashMap<(SessionId, PacketId), Vec<usize>>,
+    /// `session_id` -> connection info of accepted client, written to access log.
+    access_records: HashMap<SessionId, AccessRecord>,
 
-    // session_id -> clean_session.
-    connecting_sessions: HashSet<SessionId>,
+    /// `session_id` -> `(client_id, taken_over)` of sessions waiting for auth result.
+    ///
+    /// `taken_over` is true if a connected client with the same id has been disconnected
+    /// by this session, its presence is cleared if this session is not accepted.
+    connecting_sessions: HashMap<SessionId, (String, bool)>,
+
+    admission: AdmissionPolicy,
+
+    /// `client_id` -> will message waiting for Will Delay Interval.
+    delayed_wills: HashMap<String, DelayedWill>,
+
+    /// Accepted qos=1 and qos=2 messages are acked after they are written to journal,
+    /// disabled if None.
+    journal: Option<Journal>,
 
     session_sender: Sender<SessionToListenerCmd>,
     session_receiver: Option<Receiver<SessionToListenerCmd>>,
 
+    /// New connections whose handshake is finished, or failed.
+    handshake_sender: Sender<Result<Accepted, Error>>,
+    handshake_receiver: Option<Receiver<Result<Accepted, Error>>>,
+
     dispatcher_sender: Sender<ListenerToDispatcherCmd>,
-    dispatcher_receiver: Option<Receiver<DispatcherToListenerCmd>>,
+    dispatcher_receiver: Option<UnboundedReceiver<DispatcherToListenerCmd>>,
 
     auth_sender: Sender<ListenerToAuthCmd>,
-    auth_receiver: Option<Receiver<AuthToListenerCmd>>,
+    auth_receiver: Option<UnboundedReceiver<AuthToListenerCmd>>,
 
     acl_sender: Sender<ListenerToAclCmd>,
-    acl_receiver: Option<Receiver<AclToListenerCmd>>,
+    acl_receiver: Option<UnboundedReceiver<AclToListenerCmd>>,
+
+    server_ctx_receiver: Option<Receiver<ServerContextToListenerCmd>>,
 }
 
 impl Drop for Listener {
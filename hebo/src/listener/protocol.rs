@@ -3,6 +3,7 @@
 // in the LICENSE file.
 
 use std::fmt;
+use std::net::SocketAddr;
 use tokio::net::TcpListener;
 #[cfg(unix)]
 use tokio::net::UnixListener;
@@ -33,3 +34,18 @@ impl fmt::Debug for Protocol {
         write!(f, "{msg}")
     }
 }
+
+impl Protocol {
+    /// Get socket address this listener is bound to, None for unix domain socket.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Mqtt(listener)
+            | Self::Mqtts(listener, _)
+            | Self::Ws(listener)
+            | Self::Wss(listener, _) => listener.local_addr().ok(),
+            #[cfg(unix)]
+            Self::Uds(_) => None,
+            Self::Quic(endpoint) => endpoint.local_addr().ok(),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Read PROXY protocol header sent by load balancer before mqtt packets.
+//!
+//! See <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::{Error, ErrorKind};
+
+/// Header shall be sent right after connection is established.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const V1_PREFIX: &[u8] = b"PROXY";
+/// Maximum length of v1 header line, including CRLF.
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+
+/// Read PROXY protocol header from `stream`, bytes after the header are left in stream.
+///
+/// Returns source address of client, or None if header is sent by proxy itself,
+/// like health checks, in which case address of connection is used.
+///
+/// # Errors
+///
+/// Returns error if header is malformed or not received in time.
+pub async fn read_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> Result<Option<SocketAddr>, Error> {
+    tokio::time::timeout(HEADER_TIMEOUT, read_header_inner(stream))
+        .await
+        .map_err(|_elapsed| {
+            Error::new(
+                ErrorKind::SocketError,
+                "Timeout to read proxy protocol header",
+            )
+        })?
+}
+
+async fn read_header_inner<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> Result<Option<SocketAddr>, Error> {
+    // Both v1 and v2 headers are longer than this prefix.
+    let mut buf = vec![0; V1_PREFIX.len()];
+    stream.read_exact(&mut buf).await?;
+
+    if buf == V1_PREFIX {
+        // Read byte by byte, so that mqtt packets after the header are not consumed.
+        while !buf.ends_with(b"\r\n") {
+            if buf.len() >= V1_MAX_LEN {
+                return Err(malformed("v1 header is too long"));
+            }
+            buf.push(stream.read_u8().await?);
+        }
+        return parse_v1(&buf);
+    }
+
+    if buf != V2_SIGNATURE[..V1_PREFIX.len()] {
+        return Err(malformed("invalid signature"));
+    }
+    buf.resize(V2_HEADER_LEN, 0);
+    stream.read_exact(&mut buf[V1_PREFIX.len()..]).await?;
+    let len = usize::from(u16::from_be_bytes([buf[14], buf[15]]));
+    buf.resize(V2_HEADER_LEN + len, 0);
+    stream.read_exact(&mut buf[V2_HEADER_LEN..]).await?;
+    parse_v2(&buf)
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::from_string(
+        ErrorKind::DecodeError,
+        format!("Malformed proxy protocol header: {reason}"),
+    )
+}
+
+/// Parse v1 header line, like `PROXY TCP4 192.168.0.1 192.168.0.11 56324 1883\r\n`.
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    let line = std::str::from_utf8(line).map_err(|_err| malformed("invalid v1 header"))?;
+    let line = line
+        .strip_suffix("\r\n")
+        .ok_or_else(|| malformed("invalid v1 header"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        // Remaining parts are ignored for unknown protocol.
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), src_ip, _dst_ip, src_port, _dst_port] => {
+            let ip: IpAddr = src_ip
+                .parse()
+                .map_err(|_err| malformed("invalid source address"))?;
+            if ip.is_ipv4() != (*protocol == "TCP4") {
+                return Err(malformed("address does not match protocol"));
+            }
+            let port: u16 = src_port
+                .parse()
+                .map_err(|_err| malformed("invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(malformed("invalid v1 header")),
+    }
+}
+
+/// Parse v2 binary header, including signature.
+fn parse_v2(buf: &[u8]) -> Result<Option<SocketAddr>, Error> {
+    if buf.len() < V2_HEADER_LEN || !buf.starts_with(V2_SIGNATURE) {
+        return Err(malformed("invalid signature"));
+    }
+    let version_command = buf[12];
+    if version_command >> 4 != 2 {
+        return Err(malformed("unsupported version"));
+    }
+    let addresses = &buf[V2_HEADER_LEN..];
+    match version_command & 0x0f {
+        // LOCAL command, connection is established by proxy itself.
+        0x00 => Ok(None),
+        // PROXY command.
+        0x01 => match buf[13] >> 4 {
+            // AF_INET
+            0x01 => {
+                let Some(addresses) = addresses.get(..12) else {
+                    return Err(malformed("address block is too short"));
+                };
+                let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+                let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+                Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+            }
+            // AF_INET6
+            0x02 => {
+                let Some(addresses) = addresses.get(..36) else {
+                    return Err(malformed("address block is too short"));
+                };
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&addresses[..16]);
+                let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+                Ok(Some(SocketAddr::new(
+                    IpAddr::V6(Ipv6Addr::from(octets)),
+                    port,
+                )))
+            }
+            // AF_UNSPEC or AF_UNIX, address of connection is used.
+            _ => Ok(None),
+        },
+        _ => Err(malformed("unsupported command")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::read_header;
+
+    async fn read(header: &[u8]) -> (Option<Option<SocketAddr>>, Vec<u8>) {
+        let mut stream = header;
+        let addr = read_header(&mut stream).await.ok();
+        (addr, stream.to_vec())
+    }
+
+    fn v2_header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut buf = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+        buf.push(0x20 | command);
+        buf.push(family);
+        buf.extend_from_slice(&u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        buf.extend_from_slice(addresses);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_v1() {
+        let (addr, remaining) =
+            read(b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 1883\r\n\x10\x00").await;
+        assert_eq!(addr, Some(Some("203.0.113.7:56324".parse().unwrap())));
+        // Mqtt packets are kept in stream.
+        assert_eq!(remaining, b"\x10\x00");
+
+        let (addr, _remaining) = read(b"PROXY TCP6 2001:db8::7 2001:db8::1 56324 1883\r\n").await;
+        assert_eq!(addr, Some(Some("[2001:db8::7]:56324".parse().unwrap())));
+
+        let (addr, _remaining) = read(b"PROXY UNKNOWN\r\n").await;
+        assert_eq!(addr, Some(None));
+    }
+
+    #[tokio::test]
+    async fn test_v2() {
+        let mut addresses = vec![203, 0, 113, 7, 10, 0, 0, 1];
+        addresses.extend_from_slice(&56324_u16.to_be_bytes());
+        addresses.extend_from_slice(&1883_u16.to_be_bytes());
+        // TLV of ALPN is ignored.
+        addresses.extend_from_slice(&[0x01, 0x00, 0x04, b'm', b'q', b't', b't']);
+        let mut header = v2_header(0x01, 0x11, &addresses);
+        header.extend_from_slice(b"\x10\x00");
+        let (addr, remaining) = read(&header).await;
+        assert_eq!(addr, Some(Some("203.0.113.7:56324".parse().unwrap())));
+        assert_eq!(remaining, b"\x10\x00");
+
+        let mut addresses = vec![0; 36];
+        addresses[..16].copy_from_slice(
+            &"2001:db8::7"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        addresses[32..34].copy_from_slice(&56324_u16.to_be_bytes());
+        let (addr, _remaining) = read(&v2_header(0x01, 0x21, &addresses)).await;
+        assert_eq!(addr, Some(Some("[2001:db8::7]:56324".parse().unwrap())));
+
+        // Health check from proxy.
+        let (addr, _remaining) = read(&v2_header(0x00, 0x00, &[])).await;
+        assert_eq!(addr, Some(None));
+    }
+
+    #[tokio::test]
+    async fn test_malformed() {
+        for header in [
+            &b"\x10\x0c\x00\x04MQTT\x04\x02\x00\x3c"[..],
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 56324\r\n",
+            b"PROXY TCP4 2001:db8::7 2001:db8::1 56324 1883\r\n",
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 99999 1883\r\n",
+            b"PROXY TCP4 203.0.113.7 10.0.0.1 56324 1883\n",
+            b"PROXY UNKNOWN",
+        ] {
+            assert_eq!(read(header).await.0, None);
+        }
+
+        // Line without CRLF is too long.
+        let header = [b"PROXY ".as_slice(), &[b'x'; 200]].concat();
+        assert_eq!(read(&header).await.0, None);
+
+        // Address block is truncated.
+        assert_eq!(
+            read(&v2_header(0x01, 0x11, &[203, 0, 113, 7])).await.0,
+            None
+        );
+        let mut header = v2_header(0x01, 0x11, &[0; 12]);
+        header.truncate(20);
+        assert_eq!(read(&header).await.0, None);
+
+        // Invalid version.
+        let mut header = v2_header(0x01, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        assert_eq!(read(&header).await.0, None);
+    }
+}
@@ -4,23 +4,46 @@
 
 //! Handles commands and new connections
 
+use codec::v5;
+use std::net::SocketAddr;
 use tokio::sync::mpsc;
 
 use super::Listener;
 use super::CHANNEL_CAPACITY;
-use crate::commands::ListenerToDispatcherCmd;
+use crate::commands::{
+    ListenerToDispatcherCmd, ListenerToSessionCmd, ServerContextToListenerCmd, SessionToListenerCmd,
+};
+use crate::error::Error;
+use crate::log::{AccessEvent, LogLimiter};
 use crate::session::{Session, SessionConfig};
 use crate::stream::Stream;
 
+/// Failed handshakes may be triggered by every client, log them less frequently.
+static ACCEPT_LOGS: LogLimiter = LogLimiter::new();
+
+fn log_accept_error(err: &Error) {
+    if let Some(suppressed) = ACCEPT_LOGS.check() {
+        log::warn!(
+            "listener: Failed to accept connection, err: {:?}{}",
+            err,
+            suppressed
+        );
+    }
+}
+
 impl Listener {
     /// # Panics
     /// Raise panic if failed to unpack channel receivers.
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         // Take ownership of mpsc receiver or else tokio select will raise error.
         let mut session_receiver = self
             .session_receiver
             .take()
             .expect("Invalid session receiver");
+        let mut handshake_receiver = self
+            .handshake_receiver
+            .take()
+            .expect("Invalid handshake receiver");
 
         let mut dispatcher_receiver = self
             .dispatcher_receiver
@@ -28,11 +51,24 @@ impl Listener {
             .expect("Invalid dispatcher receiver");
         let mut auth_receiver = self.auth_receiver.take().expect("Invalid auth receiver");
         let mut acl_receiver = self.acl_receiver.take().expect("Invalid acl receiver");
+        let mut server_ctx_receiver = self
+            .server_ctx_receiver
+            .take()
+            .expect("Invalid server ctx receiver");
 
         loop {
             tokio::select! {
-                Ok(stream) = self.accept() => {
-                    self.new_connection(stream).await;
+                // Errors are matched here, or else this branch is disabled until
+                // another branch completes.
+                ret = self.accept() => if let Err(err) = ret {
+                    log_accept_error(&err);
+                },
+
+                Some(ret) = handshake_receiver.recv() => match ret {
+                    Ok((stream, identity, proxy_address)) => {
+                        self.new_connection(stream, identity, proxy_address).await;
+                    }
+                    Err(err) => log_accept_error(&err),
                 },
 
                 Some(cmd) = session_receiver.recv() => {
@@ -58,19 +94,142 @@ impl Listener {
                         log::error!("handle acl cmd failed: {:?}", err);
                     }
                 }
+
+                Some(cmd) = server_ctx_receiver.recv() => {
+                    let reason_code = match cmd {
+                        ServerContextToListenerCmd::Stop => v5::ReasonCode::ServerShuttingDown,
+                        ServerContextToListenerCmd::Remove => v5::ReasonCode::AdministrativeAction,
+                        cmd => {
+                            if let Err(err) = self.handle_server_ctx_cmd(cmd).await {
+                                log::error!("handle server ctx cmd failed: {:?}", err);
+                            }
+                            continue;
+                        }
+                    };
+                    self.stop(session_receiver, reason_code).await;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Disconnect all sessions and wait for them to exit.
+    ///
+    /// Commands from sessions are still received meanwhile, so that sessions never
+    /// block on sending commands back to this listener, and state of persistent sessions
+    /// is passed to dispatcher before it is saved.
+    async fn stop(
+        &mut self,
+        mut session_receiver: mpsc::Receiver<SessionToListenerCmd>,
+        reason_code: v5::ReasonCode,
+    ) {
+        log::info!("listener: Stop listener {}", self.id);
+        self.session_client_ids.clear();
+        self.session_usernames.clear();
+        self.session_identities.clear();
+        self.session_addresses.clear();
+        self.denied_subscriptions.clear();
+        for record in std::mem::take(&mut self.access_records).into_values() {
+            record.log(AccessEvent::Disconnect);
+        }
+        for client_id in std::mem::take(&mut self.client_ids).into_keys() {
+            if let Err(err) = self
+                .dispatcher_sender
+                .send(ListenerToDispatcherCmd::ClientDisconnected(
+                    self.id, client_id,
+                ))
+                .await
+            {
+                log::warn!(
+                    "listener: Failed to send client disconnected, err: {:?}",
+                    err
+                );
+            }
+        }
+
+        self.disconnect_sessions(&mut session_receiver, reason_code)
+            .await;
+        // Session sends its state before it exits.
+        while let Ok(cmd) = session_receiver.try_recv() {
+            self.handle_stopping_session_cmd(cmd).await;
+        }
+        self.session_senders.clear();
+    }
+
+    /// Send disconnect cmd to all sessions and wait for them to exit, while receiving
+    /// commands from them.
+    async fn disconnect_sessions(
+        &self,
+        session_receiver: &mut mpsc::Receiver<SessionToListenerCmd>,
+        reason_code: v5::ReasonCode,
+    ) {
+        let session_senders = &self.session_senders;
+        let disconnect_sessions = async {
+            for (session_id, session_sender) in session_senders {
+                if let Err(err) = session_sender
+                    .send(ListenerToSessionCmd::Disconnect(reason_code))
+                    .await
+                {
+                    log::warn!(
+                        "listener: Failed to disconnect session {}, err: {:?}",
+                        session_id,
+                        err
+                    );
+                }
+            }
+            // Session drops its receiver after main loop exits.
+            for session_sender in session_senders.values() {
+                session_sender.closed().await;
+            }
+        };
+        tokio::pin!(disconnect_sessions);
+        loop {
+            tokio::select! {
+                () = &mut disconnect_sessions => break,
+                Some(cmd) = session_receiver.recv() => self.handle_stopping_session_cmd(cmd).await,
             }
         }
     }
 
-    async fn new_connection(&mut self, stream: Stream) {
+    /// Spawn a session for new connection.
+    ///
+    /// `proxy_address` is address of client in PROXY protocol header, which is preferred
+    /// over address of the connection.
+    async fn new_connection(
+        &mut self,
+        stream: Stream,
+        identity: Option<String>,
+        proxy_address: Option<SocketAddr>,
+    ) {
         let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let session_id = self.next_session_id();
         self.session_senders.insert(session_id, sender);
+        if let Some(identity) = identity {
+            self.session_identities.insert(session_id, identity);
+        }
+        if let Some(address) = proxy_address.or_else(|| stream.peer_addr()) {
+            self.session_addresses.insert(session_id, address);
+        }
         let mut session_config = SessionConfig::new();
         session_config
             .set_keep_alive(self.config.keep_alive())
             .set_allow_empty_client_id(self.config.allow_empty_client_id())
             .set_maximum_inflight_messages(self.config.maximum_inflight_messages())
+            .set_inflight_window(self.config.maximum_inflight_messages())
+            .set_queue_limit(self.max_queued_messages, self.queue_full_policy)
+            .set_retransmit_delay(self.config.retransmit_delay())
+            .set_max_message_rate(self.config.max_message_rate())
+            .set_max_byte_rate(self.config.max_byte_rate())
+            .set_rate_limit_policy(self.config.rate_limit_policy())
+            .set_topic_alias_maximum(self.config.topic_alias_maximum())
+            .set_read_buffer_cap(self.maximum_packet_size)
+            .set_max_will_payload_size(self.max_will_payload_size)
+            .set_maximum_qos(self.maximum_qos)
+            .set_wildcard_subscription_available(self.wildcard_subscription_available)
+            .set_validate_payload_format(self.validate_payload_format)
+            .set_response_information(&self.response_information)
+            .set_auth_methods(&self.auth_methods)
+            .set_capture(self.config.capture())
             .set_connect_timeout(self.config.connect_timeout());
         let session = Session::new(
             session_id,
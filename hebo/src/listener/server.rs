@@ -0,0 +1,159 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Server context cmd handler.
+
+use codec::v5;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use super::Listener;
+use crate::commands::{ListenerToSessionCmd, ServerContextToListenerCmd};
+use crate::error::{Error, ErrorKind};
+use crate::types::{ClientInfo, Redirect};
+
+/// Time to wait for sessions to report their inflight messages.
+const INFLIGHT_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Listener {
+    /// Handle commands other than `Stop` and `Remove`, which are handled in main loop.
+    pub(super) async fn handle_server_ctx_cmd(
+        &self,
+        cmd: ServerContextToListenerCmd,
+    ) -> Result<(), Error> {
+        match cmd {
+            ServerContextToListenerCmd::DrainSessions(redirect) => {
+                self.on_server_ctx_drain_sessions(redirect).await
+            }
+            ServerContextToListenerCmd::DrainClient(client_id, redirect, resp_tx) => {
+                self.on_server_ctx_drain_client(&client_id, redirect, resp_tx)
+                    .await
+            }
+            ServerContextToListenerCmd::DisconnectClient(client_id, resp_tx) => {
+                self.on_server_ctx_disconnect_client(&client_id, resp_tx)
+                    .await
+            }
+            ServerContextToListenerCmd::ListClients(client_id, resp_tx) => {
+                self.on_server_ctx_list_clients(client_id.as_deref(), resp_tx)
+                    .await;
+                Ok(())
+            }
+            ServerContextToListenerCmd::Stop | ServerContextToListenerCmd::Remove => Ok(()),
+        }
+    }
+
+    async fn on_server_ctx_drain_sessions(&self, redirect: Redirect) -> Result<(), Error> {
+        log::info!("listener: Drain all sessions of listener {}", self.id);
+        for session_id in self.client_ids.values() {
+            if let Some(session_sender) = self.session_senders.get(session_id) {
+                session_sender
+                    .send(ListenerToSessionCmd::Drain(redirect.clone()))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_server_ctx_drain_client(
+        &self,
+        client_id: &str,
+        redirect: Redirect,
+        resp_tx: oneshot::Sender<bool>,
+    ) -> Result<(), Error> {
+        let session_sender = self
+            .client_ids
+            .get(client_id)
+            .and_then(|session_id| self.session_senders.get(session_id));
+        let found = session_sender.is_some();
+        if let Some(session_sender) = session_sender {
+            log::info!("listener: Drain session of client id {}", client_id);
+            session_sender
+                .send(ListenerToSessionCmd::Drain(redirect))
+                .await?;
+        }
+        resp_tx.send(found).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send drain client response to server ctx",
+            )
+        })
+    }
+
+    /// Disconnect session of client, v5 clients are notified with `AdministrativeAction`.
+    async fn on_server_ctx_disconnect_client(
+        &self,
+        client_id: &str,
+        resp_tx: oneshot::Sender<bool>,
+    ) -> Result<(), Error> {
+        let session_sender = self
+            .client_ids
+            .get(client_id)
+            .and_then(|session_id| self.session_senders.get(session_id));
+        let found = session_sender.is_some();
+        if let Some(session_sender) = session_sender {
+            log::info!("listener: Disconnect session of client id {}", client_id);
+            session_sender
+                .send(ListenerToSessionCmd::Disconnect(
+                    v5::ReasonCode::AdministrativeAction,
+                ))
+                .await?;
+        }
+        resp_tx.send(found).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send disconnect client response to server ctx",
+            )
+        })
+    }
+
+    /// Collect connected clients of this listener.
+    ///
+    /// Numbers of inflight messages are queried from sessions in a separate task,
+    /// so that listener is not blocked by busy sessions.
+    async fn on_server_ctx_list_clients(
+        &self,
+        client_id: Option<&str>,
+        resp_tx: oneshot::Sender<Vec<ClientInfo>>,
+    ) {
+        let mut clients = Vec::new();
+        let mut inflight_receivers = Vec::new();
+        for (session_id, record) in &self.access_records {
+            if client_id.map_or(false, |client_id| client_id != record.client_id()) {
+                continue;
+            }
+            let (inflight_tx, inflight_rx) = oneshot::channel();
+            if let Some(session_sender) = self.session_senders.get(session_id) {
+                // Inflight messages are reported as 0 if session is closed.
+                let _ret = session_sender
+                    .send(ListenerToSessionCmd::GetInflightMessages(inflight_tx))
+                    .await;
+            }
+            clients.push(ClientInfo {
+                listener_id: self.id,
+                session_id: *session_id,
+                client_id: record.client_id().to_string(),
+                username: record.username().to_string(),
+                remote_address: record.remote_address().map(|address| address.to_string()),
+                connected_at: record.connected_at(),
+                protocol_level: record.protocol_level() as u8,
+                subscriptions: 0,
+                inflight_messages: 0,
+            });
+            inflight_receivers.push(inflight_rx);
+        }
+
+        tokio::spawn(async move {
+            for (client, inflight_rx) in clients.iter_mut().zip(inflight_receivers) {
+                if let Ok(Ok(inflight)) =
+                    tokio::time::timeout(INFLIGHT_QUERY_TIMEOUT, inflight_rx).await
+                {
+                    client.inflight_messages = inflight;
+                }
+            }
+            if resp_tx.send(clients).is_err() {
+                log::warn!("listener: Failed to send clients to server ctx");
+            }
+        });
+    }
+}
@@ -4,15 +4,16 @@
 
 //! Session cmd handlers.
 
-use codec::{v3, v5};
+use codec::{v3, v5, EncodeError, ProtocolLevel, StringData};
 
+use super::identity;
 use super::Listener;
 use crate::listener::{
-    ListenerToAclCmd, ListenerToAuthCmd, ListenerToDispatcherCmd, ListenerToSessionCmd,
-    SessionToListenerCmd,
+    ListenerToAuthCmd, ListenerToDispatcherCmd, ListenerToSessionCmd, SessionToListenerCmd,
 };
+use crate::log::{AccessEvent, AccessRecord};
 use crate::session::CachedSession;
-use crate::types::{SessionGid, SessionId};
+use crate::types::{AclPacket, SessionGid, SessionId};
 use crate::Error;
 
 impl Listener {
@@ -26,6 +27,7 @@ impl Listener {
         cmd: SessionToListenerCmd,
     ) -> Result<(), Error> {
         log::info!("Listener::handle_session_cmd: {:?}", cmd);
+        self.update_access_record(&cmd);
         match cmd {
             SessionToListenerCmd::Connect(session_id, packet) => {
                 self.on_session_connect(session_id, packet).await
@@ -57,6 +59,57 @@ impl Listener {
             SessionToListenerCmd::DisconnectV5(session_id) => {
                 self.on_session_disconnect_v5(session_id).await
             }
+            SessionToListenerCmd::CacheSession(session_id, cached_session) => {
+                self.on_session_cache_session(session_id, cached_session)
+                    .await
+            }
+            SessionToListenerCmd::DelayedWillV5(session_id, client_id, packet, delay) => {
+                self.on_session_delayed_will_v5(session_id, client_id, packet, delay);
+                Ok(())
+            }
+            SessionToListenerCmd::WillDelayElapsed(session_id, client_id) => {
+                self.on_session_will_delay_elapsed(session_id, client_id)
+                    .await
+            }
+        }
+    }
+
+    /// Pass state of persistent sessions to dispatcher, other commands are dropped
+    /// as listener is stopping.
+    pub(super) async fn handle_stopping_session_cmd(&self, cmd: SessionToListenerCmd) {
+        if let SessionToListenerCmd::CacheSession(session_id, cached_session) = cmd {
+            if let Err(err) = self
+                .on_session_cache_session(session_id, cached_session)
+                .await
+            {
+                log::error!(
+                    "listener: Failed to cache session {}, err: {:?}",
+                    session_id,
+                    err
+                );
+            }
+        }
+    }
+
+    /// Count requests of client, which are written to access log on disconnect.
+    fn update_access_record(&mut self, cmd: &SessionToListenerCmd) {
+        let (session_id, update): (SessionId, fn(&mut AccessRecord)) = match cmd {
+            SessionToListenerCmd::Publish(session_id, _)
+            | SessionToListenerCmd::PublishV5(session_id, _) => {
+                (*session_id, AccessRecord::on_publish)
+            }
+            SessionToListenerCmd::Subscribe(session_id, _)
+            | SessionToListenerCmd::SubscribeV5(session_id, _) => {
+                (*session_id, AccessRecord::on_subscribe)
+            }
+            SessionToListenerCmd::Unsubscribe(session_id, _)
+            | SessionToListenerCmd::UnsubscribeV5(session_id, _) => {
+                (*session_id, AccessRecord::on_unsubscribe)
+            }
+            _ => return,
+        };
+        if let Some(record) = self.access_records.get_mut(&session_id) {
+            update(record);
         }
     }
 
@@ -67,27 +120,19 @@ impl Listener {
     ) -> Result<(), Error> {
         log::info!("Listener::on_session_connect()");
 
-        // If the ClientId represents a Client already connected to the Server then the Server MUST
-        // disconnect the existing Client [MQTT-3.1.4-2].
-        let old_session_id = self.client_ids.get(packet.client_id());
-        if let Some(old_session_id) = old_session_id {
-            let old_session_id = *old_session_id;
-            if let Err(err) = self.disconnect_session(old_session_id).await {
-                log::error!(
-                    "Failed to send disconnect cmd to {}, err: {:?}",
-                    old_session_id,
-                    err
-                );
-            }
+        if !self.check_admission(session_id).await? {
+            return Ok(());
         }
 
-        // TODO(Shaohua): Check duplicated ConnectPacket.
-        self.connecting_sessions.insert(session_id);
+        self.take_over_client_id(session_id, packet.client_id())
+            .await;
 
         // Send request to auth app.
         self.auth_sender
             .send(ListenerToAuthCmd::RequestAuth(
                 SessionGid::new(self.id, session_id),
+                self.session_addresses.get(&session_id).copied(),
+                self.session_identities.get(&session_id).cloned(),
                 packet,
             ))
             .await
@@ -101,28 +146,20 @@ impl Listener {
     ) -> Result<(), Error> {
         log::info!("Listener::on_session_connect_v5()");
 
-        // TODO(Shaohua): Update comments.
-        // If the ClientId represents a Client already connected to the Server then the Server MUST
-        // disconnect the existing Client [MQTT-3.1.4-2].
-        let old_session_id = self.client_ids.get(packet.client_id());
-        if let Some(old_session_id) = old_session_id {
-            let old_session_id = *old_session_id;
-            if let Err(err) = self.disconnect_session(old_session_id).await {
-                log::error!(
-                    "Failed to send disconnect cmd to {}, err: {:?}",
-                    old_session_id,
-                    err
-                );
-            }
+        // Reject or redirect new connection if server is overloaded.
+        if !self.check_admission_v5(session_id).await? {
+            return Ok(());
         }
 
-        // TODO(Shaohua): Check duplicated ConnectPacket.
-        self.connecting_sessions.insert(session_id);
+        self.take_over_client_id(session_id, packet.client_id())
+            .await;
 
         // Send request to auth app.
         self.auth_sender
             .send(ListenerToAuthCmd::RequestAuthV5(
                 SessionGid::new(self.id, session_id),
+                self.session_addresses.get(&session_id).copied(),
+                self.session_identities.get(&session_id).cloned(),
                 packet,
             ))
             .await
@@ -135,6 +172,7 @@ impl Listener {
         if self.session_senders.remove(&session_id).is_none() {
             log::error!("Failed to remove pipeline with session id: {}", session_id);
         }
+        self.on_client_disconnected(session_id).await?;
 
         self.dispatcher_sender
             .send(ListenerToDispatcherCmd::SessionRemoved(self.id))
@@ -148,6 +186,7 @@ impl Listener {
         if self.session_senders.remove(&session_id).is_none() {
             log::error!("Failed to remove pipeline with session id: {}", session_id);
         }
+        self.on_client_disconnected(session_id).await?;
 
         self.dispatcher_sender
             .send(ListenerToDispatcherCmd::SessionRemoved(self.id))
@@ -155,14 +194,120 @@ impl Listener {
             .map_err(Into::into)
     }
 
+    /// Register client id of accepted session and notify dispatcher.
+    pub(super) async fn on_client_connected(
+        &mut self,
+        session_id: SessionId,
+        client_id: &str,
+        username: &str,
+        protocol_level: ProtocolLevel,
+        clean_session: bool,
+    ) -> Result<(), Error> {
+        let record = AccessRecord::new(
+            self.id,
+            client_id,
+            username,
+            self.session_addresses.get(&session_id).copied(),
+            protocol_level,
+            clean_session,
+        );
+        record.log(AccessEvent::Connect);
+        self.access_records.insert(session_id, record);
+
+        let identity = identity::acl_identity(
+            self.config.identity_source(),
+            username,
+            self.session_identities.get(&session_id).map(String::as_str),
+        );
+        self.session_usernames.insert(session_id, identity);
+        // Old session has been removed in `take_over_client_id()`.
+        self.client_ids.insert(client_id.to_string(), session_id);
+        self.session_client_ids
+            .insert(session_id, client_id.to_string());
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::ClientConnected(
+                self.id,
+                client_id.to_string(),
+            ))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Remove client id of closed session and notify dispatcher.
+    async fn on_client_disconnected(&mut self, session_id: SessionId) -> Result<(), Error> {
+        if let Some(record) = self.access_records.remove(&session_id) {
+            record.log(AccessEvent::Disconnect);
+        }
+        self.session_usernames.remove(&session_id);
+        self.session_identities.remove(&session_id);
+        self.session_addresses.remove(&session_id);
+        self.denied_subscriptions
+            .retain(|(id, _packet_id), _indices| *id != session_id);
+        if let Some((client_id, taken_over)) = self.connecting_sessions.remove(&session_id) {
+            // Closed before auth result is received.
+            return self.on_connect_aborted(client_id, taken_over).await;
+        }
+        let Some(client_id) = self.session_client_ids.remove(&session_id) else {
+            return Ok(());
+        };
+        self.client_ids.remove(&client_id);
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::ClientDisconnected(
+                self.id, client_id,
+            ))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Clear presence of client taken over by a session which is not accepted.
+    pub(super) async fn on_connect_aborted(
+        &self,
+        client_id: String,
+        taken_over: bool,
+    ) -> Result<(), Error> {
+        if !taken_over {
+            return Ok(());
+        }
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::ClientDisconnected(
+                self.id, client_id,
+            ))
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn on_session_cache_session(
+        &self,
+        session_id: SessionId,
+        cached_session: CachedSession,
+    ) -> Result<(), Error> {
+        log::info!(
+            "Listener::on_session_cache_session(), session id: {}",
+            session_id
+        );
+        self.dispatcher_sender
+            .send(ListenerToDispatcherCmd::CacheSession(
+                SessionGid::new(self.id, session_id),
+                cached_session,
+            ))
+            .await
+            .map_err(Into::into)
+    }
+
     async fn on_session_subscribe(
         &mut self,
         session_id: SessionId,
         packet: v3::SubscribePacket,
     ) -> Result<(), Error> {
-        // Check ACL.
-        let cmd = ListenerToAclCmd::Subscribe(SessionGid::new(self.id, session_id), packet);
-        self.acl_sender.send(cmd).await.map_err(Into::into)
+        if !cfg!(feature = "acl") {
+            // ACL module is not built, accept all topic filters.
+            let granted = vec![true; packet.topics().len()];
+            return self
+                .on_acl_subscribe_ack(session_id, packet, &granted)
+                .await;
+        }
+        self.check_acl(session_id, AclPacket::Subscribe(packet))
+            .await
     }
 
     async fn on_session_subscribe_v5(
@@ -170,13 +315,19 @@ impl Listener {
         session_id: SessionId,
         packet: v5::SubscribePacket,
     ) -> Result<(), Error> {
-        // Check ACL.
-        let cmd = ListenerToAclCmd::SubscribeV5(SessionGid::new(self.id, session_id), packet);
-        self.acl_sender.send(cmd).await.map_err(Into::into)
+        if !cfg!(feature = "acl") {
+            // ACL module is not built, accept all topic filters.
+            let granted = vec![true; packet.topics().len()];
+            return self
+                .on_acl_subscribe_ack_v5(session_id, packet, &granted)
+                .await;
+        }
+        self.check_acl(session_id, AclPacket::SubscribeV5(packet))
+            .await
     }
 
     async fn on_session_unsubscribe(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v3::UnsubscribePacket,
     ) -> Result<(), Error> {
@@ -192,7 +343,7 @@ impl Listener {
     }
 
     async fn on_session_unsubscribe_v5(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v5::UnsubscribePacket,
     ) -> Result<(), Error> {
@@ -212,24 +363,80 @@ impl Listener {
         session_id: SessionId,
         packet: v3::PublishPacket,
     ) -> Result<(), Error> {
-        // Check ACL.
-        let cmd = ListenerToAclCmd::Publish(SessionGid::new(self.id, session_id), packet);
-        self.acl_sender.send(cmd).await.map_err(Into::into)
+        self.admission.on_message();
+
+        if !cfg!(feature = "acl") {
+            // ACL module is not built, accept all messages.
+            return self.on_acl_publish_ack(session_id, packet, true).await;
+        }
+
+        self.check_acl(session_id, AclPacket::Publish(packet)).await
     }
 
-    async fn on_session_publish_v5(
+    pub(super) async fn on_session_publish_v5(
         &mut self,
         session_id: SessionId,
         packet: v5::PublishPacket,
     ) -> Result<(), Error> {
-        // Check ACL.
-        let cmd = ListenerToAclCmd::PublishV5(SessionGid::new(self.id, session_id), packet);
-        self.acl_sender.send(cmd).await.map_err(Into::into)
+        self.admission.on_message();
+
+        if !cfg!(feature = "acl") {
+            // ACL module is not built, accept all messages.
+            return self.on_acl_publish_ack_v5(session_id, packet, true).await;
+        }
+
+        self.check_acl(session_id, AclPacket::PublishV5(packet))
+            .await
+    }
+
+    /// Disconnect older sessions with the same `client_id` as new session.
+    ///
+    /// If the `ClientId` represents a Client already connected to the Server then the Server MUST
+    /// disconnect the existing Client [MQTT-3.1.4-2]. Sessions still waiting for auth result
+    /// are disconnected too, so that only the latest one is accepted if clients with the same
+    /// id connect at nearly the same time.
+    async fn take_over_client_id(&mut self, session_id: SessionId, client_id: &str) {
+        let mut old_session_ids = Vec::new();
+        let mut taken_over = if let Some(old_session_id) = self.client_ids.remove(client_id) {
+            // Old session shall not clear presence of this client when it exits.
+            self.session_client_ids.remove(&old_session_id);
+            old_session_ids.push(old_session_id);
+            true
+        } else {
+            false
+        };
+        self.connecting_sessions
+            .retain(|old_session_id, (old_client_id, old_taken_over)| {
+                if old_client_id != client_id {
+                    return true;
+                }
+                old_session_ids.push(*old_session_id);
+                taken_over |= *old_taken_over;
+                false
+            });
+        self.connecting_sessions
+            .insert(session_id, (client_id.to_string(), taken_over));
+
+        for old_session_id in old_session_ids {
+            log::info!(
+                "listener: Session {} is taken over by {}, client id: {}",
+                old_session_id,
+                session_id,
+                client_id
+            );
+            if let Err(err) = self.disconnect_session(old_session_id).await {
+                log::error!(
+                    "Failed to send disconnect cmd to {}, err: {:?}",
+                    old_session_id,
+                    err
+                );
+            }
+        }
     }
 
     /// Send disconnect cmd to session.
-    async fn disconnect_session(&mut self, session_id: SessionId) -> Result<(), Error> {
-        let cmd = ListenerToSessionCmd::Disconnect;
+    async fn disconnect_session(&self, session_id: SessionId) -> Result<(), Error> {
+        let cmd = ListenerToSessionCmd::Disconnect(v5::ReasonCode::SessionTakenOver);
         if let Some(session_sender) = self.session_senders.get(&session_id) {
             session_sender.send(cmd).await.map_err(Into::into)
         } else {
@@ -238,12 +445,13 @@ impl Listener {
     }
 
     pub(crate) async fn session_send_connect_ack(
-        &mut self,
+        &self,
         session_id: SessionId,
         reason: v3::ConnectReturnCode,
         cached_session: Option<CachedSession>,
     ) -> Result<(), Error> {
-        let ack_packet = v3::ConnectAckPacket::new(false, reason);
+        // Session present flag is set if cached session state is resumed [MQTT-3.2.2-2].
+        let ack_packet = v3::ConnectAckPacket::new(cached_session.is_some(), reason);
         let cmd = ListenerToSessionCmd::ConnectAck(ack_packet, cached_session);
 
         if let Some(session_sender) = self.session_senders.get(&session_id) {
@@ -254,12 +462,25 @@ impl Listener {
     }
 
     pub(crate) async fn session_send_connect_ack_v5(
-        &mut self,
+        &self,
         session_id: SessionId,
         reason: v5::ReasonCode,
         cached_session: Option<CachedSession>,
     ) -> Result<(), Error> {
-        let ack_packet = v5::ConnectAckPacket::new(false, reason);
+        // Session present flag is set if cached session state is resumed [MQTT-3.2.2-2].
+        let mut ack_packet = v5::ConnectAckPacket::new(cached_session.is_some(), reason);
+        if reason.is_error() {
+            log::info!(
+                "listener: Reject session {}, reason: {}",
+                session_id,
+                reason.description()
+            );
+            let reason_string =
+                StringData::from(reason.description()).map_err(EncodeError::from)?;
+            ack_packet
+                .properties_mut()
+                .push(v5::Property::ReasonString(reason_string))?;
+        }
         let cmd = ListenerToSessionCmd::ConnectAckV5(ack_packet, cached_session);
 
         if let Some(session_sender) = self.session_senders.get(&session_id) {
@@ -270,7 +491,7 @@ impl Listener {
     }
 
     pub(super) async fn session_send_publish_ack(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v3::SubscribeAckPacket,
     ) -> Result<(), Error> {
@@ -283,7 +504,7 @@ impl Listener {
     }
 
     pub(super) async fn session_send_publish_ack_v5(
-        &mut self,
+        &self,
         session_id: SessionId,
         packet: v5::SubscribeAckPacket,
     ) -> Result<(), Error> {
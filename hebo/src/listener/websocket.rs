@@ -0,0 +1,140 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Check websocket handshake request of mqtt clients.
+
+use http::header::SEC_WEBSOCKET_PROTOCOL;
+use http::{HeaderValue, StatusCode};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+/// Websocket subprotocols of mqtt, `mqttv3.1` is used by some old v3.1 clients.
+const SUBPROTOCOLS: &[&str] = &["mqtt", "mqttv3.1"];
+
+fn error_response(status: StatusCode) -> ErrorResponse {
+    let mut response = ErrorResponse::new(None);
+    *response.status_mut() = status;
+    response
+}
+
+/// Check url path and negotiate subprotocol.
+///
+/// Requests to other paths than `path` are rejected with 404, if it is set.
+/// If client offers subprotocols, the first mqtt one is selected, or the request
+/// is rejected with 400 if none of them is supported.
+///
+/// Error type is required by callback of `tokio_tungstenite::accept_hdr_async()`.
+#[allow(clippy::result_large_err)]
+pub fn check_request(
+    request: &Request,
+    mut response: Response,
+    path: Option<&str>,
+) -> Result<Response, ErrorResponse> {
+    if path.map_or(false, |path| path != request.uri().path()) {
+        return Err(error_response(StatusCode::NOT_FOUND));
+    }
+
+    let mut offered = request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|protocol| !protocol.is_empty())
+        .peekable();
+    if offered.peek().is_none() {
+        return Ok(response);
+    }
+    let Some(protocol) = offered.find(|protocol| SUBPROTOCOLS.contains(protocol)) else {
+        log::warn!(
+            "listener: No supported websocket subprotocol in {:?}",
+            request.headers().get(SEC_WEBSOCKET_PROTOCOL)
+        );
+        return Err(error_response(StatusCode::BAD_REQUEST));
+    };
+    response.headers_mut().insert(
+        SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_str(protocol).map_err(|_err| error_response(StatusCode::BAD_REQUEST))?,
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::SEC_WEBSOCKET_PROTOCOL;
+    use http::StatusCode;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_tungstenite::tungstenite::Error as WsError;
+
+    use super::check_request;
+
+    /// Accept one websocket connection on `path` and returns server address.
+    async fn start_server(path: Option<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (tcp_stream, _address) = listener.accept().await.unwrap();
+            #[allow(clippy::result_large_err)]
+            let callback =
+                |request: &Request, response: Response| check_request(request, response, path);
+            let _ret = tokio_tungstenite::accept_hdr_async(tcp_stream, callback).await;
+        });
+        address
+    }
+
+    async fn handshake(
+        path: Option<&'static str>,
+        url_path: &str,
+        protocols: Option<&str>,
+    ) -> Result<Option<String>, WsError> {
+        let address = start_server(path).await;
+        let mut request = format!("ws://{address}{url_path}")
+            .into_client_request()
+            .unwrap();
+        if let Some(protocols) = protocols {
+            request
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, protocols.parse().unwrap());
+        }
+        let tcp_stream = TcpStream::connect(&address).await.unwrap();
+        let (_ws_stream, response) = tokio_tungstenite::client_async(request, tcp_stream).await?;
+        Ok(response
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .map(|value| value.to_str().unwrap().to_string()))
+    }
+
+    fn status(ret: Result<Option<String>, WsError>) -> StatusCode {
+        match ret {
+            Err(WsError::Http(response)) => response.status(),
+            _ => panic!("Expected http error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subprotocol() {
+        let ret = handshake(None, "/", Some("mqtt")).await.unwrap();
+        assert_eq!(ret.as_deref(), Some("mqtt"));
+        let ret = handshake(None, "/", Some("wamp, mqttv3.1")).await.unwrap();
+        assert_eq!(ret.as_deref(), Some("mqttv3.1"));
+        let ret = handshake(None, "/", None).await.unwrap();
+        assert_eq!(ret, None);
+
+        let ret = handshake(None, "/", Some("wamp")).await;
+        assert_eq!(status(ret), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_path() {
+        let ret = handshake(Some("/mqtt"), "/mqtt", Some("mqtt"))
+            .await
+            .unwrap();
+        assert_eq!(ret.as_deref(), Some("mqtt"));
+
+        let ret = handshake(Some("/mqtt"), "/ws", Some("mqtt")).await;
+        assert_eq!(status(ret), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Delayed will messages of v5 sessions.
+
+use codec::v5;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use super::Listener;
+use crate::commands::SessionToListenerCmd;
+use crate::types::SessionId;
+use crate::Error;
+
+/// Will message waiting for its Will Delay Interval to elapse.
+#[derive(Debug)]
+pub struct DelayedWill {
+    session_id: SessionId,
+    packet: v5::PublishPacket,
+    timer: JoinHandle<()>,
+}
+
+impl Listener {
+    /// Start a timer task to publish will message after `delay`.
+    pub(super) fn on_session_delayed_will_v5(
+        &mut self,
+        session_id: SessionId,
+        client_id: String,
+        packet: v5::PublishPacket,
+        delay: Duration,
+    ) {
+        log::info!(
+            "Listener::on_session_delayed_will_v5(), client id: {}, delay: {:?}",
+            client_id,
+            delay
+        );
+        let sender = self.session_sender.clone();
+        let timer_client_id = client_id.clone();
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let cmd = SessionToListenerCmd::WillDelayElapsed(session_id, timer_client_id);
+            if let Err(err) = sender.send(cmd).await {
+                log::error!(
+                    "listener: Failed to send will delay elapsed cmd, err: {:?}",
+                    err
+                );
+            }
+        });
+
+        let will = DelayedWill {
+            session_id,
+            packet,
+            timer,
+        };
+        if let Some(old_will) = self.delayed_wills.insert(client_id, will) {
+            old_will.timer.abort();
+        }
+    }
+
+    pub(super) async fn on_session_will_delay_elapsed(
+        &mut self,
+        session_id: SessionId,
+        client_id: String,
+    ) -> Result<(), Error> {
+        // Will message may be cancelled or replaced by a newer session while
+        // this cmd is in channel.
+        if self
+            .delayed_wills
+            .get(&client_id)
+            .map_or(true, |will| will.session_id != session_id)
+        {
+            return Ok(());
+        }
+        if let Some(will) = self.delayed_wills.remove(&client_id) {
+            log::info!(
+                "listener: Publish delayed will message, client id: {}",
+                client_id
+            );
+            self.on_session_publish_v5(will.session_id, will.packet)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Publish pending will message at once, if previous session of `client_id` is
+    /// discarded before Will Delay Interval has passed.
+    pub(super) async fn publish_delayed_will(&mut self, client_id: &str) -> Result<(), Error> {
+        if let Some(will) = self.delayed_wills.remove(client_id) {
+            log::info!(
+                "listener: Session discarded, publish delayed will message, client id: {}",
+                client_id
+            );
+            will.timer.abort();
+            self.on_session_publish_v5(will.session_id, will.packet)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Cancel pending will message if the client reconnects before Will Delay Interval
+    /// has passed [MQTT-3.1.3-9].
+    pub(super) fn cancel_delayed_will(&mut self, client_id: &str) {
+        if let Some(will) = self.delayed_wills.remove(client_id) {
+            log::info!(
+                "listener: Cancel delayed will message, client id: {}",
+                client_id
+            );
+            will.timer.abort();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Access log records connect and disconnect events of clients for auditing.
+//!
+//! Lines are written to `access_log_file` in log config, with `ACCESS_LOG_TARGET`.
+
+use codec::ProtocolLevel;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::json::json_string;
+use crate::config::LogFormat;
+use crate::types::ListenerId;
+
+/// Log target of access log lines.
+pub const ACCESS_LOG_TARGET: &str = "hebo::access";
+
+/// Access log is formatted as json if true.
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn set_format(format: LogFormat) {
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+fn format() -> LogFormat {
+    if JSON_FORMAT.load(Ordering::Relaxed) {
+        LogFormat::Json
+    } else {
+        LogFormat::Text
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessEvent {
+    Connect,
+    Disconnect,
+}
+
+impl AccessEvent {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// Value of a field in access log line.
+enum Value<'a> {
+    Str(&'a str),
+    Num(u64),
+    Bool(bool),
+}
+
+/// Connection of an accepted client, and number of requests it has sent.
+#[derive(Debug, Clone)]
+pub struct AccessRecord {
+    listener_id: ListenerId,
+    client_id: String,
+    username: String,
+    remote_address: Option<SocketAddr>,
+    protocol_level: ProtocolLevel,
+    clean_session: bool,
+    connected_at: SystemTime,
+
+    subscribe: u64,
+    unsubscribe: u64,
+    publish: u64,
+}
+
+impl AccessRecord {
+    #[must_use]
+    pub fn new(
+        listener_id: ListenerId,
+        client_id: &str,
+        username: &str,
+        remote_address: Option<SocketAddr>,
+        protocol_level: ProtocolLevel,
+        clean_session: bool,
+    ) -> Self {
+        Self {
+            listener_id,
+            client_id: client_id.to_string(),
+            username: username.to_string(),
+            remote_address,
+            protocol_level,
+            clean_session,
+            connected_at: SystemTime::now(),
+            subscribe: 0,
+            unsubscribe: 0,
+            publish: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    #[must_use]
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    #[must_use]
+    pub const fn remote_address(&self) -> Option<SocketAddr> {
+        self.remote_address
+    }
+
+    #[must_use]
+    pub const fn protocol_level(&self) -> ProtocolLevel {
+        self.protocol_level
+    }
+
+    /// Get seconds since unix epoch when client is connected.
+    #[must_use]
+    pub fn connected_at(&self) -> u64 {
+        self.connected_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+
+    pub fn on_subscribe(&mut self) {
+        self.subscribe += 1;
+    }
+
+    pub fn on_unsubscribe(&mut self) {
+        self.unsubscribe += 1;
+    }
+
+    pub fn on_publish(&mut self) {
+        self.publish += 1;
+    }
+
+    /// Write `event` of this client to access log, if it is enabled.
+    pub fn log(&self, event: AccessEvent) {
+        if log::log_enabled!(target: ACCESS_LOG_TARGET, log::Level::Info) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| {
+                    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+                });
+            log::info!(
+                target: ACCESS_LOG_TARGET,
+                "{}",
+                self.format(event, timestamp, format())
+            );
+        }
+    }
+
+    /// Format an access log line, `timestamp` is milliseconds since unix epoch.
+    ///
+    /// Request counts are only written in disconnect events.
+    #[must_use]
+    pub fn format(&self, event: AccessEvent, timestamp: u64, format: LogFormat) -> String {
+        let address = self
+            .remote_address
+            .map(|address| address.to_string())
+            .unwrap_or_default();
+        let mut fields = vec![
+            ("timestamp", Value::Num(timestamp)),
+            ("event", Value::Str(event.name())),
+            ("listener", Value::Num(u64::from(self.listener_id))),
+            ("client_id", Value::Str(&self.client_id)),
+            ("username", Value::Str(&self.username)),
+            ("address", Value::Str(&address)),
+            ("protocol", Value::Num(self.protocol_level as u64)),
+            ("clean_session", Value::Bool(self.clean_session)),
+        ];
+        if event == AccessEvent::Disconnect {
+            fields.push(("subscribe", Value::Num(self.subscribe)));
+            fields.push(("unsubscribe", Value::Num(self.unsubscribe)));
+            fields.push(("publish", Value::Num(self.publish)));
+        }
+
+        let mut line = String::new();
+        for (index, (key, value)) in fields.into_iter().enumerate() {
+            // Writing to string never fails.
+            let _ret = match (format, value) {
+                (LogFormat::Text, Value::Str(s)) => write!(line, " {key}={s:?}"),
+                (LogFormat::Text, Value::Num(n)) => write!(line, " {key}={n}"),
+                (LogFormat::Text, Value::Bool(b)) => write!(line, " {key}={b}"),
+                (LogFormat::Json, value) => {
+                    line.push(if index == 0 { '{' } else { ',' });
+                    match value {
+                        Value::Str(s) => write!(line, "\"{key}\":{}", json_string(s)),
+                        Value::Num(n) => write!(line, "\"{key}\":{n}"),
+                        Value::Bool(b) => write!(line, "\"{key}\":{b}"),
+                    }
+                }
+            };
+        }
+        match format {
+            LogFormat::Text => line.split_off(1),
+            LogFormat::Json => line + "}",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::ProtocolLevel;
+
+    use super::{AccessEvent, AccessRecord};
+    use crate::config::LogFormat;
+
+    #[test]
+    fn test_format() {
+        let mut record = AccessRecord::new(
+            1,
+            "client-\"1\"",
+            "alice",
+            Some("127.0.0.1:5000".parse().unwrap()),
+            ProtocolLevel::V5,
+            true,
+        );
+        assert_eq!(
+            record.format(AccessEvent::Connect, 1000, LogFormat::Text),
+            "timestamp=1000 event=\"connect\" listener=1 client_id=\"client-\\\"1\\\"\" \
+             username=\"alice\" address=\"127.0.0.1:5000\" protocol=5 clean_session=true"
+        );
+
+        record.on_subscribe();
+        record.on_publish();
+        record.on_publish();
+        assert_eq!(
+            record.format(AccessEvent::Disconnect, 2000, LogFormat::Json),
+            "{\"timestamp\":2000,\"event\":\"disconnect\",\"listener\":1,\
+             \"client_id\":\"client-\\\"1\\\"\",\"username\":\"alice\",\
+             \"address\":\"127.0.0.1:5000\",\"protocol\":5,\"clean_session\":true,\
+             \"subscribe\":1,\"unsubscribe\":0,\"publish\":2}"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Encode log records as json objects, one object per line.
+
+use log::Record;
+use log4rs::encode::{Encode, Write};
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes `timestamp`, `level`, `target` and `message` fields of each record.
+///
+/// `timestamp` is milliseconds since unix epoch.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonEncoder {}
+
+impl JsonEncoder {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {}
+    }
+
+    fn format(record: &Record, timestamp: u64) -> String {
+        format!(
+            "{{\"timestamp\":{timestamp},\"level\":{},\"target\":{},\"message\":{}}}\n",
+            json_string(record.level().as_str()),
+            json_string(record.target()),
+            json_string(&record.args().to_string()),
+        )
+    }
+}
+
+impl Encode for JsonEncoder {
+    fn encode(&self, w: &mut dyn Write, record: &Record) -> anyhow::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+            });
+        w.write_all(Self::format(record, timestamp).as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Quote and escape `s` as json string.
+pub(super) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ret = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+    use log4rs::encode::writer::simple::SimpleWriter;
+    use log4rs::encode::Encode;
+
+    use super::JsonEncoder;
+
+    #[test]
+    fn test_encode() {
+        let mut writer = SimpleWriter(Vec::new());
+        for (level, message) in [
+            (Level::Info, "Listening on 127.0.0.1:1883".to_string()),
+            (Level::Error, "Invalid \"client\"\n\ttab \u{1}".to_string()),
+        ] {
+            JsonEncoder::new()
+                .encode(
+                    &mut writer,
+                    &log::Record::builder()
+                        .level(level)
+                        .target("hebo::listener")
+                        .args(format_args!("{message}"))
+                        .build(),
+                )
+                .unwrap();
+        }
+
+        let output = String::from_utf8(writer.0).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0]["timestamp"].as_u64().unwrap() > 0);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["target"], "hebo::listener");
+        assert_eq!(lines[0]["message"], "Listening on 127.0.0.1:1883");
+        assert_eq!(lines[1]["level"], "ERROR");
+        assert_eq!(lines[1]["message"], "Invalid \"client\"\n\ttab \u{1}");
+    }
+}
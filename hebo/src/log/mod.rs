@@ -0,0 +1,274 @@
+// Copyright (c) 2021 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use log::LevelFilter;
+use log4rs::{
+    append::console::ConsoleAppender,
+    append::console::Target,
+    append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller,
+    append::rolling_file::policy::compound::trigger::size::SizeTrigger,
+    append::rolling_file::policy::compound::CompoundPolicy,
+    append::rolling_file::RollingFileAppender,
+    config::{Appender, Config, Logger, Root},
+    encode::{pattern::PatternEncoder, Encode},
+};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, LogFormat, LogLevel};
+use crate::error::{Error, ErrorKind};
+
+mod access;
+mod json;
+
+pub use access::{AccessEvent, AccessRecord, ACCESS_LOG_TARGET};
+pub use json::JsonEncoder;
+
+const LOG_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const ROLLER_PATTERN: &str = ".{}.gz";
+const ROLLER_COUNT: u32 = 16;
+const STDOUT_NAME: &str = "stdout";
+const ROLLER_NAME: &str = "roller";
+const ACCESS_NAME: &str = "access";
+
+const fn get_log_level(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::Off,
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+/// Initialize log module.
+///
+/// # Errors
+///
+/// Returns error if:
+/// - Failed to init rolling pattern or rolling appender
+/// - Failed to init log4rs
+#[allow(clippy::module_name_repetitions)]
+pub fn init_log(log_conf: &config::Log) -> Result<(), Error> {
+    let mut config_builder = Config::builder();
+    let mut root_builder = Root::builder();
+    let json_format = log_conf.format() == LogFormat::Json;
+    if log_conf.console_log() {
+        let encoder: Box<dyn Encode> = if json_format {
+            Box::new(JsonEncoder::new())
+        } else {
+            Box::new(PatternEncoder::new(
+                "{d(%Y-%m-%d %H:%M:%S)} {l} {M}:{L} - {m}{n}",
+            ))
+        };
+        let stdout = ConsoleAppender::builder()
+            .encoder(encoder)
+            .target(Target::Stderr)
+            .build();
+        config_builder =
+            config_builder.appender(Appender::builder().build(STDOUT_NAME, Box::new(stdout)));
+        root_builder = root_builder.appender(STDOUT_NAME);
+    }
+
+    if let Some(log_file) = log_conf.log_file() {
+        let encoder: Option<Box<dyn Encode>> = if json_format {
+            Some(Box::new(JsonEncoder::new()))
+        } else {
+            None
+        };
+        let requests = new_rolling_appender(log_file, encoder)?;
+        config_builder =
+            config_builder.appender(Appender::builder().build(ROLLER_NAME, Box::new(requests)));
+        root_builder = root_builder.appender(ROLLER_NAME);
+    }
+
+    // Access log is written to its own file only, and never to diagnostic log.
+    let access_logger = Logger::builder().additive(false);
+    let access_logger = if let Some(access_log_file) = log_conf.access_log_file() {
+        let encoder = PatternEncoder::new("{m}{n}");
+        let access = new_rolling_appender(access_log_file, Some(Box::new(encoder)))?;
+        config_builder =
+            config_builder.appender(Appender::builder().build(ACCESS_NAME, Box::new(access)));
+        access_logger
+            .appender(ACCESS_NAME)
+            .build(ACCESS_LOG_TARGET, LevelFilter::Info)
+    } else {
+        access_logger.build(ACCESS_LOG_TARGET, LevelFilter::Off)
+    };
+    config_builder = config_builder.logger(access_logger);
+    access::set_format(log_conf.access_log_format());
+
+    let log_level = get_log_level(log_conf.log_level());
+    let config = config_builder
+        .build(root_builder.build(log_level))
+        .map_err(|err| {
+            Error::from_string(
+                ErrorKind::LoggerError,
+                format!("Failed to build log4rs config, {err:?}"),
+            )
+        })?;
+
+    log4rs::init_config(config).map_err(|err| {
+        Error::from_string(
+            ErrorKind::LoggerError,
+            format!("Failed to init log4rs, {err:?}"),
+        )
+    })?;
+    Ok(())
+}
+
+/// Create a log file appender which is rotated when it is too large.
+fn new_rolling_appender(
+    log_file: &str,
+    encoder: Option<Box<dyn Encode>>,
+) -> Result<RollingFileAppender, Error> {
+    let roller_pattern = log_file.to_string() + ROLLER_PATTERN;
+    let roller = FixedWindowRoller::builder()
+        .build(&roller_pattern, ROLLER_COUNT)
+        .map_err(|err| {
+            Error::from_string(
+                ErrorKind::LoggerError,
+                format!("Failed to init roller pattern, {err:?}"),
+            )
+        })?;
+    let rolling_policy = Box::new(CompoundPolicy::new(
+        Box::new(SizeTrigger::new(LOG_FILE_SIZE)),
+        Box::new(roller),
+    ));
+    let mut builder = RollingFileAppender::builder();
+    if let Some(encoder) = encoder {
+        builder = builder.encoder(encoder);
+    }
+    builder.build(log_file, rolling_policy).map_err(|err| {
+        Error::from_string(
+            ErrorKind::LoggerError,
+            format!("Failed to init roller appender, {err:?}"),
+        )
+    })
+}
+
+/// Coalesce repetitive log messages in hot error paths.
+///
+/// At most one message is logged in each interval, the others are counted
+/// and reported along with the next logged one.
+///
+/// ```
+/// use hebo::log::LogLimiter;
+///
+/// static DECODE_ERRORS: LogLimiter = LogLimiter::new();
+/// if let Some(suppressed) = DECODE_ERRORS.check() {
+///     log::error!("session: Invalid packet{suppressed}");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct LogLimiter {
+    interval: u64,
+
+    /// Milliseconds since unix epoch when last message was logged.
+    logged_at: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+/// Number of messages suppressed since last logged one.
+///
+/// It is formatted as empty string if no message is suppressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suppressed(u64);
+
+impl Suppressed {
+    #[must_use]
+    pub const fn count(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Suppressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 > 0 {
+            write!(f, " ({} similar messages suppressed)", self.0)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for LogLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogLimiter {
+    /// Log at most one message per second.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::with_interval(Duration::from_secs(1))
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval: interval.as_millis() as u64,
+            logged_at: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether current message shall be logged.
+    ///
+    /// Returns None if it is suppressed.
+    pub fn check(&self) -> Option<Suppressed> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+            });
+        let logged_at = self.logged_at.load(Ordering::Relaxed);
+        if now.saturating_sub(logged_at) >= self.interval
+            && self
+                .logged_at
+                .compare_exchange(logged_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            Some(Suppressed(self.suppressed.swap(0, Ordering::Relaxed)))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::LogLimiter;
+
+    #[test]
+    fn test_log_limiter() {
+        let limiter = LogLimiter::with_interval(Duration::from_millis(100));
+        let flood = |lines: &mut Vec<String>| {
+            for i in 0..1000 {
+                if let Some(suppressed) = limiter.check() {
+                    lines.push(format!("Invalid packet {i}{suppressed}"));
+                }
+            }
+        };
+
+        let mut lines = Vec::new();
+        flood(&mut lines);
+        assert_eq!(lines, ["Invalid packet 0"]);
+
+        std::thread::sleep(Duration::from_millis(150));
+        lines.clear();
+        flood(&mut lines);
+        assert_eq!(
+            lines,
+            ["Invalid packet 0 (999 similar messages suppressed)"]
+        );
+    }
+}
@@ -9,16 +9,27 @@
 
 use codec::{v3, QoS};
 use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::time::{Duration, SystemTime};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::time::interval;
+use tokio::time::{interval, Interval};
 
 use crate::cache_types::{ListenerMetrics, ListenersMapMetrics, SystemMetrics};
 use crate::commands::{DispatcherToMetricsCmd, MetricsToDispatcherCmd, ServerContextToMetricsCmd};
 use crate::error::Error;
 use crate::types::Uptime;
 
-pub const UPTIME: &str = "$SYS/uptime";
+pub const UPTIME: &str = "$SYS/broker/uptime";
+pub const CLIENTS_CONNECTED: &str = "$SYS/broker/clients/connected";
+pub const SUBSCRIPTIONS_COUNT: &str = "$SYS/broker/subscriptions/count";
+pub const MESSAGES_RECEIVED: &str = "$SYS/broker/messages/received";
+pub const MESSAGES_SENT: &str = "$SYS/broker/messages/sent";
+pub const BYTES_RECEIVED: &str = "$SYS/broker/bytes/received";
+pub const BYTES_SENT: &str = "$SYS/broker/bytes/sent";
+pub const SUBSCRIPTIONS_TOP_TOPICS: &str = "$SYS/broker/subscriptions/top-topics";
+
+/// Number of topic filters reported in `SUBSCRIPTIONS_TOP_TOPICS`.
+const TOP_TOPICS_LEN: usize = 10;
 
 /// Key-value store.
 #[derive(Debug)]
@@ -29,6 +40,8 @@ pub struct Metrics {
 
     system: SystemMetrics,
     listeners: ListenersMapMetrics,
+    /// Number of subscribers of each topic filter.
+    topic_subscribers: HashMap<String, usize>,
 
     dispatcher_sender: Sender<MetricsToDispatcherCmd>,
     dispatcher_receiver: Receiver<DispatcherToMetricsCmd>,
@@ -52,6 +65,7 @@ impl Metrics {
             uptime: 0,
             system: SystemMetrics::default(),
             listeners: HashMap::new(),
+            topic_subscribers: HashMap::new(),
 
             dispatcher_sender,
             dispatcher_receiver,
@@ -60,10 +74,12 @@ impl Metrics {
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
         // Update uptime property each second.
         let mut sys_tree_uptime_timer = interval(Duration::from_secs(1));
-        let mut sys_tree_timer = interval(self.sys_tree_interval);
+        // $SYS messages are disabled if interval is 0.
+        let mut sys_tree_timer =
+            (!self.sys_tree_interval.is_zero()).then(|| interval(self.sys_tree_interval));
 
         loop {
             tokio::select! {
@@ -72,6 +88,10 @@ impl Metrics {
                 }
 
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToMetricsCmd::Stop) {
+                        log::info!("metrics: Stop app");
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
 
@@ -79,7 +99,7 @@ impl Metrics {
                     self.sys_tree_update_uptime();
                 }
 
-                _ = sys_tree_timer.tick() => {
+                () = sys_tree_tick(&mut sys_tree_timer) => {
                     self.sys_tree_handle_timeout().await;
                 }
             }
@@ -92,7 +112,7 @@ impl Metrics {
         match cmd {
             DispatcherToMetricsCmd::ListenerAdded(listener_id, address) => {
                 log::info!("Add listener id: {}, addr: {:?}", listener_id, address);
-                assert!(self.listeners.get(&listener_id).is_none());
+                assert!(!self.listeners.contains_key(&listener_id));
                 let listener_cache = ListenerMetrics::new(listener_id, address);
                 self.listeners.insert(listener_id, listener_cache);
                 self.system.listener_count += 1;
@@ -142,6 +162,15 @@ impl Metrics {
                     log::error!("Failed to found listener with id: {}", listener_id);
                 }
             }
+            DispatcherToMetricsCmd::TopicSubscribers(counts) => {
+                for (topic, count) in counts {
+                    if count == 0 {
+                        self.topic_subscribers.remove(&topic);
+                    } else {
+                        self.topic_subscribers.insert(topic, count);
+                    }
+                }
+            }
             DispatcherToMetricsCmd::RetainedMessageAdded(listener_id, count, bytes) => {
                 log::info!("{} retained messages added to #{}", count, listener_id);
                 if let Some(listener) = self.listeners.get_mut(&listener_id) {
@@ -169,7 +198,7 @@ impl Metrics {
                 }
             }
             DispatcherToMetricsCmd::PublishPacketSent(listener_id, count, bytes) => {
-                log::info!("{} publishPacketSent added to #{}", count, listener_id);
+                log::debug!("{} publishPacketSent added to #{}", count, listener_id);
                 if let Some(listener) = self.listeners.get_mut(&listener_id) {
                     let count = count as i64;
                     let bytes = bytes as i64;
@@ -182,7 +211,7 @@ impl Metrics {
                 }
             }
             DispatcherToMetricsCmd::PublishPacketReceived(listener_id, count, bytes) => {
-                log::info!("{} publishPacketReceived added to #{}", count, listener_id);
+                log::debug!("{} publishPacketReceived added to #{}", count, listener_id);
                 if let Some(listener) = self.listeners.get_mut(&listener_id) {
                     let count = count as i64;
                     let bytes = bytes as i64;
@@ -230,14 +259,42 @@ impl Metrics {
         }
     }
 
-    async fn sys_tree_handle_timeout(&mut self) {
-        // TODO(Shaohua): Send other messages.
-        if let Err(err) = self.sys_tree_send_uptime().await {
-            log::error!(
-                "Failed to send publish packet from metrics to dispatcher: {:?}",
-                err
-            );
+    async fn sys_tree_handle_timeout(&self) {
+        let messages = [
+            (UPTIME, self.uptime.to_string()),
+            (CLIENTS_CONNECTED, self.system.sessions.to_string()),
+            (SUBSCRIPTIONS_COUNT, self.system.subscriptions.to_string()),
+            (
+                MESSAGES_RECEIVED,
+                self.system.publish_messages_received.to_string(),
+            ),
+            (MESSAGES_SENT, self.system.publish_messages_sent.to_string()),
+            (
+                BYTES_RECEIVED,
+                self.system.publish_bytes_received.to_string(),
+            ),
+            (BYTES_SENT, self.system.publish_bytes_sent.to_string()),
+            (SUBSCRIPTIONS_TOP_TOPICS, self.top_topics()),
+        ];
+        for (topic, value) in messages {
+            if let Err(err) = self.sys_tree_send(topic, &value).await {
+                log::error!(
+                    "Failed to send publish packet from metrics to dispatcher: {:?}",
+                    err
+                );
+            }
+        }
+    }
+
+    /// Topic filters with most subscribers, one `<count> <topic>` pair each line.
+    fn top_topics(&self) -> String {
+        let mut topics: Vec<(&String, &usize)> = self.topic_subscribers.iter().collect();
+        topics.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let mut value = String::new();
+        for (topic, count) in topics.into_iter().take(TOP_TOPICS_LEN) {
+            let _ret = writeln!(value, "{count} {topic}");
         }
+        value
     }
 
     fn sys_tree_update_uptime(&mut self) {
@@ -251,10 +308,8 @@ impl Metrics {
         }
     }
 
-    async fn sys_tree_send_uptime(&mut self) -> Result<(), Error> {
-        //log::info!("metrics::sys_tree_send_uptime()");
-        let msg = format!("{}", self.uptime).into_bytes();
-        let packet = v3::PublishPacket::new(UPTIME, QoS::AtMostOnce, &msg)?;
+    async fn sys_tree_send(&self, topic: &str, value: &str) -> Result<(), Error> {
+        let packet = v3::PublishPacket::new(topic, QoS::AtMostOnce, value.as_bytes())?;
         self.dispatcher_sender
             .send(MetricsToDispatcherCmd::Publish(packet))
             .await
@@ -263,13 +318,29 @@ impl Metrics {
     }
 
     /// Server context handler
-    async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToMetricsCmd) {
+    async fn handle_server_ctx_cmd(&self, cmd: ServerContextToMetricsCmd) {
         match cmd {
             ServerContextToMetricsCmd::MetricsGetUptime(resp_tx) => {
                 if let Err(err) = resp_tx.send(self.uptime) {
                     log::error!("Failed to send uptime to server ctx: {:?}", err);
                 }
             }
+            ServerContextToMetricsCmd::MetricsGetSystem(resp_tx) => {
+                if let Err(err) = resp_tx.send(self.system) {
+                    log::error!("Failed to send system metrics to server ctx: {:?}", err);
+                }
+            }
+            // Handled in run_loop().
+            ServerContextToMetricsCmd::Stop => (),
         }
     }
 }
+
+/// Wait for next tick of `timer`, never completes if it is None.
+async fn sys_tree_tick(timer: &mut Option<Interval>) {
+    if let Some(timer) = timer {
+        timer.tick().await;
+    } else {
+        std::future::pending::<()>().await;
+    }
+}
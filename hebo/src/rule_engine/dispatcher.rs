@@ -2,16 +2,27 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use super::RuleEngineApp;
-use crate::commands::DispatcherToRuleEngineCmd;
+use super::{rules, RuleEngineApp};
+use crate::commands::{DispatcherToRuleEngineCmd, RuleEngineToDispatcherCmd};
 use crate::error::Error;
 
 impl RuleEngineApp {
-    pub(super) async fn handle_dispatcher_cmd(
-        &mut self,
+    pub(super) fn handle_dispatcher_cmd(
+        &self,
         cmd: DispatcherToRuleEngineCmd,
     ) -> Result<(), Error> {
-        log::info!("cmd: {:?}", cmd);
-        Ok(())
+        match cmd {
+            DispatcherToRuleEngineCmd::Publish(listener_id, packet) => {
+                let output = rules::apply_rules(&self.rules, packet);
+                for packet in output.packets {
+                    let cmd = RuleEngineToDispatcherCmd::Publish(listener_id, packet);
+                    self.dispatcher_sender.send(cmd)?;
+                }
+                for (index, record) in output.records {
+                    self.send_to_output(index, record);
+                }
+                Ok(())
+            }
+        }
     }
 }
@@ -2,19 +2,36 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use tokio::sync::mpsc::{Receiver, Sender};
+use std::collections::HashMap;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedSender};
+use tokio::task::JoinHandle;
 
 use crate::commands::{
     DispatcherToRuleEngineCmd, RuleEngineToDispatcherCmd, ServerContextToRuleEngineCmd,
 };
+use crate::config::Rule;
+use crate::types::PublishRecord;
 
 mod dispatcher;
+mod outputs;
+mod rules;
 mod server;
+#[cfg(feature = "pgsql_conn")]
+mod sql_output;
+mod webhook_output;
 
+/// Transform and reroute messages published by clients with rules.
 #[allow(clippy::module_name_repetitions)]
-#[allow(dead_code)]
 pub struct RuleEngineApp {
-    dispatcher_sender: Sender<RuleEngineToDispatcherCmd>,
+    /// Rules evaluated in order.
+    rules: Vec<Rule>,
+
+    /// Index of rule -> queue of its output, like sql table.
+    outputs: HashMap<usize, Sender<PublishRecord>>,
+    output_handles: Vec<JoinHandle<()>>,
+
+    /// Unbounded, so that rule engine never waits for dispatcher.
+    dispatcher_sender: UnboundedSender<RuleEngineToDispatcherCmd>,
     dispatcher_receiver: Receiver<DispatcherToRuleEngineCmd>,
 
     server_ctx_receiver: Receiver<ServerContextToRuleEngineCmd>,
@@ -22,30 +39,40 @@ pub struct RuleEngineApp {
 
 impl RuleEngineApp {
     #[must_use]
-    pub const fn new(
+    pub fn new(
+        rules: Vec<Rule>,
         // dispatcher
-        dispatcher_sender: Sender<RuleEngineToDispatcherCmd>,
+        dispatcher_sender: UnboundedSender<RuleEngineToDispatcherCmd>,
         dispatcher_receiver: Receiver<DispatcherToRuleEngineCmd>,
         // server ctx
         server_ctx_receiver: Receiver<ServerContextToRuleEngineCmd>,
     ) -> Self {
         Self {
+            rules,
+            outputs: HashMap::new(),
+            output_handles: Vec::new(),
             dispatcher_sender,
             dispatcher_receiver,
             server_ctx_receiver,
         }
     }
 
-    pub async fn run_loop(&mut self) -> ! {
+    pub async fn run_loop(&mut self) {
+        self.start_outputs();
         loop {
             tokio::select! {
                 Some(cmd) = self.dispatcher_receiver.recv() => {
-                    if let Err(err) = self.handle_dispatcher_cmd(cmd).await {
+                    if let Err(err) = self.handle_dispatcher_cmd(cmd) {
                         log::error!("Failed to handle dispatcher cmd: {:?}", err);
                     }
                 }
 
                 Some(cmd) = self.server_ctx_receiver.recv() => {
+                    if matches!(cmd, ServerContextToRuleEngineCmd::Stop) {
+                        log::info!("rule_engine: Stop app");
+                        self.stop_outputs().await;
+                        break;
+                    }
                     self.handle_server_ctx_cmd(cmd).await;
                 }
             }
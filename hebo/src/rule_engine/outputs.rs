@@ -0,0 +1,98 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Outputs of rules, which messages matching rules are written to.
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+
+use super::webhook_output::WebhookWriter;
+use super::RuleEngineApp;
+use crate::config::{Rule, RuleAction};
+use crate::log::LogLimiter;
+use crate::types::PublishRecord;
+
+/// A stalled output drops every new message, log them less frequently.
+static QUEUE_FULL_LOGS: LogLimiter = LogLimiter::new();
+
+impl RuleEngineApp {
+    /// Spawn a task for output of each rule.
+    pub(super) fn start_outputs(&mut self) {
+        for (index, rule) in self.rules.iter().enumerate() {
+            if let Some((sender, handle)) = start_output(rule) {
+                self.outputs.insert(index, sender);
+                self.output_handles.push(handle);
+            }
+        }
+    }
+
+    /// Send message to output of rule at `index`.
+    ///
+    /// Message is dropped if queue of output is full, so that a stalled output
+    /// never holds back dispatcher.
+    pub(super) fn send_to_output(&self, index: usize, record: PublishRecord) {
+        let Some(sender) = self.outputs.get(&index) else {
+            return;
+        };
+        match sender.try_send(record) {
+            Ok(()) => (),
+            Err(TrySendError::Full(record)) => {
+                if let Some(suppressed) = QUEUE_FULL_LOGS.check() {
+                    log::warn!(
+                        "rule_engine: Queue of output of rule {} is full, drop message of {:?}{}",
+                        self.rules[index].topic_filter(),
+                        record.topic,
+                        suppressed
+                    );
+                }
+            }
+            Err(TrySendError::Closed(_record)) => {
+                log::error!(
+                    "rule_engine: Output of rule {} is closed",
+                    self.rules[index].topic_filter()
+                );
+            }
+        }
+    }
+
+    /// Close queues of outputs, and wait for pending messages to be written.
+    pub(super) async fn stop_outputs(&mut self) {
+        self.outputs.clear();
+        for handle in self.output_handles.drain(..) {
+            if let Err(err) = handle.await {
+                log::error!("rule_engine: Failed to stop output, err: {:?}", err);
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "pgsql_conn"), allow(clippy::missing_const_for_fn))]
+fn start_output(rule: &Rule) -> Option<(Sender<PublishRecord>, JoinHandle<()>)> {
+    match rule.action() {
+        #[cfg(feature = "pgsql_conn")]
+        RuleAction::Sql => {
+            let config = rule.sql()?;
+            let (sender, receiver) = tokio::sync::mpsc::channel(config.queue_size());
+            let writer = super::sql_output::SqlWriter::new(config, receiver);
+            Some((sender, tokio::spawn(writer.run_loop())))
+        }
+        RuleAction::Webhook => {
+            let config = rule.webhook()?;
+            let (sender, receiver) = tokio::sync::mpsc::channel(config.queue_size());
+            match WebhookWriter::new(config, receiver) {
+                Ok(writer) => Some((sender, tokio::spawn(writer.run_loop()))),
+                Err(err) => {
+                    log::error!(
+                        "rule_engine: Failed to create webhook of rule {}, err: {:?}",
+                        rule.topic_filter(),
+                        err
+                    );
+                    None
+                }
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Match messages against rules, and apply rule actions.
+
+use crate::config::{Rule, RuleAction};
+use crate::session::OutgoingPacket;
+use crate::types::PublishRecord;
+
+/// Messages produced by evaluating rules against a message.
+#[derive(Debug, Default)]
+pub struct RuleOutput {
+    /// Messages dispatched to subscribers, the original one first if it is not dropped.
+    pub packets: Vec<OutgoingPacket>,
+
+    /// Messages written to outputs of rules, like sql tables and webhooks,
+    /// with index of the rule.
+    pub records: Vec<(usize, PublishRecord)>,
+}
+
+/// Match `topic` against `topic_filter`, returns topic levels captured by wildcards.
+///
+/// `+` captures one level, and `#` captures all remaining levels, which may be empty.
+/// Topics starting with `$` are not matched by filters starting with wildcards.
+fn capture<'a>(topic_filter: &str, topic: &'a str) -> Option<Vec<&'a str>> {
+    if topic.starts_with('$') && topic_filter.starts_with(['+', '#']) {
+        return None;
+    }
+
+    let mut captures = Vec::new();
+    let mut topic_levels = topic.split('/');
+    let mut offset = 0;
+    for filter_level in topic_filter.split('/') {
+        if filter_level == "#" {
+            captures.push(topic.get(offset..).unwrap_or_default());
+            return Some(captures);
+        }
+        let topic_level = topic_levels.next()?;
+        if filter_level == "+" {
+            captures.push(topic_level);
+        } else if filter_level != topic_level {
+            return None;
+        }
+        offset += topic_level.len() + 1;
+    }
+
+    if topic_levels.next().is_some() {
+        None
+    } else {
+        Some(captures)
+    }
+}
+
+/// Replace placeholders in `template`.
+///
+/// `{n}` is replaced with the nth captured level, `{topic}` and `{payload}` are replaced
+/// with topic and payload of message. Unknown placeholders are kept as is.
+fn render(template: &str, captures: &[&str], topic: &str, payload: &[u8]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &rest[start + 1..start + len];
+        if name.contains('{') {
+            // Literal brace, like in json template.
+            result.push('{');
+            rest = &rest[start + 1..];
+            continue;
+        }
+        match name {
+            "topic" => result.push_str(topic),
+            "payload" => result.push_str(&String::from_utf8_lossy(payload)),
+            _ => match name.parse::<usize>() {
+                Ok(index) if index > 0 && index <= captures.len() => {
+                    result.push_str(captures[index - 1]);
+                }
+                _ => result.push_str(&rest[start..=start + len]),
+            },
+        }
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn topic(packet: &OutgoingPacket) -> &str {
+    match packet {
+        OutgoingPacket::V3(packet) => packet.topic(),
+        OutgoingPacket::V5(packet) => packet.topic(),
+    }
+}
+
+fn payload(packet: &OutgoingPacket) -> &[u8] {
+    match packet {
+        OutgoingPacket::V3(packet) => packet.message(),
+        OutgoingPacket::V5(packet) => packet.message(),
+    }
+}
+
+fn set_payload(packet: &mut OutgoingPacket, payload: &[u8]) {
+    match packet {
+        OutgoingPacket::V3(packet) => {
+            packet.set_message(payload);
+        }
+        OutgoingPacket::V5(packet) => {
+            packet.set_message(payload);
+        }
+    }
+}
+
+/// Evaluate `rules` in order against `packet`.
+///
+/// Payload changed by `rewrite` rule is seen by the rules after it.
+#[must_use]
+pub fn apply_rules(rules: &[Rule], mut packet: OutgoingPacket) -> RuleOutput {
+    let mut output = RuleOutput::default();
+    for (index, rule) in rules.iter().enumerate() {
+        let topic_name = topic(&packet);
+        let Some(captures) = capture(rule.topic_filter(), topic_name) else {
+            continue;
+        };
+        let new_payload = rule
+            .template()
+            .map(|template| render(template, &captures, topic_name, payload(&packet)));
+
+        match rule.action() {
+            RuleAction::Drop => {
+                log::debug!(
+                    "rule_engine: Drop message of {topic_name}, rule: {}",
+                    rule.topic_filter()
+                );
+                return output;
+            }
+            RuleAction::Rewrite => {
+                if let Some(new_payload) = new_payload {
+                    set_payload(&mut packet, new_payload.as_bytes());
+                }
+            }
+            RuleAction::Republish => {
+                let Some(target) = rule.target() else {
+                    continue;
+                };
+                let target = render(target, &captures, topic_name, payload(&packet));
+                let mut new_packet = packet.clone();
+                let ret = match &mut new_packet {
+                    OutgoingPacket::V3(packet) => packet.set_topic(&target).map(drop),
+                    OutgoingPacket::V5(packet) => packet.set_topic(&target).map(drop),
+                };
+                if let Err(err) = ret {
+                    log::warn!(
+                        "rule_engine: Invalid target topic {target} of rule {}, err: {err:?}",
+                        rule.topic_filter()
+                    );
+                    continue;
+                }
+                if let Some(new_payload) = new_payload {
+                    set_payload(&mut new_packet, new_payload.as_bytes());
+                }
+                output.packets.push(new_packet);
+            }
+            RuleAction::Sql | RuleAction::Webhook => {
+                let record = PublishRecord::new(
+                    topic_name,
+                    new_payload
+                        .as_ref()
+                        .map_or_else(|| payload(&packet), String::as_bytes),
+                    packet.qos(),
+                );
+                output.records.push((index, record));
+            }
+        }
+    }
+
+    output.packets.insert(0, packet);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, QoS};
+
+    use super::{apply_rules, capture, payload, render, topic, RuleOutput};
+    use crate::config::{Rule, RuleAction};
+    use crate::session::OutgoingPacket;
+
+    fn new_packet(topic: &str, payload: &[u8]) -> OutgoingPacket {
+        OutgoingPacket::V3(v3::PublishPacket::new(topic, QoS::AtLeastOnce, payload).unwrap())
+    }
+
+    fn new_rule(topic_filter: &str, action: RuleAction, target: &str, template: &str) -> Rule {
+        let mut rule = Rule::new(topic_filter, action);
+        if !target.is_empty() {
+            rule.set_target(target);
+        }
+        if !template.is_empty() {
+            rule.set_template(template);
+        }
+        rule
+    }
+
+    fn messages(output: &RuleOutput) -> Vec<(String, String)> {
+        output
+            .packets
+            .iter()
+            .map(|packet| {
+                (
+                    topic(packet).to_string(),
+                    String::from_utf8_lossy(payload(packet)).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_capture() {
+        assert_eq!(capture("sensors/+/temp", "sensors/1/temp"), Some(vec!["1"]));
+        assert_eq!(capture("sensors/+/temp", "sensors/1/humidity"), None);
+        assert_eq!(capture("sensors/+", "sensors/1/temp"), None);
+        assert_eq!(capture("sensors/#", "sensors/1/temp"), Some(vec!["1/temp"]));
+        assert_eq!(capture("sensors/#", "sensors"), Some(vec![""]));
+        assert_eq!(
+            capture("+/+/#", "home/kitchen/light/on"),
+            Some(vec!["home", "kitchen", "light/on"])
+        );
+        assert_eq!(capture("sensors/temp", "sensors/temp"), Some(vec![]));
+        assert_eq!(capture("#", "$SYS/uptime"), None);
+        assert_eq!(capture("$SYS/#", "$SYS/uptime"), Some(vec!["uptime"]));
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("agg/{1}/temp", &["1"], "", b""), "agg/1/temp");
+        assert_eq!(
+            render("{topic}: {payload}", &[], "sensors/1", b"25"),
+            "sensors/1: 25"
+        );
+        assert_eq!(render("{2}/{x}/{", &["1"], "", b""), "{2}/{x}/{");
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let rules = [
+            new_rule("sensors/+/temp", RuleAction::Republish, "agg/{1}/temp", ""),
+            new_rule("sensors/+/raw", RuleAction::Drop, "", ""),
+            new_rule("sensors/+/raw", RuleAction::Republish, "never/{1}", ""),
+            new_rule(
+                "sensors/#",
+                RuleAction::Rewrite,
+                "",
+                r#"{"sensor": "{1}", "value": {payload}}"#,
+            ),
+            new_rule("sensors/+/temp", RuleAction::Republish, "last/{1}", ""),
+        ];
+
+        assert_eq!(
+            messages(&apply_rules(&rules, new_packet("sensors/1/temp", b"25"))),
+            [
+                (
+                    "sensors/1/temp".to_string(),
+                    r#"{"sensor": "1/temp", "value": 25}"#.to_string()
+                ),
+                ("agg/1/temp".to_string(), "25".to_string()),
+                (
+                    "last/1".to_string(),
+                    r#"{"sensor": "1/temp", "value": 25}"#.to_string()
+                ),
+            ]
+        );
+
+        // Rules after drop are not evaluated.
+        assert!(apply_rules(&rules, new_packet("sensors/1/raw", b"0"))
+            .packets
+            .is_empty());
+
+        assert_eq!(
+            messages(&apply_rules(&rules, new_packet("other/1/temp", b"0"))),
+            [("other/1/temp".to_string(), "0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_invalid_target() {
+        let rules = [new_rule(
+            "sensors/+",
+            RuleAction::Republish,
+            "agg/{payload}",
+            "",
+        )];
+        assert_eq!(
+            messages(&apply_rules(&rules, new_packet("sensors/1", b"#"))),
+            [("sensors/1".to_string(), "#".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_output_records() {
+        let rules = [
+            new_rule("sensors/#", RuleAction::Sql, "", ""),
+            new_rule("sensors/+/temp", RuleAction::Rewrite, "", "{1}={payload}"),
+            new_rule("sensors/+/temp", RuleAction::Sql, "", ""),
+        ];
+        let output = apply_rules(&rules, new_packet("sensors/1/temp", b"25"));
+        assert_eq!(
+            messages(&output),
+            [("sensors/1/temp".to_string(), "1=25".to_string())]
+        );
+
+        let records: Vec<(usize, &str, &[u8])> = output
+            .records
+            .iter()
+            .map(|(index, record)| (*index, record.topic.as_str(), record.payload.as_slice()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (0, "sensors/1/temp", &b"25"[..]),
+                (2, "sensors/1/temp", &b"1=25"[..]),
+            ]
+        );
+        assert_eq!(output.records[0].1.qos, QoS::AtLeastOnce);
+    }
+}
@@ -8,7 +8,8 @@ use super::RuleEngineApp;
 use crate::commands::ServerContextToRuleEngineCmd;
 
 impl RuleEngineApp {
-    pub(super) async fn handle_server_ctx_cmd(&mut self, cmd: ServerContextToRuleEngineCmd) {
+    #[allow(clippy::unused_async)]
+    pub(super) async fn handle_server_ctx_cmd(&self, cmd: ServerContextToRuleEngineCmd) {
         log::info!("cmd: {:?}", cmd);
     }
 }
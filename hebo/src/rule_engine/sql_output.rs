@@ -0,0 +1,159 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Insert messages matched by rules into `PgSQL` table.
+
+use std::fmt::Write;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::Instant;
+use tokio_postgres::types::ToSql;
+
+use crate::config::{SqlColumns, SqlOutput};
+use crate::connectors::pgsql_conn::PgSQLPool;
+use crate::error::Error;
+use crate::types::PublishRecord;
+
+/// Insert messages received from rule engine in batches.
+pub struct SqlWriter {
+    pool: PgSQLPool,
+    table: String,
+    columns: SqlColumns,
+    batch_size: usize,
+    flush_interval: Duration,
+    receiver: Receiver<PublishRecord>,
+}
+
+impl SqlWriter {
+    /// Create a new sql writer, connections are opened on first insert.
+    #[must_use]
+    pub fn new(config: &SqlOutput, receiver: Receiver<PublishRecord>) -> Self {
+        Self {
+            pool: PgSQLPool::new(config.connection()),
+            table: config.table().to_string(),
+            columns: config.columns().clone(),
+            batch_size: config.batch_size(),
+            flush_interval: config.flush_interval(),
+            receiver,
+        }
+    }
+
+    /// Insert messages until channel is closed.
+    ///
+    /// Messages are inserted when batch is full, or flush interval elapsed since
+    /// the first message in batch is received.
+    pub async fn run_loop(mut self) {
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while let Some(record) = self.receiver.recv().await {
+            batch.push(record);
+            let deadline = Instant::now() + self.flush_interval;
+            while batch.len() < self.batch_size {
+                match tokio::time::timeout_at(deadline, self.receiver.recv()).await {
+                    Ok(Some(record)) => batch.push(record),
+                    // Channel is closed or flush interval elapsed.
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            if let Err(err) = self.insert(&batch).await {
+                log::error!(
+                    "rule_engine: Failed to insert {} messages into {}, err: {:?}",
+                    batch.len(),
+                    self.table,
+                    err
+                );
+            }
+            batch.clear();
+        }
+    }
+
+    async fn insert(&self, batch: &[PublishRecord]) -> Result<u64, Error> {
+        let values: Vec<(i16, i64)> = batch
+            .iter()
+            .map(|record| {
+                (
+                    i16::from(record.qos as u8),
+                    i64::try_from(record.timestamp).unwrap_or(i64::MAX),
+                )
+            })
+            .collect();
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(batch.len() * 4);
+        for (record, (qos, timestamp)) in batch.iter().zip(&values) {
+            if !self.columns.topic().is_empty() {
+                params.push(&record.topic);
+            }
+            if !self.columns.payload().is_empty() {
+                params.push(&record.payload);
+            }
+            if !self.columns.qos().is_empty() {
+                params.push(qos);
+            }
+            if !self.columns.timestamp().is_empty() {
+                params.push(timestamp);
+            }
+        }
+
+        let statement = insert_statement(&self.table, &self.columns, batch.len());
+        self.pool.execute(&statement, &params).await
+    }
+}
+
+/// Get statement to insert `rows` messages into `table`.
+fn insert_statement(table: &str, columns: &SqlColumns, rows: usize) -> String {
+    let names: Vec<&str> = [
+        columns.topic(),
+        columns.payload(),
+        columns.qos(),
+        columns.timestamp(),
+    ]
+    .into_iter()
+    .filter(|name| !name.is_empty())
+    .collect();
+
+    let mut statement = format!("INSERT INTO {table} ({}) VALUES ", names.join(", "));
+    let mut index = 1;
+    for row in 0..rows {
+        if row > 0 {
+            statement.push_str(", ");
+        }
+        statement.push('(');
+        for column in 0..names.len() {
+            if column > 0 {
+                statement.push_str(", ");
+            }
+            let _ = write!(statement, "${index}");
+            index += 1;
+        }
+        statement.push(')');
+    }
+    statement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::insert_statement;
+    use crate::config::SqlColumns;
+
+    #[test]
+    fn test_insert_statement() {
+        let columns = SqlColumns::default();
+        assert_eq!(
+            insert_statement("mqtt_messages", &columns, 2),
+            "INSERT INTO mqtt_messages (topic, payload, qos, ts) \
+             VALUES ($1, $2, $3, $4), ($5, $6, $7, $8)"
+        );
+
+        let columns: SqlColumns = toml::from_str(
+            r#"
+            qos = ""
+            timestamp = "created_at"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            insert_statement("iot.messages", &columns, 1),
+            "INSERT INTO iot.messages (topic, payload, created_at) VALUES ($1, $2, $3)"
+        );
+    }
+}
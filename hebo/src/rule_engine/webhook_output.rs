@@ -0,0 +1,164 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Post messages matched by rules to http endpoints.
+
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Client, Request, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinSet;
+
+use crate::config::WebhookOutput;
+use crate::error::{Error, ErrorKind};
+use crate::log::LogLimiter;
+use crate::types::PublishRecord;
+
+static RETRY_LOGS: LogLimiter = LogLimiter::new();
+static DROPPED_LOGS: LogLimiter = LogLimiter::new();
+
+/// Http endpoint, shared by requests.
+struct Webhook {
+    client: Client<HttpsConnector<HttpConnector>>,
+    url: Uri,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    timeout: Duration,
+    max_retries: u32,
+    retry_interval: Duration,
+}
+
+impl Webhook {
+    /// Post `body` once.
+    async fn post(&self, body: &[u8]) -> Result<(), Error> {
+        let mut builder = Request::post(self.url.clone()).header(CONTENT_TYPE, "application/json");
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let request = builder.body(Body::from(body.to_vec()))?;
+
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_elapsed| Error::new(ErrorKind::HttpError, "webhook: Request timeout"))??;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::from_string(
+                ErrorKind::HttpError,
+                format!("webhook: Got status code {}", response.status()),
+            ))
+        }
+    }
+
+    /// Post `record` as json, failed requests are retried with exponential backoff.
+    async fn post_record(&self, record: &PublishRecord) {
+        let body = match serde_json::to_vec(record) {
+            Ok(body) => body,
+            Err(err) => {
+                log::error!("rule_engine: Failed to serialize message, err: {:?}", err);
+                return;
+            }
+        };
+
+        let mut interval = self.retry_interval;
+        for retry in 0..=self.max_retries {
+            let Err(err) = self.post(&body).await else {
+                return;
+            };
+            if retry == self.max_retries {
+                if let Some(suppressed) = DROPPED_LOGS.check() {
+                    log::error!(
+                        "rule_engine: Drop message of {:?} after {} retries to {}, err: {:?}{}",
+                        record.topic,
+                        self.max_retries,
+                        self.url,
+                        err,
+                        suppressed
+                    );
+                }
+                return;
+            }
+            if let Some(suppressed) = RETRY_LOGS.check() {
+                log::warn!(
+                    "rule_engine: Failed to post message of {:?} to {}, retry in {:?}, err: {:?}{}",
+                    record.topic,
+                    self.url,
+                    interval,
+                    err,
+                    suppressed
+                );
+            }
+            tokio::time::sleep(interval).await;
+            interval = interval.saturating_mul(2);
+        }
+    }
+}
+
+/// Post messages received from rule engine, with a limited number of concurrent requests.
+pub struct WebhookWriter {
+    webhook: Arc<Webhook>,
+    max_concurrency: usize,
+    receiver: Receiver<PublishRecord>,
+}
+
+impl WebhookWriter {
+    /// Create a new webhook writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if url is invalid.
+    pub fn new(config: &WebhookOutput, receiver: Receiver<PublishRecord>) -> Result<Self, Error> {
+        let url = config.url().parse::<Uri>().map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid webhook url {:?}, err: {err:?}", config.url()),
+            )
+        })?;
+        let headers = config
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::try_from(name).ok()?,
+                    HeaderValue::try_from(value).ok()?,
+                ))
+            })
+            .collect();
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        Ok(Self {
+            webhook: Arc::new(Webhook {
+                client: Client::builder().build(connector),
+                url,
+                headers,
+                timeout: config.timeout(),
+                max_retries: config.max_retries(),
+                retry_interval: config.retry_interval(),
+            }),
+            max_concurrency: config.max_concurrency(),
+            receiver,
+        })
+    }
+
+    /// Post messages until channel is closed, then wait for pending requests.
+    pub async fn run_loop(mut self) {
+        let mut requests = JoinSet::new();
+        while let Some(record) = self.receiver.recv().await {
+            // Queue is not read while waiting, so that rule engine drops new messages
+            // when it is full.
+            if requests.len() >= self.max_concurrency {
+                requests.join_next().await;
+            }
+            let webhook = Arc::clone(&self.webhook);
+            requests.spawn(async move { webhook.post_record(&record).await });
+        }
+        while requests.join_next().await.is_some() {}
+    }
+}
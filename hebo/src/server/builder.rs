@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Build server context in code, without config file or command line arguments.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::ServerContext;
+use crate::auth::{AuthMethod, Authenticator};
+use crate::config::{Bridge, Config, Dashboard, General, Listener, Log, Security, Storage};
+use crate::error::Error;
+
+/// Builder of `ServerContext`.
+///
+/// Pid file is not written unless it is set in `general` section or config file,
+/// and a raw mqtt listener on `0.0.0.0:1883` is added if no listener is set.
+///
+/// ```no_run
+/// use hebo::config::{Listener, Security};
+/// use hebo::server::ServerContext;
+///
+/// let mut security = Security::default();
+/// security.set_allow_anonymous(true);
+/// let server = ServerContext::builder()
+///     .listener(Listener::tcp("127.0.0.1:1883"))
+///     .security(security)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ServerBuilder {
+    config: Config,
+    config_file: Option<PathBuf>,
+    authenticators: Vec<Box<dyn Authenticator>>,
+    auth_methods: Vec<Arc<dyn AuthMethod>>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut general = General::default();
+        general.set_pid_file("");
+        let mut config = Config::default();
+        config.set_general(general);
+        Self {
+            config,
+            config_file: None,
+            authenticators: Vec::new(),
+            auth_methods: Vec::new(),
+        }
+    }
+
+    /// Read config from file, which is read again when server reloads.
+    ///
+    /// Options set before this call are replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if failed to read config file or it is not a valid toml file.
+    pub fn config_file<P: AsRef<Path>>(mut self, config_file: P) -> Result<Self, Error> {
+        let config_file = config_file.as_ref();
+        self.config = Config::from_file(config_file)?;
+        self.config_file = Some(config_file.to_path_buf());
+        Ok(self)
+    }
+
+    /// Replace whole config.
+    #[must_use]
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    #[must_use]
+    pub fn general(mut self, general: General) -> Self {
+        self.config.set_general(general);
+        self
+    }
+
+    /// Append a listener.
+    #[must_use]
+    pub fn listener(mut self, listener: Listener) -> Self {
+        self.config.add_listener(listener);
+        self
+    }
+
+    #[must_use]
+    pub fn security(mut self, security: Security) -> Self {
+        self.config.set_security(security);
+        self
+    }
+
+    #[must_use]
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.config.set_storage(storage);
+        self
+    }
+
+    #[must_use]
+    pub fn log(mut self, log: Log) -> Self {
+        self.config.set_log(log);
+        self
+    }
+
+    #[must_use]
+    pub fn dashboard(mut self, dashboard: Dashboard) -> Self {
+        self.config.set_dashboard(dashboard);
+        self
+    }
+
+    /// Append a bridge.
+    #[must_use]
+    pub fn bridge(mut self, bridge: Bridge) -> Self {
+        self.config.add_bridge(bridge);
+        self
+    }
+
+    /// Register a custom authenticator, see `ServerContext::add_authenticator()`.
+    #[must_use]
+    pub fn authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.authenticators.push(Box::new(authenticator));
+        self
+    }
+
+    /// Register an enhanced auth method, see `ServerContext::add_auth_method()`.
+    #[must_use]
+    pub fn auth_method<M: AuthMethod + 'static>(mut self, auth_method: M) -> Self {
+        self.auth_methods.push(Arc::new(auth_method));
+        self
+    }
+
+    /// Validate config and create server context.
+    ///
+    /// Socket addresses are not bound until `ServerContext::run_loop()` is called.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if some options in config is invalid.
+    pub fn build(mut self) -> Result<ServerContext, Error> {
+        if self.config.listeners().is_empty() {
+            self.config.set_listeners(Listener::default_listeners());
+        }
+        self.config.validate(false)?;
+
+        let mut server = ServerContext::new(self.config);
+        if let Some(config_file) = self.config_file {
+            server.set_config_file(config_file);
+        }
+        server.authenticators = self.authenticators;
+        server.auth_methods = self.auth_methods;
+        Ok(server)
+    }
+}
@@ -7,27 +7,91 @@
 use tokio::sync::oneshot;
 
 use super::ServerContext;
-use crate::commands::{DashboardToServerContexCmd, ServerContextToMetricsCmd};
+use crate::cache_types::SystemMetrics;
+use crate::commands::{
+    DashboardToServerContexCmd, ServerContextToBackendsCmd, ServerContextToDispatcherCmd,
+    ServerContextToListenerCmd, ServerContextToMetricsCmd,
+};
 use crate::error::{Error, ErrorKind};
-use crate::types::Uptime;
+use crate::types::{
+    ClientInfo, ListenerId, PublishRecord, Redirect, SessionGid, SubscriptionInfo, Uptime,
+};
 
 impl ServerContext {
     pub(crate) async fn handle_dashboard_cmd(
-        &mut self,
+        &self,
         cmd: DashboardToServerContexCmd,
     ) -> Result<(), Error> {
         match cmd {
             DashboardToServerContexCmd::MetricsGetUptime(resp_tx) => {
                 self.handle_metrics_uptime(resp_tx).await
             }
+            DashboardToServerContexCmd::MetricsGetSystem(resp_tx) => {
+                self.handle_metrics_system(resp_tx).await
+            }
+            DashboardToServerContexCmd::DrainListener(listener_id, redirect, resp_tx) => {
+                self.handle_drain_listener(listener_id, redirect, resp_tx)
+                    .await
+            }
+            DashboardToServerContexCmd::DrainClient(client_id, redirect, resp_tx) => {
+                self.handle_drain_client(client_id, redirect, resp_tx).await
+            }
+            DashboardToServerContexCmd::DisconnectClient(client_id, resp_tx) => {
+                self.handle_disconnect_client(client_id, resp_tx).await
+            }
+            DashboardToServerContexCmd::ListClients(resp_tx) => {
+                let clients = self.list_clients(None).await?;
+                resp_tx.send(clients).map_err(|_| {
+                    Error::new(
+                        ErrorKind::ChannelError,
+                        "Failed to send clients to dashboard",
+                    )
+                })
+            }
+            DashboardToServerContexCmd::GetClient(client_id, resp_tx) => {
+                let clients = self.list_clients(Some(client_id)).await?;
+                resp_tx.send(clients.into_iter().next()).map_err(|_| {
+                    Error::new(
+                        ErrorKind::ChannelError,
+                        "Failed to send client to dashboard",
+                    )
+                })
+            }
+            DashboardToServerContexCmd::GetClientSubscriptions(client_id, resp_tx) => {
+                self.handle_client_subscriptions(client_id, resp_tx).await
+            }
+            DashboardToServerContexCmd::GetTopicHistory(topic_filter, limit, resp_tx) => {
+                self.handle_topic_history(topic_filter, limit, resp_tx)
+                    .await
+            }
         }
     }
 
-    async fn handle_metrics_uptime(
-        &mut self,
-        resp_tx: oneshot::Sender<Uptime>,
+    async fn handle_topic_history(
+        &self,
+        topic_filter: String,
+        limit: usize,
+        resp_tx: oneshot::Sender<Option<Vec<PublishRecord>>>,
     ) -> Result<(), Error> {
         let (resp2_tx, resp2_rx) = oneshot::channel();
+        self.backends_sender
+            .send(ServerContextToBackendsCmd::GetHistory(
+                topic_filter,
+                limit,
+                resp2_tx,
+            ))
+            .await?;
+        let history = resp2_rx.await?;
+        resp_tx.send(history).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send topic history to dashboard",
+            )
+        })
+    }
+
+    async fn handle_metrics_uptime(&self, resp_tx: oneshot::Sender<Uptime>) -> Result<(), Error> {
+        let (resp2_tx, resp2_rx) = oneshot::channel();
 
         self.metrics_sender
             .send(ServerContextToMetricsCmd::MetricsGetUptime(resp2_tx))
@@ -40,4 +104,162 @@ impl ServerContext {
             )
         })
     }
+
+    async fn handle_metrics_system(
+        &self,
+        resp_tx: oneshot::Sender<SystemMetrics>,
+    ) -> Result<(), Error> {
+        let (resp2_tx, resp2_rx) = oneshot::channel();
+
+        self.metrics_sender
+            .send(ServerContextToMetricsCmd::MetricsGetSystem(resp2_tx))
+            .await?;
+        let ret = resp2_rx.await?;
+        resp_tx.send(ret).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send system metrics to dashboard",
+            )
+        })
+    }
+
+    async fn handle_drain_listener(
+        &self,
+        listener_id: ListenerId,
+        redirect: Redirect,
+        resp_tx: oneshot::Sender<bool>,
+    ) -> Result<(), Error> {
+        let listener = self
+            .listeners
+            .iter()
+            .find(|listener| listener.id == listener_id);
+        let found = listener.is_some();
+        if let Some(listener) = listener {
+            listener
+                .sender
+                .send(ServerContextToListenerCmd::DrainSessions(redirect))
+                .await?;
+        }
+        resp_tx.send(found).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send drain listener response to dashboard",
+            )
+        })
+    }
+
+    /// Client id is unique in each listener, so ask all of them.
+    async fn handle_drain_client(
+        &self,
+        client_id: String,
+        redirect: Redirect,
+        resp_tx: oneshot::Sender<bool>,
+    ) -> Result<(), Error> {
+        let mut found = false;
+        for listener in &self.listeners {
+            let (resp2_tx, resp2_rx) = oneshot::channel();
+            listener
+                .sender
+                .send(ServerContextToListenerCmd::DrainClient(
+                    client_id.clone(),
+                    redirect.clone(),
+                    resp2_tx,
+                ))
+                .await?;
+            found |= resp2_rx.await?;
+        }
+        resp_tx.send(found).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send drain client response to dashboard",
+            )
+        })
+    }
+
+    /// Client id is unique in each listener, so ask all of them.
+    async fn handle_disconnect_client(
+        &self,
+        client_id: String,
+        resp_tx: oneshot::Sender<bool>,
+    ) -> Result<(), Error> {
+        let mut found = false;
+        for listener in &self.listeners {
+            let (resp2_tx, resp2_rx) = oneshot::channel();
+            listener
+                .sender
+                .send(ServerContextToListenerCmd::DisconnectClient(
+                    client_id.clone(),
+                    resp2_tx,
+                ))
+                .await?;
+            found |= resp2_rx.await?;
+        }
+        resp_tx.send(found).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send disconnect client response to dashboard",
+            )
+        })
+    }
+
+    async fn handle_client_subscriptions(
+        &self,
+        client_id: String,
+        resp_tx: oneshot::Sender<Option<Vec<SubscriptionInfo>>>,
+    ) -> Result<(), Error> {
+        let client = self.list_clients(Some(client_id)).await?.into_iter().next();
+        let subscriptions = if let Some(client) = client {
+            let (resp2_tx, resp2_rx) = oneshot::channel();
+            self.dispatcher_sender
+                .send(ServerContextToDispatcherCmd::GetSubscriptions(
+                    SessionGid::new(client.listener_id, client.session_id),
+                    resp2_tx,
+                ))
+                .await?;
+            Some(resp2_rx.await?)
+        } else {
+            None
+        };
+        resp_tx.send(subscriptions).map_err(|_| {
+            Error::new(
+                ErrorKind::ChannelError,
+                "Failed to send client subscriptions to dashboard",
+            )
+        })
+    }
+
+    /// Collect connected clients from listeners, and count their subscriptions in dispatcher.
+    ///
+    /// Clients are sorted by client id.
+    async fn list_clients(&self, client_id: Option<String>) -> Result<Vec<ClientInfo>, Error> {
+        let mut clients = Vec::new();
+        for listener in &self.listeners {
+            let (resp2_tx, resp2_rx) = oneshot::channel();
+            listener
+                .sender
+                .send(ServerContextToListenerCmd::ListClients(
+                    client_id.clone(),
+                    resp2_tx,
+                ))
+                .await?;
+            clients.extend(resp2_rx.await?);
+        }
+
+        let session_gids = clients
+            .iter()
+            .map(|client| SessionGid::new(client.listener_id, client.session_id))
+            .collect();
+        let (resp2_tx, resp2_rx) = oneshot::channel();
+        self.dispatcher_sender
+            .send(ServerContextToDispatcherCmd::CountSubscriptions(
+                session_gids,
+                resp2_tx,
+            ))
+            .await?;
+        for (client, subscriptions) in clients.iter_mut().zip(resp2_rx.await?) {
+            client.subscriptions = subscriptions;
+        }
+        clients.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        Ok(clients)
+    }
 }
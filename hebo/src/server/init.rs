@@ -5,18 +5,26 @@
 //! Init server context internal modules and apps.
 
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, Sender, UnboundedSender};
 
-use super::{ServerContext, CHANNEL_CAPACITY};
+use super::{ListenerHandle, ServerContext, CHANNEL_CAPACITY};
 use crate::auth::AuthApp;
+use crate::backends::batch::BatchWriter;
+use crate::backends::journal::JournalBackend;
+use crate::backends::message_store::MessageStore;
 use crate::backends::BackendsApp;
-use crate::bridge::BridgeApp;
-use crate::commands::DispatcherToMetricsCmd;
+use crate::bridge::{BridgeApp, TopicMapping};
+use crate::commands::{
+    AclToListenerCmd, AuthToListenerCmd, DispatcherToListenerCmd, DispatcherToMetricsCmd,
+    ServerContextToListenerCmd,
+};
+use crate::config;
 use crate::dispatcher::Dispatcher;
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 use crate::gateway::GatewayApp;
 use crate::listener::Listener;
 use crate::metrics::Metrics;
+use crate::types::ListenerId;
 
 #[cfg(feature = "acl")]
 use crate::acl::AclApp;
@@ -25,64 +33,65 @@ use crate::dashboard::DashboardApp;
 #[cfg(feature = "rule_engine")]
 use crate::rule_engine::RuleEngineApp;
 
+/// A listener bound to its address but not spawned yet.
+///
+/// Senders are handed to apps which talk to this listener.
+pub(super) struct BoundListener {
+    pub(super) id: ListenerId,
+    pub(super) address: String,
+    pub(super) listener: Listener,
+    pub(super) server_ctx_sender: Sender<ServerContextToListenerCmd>,
+    pub(super) dispatcher_sender: UnboundedSender<DispatcherToListenerCmd>,
+    pub(super) auth_sender: UnboundedSender<AuthToListenerCmd>,
+    pub(super) acl_sender: UnboundedSender<AclToListenerCmd>,
+}
+
 impl ServerContext {
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::collection_is_never_read)]
     pub(crate) async fn init_modules(&mut self, runtime: &Runtime) -> Result<(), Error> {
         log::info!("ServerContext::init_modules()");
 
-        let (listeners_to_dispatcher_sender, listeners_to_dispatcher_receiver) =
-            mpsc::channel(CHANNEL_CAPACITY);
-        let mut dispatcher_to_listener_senders = Vec::new();
-        let (listeners_to_auth_sender, listeners_to_auth_receiver) =
-            mpsc::channel(CHANNEL_CAPACITY);
-        let mut auth_to_listener_senders = Vec::new();
-        let (listeners_to_acl_sender, listeners_to_acl_receiver) = mpsc::channel(CHANNEL_CAPACITY);
-        let mut acl_to_listener_senders = Vec::new();
-        let mut handles = Vec::new();
-        let mut listeners_info = Vec::new();
+        // Journal is opened before listeners, which send messages to it.
+        self.init_journal(runtime)?;
 
         // Listeners module.
-        let mut listener_objs = Vec::new();
-        for (listener_id, l) in (0_u32..).zip(self.config.listeners().iter()) {
-            listeners_info.push((listener_id, l.address()));
-            let (dispatcher_to_listener_sender, dispatcher_to_listener_receiver) =
-                mpsc::channel(CHANNEL_CAPACITY);
-            dispatcher_to_listener_senders.push((listener_id, dispatcher_to_listener_sender));
-
-            let (auth_to_listener_sender, auth_to_listener_receiver) =
-                mpsc::channel(CHANNEL_CAPACITY);
-            auth_to_listener_senders.push((listener_id, auth_to_listener_sender));
-
-            let (acl_to_listener_sender, acl_to_listener_receiver) =
-                mpsc::channel(CHANNEL_CAPACITY);
-            acl_to_listener_senders.push((listener_id, acl_to_listener_sender));
-
-            let listener = Listener::bind(
-                listener_id,
-                l.clone(),
-                // dispatcher module
-                listeners_to_dispatcher_sender.clone(),
-                dispatcher_to_listener_receiver,
-                // Auth module
-                listeners_to_auth_sender.clone(),
-                auth_to_listener_receiver,
-                // acl module
-                listeners_to_acl_sender.clone(),
-                acl_to_listener_receiver,
-            )
-            .await
-            .unwrap_or_else(|_| panic!("Failed to listen at {:?}", &listeners_info.last()));
-            listener_objs.push(listener);
+        let general = self.config.general().clone();
+        let mut bound_listeners = Vec::new();
+        for listener_config in self.config.listeners().to_vec() {
+            bound_listeners.push(self.bind_listener(&listener_config, &general).await?);
         }
 
         self.set_uid()?;
 
-        for mut listener in listener_objs {
-            let handle = runtime.spawn(async move {
-                listener.run_loop().await;
-            });
-            handles.push(handle);
+        // Listeners accept all topic filters if ACL module is disabled, which is
+        // detected by closed channel. Close it before any session is accepted.
+        #[cfg(not(feature = "acl"))]
+        self.listeners_to_acl_receiver.take();
+
+        let mut dispatcher_to_listener_senders = Vec::new();
+        let mut auth_to_listener_senders = Vec::new();
+        let mut acl_to_listener_senders = Vec::new();
+        let mut listeners_info = Vec::new();
+        for bound_listener in bound_listeners {
+            let BoundListener {
+                id,
+                address,
+                listener,
+                server_ctx_sender,
+                dispatcher_sender,
+                auth_sender,
+                acl_sender,
+            } = bound_listener;
+            listeners_info.push((id, address.clone()));
+            dispatcher_to_listener_senders.push((id, dispatcher_sender));
+            auth_to_listener_senders.push((id, auth_sender));
+            acl_to_listener_senders.push((id, acl_sender));
+            self.spawn_listener(id, address, listener, server_ctx_sender);
+        }
+        let bound_addresses = self.listener_addresses();
+        for sender in self.bound_addresses_senders.drain(..) {
+            let _ret = sender.send(bound_addresses.clone());
         }
 
         // Metrics module.
@@ -100,13 +109,13 @@ impl ServerContext {
         let metrics_handle = runtime.spawn(async move {
             metrics.run_loop().await;
         });
-        handles.push(metrics_handle);
+        self.handles.push(metrics_handle);
 
         for listener_info in &listeners_info {
             if let Err(err) = dispatcher_to_metrics_sender
                 .send(DispatcherToMetricsCmd::ListenerAdded(
                     listener_info.0,
-                    listener_info.1.to_string(),
+                    listener_info.1.clone(),
                 ))
                 .await
             {
@@ -121,44 +130,50 @@ impl ServerContext {
         // Auth module.
         let mut auth_app = AuthApp::new(
             self.config.security(),
+            std::mem::take(&mut self.authenticators),
             // listeners
             auth_to_listener_senders,
-            listeners_to_auth_receiver,
+            self.listeners_to_auth_receiver.take().unwrap(),
             // server ctx
             self.auth_receiver.take().unwrap(),
         )?;
         let auth_app_handle = runtime.spawn(async move {
             auth_app.run_loop().await;
         });
-        handles.push(auth_app_handle);
+        self.handles.push(auth_app_handle);
 
         #[cfg(feature = "acl")]
         {
             // ACL module.
             let mut acl_app = AclApp::new(
+                self.config.security(),
+                self.config.general().delayed_publish(),
                 // listeners
                 acl_to_listener_senders,
-                listeners_to_acl_receiver,
+                self.listeners_to_acl_receiver.take().unwrap(),
                 // server ctx
                 self.acl_receiver.take().unwrap(),
-            );
+            )?;
             let acl_app_handle = runtime.spawn(async move {
                 acl_app.run_loop().await;
             });
-            handles.push(acl_app_handle);
+            self.handles.push(acl_app_handle);
         }
 
         #[cfg(not(feature = "acl"))]
-        {
-            drop(listeners_to_acl_receiver);
-        }
+        drop(acl_to_listener_senders);
 
         // Backends module.
         let (backends_to_dispatcher_sender, backends_to_dispatcher_receiver) =
             mpsc::channel(CHANNEL_CAPACITY);
         let (dispatcher_to_backends_sender, dispatcher_to_backends_receiver) =
-            mpsc::channel(CHANNEL_CAPACITY);
+            mpsc::channel(self.config.storage().message_queue_size());
+        let message_store = MessageStore::new(self.config.storage())?;
+        let message_queue_policy = message_store
+            .as_ref()
+            .map(|_store| self.config.storage().message_queue_policy());
         let mut backends_app = BackendsApp::new(
+            message_store,
             // dispatcher
             backends_to_dispatcher_sender,
             dispatcher_to_backends_receiver,
@@ -168,7 +183,7 @@ impl ServerContext {
         let backends_handle = runtime.spawn(async move {
             backends_app.run_loop().await;
         });
-        handles.push(backends_handle);
+        self.handles.push(backends_handle);
 
         // bridge module.
         let (bridge_to_dispatcher_sender, bridge_to_dispatcher_receiver) =
@@ -176,6 +191,7 @@ impl ServerContext {
         let (dispatcher_to_bridge_sender, dispatcher_to_bridge_receiver) =
             mpsc::channel(CHANNEL_CAPACITY);
         let mut bridge_app = BridgeApp::new(
+            self.config.bridges().to_vec(),
             // dispatcher
             bridge_to_dispatcher_sender,
             dispatcher_to_bridge_receiver,
@@ -185,7 +201,7 @@ impl ServerContext {
         let bridge_handle = runtime.spawn(async move {
             bridge_app.run_loop().await;
         });
-        handles.push(bridge_handle);
+        self.handles.push(bridge_handle);
 
         // dashboard module.
         #[cfg(feature = "dashboard")]
@@ -198,7 +214,7 @@ impl ServerContext {
             let dashboard_handle = runtime.spawn(async move {
                 dashboard_app.run_loop().await;
             });
-            handles.push(dashboard_handle);
+            self.dashboard_handle = Some(dashboard_handle);
         }
 
         // gateway module.
@@ -216,17 +232,18 @@ impl ServerContext {
         let gateway_handle = runtime.spawn(async move {
             gateway_app.run_loop().await;
         });
-        handles.push(gateway_handle);
+        self.handles.push(gateway_handle);
 
         // rule engine module.
         let (rule_engine_to_dispatcher_sender, rule_engine_to_dispatcher_receiver) =
-            mpsc::channel(CHANNEL_CAPACITY);
+            mpsc::unbounded_channel();
         let (dispatcher_to_rule_engine_sender, dispatcher_to_rule_engine_receiver) =
             mpsc::channel(CHANNEL_CAPACITY);
 
         #[cfg(feature = "rule_engine")]
         {
             let mut rule_engine_app = RuleEngineApp::new(
+                self.config.rules().to_vec(),
                 // dispatcher
                 rule_engine_to_dispatcher_sender,
                 dispatcher_to_rule_engine_receiver,
@@ -236,7 +253,7 @@ impl ServerContext {
             let rule_engine_handle = runtime.spawn(async move {
                 rule_engine_app.run_loop().await;
             });
-            handles.push(rule_engine_handle);
+            self.handles.push(rule_engine_handle);
         }
         #[cfg(not(feature = "rule_engine"))]
         {
@@ -246,10 +263,27 @@ impl ServerContext {
 
         // Dispatcher module.
         let mut dispatcher = Dispatcher::new(
+            self.config
+                .general()
+                .presence_topic()
+                .map(ToString::to_string),
+            self.config.general().max_queued_messages(),
+            self.config.general().queue_full_policy(),
+            self.config.general().max_topic_subscribers(),
+            self.config.general().delayed_publish(),
+            self.config.general().max_delayed_messages(),
             // backends module
+            message_queue_policy,
             dispatcher_to_backends_sender,
             backends_to_dispatcher_receiver,
             // bridge module
+            self.config
+                .bridges()
+                .iter()
+                .flat_map(config::Bridge::topics)
+                .map(TopicMapping::new)
+                .filter_map(|mapping| mapping.out_filter().map(ToString::to_string))
+                .collect(),
             dispatcher_to_bridge_sender,
             bridge_to_dispatcher_receiver,
             // gateway module
@@ -260,16 +294,126 @@ impl ServerContext {
             metrics_to_dispatcher_receiver,
             // listeners module
             dispatcher_to_listener_senders,
-            listeners_to_dispatcher_receiver,
+            self.listeners_to_dispatcher_receiver.take().unwrap(),
             // rule engine module
+            cfg!(feature = "rule_engine") && !self.config.rules().is_empty(),
             dispatcher_to_rule_engine_sender,
             rule_engine_to_dispatcher_receiver,
+            // server ctx
+            self.dispatcher_receiver.take().unwrap(),
         );
+        if self.config.storage().persistence() {
+            dispatcher.load_sessions(self.config.storage().db_path());
+        }
         let dispatcher_handle = runtime.spawn(async move {
             dispatcher.run_loop().await;
         });
-        handles.push(dispatcher_handle);
+        self.handles.push(dispatcher_handle);
 
         Ok(())
     }
+
+    /// Open message journal and start its batch writer, if enabled.
+    fn init_journal(&mut self, runtime: &Runtime) -> Result<(), Error> {
+        let storage = self.config.storage();
+        let Some(path) = storage.journal_path() else {
+            return Ok(());
+        };
+        let store = JournalBackend::open(path).map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Failed to open journal at {}, err: {err:?}", path.display()),
+            )
+        })?;
+        let (writer, sender) =
+            BatchWriter::new(store, storage.batch_size(), storage.batch_window());
+        // Writer stops after all listeners are dropped.
+        runtime.spawn(writer.run_loop());
+        self.journal = Some(sender);
+        Ok(())
+    }
+
+    /// Bind a new listener, which is spawned later by `spawn_listener()`.
+    pub(super) async fn bind_listener(
+        &mut self,
+        listener_config: &config::Listener,
+        general: &config::General,
+    ) -> Result<BoundListener, Error> {
+        let id = self.next_listener_id;
+        self.next_listener_id += 1;
+
+        // Dispatcher, auth and acl apps never wait for listener, which may be waiting for them.
+        let (dispatcher_sender, dispatcher_receiver) = mpsc::unbounded_channel();
+        let (auth_sender, auth_receiver) = mpsc::unbounded_channel();
+        let (acl_sender, acl_receiver) = mpsc::unbounded_channel();
+        let (server_ctx_sender, server_ctx_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        let address = listener_config.address().to_string();
+        let mut listener = Listener::bind(
+            id,
+            listener_config.clone(),
+            // dispatcher module
+            self.listeners_to_dispatcher_sender.clone(),
+            dispatcher_receiver,
+            // Auth module
+            self.listeners_to_auth_sender.clone(),
+            auth_receiver,
+            // acl module
+            self.listeners_to_acl_sender.clone(),
+            acl_receiver,
+            // server ctx
+            server_ctx_receiver,
+        )
+        .await
+        .map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Failed to listen at {address}, err: {err:?}"),
+            )
+        })?;
+        let maximum_packet_size = listener_config
+            .maximum_packet_size()
+            .unwrap_or_else(|| general.maximum_packet_size());
+        listener.set_maximum_packet_size(maximum_packet_size);
+        listener.set_max_will_payload_size(general.max_will_payload_size());
+        listener.set_maximum_qos(general.maximum_qos());
+        listener.set_queue_limit(general.max_queued_messages(), general.queue_full_policy());
+        listener.set_wildcard_subscription_available(general.wildcard_subscription_available());
+        listener.set_validate_payload_format(general.validate_payload_format());
+        listener.set_response_information(general.response_information());
+        listener.set_auth_methods(&self.auth_methods);
+        if let Some(journal) = &self.journal {
+            listener.set_journal(journal.clone());
+        }
+
+        Ok(BoundListener {
+            id,
+            address,
+            listener,
+            server_ctx_sender,
+            dispatcher_sender,
+            auth_sender,
+            acl_sender,
+        })
+    }
+
+    pub(super) fn spawn_listener(
+        &mut self,
+        id: ListenerId,
+        address: String,
+        mut listener: Listener,
+        sender: Sender<ServerContextToListenerCmd>,
+    ) {
+        let local_addr = listener.local_addr();
+        let handle = tokio::spawn(async move {
+            listener.run_loop().await;
+        });
+        self.listeners.push(ListenerHandle {
+            id,
+            address,
+            local_addr,
+            sender,
+            handle,
+        });
+    }
 }
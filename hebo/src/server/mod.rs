@@ -6,26 +6,73 @@
 
 use std::fs::File;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use sysinfo::{System, SystemExt, UserExt};
 use tokio::runtime::Runtime;
 #[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
+use crate::auth::{AuthMethod, Authenticator};
+use crate::backends::batch::BatchSender;
 use crate::commands::{
-    DashboardToServerContexCmd, ServerContextToAclCmd, ServerContextToAuthCmd,
-    ServerContextToBackendsCmd, ServerContextToBridgeCmd, ServerContextToGatewayCmd,
-    ServerContextToMetricsCmd, ServerContextToRuleEngineCmd,
+    DashboardToServerContexCmd, ListenerToAclCmd, ListenerToAuthCmd, ListenerToDispatcherCmd,
+    ServerContextToAclCmd, ServerContextToAuthCmd, ServerContextToBackendsCmd,
+    ServerContextToBridgeCmd, ServerContextToDispatcherCmd, ServerContextToGatewayCmd,
+    ServerContextToListenerCmd, ServerContextToMetricsCmd, ServerContextToRuleEngineCmd,
 };
 use crate::config::Config;
 use crate::error::{Error, ErrorKind};
+use crate::session::OutgoingPacket;
+use crate::types::ListenerId;
 
+mod builder;
 mod dashboard;
 mod init;
+mod reload;
 pub mod run;
 
+pub use builder::ServerBuilder;
+
 pub const CHANNEL_CAPACITY: usize = 16;
 
+/// Apps still running after this timeout are aborted on exit.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Listener app spawned by server context.
+struct ListenerHandle {
+    id: ListenerId,
+    address: String,
+
+    /// Socket address bound to, None for unix domain socket.
+    local_addr: Option<SocketAddr>,
+    sender: Sender<ServerContextToListenerCmd>,
+    handle: JoinHandle<()>,
+}
+
+/// Stop a running server from another task or thread.
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    sender: Sender<()>,
+}
+
+impl StopHandle {
+    /// Ask server to exit, same as `SIGTERM` signal.
+    ///
+    /// v5 clients are sent a DISCONNECT packet with reason of Server shutting down,
+    /// and connections are closed after pending data is flushed.
+    pub fn stop(&self) {
+        // Channel is full if stop request is pending, or closed if server has exited.
+        let _ret = self.sender.try_send(());
+    }
+}
+
 /// `ServerContext` manages lifetime of Dispatcher and Listeners.
 ///
 /// All kernel signals are handled here.
@@ -35,10 +82,29 @@ pub const CHANNEL_CAPACITY: usize = 16;
 pub struct ServerContext {
     config: Config,
 
+    /// Path to config file, used to reload config.
+    config_file: Option<PathBuf>,
+
+    /// Custom authenticators, moved to auth app when it is spawned.
+    authenticators: Vec<Box<dyn Authenticator>>,
+
+    /// Enhanced auth methods of v5 clients, shared by all listeners.
+    auth_methods: Vec<Arc<dyn AuthMethod>>,
+
+    /// Writer of message journal, shared by all listeners, disabled if None.
+    journal: Option<BatchSender<OutgoingPacket>>,
+
+    /// Notified with bound addresses after listeners are initialized.
+    bound_addresses_senders: Vec<oneshot::Sender<Vec<(ListenerId, SocketAddr)>>>,
+
     // dashboard -> server_ctx
     dashboard_sender: Option<Sender<DashboardToServerContexCmd>>,
     dashboard_receiver: Receiver<DashboardToServerContexCmd>,
 
+    /// Stop requests from `StopHandle`.
+    stop_sender: Sender<()>,
+    stop_receiver: Receiver<()>,
+
     // server_ctx -> acl
     acl_sender: Sender<ServerContextToAclCmd>,
     acl_receiver: Option<Receiver<ServerContextToAclCmd>>,
@@ -55,6 +121,10 @@ pub struct ServerContext {
     bridge_sender: Sender<ServerContextToBridgeCmd>,
     bridge_receiver: Option<Receiver<ServerContextToBridgeCmd>>,
 
+    // server_ctx -> dispatcher
+    dispatcher_sender: Sender<ServerContextToDispatcherCmd>,
+    dispatcher_receiver: Option<Receiver<ServerContextToDispatcherCmd>>,
+
     // server_ctx -> gateway
     gateway_sender: Sender<ServerContextToGatewayCmd>,
     gateway_receiver: Option<Receiver<ServerContextToGatewayCmd>>,
@@ -66,26 +136,61 @@ pub struct ServerContext {
     // server_ctx -> rule_engine
     rule_engine_sender: Sender<ServerContextToRuleEngineCmd>,
     rule_engine_receiver: Option<Receiver<ServerContextToRuleEngineCmd>>,
+
+    // listeners -> dispatcher
+    listeners_to_dispatcher_sender: Sender<ListenerToDispatcherCmd>,
+    listeners_to_dispatcher_receiver: Option<Receiver<ListenerToDispatcherCmd>>,
+
+    // listeners -> auth
+    listeners_to_auth_sender: Sender<ListenerToAuthCmd>,
+    listeners_to_auth_receiver: Option<Receiver<ListenerToAuthCmd>>,
+
+    // listeners -> acl
+    listeners_to_acl_sender: Sender<ListenerToAclCmd>,
+    listeners_to_acl_receiver: Option<Receiver<ListenerToAclCmd>>,
+
+    // server_ctx -> listeners
+    listeners: Vec<ListenerHandle>,
+    next_listener_id: ListenerId,
+
+    /// Join handles of spawned apps.
+    handles: Vec<JoinHandle<()>>,
+    dashboard_handle: Option<JoinHandle<()>>,
 }
 
 impl ServerContext {
     #[must_use]
     pub fn new(config: Config) -> Self {
         let (dashboard_sender, dashboard_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let (stop_sender, stop_receiver) = mpsc::channel(1);
         let (acl_sender, acl_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (auth_sender, auth_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (backends_sender, backends_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (bridge_sender, bridge_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let (dispatcher_sender, dispatcher_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (gateway_sender, gateway_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (metrics_sender, metrics_receiver) = mpsc::channel(CHANNEL_CAPACITY);
         let (rule_engine_sender, rule_engine_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let (listeners_to_dispatcher_sender, listeners_to_dispatcher_receiver) =
+            mpsc::channel(CHANNEL_CAPACITY);
+        let (listeners_to_auth_sender, listeners_to_auth_receiver) =
+            mpsc::channel(CHANNEL_CAPACITY);
+        let (listeners_to_acl_sender, listeners_to_acl_receiver) = mpsc::channel(CHANNEL_CAPACITY);
 
         Self {
             config,
+            config_file: None,
+            authenticators: Vec::new(),
+            auth_methods: Vec::new(),
+            journal: None,
+            bound_addresses_senders: Vec::new(),
 
             dashboard_sender: Some(dashboard_sender),
             dashboard_receiver,
 
+            stop_sender,
+            stop_receiver,
+
             acl_sender,
             acl_receiver: Some(acl_receiver),
 
@@ -98,6 +203,9 @@ impl ServerContext {
             bridge_sender,
             bridge_receiver: Some(bridge_receiver),
 
+            dispatcher_sender,
+            dispatcher_receiver: Some(dispatcher_receiver),
+
             gateway_sender,
             gateway_receiver: Some(gateway_receiver),
 
@@ -106,9 +214,81 @@ impl ServerContext {
 
             rule_engine_sender,
             rule_engine_receiver: Some(rule_engine_receiver),
+
+            listeners_to_dispatcher_sender,
+            listeners_to_dispatcher_receiver: Some(listeners_to_dispatcher_receiver),
+
+            listeners_to_auth_sender,
+            listeners_to_auth_receiver: Some(listeners_to_auth_receiver),
+
+            listeners_to_acl_sender,
+            listeners_to_acl_receiver: Some(listeners_to_acl_receiver),
+
+            listeners: Vec::new(),
+            next_listener_id: 0,
+            handles: Vec::new(),
+            dashboard_handle: None,
+        }
+    }
+
+    /// Create a builder to construct server context in code.
+    #[must_use]
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// Set path to config file, which is read again on `SIGUSR1` signal.
+    pub fn set_config_file<P: Into<PathBuf>>(&mut self, config_file: P) -> &mut Self {
+        self.config_file = Some(config_file.into());
+        self
+    }
+
+    /// Register a custom authenticator to check connecting clients.
+    ///
+    /// It shall be called before `run_loop()`. Authenticators are asked after
+    /// the database and password file in `security` config, in registration order,
+    /// until one of them grants or denies access.
+    pub fn add_authenticator<A: Authenticator + 'static>(&mut self, authenticator: A) -> &mut Self {
+        self.authenticators.push(Box::new(authenticator));
+        self
+    }
+
+    /// Register an enhanced auth method for v5 clients, like SCRAM.
+    ///
+    /// It shall be called before `run_loop()`. Clients setting Authentication Method
+    /// in connect packet to name of this method are authenticated with it, instead of
+    /// authenticators.
+    pub fn add_auth_method<M: AuthMethod + 'static>(&mut self, auth_method: M) -> &mut Self {
+        self.auth_methods.push(Arc::new(auth_method));
+        self
+    }
+
+    /// Get socket addresses of listeners once they are bound.
+    ///
+    /// It shall be called before `run_loop()`. Ports picked by system are reported
+    /// for listeners bound to port 0, unix domain sockets are skipped.
+    pub fn bound_addresses(&mut self) -> oneshot::Receiver<Vec<(ListenerId, SocketAddr)>> {
+        let (sender, receiver) = oneshot::channel();
+        self.bound_addresses_senders.push(sender);
+        receiver
+    }
+
+    /// Get a handle to stop server gracefully, when it is run in another thread.
+    #[must_use]
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            sender: self.stop_sender.clone(),
         }
     }
 
+    /// Get socket addresses of running listeners.
+    fn listener_addresses(&self) -> Vec<(ListenerId, SocketAddr)> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr.map(|addr| (listener.id, addr)))
+            .collect()
+    }
+
     /// Send `SIGUSR1` signal to running process.
     ///
     /// # Errors
@@ -147,7 +327,7 @@ impl ServerContext {
 
     /// Notify server process to reload config by sending a signal.
     #[cfg(unix)]
-    fn send_signal(&mut self, sig: i32) -> Result<(), Error> {
+    fn send_signal(&self, sig: i32) -> Result<(), Error> {
         log::info!("send_signal() {}", sig);
         let mut fd = File::open(self.config.general().pid_file())?;
         let mut pid_str = String::new();
@@ -157,9 +337,9 @@ impl ServerContext {
             Error::from_string(
                 ErrorKind::PidError,
                 format!(
-                    "Failed to parse pid {} from file {:?}, err: {:?}",
+                    "Failed to parse pid {} from file {}, err: {:?}",
                     pid_str,
-                    &self.config.general().pid_file(),
+                    self.config.general().pid_file().display(),
                     err
                 ),
             )
@@ -181,13 +361,16 @@ impl ServerContext {
     }
 
     fn write_pid(&self) -> Result<(), Error> {
+        if self.config.general().pid_file().as_os_str().is_empty() {
+            return Ok(());
+        }
         let pid = std::process::id();
         let mut fd = File::create(self.config.general().pid_file()).map_err(|err| {
             Error::from_string(
                 ErrorKind::IoError,
                 format!(
-                    "Failed to write pid to file {:?}, got err: {:?}",
-                    &self.config.general().pid_file(),
+                    "Failed to write pid to file {}, got err: {:?}",
+                    self.config.general().pid_file().display(),
                     err
                 ),
             )
@@ -257,10 +440,88 @@ impl ServerContext {
 
         runtime.block_on(async {
             self.init_modules(runtime).await?;
-            self.run_inner_loop().await
+            self.run_inner_loop().await?;
+            self.stop_modules().await;
+            Ok(())
         })
     }
 
+    /// Notify all apps to stop and wait for them to exit.
+    ///
+    /// Listeners are stopped first, so that messages sent by disconnecting sessions,
+    /// like will messages, can still be routed by dispatcher.
+    async fn stop_modules(&mut self) {
+        log::info!("ServerContext::stop_modules()");
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        for listener in &self.listeners {
+            if let Err(err) = listener.sender.send(ServerContextToListenerCmd::Stop).await {
+                log::error!("Failed to stop listener, err: {:?}", err);
+            }
+        }
+        for listener in &self.listeners {
+            if tokio::time::timeout_at(deadline, listener.sender.closed())
+                .await
+                .is_err()
+            {
+                log::warn!("Listener not stopped in {:?}", STOP_TIMEOUT);
+            }
+        }
+        let listener_handles = self.listeners.drain(..).map(|listener| listener.handle);
+        self.handles.extend(listener_handles);
+
+        let ret = [
+            self.dispatcher_sender
+                .send(ServerContextToDispatcherCmd::Stop)
+                .await
+                .is_ok(),
+            self.acl_sender
+                .send(ServerContextToAclCmd::Stop)
+                .await
+                .is_ok(),
+            self.auth_sender
+                .send(ServerContextToAuthCmd::Stop)
+                .await
+                .is_ok(),
+            self.backends_sender
+                .send(ServerContextToBackendsCmd::Stop)
+                .await
+                .is_ok(),
+            self.bridge_sender
+                .send(ServerContextToBridgeCmd::Stop)
+                .await
+                .is_ok(),
+            self.gateway_sender
+                .send(ServerContextToGatewayCmd::Stop)
+                .await
+                .is_ok(),
+            self.metrics_sender
+                .send(ServerContextToMetricsCmd::Stop)
+                .await
+                .is_ok(),
+            self.rule_engine_sender
+                .send(ServerContextToRuleEngineCmd::Stop)
+                .await
+                .is_ok(),
+        ];
+        if ret.contains(&false) {
+            log::error!("Failed to send stop cmd to some apps");
+        }
+
+        // Dashboard app has no stop command.
+        if let Some(handle) = self.dashboard_handle.take() {
+            handle.abort();
+        }
+        for mut handle in self.handles.drain(..) {
+            if tokio::time::timeout_at(deadline, &mut handle)
+                .await
+                .is_err()
+            {
+                log::warn!("App not stopped in {:?}, abort it", STOP_TIMEOUT);
+                handle.abort();
+            }
+        }
+    }
+
     #[cfg(not(unix))]
     async fn run_inner_loop(&mut self) -> Result<(), Error> {
         loop {
@@ -270,8 +531,18 @@ impl ServerContext {
                         log::error!("Failed to handle dashboard cmd: {:?}", err);
                     }
                 }
+                Ok(()) = tokio::signal::ctrl_c() => {
+                    log::info!("Quit with Ctrl-C");
+                    break;
+                }
+                Some(()) = self.stop_receiver.recv() => {
+                    log::info!("Quit with stop handle");
+                    break;
+                }
             }
         }
+
+        Ok(())
     }
 
     #[cfg(unix)]
@@ -290,8 +561,10 @@ impl ServerContext {
                     }
                 }
                 Some(_n) = sigusr1_stream.recv() => {
-                    log::info!("Realod config");
-                    // TODO(Shaohua): Reload config and send new config to other apps.
+                    log::info!("Reload config");
+                    if let Err(err) = self.reload_config().await {
+                        log::error!("Failed to reload config, keep old one: {:?}", err);
+                    }
                 },
                 Some(_n) = sigterm_stream.recv() => {
                     log::info!("Quit with SIGTERM");
@@ -305,6 +578,10 @@ impl ServerContext {
                     log::info!("Quit with SIGINT");
                     break;
                 }
+                Some(()) = self.stop_receiver.recv() => {
+                    log::info!("Quit with stop handle");
+                    break;
+                }
             }
         }
 
@@ -0,0 +1,250 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Reload config file and apply changes to running apps.
+
+use tokio::time::Instant;
+
+use super::init::BoundListener;
+use super::{ServerContext, STOP_TIMEOUT};
+use crate::auth::AuthSettings;
+use crate::commands::{
+    ServerContextToAuthCmd, ServerContextToDispatcherCmd, ServerContextToListenerCmd,
+};
+use crate::config::{self, Config};
+use crate::error::{Error, ErrorKind};
+use crate::types::ListenerId;
+
+#[cfg(feature = "acl")]
+use crate::acl::{rules::AclRules, AclApp};
+#[cfg(feature = "acl")]
+use crate::commands::ServerContextToAclCmd;
+
+impl ServerContext {
+    /// Read config file again and apply the difference.
+    ///
+    /// Listeners are matched by bind address, those with unchanged address keep running
+    /// with their old settings. Other sections except `security` are applied on next restart.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if new config is invalid, or new listeners cannot be bound.
+    /// Old config keeps running in that case.
+    pub(super) async fn reload_config(&mut self) -> Result<(), Error> {
+        let config_file = self
+            .config_file
+            .clone()
+            .ok_or_else(|| Error::new(ErrorKind::ConfigError, "No config file to reload"))?;
+        let mut config = Config::from_file(&config_file)?;
+        // Log file may be not writable after privileges are dropped, so only validate
+        // sections which are applied here.
+        config.general().validate()?;
+        for listener_config in config.listeners() {
+            listener_config.validate(false)?;
+        }
+        config.security().validate()?;
+
+        // Load security settings before touching running apps, so that nothing is changed
+        // if password file or acl file is invalid.
+        let auth_settings = AuthSettings::new(config.security())?;
+        #[cfg(feature = "acl")]
+        let acl_rules = AclApp::new_rules(config.security())?;
+
+        let removed_listeners: Vec<ListenerId> = self
+            .listeners
+            .iter()
+            .filter(|listener| {
+                !config
+                    .listeners()
+                    .iter()
+                    .any(|listener_config| listener_config.address() == listener.address)
+            })
+            .map(|listener| listener.id)
+            .collect();
+
+        // Bind new listeners before touching running apps too.
+        let mut bound_listeners = Vec::new();
+        for listener_config in config.listeners() {
+            if self
+                .listeners
+                .iter()
+                .any(|listener| listener.address == listener_config.address())
+            {
+                continue;
+            }
+            bound_listeners.push(
+                self.bind_listener(listener_config, config.general())
+                    .await?,
+            );
+        }
+
+        self.update_security(
+            auth_settings,
+            #[cfg(feature = "acl")]
+            acl_rules,
+        )
+        .await;
+
+        for bound_listener in bound_listeners {
+            self.add_listener(bound_listener).await;
+        }
+        for listener_id in removed_listeners {
+            self.remove_listener(listener_id).await;
+        }
+
+        let listeners = self.keep_running_listeners(config.listeners());
+        config.set_listeners(listeners);
+        self.config = config;
+        Ok(())
+    }
+
+    /// Running listeners are not restarted, keep their old settings in config,
+    /// so that it matches what is actually running.
+    fn keep_running_listeners(
+        &self,
+        listener_configs: &[config::Listener],
+    ) -> Vec<config::Listener> {
+        listener_configs
+            .iter()
+            .map(|listener_config| {
+                let Some(old_config) = self
+                    .config
+                    .listeners()
+                    .iter()
+                    .find(|old_config| old_config.address() == listener_config.address())
+                else {
+                    return listener_config.clone();
+                };
+                if old_config != listener_config {
+                    log::warn!(
+                        "Changes of listener at {} are not applied until restart",
+                        listener_config.address()
+                    );
+                }
+                old_config.clone()
+            })
+            .collect()
+    }
+
+    async fn update_security(
+        &self,
+        auth_settings: AuthSettings,
+        #[cfg(feature = "acl")] acl_rules: Option<AclRules>,
+    ) {
+        #[cfg(feature = "acl")]
+        if let Err(err) = self
+            .acl_sender
+            .send(ServerContextToAclCmd::UpdateSecurity(acl_rules))
+            .await
+        {
+            log::error!("Failed to send acl rules to acl, err: {:?}", err);
+        }
+
+        if let Err(err) = self
+            .auth_sender
+            .send(ServerContextToAuthCmd::UpdateSecurity(Box::new(
+                auth_settings,
+            )))
+            .await
+        {
+            log::error!("Failed to send security settings to auth, err: {:?}", err);
+        }
+    }
+
+    async fn add_listener(&mut self, bound_listener: BoundListener) {
+        let BoundListener {
+            id,
+            address,
+            listener,
+            server_ctx_sender,
+            dispatcher_sender,
+            auth_sender,
+            acl_sender,
+        } = bound_listener;
+        log::info!("Add listener #{} at {}", id, address);
+
+        if let Err(err) = self
+            .dispatcher_sender
+            .send(ServerContextToDispatcherCmd::ListenerAdded(
+                id,
+                address.clone(),
+                dispatcher_sender,
+            ))
+            .await
+        {
+            log::error!("Failed to send new listener to dispatcher, err: {:?}", err);
+        }
+        if let Err(err) = self
+            .auth_sender
+            .send(ServerContextToAuthCmd::ListenerAdded(id, auth_sender))
+            .await
+        {
+            log::error!("Failed to send new listener to auth, err: {:?}", err);
+        }
+        #[cfg(feature = "acl")]
+        if let Err(err) = self
+            .acl_sender
+            .send(ServerContextToAclCmd::ListenerAdded(id, acl_sender))
+            .await
+        {
+            log::error!("Failed to send new listener to acl, err: {:?}", err);
+        }
+        #[cfg(not(feature = "acl"))]
+        drop(acl_sender);
+
+        self.spawn_listener(id, address, listener, server_ctx_sender);
+    }
+
+    /// Disconnect all sessions of listener and wait for it to exit.
+    async fn remove_listener(&mut self, listener_id: ListenerId) {
+        let Some(index) = self
+            .listeners
+            .iter()
+            .position(|listener| listener.id == listener_id)
+        else {
+            return;
+        };
+        let mut listener = self.listeners.remove(index);
+        log::info!("Remove listener #{} at {}", listener.id, listener.address);
+
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        if let Err(err) = listener
+            .sender
+            .send(ServerContextToListenerCmd::Remove)
+            .await
+        {
+            log::error!("Failed to stop listener, err: {:?}", err);
+        }
+        if tokio::time::timeout_at(deadline, &mut listener.handle)
+            .await
+            .is_err()
+        {
+            log::warn!("Listener not stopped in {:?}, abort it", STOP_TIMEOUT);
+            listener.handle.abort();
+        }
+
+        if let Err(err) = self
+            .dispatcher_sender
+            .send(ServerContextToDispatcherCmd::ListenerRemoved(listener_id))
+            .await
+        {
+            log::error!("Failed to remove listener from dispatcher, err: {:?}", err);
+        }
+        if let Err(err) = self
+            .auth_sender
+            .send(ServerContextToAuthCmd::ListenerRemoved(listener_id))
+            .await
+        {
+            log::error!("Failed to remove listener from auth, err: {:?}", err);
+        }
+        #[cfg(feature = "acl")]
+        if let Err(err) = self
+            .acl_sender
+            .send(ServerContextToAclCmd::ListenerRemoved(listener_id))
+            .await
+        {
+            log::error!("Failed to remove listener from acl, err: {:?}", err);
+        }
+    }
+}
@@ -8,7 +8,7 @@ use tokio::runtime::Runtime;
 
 use super::ServerContext;
 use crate::config::Config;
-use crate::error::{Error, ErrorKind};
+use crate::error::Error;
 use crate::log::init_log;
 
 pub const DEFAULT_CONFIG: &str = "/etc/hebo/hebo.toml";
@@ -85,18 +85,7 @@ pub fn handle_cmdline() -> Result<(), Error> {
     );
 
     let config = if let Some(config_file) = config_file {
-        let config_content = std::fs::read_to_string(config_file).map_err(|err| {
-            Error::from_string(
-                ErrorKind::ConfigError,
-                format!("Failed to read config file {config_file}, err: {err:?}"),
-            )
-        })?;
-        let config: Config = toml::from_str(&config_content).map_err(|err| {
-            Error::from_string(
-                ErrorKind::ConfigError,
-                format!("Invalid toml config file {config_file}, err: {err:?}"),
-            )
-        })?;
+        let config = Config::from_file(config_file)?;
 
         if args.test {
             if let Err(err) = config.validate(false) {
@@ -108,12 +97,15 @@ pub fn handle_cmdline() -> Result<(), Error> {
         }
         config
     } else {
-        Config::default()
+        Config::from_env()?
     };
 
     init_log(config.log())?;
 
     let mut server = ServerContext::new(config);
+    if let Some(config_file) = config_file {
+        server.set_config_file(config_file);
+    }
 
     if args.stop {
         return server.send_stop_signal();
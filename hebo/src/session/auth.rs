@@ -0,0 +1,196 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Enhanced authentication of v5 clients with AUTH packets.
+
+use codec::{v5, BinaryData, ByteArray, DecodePacket, EncodeError, StringData};
+
+use super::{Session, Status};
+use crate::auth::{AuthExchange, AuthStep};
+use crate::error::Error;
+
+/// Authentication exchange with a v5 client.
+#[derive(Debug)]
+pub struct EnhancedAuth {
+    /// Authentication Method in connect packet.
+    method: String,
+    exchange: Box<dyn AuthExchange>,
+    /// Connect packet waiting for authentication, sent to listener once client is authenticated.
+    connect_packet: Option<v5::ConnectPacket>,
+    /// Authentication Data sent to client in connect ack packet.
+    success_data: Vec<u8>,
+}
+
+/// Get Authentication Method and Authentication Data in `properties`.
+fn authentication_properties(properties: &v5::Properties) -> (Option<&str>, Option<&[u8]>) {
+    let mut method = None;
+    let mut data = None;
+    for property in properties.props() {
+        match property {
+            v5::Property::AuthenticationMethod(value) => method = Some(value.as_ref()),
+            v5::Property::AuthenticationData(value) => data = Some(value.as_ref()),
+            _ => (),
+        }
+    }
+    (method, data)
+}
+
+impl Session {
+    /// Start authentication exchange if Authentication Method is set in connect packet,
+    /// or else send the connect packet to listener.
+    pub(super) async fn check_enhanced_auth(
+        &mut self,
+        packet: v5::ConnectPacket,
+    ) -> Result<(), Error> {
+        let (method, data) = authentication_properties(packet.properties());
+        let Some(method) = method.map(ToString::to_string) else {
+            // It is a Protocol Error to include Authentication Data if there is
+            // no Authentication Method.
+            if data.is_some() {
+                return self.abort_connect_v5(v5::ReasonCode::ProtocolError).await;
+            }
+            return self.send_connect_v5(packet).await;
+        };
+        let data = data.unwrap_or_default().to_vec();
+
+        let Some(auth_method) = self.config.auth_method(&method) else {
+            log::warn!(
+                "session: Unsupported auth method of {}: {:?}",
+                self.client_id,
+                method
+            );
+            return self
+                .abort_connect_v5(v5::ReasonCode::BadAuthenticationMethod)
+                .await;
+        };
+        self.auth = Some(EnhancedAuth {
+            exchange: auth_method.start(&self.client_id),
+            method,
+            connect_packet: Some(packet),
+            success_data: Vec::new(),
+        });
+        self.status = Status::Authenticating;
+        self.step_enhanced_auth(&data).await
+    }
+
+    pub(super) async fn on_client_auth(&mut self, buf: &[u8]) -> Result<(), Error> {
+        // AUTH packet is not defined in v3 protocol.
+        if !self.is_v5() {
+            return self.send_disconnect(v5::ReasonCode::ProtocolError).await;
+        }
+
+        let mut ba = ByteArray::new(buf);
+        let packet = v5::AuthPacket::decode(&mut ba)?;
+
+        // Re-authentication of connected clients is not supported.
+        if self.status != Status::Authenticating {
+            log::warn!(
+                "session: Unexpected auth packet of {}, reason: {:?}",
+                self.client_id,
+                packet.reason_code()
+            );
+            return self.send_disconnect(v5::ReasonCode::ProtocolError).await;
+        }
+
+        // If the Client sends an AUTH packet, it MUST use the same Authentication Method
+        // as in the CONNECT packet.
+        let (method, data) = authentication_properties(packet.properties());
+        let method_matched = self
+            .auth
+            .as_ref()
+            .map_or(false, |auth| Some(auth.method.as_str()) == method);
+        if packet.reason_code() != v5::ReasonCode::ContinueAuthentication || !method_matched {
+            return self.abort_connect_v5(v5::ReasonCode::ProtocolError).await;
+        }
+        let data = data.unwrap_or_default().to_vec();
+        self.step_enhanced_auth(&data).await
+    }
+
+    /// Pass Authentication Data from client to auth method, and send its response.
+    async fn step_enhanced_auth(&mut self, data: &[u8]) -> Result<(), Error> {
+        let Some(auth) = self.auth.as_mut() else {
+            return self.abort_connect_v5(v5::ReasonCode::ProtocolError).await;
+        };
+        match auth.exchange.step(data).await {
+            AuthStep::Continue(data) => {
+                let mut packet = v5::AuthPacket::new();
+                packet.set_reason_code(v5::ReasonCode::ContinueAuthentication);
+                let method = StringData::from(&auth.method).map_err(EncodeError::from)?;
+                packet
+                    .properties_mut()
+                    .push(v5::Property::AuthenticationMethod(method))?;
+                packet
+                    .properties_mut()
+                    .push(v5::Property::AuthenticationData(BinaryData::from_slice(
+                        &data,
+                    )?))?;
+                self.send(packet).await
+            }
+            AuthStep::Success(identity, data) => {
+                auth.success_data = data;
+                let Some(mut packet) = auth.connect_packet.take() else {
+                    return self.abort_connect_v5(v5::ReasonCode::ProtocolError).await;
+                };
+                // Username is not checked by auth app again, so that it shall be the
+                // identity authenticated by auth method.
+                if !packet.username().is_empty() && packet.username() != identity {
+                    log::warn!(
+                        "session: Username {:?} of {} does not match authenticated identity {:?}",
+                        packet.username(),
+                        self.client_id,
+                        identity
+                    );
+                    return self.abort_connect_v5(v5::ReasonCode::NotAuthorized).await;
+                }
+                packet.set_username(Some(&identity))?;
+                self.send_connect_v5(packet).await
+            }
+            AuthStep::Failure => {
+                log::warn!(
+                    "session: Authentication failed, client id: {}, method: {:?}",
+                    self.client_id,
+                    auth.method
+                );
+                self.abort_connect_v5(v5::ReasonCode::NotAuthorized).await
+            }
+        }
+    }
+
+    /// Add Authentication Method and Authentication Data to connect ack packet,
+    /// if client is authenticated by an auth method.
+    pub(super) fn add_authentication(
+        &mut self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        let Some(auth) = self.auth.take() else {
+            return Ok(());
+        };
+        let method = StringData::from(&auth.method).map_err(EncodeError::from)?;
+        packet
+            .properties_mut()
+            .push(v5::Property::AuthenticationMethod(method))?;
+        if !auth.success_data.is_empty() {
+            packet
+                .properties_mut()
+                .push(v5::Property::AuthenticationData(BinaryData::from_slice(
+                    &auth.success_data,
+                )?))?;
+        }
+        Ok(())
+    }
+
+    /// Reject connect request with `reason_code` and close network connection.
+    pub(super) async fn abort_connect_v5(
+        &mut self,
+        reason_code: v5::ReasonCode,
+    ) -> Result<(), Error> {
+        // If a Server sends a CONNACK packet containing a non-zero Reason Code
+        // it MUST set Session Present to 0 [MQTT-3.2.2-6].
+        let ack_packet = v5::ConnectAckPacket::new(false, reason_code);
+        self.auth = None;
+        self.send(ack_packet).await?;
+        self.close();
+        Ok(())
+    }
+}
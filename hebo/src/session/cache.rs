@@ -2,28 +2,241 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use super::Session;
+use codec::{
+    v3, v5, ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, ProtocolLevel,
+    StringData, U32Data,
+};
+use std::time::Duration;
+use tokio::time::Instant;
 
+use super::{InflightMessages, OutgoingPacket, Session};
+use crate::config::QueueFullPolicy;
+use crate::error::Error;
+
+/// State of a disconnected session, kept until client reconnects or it expires.
+///
+/// It is serialized when cached sessions are saved to disk, and restored after server
+/// restarts. Expiry interval is restarted when the session is restored.
 #[derive(Debug, Clone)]
 pub struct CachedSession {
     client_id: String,
+    inflight_messages: InflightMessages,
+    /// Discard session state after this interval, None means never expire.
+    expiry_interval: Option<Duration>,
 }
 
 impl CachedSession {
     #[must_use]
-    pub const fn new(client_id: String) -> Self {
-        Self { client_id }
+    pub const fn new(client_id: String, inflight_messages: InflightMessages) -> Self {
+        Self {
+            client_id,
+            inflight_messages,
+            expiry_interval: None,
+        }
+    }
+
+    pub fn set_expiry_interval(&mut self, expiry_interval: Option<Duration>) -> &mut Self {
+        self.expiry_interval = expiry_interval;
+        self
+    }
+
+    /// Get time to keep session state after client is disconnected.
+    #[must_use]
+    pub const fn expiry_interval(&self) -> Option<Duration> {
+        self.expiry_interval
     }
 
     #[must_use]
     pub fn client_id(&self) -> &str {
         &self.client_id
     }
+
+    /// Get unacknowledged messages sent to client.
+    #[must_use]
+    pub const fn inflight_messages(&self) -> &InflightMessages {
+        &self.inflight_messages
+    }
+
+    /// Get number of messages waiting to be sent after client reconnects.
+    #[must_use]
+    pub fn queued_len(&self) -> usize {
+        self.inflight_messages.pending_len()
+    }
+
+    /// Queue message published while client is offline.
+    ///
+    /// Messages are delivered in order after client reconnects. When the queue has
+    /// `max_len` messages, the oldest one is evicted or `packet` is dropped according to `policy`.
+    /// Expired messages are removed first when the queue is full.
+    ///
+    /// Returns false if `packet` is dropped.
+    pub fn enqueue(
+        &mut self,
+        packet: OutgoingPacket,
+        max_len: usize,
+        policy: QueueFullPolicy,
+    ) -> bool {
+        self.inflight_messages.enqueue(packet, max_len, policy)
+    }
+}
+
+impl EncodePacket for CachedSession {
+    fn encode(&self, v: &mut Vec<u8>) -> Result<usize, EncodeError> {
+        let old_len = v.len();
+        StringData::from(&self.client_id)?.encode(v)?;
+        if let Some(interval) = self.expiry_interval {
+            let secs =
+                u32::try_from(interval.as_secs()).map_err(|_err| EncodeError::TooManyData)?;
+            v.push(1);
+            U32Data::new(secs).encode(v)?;
+        } else {
+            v.push(0);
+        }
+        self.inflight_messages.encode(v)?;
+        Ok(v.len() - old_len)
+    }
+}
+
+impl DecodePacket for CachedSession {
+    fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
+        let client_id = StringData::decode(ba)?;
+        let expiry_interval = match ba.read_byte()? {
+            0 => None,
+            1 => Some(Duration::from_secs(u64::from(U32Data::decode(ba)?.value()))),
+            _ => return Err(DecodeError::InvalidBoolData),
+        };
+        let inflight_messages = InflightMessages::decode(ba)?;
+        Ok(Self {
+            client_id: client_id.as_ref().to_string(),
+            inflight_messages,
+            expiry_interval,
+        })
+    }
 }
 
 impl Session {
-    pub(crate) fn load_cached_session(&mut self, _cached_session: &CachedSession) {
-        // Do nothing currently.
-        todo!()
+    /// Restore session state and re-deliver unacknowledged messages.
+    ///
+    /// Messages are re-sent after `retransmit_delay`, and no more than inflight window
+    /// at a time, the others are re-sent when previous ones are acknowledged.
+    pub(crate) async fn load_cached_session(
+        &mut self,
+        cached_session: CachedSession,
+    ) -> Result<(), Error> {
+        let window = self.config.inflight_window();
+        self.inflight_messages = cached_session.inflight_messages;
+        self.inflight_messages.set_window(window);
+        self.inflight_messages.set_queue_limit(
+            self.config.max_queued_messages(),
+            self.config.queue_full_policy(),
+        );
+
+        // When a Client reconnects with CleanSession set to 0, both the Client and Server MUST
+        // re-send any unacknowledged PUBLISH Packets (where QoS > 0) and PUBREL Packets
+        // using their original Packet Identifiers [MQTT-4.4.0-1].
+        self.inflight_messages.mark_retransmit();
+        let delay = self.config.retransmit_delay();
+        if delay.is_zero() {
+            self.send_queued_packets().await
+        } else {
+            self.retransmit_at = Some(Instant::now() + delay);
+            Ok(())
+        }
+    }
+
+    /// Send inflight messages waiting to be re-sent and then pending messages,
+    /// until inflight window is full.
+    pub(super) async fn send_queued_packets(&mut self) -> Result<(), Error> {
+        if self.retransmit_at.is_some() {
+            return Ok(());
+        }
+        for mut packet in self.inflight_messages.pop_retransmit() {
+            let packet_id = packet.packet_id();
+            if self.inflight_messages.is_released(packet_id) {
+                // PUBREC has been received, continue the handshake with PUBREL.
+                match packet {
+                    OutgoingPacket::V3(_packet) => {
+                        self.send(v3::PublishReleasePacket::new(packet_id)).await?;
+                    }
+                    OutgoingPacket::V5(_packet) => {
+                        self.send(v5::PublishReleasePacket::new(packet_id)).await?;
+                    }
+                }
+            } else {
+                packet.set_dup(true)?;
+                self.send_outgoing_packet(packet).await?;
+            }
+        }
+        for packet in self.inflight_messages.pop_pending() {
+            self.send_outgoing_packet(packet).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if session state shall be sent to dispatcher when the client is disconnected.
+    ///
+    /// Session state of v5 clients is kept until session expiry interval elapses,
+    /// regardless of clean start flag.
+    pub(super) fn keep_cached_session(&self) -> bool {
+        !self.client_id.is_empty()
+            && (!self.clean_session || self.protocol_level == ProtocolLevel::V5)
+    }
+
+    /// Save session state when the client is disconnected.
+    pub(super) fn to_cached_session(&self) -> CachedSession {
+        let mut cached_session =
+            CachedSession::new(self.client_id.clone(), self.inflight_messages.clone());
+        if self.protocol_level == ProtocolLevel::V5 {
+            cached_session.set_expiry_interval(self.config.session_expiry_interval());
+        }
+        cached_session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, QoS};
+
+    use super::CachedSession;
+    use crate::config::QueueFullPolicy;
+    use crate::session::{InflightMessages, OutgoingPacket};
+
+    fn new_packet(msg: &[u8]) -> OutgoingPacket {
+        OutgoingPacket::V3(v3::PublishPacket::new("offline/test", QoS::AtLeastOnce, msg).unwrap())
+    }
+
+    fn queued_messages(session: &CachedSession) -> Vec<Vec<u8>> {
+        let mut inflight_messages = session.inflight_messages().clone();
+        inflight_messages
+            .pop_pending()
+            .into_iter()
+            .map(|packet| match packet {
+                OutgoingPacket::V3(packet) => packet.message().to_vec(),
+                OutgoingPacket::V5(packet) => packet.message().to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_enqueue_drop_oldest() {
+        let mut session = CachedSession::new("client-1".to_string(), InflightMessages::new(0));
+        for msg in [b"1", b"2", b"3", b"4"] {
+            assert!(session.enqueue(new_packet(msg), 3, QueueFullPolicy::DropOldest));
+        }
+        assert_eq!(session.queued_len(), 3);
+        assert_eq!(queued_messages(&session), [b"2", b"3", b"4"]);
+    }
+
+    #[test]
+    fn test_enqueue_reject() {
+        let mut session = CachedSession::new("client-1".to_string(), InflightMessages::new(0));
+        for msg in [b"1", b"2", b"3"] {
+            assert!(session.enqueue(new_packet(msg), 3, QueueFullPolicy::Reject));
+        }
+        assert!(!session.enqueue(new_packet(b"4"), 3, QueueFullPolicy::Reject));
+        assert_eq!(queued_messages(&session), [b"1", b"2", b"3"]);
+
+        // Queueing is disabled.
+        assert!(!session.enqueue(new_packet(b"5"), 0, QueueFullPolicy::DropOldest));
     }
 }
@@ -0,0 +1,144 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Capture raw packets of a client connection.
+
+use codec::{ByteArray, DecodePacket, FixedHeader};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use super::Session;
+use crate::config::Capture;
+use crate::error::Error;
+
+/// Max number of lines waiting to be written, packets are dropped if exceeded.
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Packet received from client.
+    Received,
+
+    /// Packet sent to client.
+    Sent,
+}
+
+impl Direction {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Received => "RECV",
+            Self::Sent => "SEND",
+        }
+    }
+}
+
+/// Append packets to capture file of a client.
+///
+/// Lines are written by a background task, so that session is not blocked by file io.
+#[derive(Debug)]
+pub struct PacketCapture {
+    sender: Sender<String>,
+}
+
+impl PacketCapture {
+    /// Start writer task of capture file if `client_id` is in capture list.
+    #[must_use]
+    pub fn open(capture: &Capture, client_id: &str) -> Option<Self> {
+        if !capture.contains(client_id) {
+            return None;
+        }
+
+        // Client id may contain path separators.
+        let filename: String = client_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let directory = capture.directory().to_path_buf();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            if let Err(err) = write_lines(directory, &filename, receiver).await {
+                log::error!(
+                    "capture: Failed to write capture file {}, err: {:?}",
+                    filename,
+                    err
+                );
+            }
+        });
+        Some(Self { sender })
+    }
+
+    /// Write a packet as a line of `<timestamp> <direction> <packet type> <hex bytes>`.
+    pub fn record(&self, direction: Direction, buf: &[u8]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut ba = ByteArray::new(buf);
+        let packet_type = FixedHeader::decode(&mut ba).map_or_else(
+            |_err| "Invalid".to_owned(),
+            |fixed_header| format!("{:?}", fixed_header.packet_type()),
+        );
+
+        let mut line = format!(
+            "{}.{:06} {} {} ",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            direction.as_str(),
+            packet_type
+        );
+        for byte in buf {
+            let _ret = write!(line, "{byte:02x}");
+        }
+        line.push('\n');
+
+        if let Err(err) = self.sender.try_send(line) {
+            log::error!("capture: Failed to record packet, err: {:?}", err);
+        }
+    }
+}
+
+/// Append lines to capture file until session is closed.
+///
+/// Lines are buffered, and flushed when no more lines are pending.
+async fn write_lines(
+    directory: PathBuf,
+    filename: &str,
+    mut receiver: Receiver<String>,
+) -> Result<(), Error> {
+    fs::create_dir_all(&directory).await?;
+    let path = directory.join(format!("{filename}.cap"));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let mut writer = BufWriter::new(file);
+    while let Some(line) = receiver.recv().await {
+        writer.write_all(line.as_bytes()).await?;
+        if receiver.is_empty() {
+            writer.flush().await?;
+        }
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+impl Session {
+    /// Start capturing packets if client id is in capture list of listener.
+    pub(super) fn open_capture(&mut self) {
+        let Some(capture) = self.config.capture() else {
+            return;
+        };
+        self.capture = PacketCapture::open(capture, &self.client_id);
+    }
+
+    pub(super) fn capture_packet(&self, direction: Direction, buf: &[u8]) {
+        if let Some(capture) = &self.capture {
+            capture.record(direction, buf);
+        }
+    }
+}
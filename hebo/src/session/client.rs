@@ -5,13 +5,14 @@
 //! Handles client packets
 
 use codec::{
-    utils::random_client_id, v3, v5, ByteArray, DecodeError, DecodePacket, FixedHeader, PacketType,
-    ProtocolLevel, QoS,
+    utils::random_client_id, v3, v5, ByteArray, DecodeError, DecodePacket, EncodeError,
+    FixedHeader, PacketId, PacketType, ProtocolLevel, QoS, StringData,
 };
 
-use super::{Session, Status};
+use super::{PubRecvState, Session, Status, WillMessage};
 use crate::commands::SessionToListenerCmd;
 use crate::error::{Error, ErrorKind};
+use crate::log::LogLimiter;
 
 impl Session {
     pub(super) async fn handle_client_packet(&mut self, buf: &[u8]) -> Result<(), Error> {
@@ -20,8 +21,17 @@ impl Session {
             Ok(fixed_header) => fixed_header,
             Err(err) => {
                 // Disconnect the network if Connect Packet is invalid.
-                log::error!("session: Invalid packet: {:?}, content: {:?}", err, buf);
-                self.send_disconnect().await?;
+                static INVALID_PACKETS: LogLimiter = LogLimiter::new();
+                if let Some(suppressed) = INVALID_PACKETS.check() {
+                    log::error!(
+                        "session: Invalid packet: {:?}, content: {:?}{}",
+                        err,
+                        buf,
+                        suppressed
+                    );
+                }
+                self.send_disconnect(v5::ReasonCode::MalformedPacket)
+                    .await?;
                 return Err(err.into());
             }
         };
@@ -35,59 +45,135 @@ impl Session {
         // a PINGREQ Packet [MQTT-3.1.2-23].
         self.reset_instant();
 
-        // TODO(Shaohua): Check packet oversize.
+        // After a Network Connection is established by a Client to a Server, the first Packet
+        // sent from the Client to the Server MUST be a CONNECT Packet [MQTT-3.1.0-1].
+        let packet_type = fixed_header.packet_type();
+        if self.status == Status::Invalid && packet_type != PacketType::Connect {
+            static NOT_CONNECTED_ERRORS: LogLimiter = LogLimiter::new();
+            if let Some(suppressed) = NOT_CONNECTED_ERRORS.check() {
+                log::error!(
+                    "session: Got {:?} before CONNECT, do disconnect!{}",
+                    packet_type,
+                    suppressed
+                );
+            }
+            self.status = Status::Disconnected;
+            return Err(Error::from_string(
+                ErrorKind::StatusError,
+                format!("session: Got {packet_type:?} packet before CONNECT"),
+            ));
+        }
 
-        match fixed_header.packet_type() {
+        // Only AUTH packets are expected until authentication exchange is completed.
+        if self.status == Status::Authenticating && packet_type != PacketType::Auth {
+            log::warn!(
+                "session: Got {:?} packet during authentication, {}",
+                packet_type,
+                self.client_id
+            );
+            return self.abort_connect_v5(v5::ReasonCode::ProtocolError).await;
+        }
+
+        match packet_type {
             PacketType::Connect => self.on_client_connect(buf).await,
-            PacketType::PingRequest => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_ping_v5(buf).await
-                } else {
-                    self.on_client_ping(buf).await
-                }
-            }
-            PacketType::Publish { .. } => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_publish_v5(buf).await
-                } else {
-                    self.on_client_publish(buf).await
-                }
-            }
-            PacketType::PublishRelease { .. } => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_publish_release_v5(buf).await
-                } else {
-                    self.on_client_publish_release(buf).await
-                }
-            }
-            PacketType::Subscribe => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_subscribe_v5(buf).await
-                } else {
-                    self.on_client_subscribe(buf).await
-                }
-            }
-            PacketType::Unsubscribe => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_unsubscribe_v5(buf).await
-                } else {
-                    self.on_client_unsubscribe(buf).await
-                }
-            }
-            PacketType::Disconnect => {
-                if self.protocol_level == ProtocolLevel::V5 {
-                    self.on_client_disconnect_v5(buf).await
-                } else {
-                    self.on_client_disconnect(buf).await
-                }
-            }
+            PacketType::PingRequest => self.on_client_ping(buf).await,
+            PacketType::Publish { .. } => self.on_client_publish(buf).await,
+            PacketType::PublishAck => self.on_client_publish_ack(buf).await,
+            PacketType::PublishReceived => self.on_client_publish_received(buf).await,
+            PacketType::PublishRelease => self.on_client_publish_release(buf).await,
+            PacketType::PublishComplete => self.on_client_publish_complete(buf).await,
+            PacketType::Subscribe => self.on_client_subscribe(buf).await,
+            PacketType::Unsubscribe => self.on_client_unsubscribe(buf).await,
+            PacketType::Disconnect => self.on_client_disconnect(buf).await,
+            PacketType::Auth => self.on_client_auth(buf).await,
             t => {
+                // Packets like CONNACK and SUBACK are only sent from server to client.
                 log::warn!("Unhandled msg: {:?}", t);
-                self.send_disconnect().await
+                self.send_disconnect(v5::ReasonCode::ProtocolError).await
             }
         }
     }
 
+    #[inline]
+    pub(super) fn is_v5(&self) -> bool {
+        self.protocol_level == ProtocolLevel::V5
+    }
+
+    async fn on_client_ping(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_ping_v5(buf).await
+        } else {
+            self.on_client_ping_v3(buf).await
+        }
+    }
+
+    async fn on_client_publish(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if !self.check_rate_limit(buf.len()).await? {
+            return Ok(());
+        }
+        if self.is_v5() {
+            self.on_client_publish_v5(buf).await
+        } else {
+            self.on_client_publish_v3(buf).await
+        }
+    }
+
+    async fn on_client_publish_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_publish_ack_v5(buf).await
+        } else {
+            self.on_client_publish_ack_v3(buf).await
+        }
+    }
+
+    async fn on_client_publish_received(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_publish_received_v5(buf).await
+        } else {
+            self.on_client_publish_received_v3(buf).await
+        }
+    }
+
+    async fn on_client_publish_release(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_publish_release_v5(buf).await
+        } else {
+            self.on_client_publish_release_v3(buf).await
+        }
+    }
+
+    async fn on_client_publish_complete(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_publish_complete_v5(buf).await
+        } else {
+            self.on_client_publish_complete_v3(buf).await
+        }
+    }
+
+    async fn on_client_subscribe(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_subscribe_v5(buf).await
+        } else {
+            self.on_client_subscribe_v3(buf).await
+        }
+    }
+
+    async fn on_client_unsubscribe(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_unsubscribe_v5(buf).await
+        } else {
+            self.on_client_unsubscribe_v3(buf).await
+        }
+    }
+
+    async fn on_client_disconnect(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.is_v5() {
+            self.on_client_disconnect_v5(buf).await
+        } else {
+            self.on_client_disconnect_v3(buf).await
+        }
+    }
+
     pub(super) async fn reject_client_id(&mut self) -> Result<(), Error> {
         log::info!("Session::reject_client_id()");
         // If a server sends a CONNACK packet containing a non-zero return code
@@ -99,6 +185,22 @@ impl Session {
         Ok(())
     }
 
+    /// Check will payload size against `max_will_payload_size` in config.
+    pub(super) const fn is_will_payload_too_large(&self, len: usize) -> bool {
+        let max_size = self.config.max_will_payload_size();
+        max_size > 0 && len > max_size
+    }
+
+    /// Check `qos` of publish packet against `maximum_qos` in config.
+    pub(super) fn is_qos_not_supported(&self, qos: QoS) -> bool {
+        qos > self.config.maximum_qos()
+    }
+
+    /// Downgrade requested `QoS` of subscriptions to `maximum_qos` in config.
+    pub(super) fn downgrade_subscribe_qos(&self, qos: QoS) -> QoS {
+        qos.min(self.config.maximum_qos())
+    }
+
     async fn on_client_connect(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
         let protocol_level = match ProtocolLevel::decode(&mut ba) {
@@ -201,6 +303,7 @@ impl Session {
             }
         }
         self.client_id = packet.client_id().to_string();
+        self.open_capture();
 
         // Update keep_alive timer.
         //
@@ -221,10 +324,28 @@ impl Session {
             let ack_packet =
                 v3::ConnectAckPacket::new(false, v3::ConnectReturnCode::IdentifierRejected);
             self.send(ack_packet).await?;
-            return self.send_disconnect().await;
+            self.close();
+            return Ok(());
+        }
+
+        if self.is_will_payload_too_large(packet.will_message().len()) {
+            // If none of the return codes listed are deemed applicable, then the Server
+            // MUST close the Network Connection without sending a CONNACK [MQTT-3.2.2-6].
+            log::warn!(
+                "session: will payload of {} is too large, got {} bytes",
+                self.client_id,
+                packet.will_message().len()
+            );
+            self.close();
+            return Ok(());
         }
 
         self.clean_session = packet.connect_flags().clean_session();
+
+        // If the Will Flag is set to 1 this indicates that, if the Connect request is accepted,
+        // a Will Message MUST be stored on the Server and associated with
+        // the Network Connection [MQTT-3.1.2-8].
+        self.will = WillMessage::from_connect(&packet)?;
         // TODO(Shaohua): Handle other connection flags.
 
         // Send the connect packet to listener.
@@ -236,39 +357,142 @@ impl Session {
         Ok(())
     }
 
-    async fn on_client_ping(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_client_ping_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
         let _packet = v3::PingRequestPacket::decode(&mut ba)?;
 
-        // Send ping resp packet to client.
+        // The Server MUST send a PINGRESP packet in response to a PINGREQ packet [MQTT-3.12.4-1].
         let ping_resp_packet = v3::PingResponsePacket::new();
         self.send(ping_resp_packet).await
     }
 
-    async fn on_client_publish(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_client_publish_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
         log::info!("Session::on_client_publish()");
         let mut ba = ByteArray::new(buf);
         let packet = v3::PublishPacket::decode(&mut ba)?;
 
-        // Check dup flag for QoS2.
-        if packet.qos() == QoS::ExactOnce && packet.dup() {
-            // If this packet_id is already handled, send PublishReceivedPacket again.
-            if self.pub_recv_packets.contains(&packet.packet_id()) {
+        if self.is_qos_not_supported(packet.qos()) {
+            log::warn!(
+                "session: {} published with unsupported qos: {:?}",
+                self.client_id,
+                packet.qos()
+            );
+            return self.send_disconnect(v5::ReasonCode::QoSNotSupported).await;
+        }
+
+        if packet.qos() == QoS::ExactOnce {
+            // In the QoS 2 delivery protocol, the receiver MUST respond with a PUBREC containing
+            // the Packet Identifier from the incoming PUBLISH Packet, having accepted ownership
+            // of the Application Message. Until it has received the corresponding PUBREL packet,
+            // the Receiver MUST acknowledge any subsequent PUBLISH packet with the same
+            // Packet Identifier by sending a PUBREC. It MUST NOT cause duplicate messages
+            // to be delivered to any onward recipients in this case [MQTT-4.3.3-2].
+            if let Some(state) = self.pub_recv_packets.state(packet.packet_id()) {
+                if !packet.dup() {
+                    log::warn!(
+                        "session: Got duplicated qos=2 packet without dup flag: {}",
+                        packet.packet_id()
+                    );
+                }
+                // PUBREC is sent once the first one is acknowledged by listener.
+                if state == PubRecvState::Received {
+                    return Ok(());
+                }
                 let ack_packet = v3::PublishReceivedPacket::new(packet.packet_id());
-                // TODO(Shaohua): Catch errors
                 return self.send(ack_packet).await;
             }
+
+            // Check inflight messages overflow.
+            if self.pub_recv_packets.len() >= self.config.maximum_inflight_messages() {
+                log::error!("session: Too many unacknowledged qos=2 messages, disconnect client!");
+                return self
+                    .send_disconnect(v5::ReasonCode::ReceiveMaximumExceeded)
+                    .await;
+            }
+            self.pub_recv_packets.receive(packet.packet_id());
+        }
+
+        if packet.qos() == QoS::AtLeastOnce {
+            self.pub_ack_packets.insert(packet.packet_id());
         }
 
         // Send the publish packet to listener.
-        self.sender
-            .send(SessionToListenerCmd::Publish(self.id, packet))
+        self.send_to_listener(SessionToListenerCmd::Publish(self.id, packet))
             .await
-            .map(drop)?;
-        Ok(())
     }
 
-    async fn on_client_publish_release(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_client_publish_ack_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v3::PublishAckPacket::decode(&mut ba)?;
+        self.on_client_outgoing_ack(packet.packet_id()).await
+    }
+
+    async fn on_client_publish_received_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v3::PublishReceivedPacket::decode(&mut ba)?;
+        let packet_id = packet.packet_id();
+        if !self.inflight_messages.mark_released(packet_id) {
+            log::warn!("session: Got PUBREC with unknown packet id: {}", packet_id);
+        }
+
+        // The sender MUST send a PUBREL packet when it receives a PUBREC packet
+        // from the receiver [MQTT-4.3.3-1].
+        let release_packet = v3::PublishReleasePacket::new(packet_id);
+        self.send(release_packet).await
+    }
+
+    async fn on_client_publish_complete_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v3::PublishCompletePacket::decode(&mut ba)?;
+        self.on_client_outgoing_ack(packet.packet_id()).await
+    }
+
+    /// Remove acknowledged packet from inflight list and send pending packets.
+    pub(super) async fn on_client_outgoing_ack(
+        &mut self,
+        packet_id: PacketId,
+    ) -> Result<(), Error> {
+        if self.inflight_messages.remove(packet_id).is_none() {
+            log::warn!(
+                "session: Got ack with unknown packet id: {}, client id: {}",
+                packet_id,
+                self.client_id
+            );
+            return Ok(());
+        }
+
+        self.send_queued_packets().await?;
+        self.disconnect_if_drained().await
+    }
+
+    /// Redirect client to another server once all inflight messages of a draining session
+    /// are acknowledged.
+    pub(super) async fn disconnect_if_drained(&mut self) -> Result<(), Error> {
+        if self.status != Status::Draining
+            || !self.inflight_messages.is_empty()
+            || self.inflight_messages.pending_len() > 0
+        {
+            return Ok(());
+        }
+        let Some(redirect) = self.redirect.take() else {
+            return Ok(());
+        };
+        log::info!(
+            "session: Session {} drained, redirect to {:?}",
+            self.id,
+            redirect.server_reference()
+        );
+        let mut packet = new_disconnect_packet(redirect.reason_code())?;
+        if let Some(server_reference) = redirect.server_reference() {
+            let server_reference = StringData::from(server_reference).map_err(EncodeError::from)?;
+            packet
+                .properties_mut()
+                .push(v5::Property::ServerReference(server_reference))?;
+        }
+        self.send_disconnect_packet(packet).await
+    }
+
+    async fn on_client_publish_release_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
         let packet = match v3::PublishReleasePacket::decode(&mut ba) {
             Ok(packet) => packet,
@@ -280,29 +504,36 @@ impl Session {
                     log::error!(
                         "session: Invalid bit flags for publish release packet, do disconnect!"
                     );
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => return Err(err.into()),
             },
         };
 
-        if self.pub_recv_packets.contains(&packet.packet_id()) {
-            // Remove packet_id from cache then send complete packet.
-            self.pub_recv_packets.remove(&packet.packet_id());
-            let ack_packet = v3::PublishCompletePacket::new(packet.packet_id());
-            self.send(ack_packet).await
-        } else {
-            log::error!(
-                "session: Failed to remove {} from pub_recv_packets",
-                packet.packet_id()
-            );
-            Ok(())
+        // The receiver MUST respond to a PUBREL packet by sending a PUBCOMP packet containing
+        // the same Packet Identifier as the PUBREL [MQTT-4.3.3-2].
+        let packet_id = packet.packet_id();
+        if self.pub_recv_packets.state(packet_id) == Some(PubRecvState::Received) {
+            // The sender sends PUBREL only after it has received PUBREC [MQTT-4.3.3-1],
+            // ignore it so that PUBREC is not sent after PUBCOMP.
+            log::warn!("session: Got PUBREL before PUBREC is sent: {}", packet_id);
+            return Ok(());
+        }
+        if !self.pub_recv_packets.release(packet_id) {
+            log::warn!("session: Got PUBREL with unknown packet id: {}", packet_id);
         }
+        let ack_packet = v3::PublishCompletePacket::new(packet_id);
+        self.send(ack_packet).await?;
+
+        // After it has sent a PUBCOMP, the receiver MUST treat any subsequent PUBLISH packet
+        // that contains that Packet Identifier as being a new publication [MQTT-4.3.3-2].
+        self.pub_recv_packets.complete(packet_id);
+        Ok(())
     }
 
-    async fn on_client_subscribe(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_client_subscribe_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
-        let packet = match v3::SubscribePacket::decode(&mut ba) {
+        let mut packet = match v3::SubscribePacket::decode(&mut ba) {
             Ok(packet) => packet,
             Err(err) => match err {
                 DecodeError::InvalidPacketFlags => {
@@ -310,7 +541,7 @@ impl Session {
                     // and MUST be set to 0,0,1 and 0 respectively. The Server MUST treat
                     // any other value as malformed and close the Network Connection [MQTT-3.8.1-1].
                     log::error!("session: Invalid bit flags for subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 DecodeError::EmptyTopicFilter => {
                     // The payload of a SUBSCRIBE packet MUST contain at least one Topic Filter / QoS pair.
@@ -320,7 +551,7 @@ impl Session {
                     // it MUST close the Network Connection on which it received that Control Packet
                     // which caused the protocol violation [MQTT-4.8.0-1].
                     log::error!("session: Empty topic filter in subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::ProtocolError).await;
                 }
                 DecodeError::InvalidQoS => {
                     // The upper 6 bits of the Requested QoS byte are not used in the current version of the protocol.
@@ -328,7 +559,7 @@ impl Session {
                     // and close the Network Connection if any of Reserved bits in the payload are non-zero,
                     // or QoS is not 0,1 or 2 [MQTT-3-8.3-4].
                     log::error!("session: Invalid QoS flag in subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => {
                     // TODO(Shaohua): Send disconnect when got error.
@@ -337,11 +568,20 @@ impl Session {
             },
         };
 
+        // Subscriptions are granted at most the maximum QoS supported by server.
+        for topic in packet.mut_topics() {
+            let qos = self.downgrade_subscribe_qos(topic.qos());
+            topic.set_qos(qos);
+        }
+        if let Some(ack_packet) = self.reject_wildcard_subscriptions(&mut packet) {
+            log::warn!("session: Wildcard subscriptions are not supported, reject them");
+            return self.send(ack_packet).await;
+        }
+
         // Send subscribe packet to listener, which will check ACL.
         let packet_id = packet.packet_id();
         if let Err(err) = self
-            .sender
-            .send(SessionToListenerCmd::Subscribe(self.id, packet))
+            .send_to_listener(SessionToListenerCmd::Subscribe(self.id, packet))
             .await
         {
             // Send subscribe ack (failed) to client.
@@ -355,7 +595,7 @@ impl Session {
         }
     }
 
-    async fn on_client_unsubscribe(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_client_unsubscribe_v3(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
         let packet = match v3::UnsubscribePacket::decode(&mut ba) {
             Ok(packet) => packet,
@@ -366,7 +606,7 @@ impl Session {
                     log::error!(
                         "session: Invalid bit flags for unsubscribe packet, do disconnect!"
                     );
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => {
                     // TODO(Shaohua): Send disconnect when got error.
@@ -376,8 +616,7 @@ impl Session {
         };
         let packet_id = packet.packet_id();
         if let Err(err) = self
-            .sender
-            .send(SessionToListenerCmd::Unsubscribe(self.id, packet))
+            .send_to_listener(SessionToListenerCmd::Unsubscribe(self.id, packet))
             .await
         {
             log::warn!("Failed to send unsubscribe command to server: {:?}", err);
@@ -388,7 +627,10 @@ impl Session {
     }
 
     /// Handle disconnect request from client.
-    async fn on_client_disconnect(&mut self, _: &[u8]) -> Result<(), Error> {
+    async fn on_client_disconnect_v3(&mut self, _: &[u8]) -> Result<(), Error> {
+        // On receipt of DISCONNECT the Server MUST discard any Will Message associated with
+        // the current connection without publishing it [MQTT-3.14.4-3].
+        self.will = None;
         self.status = Status::Disconnected;
         let cmd = SessionToListenerCmd::Disconnect(self.id);
         if let Err(err) = self.sender.send(cmd).await {
@@ -397,17 +639,31 @@ impl Session {
         Ok(())
     }
 
-    /// Send v3 disconnect packet to client and update status.
-    pub(super) async fn send_disconnect(&mut self) -> Result<(), Error> {
-        log::info!("send_disconnect()");
+    /// Send disconnect packet to client and close network connection.
+    ///
+    /// `reason_code` is only sent to v5 clients, as v3 server has no DISCONNECT packet.
+    pub(super) async fn send_disconnect(
+        &mut self,
+        reason_code: v5::ReasonCode,
+    ) -> Result<(), Error> {
+        log::info!("send_disconnect(), reason: {}", reason_code.description());
+        let packet = new_disconnect_packet(reason_code)?;
+        self.send_disconnect_packet(packet).await
+    }
+
+    async fn send_disconnect_packet(&mut self, packet: v5::DisconnectPacket) -> Result<(), Error> {
+        // The Server MUST NOT send a DISCONNECT until after it has sent a CONNACK
+        // with Reason Code of less than 0x80 [MQTT-3.14.0-1].
+        if self.protocol_level != ProtocolLevel::V5
+            || (self.status != Status::Connected && self.status != Status::Draining)
+        {
+            self.close();
+            return Ok(());
+        }
+
         self.status = Status::Disconnecting;
-        let ret = if self.protocol_level == ProtocolLevel::V5 {
-            let packet = v5::DisconnectPacket::new();
-            self.send(packet).await
-        } else {
-            let packet = v3::DisconnectPacket::new();
-            self.send(packet).await
-        };
+        let ret = self.send(packet).await;
+        self.close();
         if let Err(err) = ret {
             log::error!(
                 "session: Failed to send v5 disconnect packet, {}, err: {:?}",
@@ -416,7 +672,24 @@ impl Session {
             );
             return Err(err);
         }
-        self.status = Status::Disconnected;
         Ok(())
     }
+
+    /// Close network connection without sending DISCONNECT packet.
+    pub(super) fn close(&mut self) {
+        self.status = Status::Disconnected;
+    }
+}
+
+/// Create a v5 DISCONNECT packet, with reason string if `reason_code` is an error.
+fn new_disconnect_packet(reason_code: v5::ReasonCode) -> Result<v5::DisconnectPacket, Error> {
+    let mut packet = v5::DisconnectPacket::new();
+    packet.set_reason_code(reason_code);
+    if reason_code.is_error() {
+        let reason = StringData::from(reason_code.description()).map_err(EncodeError::from)?;
+        packet
+            .properties_mut()
+            .push(v5::Property::ReasonString(reason))?;
+    }
+    Ok(packet)
 }
@@ -4,7 +4,7 @@
 
 use codec::{utils::random_client_id, v5, ByteArray, DecodeError, DecodePacket, QoS};
 
-use super::{Session, Status};
+use super::{PubRecvState, Session, Status, WillMessage};
 use crate::commands::SessionToListenerCmd;
 use crate::error::{Error, ErrorKind};
 
@@ -57,6 +57,7 @@ impl Session {
             }
         }
         self.client_id = packet.client_id().to_string();
+        self.open_capture();
 
         if packet.keep_alive() > 0 {
             self.config.set_keep_alive(packet.keep_alive());
@@ -66,18 +67,60 @@ impl Session {
             let ack_packet =
                 v5::ConnectAckPacket::new(false, v5::ReasonCode::ClientIdentifierNotValid);
             self.send(ack_packet).await?;
-            return self.send_disconnect().await;
+            self.close();
+            return Ok(());
+        }
+
+        if self.is_will_payload_too_large(packet.will_message().len()) {
+            log::warn!(
+                "session: will payload of {} is too large, got {} bytes",
+                self.client_id,
+                packet.will_message().len()
+            );
+            let ack_packet = v5::ConnectAckPacket::new(false, v5::ReasonCode::PacketTooLarge);
+            self.send(ack_packet).await?;
+            self.close();
+            return Ok(());
+        }
+
+        // If the Server receives a CONNECT packet containing a Will QoS that exceeds
+        // its capabilities, it MUST reject the connection [MQTT-3.2.2-12].
+        if packet.will() && self.is_qos_not_supported(packet.will_qos()) {
+            log::warn!(
+                "session: will qos of {} is not supported: {:?}",
+                self.client_id,
+                packet.will_qos()
+            );
+            let ack_packet = v5::ConnectAckPacket::new(false, v5::ReasonCode::QoSNotSupported);
+            self.send(ack_packet).await?;
+            self.close();
+            return Ok(());
         }
 
         self.clean_session = packet.connect_flags().clean_session();
         // TODO(Shaohua): Handle other connection flags.
-        // TODO(Shaohua): Check will and will_qos is valid.
 
-        self.process_connect_properties(&packet);
+        // If the Will Flag is set to 1 this indicates that a Will Message MUST be stored
+        // on the Server and associated with the Session [MQTT-3.1.2-7].
+        self.will = WillMessage::from_connect_v5(&packet)?;
 
-        // TODO(Shaohua): Read auth-method and auth-data in properties.
+        if let Err(reason_code) = self.process_connect_properties(&packet) {
+            log::warn!(
+                "session: Invalid connect properties of {}, {}",
+                self.client_id,
+                reason_code.description()
+            );
+            let ack_packet = v5::ConnectAckPacket::new(false, reason_code);
+            self.send(ack_packet).await?;
+            self.close();
+            return Ok(());
+        }
 
-        // Send the connect packet to listener.
+        self.check_enhanced_auth(packet).await
+    }
+
+    /// Send the connect packet to listener, waiting for connect ack.
+    pub(super) async fn send_connect_v5(&mut self, packet: v5::ConnectPacket) -> Result<(), Error> {
         self.status = Status::Connecting;
         self.sender
             .send(SessionToListenerCmd::ConnectV5(self.id, packet))
@@ -90,7 +133,7 @@ impl Session {
         let mut ba = ByteArray::new(buf);
         let _packet = v5::PingRequestPacket::decode(&mut ba)?;
 
-        // Send ping resp packet to client.
+        // The Server MUST send a PINGRESP packet in response to a PINGREQ packet [MQTT-3.12.4-1].
         let ping_resp_packet = v5::PingResponsePacket::new();
         self.send(ping_resp_packet).await
     }
@@ -98,24 +141,103 @@ impl Session {
     pub(super) async fn on_client_publish_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
         log::info!("Session::on_client_publish_v5()");
         let mut ba = ByteArray::new(buf);
-        let packet = v5::PublishPacket::decode(&mut ba)?;
+        let mut packet = v5::PublishPacket::decode(&mut ba)?;
+        if let Err(reason_code) = self.resolve_topic_alias(&mut packet) {
+            return self.send_disconnect(reason_code).await;
+        }
+        Self::remove_broker_properties(&mut packet);
+
+        // If the Server receives a PUBLISH packet with a QoS greater than the Maximum QoS
+        // it specified, it uses DISCONNECT with Reason Code 0x9B (QoS not supported).
+        if self.is_qos_not_supported(packet.qos()) {
+            log::warn!(
+                "session: {} published with unsupported qos: {:?}",
+                self.client_id,
+                packet.qos()
+            );
+            return self.send_disconnect(v5::ReasonCode::QoSNotSupported).await;
+        }
+
+        if self.is_payload_format_invalid(&packet) {
+            return self.reject_invalid_payload_format(&packet).await;
+        }
 
-        // Check dup flag for QoS2.
-        if packet.qos() == QoS::ExactOnce && packet.dup() {
-            // If this packet_id is already handled, send PublishReceivedPacket again.
-            if self.pub_recv_packets.contains(&packet.packet_id()) {
+        if packet.qos() == QoS::ExactOnce {
+            // In the QoS 2 delivery protocol, the receiver MUST respond with a PUBREC containing
+            // the Packet Identifier from the incoming PUBLISH Packet, having accepted ownership
+            // of the Application Message. Until it has received the corresponding PUBREL packet,
+            // the Receiver MUST acknowledge any subsequent PUBLISH packet with the same
+            // Packet Identifier by sending a PUBREC. It MUST NOT cause duplicate messages
+            // to be delivered to any onward recipients in this case [MQTT-4.3.3-2].
+            if let Some(state) = self.pub_recv_packets.state(packet.packet_id()) {
+                if !packet.dup() {
+                    log::warn!(
+                        "session: Got duplicated qos=2 packet without dup flag: {}",
+                        packet.packet_id()
+                    );
+                }
+                // PUBREC is sent once the first one is acknowledged by listener.
+                if state == PubRecvState::Received {
+                    return Ok(());
+                }
                 let ack_packet = v5::PublishReceivedPacket::new(packet.packet_id());
-                // TODO(Shaohua): Catch errors
                 return self.send(ack_packet).await;
             }
         }
 
+        // If the Server receives more than Receive Maximum QoS 1 and QoS 2 PUBLISH packets
+        // where it has not sent a PUBACK or PUBCOMP in response, it uses a DISCONNECT packet
+        // with Reason Code 0x93 (Receive Maximum exceeded).
+        // Re-sent QoS 1 packets are not counted again.
+        if packet.qos() != QoS::AtMostOnce
+            && !self.pub_ack_packets.contains(&packet.packet_id())
+            && self.is_receive_maximum_reached()
+        {
+            log::error!("session: Too many unacknowledged qos>0 messages, disconnect client!");
+            return self
+                .send_disconnect(v5::ReasonCode::ReceiveMaximumExceeded)
+                .await;
+        }
+
+        if packet.qos() == QoS::ExactOnce {
+            self.pub_recv_packets.receive(packet.packet_id());
+        }
+        if packet.qos() == QoS::AtLeastOnce {
+            self.pub_ack_packets.insert(packet.packet_id());
+        }
+
         // Send the publish packet to listener.
-        self.sender
-            .send(SessionToListenerCmd::PublishV5(self.id, packet))
+        self.send_to_listener(SessionToListenerCmd::PublishV5(self.id, packet))
             .await
-            .map(drop)?;
-        Ok(())
+    }
+
+    pub(super) async fn on_client_publish_ack_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v5::PublishAckPacket::decode(&mut ba)?;
+        self.on_client_outgoing_ack(packet.packet_id()).await
+    }
+
+    pub(super) async fn on_client_publish_received_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v5::PublishReceivedPacket::decode(&mut ba)?;
+        let packet_id = packet.packet_id();
+
+        // Client rejects this message, no PUBREL is expected.
+        if packet.reason_code().is_error() {
+            log::warn!(
+                "session: Publish packet {} rejected by client, {}",
+                packet_id,
+                packet.reason_code().description()
+            );
+            return self.on_client_outgoing_ack(packet_id).await;
+        }
+
+        let mut release_packet = v5::PublishReleasePacket::new(packet_id);
+        if !self.inflight_messages.mark_released(packet_id) {
+            log::warn!("session: Got PUBREC with unknown packet id: {}", packet_id);
+            release_packet.set_reason_code(v5::ReasonCode::PacketIdentifierNotFound);
+        }
+        self.send(release_packet).await
     }
 
     pub(super) async fn on_client_publish_release_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
@@ -128,45 +250,53 @@ impl Session {
                     log::error!(
                         "session: Invalid bit flags for publish release packet, do disconnect!"
                     );
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => return Err(err.into()),
             },
         };
 
-        if self.pub_recv_packets.contains(&packet.packet_id()) {
-            // Remove packet_id from cache then send complete packet.
-            self.pub_recv_packets.remove(&packet.packet_id());
-            let ack_packet = v5::PublishCompletePacket::new(packet.packet_id());
-            self.send(ack_packet).await
-        } else {
-            log::error!(
-                "session: Failed to remove {} from pub_recv_packets",
-                packet.packet_id()
-            );
-            Ok(())
+        let packet_id = packet.packet_id();
+        if self.pub_recv_packets.state(packet_id) == Some(PubRecvState::Received) {
+            // PUBREL is ignored until PUBREC is sent, see `on_client_publish_release_v3()`.
+            log::warn!("session: Got PUBREL before PUBREC is sent: {}", packet_id);
+            return Ok(());
         }
+        let mut ack_packet = v5::PublishCompletePacket::new(packet_id);
+        if !self.pub_recv_packets.release(packet_id) {
+            log::warn!("session: Got PUBREL with unknown packet id: {}", packet_id);
+            ack_packet.set_reason_code(v5::ReasonCode::PacketIdentifierNotFound);
+        }
+        self.send(ack_packet).await?;
+        self.pub_recv_packets.complete(packet_id);
+        Ok(())
+    }
+
+    pub(super) async fn on_client_publish_complete_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v5::PublishCompletePacket::decode(&mut ba)?;
+        self.on_client_outgoing_ack(packet.packet_id()).await
     }
 
     pub(super) async fn on_client_subscribe_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
         let mut ba = ByteArray::new(buf);
-        let packet = match v5::SubscribePacket::decode(&mut ba) {
+        let mut packet = match v5::SubscribePacket::decode(&mut ba) {
             Ok(packet) => packet,
             Err(err) => match err {
                 DecodeError::InvalidPacketFlags => {
                     // TODO(Shaohua): Add comments
                     log::error!("session: Invalid bit flags for subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 DecodeError::EmptyTopicFilter => {
                     // TODO(Shaohua): Add comments
                     log::error!("session: Empty topic filter in subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::ProtocolError).await;
                 }
                 DecodeError::InvalidQoS => {
                     // TODO(Shaohua): Add comments
                     log::error!("session: Invalid QoS flag in subscribe packet, do disconnect!");
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => {
                     // TODO(Shaohua): Send disconnect when got error.
@@ -175,11 +305,22 @@ impl Session {
             },
         };
 
+        // Subscriptions are granted at most the maximum QoS supported by server.
+        for topic in packet.mut_topics() {
+            let qos = self.downgrade_subscribe_qos(topic.qos());
+            topic.set_qos(qos);
+        }
+        // If the Server does not support Wildcard Subscriptions, it responds with
+        // SUBACK of Reason Code 0xA2 (Wildcard Subscriptions not supported).
+        if let Some(ack_packet) = self.reject_wildcard_subscriptions_v5(&mut packet) {
+            log::warn!("session: Wildcard subscriptions are not supported, reject them");
+            return self.send(ack_packet).await;
+        }
+
         // Send subscribe packet to listener, which will check ACL.
         let packet_id = packet.packet_id();
         if let Err(err) = self
-            .sender
-            .send(SessionToListenerCmd::SubscribeV5(self.id, packet))
+            .send_to_listener(SessionToListenerCmd::SubscribeV5(self.id, packet))
             .await
         {
             // Send subscribe ack (failed) to client.
@@ -203,7 +344,7 @@ impl Session {
                     log::error!(
                         "session: Invalid bit flags for unsubscribe packet, do disconnect!"
                     );
-                    return self.send_disconnect().await;
+                    return self.send_disconnect(v5::ReasonCode::MalformedPacket).await;
                 }
                 _ => {
                     // TODO(Shaohua): Send disconnect when got error.
@@ -213,8 +354,7 @@ impl Session {
         };
         let packet_id = packet.packet_id();
         if let Err(err) = self
-            .sender
-            .send(SessionToListenerCmd::UnsubscribeV5(self.id, packet))
+            .send_to_listener(SessionToListenerCmd::UnsubscribeV5(self.id, packet))
             .await
         {
             log::warn!("Failed to send unsubscribe command to server: {:?}", err);
@@ -225,7 +365,17 @@ impl Session {
         self.send(unsubscribe_ack_packet).await
     }
 
-    pub(super) async fn on_client_disconnect_v5(&mut self, _: &[u8]) -> Result<(), Error> {
+    pub(super) async fn on_client_disconnect_v5(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut ba = ByteArray::new(buf);
+        let packet = v5::DisconnectPacket::decode(&mut ba)?;
+        self.process_disconnect_properties(&packet);
+
+        // The Will Message MUST be removed from the stored Session State in the Server once
+        // it has been published or the Server has received a DISCONNECT packet with
+        // a Reason Code of 0x00 (Normal disconnection) from the Client [MQTT-3.1.2-10].
+        if packet.reason_code() != v5::ReasonCode::DisconnectWithWillMessage {
+            self.will = None;
+        }
         self.status = Status::Disconnected;
         let cmd = SessionToListenerCmd::DisconnectV5(self.id);
         if let Err(err) = self.sender.send(cmd).await {
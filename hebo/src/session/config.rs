@@ -2,22 +2,69 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
+use codec::QoS;
+use std::sync::Arc;
 use std::time::Duration;
 
+use crate::auth::AuthMethod;
+use crate::config::{Capture, General, QueueFullPolicy, RateLimitPolicy};
+
+/// Maximum size of an mqtt packet, including fixed header.
+pub const MAXIMUM_PACKET_SIZE: usize = 268_435_455 + 5;
+
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone)]
 pub struct SessionConfig {
     keep_alive: Duration,
     connect_timeout: Duration,
 
+    /// Receive Maximum of server, limits `QoS` 1 and `QoS` 2 messages sent by client
+    /// but not acknowledged yet.
     maximum_inflight_messages: usize,
+    /// Limits `QoS` 1 and `QoS` 2 messages sent to client but not acknowledged yet,
+    /// updated with Receive Maximum in connect packet. 0 means no limit.
+    inflight_window: usize,
+    /// Maximum number of messages waiting for a free slot in inflight window.
+    max_queued_messages: usize,
+    /// What to do with new messages when the queue is full.
+    queue_full_policy: QueueFullPolicy,
+    /// Maximum packet size accepted by client, in connect packet, 0 means no limit.
     maximum_packet_size: usize,
+    /// Highest topic alias accepted by client, in connect packet.
     maximum_topic_alias: u16,
+    /// Highest topic alias accepted from client, in connect ack packet.
+    topic_alias_maximum: u16,
+    read_buffer_cap: usize,
+    /// Maximum will payload size in connect packet, 0 means no limit.
+    max_will_payload_size: usize,
+    /// Maximum `QoS` of publish packets and subscriptions accepted from client.
+    maximum_qos: QoS,
+    /// Accept topic filters with wildcard characters from client.
+    wildcard_subscription_available: bool,
+    /// Validate payload of publish packets declared as UTF-8 string by client.
+    validate_payload_format: bool,
+    /// Response information sent in connect ack packet, empty if disabled.
+    response_information: String,
+    /// Client requests response information in connect packet.
+    request_response_information: bool,
+    /// Enhanced auth methods of v5 clients.
+    auth_methods: Vec<Arc<dyn AuthMethod>>,
+    /// Delay before re-sending unacknowledged messages to reconnected client.
+    retransmit_delay: Duration,
+    /// Maximum publish packets per second from client, 0 means no limit.
+    max_message_rate: u32,
+    /// Maximum bytes of publish packets per second from client, 0 means no limit.
+    max_byte_rate: u32,
+    rate_limit_policy: RateLimitPolicy,
 
     allow_empty_client_id: bool,
 
+    capture: Option<Capture>,
+
     out_packet_count: usize,
     last_packet_id: u16,
-    session_expiry_interval: Duration,
+    /// Keep session state after client is disconnected, None means never expire.
+    session_expiry_interval: Option<Duration>,
 }
 
 impl Default for SessionConfig {
@@ -34,22 +81,40 @@ impl SessionConfig {
             connect_timeout: Duration::from_secs(30),
 
             maximum_inflight_messages: 10,
-            maximum_packet_size: 10,
+            inflight_window: 20,
+            max_queued_messages: General::default_max_queued_messages(),
+            queue_full_policy: QueueFullPolicy::DropOldest,
+            maximum_packet_size: 0,
             maximum_topic_alias: 10,
+            topic_alias_maximum: 10,
+            read_buffer_cap: MAXIMUM_PACKET_SIZE,
+            max_will_payload_size: 0,
+            maximum_qos: QoS::ExactOnce,
+            wildcard_subscription_available: true,
+            validate_payload_format: false,
+            response_information: String::new(),
+            request_response_information: false,
+            auth_methods: Vec::new(),
+            retransmit_delay: Duration::ZERO,
+            max_message_rate: 0,
+            max_byte_rate: 0,
+            rate_limit_policy: RateLimitPolicy::Throttle,
 
             allow_empty_client_id: false,
 
+            capture: None,
+
             out_packet_count: 0,
             last_packet_id: 0,
-            session_expiry_interval: Duration::from_secs(180),
+            // If the Session Expiry Interval is absent the value 0 is used. If it is set to 0,
+            // or is absent, the Session ends when the Network Connection is closed.
+            session_expiry_interval: Some(Duration::ZERO),
         }
     }
 
-    #[allow(clippy::cast_possible_truncation)]
-    #[allow(clippy::cast_sign_loss)]
+    /// Set keep alive in seconds, session is disconnected after 1.5 times of it.
     pub fn set_keep_alive(&mut self, keep_alive: u16) -> &mut Self {
-        let keep_alive = (f64::from(keep_alive) * 1.5).round() as u64;
-        self.keep_alive = Duration::from_secs(keep_alive);
+        self.keep_alive = Duration::from_millis(u64::from(keep_alive) * 1500);
         self
     }
 
@@ -81,6 +146,50 @@ impl SessionConfig {
         self.maximum_inflight_messages
     }
 
+    /// Set maximum number of outgoing `QoS` 1 and `QoS` 2 messages which are
+    /// sent to client but not acknowledged yet.
+    ///
+    /// Set to 0 to disable this limitation.
+    pub fn set_inflight_window(&mut self, inflight_window: u16) -> &mut Self {
+        self.inflight_window = inflight_window as usize;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn inflight_window(&self) -> usize {
+        self.inflight_window
+    }
+
+    /// Set maximum number of outgoing `QoS` 1 and `QoS` 2 messages queued when
+    /// inflight window is full, and what to do with new messages then.
+    ///
+    /// Set `max_queued_messages` to 0 to drop messages instead of queueing them.
+    pub fn set_queue_limit(
+        &mut self,
+        max_queued_messages: usize,
+        queue_full_policy: QueueFullPolicy,
+    ) -> &mut Self {
+        self.max_queued_messages = max_queued_messages;
+        self.queue_full_policy = queue_full_policy;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_queued_messages(&self) -> usize {
+        self.max_queued_messages
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn queue_full_policy(&self) -> QueueFullPolicy {
+        self.queue_full_policy
+    }
+
+    /// Set maximum packet size accepted by client.
+    ///
+    /// Packets larger than this value are not sent to client.
     pub fn set_maximum_packet_size(&mut self, maximum_packet_size: u32) -> &mut Self {
         self.maximum_packet_size = maximum_packet_size as usize;
         self
@@ -92,6 +201,189 @@ impl SessionConfig {
         self.maximum_packet_size
     }
 
+    /// Set maximum number of bytes buffered while reading a packet from client.
+    ///
+    /// Client is disconnected if a packet is larger than this value.
+    /// Set to 0 to use the maximum size of mqtt packet.
+    pub fn set_read_buffer_cap(&mut self, read_buffer_cap: usize) -> &mut Self {
+        self.read_buffer_cap = if read_buffer_cap == 0 {
+            MAXIMUM_PACKET_SIZE
+        } else {
+            read_buffer_cap
+        };
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn read_buffer_cap(&self) -> usize {
+        self.read_buffer_cap
+    }
+
+    /// Set maximum size of will payload in connect packet.
+    ///
+    /// Connect packet is rejected if will payload is larger than this value.
+    /// Set to 0 to disable this limitation.
+    pub fn set_max_will_payload_size(&mut self, max_will_payload_size: usize) -> &mut Self {
+        self.max_will_payload_size = max_will_payload_size;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_will_payload_size(&self) -> usize {
+        self.max_will_payload_size
+    }
+
+    /// Set maximum `QoS` supported by server.
+    ///
+    /// Subscriptions are granted at most this `QoS`, and client is disconnected
+    /// if it publishes at a higher `QoS`.
+    pub fn set_maximum_qos(&mut self, maximum_qos: QoS) -> &mut Self {
+        self.maximum_qos = maximum_qos;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn maximum_qos(&self) -> QoS {
+        self.maximum_qos
+    }
+
+    /// Set whether topic filters with wildcard characters are accepted.
+    ///
+    /// If disabled, wildcard subscriptions are rejected in subscribe ack packet.
+    pub fn set_wildcard_subscription_available(&mut self, available: bool) -> &mut Self {
+        self.wildcard_subscription_available = available;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn wildcard_subscription_available(&self) -> bool {
+        self.wildcard_subscription_available
+    }
+
+    /// Set whether payload of v5 publish packets with Payload Format Indicator
+    /// property is checked to be valid UTF-8 string.
+    pub fn set_validate_payload_format(&mut self, validate: bool) -> &mut Self {
+        self.validate_payload_format = validate;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn validate_payload_format(&self) -> bool {
+        self.validate_payload_format
+    }
+
+    /// Set response information, `%c` in it is replaced with client id.
+    pub fn set_response_information(&mut self, response_information: &str) -> &mut Self {
+        self.response_information = response_information.to_string();
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn response_information(&self) -> &str {
+        &self.response_information
+    }
+
+    /// Set whether client requests response information, in connect packet.
+    pub fn set_request_response_information(&mut self, request: bool) -> &mut Self {
+        self.request_response_information = request;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn request_response_information(&self) -> bool {
+        self.request_response_information
+    }
+
+    /// Set enhanced auth methods of v5 clients.
+    pub fn set_auth_methods(&mut self, auth_methods: &[Arc<dyn AuthMethod>]) -> &mut Self {
+        self.auth_methods = auth_methods.to_vec();
+        self
+    }
+
+    /// Get enhanced auth method with `name`.
+    #[must_use]
+    pub fn auth_method(&self, name: &str) -> Option<&dyn AuthMethod> {
+        self.auth_methods
+            .iter()
+            .find(|method| method.name() == name)
+            .map(AsRef::as_ref)
+    }
+
+    /// Set delay in milliseconds before re-sending unacknowledged messages
+    /// when a persistent session is resumed.
+    pub fn set_retransmit_delay(&mut self, retransmit_delay: u32) -> &mut Self {
+        self.retransmit_delay = Duration::from_millis(u64::from(retransmit_delay));
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn retransmit_delay(&self) -> Duration {
+        self.retransmit_delay
+    }
+
+    /// Set maximum number of publish packets per second received from client.
+    ///
+    /// Set to 0 to disable this limitation.
+    pub fn set_max_message_rate(&mut self, max_message_rate: u32) -> &mut Self {
+        self.max_message_rate = max_message_rate;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_message_rate(&self) -> u32 {
+        self.max_message_rate
+    }
+
+    /// Set maximum bytes of publish packets per second received from client.
+    ///
+    /// Set to 0 to disable this limitation.
+    pub fn set_max_byte_rate(&mut self, max_byte_rate: u32) -> &mut Self {
+        self.max_byte_rate = max_byte_rate;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn max_byte_rate(&self) -> u32 {
+        self.max_byte_rate
+    }
+
+    pub fn set_rate_limit_policy(&mut self, rate_limit_policy: RateLimitPolicy) -> &mut Self {
+        self.rate_limit_policy = rate_limit_policy;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn rate_limit_policy(&self) -> RateLimitPolicy {
+        self.rate_limit_policy
+    }
+
+    /// Capture packets of clients in `capture` list, disabled if `capture` is empty.
+    pub fn set_capture(&mut self, capture: &Capture) -> &mut Self {
+        self.capture = if capture.is_disabled() {
+            None
+        } else {
+            Some(capture.clone())
+        };
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
+    }
+
     pub fn set_maximum_topic_alias(&mut self, maximum_topic_alias: u16) -> &mut Self {
         self.maximum_topic_alias = maximum_topic_alias;
         self
@@ -103,6 +395,17 @@ impl SessionConfig {
         self.maximum_topic_alias
     }
 
+    pub fn set_topic_alias_maximum(&mut self, topic_alias_maximum: u16) -> &mut Self {
+        self.topic_alias_maximum = topic_alias_maximum;
+        self
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn topic_alias_maximum(&self) -> u16 {
+        self.topic_alias_maximum
+    }
+
     pub fn set_allow_empty_client_id(&mut self, allow_empty_client_id: bool) -> &mut Self {
         self.allow_empty_client_id = allow_empty_client_id;
         self
@@ -136,14 +439,19 @@ impl SessionConfig {
         self.last_packet_id
     }
 
+    /// Set session expiry interval in seconds, `0xFFFFFFFF` means never expire.
     pub fn set_session_expiry_interval(&mut self, session_expiry_interval: u32) -> &mut Self {
-        self.session_expiry_interval = Duration::from_secs(u64::from(session_expiry_interval));
+        self.session_expiry_interval = if session_expiry_interval == u32::MAX {
+            None
+        } else {
+            Some(Duration::from_secs(u64::from(session_expiry_interval)))
+        };
         self
     }
 
     #[inline]
     #[must_use]
-    pub const fn session_expiry_interval(&self) -> Duration {
+    pub const fn session_expiry_interval(&self) -> Option<Duration> {
         self.session_expiry_interval
     }
 }
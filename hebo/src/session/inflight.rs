@@ -0,0 +1,715 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Track outgoing `QoS` 1 and `QoS` 2 messages until they are acknowledged by client.
+
+use codec::{
+    v3, v5, ByteArray, DecodeError, DecodePacket, EncodeError, EncodePacket, PacketId,
+    PacketIdPool, QoS, U32Data,
+};
+use rustc_hash::FxHashSet;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::QueueFullPolicy;
+
+/// Publish packet sent from server to client.
+#[derive(Debug, Clone)]
+pub enum OutgoingPacket {
+    V3(v3::PublishPacket),
+    V5(v5::PublishPacket),
+}
+
+impl OutgoingPacket {
+    #[must_use]
+    pub const fn qos(&self) -> QoS {
+        match self {
+            Self::V3(packet) => packet.qos(),
+            Self::V5(packet) => packet.qos(),
+        }
+    }
+
+    #[must_use]
+    pub const fn packet_id(&self) -> PacketId {
+        match self {
+            Self::V3(packet) => packet.packet_id(),
+            Self::V5(packet) => packet.packet_id(),
+        }
+    }
+
+    fn set_packet_id(&mut self, packet_id: PacketId) {
+        match self {
+            Self::V3(packet) => {
+                packet.set_packet_id(packet_id);
+            }
+            Self::V5(packet) => {
+                packet.set_packet_id(packet_id);
+            }
+        }
+    }
+
+    /// Subtract `elapsed` from Message Expiry Interval of v5 packet.
+    ///
+    /// Returns false if the message has expired and shall not be delivered.
+    pub fn update_message_expiry(&mut self, elapsed: Duration) -> bool {
+        match self {
+            Self::V3(_packet) => true,
+            Self::V5(packet) => update_message_expiry(packet, elapsed),
+        }
+    }
+
+    /// Update `dup` flag before re-delivering this packet.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if `dup` is set in `QoS` 0 packet.
+    pub fn set_dup(&mut self, dup: bool) -> Result<(), EncodeError> {
+        match self {
+            Self::V3(packet) => packet.set_dup(dup).map(drop),
+            Self::V5(packet) => packet.set_dup(dup).map(drop),
+        }
+    }
+}
+
+/// Get Message Expiry Interval property of `packet` in seconds.
+fn message_expiry_interval(packet: &v5::PublishPacket) -> Option<u32> {
+    packet
+        .properties()
+        .props()
+        .iter()
+        .find_map(|property| match property {
+            v5::Property::MessageExpiryInterval(interval) => Some(interval.value()),
+            _ => None,
+        })
+}
+
+/// Returns true if Message Expiry Interval of `packet` is less than `elapsed`.
+///
+/// Messages without this property never expire.
+fn is_message_expired(packet: &v5::PublishPacket, elapsed: Duration) -> bool {
+    message_expiry_interval(packet).map_or(false, |interval| {
+        elapsed >= Duration::from_secs(u64::from(interval))
+    })
+}
+
+/// Set Message Expiry Interval of `packet` to the received value minus `elapsed`,
+/// the time it has been waiting in server [MQTT-3.3.2-6].
+///
+/// Returns false if the interval has passed, the message shall be discarded then [MQTT-3.3.2-5].
+pub fn update_message_expiry(packet: &mut v5::PublishPacket, elapsed: Duration) -> bool {
+    let Some(interval) = message_expiry_interval(packet) else {
+        return true;
+    };
+    if is_message_expired(packet, elapsed) {
+        return false;
+    }
+    // Elapsed seconds are rounded down, so that remaining interval is never 0.
+    let remaining = u64::from(interval) - elapsed.as_secs();
+    let remaining = u32::try_from(remaining).unwrap_or(interval);
+    let properties = packet.properties_mut();
+    properties.retain(|property| !matches!(property, v5::Property::MessageExpiryInterval(_)));
+    if let Err(err) = properties.push(v5::Property::MessageExpiryInterval(U32Data::new(remaining)))
+    {
+        log::error!("Failed to update message expiry interval, err: {:?}", err);
+    }
+    true
+}
+
+/// Message waiting in pending queue, with the time it is queued.
+#[derive(Debug, Clone)]
+struct PendingPacket {
+    packet: OutgoingPacket,
+    queued_at: Instant,
+}
+
+impl PendingPacket {
+    fn new(packet: OutgoingPacket) -> Self {
+        Self {
+            packet,
+            queued_at: Instant::now(),
+        }
+    }
+
+    /// Returns packet to be sent, or None if it has expired.
+    fn take(mut self) -> Option<OutgoingPacket> {
+        self.packet
+            .update_message_expiry(self.queued_at.elapsed())
+            .then_some(self.packet)
+    }
+
+    fn is_expired(&self) -> bool {
+        match &self.packet {
+            OutgoingPacket::V3(_packet) => false,
+            OutgoingPacket::V5(packet) => is_message_expired(packet, self.queued_at.elapsed()),
+        }
+    }
+}
+
+/// Tag of protocol version in serialized outgoing packets.
+const OUTGOING_PACKET_V3: u8 = 3;
+const OUTGOING_PACKET_V5: u8 = 5;
+
+impl EncodePacket for OutgoingPacket {
+    fn encode(&self, v: &mut Vec<u8>) -> Result<usize, EncodeError> {
+        let bytes = match self {
+            Self::V3(packet) => {
+                v.push(OUTGOING_PACKET_V3);
+                packet.encode(v)?
+            }
+            Self::V5(packet) => {
+                v.push(OUTGOING_PACKET_V5);
+                packet.encode(v)?
+            }
+        };
+        Ok(1 + bytes)
+    }
+}
+
+impl DecodePacket for OutgoingPacket {
+    fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
+        match ba.read_byte()? {
+            OUTGOING_PACKET_V3 => Ok(Self::V3(v3::PublishPacket::decode(ba)?)),
+            OUTGOING_PACKET_V5 => Ok(Self::V5(v5::PublishPacket::decode(ba)?)),
+            _ => Err(DecodeError::InvalidProtocolLevel),
+        }
+    }
+}
+
+/// Outgoing messages which have been sent to client but not acknowledged yet.
+///
+/// At most `window` messages are inflight at the same time, others are queued
+/// in `pending` list and will be sent when an inflight message is acknowledged.
+/// Length of `pending` list is limited by `queue_limit` if it is set.
+///
+/// After client reconnects, inflight messages are re-sent within the same window,
+/// and no pending message is sent until all of them are re-sent.
+///
+/// Inflight and pending messages, with their packet ids, are serialized when session
+/// state is saved to disk. Inflight window is not saved, it is reset by the new session.
+#[derive(Debug, Default, Clone)]
+pub struct InflightMessages {
+    window: usize,
+    /// Packet ids of inflight messages.
+    packet_ids: PacketIdPool,
+
+    /// Messages in sending order.
+    messages: VecDeque<OutgoingPacket>,
+    pending: VecDeque<PendingPacket>,
+    /// Maximum length of pending list and what to do when it is full, None means no limit.
+    queue_limit: Option<(usize, QueueFullPolicy)>,
+    /// Number of pending messages dropped since last `take_dropped()`.
+    dropped: usize,
+    /// Packet ids of inflight messages waiting to be re-sent, in sending order.
+    retransmit: VecDeque<PacketId>,
+
+    /// Packet ids of `QoS` 2 messages acknowledged with PUBREC, PUBREL is re-sent
+    /// for them instead of PUBLISH.
+    released: FxHashSet<PacketId>,
+}
+
+impl InflightMessages {
+    /// Create a new inflight message list.
+    ///
+    /// `window` of 0 means unlimited.
+    #[must_use]
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            packet_ids: PacketIdPool::new(),
+            messages: VecDeque::new(),
+            pending: VecDeque::new(),
+            queue_limit: None,
+            dropped: 0,
+            retransmit: VecDeque::new(),
+            released: FxHashSet::default(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window;
+    }
+
+    #[must_use]
+    pub const fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Limit pending list to `max_len` messages, new messages are queued with `policy`
+    /// when it is full.
+    pub fn set_queue_limit(&mut self, max_len: usize, policy: QueueFullPolicy) {
+        self.queue_limit = Some((max_len, policy));
+    }
+
+    /// Get number of pending messages dropped as pending list is full, and reset the counter.
+    pub fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Get number of inflight messages.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Get number of messages waiting for a free inflight slot.
+    #[must_use]
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Get number of inflight messages waiting to be re-sent.
+    #[must_use]
+    pub fn retransmit_len(&self) -> usize {
+        self.retransmit.len()
+    }
+
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        (self.window > 0 && self.messages.len() >= self.window) || self.packet_ids.is_full()
+    }
+
+    #[must_use]
+    pub fn contains(&self, packet_id: PacketId) -> bool {
+        self.packet_ids.contains(packet_id)
+    }
+
+    /// Append a new message.
+    ///
+    /// Returns the packet with a new packet id if it can be sent to client right now,
+    /// or else it is kept in pending queue, within `queue_limit`.
+    pub fn push(&mut self, packet: OutgoingPacket) -> Option<OutgoingPacket> {
+        if self.is_full() || !self.retransmit.is_empty() {
+            self.queue(packet);
+            return None;
+        }
+        self.start(packet).map_err(|packet| self.queue(packet)).ok()
+    }
+
+    /// Append `packet` to pending queue, within `queue_limit` if it is set.
+    fn queue(&mut self, packet: OutgoingPacket) {
+        match self.queue_limit {
+            Some((max_len, policy)) => {
+                self.enqueue(packet, max_len, policy);
+            }
+            None => self.push_pending(packet),
+        }
+    }
+
+    /// Append `packet` to pending queue which holds at most `max_len` messages.
+    ///
+    /// When the queue is full, expired messages are removed first, then the oldest one
+    /// is evicted or `packet` is dropped according to `policy`.
+    ///
+    /// Returns false if `packet` is dropped.
+    pub fn enqueue(
+        &mut self,
+        packet: OutgoingPacket,
+        max_len: usize,
+        policy: QueueFullPolicy,
+    ) -> bool {
+        if max_len == 0 {
+            self.dropped = self.dropped.saturating_add(1);
+            return false;
+        }
+        if self.pending.len() >= max_len {
+            self.remove_expired_pending();
+        }
+        while self.pending.len() >= max_len {
+            match policy {
+                QueueFullPolicy::DropOldest => {
+                    self.evict_pending();
+                    self.dropped = self.dropped.saturating_add(1);
+                }
+                QueueFullPolicy::Reject => {
+                    self.dropped = self.dropped.saturating_add(1);
+                    return false;
+                }
+            }
+        }
+        self.push_pending(packet);
+        true
+    }
+
+    /// Assign a free packet id to `packet` and append it to inflight list.
+    ///
+    /// Packet is returned back as error if no packet id is available.
+    fn start(&mut self, mut packet: OutgoingPacket) -> Result<OutgoingPacket, OutgoingPacket> {
+        let Some(packet_id) = self.packet_ids.alloc() else {
+            return Err(packet);
+        };
+        packet.set_packet_id(packet_id);
+        self.messages.push_back(packet.clone());
+        Ok(packet)
+    }
+
+    /// Remove message with `packet_id` when it is acknowledged by client.
+    ///
+    /// Returns the acknowledged packet if found.
+    pub fn remove(&mut self, packet_id: PacketId) -> Option<OutgoingPacket> {
+        let index = self
+            .messages
+            .iter()
+            .position(|packet| packet.packet_id() == packet_id)?;
+        self.packet_ids.release(packet_id);
+        self.retransmit.retain(|id| *id != packet_id);
+        self.released.remove(&packet_id);
+        self.messages.remove(index)
+    }
+
+    /// Mark `QoS` 2 message with `packet_id` as released, when PUBREC is received.
+    ///
+    /// Returns false if no such message is inflight.
+    pub fn mark_released(&mut self, packet_id: PacketId) -> bool {
+        if !self.contains(packet_id) {
+            return false;
+        }
+        self.released.insert(packet_id);
+        true
+    }
+
+    /// Returns true if PUBREL has been sent for message with `packet_id`.
+    #[must_use]
+    pub fn is_released(&self, packet_id: PacketId) -> bool {
+        self.released.contains(&packet_id)
+    }
+
+    /// Append a message to pending queue, it is sent after previous messages.
+    pub fn push_pending(&mut self, packet: OutgoingPacket) {
+        self.pending.push_back(PendingPacket::new(packet));
+    }
+
+    /// Remove the oldest message in pending queue.
+    pub fn evict_pending(&mut self) -> Option<OutgoingPacket> {
+        self.pending.pop_front().map(|pending| pending.packet)
+    }
+
+    /// Remove pending messages whose Message Expiry Interval has passed.
+    ///
+    /// Returns number of removed messages.
+    pub fn remove_expired_pending(&mut self) -> usize {
+        let len = self.pending.len();
+        self.pending.retain(|pending| !pending.is_expired());
+        len - self.pending.len()
+    }
+
+    /// Move pending messages to inflight list until the window is full.
+    ///
+    /// Expired messages are discarded, and Message Expiry Interval of the others
+    /// is updated with the time they are queued.
+    ///
+    /// Returns packets to be sent to client.
+    pub fn pop_pending(&mut self) -> Vec<OutgoingPacket> {
+        let mut packets = Vec::new();
+        if !self.retransmit.is_empty() {
+            return packets;
+        }
+        while !self.is_full() {
+            let Some(pending) = self.pending.pop_front() else {
+                break;
+            };
+            let Some(packet) = pending.clone().take() else {
+                continue;
+            };
+            match self.start(packet) {
+                Ok(packet) => packets.push(packet),
+                Err(_packet) => {
+                    self.pending.push_front(pending);
+                    break;
+                }
+            }
+        }
+        packets
+    }
+
+    /// Get inflight messages in sending order, used for re-delivery.
+    pub fn messages(&self) -> impl Iterator<Item = &OutgoingPacket> {
+        self.messages.iter()
+    }
+
+    /// Mark all inflight messages to be re-sent, when client reconnects.
+    pub fn mark_retransmit(&mut self) {
+        self.retransmit = self
+            .messages
+            .iter()
+            .map(OutgoingPacket::packet_id)
+            .collect();
+    }
+
+    /// Take messages to be re-sent until the window is full.
+    ///
+    /// Messages which are re-sent but not acknowledged yet count against the window.
+    pub fn pop_retransmit(&mut self) -> Vec<OutgoingPacket> {
+        let mut packets = Vec::new();
+        while self.window == 0 || self.messages.len() - self.retransmit.len() < self.window {
+            let Some(packet_id) = self.retransmit.pop_front() else {
+                break;
+            };
+            if let Some(packet) = self
+                .messages
+                .iter()
+                .find(|packet| packet.packet_id() == packet_id)
+            {
+                packets.push(packet.clone());
+            }
+        }
+        packets
+    }
+}
+
+impl EncodePacket for InflightMessages {
+    fn encode(&self, v: &mut Vec<u8>) -> Result<usize, EncodeError> {
+        let old_len = v.len();
+        let len = u32::try_from(self.messages.len()).map_err(|_err| EncodeError::TooManyData)?;
+        U32Data::new(len).encode(v)?;
+        for packet in &self.messages {
+            v.push(u8::from(self.is_released(packet.packet_id())));
+            packet.encode(v)?;
+        }
+
+        // Expiry interval of pending messages is reduced by the time they have been queued,
+        // and it is restarted when they are restored.
+        let pending: Vec<OutgoingPacket> = self
+            .pending
+            .iter()
+            .filter_map(|pending| pending.clone().take())
+            .collect();
+        let len = u32::try_from(pending.len()).map_err(|_err| EncodeError::TooManyData)?;
+        U32Data::new(len).encode(v)?;
+        for mut packet in pending {
+            // Pending messages have no packet id yet, which is required by decoder.
+            // A placeholder is written and a new id is allocated when they are sent.
+            packet.set_packet_id(PacketId::new(1));
+            packet.encode(v)?;
+        }
+        Ok(v.len() - old_len)
+    }
+}
+
+impl DecodePacket for InflightMessages {
+    fn decode(ba: &mut ByteArray) -> Result<Self, DecodeError> {
+        let mut inflight = Self::default();
+        let len = U32Data::decode(ba)?.value();
+        for _i in 0..len {
+            let released = ba.read_byte()? != 0;
+            let packet = OutgoingPacket::decode(ba)?;
+            let packet_id = packet.packet_id();
+            // Restored messages keep their original packet ids.
+            if !inflight.packet_ids.insert(packet_id) {
+                return Err(DecodeError::InvalidPacketId);
+            }
+            if released {
+                inflight.released.insert(packet_id);
+            }
+            inflight.messages.push_back(packet);
+        }
+
+        let len = U32Data::decode(ba)?.value();
+        for _i in 0..len {
+            inflight.push_pending(OutgoingPacket::decode(ba)?);
+        }
+        Ok(inflight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5, ByteArray, DecodePacket, EncodePacket, PacketId, QoS, U32Data};
+    use std::time::Duration;
+
+    use super::{InflightMessages, OutgoingPacket};
+    use crate::config::QueueFullPolicy;
+
+    fn new_packet() -> OutgoingPacket {
+        let packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, b"world").unwrap();
+        OutgoingPacket::V3(packet)
+    }
+
+    #[test]
+    fn test_push_and_remove() {
+        let mut inflight = InflightMessages::new(2);
+        let first = inflight.push(new_packet()).unwrap();
+        let second = inflight.push(new_packet()).unwrap();
+        assert_eq!(first.packet_id(), PacketId::new(1));
+        assert_eq!(second.packet_id(), PacketId::new(2));
+        assert!(inflight.is_full());
+
+        assert!(inflight.push(new_packet()).is_none());
+        assert_eq!(inflight.pending_len(), 1);
+
+        assert!(inflight.remove(PacketId::new(1)).is_some());
+        assert!(inflight.remove(PacketId::new(1)).is_none());
+        let packets = inflight.pop_pending();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].packet_id(), PacketId::new(3));
+        assert_eq!(inflight.pending_len(), 0);
+        assert_eq!(inflight.len(), 2);
+    }
+
+    #[test]
+    fn test_queue_limit() {
+        let mut inflight = InflightMessages::new(1);
+        inflight.set_queue_limit(2, QueueFullPolicy::DropOldest);
+        assert!(inflight.push(new_packet()).is_some());
+        for _ in 0..4 {
+            assert!(inflight.push(new_packet()).is_none());
+        }
+        assert_eq!(inflight.pending_len(), 2);
+        assert_eq!(inflight.take_dropped(), 2);
+        assert_eq!(inflight.take_dropped(), 0);
+
+        inflight.set_queue_limit(2, QueueFullPolicy::Reject);
+        assert!(inflight.push(new_packet()).is_none());
+        assert_eq!(inflight.pending_len(), 2);
+        assert_eq!(inflight.take_dropped(), 1);
+
+        // Queued messages are sent when inflight message is acknowledged.
+        assert!(inflight.remove(PacketId::new(1)).is_some());
+        assert_eq!(inflight.pop_pending().len(), 1);
+        assert_eq!(inflight.pending_len(), 1);
+    }
+
+    #[test]
+    fn test_packet_id_wraps() {
+        let mut inflight = InflightMessages::new(0);
+        let mut packet_ids = Vec::new();
+        for _ in 1..u16::MAX {
+            packet_ids.push(inflight.push(new_packet()).unwrap().packet_id());
+        }
+        // Keep packet 2 and 4 inflight.
+        for packet_id in packet_ids {
+            if packet_id != PacketId::new(2) && packet_id != PacketId::new(4) {
+                assert!(inflight.remove(packet_id).is_some());
+            }
+        }
+
+        let packet = inflight.push(new_packet()).unwrap();
+        assert_eq!(packet.packet_id(), PacketId::new(u16::MAX));
+        let packet = inflight.push(new_packet()).unwrap();
+        assert_eq!(packet.packet_id(), PacketId::new(1));
+        let packet = inflight.push(new_packet()).unwrap();
+        assert_eq!(packet.packet_id(), PacketId::new(3));
+        let packet = inflight.push(new_packet()).unwrap();
+        assert_eq!(packet.packet_id(), PacketId::new(5));
+    }
+
+    #[test]
+    fn test_retransmit_within_window() {
+        let mut inflight = InflightMessages::new(0);
+        for _ in 0..5 {
+            assert!(inflight.push(new_packet()).is_some());
+        }
+
+        // Client reconnects with a smaller window.
+        inflight.set_window(2);
+        inflight.mark_retransmit();
+        assert_eq!(inflight.retransmit_len(), 5);
+        let packet_ids = |packets: Vec<OutgoingPacket>| -> Vec<u16> {
+            packets
+                .iter()
+                .map(|packet| packet.packet_id().value())
+                .collect()
+        };
+        assert_eq!(packet_ids(inflight.pop_retransmit()), [1, 2]);
+        assert!(inflight.pop_retransmit().is_empty());
+
+        // New messages wait until all inflight messages are re-sent.
+        assert!(inflight.push(new_packet()).is_none());
+        assert!(inflight.pop_pending().is_empty());
+
+        assert!(inflight.remove(PacketId::new(1)).is_some());
+        assert_eq!(packet_ids(inflight.pop_retransmit()), [3]);
+        // Message acknowledged before being re-sent is skipped.
+        assert!(inflight.remove(PacketId::new(4)).is_some());
+        assert!(inflight.remove(PacketId::new(2)).is_some());
+        assert_eq!(packet_ids(inflight.pop_retransmit()), [5]);
+        assert_eq!(inflight.retransmit_len(), 0);
+
+        assert!(inflight.remove(PacketId::new(3)).is_some());
+        assert_eq!(packet_ids(inflight.pop_pending()), [6]);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut inflight = InflightMessages::new(0);
+        for _ in 0..3 {
+            assert!(inflight.push(new_packet()).is_some());
+        }
+        assert!(inflight.remove(PacketId::new(1)).is_some());
+        assert!(inflight.mark_released(PacketId::new(3)));
+        assert!(!inflight.mark_released(PacketId::new(1)));
+        inflight.push_pending(new_packet());
+
+        let mut buf = Vec::new();
+        inflight.encode(&mut buf).unwrap();
+        let mut ba = ByteArray::new(&buf);
+        let mut restored = InflightMessages::decode(&mut ba).unwrap();
+        assert_eq!(ba.remaining_bytes(), 0);
+
+        let packet_ids: Vec<u16> = restored
+            .messages()
+            .map(|packet| packet.packet_id().value())
+            .collect();
+        assert_eq!(packet_ids, [2, 3]);
+        assert!(!restored.is_released(PacketId::new(2)));
+        assert!(restored.is_released(PacketId::new(3)));
+        assert_eq!(restored.pending_len(), 1);
+
+        // Packet ids of restored messages are not allocated again.
+        let packets = restored.pop_pending();
+        assert_eq!(packets.len(), 1);
+        assert_ne!(packets[0].packet_id(), PacketId::new(2));
+        assert_ne!(packets[0].packet_id(), PacketId::new(3));
+    }
+
+    fn new_packet_v5(expiry_interval: Option<u32>) -> OutgoingPacket {
+        let mut packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, b"world").unwrap();
+        if let Some(interval) = expiry_interval {
+            packet
+                .properties_mut()
+                .push(v5::Property::MessageExpiryInterval(U32Data::new(interval)))
+                .unwrap();
+        }
+        OutgoingPacket::V5(packet)
+    }
+
+    fn expiry_interval(packet: &OutgoingPacket) -> Option<u32> {
+        let OutgoingPacket::V5(packet) = packet else {
+            return None;
+        };
+        packet
+            .properties()
+            .props()
+            .iter()
+            .find_map(|property| match property {
+                v5::Property::MessageExpiryInterval(interval) => Some(interval.value()),
+                _ => None,
+            })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pending_message_expiry() {
+        let mut inflight = InflightMessages::new(0);
+        inflight.push_pending(new_packet_v5(Some(2)));
+        inflight.push_pending(new_packet_v5(Some(10)));
+        inflight.push_pending(new_packet_v5(None));
+        inflight.push_pending(new_packet());
+
+        tokio::time::advance(Duration::from_millis(3500)).await;
+        let packets = inflight.pop_pending();
+        let intervals: Vec<Option<u32>> = packets.iter().map(expiry_interval).collect();
+        assert_eq!(intervals, [Some(7), None, None]);
+
+        inflight.push_pending(new_packet_v5(Some(1)));
+        inflight.push_pending(new_packet_v5(Some(5)));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(inflight.remove_expired_pending(), 1);
+        assert_eq!(inflight.pending_len(), 1);
+    }
+}
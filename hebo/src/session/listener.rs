@@ -5,13 +5,43 @@
 //! Handles commands from listener.
 
 use codec::{v3, v5, PacketId, QoS};
+use tokio::sync::mpsc;
 
-use super::{Session, Status};
-use crate::commands::ListenerToSessionCmd;
+use super::{PubRecvState, Session, Status, QUEUE_FULL_LOGS};
+use crate::commands::{ListenerToSessionCmd, SessionToListenerCmd};
 use crate::error::Error;
-use crate::session::CachedSession;
+use crate::session::{CachedSession, OutgoingPacket};
+use crate::types::Redirect;
 
 impl Session {
+    /// Send `cmd` to listener, handling commands from listener while waiting.
+    ///
+    /// Listener may be waiting for queue of this session to be read, so that a blocking
+    /// send would make both of them wait for each other.
+    pub(super) async fn send_to_listener(
+        &mut self,
+        cmd: SessionToListenerCmd,
+    ) -> Result<(), Error> {
+        // Permit borrows sender, while commands from listener are handled with `&mut self`.
+        let sender = self.sender.clone();
+        loop {
+            tokio::select! {
+                permit = sender.reserve() => {
+                    let Ok(permit) = permit else {
+                        return Err(mpsc::error::SendError(cmd).into());
+                    };
+                    permit.send(cmd);
+                    return Ok(());
+                }
+                Some(listener_cmd) = self.receiver.recv() => {
+                    if let Err(err) = self.handle_listener_cmd(listener_cmd).await {
+                        log::error!("Failed to handle server packet: {:?}", err);
+                    }
+                }
+            }
+        }
+    }
+
     pub(super) async fn handle_listener_cmd(
         &mut self,
         cmd: ListenerToSessionCmd,
@@ -39,8 +69,14 @@ impl Session {
             ListenerToSessionCmd::SubscribeAckV5(packet) => {
                 self.on_listener_subscribe_ack_v5(packet).await
             }
-            ListenerToSessionCmd::Disconnect | ListenerToSessionCmd::DisconnectV5 => {
-                self.on_listener_disconnect().await
+            ListenerToSessionCmd::Disconnect(reason_code) => {
+                self.on_listener_disconnect(reason_code).await
+            }
+            ListenerToSessionCmd::Drain(redirect) => self.on_listener_drain(redirect).await,
+            ListenerToSessionCmd::GetInflightMessages(resp_tx) => {
+                // Listener may have stopped waiting for the response.
+                let _ret = resp_tx.send(self.inflight_messages.len());
+                Ok(())
             }
         }
     }
@@ -59,8 +95,13 @@ impl Session {
             _ => Status::Disconnected,
         };
 
-        if let Some(cached_session) = cached_session {
-            self.load_cached_session(&cached_session);
+        if self.status == Status::Connected {
+            if let Some(cached_session) = cached_session {
+                self.load_cached_session(cached_session).await?;
+            }
+        } else {
+            // Will message is stored only if connect request is accepted.
+            self.will = None;
         }
 
         Ok(())
@@ -68,11 +109,20 @@ impl Session {
 
     async fn on_listener_connect_ack_v5(
         &mut self,
-        packet: v5::ConnectAckPacket,
+        mut packet: v5::ConnectAckPacket,
         cached_session: Option<CachedSession>,
     ) -> Result<(), Error> {
         // Send connect ack first, then update status.
         let reason_code = packet.reason_code();
+        if reason_code == v5::ReasonCode::Success {
+            self.add_topic_alias_maximum(&mut packet)?;
+            self.add_maximum_packet_size(&mut packet)?;
+            self.add_receive_maximum(&mut packet)?;
+            self.add_maximum_qos(&mut packet)?;
+            self.add_wildcard_subscription_available(&mut packet)?;
+            self.add_response_information(&mut packet)?;
+            self.add_authentication(&mut packet)?;
+        }
         self.send(packet).await?;
 
         self.status = match reason_code {
@@ -80,8 +130,13 @@ impl Session {
             _ => Status::Disconnected,
         };
 
-        if let Some(cached_session) = cached_session {
-            self.load_cached_session(&cached_session);
+        if self.status == Status::Connected {
+            if let Some(cached_session) = cached_session {
+                self.load_cached_session(cached_session).await?;
+            }
+        } else {
+            // Will message is stored only if connect request is accepted.
+            self.will = None;
         }
 
         Ok(())
@@ -98,25 +153,28 @@ impl Session {
         // it has no way of informing that Client. It MUST either make a positive acknowledgement,
         // according to the normal QoS rules, or close the Network Connection [MQTT-3.3.5-2].
         if !accepted {
-            return self.send_disconnect().await;
+            return self.send_disconnect(v5::ReasonCode::NotAuthorized).await;
         }
 
         // Check qos and send publish ack packet to client.
         if qos == QoS::AtLeastOnce {
+            if !self.pub_ack_packets.remove(&packet_id) {
+                log::warn!("session: Got unknown qos=1 packet id: {}", packet_id);
+            }
+
+            // Acknowledge with the same packet id as the PUBLISH packet [MQTT-2.3.1-6].
             let ack_packet = v3::PublishAckPacket::new(packet_id);
-            // TODO(Shaohua): Catch errors
             self.send(ack_packet).await?;
         } else if qos == QoS::ExactOnce {
-            // Check inflight messages overflow.
-            if self.pub_recv_packets.len() > self.config.maximum_inflight_messages() {
-                log::error!("session: Too many unacknowledged qos=2 messages, disconnect client!");
-                return self.send_disconnect().await;
+            // Packet id is recorded when PUBLISH packet is received.
+            if !self.pub_recv_packets.acknowledge(packet_id) {
+                log::warn!(
+                    "session: Got late or unknown qos=2 packet id: {}",
+                    packet_id
+                );
+                return Ok(());
             }
-
-            // Send PublishReceived.
-            self.pub_recv_packets.insert(packet_id);
             let ack_packet = v3::PublishReceivedPacket::new(packet_id);
-            // TODO(Shaohua): Catch errors
             self.send(ack_packet).await?;
         }
         Ok(())
@@ -129,46 +187,137 @@ impl Session {
         qos: QoS,
         accepted: bool,
     ) -> Result<(), Error> {
-        // If a Server implementation does not authorize a PUBLISH to be performed by a Client;
-        // it has no way of informing that Client. It MUST either make a positive acknowledgement,
-        // according to the normal QoS rules, or close the Network Connection [MQTT-3.3.5-2].
+        // Reason code of PUBACK and PUBREC packets tells client that the PUBLISH
+        // is not authorized, there is no ack packet for QoS 0 messages.
         if !accepted {
-            return self.send_disconnect().await;
+            return self.reject_publish_v5(packet_id, qos).await;
         }
 
         // Check qos and send publish ack packet to client.
         if qos == QoS::AtLeastOnce {
+            if !self.pub_ack_packets.remove(&packet_id) {
+                log::warn!("session: Got unknown qos=1 packet id: {}", packet_id);
+            }
+
+            // Acknowledge with the same packet id as the PUBLISH packet [MQTT-2.3.1-6].
             let ack_packet = v5::PublishAckPacket::new(packet_id);
-            // TODO(Shaohua): Catch errors
             self.send(ack_packet).await?;
         } else if qos == QoS::ExactOnce {
-            // Check inflight messages overflow.
-            if self.pub_recv_packets.len() > self.config.maximum_inflight_messages() {
-                log::error!("session: Too many unacknowledged qos=2 messages, disconnect client!");
-                return self.send_disconnect().await;
+            // Packet id is recorded when PUBLISH packet is received.
+            if !self.pub_recv_packets.acknowledge(packet_id) {
+                log::warn!(
+                    "session: Got late or unknown qos=2 packet id: {}",
+                    packet_id
+                );
+                return Ok(());
             }
-
-            // Send PublishReceived.
-            self.pub_recv_packets.insert(packet_id);
             let ack_packet = v5::PublishReceivedPacket::new(packet_id);
-            // TODO(Shaohua): Catch errors
             self.send(ack_packet).await?;
         }
         Ok(())
     }
 
+    /// Send ack packet with `NotAuthorized` reason code to client.
+    async fn reject_publish_v5(&mut self, packet_id: PacketId, qos: QoS) -> Result<(), Error> {
+        match qos {
+            QoS::AtMostOnce => self.send_disconnect(v5::ReasonCode::NotAuthorized).await,
+            QoS::AtLeastOnce => {
+                if !self.pub_ack_packets.remove(&packet_id) {
+                    log::warn!("session: Got unknown qos=1 packet id: {}", packet_id);
+                }
+                let mut ack_packet = v5::PublishAckPacket::new(packet_id);
+                ack_packet.set_reason_code(v5::ReasonCode::NotAuthorized);
+                self.send(ack_packet).await
+            }
+            QoS::ExactOnce => {
+                if self.pub_recv_packets.state(packet_id) != Some(PubRecvState::Received) {
+                    log::warn!(
+                        "session: Got late or unknown qos=2 packet id: {}",
+                        packet_id
+                    );
+                    return Ok(());
+                }
+                // PUBREC with an error reason code completes the handshake, no PUBREL follows.
+                self.pub_recv_packets.complete(packet_id);
+                let mut ack_packet = v5::PublishReceivedPacket::new(packet_id);
+                ack_packet.set_reason_code(v5::ReasonCode::NotAuthorized);
+                self.send(ack_packet).await
+            }
+        }
+    }
+
     async fn on_listener_publish(&mut self, packet: v3::PublishPacket) -> Result<(), Error> {
-        self.send(packet).await
+        if self.status == Status::Draining {
+            log::info!(
+                "session: Drop publish packet to draining session {}",
+                self.id
+            );
+            return Ok(());
+        }
+        if packet.qos() == QoS::AtMostOnce {
+            self.send(packet).await
+        } else {
+            self.publish_with_ack(OutgoingPacket::V3(packet)).await
+        }
     }
 
     async fn on_listener_publish_v5(&mut self, packet: v5::PublishPacket) -> Result<(), Error> {
-        self.send(packet).await
+        if self.status == Status::Draining {
+            log::info!(
+                "session: Drop publish packet to draining session {}",
+                self.id
+            );
+            return Ok(());
+        }
+        // Where a Packet is too large to send, the Server MUST discard it without sending it
+        // and then behave as if it had completed sending that Application Message [MQTT-3.1.2-25].
+        if self.exceeds_maximum_packet_size(&packet) {
+            log::warn!(
+                "session: Discard publish packet larger than maximum packet size of {}",
+                self.client_id
+            );
+            return Ok(());
+        }
+        if packet.qos() == QoS::AtMostOnce {
+            self.send(packet).await
+        } else {
+            self.publish_with_ack(OutgoingPacket::V5(packet)).await
+        }
+    }
+
+    /// Keep packet in inflight list until it is acknowledged by client.
+    ///
+    /// Packet is queued if inflight window is full, and messages are dropped
+    /// if the queue is full too.
+    async fn publish_with_ack(&mut self, packet: OutgoingPacket) -> Result<(), Error> {
+        if let Some(packet) = self.inflight_messages.push(packet) {
+            self.send_outgoing_packet(packet).await
+        } else {
+            let dropped = self.inflight_messages.take_dropped();
+            if dropped > 0 {
+                if let Some(suppressed) = QUEUE_FULL_LOGS.check() {
+                    log::warn!(
+                        "session: Message queue of {} is full, dropped {} messages{}",
+                        self.client_id,
+                        dropped,
+                        suppressed
+                    );
+                }
+                return Ok(());
+            }
+            log::info!(
+                "session: Inflight window is full, {} messages pending",
+                self.inflight_messages.pending_len()
+            );
+            Ok(())
+        }
     }
 
     async fn on_listener_subscribe_ack(
         &mut self,
-        packet: v3::SubscribeAckPacket,
+        mut packet: v3::SubscribeAckPacket,
     ) -> Result<(), Error> {
+        self.insert_rejected_subscriptions(&mut packet);
         // When the Server receives a SUBSCRIBE Packet from a Client, the Server MUST respond with a
         // SUBACK Packet [MQTT-3.8.4-1]. The SUBACK Packet MUST have the same Packet Identifier as the
         // SUBSCRIBE Packet that it is acknowledging [MQTT-3.8.4-2].
@@ -177,13 +326,33 @@ impl Session {
 
     async fn on_listener_subscribe_ack_v5(
         &mut self,
-        packet: v5::SubscribeAckPacket,
+        mut packet: v5::SubscribeAckPacket,
     ) -> Result<(), Error> {
+        self.insert_rejected_subscriptions_v5(&mut packet);
         // TODO(Shaohua): Add comments
         self.send(packet).await
     }
 
-    async fn on_listener_disconnect(&mut self) -> Result<(), Error> {
-        self.send_disconnect().await
+    async fn on_listener_disconnect(&mut self, reason_code: v5::ReasonCode) -> Result<(), Error> {
+        self.send_disconnect(reason_code).await
+    }
+
+    async fn on_listener_drain(&mut self, redirect: Redirect) -> Result<(), Error> {
+        if self.status != Status::Connected {
+            log::warn!(
+                "session: Cannot drain session {} with status {:?}",
+                self.id,
+                self.status
+            );
+            return Ok(());
+        }
+        log::info!(
+            "session: Drain session {}, client id: {}",
+            self.id,
+            self.client_id
+        );
+        self.status = Status::Draining;
+        self.redirect = Some(redirect);
+        self.disconnect_if_drained().await
     }
 }
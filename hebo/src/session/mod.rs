@@ -4,31 +4,62 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use codec::{EncodePacket, Packet, PacketId, PacketType, ProtocolLevel};
-use std::collections::HashSet;
-use std::time::Instant;
+use codec::{v5, EncodePacket, Packet, PacketDecoder, PacketId, PacketType, ProtocolLevel};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Instant;
 
 use crate::commands::{ListenerToSessionCmd, SessionToListenerCmd};
+use crate::config::RateLimitPolicy;
 use crate::error::{Error, ErrorKind};
+use crate::log::LogLimiter;
 use crate::stream::Stream;
-use crate::types::SessionId;
+use crate::types::{Redirect, SessionId};
 
+mod auth;
 mod cache;
+mod capture;
 mod client;
 mod client_v5;
 mod config;
+mod inflight;
 mod listener;
+mod payload_format;
 mod properties;
+mod pub_recv;
+mod rate_limit;
+mod topic_alias;
+mod wildcard;
+mod will;
 
+use auth::EnhancedAuth;
 pub use cache::CachedSession;
+use capture::{Direction, PacketCapture};
 pub use config::SessionConfig;
+pub use inflight::{update_message_expiry, InflightMessages, OutgoingPacket};
+use pub_recv::{PubRecvPackets, PubRecvState};
+use rate_limit::RateLimiter;
+use will::WillMessage;
+
+/// Time to flush pending data before network connection is closed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Rate limit is exceeded by every packet of an abusive client, log them less frequently.
+static RATE_LIMIT_LOGS: LogLimiter = LogLimiter::new();
+
+/// Messages are dropped for every publish to a slow client, log them less frequently.
+static QUEUE_FULL_LOGS: LogLimiter = LogLimiter::new();
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Status {
     Invalid,
+    /// Exchanging AUTH packets with v5 client, before connect packet is sent to listener.
+    Authenticating,
     Connecting,
     Connected,
+    /// No new message is delivered to client, waiting for inflight messages to be acknowledged.
+    Draining,
     Disconnecting,
     Disconnected,
 }
@@ -36,6 +67,7 @@ pub enum Status {
 /// `ConnectionContext` represents a client connection.
 ///
 /// All the status of this client is maintained in this struct.
+#[allow(clippy::struct_field_names)]
 #[derive(Debug)]
 pub struct Session {
     id: SessionId,
@@ -46,12 +78,38 @@ pub struct Session {
 
     status: Status,
     client_id: String,
-    // TODO(Shaohua): Handle Will Message
+    /// Will message in connect packet, published if network connection is closed abnormally.
+    will: Option<WillMessage>,
+    /// Capture packets of this client if enabled.
+    capture: Option<PacketCapture>,
+    /// Enhanced authentication in progress, or completed but connect ack not sent yet.
+    auth: Option<EnhancedAuth>,
     // TODO(Shaohua): Add session flag
     instant: Instant,
     clean_session: bool,
 
-    pub_recv_packets: HashSet<PacketId>,
+    /// `QoS` 1 packets received from client and waiting for acknowledgement.
+    pub_ack_packets: HashSet<PacketId>,
+    /// `QoS` 2 packets received from client and not fully acknowledged yet.
+    pub_recv_packets: PubRecvPackets,
+
+    /// `QoS` 1 and `QoS` 2 packets sent to client and waiting for acknowledgement.
+    inflight_messages: InflightMessages,
+    /// Start re-sending inflight messages of resumed session at this time.
+    retransmit_at: Option<Instant>,
+    /// Redirect client to another server after session is drained.
+    redirect: Option<Redirect>,
+
+    /// Topic names of v5 publish packets received from client, indexed by topic alias.
+    topic_aliases: HashMap<u16, String>,
+    /// `packet_id` -> indices of wildcard topic filters rejected in subscribe packet,
+    /// which are inserted back to subscribe ack packet.
+    rejected_subscriptions: HashMap<PacketId, Vec<usize>>,
+
+    /// Limits rate of publish packets received from client.
+    rate_limiter: Option<RateLimiter>,
+    /// Stop reading packets from client until this time, as rate limit is exceeded.
+    throttled_until: Option<Instant>,
 
     sender: Sender<SessionToListenerCmd>,
     receiver: Receiver<ListenerToSessionCmd>,
@@ -65,6 +123,9 @@ impl Session {
         sender: Sender<SessionToListenerCmd>,
         receiver: Receiver<ListenerToSessionCmd>,
     ) -> Self {
+        let mut inflight_messages = InflightMessages::new(config.inflight_window());
+        inflight_messages.set_queue_limit(config.max_queued_messages(), config.queue_full_policy());
+        let rate_limiter = RateLimiter::new(config.max_message_rate(), config.max_byte_rate());
         Self {
             id,
             protocol_level: ProtocolLevel::default(),
@@ -74,29 +135,44 @@ impl Session {
 
             status: Status::Invalid,
             client_id: String::new(),
+            will: None,
+            capture: None,
+            auth: None,
             instant: Instant::now(),
             clean_session: true,
 
-            pub_recv_packets: HashSet::new(),
+            pub_ack_packets: HashSet::new(),
+            pub_recv_packets: PubRecvPackets::new(),
+
+            inflight_messages,
+            retransmit_at: None,
+            redirect: None,
+
+            topic_aliases: HashMap::new(),
+            rejected_subscriptions: HashMap::new(),
+
+            rate_limiter,
+            throttled_until: None,
 
             sender,
             receiver,
         }
     }
 
+    #[allow(clippy::too_many_lines)]
     pub async fn run_loop(mut self) {
-        // TODO(Shaohua): Set buffer cap based on settings
-        let mut buf = Vec::with_capacity(1024);
+        let mut decoder = PacketDecoder::with_capacity(1024);
 
-        let connect_timeout = Instant::now();
+        let connect_deadline = Instant::now() + self.config.connect_timeout();
 
         loop {
             // If the Server does not receive a CONNECT Packet within a reasonable amount of time after the
             // Network Connection is established, the Server SHOULD close the connection.
-            if self.status == Status::Invalid
-                && !self.config.connect_timeout().is_zero()
-                && connect_timeout.elapsed() > self.config.connect_timeout()
-            {
+            let waiting_connect = matches!(self.status, Status::Invalid | Status::Authenticating)
+                && !self.config.connect_timeout().is_zero();
+            if waiting_connect && Instant::now() >= connect_deadline {
+                log::info!("session: connect timeout reached, close stream {}", self.id);
+                self.close();
                 break;
             }
 
@@ -105,21 +181,26 @@ impl Session {
                 break;
             }
 
+            let keep_alive_timer = keep_alive_timer(self.instant, self.config.keep_alive());
+            let retransmit_timer = deadline_timer(self.retransmit_at);
+            let throttle_timer = deadline_timer(self.throttled_until);
+            let connect_timer = deadline_timer(waiting_connect.then_some(connect_deadline));
+
             tokio::select! {
-                Ok(n_recv) = self.stream.read_buf(&mut buf) => {
+                Ok(n_recv) = self.stream.read_buf(decoder.buffer_mut()), if self.throttled_until.is_none() => {
                     log::info!("n_recv: {}", n_recv);
                     if n_recv > 0 {
-                        if let Err(err) = self.handle_client_packet(&buf).await {
-                            log::error!("handle_client_packet() failed: {:?}", err);
+                        if let Err(err) = self.handle_client_packets(&mut decoder).await {
+                            static CLIENT_PACKET_ERRORS: LogLimiter = LogLimiter::new();
+                            if let Some(suppressed) = CLIENT_PACKET_ERRORS.check() {
+                                log::error!("handle_client_packets() failed: {:?}{}", err, suppressed);
+                            }
                             break;
                         }
-                        buf.clear();
-
                     } else {
+                        // Stream is closed by client.
                         log::info!("session: Empty packet received, disconnect client, {}", self.id);
-                        if let Err(err) = self.send_disconnect().await {
-                            log::error!("session: Failed to send disconnect packet: {:?}", err);
-                        }
+                        self.close();
                         break;
                     }
                 }
@@ -128,6 +209,26 @@ impl Session {
                         log::error!("Failed to handle server packet: {:?}", err);
                     }
                 },
+                () = keep_alive_timer => {
+                    // Checked below.
+                },
+                () = connect_timer => {
+                    // Checked at start of next loop.
+                },
+                () = retransmit_timer => {
+                    self.retransmit_at = None;
+                    if let Err(err) = self.send_queued_packets().await {
+                        log::error!("session: Failed to re-send inflight messages: {:?}", err);
+                    }
+                },
+                () = throttle_timer => {
+                    self.throttled_until = None;
+                    // Packets already read are handled before reading more.
+                    if let Err(err) = self.handle_client_packets(&mut decoder).await {
+                        log::error!("handle_client_packets() failed: {:?}", err);
+                        break;
+                    }
+                },
             }
 
             // From [MQTT-3.1.2-24]
@@ -144,16 +245,45 @@ impl Session {
             // Note that a Server is permitted to disconnect a Client that it determines to be inactive
             // or non-responsive at any time, regardless of the Keep Alive value provided by that Client.
             if !self.config.keep_alive().is_zero()
-                && self.instant.elapsed() > self.config.keep_alive()
+                && self.throttled_until.is_none()
+                && self.instant.elapsed() >= self.config.keep_alive()
             {
                 log::warn!("sessoin: keep_alive time reached, disconnect client!");
-                if let Err(err) = self.send_disconnect().await {
+                if let Err(err) = self.send_disconnect(v5::ReasonCode::KeepAliveTimeout).await {
                     log::error!("session: Failed to send disconnect packet: {:?}", err);
                 }
                 break;
             }
         }
 
+        // Make sure packets written before, like DISCONNECT sent on server shutdown,
+        // reach the client, without waiting for a slow client forever.
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, self.stream.shutdown()).await {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => log::info!("session: Failed to shutdown stream {}: {:?}", self.id, err),
+            Err(_elapsed) => log::warn!("session: Timeout to shutdown stream {}", self.id),
+        }
+
+        // Will message is discarded if DISCONNECT packet is received from client.
+        self.publish_will().await;
+
+        // Keep session state if clean session flag is off, so that unacknowledged messages
+        // can be re-delivered when this client reconnects.
+        if self.keep_cached_session() {
+            let cached_session = self.to_cached_session();
+            if let Err(err) = self
+                .sender
+                .send(SessionToListenerCmd::CacheSession(self.id, cached_session))
+                .await
+            {
+                log::error!(
+                    "Failed to send cache session cmd to server, id: {}, err: {:?}",
+                    self.id,
+                    err
+                );
+            }
+        }
+
         if let Err(err) = self
             .sender
             .send(SessionToListenerCmd::Disconnect(self.id))
@@ -171,7 +301,90 @@ impl Session {
         // Now session object goes out of scope and stream is dropped.
     }
 
-    /// Reset instant if packet is send to or receive from client.
+    /// Handle all complete packets in `decoder`.
+    ///
+    /// Bytes of an incomplete packet are kept in `decoder`, waiting for next read.
+    async fn handle_client_packets(&mut self, decoder: &mut PacketDecoder) -> Result<(), Error> {
+        // If the Server rejects the CONNECT, it MUST NOT process any data sent by the
+        // Client after the CONNECT Packet [MQTT-3.1.4-5].
+        while self.status != Status::Disconnected && self.throttled_until.is_none() {
+            let packet_len = match decoder.packet_length() {
+                Ok(Some(packet_len)) => packet_len,
+                Ok(None) => break,
+                Err(err) => {
+                    static FIXED_HEADER_ERRORS: LogLimiter = LogLimiter::new();
+                    if let Some(suppressed) = FIXED_HEADER_ERRORS.check() {
+                        log::error!("session: Invalid fixed header: {:?}{}", err, suppressed);
+                    }
+                    self.send_disconnect(v5::ReasonCode::MalformedPacket)
+                        .await?;
+                    return Err(err.into());
+                }
+            };
+            if packet_len > self.config.read_buffer_cap() {
+                self.on_packet_too_large(packet_len).await;
+                return Err(Error::from_string(
+                    ErrorKind::DecodeError,
+                    format!("session: Packet too large: {packet_len}"),
+                ));
+            }
+
+            // Wait for more bytes if packet is incomplete.
+            let Ok(Some(buf)) = decoder.next_packet_bytes() else {
+                break;
+            };
+            self.handle_client_packet(buf).await?;
+            // Capture file is opened after CONNECT packet is handled.
+            self.capture_packet(Direction::Received, buf);
+        }
+        Ok(())
+    }
+
+    /// Disconnect client if packet size exceeds read buffer cap.
+    async fn on_packet_too_large(&mut self, packet_len: usize) {
+        log::warn!(
+            "session: Packet too large: {}, disconnect client, {}",
+            packet_len,
+            self.id
+        );
+        if let Err(err) = self.send_disconnect(v5::ReasonCode::PacketTooLarge).await {
+            log::error!("session: Failed to send disconnect packet: {:?}", err);
+        }
+    }
+
+    /// Count publish packet of `packet_len` bytes against rate limits.
+    ///
+    /// Returns false if client is disconnected as rate limit is exceeded.
+    async fn check_rate_limit(&mut self, packet_len: usize) -> Result<bool, Error> {
+        let Some(rate_limiter) = self.rate_limiter.as_mut() else {
+            return Ok(true);
+        };
+        let wait = rate_limiter.on_publish(packet_len);
+        if wait.is_zero() {
+            return Ok(true);
+        }
+
+        if let Some(suppressed) = RATE_LIMIT_LOGS.check() {
+            log::warn!(
+                "session: Rate limit exceeded by client {:?}, policy: {:?}{}",
+                self.client_id,
+                self.config.rate_limit_policy(),
+                suppressed
+            );
+        }
+        match self.config.rate_limit_policy() {
+            RateLimitPolicy::Throttle => {
+                self.throttled_until = Some(Instant::now() + wait);
+                Ok(true)
+            }
+            RateLimitPolicy::Disconnect => {
+                self.send_disconnect(v5::ReasonCode::QuotaExceeded).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Reset instant if packet is received from client.
     fn reset_instant(&mut self) {
         self.instant = Instant::now();
     }
@@ -202,6 +415,17 @@ impl Session {
 
         let mut buf = Vec::new();
         packet.encode(&mut buf)?;
+        // The Server MUST NOT send packets exceeding Maximum Packet Size to the Client [MQTT-3.1.2-24].
+        let maximum_packet_size = self.config.maximum_packet_size();
+        if maximum_packet_size > 0 && buf.len() > maximum_packet_size {
+            log::warn!(
+                "session: Discard {:?} packet of {} bytes, larger than maximum packet size {}",
+                packet.packet_type(),
+                buf.len(),
+                maximum_packet_size
+            );
+            return Ok(());
+        }
         let n_write = self.stream.write(&buf).await?;
         if n_write != buf.len() {
             log::error!("packet: {:?}", packet);
@@ -214,7 +438,1479 @@ impl Session {
                 ),
             ));
         }
-        self.reset_instant();
+        self.capture_packet(Direction::Sent, &buf);
         Ok(())
     }
+
+    pub(super) async fn send_outgoing_packet(
+        &mut self,
+        packet: OutgoingPacket,
+    ) -> Result<(), Error> {
+        match packet {
+            OutgoingPacket::V3(packet) => self.send(packet).await,
+            OutgoingPacket::V5(packet) => self.send(packet).await,
+        }
+    }
+}
+
+/// Wait until keep alive time reached since `instant`, never completes if `keep_alive` is 0.
+async fn keep_alive_timer(instant: Instant, keep_alive: Duration) {
+    if keep_alive.is_zero() {
+        std::future::pending::<()>().await;
+    } else {
+        tokio::time::sleep_until(instant + keep_alive).await;
+    }
+}
+
+/// Wait until `deadline`, never completes if it is None.
+async fn deadline_timer(deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline).await;
+    } else {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use codec::{
+        v3, v5, BinaryData, BoolData, ByteArray, DecodePacket, EncodePacket, FixedHeader, PacketId,
+        QoS, StringData, U16Data, U32Data,
+    };
+    use futures::future::BoxFuture;
+    use std::fmt::Write as _;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+    use tokio::sync::mpsc::{self, Receiver, Sender};
+    use tokio::time::Instant;
+
+    use super::{CachedSession, InflightMessages, OutgoingPacket, Session, SessionConfig};
+    use crate::auth::{AuthExchange, AuthMethod, AuthStep};
+    use crate::commands::{ListenerToSessionCmd, SessionToListenerCmd};
+    use crate::config::{Capture, RateLimitPolicy};
+    use crate::stream::Stream;
+    use crate::types::Redirect;
+
+    struct Client {
+        stream: UnixStream,
+        buf: Vec<u8>,
+        sender: Sender<ListenerToSessionCmd>,
+        receiver: Receiver<SessionToListenerCmd>,
+    }
+
+    impl Client {
+        async fn write_packet<P: EncodePacket + Sync>(&mut self, packet: &P) {
+            let mut buf = Vec::new();
+            packet.encode(&mut buf).unwrap();
+            self.stream.write_all(&buf).await.unwrap();
+        }
+
+        /// Read exactly one packet from stream.
+        async fn read_packet<P: DecodePacket + Send>(&mut self) -> P {
+            loop {
+                let mut ba = ByteArray::new(&self.buf);
+                if self.buf.is_empty() {
+                    // Wait for more bytes.
+                } else if let Ok(fixed_header) = FixedHeader::decode(&mut ba) {
+                    let packet_len = ba.offset() + fixed_header.remaining_length();
+                    if self.buf.len() >= packet_len {
+                        let mut ba = ByteArray::new(&self.buf[..packet_len]);
+                        let packet = P::decode(&mut ba).unwrap();
+                        self.buf.drain(..packet_len);
+                        return packet;
+                    }
+                }
+                self.stream.read_buf(&mut self.buf).await.unwrap();
+            }
+        }
+    }
+
+    /// Spawn a session without sending CONNECT packet.
+    fn start_session(config: SessionConfig) -> Client {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let (session_sender, receiver) = mpsc::channel(16);
+        let (sender, session_receiver) = mpsc::channel(16);
+        let session = Session::new(
+            1,
+            config,
+            Stream::Uds(server_stream),
+            session_sender,
+            session_receiver,
+        );
+        tokio::spawn(session.run_loop());
+
+        Client {
+            stream: client_stream,
+            buf: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    async fn connect(config: SessionConfig, cached_session: Option<CachedSession>) -> Client {
+        let connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        connect_with_packet(config, cached_session, &connect_packet).await
+    }
+
+    async fn connect_with_packet(
+        config: SessionConfig,
+        cached_session: Option<CachedSession>,
+        connect_packet: &v3::ConnectPacket,
+    ) -> Client {
+        let mut client = start_session(config);
+        client.write_packet(connect_packet).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Connect(1, _))
+        ));
+        let ack_packet = v3::ConnectAckPacket::new(false, v3::ConnectReturnCode::Accepted);
+        client
+            .sender
+            .send(ListenerToSessionCmd::ConnectAck(ack_packet, cached_session))
+            .await
+            .unwrap();
+        let ack_packet: v3::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+        client
+    }
+
+    async fn connect_v5(config: SessionConfig) -> (Client, v5::ConnectAckPacket) {
+        let connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_v5_with_packet(config, &connect_packet).await
+    }
+
+    async fn connect_v5_with_packet(
+        config: SessionConfig,
+        connect_packet: &v5::ConnectPacket,
+    ) -> (Client, v5::ConnectAckPacket) {
+        let mut client = start_session(config);
+        client.write_packet(connect_packet).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::ConnectV5(1, _))
+        ));
+        let ack_packet = v5::ConnectAckPacket::new(false, v5::ReasonCode::Success);
+        client
+            .sender
+            .send(ListenerToSessionCmd::ConnectAckV5(ack_packet, None))
+            .await
+            .unwrap();
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+        (client, ack_packet)
+    }
+
+    #[tokio::test]
+    async fn test_client_publish_qos1() {
+        let mut client = connect(SessionConfig::new(), None).await;
+
+        let mut packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, b"world").unwrap();
+        packet.set_packet_id(PacketId::new(42));
+        client.write_packet(&packet).await;
+
+        let Some(SessionToListenerCmd::Publish(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        assert_eq!(packet.packet_id(), PacketId::new(42));
+        client
+            .sender
+            .send(ListenerToSessionCmd::PublishAck(
+                packet.packet_id(),
+                packet.qos(),
+                true,
+            ))
+            .await
+            .unwrap();
+
+        let ack_packet: v3::PublishAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), PacketId::new(42));
+    }
+
+    /// Send a `QoS` 2 PUBLISH packet and wait for the PUBREC packet.
+    async fn client_publish_qos2(client: &mut Client, packet_id: PacketId) {
+        let mut packet = v3::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(packet_id);
+        client.write_packet(&packet).await;
+
+        let Some(SessionToListenerCmd::Publish(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        client
+            .sender
+            .send(ListenerToSessionCmd::PublishAck(
+                packet.packet_id(),
+                packet.qos(),
+                true,
+            ))
+            .await
+            .unwrap();
+        let ack_packet: v3::PublishReceivedPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+    }
+
+    #[tokio::test]
+    async fn test_client_publish_qos2() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let packet_id = PacketId::new(7);
+        client_publish_qos2(&mut client, packet_id).await;
+
+        client
+            .write_packet(&v3::PublishReleasePacket::new(packet_id))
+            .await;
+        let ack_packet: v3::PublishCompletePacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+        assert!(client.receiver.try_recv().is_err());
+
+        // Packet id can be reused after handshake completed.
+        client_publish_qos2(&mut client, packet_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_publish_qos2_dup() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let packet_id = PacketId::new(7);
+        client_publish_qos2(&mut client, packet_id).await;
+
+        // Re-delivered PUBLISH packet is acknowledged but not forwarded again.
+        let mut packet = v3::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(packet_id);
+        packet.set_dup(true).unwrap();
+        client.write_packet(&packet).await;
+        let ack_packet: v3::PublishReceivedPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+
+        client
+            .write_packet(&v3::PublishReleasePacket::new(packet_id))
+            .await;
+        let ack_packet: v3::PublishCompletePacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+        assert!(client.receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_publish_qos2_early_release() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let packet_id = PacketId::new(7);
+
+        // PUBLISH and PUBREL packets are sent in one write.
+        let mut packet = v3::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(packet_id);
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        v3::PublishReleasePacket::new(packet_id)
+            .encode(&mut buf)
+            .unwrap();
+        client.stream.write_all(&buf).await.unwrap();
+
+        let Some(SessionToListenerCmd::Publish(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        client
+            .sender
+            .send(ListenerToSessionCmd::PublishAck(
+                packet.packet_id(),
+                packet.qos(),
+                true,
+            ))
+            .await
+            .unwrap();
+
+        // PUBREL sent before PUBREC is ignored.
+        let ack_packet: v3::PublishReceivedPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+        client
+            .write_packet(&v3::PublishReleasePacket::new(packet_id))
+            .await;
+        let ack_packet: v3::PublishCompletePacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), packet_id);
+    }
+
+    #[tokio::test]
+    async fn test_client_publish_not_authorized_v5() {
+        let (mut client, _ack_packet) = connect_v5(SessionConfig::new()).await;
+
+        for (qos, packet_id) in [(QoS::AtLeastOnce, 3), (QoS::ExactOnce, 4)] {
+            let packet_id = PacketId::new(packet_id);
+            let mut packet = v5::PublishPacket::new("hello", qos, b"world").unwrap();
+            packet.set_packet_id(packet_id);
+            client.write_packet(&packet).await;
+            assert!(matches!(
+                client.receiver.recv().await,
+                Some(SessionToListenerCmd::PublishV5(1, _))
+            ));
+            client
+                .sender
+                .send(ListenerToSessionCmd::PublishAckV5(packet_id, qos, false))
+                .await
+                .unwrap();
+            if qos == QoS::AtLeastOnce {
+                let ack_packet: v5::PublishAckPacket = client.read_packet().await;
+                assert_eq!(ack_packet.packet_id(), packet_id);
+                assert_eq!(ack_packet.reason_code(), v5::ReasonCode::NotAuthorized);
+            } else {
+                let ack_packet: v5::PublishReceivedPacket = client.read_packet().await;
+                assert_eq!(ack_packet.packet_id(), packet_id);
+                assert_eq!(ack_packet.reason_code(), v5::ReasonCode::NotAuthorized);
+            }
+        }
+
+        // Handshake of rejected QoS 2 packet is completed, packet id can be reused.
+        let mut packet = v5::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(PacketId::new(4));
+        client.write_packet(&packet).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::PublishV5(1, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_publish_qos1() {
+        let mut config = SessionConfig::new();
+        config.set_inflight_window(1);
+        let mut client = connect(config, None).await;
+
+        for msg in [b"first", b"secnd"] {
+            let packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::Publish(packet))
+                .await
+                .unwrap();
+        }
+
+        // Only one message is sent as inflight window is 1.
+        let packet: v3::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"first");
+        assert_eq!(packet.packet_id(), PacketId::new(1));
+
+        // Pending message is sent after the first one is acknowledged.
+        client
+            .write_packet(&v3::PublishAckPacket::new(packet.packet_id()))
+            .await;
+        let packet: v3::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"secnd");
+        assert_eq!(packet.packet_id(), PacketId::new(2));
+    }
+
+    #[tokio::test]
+    async fn test_server_publish_qos2() {
+        let mut config = SessionConfig::new();
+        config.set_inflight_window(1);
+        let mut client = connect(config, None).await;
+
+        for msg in [b"first", b"secnd"] {
+            let packet = v3::PublishPacket::new("hello", QoS::ExactOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::Publish(packet))
+                .await
+                .unwrap();
+        }
+
+        let packet: v3::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"first");
+        client
+            .write_packet(&v3::PublishReceivedPacket::new(packet.packet_id()))
+            .await;
+        let release_packet: v3::PublishReleasePacket = client.read_packet().await;
+        assert_eq!(release_packet.packet_id(), packet.packet_id());
+
+        // Inflight slot is freed after PUBCOMP.
+        client
+            .write_packet(&v3::PublishCompletePacket::new(packet.packet_id()))
+            .await;
+        let packet: v3::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"secnd");
+    }
+
+    #[tokio::test]
+    async fn test_server_publish_qos2_v5() {
+        let mut config = SessionConfig::new();
+        config.set_inflight_window(1);
+        let (mut client, _ack_packet) = connect_v5(config).await;
+
+        // Unknown packet id.
+        client
+            .write_packet(&v5::PublishReceivedPacket::new(PacketId::new(9)))
+            .await;
+        let release_packet: v5::PublishReleasePacket = client.read_packet().await;
+        assert_eq!(release_packet.packet_id(), PacketId::new(9));
+        assert_eq!(
+            release_packet.reason_code(),
+            v5::ReasonCode::PacketIdentifierNotFound
+        );
+
+        for msg in [b"first", b"secnd"] {
+            let packet = v5::PublishPacket::new("hello", QoS::ExactOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::PublishV5(packet))
+                .await
+                .unwrap();
+        }
+
+        let packet: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"first");
+        client
+            .write_packet(&v5::PublishReceivedPacket::new(packet.packet_id()))
+            .await;
+        let release_packet: v5::PublishReleasePacket = client.read_packet().await;
+        assert_eq!(release_packet.packet_id(), packet.packet_id());
+        assert_eq!(release_packet.reason_code(), v5::ReasonCode::Success);
+
+        client
+            .write_packet(&v5::PublishCompletePacket::new(packet.packet_id()))
+            .await;
+        let packet: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"secnd");
+    }
+
+    #[tokio::test]
+    async fn test_drain_with_inflight_messages() {
+        let (mut client, _ack_packet) = connect_v5(SessionConfig::new()).await;
+
+        for msg in [b"first", b"secnd"] {
+            let packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::PublishV5(packet))
+                .await
+                .unwrap();
+        }
+        let first: v5::PublishPacket = client.read_packet().await;
+        let second: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(second.message(), b"secnd");
+
+        let redirect = Redirect::new(Some("127.0.0.1:1884".to_owned()), false);
+        client
+            .sender
+            .send(ListenerToSessionCmd::Drain(redirect))
+            .await
+            .unwrap();
+        // New messages are not delivered to draining session.
+        let packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, b"third").unwrap();
+        client
+            .sender
+            .send(ListenerToSessionCmd::PublishV5(packet))
+            .await
+            .unwrap();
+
+        // Inflight messages are still acknowledged.
+        client
+            .write_packet(&v5::PublishAckPacket::new(first.packet_id()))
+            .await;
+        // Still connected while one message is inflight.
+        client.write_packet(&v5::PingRequestPacket::new()).await;
+        let _packet: v5::PingResponsePacket = client.read_packet().await;
+        client
+            .write_packet(&v5::PublishAckPacket::new(second.packet_id()))
+            .await;
+
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::UseAnotherServer);
+        assert!(packet.properties().props().iter().any(|property| matches!(
+            property,
+            v5::Property::ServerReference(reference) if reference.as_ref() == "127.0.0.1:1884"
+        )));
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_subscribe_unsubscribe() {
+        let mut client = connect(SessionConfig::new(), None).await;
+
+        let packet = v3::SubscribePacket::new("hello", QoS::AtLeastOnce, PacketId::new(3)).unwrap();
+        client.write_packet(&packet).await;
+        let Some(SessionToListenerCmd::Subscribe(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected subscribe cmd");
+        };
+        assert_eq!(packet.packet_id(), PacketId::new(3));
+
+        let packet = v3::UnsubscribePacket::new("hello", PacketId::new(4)).unwrap();
+        client.write_packet(&packet).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Unsubscribe(1, _))
+        ));
+        let ack_packet: v3::UnsubscribeAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), PacketId::new(4));
+
+        client.write_packet(&v3::DisconnectPacket::new()).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Disconnect(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_maximum_qos_subscribe() {
+        let mut config = SessionConfig::new();
+        config.set_maximum_qos(QoS::AtLeastOnce);
+        let mut client = connect(config, None).await;
+
+        let packet = v3::SubscribePacket::new("hello", QoS::ExactOnce, PacketId::new(3)).unwrap();
+        client.write_packet(&packet).await;
+        let Some(SessionToListenerCmd::Subscribe(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected subscribe cmd");
+        };
+        assert_eq!(packet.topics()[0].qos(), QoS::AtLeastOnce);
+    }
+
+    #[tokio::test]
+    async fn test_maximum_qos_publish() {
+        let mut config = SessionConfig::new();
+        config.set_maximum_qos(QoS::AtLeastOnce);
+        let mut client = connect(config, None).await;
+
+        let mut packet = v3::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        client.write_packet(&packet).await;
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_maximum_qos_v5() {
+        let mut config = SessionConfig::new();
+        config.set_maximum_qos(QoS::AtLeastOnce);
+        let (mut client, ack_packet) = connect_v5(config).await;
+        assert!(ack_packet
+            .properties()
+            .props()
+            .contains(&v5::Property::MaximumQoS(QoS::AtLeastOnce)));
+
+        let packet = v5::SubscribePacket::new("hello", QoS::ExactOnce, PacketId::new(3)).unwrap();
+        client.write_packet(&packet).await;
+        let Some(SessionToListenerCmd::SubscribeV5(1, packet)) = client.receiver.recv().await
+        else {
+            panic!("Expected subscribe cmd");
+        };
+        assert_eq!(packet.topics()[0].qos(), QoS::AtLeastOnce);
+
+        let mut packet = v5::PublishPacket::new("hello", QoS::ExactOnce, b"world").unwrap();
+        packet.set_packet_id(PacketId::new(1));
+        client.write_packet(&packet).await;
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::QoSNotSupported);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_validate_payload_format_v5() {
+        fn publish_packet(qos: QoS, msg: &[u8], utf8: bool, packet_id: u16) -> v5::PublishPacket {
+            let mut packet = v5::PublishPacket::new("hello", qos, msg).unwrap();
+            packet.set_packet_id(PacketId::new(packet_id));
+            if utf8 {
+                packet
+                    .properties_mut()
+                    .set_payload_format_indicator(true)
+                    .unwrap();
+            }
+            packet
+        }
+
+        let mut config = SessionConfig::new();
+        config.set_validate_payload_format(true);
+        let (mut client, _ack_packet) = connect_v5(config).await;
+
+        // Valid UTF-8 string, or payload without indicator is not checked.
+        for (msg, utf8, packet_id) in [(&b"world"[..], true, 1), (&[0xff, 0xfe], false, 2)] {
+            client
+                .write_packet(&publish_packet(QoS::AtLeastOnce, msg, utf8, packet_id))
+                .await;
+            let Some(SessionToListenerCmd::PublishV5(1, packet)) = client.receiver.recv().await
+            else {
+                panic!("Expected publish cmd");
+            };
+            assert_eq!(packet.message(), msg);
+        }
+
+        client
+            .write_packet(&publish_packet(QoS::AtLeastOnce, &[0xff, 0xfe], true, 3))
+            .await;
+        let ack_packet: v5::PublishAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), PacketId::new(3));
+        assert_eq!(
+            ack_packet.reason_code(),
+            v5::ReasonCode::PayloadFormatInvalid
+        );
+
+        client
+            .write_packet(&publish_packet(QoS::ExactOnce, &[0xff, 0xfe], true, 4))
+            .await;
+        let ack_packet: v5::PublishReceivedPacket = client.read_packet().await;
+        assert_eq!(ack_packet.packet_id(), PacketId::new(4));
+        assert_eq!(
+            ack_packet.reason_code(),
+            v5::ReasonCode::PayloadFormatInvalid
+        );
+
+        client
+            .write_packet(&publish_packet(QoS::AtMostOnce, &[0xff, 0xfe], true, 5))
+            .await;
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::PayloadFormatInvalid);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_response_information() {
+        let mut config = SessionConfig::new();
+        config.set_response_information("responses/%c/");
+
+        // Response information is not sent if client does not request it.
+        let (_client, ack_packet) = connect_v5(config.clone()).await;
+        assert!(!ack_packet
+            .properties()
+            .props()
+            .iter()
+            .any(|property| matches!(property, v5::Property::ResponseInformation(_))));
+
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet
+            .properties_mut()
+            .push(v5::Property::RequestResponseInformation(BoolData::new(
+                true,
+            )))
+            .unwrap();
+        let (_client, ack_packet) = connect_v5_with_packet(config, &connect_packet).await;
+        assert!(ack_packet
+            .properties()
+            .props()
+            .contains(&v5::Property::ResponseInformation(
+                StringData::from("responses/session-test/").unwrap()
+            )));
+    }
+
+    /// Send Authentication Data back to client, and accept client if it is returned.
+    #[derive(Debug)]
+    struct EchoAuth;
+
+    #[derive(Debug, Default)]
+    struct EchoExchange {
+        challenge: Option<Vec<u8>>,
+    }
+
+    impl AuthExchange for EchoExchange {
+        fn step<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, AuthStep> {
+            Box::pin(async move {
+                match self.challenge.take() {
+                    None => {
+                        self.challenge = Some(data.to_vec());
+                        AuthStep::Continue(data.to_vec())
+                    }
+                    Some(challenge) if challenge == data => {
+                        AuthStep::Success("echo-user".to_string(), b"welcome".to_vec())
+                    }
+                    Some(_) => AuthStep::Failure,
+                }
+            })
+        }
+    }
+
+    impl AuthMethod for EchoAuth {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn start(&self, _client_id: &str) -> Box<dyn AuthExchange> {
+            Box::<EchoExchange>::default()
+        }
+    }
+
+    fn push_auth_properties(properties: &mut v5::Properties, method: &str, data: &[u8]) {
+        properties
+            .push(v5::Property::AuthenticationMethod(
+                StringData::from(method).unwrap(),
+            ))
+            .unwrap();
+        properties
+            .push(v5::Property::AuthenticationData(
+                BinaryData::from_slice(data).unwrap(),
+            ))
+            .unwrap();
+    }
+
+    /// Send connect packet with Authentication Method and Authentication Data.
+    async fn connect_with_auth(method: &str, data: &[u8]) -> Client {
+        connect_with_auth_username(method, data, None).await
+    }
+
+    async fn connect_with_auth_username(
+        method: &str,
+        data: &[u8],
+        username: Option<&str>,
+    ) -> Client {
+        let mut config = SessionConfig::new();
+        config.set_auth_methods(&[Arc::new(EchoAuth) as Arc<dyn AuthMethod>]);
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_username(username).unwrap();
+        push_auth_properties(connect_packet.properties_mut(), method, data);
+        let mut client = start_session(config);
+        client.write_packet(&connect_packet).await;
+        client
+    }
+
+    async fn send_auth_data(client: &mut Client, data: &[u8]) {
+        let mut packet = v5::AuthPacket::new();
+        packet.set_reason_code(v5::ReasonCode::ContinueAuthentication);
+        push_auth_properties(packet.properties_mut(), "echo", data);
+        client.write_packet(&packet).await;
+    }
+
+    #[tokio::test]
+    async fn test_enhanced_auth() {
+        let mut client = connect_with_auth("echo", b"hello").await;
+        let packet: v5::AuthPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::ContinueAuthentication);
+        assert!(packet
+            .properties()
+            .props()
+            .contains(&v5::Property::AuthenticationData(
+                BinaryData::from_slice(b"hello").unwrap()
+            )));
+
+        send_auth_data(&mut client, b"hello").await;
+        // Authenticated identity is used as username.
+        let Some(SessionToListenerCmd::ConnectV5(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected connect cmd");
+        };
+        assert_eq!(packet.username(), "echo-user");
+        let ack_packet = v5::ConnectAckPacket::new(false, v5::ReasonCode::Success);
+        client
+            .sender
+            .send(ListenerToSessionCmd::ConnectAckV5(ack_packet, None))
+            .await
+            .unwrap();
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+        let props = ack_packet.properties().props();
+        assert!(props.contains(&v5::Property::AuthenticationMethod(
+            StringData::from("echo").unwrap()
+        )));
+        assert!(props.contains(&v5::Property::AuthenticationData(
+            BinaryData::from_slice(b"welcome").unwrap()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_enhanced_auth_failed() {
+        let mut client = connect_with_auth("echo", b"hello").await;
+        let _packet: v5::AuthPacket = client.read_packet().await;
+        send_auth_data(&mut client, b"world").await;
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::NotAuthorized);
+        assert_closed(&mut client).await;
+
+        // Other packets are not allowed during authentication.
+        let mut client = connect_with_auth("echo", b"hello").await;
+        let _packet: v5::AuthPacket = client.read_packet().await;
+        client.write_packet(&v5::PingRequestPacket::new()).await;
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::ProtocolError);
+        assert_closed(&mut client).await;
+
+        let mut client = connect_with_auth("SCRAM-SHA-1", b"hello").await;
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(
+            ack_packet.reason_code(),
+            v5::ReasonCode::BadAuthenticationMethod
+        );
+        assert_closed(&mut client).await;
+
+        // Username shall be the authenticated identity.
+        let mut client = connect_with_auth_username("echo", b"hello", Some("admin")).await;
+        let _packet: v5::AuthPacket = client.read_packet().await;
+        send_auth_data(&mut client, b"hello").await;
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::NotAuthorized);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_subscription_unavailable() {
+        let mut config = SessionConfig::new();
+        config.set_wildcard_subscription_available(false);
+        let mut client = connect(config, None).await;
+
+        let mut packet =
+            v3::SubscribePacket::new("sport/+", QoS::AtMostOnce, PacketId::new(3)).unwrap();
+        packet.mut_topics().extend([
+            v3::SubscribeTopic::new("sport/tennis", QoS::AtMostOnce).unwrap(),
+            v3::SubscribeTopic::new("#", QoS::AtMostOnce).unwrap(),
+        ]);
+        client.write_packet(&packet).await;
+        let Some(SessionToListenerCmd::Subscribe(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected subscribe cmd");
+        };
+        assert_eq!(packet.topics().len(), 1);
+        assert_eq!(packet.topics()[0].topic(), "sport/tennis");
+
+        let ack_packet =
+            v3::SubscribeAckPacket::new(PacketId::new(3), v3::SubscribeAck::QoS(QoS::AtMostOnce));
+        client
+            .sender
+            .send(ListenerToSessionCmd::SubscribeAck(ack_packet))
+            .await
+            .unwrap();
+        let ack_packet: v3::SubscribeAckPacket = client.read_packet().await;
+        assert_eq!(
+            ack_packet.acknowledgements(),
+            [
+                v3::SubscribeAck::Failed,
+                v3::SubscribeAck::QoS(QoS::AtMostOnce),
+                v3::SubscribeAck::Failed,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_subscription_unavailable_v5() {
+        let mut config = SessionConfig::new();
+        config.set_wildcard_subscription_available(false);
+        let (mut client, ack_packet) = connect_v5(config).await;
+        assert!(ack_packet.properties().props().contains(
+            &v5::Property::WildcardSubscriptionAvailable(BoolData::new(false))
+        ));
+
+        // Rejected without asking listener.
+        let packet =
+            v5::SubscribePacket::new("sport/#", QoS::AtMostOnce, PacketId::new(3)).unwrap();
+        client.write_packet(&packet).await;
+        let ack_packet: v5::SubscribeAckPacket = client.read_packet().await;
+        assert_eq!(
+            ack_packet.reasons(),
+            [v5::ReasonCode::WildcardSubscriptionsNotSupported]
+        );
+
+        // Literal topic filter is accepted.
+        let packet =
+            v5::SubscribePacket::new("sport/tennis", QoS::AtMostOnce, PacketId::new(4)).unwrap();
+        client.write_packet(&packet).await;
+        let Some(SessionToListenerCmd::SubscribeV5(1, packet)) = client.receiver.recv().await
+        else {
+            panic!("Expected subscribe cmd");
+        };
+        assert_eq!(packet.topics()[0].topic(), "sport/tennis");
+    }
+
+    #[tokio::test]
+    async fn test_packet_before_connect() {
+        let mut client = start_session(SessionConfig::new());
+        client.write_packet(&v3::PingRequestPacket::new()).await;
+        let _elapsed = wait_disconnect(&mut client).await;
+        let mut buf = Vec::new();
+        assert_eq!(client.stream.read_buf(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_server_only_packet() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let packet = v3::SubscribeAckPacket::new(PacketId::new(1), v3::SubscribeAck::Failed);
+        client.write_packet(&packet).await;
+        let _elapsed = wait_disconnect(&mut client).await;
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_resend_on_reconnect() {
+        let mut inflight_messages = InflightMessages::new(10);
+        let packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, b"world").unwrap();
+        let packet_id = inflight_messages
+            .push(OutgoingPacket::V3(packet))
+            .unwrap()
+            .packet_id();
+        let cached_session = CachedSession::new("session-test".to_string(), inflight_messages);
+        let mut client = connect(SessionConfig::new(), Some(cached_session)).await;
+
+        let packet: v3::PublishPacket = client.read_packet().await;
+        assert!(packet.dup());
+        assert_eq!(packet.packet_id(), packet_id);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_paced_resend_on_reconnect() {
+        let mut inflight_messages = InflightMessages::new(0);
+        for msg in [b"1", b"2", b"3", b"4", b"5"] {
+            let packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, msg).unwrap();
+            assert!(inflight_messages.push(OutgoingPacket::V3(packet)).is_some());
+        }
+        let cached_session = CachedSession::new("session-test".to_string(), inflight_messages);
+        let mut config = SessionConfig::new();
+        config.set_inflight_window(2).set_retransmit_delay(500);
+        let instant = Instant::now();
+        let mut client = connect(config, Some(cached_session)).await;
+
+        // Nothing is re-sent before the delay, then only as many as inflight window.
+        let first: v3::PublishPacket = client.read_packet().await;
+        assert!(instant.elapsed() >= Duration::from_millis(500));
+        let second: v3::PublishPacket = client.read_packet().await;
+        assert_eq!(first.message(), b"1");
+        assert_eq!(second.message(), b"2");
+        assert!(first.dup() && second.dup());
+        let read_more = client.read_packet::<v3::PublishPacket>();
+        assert!(tokio::time::timeout(Duration::from_secs(5), read_more)
+            .await
+            .is_err());
+
+        // New message is queued after the re-sent ones.
+        let packet = v3::PublishPacket::new("hello", QoS::AtLeastOnce, b"6").unwrap();
+        client
+            .sender
+            .send(ListenerToSessionCmd::Publish(packet))
+            .await
+            .unwrap();
+
+        // Every acknowledgement frees one slot for the next message.
+        let mut next_ack = first.packet_id();
+        for msg in [b"3", b"4", b"5", b"6"] {
+            client
+                .write_packet(&v3::PublishAckPacket::new(next_ack))
+                .await;
+            let packet: v3::PublishPacket = client.read_packet().await;
+            assert_eq!(packet.message(), msg);
+            assert_eq!(packet.dup(), msg != b"6");
+            next_ack = PacketId::new(next_ack.value() + 1);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_client_ping() {
+        let mut connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_keep_alive(10);
+        let mut client = connect_with_packet(SessionConfig::new(), None, &connect_packet).await;
+
+        // Keep pinging for longer than 1.5 times of keep alive.
+        for _i in 0..3 {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            client.write_packet(&v3::PingRequestPacket::new()).await;
+            let instant = Instant::now();
+            let _ping_resp: v3::PingResponsePacket = client.read_packet().await;
+            assert!(instant.elapsed() < Duration::from_secs(10));
+        }
+        assert!(client.receiver.try_recv().is_err());
+    }
+
+    /// Wait for session to disconnect itself, returns elapsed time.
+    async fn wait_disconnect(client: &mut Client) -> Duration {
+        let instant = Instant::now();
+        while let Some(cmd) = client.receiver.recv().await {
+            if matches!(cmd, SessionToListenerCmd::Disconnect(1)) {
+                return instant.elapsed();
+            }
+        }
+        panic!("Expected disconnect cmd");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_from_connect() {
+        let mut connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_keep_alive(10);
+        let mut client = connect_with_packet(SessionConfig::new(), None, &connect_packet).await;
+
+        // Session is closed after 1.5 times of keep alive without any traffic.
+        let elapsed = wait_disconnect(&mut client).await;
+        assert!(elapsed >= Duration::from_secs(15));
+        assert!(elapsed < Duration::from_secs(16));
+    }
+
+    /// Stream is closed by session without sending any more bytes.
+    async fn assert_closed(client: &mut Client) {
+        assert!(client.buf.is_empty());
+        let mut buf = Vec::new();
+        assert_eq!(client.stream.read_buf(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_timeout() {
+        let mut connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_keep_alive(10);
+        let mut client = connect_with_packet(SessionConfig::new(), None, &connect_packet).await;
+
+        // v3 has no DISCONNECT packet sent from server.
+        let _elapsed = wait_disconnect(&mut client).await;
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_timeout_v5() {
+        let (mut client, _ack_packet) = connect_v5(SessionConfig::new()).await;
+        let _elapsed = wait_disconnect(&mut client).await;
+
+        let mut buf = Vec::new();
+        while client.stream.read_buf(&mut buf).await.unwrap() > 0 {}
+        // Fixed header, remaining length, then reason code.
+        assert_eq!(buf[0], 0xe0);
+        assert_eq!(buf[2], 0x8d);
+        let mut ba = ByteArray::new(&buf);
+        let packet = v5::DisconnectPacket::decode(&mut ba).unwrap();
+        assert_eq!(packet.reason_code(), v5::ReasonCode::KeepAliveTimeout);
+        assert_eq!(ba.remaining_bytes(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_fallback() {
+        let mut config = SessionConfig::new();
+        config.set_keep_alive(10);
+        let mut connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_keep_alive(0);
+        let mut client = connect_with_packet(config, None, &connect_packet).await;
+
+        // PINGREQ resets keep alive timer.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        client.write_packet(&v3::PingRequestPacket::new()).await;
+        let _ping_resp: v3::PingResponsePacket = client.read_packet().await;
+
+        let elapsed = wait_disconnect(&mut client).await;
+        assert!(elapsed >= Duration::from_secs(15));
+        assert!(elapsed < Duration::from_secs(16));
+    }
+
+    fn new_will_connect_packet() -> v3::ConnectPacket {
+        let mut connect_packet = v3::ConnectPacket::new("session-test").unwrap();
+        let mut flags = connect_packet.connect_flags().clone();
+        flags.set_will(true).set_will_qos(QoS::AtLeastOnce);
+        connect_packet.set_connect_flags(flags);
+        connect_packet.set_will_topic("will/session-test").unwrap();
+        connect_packet.set_will_message(b"offline").unwrap();
+        connect_packet
+    }
+
+    #[tokio::test]
+    async fn test_will_on_stream_closed() {
+        let connect_packet = new_will_connect_packet();
+        let client = connect_with_packet(SessionConfig::new(), None, &connect_packet).await;
+        let Client {
+            stream,
+            mut receiver,
+            ..
+        } = client;
+        drop(stream);
+
+        let Some(SessionToListenerCmd::Publish(1, packet)) = receiver.recv().await else {
+            panic!("Expected will message");
+        };
+        assert_eq!(packet.topic(), "will/session-test");
+        assert_eq!(packet.message(), b"offline");
+        assert_eq!(packet.qos(), QoS::AtLeastOnce);
+    }
+
+    #[tokio::test]
+    async fn test_will_discarded_on_disconnect() {
+        let connect_packet = new_will_connect_packet();
+        let mut client = connect_with_packet(SessionConfig::new(), None, &connect_packet).await;
+        client.write_packet(&v3::DisconnectPacket::new()).await;
+
+        while let Some(cmd) = client.receiver.recv().await {
+            assert!(!matches!(cmd, SessionToListenerCmd::Publish(..)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_will_payload_within_limit() {
+        let mut config = SessionConfig::new();
+        config.set_max_will_payload_size(b"offline".len());
+        let connect_packet = new_will_connect_packet();
+        let _client = connect_with_packet(config, None, &connect_packet).await;
+    }
+
+    #[tokio::test]
+    async fn test_will_payload_too_large() {
+        let mut config = SessionConfig::new();
+        config.set_max_will_payload_size(4);
+        let mut client = start_session(config);
+        client.write_packet(&new_will_connect_packet()).await;
+
+        // v3 has no suitable return code, connection is closed without CONNACK.
+        assert_closed(&mut client).await;
+        assert!(!matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Connect(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_will_payload_too_large_v5() {
+        let mut config = SessionConfig::new();
+        config.set_max_will_payload_size(4);
+        let mut client = start_session(config);
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet.set_will(true);
+        connect_packet.set_will_topic("will/session-test").unwrap();
+        connect_packet.set_will_message(b"offline").unwrap();
+        client.write_packet(&connect_packet).await;
+
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::PacketTooLarge);
+        assert!(!ack_packet.session_present());
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_capture_packets() {
+        let directory = std::env::temp_dir().join(format!("hebo-capture-{}", std::process::id()));
+        let capture: Capture = toml::from_str(&format!(
+            "client_ids = [\"session-test\"]\ndirectory = {directory:?}"
+        ))
+        .unwrap();
+        let mut config = SessionConfig::new();
+        config.set_capture(&capture);
+        let mut client = connect(config, None).await;
+
+        let packet = v3::PublishPacket::new("hello", QoS::AtMostOnce, b"world").unwrap();
+        client.write_packet(&packet).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Publish(1, _))
+        ));
+        // Packets are handled in order, publish packet has been recorded when ping resp is received.
+        client.write_packet(&v3::PingRequestPacket::new()).await;
+        let _ping_resp: v3::PingResponsePacket = client.read_packet().await;
+
+        // Capture file is written in background.
+        let path = directory.join("session_test.cap");
+        let mut content = String::new();
+        for _ in 0..50 {
+            content = std::fs::read_to_string(&path).unwrap_or_default();
+            if content.lines().count() >= 4 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+        let lines: Vec<Vec<&str>> = content
+            .lines()
+            .map(|line| line.splitn(4, ' ').collect())
+            .collect();
+        let mut buf = Vec::new();
+        v3::ConnectPacket::new("session-test")
+            .unwrap()
+            .encode(&mut buf)
+            .unwrap();
+        let connect_hex = buf.iter().fold(String::new(), |mut hex, byte| {
+            let _ret = write!(hex, "{byte:02x}");
+            hex
+        });
+        assert_eq!(lines[0][1..], ["RECV", "Connect", connect_hex.as_str()]);
+        assert_eq!(lines[1][1..3], ["SEND", "ConnectAck"]);
+        assert_eq!(lines[2][1], "RECV");
+        assert!(lines[2][2].starts_with("Publish"));
+    }
+
+    /// Write packet to stream byte by byte.
+    async fn dribble(client: &mut Client, bytes: &[u8]) {
+        for byte in bytes {
+            client.stream.write_all(&[*byte]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_partial_packet() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let packet = v3::PublishPacket::new("hello", QoS::AtMostOnce, b"world").unwrap();
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        dribble(&mut client, &buf).await;
+
+        let Some(SessionToListenerCmd::Publish(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        assert_eq!(packet.message(), b"world");
+    }
+
+    #[tokio::test]
+    async fn test_read_multiple_packets() {
+        let mut client = connect(SessionConfig::new(), None).await;
+        let mut buf = Vec::new();
+        for msg in [b"first", b"secnd"] {
+            let packet = v3::PublishPacket::new("hello", QoS::AtMostOnce, msg).unwrap();
+            packet.encode(&mut buf).unwrap();
+        }
+        v3::PingRequestPacket::new().encode(&mut buf).unwrap();
+        client.stream.write_all(&buf).await.unwrap();
+
+        for msg in [b"first", b"secnd"] {
+            let Some(SessionToListenerCmd::Publish(1, packet)) = client.receiver.recv().await
+            else {
+                panic!("Expected publish cmd");
+            };
+            assert_eq!(packet.message(), msg);
+        }
+        let _ping_resp: v3::PingResponsePacket = client.read_packet().await;
+    }
+
+    #[tokio::test]
+    async fn test_read_buffer_cap() {
+        let mut config = SessionConfig::new();
+        config.set_read_buffer_cap(64);
+        let mut client = connect(config, None).await;
+
+        // Fixed header of a PUBLISH packet with 1000 bytes remaining length.
+        dribble(&mut client, &[0x30, 0xe8, 0x07]).await;
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Disconnect(1))
+        ));
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_maximum_packet_size_v5() {
+        let mut config = SessionConfig::new();
+        config.set_read_buffer_cap(64);
+        let (mut client, ack_packet) = connect_v5(config).await;
+        assert!(ack_packet
+            .properties()
+            .props()
+            .contains(&v5::Property::MaximumPacketSize(U32Data::new(64))));
+
+        let packet = v5::PublishPacket::new("hello", QoS::AtMostOnce, &[b'a'; 100]).unwrap();
+        client.write_packet(&packet).await;
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::PacketTooLarge);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_maximum_packet_size() {
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet
+            .properties_mut()
+            .push(v5::Property::MaximumPacketSize(U32Data::new(64)))
+            .unwrap();
+        let mut config = SessionConfig::new();
+        config.set_inflight_window(1);
+        let (mut client, _ack_packet) = connect_v5_with_packet(config, &connect_packet).await;
+
+        // Large message is skipped without waiting for its ack.
+        for msg in [&[b'a'; 100][..], b"small"] {
+            let packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::PublishV5(packet))
+                .await
+                .unwrap();
+        }
+        let packet: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(packet.message(), b"small");
+    }
+
+    #[tokio::test]
+    async fn test_receive_maximum_v5() {
+        let mut config = SessionConfig::new();
+        config.set_maximum_inflight_messages(2);
+        let (mut client, ack_packet) = connect_v5(config).await;
+        assert!(ack_packet
+            .properties()
+            .props()
+            .contains(&v5::Property::ReceiveMaximum(U16Data::new(2))));
+
+        let publish = |packet_id: u16, dup: bool| {
+            let mut packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, b"1").unwrap();
+            packet.set_packet_id(PacketId::new(packet_id));
+            packet.set_dup(dup).unwrap();
+            packet
+        };
+        client.write_packet(&publish(1, false)).await;
+        client.write_packet(&publish(2, false)).await;
+        // Re-sent packet is not counted again.
+        client.write_packet(&publish(1, true)).await;
+        for _ in 0..3 {
+            assert!(matches!(
+                client.receiver.recv().await,
+                Some(SessionToListenerCmd::PublishV5(1, _))
+            ));
+        }
+
+        client.write_packet(&publish(3, false)).await;
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::ReceiveMaximumExceeded);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_receive_maximum_zero() {
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet
+            .properties_mut()
+            .push(v5::Property::ReceiveMaximum(U16Data::new(0)))
+            .unwrap();
+        let mut client = start_session(SessionConfig::new());
+        client.write_packet(&connect_packet).await;
+        let ack_packet: v5::ConnectAckPacket = client.read_packet().await;
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::ProtocolError);
+        assert_closed(&mut client).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_receive_maximum() {
+        let mut connect_packet = v5::ConnectPacket::new("session-test").unwrap();
+        connect_packet
+            .properties_mut()
+            .push(v5::Property::ReceiveMaximum(U16Data::new(2)))
+            .unwrap();
+        let (mut client, _ack_packet) =
+            connect_v5_with_packet(SessionConfig::new(), &connect_packet).await;
+
+        for msg in [b"1", b"2", b"3"] {
+            let packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, msg).unwrap();
+            client
+                .sender
+                .send(ListenerToSessionCmd::PublishV5(packet))
+                .await
+                .unwrap();
+        }
+        let first: v5::PublishPacket = client.read_packet().await;
+        let second: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(first.message(), b"1");
+        assert_eq!(second.message(), b"2");
+
+        // Blocked until an inflight message is acknowledged.
+        let ret = tokio::time::timeout(
+            Duration::from_millis(200),
+            client.read_packet::<v5::PublishPacket>(),
+        )
+        .await;
+        assert!(ret.is_err());
+        client
+            .write_packet(&v5::PublishAckPacket::new(first.packet_id()))
+            .await;
+        let third: v5::PublishPacket = client.read_packet().await;
+        assert_eq!(third.message(), b"3");
+    }
+
+    fn publish_v5_with_alias(topic: &str, alias: u16) -> v5::PublishPacket {
+        let mut packet = v5::PublishPacket::new("placeholder", QoS::AtMostOnce, b"21").unwrap();
+        if topic.is_empty() {
+            packet.clear_topic();
+        } else {
+            packet.set_topic(topic).unwrap();
+        }
+        packet
+            .properties_mut()
+            .push(v5::Property::TopicAlias(U16Data::new(alias)))
+            .unwrap();
+        packet
+    }
+
+    #[tokio::test]
+    async fn test_topic_alias() {
+        let (mut client, ack_packet) = connect_v5(SessionConfig::new()).await;
+        assert!(ack_packet
+            .properties()
+            .props()
+            .contains(&v5::Property::TopicAliasMaximum(U16Data::new(10))));
+
+        // Set alias.
+        client
+            .write_packet(&publish_v5_with_alias("sensors/temperature", 1))
+            .await;
+        let Some(SessionToListenerCmd::PublishV5(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        assert_eq!(packet.topic(), "sensors/temperature");
+        assert!(packet.properties().is_empty());
+
+        // Use alias with empty topic name.
+        client.write_packet(&publish_v5_with_alias("", 1)).await;
+        let Some(SessionToListenerCmd::PublishV5(1, packet)) = client.receiver.recv().await else {
+            panic!("Expected publish cmd");
+        };
+        assert_eq!(packet.topic(), "sensors/temperature");
+        assert_eq!(packet.message(), b"21");
+        assert!(packet.properties().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_topic_alias_invalid() {
+        for alias in [0, 3] {
+            let mut config = SessionConfig::new();
+            config.set_topic_alias_maximum(2);
+            let (mut client, ack_packet) = connect_v5(config).await;
+            assert!(ack_packet
+                .properties()
+                .props()
+                .contains(&v5::Property::TopicAliasMaximum(U16Data::new(2))));
+
+            client
+                .write_packet(&publish_v5_with_alias("sensors/temperature", alias))
+                .await;
+            let packet: v5::DisconnectPacket = client.read_packet().await;
+            assert_eq!(packet.reason_code(), v5::ReasonCode::TopicAliasInvalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_topic_alias_unknown() {
+        let (mut client, _ack_packet) = connect_v5(SessionConfig::new()).await;
+        client.write_packet(&publish_v5_with_alias("", 1)).await;
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::ProtocolError);
+    }
+
+    fn new_publish_packet() -> v3::PublishPacket {
+        v3::PublishPacket::new("hello", QoS::AtMostOnce, b"world").unwrap()
+    }
+
+    /// Wait for next publish cmd sent to listener.
+    async fn recv_publish(client: &mut Client) {
+        assert!(matches!(
+            client.receiver.recv().await,
+            Some(SessionToListenerCmd::Publish(1, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_throttle() {
+        let mut config = SessionConfig::new();
+        config.set_max_message_rate(10);
+        let mut client = connect(config, None).await;
+
+        // Burst is allowed, then reading is paused until tokens are refilled.
+        let instant = Instant::now();
+        for _i in 0..13 {
+            client.write_packet(&new_publish_packet()).await;
+        }
+        for _i in 0..11 {
+            recv_publish(&mut client).await;
+        }
+        assert!(instant.elapsed() < Duration::from_millis(50));
+        recv_publish(&mut client).await;
+        assert!(instant.elapsed() >= Duration::from_millis(100));
+        recv_publish(&mut client).await;
+        assert!(instant.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_slow_publisher() {
+        let mut config = SessionConfig::new();
+        config.set_max_message_rate(10).set_max_byte_rate(1000);
+        let mut client = connect(config, None).await;
+
+        for _i in 0..30 {
+            let instant = Instant::now();
+            client.write_packet(&new_publish_packet()).await;
+            recv_publish(&mut client).await;
+            assert!(instant.elapsed() < Duration::from_millis(50));
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_disconnect_v5() {
+        let mut config = SessionConfig::new();
+        config
+            .set_max_message_rate(1)
+            .set_rate_limit_policy(RateLimitPolicy::Disconnect);
+        let (mut client, _ack_packet) = connect_v5(config).await;
+        for _i in 0..2 {
+            let packet = v5::PublishPacket::new("hello", QoS::AtMostOnce, b"world").unwrap();
+            client.write_packet(&packet).await;
+        }
+        let packet: v5::DisconnectPacket = client.read_packet().await;
+        assert_eq!(packet.reason_code(), v5::ReasonCode::QuotaExceeded);
+    }
 }
@@ -0,0 +1,53 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Validate payload of publish packets declared as UTF-8 string.
+
+use codec::{v5, QoS};
+
+use super::Session;
+use crate::error::Error;
+
+impl Session {
+    /// Returns true if payload of `packet` is not valid UTF-8 string while its
+    /// Payload Format Indicator property is 1, and validation is enabled.
+    pub(super) fn is_payload_format_invalid(&self, packet: &v5::PublishPacket) -> bool {
+        if !self.config.validate_payload_format() {
+            return false;
+        }
+        let is_utf8 = packet.properties().props().iter().any(|property| {
+            matches!(property, v5::Property::PayloadFormatIndicator(indicator) if indicator.value())
+        });
+        is_utf8 && std::str::from_utf8(packet.message()).is_err()
+    }
+
+    /// Reject publish packet with invalid payload format.
+    ///
+    /// The receiver sends a PUBACK, PUBREC or DISCONNECT with Reason Code
+    /// of 0x99 (Payload format invalid).
+    pub(super) async fn reject_invalid_payload_format(
+        &mut self,
+        packet: &v5::PublishPacket,
+    ) -> Result<(), Error> {
+        log::warn!(
+            "session: {} published invalid UTF-8 payload to {}",
+            self.client_id,
+            packet.topic()
+        );
+        let reason_code = v5::ReasonCode::PayloadFormatInvalid;
+        match packet.qos() {
+            QoS::AtMostOnce => self.send_disconnect(reason_code).await,
+            QoS::AtLeastOnce => {
+                let mut ack_packet = v5::PublishAckPacket::new(packet.packet_id());
+                ack_packet.set_reason_code(reason_code);
+                self.send(ack_packet).await
+            }
+            QoS::ExactOnce => {
+                let mut ack_packet = v5::PublishReceivedPacket::new(packet.packet_id());
+                ack_packet.set_reason_code(reason_code);
+                self.send(ack_packet).await
+            }
+        }
+    }
+}
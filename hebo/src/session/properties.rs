@@ -2,21 +2,39 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use codec::v5;
+use codec::{v5, BoolData, EncodeError, Packet, QoS, StringData, U16Data, U32Data};
+use std::time::Duration;
 
+use super::config::MAXIMUM_PACKET_SIZE;
 use super::Session;
+use crate::error::Error;
 
 impl Session {
     /// Handle properties in connect packet.
-    pub(super) fn process_connect_properties(&mut self, packet: &v5::ConnectPacket) {
+    ///
+    /// Returns reason code of connect ack packet if any property is invalid.
+    pub(super) fn process_connect_properties(
+        &mut self,
+        packet: &v5::ConnectPacket,
+    ) -> Result<(), v5::ReasonCode> {
         for property in packet.properties().as_ref() {
             match property {
                 v5::Property::SessionExpiryInterval(interval) => {
                     self.config.set_session_expiry_interval(interval.value());
                 }
                 v5::Property::ReceiveMaximum(receive) => {
-                    // TODO(Shaohua): Check receive > 0
-                    self.config.set_maximum_inflight_messages(receive.value());
+                    // It is a Protocol Error to include the Receive Maximum value
+                    // more than once or for it to have the value 0.
+                    if receive.value() == 0 {
+                        return Err(v5::ReasonCode::ProtocolError);
+                    }
+                    // Do not send more unacknowledged messages than client can process.
+                    let window = self.config.inflight_window();
+                    if window == 0 || usize::from(receive.value()) < window {
+                        self.config.set_inflight_window(receive.value());
+                        self.inflight_messages
+                            .set_window(usize::from(receive.value()));
+                    }
                 }
                 v5::Property::MaximumPacketSize(packet_size) => {
                     self.config.set_maximum_packet_size(packet_size.value());
@@ -24,10 +42,150 @@ impl Session {
                 v5::Property::TopicAliasMaximum(topic_alias) => {
                     self.config.set_maximum_topic_alias(topic_alias.value());
                 }
+                v5::Property::RequestResponseInformation(request) => {
+                    self.config
+                        .set_request_response_information(request.value());
+                }
                 _ => {
                     // todo!()
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Add Receive Maximum property to connect ack packet, which limits `QoS` 1 and `QoS` 2
+    /// messages sent by client but not acknowledged yet.
+    pub(super) fn add_receive_maximum(
+        &self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        // If the Receive Maximum value is absent, then its value defaults to 65,535.
+        let receive_maximum = self.config.maximum_inflight_messages();
+        if receive_maximum > 0 && receive_maximum < usize::from(u16::MAX) {
+            #[allow(clippy::cast_possible_truncation)]
+            packet
+                .properties_mut()
+                .push(v5::Property::ReceiveMaximum(U16Data::new(
+                    receive_maximum as u16,
+                )))?;
+        }
+        Ok(())
+    }
+
+    /// Add Maximum `QoS` property to connect ack packet if server does not support `QoS` 2.
+    pub(super) fn add_maximum_qos(&self, packet: &mut v5::ConnectAckPacket) -> Result<(), Error> {
+        // If the Maximum QoS is absent, the Client uses a Maximum QoS of 2.
+        let maximum_qos = self.config.maximum_qos();
+        if maximum_qos != QoS::ExactOnce {
+            packet
+                .properties_mut()
+                .push(v5::Property::MaximumQoS(maximum_qos))?;
+        }
+        Ok(())
+    }
+
+    /// Add Wildcard Subscription Available property to connect ack packet
+    /// if wildcard subscriptions are not supported.
+    pub(super) fn add_wildcard_subscription_available(
+        &self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        // If not present, then Wildcard Subscriptions are supported.
+        if !self.config.wildcard_subscription_available() {
+            packet
+                .properties_mut()
+                .push(v5::Property::WildcardSubscriptionAvailable(BoolData::new(
+                    false,
+                )))?;
+        }
+        Ok(())
+    }
+
+    /// Add Response Information property to connect ack packet if client requests it.
+    pub(super) fn add_response_information(
+        &self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        // The Client uses this value to request the Server to return Response Information
+        // in the CONNACK. A value of 0 indicates that the Server MUST NOT return
+        // Response Information [MQTT-3.1.2-28].
+        let response_information = self.config.response_information();
+        if self.config.request_response_information() && !response_information.is_empty() {
+            let response_information = response_information.replace("%c", &self.client_id);
+            let response_information =
+                StringData::from(&response_information).map_err(EncodeError::from)?;
+            packet
+                .properties_mut()
+                .push(v5::Property::ResponseInformation(response_information))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if client has sent Receive Maximum `QoS` 1 and `QoS` 2 messages
+    /// which are not acknowledged yet.
+    pub(super) fn is_receive_maximum_reached(&self) -> bool {
+        self.pub_ack_packets.len() + self.pub_recv_packets.len()
+            >= self.config.maximum_inflight_messages()
+    }
+
+    /// Add Maximum Packet Size property to connect ack packet if read buffer cap is set.
+    pub(super) fn add_maximum_packet_size(
+        &self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        // If the Maximum Packet Size is not present, there is no limit on the packet size
+        // imposed beyond the limitations in the protocol.
+        let read_buffer_cap = self.config.read_buffer_cap();
+        if read_buffer_cap < MAXIMUM_PACKET_SIZE {
+            #[allow(clippy::cast_possible_truncation)]
+            packet
+                .properties_mut()
+                .push(v5::Property::MaximumPacketSize(U32Data::new(
+                    read_buffer_cap as u32,
+                )))?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if `packet` is larger than maximum packet size accepted by client.
+    pub(super) fn exceeds_maximum_packet_size<P: Packet>(&self, packet: &P) -> bool {
+        let maximum_packet_size = self.config.maximum_packet_size();
+        maximum_packet_size > 0
+            && packet
+                .bytes()
+                .map_or(true, |bytes| bytes > maximum_packet_size)
+    }
+
+    /// Handle properties in disconnect packet.
+    pub(super) fn process_disconnect_properties(&mut self, packet: &v5::DisconnectPacket) {
+        for property in packet.properties().as_ref() {
+            if let v5::Property::SessionExpiryInterval(interval) = property {
+                // If the Session Expiry Interval in the CONNECT packet was zero, then it is
+                // a Protocol Error to set a non-zero Session Expiry Interval in the DISCONNECT
+                // packet sent by the Client.
+                if self.config.session_expiry_interval() == Some(Duration::ZERO)
+                    && interval.value() != 0
+                {
+                    log::warn!(
+                        "session: Ignore session expiry interval in disconnect packet of {}",
+                        self.client_id
+                    );
+                } else {
+                    self.config.set_session_expiry_interval(interval.value());
+                }
+            }
+        }
+    }
+
+    /// Remove broker managed properties of publish packet received from client.
+    ///
+    /// Other properties, including user properties, are forwarded to subscribers unchanged.
+    pub(super) fn remove_broker_properties(packet: &mut v5::PublishPacket) {
+        // A PUBLISH packet sent from a Client to a Server MUST NOT contain
+        // a Subscription Identifier [MQTT-3.3.4-6]. It is set by server per subscription.
+        packet
+            .properties_mut()
+            .retain(|property| !matches!(property, v5::Property::SubscriptionIdentifier(_)));
     }
 }
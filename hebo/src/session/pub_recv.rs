@@ -0,0 +1,125 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Track `QoS` 2 messages received from client.
+
+use codec::PacketId;
+use std::collections::HashMap;
+
+/// State of `QoS` 2 handshake on receiver side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PubRecvState {
+    /// PUBLISH packet is received and forwarded to listener, waiting for its ack.
+    Received,
+
+    /// PUBLISH packet is accepted by listener, PUBREC is sent to client.
+    Acknowledged,
+
+    /// PUBREL packet is received from client, PUBCOMP is going to be sent.
+    Released,
+}
+
+/// `QoS` 2 packet ids which are not fully acknowledged yet.
+#[derive(Debug, Default, Clone)]
+pub struct PubRecvPackets {
+    map: HashMap<PacketId, PubRecvState>,
+}
+
+impl PubRecvPackets {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get number of packets in handshake.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[must_use]
+    pub fn state(&self, packet_id: PacketId) -> Option<PubRecvState> {
+        self.map.get(&packet_id).copied()
+    }
+
+    /// Mark a PUBLISH packet as received.
+    ///
+    /// Caller shall check `state()` first, PUBLISH packet with packet id already in handshake
+    /// is a duplicated one and shall not be forwarded to dispatcher again.
+    pub fn receive(&mut self, packet_id: PacketId) {
+        self.map.insert(packet_id, PubRecvState::Received);
+    }
+
+    /// Mark a packet as acknowledged when PUBREC packet is going to be sent.
+    ///
+    /// Returns false if packet id is unknown or already acknowledged.
+    pub fn acknowledge(&mut self, packet_id: PacketId) -> bool {
+        self.transit(
+            packet_id,
+            PubRecvState::Received,
+            PubRecvState::Acknowledged,
+        )
+    }
+
+    /// Mark a packet as released when PUBREL packet is received.
+    ///
+    /// Returns false if packet id is unknown or PUBREC packet is not sent yet.
+    pub fn release(&mut self, packet_id: PacketId) -> bool {
+        self.transit(
+            packet_id,
+            PubRecvState::Acknowledged,
+            PubRecvState::Released,
+        )
+    }
+
+    fn transit(&mut self, packet_id: PacketId, from: PubRecvState, to: PubRecvState) -> bool {
+        match self.map.get_mut(&packet_id) {
+            Some(state) if *state == from => {
+                *state = to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// PUBCOMP packet is sent, the packet id is removed and can be reused by client.
+    pub fn complete(&mut self, packet_id: PacketId) {
+        self.map.remove(&packet_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::PacketId;
+
+    use super::{PubRecvPackets, PubRecvState};
+
+    #[test]
+    fn test_handshake() {
+        let mut packets = PubRecvPackets::new();
+        let packet_id = PacketId::new(7);
+        assert_eq!(packets.state(packet_id), None);
+        packets.receive(packet_id);
+        assert_eq!(packets.state(packet_id), Some(PubRecvState::Received));
+
+        // PUBREL packet is not accepted before PUBREC packet is sent.
+        assert!(!packets.release(packet_id));
+        assert_eq!(packets.state(packet_id), Some(PubRecvState::Received));
+
+        assert!(packets.acknowledge(packet_id));
+        assert!(!packets.acknowledge(packet_id));
+        assert_eq!(packets.state(packet_id), Some(PubRecvState::Acknowledged));
+
+        assert!(packets.release(packet_id));
+        assert_eq!(packets.state(packet_id), Some(PubRecvState::Released));
+        packets.complete(packet_id);
+        assert_eq!(packets.state(packet_id), None);
+        assert_eq!(packets.len(), 0);
+
+        // Packet id can be reused after handshake is completed.
+        assert!(!packets.release(packet_id));
+        packets.receive(packet_id);
+        assert_eq!(packets.state(packet_id), Some(PubRecvState::Received));
+    }
+}
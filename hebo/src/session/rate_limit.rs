@@ -0,0 +1,138 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Limit rate of publish messages received from client.
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Tokens are counted in thousandths, so that partial tokens are refilled.
+const TOKEN_SCALE: i64 = 1000;
+
+/// Token bucket which allows bursts up to `rate` tokens, and refills `rate` tokens
+/// per second.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: i64,
+    /// Available tokens in thousandths, negative if tokens are borrowed.
+    tokens: i64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    #[must_use]
+    pub fn new(rate: u32) -> Self {
+        let rate = i64::from(rate.max(1));
+        Self {
+            rate,
+            tokens: rate * TOKEN_SCALE,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let elapsed = i64::try_from(elapsed.as_micros()).unwrap_or(i64::MAX);
+        let refilled = elapsed.saturating_mul(self.rate) / 1000;
+        self.tokens = self
+            .tokens
+            .saturating_add(refilled)
+            .min(self.rate * TOKEN_SCALE);
+        self.updated_at = now;
+    }
+
+    /// Take `amount` tokens from bucket.
+    ///
+    /// Tokens are borrowed if bucket does not have enough, returns time to wait
+    /// until they are paid back, or zero if not borrowed.
+    pub fn consume(&mut self, amount: usize, now: Instant) -> Duration {
+        self.refill(now);
+        let amount = i64::try_from(amount).unwrap_or(i64::MAX);
+        self.tokens = self
+            .tokens
+            .saturating_sub(amount.saturating_mul(TOKEN_SCALE));
+        if self.tokens >= 0 {
+            Duration::ZERO
+        } else {
+            let micros = self.tokens.unsigned_abs() * 1000 / self.rate.unsigned_abs();
+            Duration::from_micros(micros)
+        }
+    }
+}
+
+/// Limits number of messages and bytes per second of publish packets.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter, returns None if both limits are 0.
+    #[must_use]
+    pub fn new(max_message_rate: u32, max_byte_rate: u32) -> Option<Self> {
+        if max_message_rate == 0 && max_byte_rate == 0 {
+            return None;
+        }
+        Some(Self {
+            messages: (max_message_rate > 0).then(|| TokenBucket::new(max_message_rate)),
+            bytes: (max_byte_rate > 0).then(|| TokenBucket::new(max_byte_rate)),
+        })
+    }
+
+    /// Count a publish packet of `packet_len` bytes.
+    ///
+    /// Returns time to wait before reading more packets, or zero if rate limit
+    /// is not exceeded.
+    pub fn on_publish(&mut self, packet_len: usize) -> Duration {
+        let now = Instant::now();
+        let messages = self
+            .messages
+            .as_mut()
+            .map_or(Duration::ZERO, |bucket| bucket.consume(1, now));
+        let bytes = self
+            .bytes
+            .as_mut()
+            .map_or(Duration::ZERO, |bucket| bucket.consume(packet_len, now));
+        messages.max(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RateLimiter;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_burst_is_throttled() {
+        let mut limiter = RateLimiter::new(5, 0).unwrap();
+        for _i in 0..5 {
+            assert_eq!(limiter.on_publish(10), Duration::ZERO);
+        }
+        assert_eq!(limiter.on_publish(10), Duration::from_millis(200));
+        assert_eq!(limiter.on_publish(10), Duration::from_millis(400));
+
+        // Borrowed tokens are paid back.
+        tokio::time::advance(Duration::from_millis(400)).await;
+        assert_eq!(limiter.on_publish(10), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_publisher() {
+        let mut limiter = RateLimiter::new(5, 1000).unwrap();
+        for _i in 0..20 {
+            assert_eq!(limiter.on_publish(100), Duration::ZERO);
+            tokio::time::advance(Duration::from_millis(200)).await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_byte_rate() {
+        let mut limiter = RateLimiter::new(0, 1000).unwrap();
+        assert_eq!(limiter.on_publish(1000), Duration::ZERO);
+        assert_eq!(limiter.on_publish(500), Duration::from_millis(500));
+        assert!(RateLimiter::new(0, 0).is_none());
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Topic alias of v5 publish packets received from client.
+
+use codec::{v5, U16Data};
+
+use super::Session;
+use crate::error::Error;
+
+impl Session {
+    /// Advertise the highest topic alias accepted by server in connect ack packet.
+    pub(super) fn add_topic_alias_maximum(
+        &self,
+        packet: &mut v5::ConnectAckPacket,
+    ) -> Result<(), Error> {
+        // The Client MUST NOT send a Topic Alias to the Server if this property is absent
+        // or zero [MQTT-3.2.2-17], so it is omitted if topic alias is disabled.
+        let topic_alias_maximum = self.config.topic_alias_maximum();
+        if topic_alias_maximum > 0 {
+            packet
+                .properties_mut()
+                .push(v5::Property::TopicAliasMaximum(U16Data::new(
+                    topic_alias_maximum,
+                )))?;
+        }
+        Ok(())
+    }
+
+    /// Store or resolve topic name with Topic Alias property of publish packet.
+    ///
+    /// Topic Alias property is removed as it is only valid in this network connection.
+    ///
+    /// Returns reason code to disconnect client if topic alias is invalid.
+    pub(super) fn resolve_topic_alias(
+        &mut self,
+        packet: &mut v5::PublishPacket,
+    ) -> Result<(), v5::ReasonCode> {
+        let Some(index) = packet
+            .properties()
+            .props()
+            .iter()
+            .position(|property| matches!(property, v5::Property::TopicAlias(_)))
+        else {
+            return Ok(());
+        };
+        let Ok(v5::Property::TopicAlias(alias)) = packet.properties_mut().remove(index) else {
+            return Ok(());
+        };
+        let alias = alias.value();
+
+        // A Topic Alias of 0 is not permitted [MQTT-3.3.2-8].
+        // A Client MUST NOT send a PUBLISH packet with a Topic Alias greater than
+        // the Topic Alias Maximum value returned by the Server in the CONNACK packet [MQTT-3.3.2-9].
+        if alias == 0 || alias > self.config.topic_alias_maximum() {
+            log::warn!(
+                "session: Invalid topic alias {} from client {}",
+                alias,
+                self.client_id
+            );
+            return Err(v5::ReasonCode::TopicAliasInvalid);
+        }
+
+        if packet.topic().is_empty() {
+            // The receiver uses the Topic Alias to look up the topic name [MQTT-3.3.2-7].
+            let Some(topic) = self.topic_aliases.get(&alias) else {
+                log::warn!(
+                    "session: Unknown topic alias {} from client {}",
+                    alias,
+                    self.client_id
+                );
+                return Err(v5::ReasonCode::ProtocolError);
+            };
+            // Stored topic names have been validated already.
+            if packet.set_topic(topic).is_err() {
+                return Err(v5::ReasonCode::TopicNameInvalid);
+            }
+        } else {
+            // Update mapping of this alias, if it is already used.
+            self.topic_aliases.insert(alias, packet.topic().to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Reject wildcard subscriptions if they are not supported.
+
+use codec::{topic::is_wildcard_filter, v3, v5};
+
+use super::Session;
+
+impl Session {
+    /// Remove topic filters with wildcard characters if wildcard subscriptions
+    /// are not supported.
+    ///
+    /// Returns indices of removed topic filters, which are recorded in `rejected_subscriptions`
+    /// if some topic filters are left in `topics`.
+    fn remove_wildcard_filters<T, F>(&self, topics: &mut Vec<T>, filter: F) -> Vec<usize>
+    where
+        F: Fn(&T) -> &str,
+    {
+        if self.config.wildcard_subscription_available() {
+            return Vec::new();
+        }
+        let rejected: Vec<usize> = topics
+            .iter()
+            .enumerate()
+            .filter_map(|(index, topic)| is_wildcard_filter(filter(topic)).then_some(index))
+            .collect();
+        let mut index = 0;
+        topics.retain(|_topic| {
+            index += 1;
+            !rejected.contains(&(index - 1))
+        });
+        rejected
+    }
+
+    /// Remove wildcard topic filters from subscribe packet.
+    ///
+    /// Returns subscribe ack packet if all of topic filters are rejected.
+    pub(super) fn reject_wildcard_subscriptions(
+        &mut self,
+        packet: &mut v3::SubscribePacket,
+    ) -> Option<v3::SubscribeAckPacket> {
+        let rejected = self.remove_wildcard_filters(packet.mut_topics(), v3::SubscribeTopic::topic);
+        if rejected.is_empty() {
+            return None;
+        }
+        if packet.topics().is_empty() {
+            let acks = vec![v3::SubscribeAck::Failed; rejected.len()];
+            return Some(v3::SubscribeAckPacket::with_vec(packet.packet_id(), acks));
+        }
+        self.rejected_subscriptions
+            .insert(packet.packet_id(), rejected);
+        None
+    }
+
+    pub(super) fn reject_wildcard_subscriptions_v5(
+        &mut self,
+        packet: &mut v5::SubscribePacket,
+    ) -> Option<v5::SubscribeAckPacket> {
+        let rejected = self.remove_wildcard_filters(packet.mut_topics(), v5::SubscribeTopic::topic);
+        if rejected.is_empty() {
+            return None;
+        }
+        if packet.topics().is_empty() {
+            let reasons = vec![v5::ReasonCode::WildcardSubscriptionsNotSupported; rejected.len()];
+            return Some(v5::SubscribeAckPacket::with_vec(
+                packet.packet_id(),
+                reasons,
+            ));
+        }
+        self.rejected_subscriptions
+            .insert(packet.packet_id(), rejected);
+        None
+    }
+
+    /// Insert failure return codes of rejected wildcard topic filters back to
+    /// subscribe ack packet.
+    pub(super) fn insert_rejected_subscriptions(&mut self, packet: &mut v3::SubscribeAckPacket) {
+        if let Some(rejected) = self.rejected_subscriptions.remove(&packet.packet_id()) {
+            let mut acks = packet.acknowledgements().to_vec();
+            for index in rejected {
+                acks.insert(index, v3::SubscribeAck::Failed);
+            }
+            packet.set_ack(&acks);
+        }
+    }
+
+    pub(super) fn insert_rejected_subscriptions_v5(&mut self, packet: &mut v5::SubscribeAckPacket) {
+        if let Some(rejected) = self.rejected_subscriptions.remove(&packet.packet_id()) {
+            let reasons = packet.reasons_mut();
+            for index in rejected {
+                reasons.insert(index, v5::ReasonCode::WildcardSubscriptionsNotSupported);
+            }
+        }
+    }
+}
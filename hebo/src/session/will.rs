@@ -0,0 +1,124 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Will message handling.
+
+use codec::{v3, v5, EncodeError};
+use std::time::Duration;
+
+use super::Session;
+use crate::commands::SessionToListenerCmd;
+
+/// Will message stored in session, published when network connection is closed
+/// without receiving a DISCONNECT packet.
+#[derive(Debug, Clone)]
+pub enum WillMessage {
+    V3(v3::PublishPacket),
+
+    /// Will message and its Will Delay Interval.
+    V5(v5::PublishPacket, Duration),
+}
+
+impl WillMessage {
+    /// Build will message from connect packet.
+    ///
+    /// Returns None if will flag is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if will topic is invalid.
+    pub fn from_connect(packet: &v3::ConnectPacket) -> Result<Option<Self>, EncodeError> {
+        let flags = packet.connect_flags();
+        if !flags.will() {
+            return Ok(None);
+        }
+        let Some(topic) = packet.will_topic() else {
+            return Ok(None);
+        };
+
+        let mut will_packet =
+            v3::PublishPacket::new(topic, flags.will_qos(), packet.will_message())?;
+        will_packet.set_retain(flags.will_retain());
+        Ok(Some(Self::V3(will_packet)))
+    }
+
+    /// Build will message from v5 connect packet.
+    ///
+    /// Returns None if will flag is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if will topic or will properties are invalid.
+    pub fn from_connect_v5(packet: &v5::ConnectPacket) -> Result<Option<Self>, EncodeError> {
+        if !packet.will() {
+            return Ok(None);
+        }
+        let Some(topic) = packet.will_topic() else {
+            return Ok(None);
+        };
+
+        let mut will_packet =
+            v5::PublishPacket::new(topic, packet.will_qos(), packet.will_message())?;
+        will_packet.set_retain(packet.will_retain());
+        let mut delay = Duration::ZERO;
+        for property in packet.will_properties().as_ref() {
+            // Will Delay Interval is used by server only, other properties are
+            // sent along with the will message.
+            if let v5::Property::WillDelayInterval(interval) = property {
+                delay = Duration::from_secs(u64::from(interval.value()));
+            } else {
+                will_packet.properties_mut().push(property.clone())?;
+            }
+        }
+        Ok(Some(Self::V5(will_packet, delay)))
+    }
+}
+
+impl Session {
+    /// Publish will message if it is not discarded.
+    ///
+    /// The Will Message MUST be removed from the stored Session state once it has been
+    /// published or the Server has received a DISCONNECT packet from the Client [MQTT-3.1.2-10].
+    ///
+    /// If Will Delay Interval is set, the will message is handed over to listener,
+    /// which publishes it after the delay unless the client reconnects in time.
+    /// Delay is limited to Session Expiry Interval.
+    pub(super) async fn publish_will(&mut self) {
+        let Some(will) = self.will.take() else {
+            return;
+        };
+        log::info!(
+            "session: Publish will message, client id: {}",
+            self.client_id
+        );
+        let cmd = match will {
+            WillMessage::V3(packet) => SessionToListenerCmd::Publish(self.id, packet),
+            WillMessage::V5(packet, delay) => {
+                // The Server delays publishing the Client's Will Message until the Will Delay
+                // Interval has passed or the Session ends, whichever happens first.
+                let delay = self
+                    .config
+                    .session_expiry_interval()
+                    .map_or(delay, |expiry| delay.min(expiry));
+                if delay.is_zero() {
+                    SessionToListenerCmd::PublishV5(self.id, packet)
+                } else {
+                    SessionToListenerCmd::DelayedWillV5(
+                        self.id,
+                        self.client_id.clone(),
+                        packet,
+                        delay,
+                    )
+                }
+            }
+        };
+        if let Err(err) = self.sender.send(cmd).await {
+            log::error!(
+                "Failed to send will message to server, id: {}, err: {:?}",
+                self.id,
+                err
+            );
+        }
+    }
+}
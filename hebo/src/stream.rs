@@ -3,6 +3,7 @@
 // in the LICENSE file.
 
 use futures_util::{SinkExt, StreamExt};
+use std::net::SocketAddr;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 #[cfg(unix)]
@@ -25,6 +26,20 @@ pub enum Stream {
 }
 
 impl Stream {
+    /// Get address of remote client, returns None for unix domain socket.
+    #[must_use]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Mqtt(tcp_stream) => tcp_stream.peer_addr().ok(),
+            Self::Mqtts(tls_stream) => tls_stream.get_ref().0.peer_addr().ok(),
+            Self::Ws(ws_stream) => ws_stream.get_ref().peer_addr().ok(),
+            Self::Wss(wss_stream) => wss_stream.get_ref().get_ref().0.peer_addr().ok(),
+            #[cfg(unix)]
+            Self::Uds(_uds_stream) => None,
+            Self::Quic(connection) => Some(connection.remote_address()),
+        }
+    }
+
     /// Read from stream.
     ///
     /// # Errors
@@ -98,4 +113,24 @@ impl Stream {
             }
         }
     }
+
+    /// Flush pending data and close write side of stream.
+    ///
+    /// TLS `close_notify` alert or websocket close frame is sent to client.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if socket/stream gets error.
+    pub async fn shutdown(&mut self) -> Result<(), Error> {
+        match self {
+            Self::Mqtt(tcp_stream) => tcp_stream.shutdown().await?,
+            Self::Mqtts(tls_stream) => tls_stream.shutdown().await?,
+            Self::Ws(ws_stream) => ws_stream.close().await?,
+            Self::Wss(wss_stream) => wss_stream.close().await?,
+            #[cfg(unix)]
+            Self::Uds(uds_stream) => uds_stream.shutdown().await?,
+            Self::Quic(quic_connection) => quic_connection.close(0_u32.into(), b""),
+        }
+        Ok(())
+    }
 }
@@ -2,7 +2,12 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-use codec::QoS;
+use base64::Engine;
+use codec::{v3, v5, QoS};
+use serde::{Serialize, Serializer};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::AclPolicy;
 
 pub type ListenerId = u32;
 pub type SessionId = u64;
@@ -52,3 +57,157 @@ pub struct SessionInfo {
     pub connected_at: u64,
     pub tls: bool,
 }
+
+/// Connected client, listed in dashboard.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub listener_id: ListenerId,
+    #[serde(skip)]
+    pub session_id: SessionId,
+    pub client_id: String,
+    pub username: String,
+    pub remote_address: Option<String>,
+    /// Seconds since unix epoch.
+    pub connected_at: u64,
+    pub protocol_level: u8,
+    /// Number of topic filters subscribed.
+    pub subscriptions: usize,
+    /// Number of `QoS` 1 and `QoS` 2 messages sent to client but not acknowledged yet.
+    pub inflight_messages: usize,
+}
+
+/// Message published by a client, written to message store of backends app,
+/// or outputs of rules.
+///
+/// It is listed in dashboard and posted to webhooks with base64 encoded payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PublishRecord {
+    pub topic: String,
+    #[serde(serialize_with = "serialize_base64")]
+    pub payload: Vec<u8>,
+    #[serde(serialize_with = "serialize_qos")]
+    pub qos: QoS,
+    /// Milliseconds since unix epoch, when message is received.
+    pub timestamp: u64,
+}
+
+impl PublishRecord {
+    /// Create a record of message received just now.
+    #[must_use]
+    pub fn new(topic: &str, payload: &[u8], qos: QoS) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| {
+                u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+            });
+        Self {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            qos,
+            timestamp,
+        }
+    }
+}
+
+fn serialize_base64<S: Serializer>(payload: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(payload))
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_qos<S: Serializer>(qos: &QoS, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u8(*qos as u8)
+}
+
+/// Topic filter subscribed by a client, listed in dashboard.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubscriptionInfo {
+    pub topic: String,
+    /// Granted `QoS`.
+    pub qos: u8,
+}
+
+/// Where a draining session is redirected to, once its inflight messages are acknowledged.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    server_reference: Option<String>,
+    permanent: bool,
+}
+
+impl Redirect {
+    #[must_use]
+    pub const fn new(server_reference: Option<String>, permanent: bool) -> Self {
+        Self {
+            server_reference,
+            permanent,
+        }
+    }
+
+    /// Get server reference, sent to v5 clients in DISCONNECT packet.
+    #[must_use]
+    pub fn server_reference(&self) -> Option<&str> {
+        self.server_reference.as_deref()
+    }
+
+    /// Server is moved permanently or only temporarily unavailable.
+    #[must_use]
+    #[inline]
+    pub const fn permanent(&self) -> bool {
+        self.permanent
+    }
+
+    /// Get reason code of DISCONNECT packet.
+    #[must_use]
+    pub const fn reason_code(&self) -> v5::ReasonCode {
+        if self.permanent {
+            v5::ReasonCode::ServerMoved
+        } else {
+            v5::ReasonCode::UseAnotherServer
+        }
+    }
+}
+
+/// Publish or subscribe packet to be checked by acl app.
+#[derive(Debug, Clone)]
+pub enum AclPacket {
+    Publish(v3::PublishPacket),
+    PublishV5(v5::PublishPacket),
+    Subscribe(v3::SubscribePacket),
+    SubscribeV5(v5::SubscribePacket),
+}
+
+impl AclPacket {
+    /// Get topic name of publish packet, or topic filters of subscribe packet.
+    #[must_use]
+    pub fn topics(&self) -> Vec<&str> {
+        match self {
+            Self::Publish(packet) => vec![packet.topic()],
+            Self::PublishV5(packet) => vec![packet.topic()],
+            Self::Subscribe(packet) => packet
+                .topics()
+                .iter()
+                .map(v3::SubscribeTopic::topic)
+                .collect(),
+            Self::SubscribeV5(packet) => packet
+                .topics()
+                .iter()
+                .map(v5::SubscribeTopic::topic)
+                .collect(),
+        }
+    }
+
+    /// Publish packet requires write access, and subscribe packet requires read access.
+    #[must_use]
+    pub const fn is_publish(&self) -> bool {
+        matches!(self, Self::Publish(_) | Self::PublishV5(_))
+    }
+}
+
+/// Ask acl app whether a client may send this packet.
+#[derive(Debug, Clone)]
+pub struct AclRequest {
+    pub client_id: String,
+    pub username: String,
+    pub packet: AclPacket,
+    /// Policy of listener for topics not matched by acl rules.
+    pub policy: AclPolicy,
+}
@@ -0,0 +1,131 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test server built in code, without config file.
+
+use codec::{v3, v5, PacketId, QoS};
+use hebo::config::{Dashboard, Listener, Security};
+use hebo::error::Error;
+use hebo::server::ServerContext;
+use std::thread::{self, sleep};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+mod common;
+use common::Client;
+
+const ADDRESS: &str = "127.0.0.1:1914";
+
+#[test]
+fn test_server_builder() -> Result<(), Error> {
+    let mut security = Security::default();
+    security.set_allow_anonymous(true);
+    let mut dashboard = Dashboard::default();
+    dashboard.set_enable(false);
+    let mut server = ServerContext::builder()
+        .listener(Listener::tcp(ADDRESS))
+        .security(security)
+        .dashboard(dashboard)
+        .build()?;
+
+    // Server is dropped when test process exits.
+    thread::spawn(move || {
+        let runtime = Runtime::new().unwrap();
+        server.run_loop(&runtime).unwrap();
+    });
+    sleep(Duration::from_secs(1));
+
+    let mut subscriber = Client::connect(ADDRESS);
+    subscriber.send(&v3::ConnectPacket::new("builder-subscriber")?);
+    let ack_packet: v3::ConnectAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    subscriber.send(&v3::SubscribePacket::new(
+        "builder/#",
+        QoS::AtMostOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+
+    let mut publisher = Client::connect(ADDRESS);
+    publisher.send(&v3::ConnectPacket::new("builder-publisher")?);
+    let _ack_packet: v3::ConnectAckPacket = publisher.recv();
+    publisher.send(&v3::PublishPacket::new(
+        "builder/status",
+        QoS::AtMostOnce,
+        b"ok",
+    )?);
+
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "builder/status");
+    assert_eq!(packet.message(), b"ok");
+    Ok(())
+}
+
+#[test]
+fn test_bound_addresses() -> Result<(), Error> {
+    let mut security = Security::default();
+    security.set_allow_anonymous(true);
+    let mut dashboard = Dashboard::default();
+    dashboard.set_enable(false);
+    let mut server = ServerContext::builder()
+        .listener(Listener::tcp("127.0.0.1:0"))
+        .security(security)
+        .dashboard(dashboard)
+        .build()?;
+    let receiver = server.bound_addresses();
+
+    thread::spawn(move || {
+        let runtime = Runtime::new().unwrap();
+        server.run_loop(&runtime).unwrap();
+    });
+
+    let addresses = receiver.blocking_recv().unwrap();
+    assert_eq!(addresses.len(), 1);
+    let (_listener_id, address) = addresses[0];
+    assert_ne!(address.port(), 0);
+
+    let mut client = Client::connect(&address.to_string());
+    client.send(&v3::ConnectPacket::new("bound-address")?);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(())
+}
+
+#[test]
+fn test_stop_handle() -> Result<(), Error> {
+    let mut security = Security::default();
+    security.set_allow_anonymous(true);
+    let mut dashboard = Dashboard::default();
+    dashboard.set_enable(false);
+    let mut server = ServerContext::builder()
+        .listener(Listener::tcp("127.0.0.1:0"))
+        .security(security)
+        .dashboard(dashboard)
+        .build()?;
+    let receiver = server.bound_addresses();
+    let stop_handle = server.stop_handle();
+
+    let handle = thread::spawn(move || {
+        let runtime = Runtime::new().unwrap();
+        server.run_loop(&runtime)
+    });
+    let address = receiver.blocking_recv().unwrap()[0].1.to_string();
+
+    let mut clients = Vec::new();
+    for client_id in ["stop-handle-1", "stop-handle-2"] {
+        let mut client = Client::connect(&address);
+        client.send(&v5::ConnectPacket::new(client_id)?);
+        let ack_packet: v5::ConnectAckPacket = client.recv();
+        assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+        clients.push(client);
+    }
+
+    stop_handle.stop();
+    for mut client in clients {
+        let packet: v5::DisconnectPacket = client.recv();
+        assert_eq!(packet.reason_code(), v5::ReasonCode::ServerShuttingDown);
+    }
+    handle.join().unwrap()?;
+    Ok(())
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test server reloads config file on SIGUSR1.
+
+use codec::{v3, v5};
+use hebo::error::Error;
+use std::net::TcpStream;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1903.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1903"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1903.log"
+"#;
+
+const INVALID_CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1903.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1903"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1904"
+
+[[listeners]]
+protocol = "mqtt"
+address = "invalid-address"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1903.log"
+"#;
+
+const INVALID_SECURITY_CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1903.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1903"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1904"
+
+[security]
+allow_anonymous = false
+password_file = "/tmp/hebo-tests/hebo-1903.passwd"
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1903.log"
+"#;
+
+const PASSWORD_FILE: &str = "/tmp/hebo-tests/hebo-1903.passwd";
+
+const NEW_CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1903.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1903"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1904"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1903.log"
+"#;
+
+fn connect(address: &str, client_id: &str) -> Client {
+    let mut client = Client::connect(address);
+    client.send(&v3::ConnectPacket::new(client_id).unwrap());
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    client
+}
+
+fn ping(client: &mut Client) {
+    client.send(&v3::PingRequestPacket::new());
+    let _packet: v3::PingResponsePacket = client.recv();
+}
+
+#[test]
+fn test_server_reload() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/00-server-reload.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = connect("127.0.0.1:1903", "server-reload-1");
+
+    // Invalid config is rejected as a whole, old one keeps running.
+    config.update(INVALID_CONFIG)?;
+    server.reload();
+    assert!(TcpStream::connect("127.0.0.1:1904").is_err());
+    ping(&mut client);
+
+    // Password file cannot be read, neither listeners nor security settings are changed.
+    std::fs::write(PASSWORD_FILE, [0xff, 0xfe, b'\n'])?;
+    config.update(INVALID_SECURITY_CONFIG)?;
+    server.reload();
+    assert!(TcpStream::connect("127.0.0.1:1904").is_err());
+    drop(connect("127.0.0.1:1903", "server-reload-3"));
+    ping(&mut client);
+    std::fs::remove_file(PASSWORD_FILE)?;
+
+    // Add a second listener, sessions of the first one are not disrupted.
+    config.update(NEW_CONFIG)?;
+    server.reload();
+    let mut client2 = Client::connect("127.0.0.1:1904");
+    client2.send(&v5::ConnectPacket::new("server-reload-2")?);
+    let ack_packet: v5::ConnectAckPacket = client2.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    ping(&mut client);
+
+    // Remove the second listener and disconnect its sessions.
+    config.update(CONFIG)?;
+    server.reload();
+    let packet: v5::DisconnectPacket = client2.recv();
+    assert_eq!(packet.reason_code(), v5::ReasonCode::AdministrativeAction);
+    assert!(TcpStream::connect("127.0.0.1:1904").is_err());
+    ping(&mut client);
+
+    server.terminate();
+    Ok(())
+}
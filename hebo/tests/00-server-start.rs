@@ -0,0 +1,49 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test server boots with a minimal config and disconnects clients on exit.
+
+use codec::v5;
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1900.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1900"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1900.log"
+"#;
+
+#[test]
+fn test_server_start_and_stop() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/00-server-start.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect("127.0.0.1:1900");
+    client.send(&v5::ConnectPacket::new("server-start")?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+
+    // Session is disconnected by server before process exits.
+    server.terminate();
+    let packet: v5::DisconnectPacket = client.recv();
+    assert_eq!(packet.reason_code(), v5::ReasonCode::ServerShuttingDown);
+    Ok(())
+}
@@ -0,0 +1,83 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test connect and disconnect events are written to access log.
+
+use codec::{v5, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1916.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1916"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1916.log"
+access_log_file = "/tmp/hebo-tests/access-1916.log"
+access_log_format = "json"
+"#;
+
+const ACCESS_LOG_FILE: &str = "/tmp/hebo-tests/access-1916.log";
+
+#[test]
+fn test_access_log() -> Result<(), Error> {
+    let _ret = std::fs::remove_file(ACCESS_LOG_FILE);
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-access-log.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect("127.0.0.1:1916");
+    client.send(&v5::ConnectPacket::new("access-client")?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    client.send(&v5::SubscribePacket::new(
+        "access/#",
+        QoS::AtMostOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v5::SubscribeAckPacket = client.recv();
+    for _i in 0..2 {
+        client.send(&v5::PublishPacket::new(
+            "access/status",
+            QoS::AtMostOnce,
+            b"on",
+        )?);
+        let _packet: v5::PublishPacket = client.recv();
+    }
+    client.send(&v5::DisconnectPacket::new());
+    drop(client);
+    sleep(Duration::from_millis(500));
+
+    let content = std::fs::read_to_string(ACCESS_LOG_FILE)?;
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("{\"timestamp\":"));
+    assert!(lines[0].contains(
+        ",\"event\":\"connect\",\"listener\":0,\"client_id\":\"access-client\",\
+         \"username\":\"\",\"address\":\"127.0.0.1:"
+    ));
+    assert!(lines[0].ends_with(",\"protocol\":5,\"clean_session\":true}"));
+    assert!(lines[1].contains(",\"event\":\"disconnect\","));
+    assert!(lines[1].contains(",\"client_id\":\"access-client\","));
+    assert!(lines[1].ends_with(
+        ",\"protocol\":5,\"clean_session\":true,\"subscribe\":1,\"unsubscribe\":0,\"publish\":2}"
+    ));
+
+    server.terminate();
+    Ok(())
+}
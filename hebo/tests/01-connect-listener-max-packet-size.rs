@@ -0,0 +1,80 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test `maximum_packet_size` of listener overrides value in general section.
+
+use codec::{v5, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1901.pid"
+maximum_packet_size = 4096
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1901"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1902"
+maximum_packet_size = 512
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1901.log"
+"#;
+
+fn connect(address: &str, client_id: &str) -> Client {
+    let mut client = Client::connect(address);
+    client.send(&v5::ConnectPacket::new(client_id).unwrap());
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    client
+}
+
+fn publish_packet() -> v5::PublishPacket {
+    let msg = vec![b'x'; 1024];
+    let mut packet = v5::PublishPacket::new("hello", QoS::AtLeastOnce, &msg).unwrap();
+    packet.set_packet_id(PacketId::new(1));
+    packet
+}
+
+#[test]
+fn test_listener_max_packet_size() -> Result<(), Error> {
+    let config = ServerConfig::new(
+        "/tmp/hebo-tests/01-connect-listener-max-packet-size.toml",
+        CONFIG,
+    )?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    // Inherits limit in general section.
+    let mut client = connect("127.0.0.1:1901", "max-packet-size-general");
+    client.send(&publish_packet());
+    let ack_packet: v5::PublishAckPacket = client.recv();
+    assert_eq!(ack_packet.packet_id(), PacketId::new(1));
+
+    // Uses its own limit.
+    let mut client = connect("127.0.0.1:1902", "max-packet-size-listener");
+    client.send(&publish_packet());
+    let disconnect_packet: v5::DisconnectPacket = client.recv();
+    assert_eq!(
+        disconnect_packet.reason_code(),
+        v5::ReasonCode::PacketTooLarge
+    );
+
+    server.terminate();
+    Ok(())
+}
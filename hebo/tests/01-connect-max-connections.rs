@@ -2,23 +2,24 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
-//! Test whether `max_connections` works with repeated connections
+//! Test whether `maximum_connections` of listener works with repeated connections
 
+use codec::{v3, v5};
 use hebo::error::Error;
 use std::thread::sleep;
 use std::time::Duration;
 
 mod common;
-use common::{Server, ServerConfig};
+use common::{Client, Server, ServerConfig};
 
 const CONFIG: &str = r#"
 [general]
 pid_file = "/tmp/hebo-tests/mqtt-1893.pid"
-max_connections = 10
 
 [[listeners]]
 protocol = "mqtt"
 address = "0.0.0.0:1893"
+maximum_connections = 2
 
 [security]
 allow_anonymous = true
@@ -30,11 +31,46 @@ enable = false
 log_file = "/tmp/hebo-tests/hebo-1893.log"
 "#;
 
+const ADDRESS: &str = "127.0.0.1:1893";
+
+fn connect(client_id: &str) -> Result<(Client, v5::ReasonCode), Error> {
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v5::ConnectPacket::new(client_id)?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    Ok((client, ack_packet.reason_code()))
+}
+
 #[test]
 fn test_connect_max_connections() -> Result<(), Error> {
     let config = ServerConfig::new("/tmp/hebo-tests/01-connect-max-connections.toml", CONFIG)?;
     let server = Server::start(config.filename())?;
-    sleep(Duration::from_secs(5));
+    sleep(Duration::from_secs(2));
+
+    let (mut client1, reason_code) = connect("max-connections-1")?;
+    assert_eq!(reason_code, v5::ReasonCode::Success);
+    let (_client2, reason_code) = connect("max-connections-2")?;
+    assert_eq!(reason_code, v5::ReasonCode::Success);
+
+    // Limit is reached.
+    let (client3, reason_code) = connect("max-connections-3")?;
+    assert_eq!(reason_code, v5::ReasonCode::QuotaExceeded);
+    let mut client4 = Client::connect(ADDRESS);
+    client4.send(&v3::ConnectPacket::new("max-connections-4")?);
+    let ack_packet: v3::ConnectAckPacket = client4.recv();
+    assert_eq!(
+        ack_packet.return_code(),
+        v3::ConnectReturnCode::ServerUnavailable
+    );
+    drop(client3);
+    drop(client4);
+
+    // Slot is released after a client disconnects.
+    client1.send(&v5::DisconnectPacket::new());
+    drop(client1);
+    sleep(Duration::from_millis(500));
+    let (_client5, reason_code) = connect("max-connections-5")?;
+    assert_eq!(reason_code, v5::ReasonCode::Success);
+
     server.terminate();
     Ok(())
 }
@@ -0,0 +1,100 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test uds listeners only accept peer processes in `allowed_uids` and `allowed_gids`.
+
+#![cfg(target_os = "linux")]
+
+use codec::{v3, ByteArray, DecodePacket, EncodePacket};
+use hebo::error::Error;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Server, ServerConfig};
+
+const ALLOWED_SOCKET: &str = "/tmp/hebo-tests/hebo-1923-allowed.sock";
+const DENIED_SOCKET: &str = "/tmp/hebo-tests/hebo-1923-denied.sock";
+const GID_SOCKET: &str = "/tmp/hebo-tests/hebo-1923-gid.sock";
+
+/// Connect to uds listener, returns connect ack packet or None if stream is closed.
+fn connect(path: &str) -> Option<v3::ConnectAckPacket> {
+    let mut stream = UnixStream::connect(path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    let mut buf = Vec::new();
+    v3::ConnectPacket::new("peer-cred")
+        .unwrap()
+        .encode(&mut buf)
+        .unwrap();
+    // Server may have closed the stream already.
+    let _ret = stream.write_all(&buf);
+
+    let mut buf = Vec::new();
+    let _ret = stream.read_to_end(&mut buf);
+    if buf.is_empty() {
+        return None;
+    }
+    let mut ba = ByteArray::new(&buf);
+    Some(v3::ConnectAckPacket::decode(&mut ba).unwrap())
+}
+
+#[test]
+fn test_peer_cred() -> Result<(), Error> {
+    // Owner of /proc/self is effective uid and gid of current process.
+    let metadata = std::fs::metadata("/proc/self")?;
+    let (uid, gid) = (metadata.uid(), metadata.gid());
+    let content = format!(
+        r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1923.pid"
+
+[[listeners]]
+protocol = "uds"
+address = "{ALLOWED_SOCKET}"
+allowed_uids = [{uid}]
+
+[[listeners]]
+protocol = "uds"
+address = "{DENIED_SOCKET}"
+allowed_uids = [{}]
+
+[[listeners]]
+protocol = "uds"
+address = "{GID_SOCKET}"
+allowed_uids = [{}]
+allowed_gids = [{gid}]
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1923.log"
+"#,
+        uid.wrapping_add(1),
+        uid.wrapping_add(1),
+    );
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-peer-cred.toml", &content)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let ack_packet = connect(ALLOWED_SOCKET).expect("Connection is refused");
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    // Connection from disallowed uid is refused before connect packet is handled.
+    assert!(connect(DENIED_SOCKET).is_none());
+
+    let ack_packet = connect(GID_SOCKET).expect("Connection is refused");
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    server.terminate();
+    Ok(())
+}
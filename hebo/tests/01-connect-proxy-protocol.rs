@@ -0,0 +1,89 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test address of client is read from PROXY protocol header.
+
+use codec::v3;
+use hebo::error::Error;
+use std::net::TcpStream;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1922.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1922"
+proxy_protocol = true
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1922.log"
+access_log_file = "/tmp/hebo-tests/access-1922.log"
+access_log_format = "json"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1922";
+const ACCESS_LOG_FILE: &str = "/tmp/hebo-tests/access-1922.log";
+
+fn connect(header: &[u8], client_id: &str) -> Client {
+    let mut client = Client::connect(ADDRESS);
+    client.send_bytes(header);
+    client.send(&v3::ConnectPacket::new(client_id).unwrap());
+    client
+}
+
+#[test]
+fn test_proxy_protocol() -> Result<(), Error> {
+    let _ret = std::fs::remove_file(ACCESS_LOG_FILE);
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-proxy-protocol.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    // Connection which never sends header does not delay other clients.
+    let _silent_stream = TcpStream::connect(ADDRESS)?;
+    sleep(Duration::from_millis(100));
+    let start = Instant::now();
+    let mut client = connect(
+        b"PROXY TCP4 203.0.113.7 127.0.0.1 56324 1922\r\n",
+        "proxy-v1",
+    );
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    let mut header = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+    header.extend_from_slice(&[198, 51, 100, 9, 127, 0, 0, 1]);
+    header.extend_from_slice(&40000_u16.to_be_bytes());
+    header.extend_from_slice(&1922_u16.to_be_bytes());
+    let mut client_v2 = connect(&header, "proxy-v2");
+    let ack_packet: v3::ConnectAckPacket = client_v2.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    // Connection without header is dropped.
+    let mut client_malformed = connect(b"", "proxy-malformed");
+    assert!(client_malformed.is_closed());
+
+    sleep(Duration::from_millis(500));
+    let content = std::fs::read_to_string(ACCESS_LOG_FILE)?;
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(",\"client_id\":\"proxy-v1\","));
+    assert!(lines[0].contains(",\"address\":\"203.0.113.7:56324\","));
+    assert!(lines[1].contains(",\"client_id\":\"proxy-v2\","));
+    assert!(lines[1].contains(",\"address\":\"198.51.100.9:40000\","));
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test whether new connections are rejected when server is busy.
+
+use codec::{v3, v5};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1895.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1895"
+
+[listeners.admission]
+busy_connections = 1
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1895.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1895";
+
+#[test]
+fn test_connect_server_busy() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-server-busy.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let connect_packet = v5::ConnectPacket::new("server-busy-1")?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+
+    // Load threshold is reached.
+    let connect_packet = v5::ConnectPacket::new("server-busy-2")?;
+    let mut client2 = Client::connect(ADDRESS);
+    client2.send(&connect_packet);
+    let ack_packet: v5::ConnectAckPacket = client2.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::ServerBusy);
+
+    let connect_packet = v3::ConnectPacket::new("server-busy-3")?;
+    let mut client3 = Client::connect(ADDRESS);
+    client3.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client3.recv();
+    assert_eq!(
+        ack_packet.return_code(),
+        v3::ConnectReturnCode::ServerUnavailable
+    );
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test session present flag in connect ack packet.
+
+use codec::{v3, v5, PacketId, QoS, U32Data};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1919.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1919"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1919.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1919";
+
+/// Connect and returns client and session present flag.
+fn connect(client_id: &str, clean_session: bool) -> (Client, bool) {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v3::ConnectPacket::new(client_id).unwrap();
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(clean_session);
+    packet.set_connect_flags(flags);
+    client.send(&packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    (client, ack_packet.session_present())
+}
+
+/// Connect with session expiry interval, returns client and session present flag.
+fn connect_v5(client_id: &str, clean_start: bool) -> Result<(Client, bool), Error> {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v5::ConnectPacket::new(client_id)?;
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(clean_start);
+    packet.set_connect_flags(flags);
+    packet
+        .properties_mut()
+        .push(v5::Property::SessionExpiryInterval(U32Data::new(60)))?;
+    client.send(&packet);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok((client, ack_packet.session_present()))
+}
+
+fn disconnect(mut client: Client) {
+    client.send(&v3::DisconnectPacket::new());
+    drop(client);
+    sleep(Duration::from_millis(500));
+}
+
+fn disconnect_v5(mut client: Client) {
+    client.send(&v5::DisconnectPacket::new());
+    drop(client);
+    sleep(Duration::from_millis(500));
+}
+
+#[test]
+fn test_session_present() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-session-present.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    // No session state is stored on first connect.
+    let (mut client, present) = connect("session-present", false);
+    assert!(!present);
+    client.send(&v3::SubscribePacket::new(
+        "session-present/#",
+        QoS::AtLeastOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v3::SubscribeAckPacket = client.recv();
+    disconnect(client);
+
+    let (client, present) = connect("session-present", false);
+    assert!(present);
+    disconnect(client);
+
+    // Stored session state is discarded if clean session flag is on.
+    let (client, present) = connect("session-present", true);
+    assert!(!present);
+    disconnect(client);
+
+    let (mut client, present) = connect("session-present", false);
+    assert!(!present);
+    let (mut publisher, _present) = connect("session-present-publisher", true);
+    let mut packet = v3::PublishPacket::new("session-present/status", QoS::AtLeastOnce, b"1")?;
+    packet.set_packet_id(PacketId::new(1));
+    publisher.send(&packet);
+    let _ack_packet: v3::PublishAckPacket = publisher.recv();
+    assert!(client.try_recv::<v3::PublishPacket>().is_none());
+
+    // Same rules apply to clean start flag of v5 clients.
+    let (client, present) = connect_v5("session-present-v5", false)?;
+    assert!(!present);
+    disconnect_v5(client);
+
+    let (client, present) = connect_v5("session-present-v5", false)?;
+    assert!(present);
+    disconnect_v5(client);
+
+    let (client, present) = connect_v5("session-present-v5", true)?;
+    assert!(!present);
+    disconnect_v5(client);
+
+    server.terminate();
+    Ok(())
+}
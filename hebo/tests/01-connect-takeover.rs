@@ -0,0 +1,103 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test new connection with an existing client id takes over the old session.
+
+use codec::{v3, v5, PacketId, QoS};
+use hebo::config::{Dashboard, Listener, Security};
+use hebo::error::Error;
+use hebo::server::ServerContext;
+use std::thread;
+use tokio::runtime::Runtime;
+
+mod common;
+use common::Client;
+
+fn start_server() -> Result<String, Error> {
+    let mut security = Security::default();
+    security.set_allow_anonymous(true);
+    let mut dashboard = Dashboard::default();
+    dashboard.set_enable(false);
+    let mut server = ServerContext::builder()
+        .listener(Listener::tcp("127.0.0.1:0"))
+        .security(security)
+        .dashboard(dashboard)
+        .build()?;
+    let receiver = server.bound_addresses();
+
+    // Server is dropped when test process exits.
+    thread::spawn(move || {
+        let runtime = Runtime::new().unwrap();
+        server.run_loop(&runtime).unwrap();
+    });
+    Ok(receiver.blocking_recv().unwrap()[0].1.to_string())
+}
+
+fn connect_v5(address: &str, client_id: &str) -> Result<Client, Error> {
+    let mut client = Client::connect(address);
+    client.send(&v5::ConnectPacket::new(client_id)?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok(client)
+}
+
+/// Returns true if `client` receives message published by itself.
+fn is_alive(client: &mut Client, topic: &str) -> Result<bool, Error> {
+    client.send(&v5::SubscribePacket::new(
+        topic,
+        QoS::AtMostOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v5::SubscribeAckPacket = client.recv();
+    client.send(&v5::PublishPacket::new(topic, QoS::AtMostOnce, b"ok")?);
+    Ok(client
+        .try_recv::<v5::PublishPacket>()
+        .map_or(false, |packet| packet.message() == b"ok"))
+}
+
+#[test]
+fn test_connect_takeover() -> Result<(), Error> {
+    let address = start_server()?;
+
+    // Old v5 client receives DISCONNECT.
+    let mut old_client = connect_v5(&address, "takeover-v5")?;
+    let mut new_client = connect_v5(&address, "takeover-v5")?;
+    let packet: v5::DisconnectPacket = old_client.recv();
+    assert_eq!(packet.reason_code(), v5::ReasonCode::SessionTakenOver);
+    assert!(old_client.is_closed());
+    assert!(is_alive(&mut new_client, "takeover/v5")?);
+
+    // Old v3 client is closed.
+    let mut old_client = Client::connect(&address);
+    old_client.send(&v3::ConnectPacket::new("takeover-v3")?);
+    let ack_packet: v3::ConnectAckPacket = old_client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    let mut new_client = connect_v5(&address, "takeover-v3")?;
+    assert!(old_client.is_closed());
+    assert!(is_alive(&mut new_client, "takeover/v3")?);
+    Ok(())
+}
+
+#[test]
+fn test_connect_takeover_race() -> Result<(), Error> {
+    let address = start_server()?;
+
+    // Both clients send CONNECT before any of them is accepted, the one handled
+    // later by server takes over the other.
+    let mut first_client = Client::connect(&address);
+    let mut second_client = Client::connect(&address);
+    first_client.send(&v5::ConnectPacket::new("takeover-race")?);
+    second_client.send(&v5::ConnectPacket::new("takeover-race")?);
+
+    let first_closed = first_client.is_closed();
+    let second_closed = second_client.is_closed();
+    assert_ne!(first_closed, second_closed);
+    let mut client = if first_closed {
+        second_client
+    } else {
+        first_client
+    };
+    assert!(is_alive(&mut client, "takeover/race")?);
+    Ok(())
+}
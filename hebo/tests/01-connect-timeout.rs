@@ -0,0 +1,64 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test streams without connect packet are closed after `connect_timeout` of listener.
+
+use codec::v3;
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1920.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1920"
+connect_timeout = 1
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1921"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1920.log"
+"#;
+
+#[test]
+fn test_connect_timeout() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-timeout.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let start = Instant::now();
+    let mut client = Client::connect("127.0.0.1:1920");
+    assert!(client.is_closed());
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    // Connect packet is accepted before timeout.
+    let mut client = Client::connect("127.0.0.1:1920");
+    client.send(&v3::ConnectPacket::new("connect-timeout")?);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    sleep(Duration::from_secs(2));
+    client.send(&v3::PingRequestPacket::new());
+    let _packet: v3::PingResponsePacket = client.recv();
+
+    // Default timeout of other listeners is not changed.
+    let mut client = Client::connect("127.0.0.1:1921");
+    assert!(!client.is_closed());
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test v5 will message with Will Delay Interval.
+
+use codec::{v5, PacketId, QoS, U32Data};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1897.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1897"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1897.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1897";
+const WILL_TOPIC: &str = "will/client-1";
+
+/// Connect with Will Delay Interval and Session Expiry Interval.
+///
+/// Previous session is resumed if `session_expiry` is set.
+fn connect(
+    client_id: &str,
+    will_delay: Option<u32>,
+    session_expiry: Option<u32>,
+) -> Result<Client, Error> {
+    let mut connect_packet = v5::ConnectPacket::new(client_id)?;
+    if let Some(will_delay) = will_delay {
+        connect_packet.set_will(true);
+        connect_packet.set_will_topic(WILL_TOPIC)?;
+        connect_packet.set_will_message(b"offline")?;
+        connect_packet
+            .will_properties_mut()
+            .push(v5::Property::WillDelayInterval(U32Data::new(will_delay)))?;
+    }
+    if let Some(session_expiry) = session_expiry {
+        connect_packet.set_clean_session(false);
+        connect_packet
+            .properties_mut()
+            .push(v5::Property::SessionExpiryInterval(U32Data::new(
+                session_expiry,
+            )))?;
+    }
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok(client)
+}
+
+#[test]
+fn test_connect_will_delay() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-will-delay.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("will-subscriber", None, None)?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v5::SubscribePacket::new(
+        WILL_TOPIC,
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    // Will message is cancelled if client resumes session within the delay.
+    let client = connect("will-client-1", Some(2), Some(60))?;
+    drop(client);
+    sleep(Duration::from_millis(500));
+    let mut client = connect("will-client-1", None, Some(60))?;
+    let packet: Option<v5::PublishPacket> = subscriber.try_recv();
+    assert!(packet.is_none());
+    client.send(&v5::DisconnectPacket::new());
+    drop(client);
+
+    // Will message is published after the delay.
+    let client = connect("will-client-2", Some(1), Some(60))?;
+    let instant = Instant::now();
+    drop(client);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert!(instant.elapsed() >= Duration::from_secs(1));
+    assert_eq!(packet.topic(), WILL_TOPIC);
+    assert_eq!(packet.message(), b"offline");
+
+    // Will message is published when session expires, before the delay.
+    let client = connect("will-client-3", Some(60), Some(1))?;
+    let instant = Instant::now();
+    drop(client);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert!(instant.elapsed() >= Duration::from_secs(1));
+    assert!(instant.elapsed() < Duration::from_secs(3));
+    assert_eq!(packet.message(), b"offline");
+
+    // Will message is published at once if session ends at disconnect.
+    let client = connect("will-client-4", Some(60), None)?;
+    let instant = Instant::now();
+    drop(client);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert!(instant.elapsed() < Duration::from_secs(1));
+    assert_eq!(packet.message(), b"offline");
+
+    // Will message is published if previous session is discarded by clean start.
+    let client = connect("will-client-5", Some(60), Some(60))?;
+    drop(client);
+    sleep(Duration::from_millis(500));
+    let packet: Option<v5::PublishPacket> = subscriber.try_recv();
+    assert!(packet.is_none());
+    let mut client = connect("will-client-5", None, None)?;
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"offline");
+    client.send(&v5::DisconnectPacket::new());
+    drop(client);
+
+    server.terminate();
+    Ok(())
+}
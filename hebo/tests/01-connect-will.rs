@@ -0,0 +1,84 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test whether will message is published when client is disconnected abnormally.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1896.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1896"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1896.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1896";
+const WILL_TOPIC: &str = "will/client-1";
+
+fn connect(client_id: &str, will: bool) -> Result<Client, Error> {
+    let mut connect_packet = v3::ConnectPacket::new(client_id)?;
+    if will {
+        let mut flags = connect_packet.connect_flags().clone();
+        flags.set_will(true);
+        connect_packet.set_connect_flags(flags);
+        connect_packet.set_will_topic(WILL_TOPIC)?;
+        connect_packet.set_will_message(b"offline")?;
+    }
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+#[test]
+fn test_connect_will() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/01-connect-will.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("will-subscriber", false)?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new(
+        WILL_TOPIC,
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    // Will message is discarded on DISCONNECT.
+    let mut client = connect("will-client-1", true)?;
+    client.send(&v3::DisconnectPacket::new());
+    drop(client);
+
+    // Will message is published when network connection is closed.
+    let client = connect("will-client-2", true)?;
+    drop(client);
+
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), WILL_TOPIC);
+    assert_eq!(packet.message(), b"offline");
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test a burst of publish packets checked by acl app are all dispatched.
+
+#![cfg(feature = "acl")]
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1932.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1932"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1932.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1932";
+const MESSAGES: usize = 500;
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+#[test]
+fn test_acl_burst() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-acl-burst.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("acl-burst-subscriber")?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new(
+        "burst/#",
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    // Queues between listener and acl app are filled up by these messages,
+    // and neither of them waits for the other one.
+    let mut publisher = connect("acl-burst-publisher")?;
+    for i in 0..MESSAGES {
+        publisher.send(&v3::PublishPacket::new(
+            "burst/1",
+            QoS::AtMostOnce,
+            i.to_string().as_bytes(),
+        )?);
+    }
+
+    for i in 0..MESSAGES {
+        let packet: v3::PublishPacket = subscriber.recv();
+        assert_eq!(packet.topic(), "burst/1");
+        assert_eq!(packet.message(), i.to_string().as_bytes());
+    }
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,195 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test publish and subscribe are checked against rules in acl file.
+
+#![cfg(feature = "acl")]
+
+use codec::{v3, v5, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1906.pid"
+delayed_publish = true
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1906"
+
+[security]
+allow_anonymous = true
+acl_file = "/tmp/hebo-tests/02-acl.acl"
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1906.log"
+"#;
+
+const ACL: &str = r"
+topic read public/#
+topic write public/cmd
+topic write $delayed/#
+pattern clients/%c/#
+pattern write sensor/secret/%c
+topic deny sensor/secret/#
+topic sensor/#
+";
+
+const ADDRESS: &str = "127.0.0.1:1906";
+
+fn subscribe_topics(topics: &[&str]) -> Result<v3::SubscribePacket, Error> {
+    let mut packet = v3::SubscribePacket::new(topics[0], QoS::AtMostOnce, PacketId::new(1))?;
+    let topics = topics
+        .iter()
+        .map(|topic| v3::SubscribeTopic::new(topic, QoS::AtMostOnce))
+        .collect::<Result<Vec<_>, _>>()?;
+    packet.set_topics(&topics);
+    Ok(packet)
+}
+
+#[test]
+fn test_acl() -> Result<(), Error> {
+    let _acl = ServerConfig::new("/tmp/hebo-tests/02-acl.acl", ACL)?;
+    let config = ServerConfig::new("/tmp/hebo-tests/02-acl.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new("acl-1")?);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    // Denied topic filter gets failure code, others are subscribed.
+    client.send(&subscribe_topics(&[
+        "public/#",
+        "secret/#",
+        "clients/acl-1/inbox",
+    ])?);
+    let ack_packet: v3::SubscribeAckPacket = client.recv();
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [
+            v3::SubscribeAck::QoS(QoS::AtMostOnce),
+            v3::SubscribeAck::Failed,
+            v3::SubscribeAck::QoS(QoS::AtMostOnce),
+        ]
+    );
+
+    // Denied publish is dropped.
+    client.send(&v3::PublishPacket::new(
+        "public/news",
+        QoS::AtMostOnce,
+        b"denied",
+    )?);
+    client.send(&v3::PublishPacket::new(
+        "public/cmd",
+        QoS::AtMostOnce,
+        b"granted",
+    )?);
+    let packet: v3::PublishPacket = client.recv();
+    assert_eq!(packet.topic(), "public/cmd");
+    client.send(&v3::PublishPacket::new(
+        "clients/acl-1/inbox",
+        QoS::AtMostOnce,
+        b"hello",
+    )?);
+    let packet: v3::PublishPacket = client.recv();
+    assert_eq!(packet.topic(), "clients/acl-1/inbox");
+
+    // All topic filters are denied.
+    let mut client_v5 = Client::connect(ADDRESS);
+    client_v5.send(&v5::ConnectPacket::new("acl-2")?);
+    let ack_packet: v5::ConnectAckPacket = client_v5.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    client_v5.send(&v5::SubscribePacket::new(
+        "clients/acl-1/inbox",
+        QoS::AtMostOnce,
+        PacketId::new(2),
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = client_v5.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::NotAuthorized]);
+
+    // Topic filter containing denied topics is rejected, so that messages of
+    // denied topics are not delivered.
+    let mut subscriber = Client::connect(ADDRESS);
+    subscriber.send(&v3::ConnectPacket::new("acl-3")?);
+    let _ack_packet: v3::ConnectAckPacket = subscriber.recv();
+    subscriber.send(&subscribe_topics(&["sensor/#", "sensor/public/#"])?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [
+            v3::SubscribeAck::Failed,
+            v3::SubscribeAck::QoS(QoS::AtMostOnce),
+        ]
+    );
+    let mut publisher = Client::connect(ADDRESS);
+    publisher.send(&v3::ConnectPacket::new("acl-4")?);
+    let _ack_packet: v3::ConnectAckPacket = publisher.recv();
+    publisher.send(&v3::PublishPacket::new(
+        "sensor/secret/acl-4",
+        QoS::AtMostOnce,
+        b"secret",
+    )?);
+    publisher.send(&v3::PublishPacket::new(
+        "sensor/public/acl-4",
+        QoS::AtMostOnce,
+        b"public",
+    )?);
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "sensor/public/acl-4");
+
+    // Topic of delayed message is checked too.
+    publisher.send(&v3::PublishPacket::new(
+        "$delayed/0/sensor/secret/acl-4",
+        QoS::AtMostOnce,
+        b"secret",
+    )?);
+    publisher.send(&v3::PublishPacket::new(
+        "$delayed/0/sensor/public/acl-4",
+        QoS::AtMostOnce,
+        b"delayed",
+    )?);
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "sensor/public/acl-4");
+    assert_eq!(packet.message(), b"delayed");
+
+    // Shared subscriptions are checked with their topic filter.
+    let mut shared = Client::connect(ADDRESS);
+    shared.send(&v3::ConnectPacket::new("acl-5")?);
+    let _ack_packet: v3::ConnectAckPacket = shared.recv();
+    shared.send(&subscribe_topics(&[
+        "$share/group/sensor/#",
+        "$share/group/sensor/secret/#",
+        "$share/group/sensor/public/#",
+    ])?);
+    let ack_packet: v3::SubscribeAckPacket = shared.recv();
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [
+            v3::SubscribeAck::Failed,
+            v3::SubscribeAck::Failed,
+            v3::SubscribeAck::QoS(QoS::AtMostOnce),
+        ]
+    );
+    publisher.send(&v3::PublishPacket::new(
+        "sensor/public/acl-4",
+        QoS::AtMostOnce,
+        b"shared",
+    )?);
+    let packet: v3::PublishPacket = shared.recv();
+    assert_eq!(packet.topic(), "sensor/public/acl-4");
+    assert_eq!(packet.message(), b"shared");
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,135 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages are forwarded between two brokers bridged together.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const REMOTE_CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1909.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1909"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1909.log"
+"#;
+
+const LOCAL_CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1910.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1910"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1910.log"
+
+[[bridges]]
+name = "remote"
+address = "127.0.0.1:1909"
+retry_interval = 1
+
+[[bridges.topics]]
+topic = "sensors/#"
+direction = "out"
+qos = 1
+local_prefix = "local/"
+remote_prefix = "site-1/"
+
+[[bridges.topics]]
+topic = "commands/#"
+direction = "in"
+"#;
+
+const REMOTE_ADDRESS: &str = "127.0.0.1:1909";
+const LOCAL_ADDRESS: &str = "127.0.0.1:1910";
+
+fn connect(address: &str, client_id: &str) -> Client {
+    let mut client = Client::connect(address);
+    client.send(&v3::ConnectPacket::new(client_id).unwrap());
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    client
+}
+
+fn subscribe(client: &mut Client, topic: &str) {
+    client.send(&v3::SubscribePacket::new(topic, QoS::AtMostOnce, PacketId::new(1)).unwrap());
+    let ack_packet: v3::SubscribeAckPacket = client.recv();
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [v3::SubscribeAck::QoS(QoS::AtMostOnce)]
+    );
+}
+
+#[test]
+fn test_bridge() -> Result<(), Error> {
+    let remote_config = ServerConfig::new("/tmp/hebo-tests/02-bridge-remote.toml", REMOTE_CONFIG)?;
+    let remote_server = Server::start(remote_config.filename())?;
+    sleep(Duration::from_secs(1));
+    let local_config = ServerConfig::new("/tmp/hebo-tests/02-bridge-local.toml", LOCAL_CONFIG)?;
+    let local_server = Server::start(local_config.filename())?;
+    // Wait for bridge connection.
+    sleep(Duration::from_secs(3));
+
+    // Local messages are published in remote broker with rewritten topic.
+    let mut remote_subscriber = connect(REMOTE_ADDRESS, "remote-subscriber");
+    subscribe(&mut remote_subscriber, "site-1/#");
+    let mut local_publisher = connect(LOCAL_ADDRESS, "local-publisher");
+    local_publisher.send(&v3::PublishPacket::new(
+        "local/sensors/temperature",
+        QoS::AtMostOnce,
+        b"21.5",
+    )?);
+    let packet: v3::PublishPacket = remote_subscriber.recv();
+    assert_eq!(packet.topic(), "site-1/sensors/temperature");
+    assert_eq!(packet.message(), b"21.5");
+
+    // Topics not in mappings are not forwarded.
+    local_publisher.send(&v3::PublishPacket::new(
+        "local/status",
+        QoS::AtMostOnce,
+        b"online",
+    )?);
+    sleep(Duration::from_millis(500));
+    assert!(remote_subscriber.try_recv::<v3::PublishPacket>().is_none());
+
+    // Remote messages are delivered to local subscribers.
+    let mut local_subscriber = connect(LOCAL_ADDRESS, "local-subscriber");
+    subscribe(&mut local_subscriber, "commands/+");
+    let mut remote_publisher = connect(REMOTE_ADDRESS, "remote-publisher");
+    remote_publisher.send(&v3::PublishPacket::new(
+        "commands/reboot",
+        QoS::AtMostOnce,
+        b"now",
+    )?);
+    let packet: v3::PublishPacket = local_subscriber.recv();
+    assert_eq!(packet.topic(), "commands/reboot");
+    assert_eq!(packet.message(), b"now");
+
+    local_server.terminate();
+    remote_server.terminate();
+    Ok(())
+}
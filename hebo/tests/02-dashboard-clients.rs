@@ -0,0 +1,137 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test connected clients are listed and disconnected with dashboard api.
+
+#![cfg(feature = "dashboard")]
+
+use codec::{v3, v5, PacketId, QoS};
+use hebo::error::Error;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1918.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1918"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+address = "127.0.0.1:18918"
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1918.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1918";
+const DASHBOARD_ADDRESS: &str = "127.0.0.1:18918";
+
+/// Send a request to dashboard, returns status code and body.
+fn http_request(method: &str, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(DASHBOARD_ADDRESS).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: {DASHBOARD_ADDRESS}\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    let (head, body) = resp.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+#[test]
+fn test_dashboard_clients() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-dashboard-clients.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v5::ConnectPacket::new("dashboard-client-v5")?);
+    let _ack_packet: v5::ConnectAckPacket = client.recv();
+    client.send(&v5::SubscribePacket::new(
+        "dashboard/#",
+        QoS::AtLeastOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v5::SubscribeAckPacket = client.recv();
+
+    let mut client_v3 = Client::connect(ADDRESS);
+    client_v3.send(&v3::ConnectPacket::new("dashboard-client-v3")?);
+    let _ack_packet: v3::ConnectAckPacket = client_v3.recv();
+
+    let (status, body) = http_request("GET", "/api/v1/clients");
+    assert_eq!(status, 200);
+    let clients: Value = serde_json::from_str(&body).unwrap();
+    let clients = clients.as_array().unwrap();
+    assert_eq!(clients.len(), 2);
+    assert_eq!(clients[0]["client_id"], "dashboard-client-v3");
+    assert_eq!(clients[0]["protocol_level"], 4);
+    assert_eq!(clients[1]["client_id"], "dashboard-client-v5");
+    assert_eq!(clients[1]["protocol_level"], 5);
+    assert_eq!(clients[1]["subscriptions"], 1);
+    assert_eq!(clients[1]["inflight_messages"], 0);
+    assert!(clients[1]["remote_address"]
+        .as_str()
+        .unwrap()
+        .starts_with("127.0.0.1:"));
+    assert!(clients[1]["connected_at"].as_u64().unwrap() > 0);
+
+    let (status, body) = http_request("GET", "/api/v1/clients/dashboard-client-v5");
+    assert_eq!(status, 200);
+    let client_info: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(client_info["client_id"], "dashboard-client-v5");
+
+    client.send(&v5::SubscribePacket::new(
+        "alerts",
+        QoS::AtMostOnce,
+        PacketId::new(2),
+    )?);
+    let _ack_packet: v5::SubscribeAckPacket = client.recv();
+    let (status, body) = http_request("GET", "/api/v1/clients/dashboard-client-v5/subscriptions");
+    assert_eq!(status, 200);
+    let subscriptions: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        subscriptions,
+        serde_json::json!([
+            {"topic": "alerts", "qos": 0},
+            {"topic": "dashboard/#", "qos": 1},
+        ])
+    );
+
+    // Disconnected clients are not listed.
+    client_v3.send(&v3::DisconnectPacket::new());
+    sleep(Duration::from_millis(500));
+    let (status, _body) = http_request("GET", "/api/v1/clients/dashboard-client-v3");
+    assert_eq!(status, 404);
+
+    // Client is kicked by administrator.
+    let (status, _body) = http_request("POST", "/api/v1/clients/dashboard-client-v5/disconnect");
+    assert_eq!(status, 200);
+    let packet: v5::DisconnectPacket = client.recv();
+    assert_eq!(packet.reason_code(), v5::ReasonCode::AdministrativeAction);
+    assert!(client.is_closed());
+    sleep(Duration::from_millis(500));
+    let (status, _body) = http_request("POST", "/api/v1/clients/dashboard-client-v5/disconnect");
+    assert_eq!(status, 404);
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test history of topics is queried from memory message store with dashboard api.
+
+#![cfg(feature = "dashboard")]
+
+use base64::Engine;
+use codec::{v3, QoS};
+use hebo::error::Error;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1925.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1925"
+
+[security]
+allow_anonymous = true
+
+[storage]
+message_store = "memory"
+
+[dashboard]
+address = "127.0.0.1:18925"
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1925.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1925";
+const DASHBOARD_ADDRESS: &str = "127.0.0.1:18925";
+
+/// Send a GET request to dashboard, returns status code and body.
+fn http_get(path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(DASHBOARD_ADDRESS).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(3)))
+        .unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {DASHBOARD_ADDRESS}\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    let (head, body) = resp.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+/// Get decoded payloads of messages in history.
+fn payloads(history: &Value) -> Vec<String> {
+    history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| {
+            let payload = base64::engine::general_purpose::STANDARD
+                .decode(message["payload"].as_str().unwrap())
+                .unwrap();
+            String::from_utf8(payload).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn test_dashboard_history() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-dashboard-history.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new("history-publisher")?);
+    let _ack_packet: v3::ConnectAckPacket = client.recv();
+    for (topic, payload) in [
+        ("sensors/1/temp", "1"),
+        ("sensors/2/temp", "2"),
+        ("sensors/1/temp", "3"),
+        ("sensors/1/temp", "4"),
+    ] {
+        client.send(&v3::PublishPacket::new(
+            topic,
+            QoS::AtMostOnce,
+            payload.as_bytes(),
+        )?);
+    }
+    sleep(Duration::from_millis(500));
+
+    let (status, body) = http_get("/api/v1/topics/sensors/1/temp/history?limit=2");
+    assert_eq!(status, 200);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payloads(&history), ["3", "4"]);
+    assert_eq!(history[0]["topic"], "sensors/1/temp");
+    assert_eq!(history[0]["qos"], 0);
+    assert!(history[0]["timestamp"].as_u64().unwrap() > 0);
+
+    // Messages of all matching topics, in the order they are published.
+    let (status, body) = http_get("/api/v1/topics/sensors/+/temp/history");
+    assert_eq!(status, 200);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payloads(&history), ["1", "2", "3", "4"]);
+
+    let (status, body) = http_get("/api/v1/topics/sensors/%23/history?limit=3");
+    assert_eq!(status, 200);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payloads(&history), ["2", "3", "4"]);
+
+    let (status, _body) = http_get("/api/v1/topics/sensors/%23/1/history");
+    assert_eq!(status, 400);
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages to `$delayed/{seconds}/{topic}` are published to `topic` after delay.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1924.pid"
+delayed_publish = true
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1924"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1924.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1924";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+#[test]
+fn test_delayed_publish() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-delayed-publish.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("delayed-subscriber")?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new(
+        "delayed/#",
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    let mut publisher = connect("delayed-publisher")?;
+    let start = Instant::now();
+    publisher.send(&v3::PublishPacket::new(
+        "$delayed/4/delayed/topic",
+        QoS::AtMostOnce,
+        b"hello",
+    )?);
+
+    // Message is held for 4 seconds, longer than read timeout of client.
+    assert!(subscriber.try_recv::<v3::PublishPacket>().is_none());
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert!(start.elapsed() >= Duration::from_secs(4));
+    assert_eq!(packet.topic(), "delayed/topic");
+    assert_eq!(packet.message(), b"hello");
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages are acked only after their batch is written to journal.
+
+use codec::{v3, EncodePacket, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1930.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1930"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[storage]
+journal_path = "/tmp/hebo-tests/hebo-1930.journal"
+batch_size = 3
+batch_window = 1000
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1930.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1930";
+const JOURNAL_PATH: &str = "/tmp/hebo-tests/hebo-1930.journal";
+
+fn journal_len() -> usize {
+    std::fs::metadata(JOURNAL_PATH).map_or(0, |metadata| metadata.len() as usize)
+}
+
+/// Byte length of a journal record, with its protocol version tag.
+fn record_len(packet: &v3::PublishPacket) -> usize {
+    let mut buf = Vec::new();
+    packet.encode(&mut buf).unwrap();
+    1 + buf.len()
+}
+
+fn new_packet(msg: &[u8], packet_id: u16) -> Result<v3::PublishPacket, Error> {
+    let mut packet = v3::PublishPacket::new("journal/status", QoS::AtLeastOnce, msg)?;
+    packet.set_packet_id(PacketId::new(packet_id));
+    Ok(packet)
+}
+
+#[test]
+fn test_journal() -> Result<(), Error> {
+    let _ret = std::fs::remove_file(JOURNAL_PATH);
+    let config = ServerConfig::new("/tmp/hebo-tests/02-journal.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new("journal-publisher")?);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    // A single message is written after batch window elapsed.
+    let packet = new_packet(b"1", 1)?;
+    let start = Instant::now();
+    client.send(&packet);
+    let ack_packet: v3::PublishAckPacket = client.recv();
+    assert_eq!(ack_packet.packet_id(), packet.packet_id());
+    assert!(start.elapsed() >= Duration::from_millis(900));
+    let mut expected_len = record_len(&packet);
+    assert_eq!(journal_len(), expected_len);
+
+    // A full batch is written at once, and acked in order.
+    let packets = [
+        new_packet(b"2", 2)?,
+        new_packet(b"3", 3)?,
+        new_packet(b"4", 4)?,
+    ];
+    let start = Instant::now();
+    for packet in &packets {
+        client.send(packet);
+    }
+    for packet in &packets {
+        let ack_packet: v3::PublishAckPacket = client.recv();
+        assert_eq!(ack_packet.packet_id(), packet.packet_id());
+        expected_len += record_len(packet);
+    }
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert_eq!(journal_len(), expected_len);
+
+    // QoS 0 messages are not written.
+    client.send(&v3::PublishPacket::new(
+        "journal/status",
+        QoS::AtMostOnce,
+        b"5",
+    )?);
+    client.send(&v3::PingRequestPacket::new());
+    let _ping_resp: v3::PingResponsePacket = client.recv();
+    sleep(Duration::from_millis(1200));
+    assert_eq!(journal_len(), expected_len);
+
+    server.terminate();
+    let _ret = std::fs::remove_file(JOURNAL_PATH);
+    Ok(())
+}
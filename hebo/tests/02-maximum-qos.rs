@@ -0,0 +1,83 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test `maximum_qos` in general section is advertised and enforced.
+
+use codec::{v3, v5, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1917.pid"
+maximum_qos = 1
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1917"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1917.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1917";
+
+#[test]
+fn test_maximum_qos() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-maximum-qos.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v5::ConnectPacket::new("maximum-qos-v5")?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    assert!(ack_packet
+        .properties()
+        .props()
+        .contains(&v5::Property::MaximumQoS(QoS::AtLeastOnce)));
+
+    // Subscription is downgraded to maximum QoS.
+    client.send(&v5::SubscribePacket::new(
+        "maximum-qos",
+        QoS::ExactOnce,
+        PacketId::new(1),
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::GrantedQoS1]);
+
+    let mut client_v3 = Client::connect(ADDRESS);
+    client_v3.send(&v3::ConnectPacket::new("maximum-qos-v3")?);
+    let _ack_packet: v3::ConnectAckPacket = client_v3.recv();
+    client_v3.send(&v3::SubscribePacket::new(
+        "maximum-qos",
+        QoS::ExactOnce,
+        PacketId::new(1),
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = client_v3.recv();
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [v3::SubscribeAck::QoS(QoS::AtLeastOnce)]
+    );
+
+    // Publishing at QoS 2 is rejected.
+    let mut packet = v5::PublishPacket::new("maximum-qos", QoS::ExactOnce, b"hello")?;
+    packet.set_packet_id(PacketId::new(2));
+    client.send(&packet);
+    let packet: v5::DisconnectPacket = client.recv();
+    assert_eq!(packet.reason_code(), v5::ReasonCode::QoSNotSupported);
+
+    server.terminate();
+    Ok(())
+}
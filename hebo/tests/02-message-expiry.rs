@@ -0,0 +1,134 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test queued and retained messages are discarded after message expiry interval.
+
+use codec::{v5, PacketId, QoS, U32Data};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1915.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1915"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1915.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1915";
+
+/// Connect with clean start flag off, session state is kept for 60 seconds.
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v5::ConnectPacket::new(client_id)?;
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(false);
+    packet.set_connect_flags(flags);
+    packet
+        .properties_mut()
+        .push(v5::Property::SessionExpiryInterval(U32Data::new(60)))?;
+    client.send(&packet);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok(client)
+}
+
+fn subscribe(client: &mut Client, filter: &str) -> Result<(), Error> {
+    client.send(&v5::SubscribePacket::new(
+        filter,
+        QoS::AtLeastOnce,
+        PacketId::new(1),
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::GrantedQoS1]);
+    Ok(())
+}
+
+fn publish(
+    client: &mut Client,
+    topic: &str,
+    msg: &[u8],
+    expiry_interval: u32,
+    retain: bool,
+) -> Result<(), Error> {
+    let mut packet = v5::PublishPacket::new(topic, QoS::AtLeastOnce, msg)?;
+    packet.set_packet_id(PacketId::new(1));
+    packet.set_retain(retain);
+    packet
+        .properties_mut()
+        .push(v5::Property::MessageExpiryInterval(U32Data::new(
+            expiry_interval,
+        )))?;
+    client.send(&packet);
+    let _ack_packet: v5::PublishAckPacket = client.recv();
+    Ok(())
+}
+
+fn expiry_interval(packet: &v5::PublishPacket) -> Option<u32> {
+    packet
+        .properties()
+        .props()
+        .iter()
+        .find_map(|property| match property {
+            v5::Property::MessageExpiryInterval(interval) => Some(interval.value()),
+            _ => None,
+        })
+}
+
+#[test]
+fn test_message_expiry() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-message-expiry.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut publisher = connect("expiry-publisher")?;
+
+    // Queued messages of offline session.
+    let mut subscriber = connect("expiry-subscriber")?;
+    subscribe(&mut subscriber, "expiry/queue")?;
+    subscriber.send(&v5::DisconnectPacket::new());
+    drop(subscriber);
+    sleep(Duration::from_millis(500));
+    publish(&mut publisher, "expiry/queue", b"short", 1, false)?;
+    publish(&mut publisher, "expiry/queue", b"long", 60, false)?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("expiry-subscriber")?;
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"long");
+    // Interval is reduced by the time message is queued.
+    let interval = expiry_interval(&packet).unwrap();
+    assert!((55..60).contains(&interval));
+    subscriber.send(&v5::PublishAckPacket::new(packet.packet_id()));
+    assert!(subscriber.try_recv::<v5::PublishPacket>().is_none());
+
+    // Retained messages.
+    publish(&mut publisher, "expiry/retain/short", b"short", 1, true)?;
+    publish(&mut publisher, "expiry/retain/long", b"long", 60, true)?;
+    sleep(Duration::from_secs(2));
+    let mut subscriber = connect("expiry-retain-subscriber")?;
+    subscribe(&mut subscriber, "expiry/retain/+")?;
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "expiry/retain/long");
+    let interval = expiry_interval(&packet).unwrap();
+    assert!((55..60).contains(&interval));
+    assert!(subscriber.try_recv::<v5::PublishPacket>().is_none());
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test QoS 1 messages are queued for disconnected persistent sessions.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1911.pid"
+max_queued_messages = 3
+queue_full_policy = "drop_oldest"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1911"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1911.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1911";
+
+fn connect(client_id: &str, clean_session: bool) -> Client {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v3::ConnectPacket::new(client_id).unwrap();
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(clean_session);
+    packet.set_connect_flags(flags);
+    client.send(&packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    client
+}
+
+fn publish(client: &mut Client, msg: &[u8], qos: QoS, packet_id: u16) -> Result<(), Error> {
+    let mut packet = v3::PublishPacket::new("offline/status", qos, msg)?;
+    if qos != QoS::AtMostOnce {
+        packet.set_packet_id(PacketId::new(packet_id));
+    }
+    client.send(&packet);
+    if qos != QoS::AtMostOnce {
+        let _ack_packet: v3::PublishAckPacket = client.recv();
+    }
+    Ok(())
+}
+
+/// Receive `count` messages and acknowledge them.
+fn recv_messages(client: &mut Client, count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|_i| {
+            let packet: v3::PublishPacket = client.recv();
+            assert_eq!(packet.qos(), QoS::AtLeastOnce);
+            client.send(&v3::PublishAckPacket::new(packet.packet_id()));
+            packet.message().to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn test_offline_queue() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-offline-queue.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("offline-subscriber", false);
+    subscriber.send(&v3::SubscribePacket::new(
+        "offline/#",
+        QoS::AtLeastOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    subscriber.send(&v3::DisconnectPacket::new());
+    drop(subscriber);
+    sleep(Duration::from_millis(500));
+
+    let mut publisher = connect("offline-publisher", true);
+    publish(&mut publisher, b"1", QoS::AtLeastOnce, 1)?;
+    publish(&mut publisher, b"2", QoS::AtLeastOnce, 2)?;
+    // QoS 0 messages are not queued.
+    publish(&mut publisher, b"qos0", QoS::AtMostOnce, 0)?;
+    publish(&mut publisher, b"3", QoS::AtLeastOnce, 3)?;
+    sleep(Duration::from_millis(500));
+
+    // Queued messages are delivered in order, with subscriptions restored.
+    let mut subscriber = connect("offline-subscriber", false);
+    assert_eq!(recv_messages(&mut subscriber, 3), [b"1", b"2", b"3"]);
+    publish(&mut publisher, b"4", QoS::AtLeastOnce, 4)?;
+    assert_eq!(recv_messages(&mut subscriber, 1), [b"4"]);
+    subscriber.send(&v3::DisconnectPacket::new());
+    drop(subscriber);
+    sleep(Duration::from_millis(500));
+
+    // Oldest messages are dropped when queue is full.
+    for (index, msg) in [b"5", b"6", b"7", b"8", b"9"].iter().enumerate() {
+        publish(&mut publisher, *msg, QoS::AtLeastOnce, 5 + index as u16)?;
+    }
+    sleep(Duration::from_millis(500));
+    let mut subscriber = connect("offline-subscriber", false);
+    assert_eq!(recv_messages(&mut subscriber, 3), [b"7", b"8", b"9"]);
+    assert!(subscriber.try_recv::<v3::PublishPacket>().is_none());
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test presence messages are published when clients connect and disconnect.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1905.pid"
+presence_topic = "$SYS/broker/clients/{client_id}/connected"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1905"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1905.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1905";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+fn subscribe_presence(client: &mut Client) -> Result<(), Error> {
+    let packet_id = PacketId::new(1);
+    client.send(&v3::SubscribePacket::new(
+        "$SYS/broker/clients/+/connected",
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+    Ok(())
+}
+
+#[test]
+fn test_presence_topic() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-presence-topic.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut monitor = connect("presence-monitor")?;
+    subscribe_presence(&mut monitor)?;
+    // Retained presence of monitor itself.
+    let packet: v3::PublishPacket = monitor.recv();
+    assert_eq!(
+        packet.topic(),
+        "$SYS/broker/clients/presence-monitor/connected"
+    );
+    assert_eq!(packet.message(), b"1");
+    assert!(packet.retain());
+
+    let mut client = connect("presence-client")?;
+    let packet: v3::PublishPacket = monitor.recv();
+    assert_eq!(
+        packet.topic(),
+        "$SYS/broker/clients/presence-client/connected"
+    );
+    assert_eq!(packet.message(), b"1");
+
+    client.send(&v3::DisconnectPacket::new());
+    let packet: v3::PublishPacket = monitor.recv();
+    assert_eq!(
+        packet.topic(),
+        "$SYS/broker/clients/presence-client/connected"
+    );
+    assert!(packet.message().is_empty());
+
+    // Retained presence of disconnected client is cleared.
+    let mut monitor2 = connect("presence-monitor2")?;
+    subscribe_presence(&mut monitor2)?;
+    let mut topics = Vec::new();
+    while let Some(packet) = monitor2.try_recv::<v3::PublishPacket>() {
+        topics.push(packet.topic().to_string());
+    }
+    topics.sort();
+    assert_eq!(
+        topics,
+        [
+            "$SYS/broker/clients/presence-monitor/connected",
+            "$SYS/broker/clients/presence-monitor2/connected"
+        ]
+    );
+
+    server.terminate();
+    Ok(())
+}
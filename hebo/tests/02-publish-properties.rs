@@ -0,0 +1,108 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test application properties of v5 publish packets are forwarded to subscribers.
+
+use codec::{
+    v5, BinaryData, BoolData, PacketId, PubTopic, QoS, StringData, StringPairData, U16Data,
+    U32Data, VarInt,
+};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1908.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1908"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1908.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1908";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v5::ConnectPacket::new(client_id)?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok(client)
+}
+
+fn application_properties() -> Vec<v5::Property> {
+    vec![
+        v5::Property::PayloadFormatIndicator(BoolData::new(true)),
+        v5::Property::ContentType(StringData::from("application/json").unwrap()),
+        v5::Property::ResponseTopic(PubTopic::new("rpc/client-1/response").unwrap()),
+        v5::Property::CorrelationData(BinaryData::from_slice(b"\x00\x01request-1").unwrap()),
+        v5::Property::UserProperty(StringPairData::new("trace-id", "abc").unwrap()),
+        v5::Property::UserProperty(StringPairData::new("trace-id", "def").unwrap()),
+        v5::Property::UserProperty(StringPairData::new("region", "eu").unwrap()),
+    ]
+}
+
+#[test]
+fn test_publish_properties() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-publish-properties.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("properties-subscriber")?;
+    subscriber.send(&v5::SubscribePacket::new(
+        "rpc/+/request",
+        QoS::AtMostOnce,
+        PacketId::new(1),
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::Success]);
+
+    let mut publisher = connect("properties-publisher")?;
+    let mut packet = v5::PublishPacket::new("rpc/1/request", QoS::AtMostOnce, b"{}")?;
+    for property in application_properties() {
+        packet.properties_mut().push(property)?;
+    }
+    // Connection scoped and broker managed properties.
+    packet
+        .properties_mut()
+        .push(v5::Property::TopicAlias(U16Data::new(1)))?;
+    packet
+        .properties_mut()
+        .push(v5::Property::SubscriptionIdentifier(
+            VarInt::from(3).unwrap(),
+        ))?;
+    publisher.send(&packet);
+
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "rpc/1/request");
+    assert_eq!(packet.message(), b"{}");
+    assert_eq!(packet.properties().props(), application_properties());
+
+    // Message expiry interval is kept too.
+    let mut packet = v5::PublishPacket::new("rpc/2/request", QoS::AtMostOnce, b"{}")?;
+    packet
+        .properties_mut()
+        .push(v5::Property::MessageExpiryInterval(U32Data::new(60)))?;
+    publisher.send(&packet);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(
+        packet.properties().props(),
+        [v5::Property::MessageExpiryInterval(U32Data::new(60))]
+    );
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test retained messages are sent to new wildcard subscriptions.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1898.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1898"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1898.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1898";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+fn publish_retained(client: &mut Client, topic: &str, message: &[u8]) -> Result<(), Error> {
+    let mut packet = v3::PublishPacket::new(topic, QoS::AtMostOnce, message)?;
+    packet.set_retain(true);
+    client.send(&packet);
+    // Wait for broker to handle this packet.
+    sleep(Duration::from_millis(100));
+    Ok(())
+}
+
+#[test]
+fn test_retain_wildcard() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-retain-wildcard.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut publisher = connect("retain-publisher")?;
+    publish_retained(&mut publisher, "sport/tennis", b"tennis")?;
+    publish_retained(&mut publisher, "sport/golf", b"golf")?;
+    publish_retained(&mut publisher, "news/today", b"news")?;
+    // Empty message removes retained message.
+    publish_retained(&mut publisher, "sport/golf", b"")?;
+
+    let mut subscriber = connect("retain-subscriber")?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new(
+        "sport/#",
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "sport/tennis");
+    assert_eq!(packet.message(), b"tennis");
+    assert!(packet.retain());
+    let packet: Option<v3::PublishPacket> = subscriber.try_recv();
+    assert!(packet.is_none());
+
+    server.terminate();
+    Ok(())
+}
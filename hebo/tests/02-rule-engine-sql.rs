@@ -0,0 +1,97 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages matching sql rule are inserted into pgsql table.
+
+#![cfg(all(feature = "rule_engine", feature = "pgsql_conn"))]
+
+use codec::{v3, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+use tokio_postgres::NoTls;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1927.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1927"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1927.log"
+
+[[rules]]
+match = "sensors/+/temp"
+action = "sql"
+
+[rules.sql]
+password = "hebo-password"
+table = "hebo_rule_sql_test"
+flush_interval = 100
+
+[rules.sql.columns]
+timestamp = "created_at"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1927";
+const DB_CONFIG: &str = "host=127.0.0.1 user=postgres password=hebo-password dbname=hebo-mqtt";
+
+fn execute(statement: &str) -> Vec<tokio_postgres::Row> {
+    tokio_test::block_on(async {
+        let (client, connection) = tokio_postgres::connect(DB_CONFIG, NoTls).await.unwrap();
+        tokio::spawn(connection);
+        client.query(statement, &[]).await.unwrap()
+    })
+}
+
+#[test]
+#[ignore = "requires a running pgsql server"]
+fn test_rule_engine_sql() -> Result<(), Error> {
+    execute(
+        "CREATE TABLE IF NOT EXISTS hebo_rule_sql_test \
+         (topic TEXT, payload BYTEA, qos SMALLINT, created_at BIGINT)",
+    );
+    execute("TRUNCATE hebo_rule_sql_test");
+
+    let config = ServerConfig::new("/tmp/hebo-tests/02-rule-engine-sql.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new("rule-sql-publisher")?);
+    let _ack_packet: v3::ConnectAckPacket = client.recv();
+    client.send(&v3::PublishPacket::new(
+        "sensors/1/temp",
+        QoS::AtMostOnce,
+        b"25",
+    )?);
+    client.send(&v3::PublishPacket::new(
+        "sensors/1/humidity",
+        QoS::AtMostOnce,
+        b"60",
+    )?);
+    sleep(Duration::from_secs(1));
+
+    let rows = execute("SELECT topic, payload, qos, created_at FROM hebo_rule_sql_test");
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get::<_, &str>(0), "sensors/1/temp");
+    assert_eq!(rows[0].get::<_, &[u8]>(1), b"25");
+    assert_eq!(rows[0].get::<_, i16>(2), 0);
+    assert!(rows[0].get::<_, i64>(3) > 0);
+
+    server.terminate();
+    execute("DROP TABLE hebo_rule_sql_test");
+    Ok(())
+}
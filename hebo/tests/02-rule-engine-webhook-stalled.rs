@@ -0,0 +1,116 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages are still dispatched while webhook of a rule never responds.
+
+#![cfg(feature = "rule_engine")]
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::net::TcpListener;
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1931.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1931"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1931.log"
+
+[[rules]]
+match = "load/+"
+action = "webhook"
+
+[rules.webhook]
+url = "http://127.0.0.1:18931/messages"
+timeout = 60
+max_retries = 0
+max_concurrency = 1
+queue_size = 1
+
+[[rules]]
+match = "load/+"
+action = "republish"
+target = "echo/{1}"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1931";
+const WEBHOOK_ADDRESS: &str = "127.0.0.1:18931";
+const MESSAGES: usize = 500;
+
+/// Start a mock http server which accepts connections but never responds.
+fn start_stalled_webhook() {
+    let listener = TcpListener::bind(WEBHOOK_ADDRESS).unwrap();
+    thread::spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            streams.push(stream.unwrap());
+        }
+    });
+}
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+#[test]
+fn test_rule_engine_webhook_stalled() -> Result<(), Error> {
+    start_stalled_webhook();
+
+    let config = ServerConfig::new(
+        "/tmp/hebo-tests/02-rule-engine-webhook-stalled.toml",
+        CONFIG,
+    )?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("rule-stalled-subscriber")?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new(
+        "echo/#",
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    // Queue of webhook output is full after the first two messages, and the others
+    // are dropped by rule engine instead of stalling dispatcher.
+    let mut publisher = connect("rule-stalled-publisher")?;
+    for i in 0..MESSAGES {
+        publisher.send(&v3::PublishPacket::new(
+            "load/1",
+            QoS::AtMostOnce,
+            i.to_string().as_bytes(),
+        )?);
+    }
+
+    for i in 0..MESSAGES {
+        let packet: v3::PublishPacket = subscriber.recv();
+        assert_eq!(packet.topic(), "echo/1");
+        assert_eq!(packet.message(), i.to_string().as_bytes());
+    }
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages matching webhook rule are posted to http endpoint, and retried on failure.
+
+#![cfg(feature = "rule_engine")]
+
+use base64::Engine;
+use codec::{v3, QoS};
+use hebo::error::Error;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1928.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1928"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1928.log"
+
+[[rules]]
+match = "sensors/+/temp"
+action = "webhook"
+
+[rules.webhook]
+url = "http://127.0.0.1:18928/messages"
+headers = { Authorization = "Bearer hebo-token" }
+retry_interval = 100
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1928";
+const WEBHOOK_ADDRESS: &str = "127.0.0.1:18928";
+
+/// Request received by mock http server.
+struct Request {
+    head: String,
+    body: String,
+}
+
+/// Start a mock http server, which responds with `statuses` in order.
+fn start_webhook(statuses: &'static [u16]) -> mpsc::Receiver<Request> {
+    let listener = TcpListener::bind(WEBHOOK_ADDRESS).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for status in statuses {
+            let (stream, _address) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let content_length = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().unwrap())
+                })
+                .unwrap();
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            sender
+                .send(Request {
+                    head,
+                    body: String::from_utf8(body).unwrap(),
+                })
+                .unwrap();
+        }
+    });
+    receiver
+}
+
+#[test]
+fn test_rule_engine_webhook() -> Result<(), Error> {
+    // The first request fails, and message is posted again.
+    let requests = start_webhook(&[500, 200]);
+
+    let config = ServerConfig::new("/tmp/hebo-tests/02-rule-engine-webhook.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new("rule-webhook-publisher")?);
+    let _ack_packet: v3::ConnectAckPacket = client.recv();
+    client.send(&v3::PublishPacket::new(
+        "sensors/1/humidity",
+        QoS::AtMostOnce,
+        b"60",
+    )?);
+    client.send(&v3::PublishPacket::new(
+        "sensors/1/temp",
+        QoS::AtMostOnce,
+        b"25",
+    )?);
+
+    let timeout = Duration::from_secs(3);
+    let first = requests.recv_timeout(timeout).unwrap();
+    assert!(first.head.starts_with("POST /messages HTTP/1.1\r\n"));
+    let head = first.head.to_ascii_lowercase();
+    assert!(head.contains("content-type: application/json\r\n"));
+    assert!(head.contains("authorization: bearer hebo-token\r\n"));
+
+    let second = requests.recv_timeout(timeout).unwrap();
+    assert_eq!(second.body, first.body);
+    let message: Value = serde_json::from_str(&second.body).unwrap();
+    assert_eq!(message["topic"], "sensors/1/temp");
+    let payload = base64::engine::general_purpose::STANDARD
+        .decode(message["payload"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(payload, b"25");
+    assert_eq!(message["qos"], 0);
+    assert!(message["timestamp"].as_u64().unwrap() > 0);
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages are republished, rewritten and dropped by rules.
+
+#![cfg(feature = "rule_engine")]
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1926.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1926"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1926.log"
+
+[[rules]]
+match = "sensors/+/temp"
+action = "republish"
+target = "agg/{1}/temp"
+
+[[rules]]
+match = "sensors/+/raw"
+action = "drop"
+
+[[rules]]
+match = "sensors/+/humidity"
+action = "rewrite"
+template = '{"sensor": "{1}", "humidity": {payload}}'
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1926";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+#[test]
+fn test_rule_engine() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-rule-engine.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("rule-subscriber")?;
+    let packet_id = PacketId::new(1);
+    subscriber.send(&v3::SubscribePacket::new("#", QoS::AtMostOnce, packet_id)?);
+    let ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+
+    let mut publisher = connect("rule-publisher")?;
+    publisher.send(&v3::PublishPacket::new(
+        "sensors/1/temp",
+        QoS::AtMostOnce,
+        b"25",
+    )?);
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "sensors/1/temp");
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "agg/1/temp");
+    assert_eq!(packet.message(), b"25");
+
+    publisher.send(&v3::PublishPacket::new(
+        "sensors/1/raw",
+        QoS::AtMostOnce,
+        b"0",
+    )?);
+    publisher.send(&v3::PublishPacket::new(
+        "sensors/2/humidity",
+        QoS::AtMostOnce,
+        b"60",
+    )?);
+    // Raw message is dropped.
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "sensors/2/humidity");
+    assert_eq!(packet.message(), br#"{"sensor": "2", "humidity": 60}"#);
+
+    server.terminate();
+    Ok(())
+}
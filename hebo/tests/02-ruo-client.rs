@@ -0,0 +1,294 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test async api of ruo client against in-process server.
+
+use codec::{ProtocolLevel, QoS};
+use hebo::config::{Dashboard, Listener, Protocol, Security};
+use hebo::server::ServerContext;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+use ruo::client::Client;
+use ruo::connect_options::{
+    ClientCert, ConnectOptions, ConnectType, MqttConnect, SelfSignedTls, TlsType,
+};
+use ruo::error::ErrorKind;
+use ruo::SubscribeAck;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+
+async fn start_server() -> SocketAddr {
+    start_server_with_listener(Listener::tcp("127.0.0.1:0")).await
+}
+
+async fn start_server_with_listener(listener: Listener) -> SocketAddr {
+    let mut security = Security::default();
+    security.set_allow_anonymous(true);
+    let mut dashboard = Dashboard::default();
+    dashboard.set_enable(false);
+    let mut server = ServerContext::builder()
+        .listener(listener)
+        .security(security)
+        .dashboard(dashboard)
+        .build()
+        .unwrap();
+    let receiver = server.bound_addresses();
+
+    // Server is dropped when test process exits.
+    thread::spawn(move || {
+        let runtime = Runtime::new().unwrap();
+        server.run_loop(&runtime).unwrap();
+    });
+    let addresses = receiver.await.unwrap();
+    addresses[0].1
+}
+
+fn new_client(address: SocketAddr, client_id: &str, protocol_level: ProtocolLevel) -> Client {
+    let mut options = ConnectOptions::new();
+    options
+        .set_connect_type(ConnectType::Mqtt(MqttConnect { address }))
+        .set_client_id(client_id)
+        .set_protocol_level(protocol_level);
+    Client::new(options)
+}
+
+async fn pubsub(address: SocketAddr, protocol_level: ProtocolLevel) {
+    let prefix = format!("ruo/{protocol_level:?}");
+    let client_id = format!("ruo-{protocol_level:?}");
+    let mut subscriber = new_client(address, &format!("{client_id}-subscriber"), protocol_level);
+    subscriber.connect().await.unwrap();
+    let ack = subscriber
+        .subscribe(&format!("{prefix}/#"), QoS::ExactOnce)
+        .await
+        .unwrap();
+    assert_eq!(ack, SubscribeAck::Granted(QoS::ExactOnce));
+
+    let mut publisher = new_client(address, &format!("{client_id}-publisher"), protocol_level);
+    publisher.connect().await.unwrap();
+    for qos in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactOnce] {
+        publisher
+            .publish(&format!("{prefix}/{qos:?}"), qos, b"hello")
+            .await
+            .unwrap();
+    }
+
+    for qos in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactOnce] {
+        let message = timeout(Duration::from_secs(3), subscriber.next_message())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.qos, qos);
+        assert_eq!(message.payload, b"hello");
+    }
+
+    subscriber
+        .unsubscribe(&format!("{prefix}/#"))
+        .await
+        .unwrap();
+    publisher
+        .publish(&format!("{prefix}/dropped"), QoS::AtLeastOnce, b"hello")
+        .await
+        .unwrap();
+    assert!(
+        timeout(Duration::from_millis(500), subscriber.next_message())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_ruo_client() {
+    let address = start_server().await;
+    pubsub(address, ProtocolLevel::V4).await;
+    pubsub(address, ProtocolLevel::V5).await;
+}
+
+#[tokio::test]
+async fn test_ruo_message_callback() {
+    let address = start_server().await;
+    let mut subscriber = new_client(address, "ruo-callback-subscriber", ProtocolLevel::V4);
+    let (sender, receiver) = mpsc::channel();
+    subscriber.set_message_callback(move |message| {
+        sender.send(message.topic.clone()).unwrap();
+    });
+    subscriber.connect().await.unwrap();
+    subscriber
+        .subscribe("ruo/callback", QoS::AtMostOnce)
+        .await
+        .unwrap();
+
+    let mut publisher = new_client(address, "ruo-callback-publisher", ProtocolLevel::V4);
+    publisher.connect().await.unwrap();
+    publisher
+        .publish("ruo/callback", QoS::AtLeastOnce, b"hello")
+        .await
+        .unwrap();
+
+    // Messages are handled in event loop.
+    let _ret = timeout(Duration::from_millis(500), subscriber.run_loop()).await;
+    assert_eq!(receiver.try_recv().unwrap(), "ruo/callback");
+}
+
+async fn will(address: SocketAddr, protocol_level: ProtocolLevel) {
+    let topic = format!("ruo/will/{protocol_level:?}");
+    let mut subscriber = new_client(
+        address,
+        &format!("ruo-will-subscriber-{protocol_level:?}"),
+        protocol_level,
+    );
+    subscriber.connect().await.unwrap();
+    subscriber
+        .subscribe(&topic, QoS::AtLeastOnce)
+        .await
+        .unwrap();
+
+    let mut options = ConnectOptions::new();
+    options
+        .set_connect_type(ConnectType::Mqtt(MqttConnect { address }))
+        .set_client_id(&format!("ruo-will-{protocol_level:?}"))
+        .set_protocol_level(protocol_level)
+        .set_will(&topic, b"offline", QoS::AtLeastOnce, false);
+    let mut client = Client::new(options);
+    client.connect().await.unwrap();
+    // Close network connection without sending disconnect packet.
+    drop(client);
+
+    let message = timeout(Duration::from_secs(3), subscriber.next_message())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message.topic, topic);
+    assert_eq!(message.qos, QoS::AtLeastOnce);
+    assert_eq!(message.payload, b"offline");
+}
+
+#[tokio::test]
+async fn test_ruo_will() {
+    let address = start_server().await;
+    will(address, ProtocolLevel::V4).await;
+    will(address, ProtocolLevel::V5).await;
+}
+
+#[tokio::test]
+async fn test_ruo_request() {
+    let address = start_server().await;
+    let mut responder = new_client(address, "ruo-responder", ProtocolLevel::V5);
+    responder.connect().await.unwrap();
+    responder
+        .subscribe("ruo/echo", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let mut requester = new_client(address, "ruo-requester", ProtocolLevel::V5);
+    requester.connect().await.unwrap();
+
+    let echo = async {
+        let request = responder.next_message().await.unwrap();
+        assert!(request.response_topic.is_some());
+        assert!(request.correlation_data.is_some());
+        responder.respond(&request, &request.payload).await.unwrap();
+    };
+    let (response, ()) = tokio::join!(
+        requester.request("ruo/echo", b"hello", Duration::from_secs(3)),
+        echo
+    );
+    let response = response.unwrap();
+    assert_eq!(response.payload, b"hello");
+
+    // Responder does not answer in time.
+    let err = requester
+        .request("ruo/echo", b"late", Duration::from_millis(300))
+        .await
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::TimeoutError));
+    let request = responder.next_message().await.unwrap();
+    responder.respond(&request, &request.payload).await.unwrap();
+    // Response topic is unsubscribed after timeout.
+    assert!(
+        timeout(Duration::from_millis(500), requester.next_message())
+            .await
+            .is_err()
+    );
+
+    let mut requester_v4 = new_client(address, "ruo-requester-v4", ProtocolLevel::V4);
+    requester_v4.connect().await.unwrap();
+    let err = requester_v4
+        .request("ruo/echo", b"hello", Duration::from_secs(1))
+        .await
+        .unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::ConfigError));
+}
+
+fn new_cert(common_name: &str, is_ca: bool) -> Certificate {
+    let mut params = CertificateParams::new(vec![common_name.to_string()]);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+    if is_ca {
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    }
+    Certificate::from_params(params).unwrap()
+}
+
+fn write_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+async fn connect_tls(url: &str, tls_type: TlsType, client_cert: Option<ClientCert>) -> bool {
+    let mut options = ConnectOptions::new();
+    options
+        .set_url(url)
+        .unwrap()
+        .set_tls(tls_type, client_cert)
+        .set_client_id("ruo-tls")
+        .set_connect_timeout(Duration::from_secs(3));
+    let mut client = Client::new(options);
+    client.connect().await.is_ok()
+}
+
+#[tokio::test]
+async fn test_ruo_client_tls() {
+    let dir = std::env::temp_dir().join("hebo-test-ruo-client-tls");
+    std::fs::create_dir_all(&dir).unwrap();
+    let ca = new_cert("hebo-ca", true);
+    let server = new_cert("localhost", false);
+    let client = new_cert("ruo-tls", false);
+    let ca_file = write_file(&dir, "ca.pem", &ca.serialize_pem().unwrap());
+    let server_cert = write_file(
+        &dir,
+        "server.pem",
+        &server.serialize_pem_with_signer(&ca).unwrap(),
+    );
+    let server_key = write_file(&dir, "server.key", &server.serialize_private_key_pem());
+    let client_cert = ClientCert {
+        cert: write_file(
+            &dir,
+            "client.pem",
+            &client.serialize_pem_with_signer(&ca).unwrap(),
+        ),
+        key: write_file(&dir, "client.key", &client.serialize_private_key_pem()),
+    };
+
+    let mut listener = Listener::new(Protocol::Mqtts, "127.0.0.1:0");
+    listener
+        .set_cert_file(server_cert, server_key)
+        .set_require_certificate(Some(ca_file.clone()));
+    let address = start_server_with_listener(listener).await;
+    let url = format!("mqtts://localhost:{}", address.port());
+    let self_signed = TlsType::SelfSigned(SelfSignedTls { cert: ca_file });
+
+    assert!(connect_tls(&url, self_signed.clone(), Some(client_cert.clone())).await);
+    assert!(connect_tls(&url, TlsType::Insecure, Some(client_cert.clone())).await);
+    // Client certificate is required by listener.
+    assert!(!connect_tls(&url, self_signed.clone(), None).await);
+    // Server certificate is not signed by public CA.
+    assert!(!connect_tls(&url, TlsType::CASigned, None).await);
+    // Listener keeps accepting connections after failed handshakes.
+    assert!(connect_tls(&url, self_signed, Some(client_cert)).await);
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test state of v5 sessions is discarded after session expiry interval.
+
+use codec::{v5, PacketId, QoS, U32Data};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1912.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1912"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1912.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1912";
+
+/// Connect with clean start flag off, returns client and session present flag.
+fn connect(client_id: &str, expiry_interval: u32) -> Result<(Client, bool), Error> {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v5::ConnectPacket::new(client_id)?;
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(false);
+    packet.set_connect_flags(flags);
+    packet
+        .properties_mut()
+        .push(v5::Property::SessionExpiryInterval(U32Data::new(
+            expiry_interval,
+        )))?;
+    client.send(&packet);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok((client, ack_packet.session_present()))
+}
+
+fn subscribe(client: &mut Client) -> Result<(), Error> {
+    client.send(&v5::SubscribePacket::new(
+        "expiry/#",
+        QoS::AtLeastOnce,
+        PacketId::new(1),
+    )?);
+    let ack_packet: v5::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::GrantedQoS1]);
+    Ok(())
+}
+
+fn disconnect(mut client: Client, expiry_interval: Option<u32>) -> Result<(), Error> {
+    let mut packet = v5::DisconnectPacket::new();
+    if let Some(expiry_interval) = expiry_interval {
+        packet
+            .properties_mut()
+            .push(v5::Property::SessionExpiryInterval(U32Data::new(
+                expiry_interval,
+            )))?;
+    }
+    client.send(&packet);
+    drop(client);
+    sleep(Duration::from_millis(500));
+    Ok(())
+}
+
+fn publish(client: &mut Client, msg: &[u8]) -> Result<(), Error> {
+    let mut packet = v5::PublishPacket::new("expiry/status", QoS::AtLeastOnce, msg)?;
+    packet.set_packet_id(PacketId::new(1));
+    client.send(&packet);
+    let _ack_packet: v5::PublishAckPacket = client.recv();
+    Ok(())
+}
+
+#[test]
+fn test_session_expiry() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-session-expiry.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let (mut publisher, _present) = connect("expiry-publisher", 0)?;
+
+    // Session is resumed before expiry interval elapses.
+    let (mut subscriber, present) = connect("expiry-subscriber", 60)?;
+    assert!(!present);
+    subscribe(&mut subscriber)?;
+    disconnect(subscriber, None)?;
+    publish(&mut publisher, b"1")?;
+    let (mut subscriber, present) = connect("expiry-subscriber", 1)?;
+    assert!(present);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"1");
+    subscriber.send(&v5::PublishAckPacket::new(packet.packet_id()));
+
+    // Session is discarded after expiry interval.
+    disconnect(subscriber, None)?;
+    sleep(Duration::from_secs(1));
+    publish(&mut publisher, b"2")?;
+    let (mut subscriber, present) = connect("expiry-subscriber", 60)?;
+    assert!(!present);
+    assert!(subscriber.try_recv::<v5::PublishPacket>().is_none());
+
+    // Expiry interval is updated to 0 in disconnect packet.
+    subscribe(&mut subscriber)?;
+    disconnect(subscriber, Some(0))?;
+    publish(&mut publisher, b"3")?;
+    let (mut subscriber, present) = connect("expiry-subscriber", 60)?;
+    assert!(!present);
+    assert!(subscriber.try_recv::<v5::PublishPacket>().is_none());
+
+    server.terminate();
+    Ok(())
+}
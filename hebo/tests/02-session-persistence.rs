@@ -0,0 +1,143 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test state of persistent sessions is restored after server restarts.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1913.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1913"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[storage]
+persistence = true
+db_path = "/tmp/hebo-tests/hebo-1913.db"
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1913.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1913";
+const DB_PATH: &str = "/tmp/hebo-tests/hebo-1913.db";
+
+/// Connect and returns client and session present flag.
+fn connect(client_id: &str, clean_session: bool) -> (Client, bool) {
+    let mut client = Client::connect(ADDRESS);
+    let mut packet = v3::ConnectPacket::new(client_id).unwrap();
+    let mut flags = packet.connect_flags().clone();
+    flags.set_clean_session(clean_session);
+    packet.set_connect_flags(flags);
+    client.send(&packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    (client, ack_packet.session_present())
+}
+
+fn publish(client: &mut Client, msg: &[u8], qos: QoS, packet_id: u16) -> Result<(), Error> {
+    let mut packet = v3::PublishPacket::new("persist/status", qos, msg)?;
+    packet.set_packet_id(PacketId::new(packet_id));
+    client.send(&packet);
+    if qos == QoS::ExactOnce {
+        let _ack_packet: v3::PublishReceivedPacket = client.recv();
+        client.send(&v3::PublishReleasePacket::new(PacketId::new(packet_id)));
+        let _ack_packet: v3::PublishCompletePacket = client.recv();
+    } else {
+        let _ack_packet: v3::PublishAckPacket = client.recv();
+    }
+    Ok(())
+}
+
+fn disconnect(mut client: Client) {
+    client.send(&v3::DisconnectPacket::new());
+    drop(client);
+    sleep(Duration::from_millis(500));
+}
+
+#[test]
+fn test_session_persistence() -> Result<(), Error> {
+    let _ret = std::fs::remove_file(DB_PATH);
+    let config = ServerConfig::new("/tmp/hebo-tests/02-session-persistence.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let (mut subscriber, present) = connect("persist-subscriber", false);
+    assert!(!present);
+    subscriber.send(&v3::SubscribePacket::new(
+        "persist/#",
+        QoS::ExactOnce,
+        PacketId::new(1),
+    )?);
+    let _ack_packet: v3::SubscribeAckPacket = subscriber.recv();
+
+    // QoS 2 handshake stops after PUBREL is sent to subscriber.
+    let (mut publisher, _present) = connect("persist-publisher", true);
+    publish(&mut publisher, b"1", QoS::ExactOnce, 1)?;
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.qos(), QoS::ExactOnce);
+    let packet_id = packet.packet_id();
+    subscriber.send(&v3::PublishReceivedPacket::new(packet_id));
+    let release_packet: v3::PublishReleasePacket = subscriber.recv();
+    assert_eq!(release_packet.packet_id(), packet_id);
+    disconnect(subscriber);
+
+    // Queued while subscriber is offline.
+    publish(&mut publisher, b"2", QoS::AtLeastOnce, 2)?;
+    disconnect(publisher);
+    server.terminate();
+
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    // Handshake resumes with the same packet id, then queued message is delivered.
+    let (mut subscriber, present) = connect("persist-subscriber", false);
+    assert!(present);
+    let release_packet: v3::PublishReleasePacket = subscriber.recv();
+    assert_eq!(release_packet.packet_id(), packet_id);
+    subscriber.send(&v3::PublishCompletePacket::new(packet_id));
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"2");
+    subscriber.send(&v3::PublishAckPacket::new(packet.packet_id()));
+
+    // Subscriptions are restored too.
+    let (mut publisher, _present) = connect("persist-publisher", true);
+    publish(&mut publisher, b"3", QoS::AtLeastOnce, 3)?;
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"3");
+    let packet_id = packet.packet_id();
+    disconnect(publisher);
+
+    // Server stops while subscriber is still connected, its unacked message is saved.
+    server.terminate();
+    drop(subscriber);
+
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let (mut subscriber, present) = connect("persist-subscriber", false);
+    assert!(present);
+    let packet: v3::PublishPacket = subscriber.recv();
+    assert_eq!(packet.message(), b"3");
+    assert_eq!(packet.packet_id(), packet_id);
+    assert!(packet.dup());
+    subscriber.send(&v3::PublishAckPacket::new(packet_id));
+
+    server.terminate();
+    Ok(())
+}
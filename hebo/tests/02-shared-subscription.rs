@@ -0,0 +1,105 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test messages are delivered to members of share group in turn.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1899.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1899"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1899.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1899";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let connect_packet = v3::ConnectPacket::new(client_id)?;
+    let mut client = Client::connect(ADDRESS);
+    client.send(&connect_packet);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+    Ok(client)
+}
+
+fn subscribe(client: &mut Client, topic: &str) -> Result<(), Error> {
+    let packet_id = PacketId::new(1);
+    client.send(&v3::SubscribePacket::new(
+        topic,
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+    assert_eq!(
+        ack_packet.acknowledgements(),
+        [v3::SubscribeAck::QoS(QoS::AtMostOnce)]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_shared_subscription() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-shared-subscription.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut member1 = connect("shared-member1")?;
+    subscribe(&mut member1, "$share/groupA/sensors/#")?;
+    let mut member2 = connect("shared-member2")?;
+    subscribe(&mut member2, "$share/groupA/sensors/#")?;
+    let mut subscriber = connect("shared-subscriber")?;
+    subscribe(&mut subscriber, "sensors/#")?;
+
+    let mut publisher = connect("shared-publisher")?;
+    for index in 0..4 {
+        let message = format!("{index}");
+        let packet =
+            v3::PublishPacket::new("sensors/temperature", QoS::AtMostOnce, message.as_bytes())?;
+        publisher.send(&packet);
+        // Wait for broker to handle this packet.
+        sleep(Duration::from_millis(100));
+    }
+
+    // Non-shared subscriber receives all of messages.
+    for index in 0..4 {
+        let packet: v3::PublishPacket = subscriber.recv();
+        assert_eq!(packet.message(), format!("{index}").as_bytes());
+    }
+
+    // Members of share group receive messages alternately.
+    for index in [0, 2] {
+        let packet: v3::PublishPacket = member1.recv();
+        assert_eq!(packet.message(), format!("{index}").as_bytes());
+    }
+    for index in [1, 3] {
+        let packet: v3::PublishPacket = member2.recv();
+        assert_eq!(packet.message(), format!("{index}").as_bytes());
+    }
+    let packet: Option<v3::PublishPacket> = member1.try_recv();
+    assert!(packet.is_none());
+    let packet: Option<v3::PublishPacket> = member2.try_recv();
+    assert!(packet.is_none());
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test subscription identifiers are sent back in messages matching the subscriptions.
+
+use codec::{v5, PacketId, QoS, VarInt};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1929.pid"
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1929"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1929.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1929";
+
+fn connect(client_id: &str) -> Result<Client, Error> {
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v5::ConnectPacket::new(client_id)?);
+    let ack_packet: v5::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.reason_code(), v5::ReasonCode::Success);
+    Ok(client)
+}
+
+fn subscribe(client: &mut Client, filter: &str, id: Option<usize>) -> Result<(), Error> {
+    let mut packet = v5::SubscribePacket::new(filter, QoS::AtMostOnce, PacketId::new(1))?;
+    if let Some(id) = id {
+        packet
+            .properties_mut()
+            .push(v5::Property::SubscriptionIdentifier(
+                VarInt::from(id).unwrap(),
+            ))?;
+    }
+    client.send(&packet);
+    let ack_packet: v5::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.reasons(), [v5::ReasonCode::Success]);
+    Ok(())
+}
+
+/// Publish a message and returns subscription identifiers in message received by subscriber.
+fn subscription_ids(
+    publisher: &mut Client,
+    subscriber: &mut Client,
+    topic: &str,
+) -> Result<Vec<usize>, Error> {
+    publisher.send(&v5::PublishPacket::new(topic, QoS::AtMostOnce, b"on")?);
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), topic);
+    let mut ids: Vec<usize> = packet
+        .properties()
+        .props()
+        .iter()
+        .filter_map(|property| match property {
+            v5::Property::SubscriptionIdentifier(id) => Some(id.value()),
+            _ => None,
+        })
+        .collect();
+    ids.sort_unstable();
+    Ok(ids)
+}
+
+#[test]
+fn test_subscription_identifier() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-subscription-identifier.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut subscriber = connect("sub-id-subscriber")?;
+    let mut publisher = connect("sub-id-publisher")?;
+    subscribe(&mut subscriber, "home/+/light", Some(1))?;
+    subscribe(&mut subscriber, "home/kitchen/#", Some(2))?;
+    subscribe(&mut subscriber, "office/#", None)?;
+
+    // Single match.
+    assert_eq!(
+        subscription_ids(&mut publisher, &mut subscriber, "home/bedroom/light")?,
+        [1]
+    );
+    assert_eq!(
+        subscription_ids(&mut publisher, &mut subscriber, "home/kitchen/fan")?,
+        [2]
+    );
+    // Multiple matches are delivered once, with all of their identifiers.
+    assert_eq!(
+        subscription_ids(&mut publisher, &mut subscriber, "home/kitchen/light")?,
+        [1, 2]
+    );
+    // Subscription without identifier.
+    assert!(subscription_ids(&mut publisher, &mut subscriber, "office/light")?.is_empty());
+
+    // Subscribing again replaces identifier.
+    subscribe(&mut subscriber, "home/kitchen/#", Some(3))?;
+    assert_eq!(
+        subscription_ids(&mut publisher, &mut subscriber, "home/kitchen/light")?,
+        [1, 3]
+    );
+
+    // Retained messages sent on subscribing carry identifier of the new subscription.
+    let mut packet = v5::PublishPacket::new("garden/light", QoS::AtMostOnce, b"on")?;
+    packet.set_retain(true);
+    publisher.send(&packet);
+    sleep(Duration::from_millis(200));
+    subscribe(&mut subscriber, "garden/+", Some(4))?;
+    let packet: v5::PublishPacket = subscriber.recv();
+    assert_eq!(packet.topic(), "garden/light");
+    assert_eq!(
+        packet.properties().props(),
+        [v5::Property::SubscriptionIdentifier(
+            VarInt::from(4).unwrap()
+        )]
+    );
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+//! Test broker metrics are published periodically to $SYS topics.
+
+use codec::{v3, PacketId, QoS};
+use hebo::error::Error;
+use std::thread::sleep;
+use std::time::Duration;
+
+mod common;
+use common::{Client, Server, ServerConfig};
+
+const CONFIG: &str = r#"
+[general]
+pid_file = "/tmp/hebo-tests/mqtt-1907.pid"
+sys_interval = 1
+
+[[listeners]]
+protocol = "mqtt"
+address = "0.0.0.0:1907"
+
+[security]
+allow_anonymous = true
+
+[dashboard]
+enable = false
+
+[log]
+log_file = "/tmp/hebo-tests/hebo-1907.log"
+"#;
+
+const ADDRESS: &str = "127.0.0.1:1907";
+
+fn subscribe(client_id: &str, topic: &str) -> Result<Client, Error> {
+    let mut client = Client::connect(ADDRESS);
+    client.send(&v3::ConnectPacket::new(client_id)?);
+    let ack_packet: v3::ConnectAckPacket = client.recv();
+    assert_eq!(ack_packet.return_code(), v3::ConnectReturnCode::Accepted);
+
+    let packet_id = PacketId::new(1);
+    client.send(&v3::SubscribePacket::new(
+        topic,
+        QoS::AtMostOnce,
+        packet_id,
+    )?);
+    let ack_packet: v3::SubscribeAckPacket = client.recv();
+    assert_eq!(ack_packet.packet_id(), packet_id);
+    Ok(client)
+}
+
+#[test]
+fn test_sys_tree() -> Result<(), Error> {
+    let config = ServerConfig::new("/tmp/hebo-tests/02-sys-tree.toml", CONFIG)?;
+    let server = Server::start(config.filename())?;
+    sleep(Duration::from_secs(2));
+
+    let mut wildcard = subscribe("sys-wildcard", "#")?;
+    let mut monitor = subscribe("sys-monitor", "$SYS/broker/uptime")?;
+
+    let packet: v3::PublishPacket = monitor.recv();
+    assert_eq!(packet.topic(), "$SYS/broker/uptime");
+    let uptime = std::str::from_utf8(packet.message()).unwrap();
+    assert!(uptime.parse::<u64>().unwrap() > 0);
+    let packet: v3::PublishPacket = monitor.recv();
+    assert_eq!(packet.topic(), "$SYS/broker/uptime");
+
+    // `#` does not match topics starting with `$`.
+    assert!(wildcard.try_recv::<v3::PublishPacket>().is_none());
+
+    server.terminate();
+    Ok(())
+}
@@ -0,0 +1,90 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use codec::{ByteArray, DecodePacket, EncodePacket, FixedHeader};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// A raw mqtt client used to send and receive packets.
+#[derive(Debug)]
+pub struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    pub fn connect(address: &str) -> Self {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(3)))
+            .unwrap();
+        Self {
+            stream,
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn send<P: EncodePacket>(&mut self, packet: &P) {
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        self.stream.write_all(&buf).unwrap();
+    }
+
+    /// Write raw bytes to stream.
+    pub fn send_bytes(&mut self, buf: &[u8]) {
+        self.stream.write_all(buf).unwrap();
+    }
+
+    /// Read exactly one packet from stream.
+    pub fn recv<P: DecodePacket>(&mut self) -> P {
+        self.try_recv().expect("Read timeout")
+    }
+
+    /// Read exactly one packet from stream, returns None if read timeout.
+    pub fn try_recv<P: DecodePacket>(&mut self) -> Option<P> {
+        loop {
+            if !self.buf.is_empty() {
+                let mut ba = ByteArray::new(&self.buf);
+                if let Ok(fixed_header) = FixedHeader::decode(&mut ba) {
+                    let packet_len = ba.offset() + fixed_header.remaining_length();
+                    if self.buf.len() >= packet_len {
+                        let mut ba = ByteArray::new(&self.buf[..packet_len]);
+                        let packet = P::decode(&mut ba).unwrap();
+                        self.buf.drain(..packet_len);
+                        return Some(packet);
+                    }
+                }
+            }
+
+            let mut buf = [0; 1024];
+            let n_read = match self.stream.read(&mut buf) {
+                Ok(n_read) => n_read,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return None;
+                }
+                Err(err) => panic!("Failed to read from stream: {err:?}"),
+            };
+            assert!(n_read > 0, "Stream is closed");
+            self.buf.extend_from_slice(&buf[..n_read]);
+        }
+    }
+
+    /// Returns true if stream is closed by server before read timeout.
+    pub fn is_closed(&mut self) -> bool {
+        let mut buf = [0; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return true,
+                Ok(_n_read) => continue,
+                Err(err) => return err.kind() == io::ErrorKind::ConnectionReset,
+            }
+        }
+    }
+}
@@ -36,6 +36,19 @@ impl ServerConfig {
         })
     }
 
+    /// Overwrite content of config file.
+    pub fn update(&self, content: &str) -> Result<(), Error> {
+        fs::write(&self.filename, content).map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!(
+                    "Failed to write to config file: {}, err: {err}",
+                    self.filename
+                ),
+            )
+        })
+    }
+
     pub fn filename(&self) -> &str {
         &self.filename
     }
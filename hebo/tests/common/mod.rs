@@ -2,8 +2,12 @@
 // Use of this source is governed by General Public License that can be found
 // in the LICENSE file.
 
+#![allow(dead_code, unused_imports)]
+
+mod client;
 mod config;
 mod server;
 
+pub use client::Client;
 pub use config::ServerConfig;
 pub use server::Server;
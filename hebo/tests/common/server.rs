@@ -38,6 +38,15 @@ impl Server {
         })
     }
 
+    /// Notify server to reload config file.
+    pub fn reload(&self) {
+        let status = Command::new(&self.exec_file)
+            .args(["-c", &self.config_file, "-r"])
+            .status();
+        assert!(status.map_or(false, |status| status.success()));
+        thread::sleep(Duration::from_secs(1));
+    }
+
     pub fn terminate(self) {
         let ret = Command::new(&self.exec_file)
             .args(["-c", &self.config_file, "-s"])
@@ -0,0 +1,47 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by General Public License that can be found
+// in the LICENSE file.
+
+use codec::QoS;
+use ruo::client::Client;
+use ruo::connect_options::ConnectOptions;
+use ruo::error::Error;
+use ruo::SubscribeAck;
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
+
+    let mut options = ConnectOptions::new();
+    options.set_client_id("ruo-pubsub");
+    let mut client = Client::new(options);
+    client.connect().await?;
+
+    let ack = client.subscribe("hello/#", QoS::ExactOnce).await?;
+    if let SubscribeAck::Rejected(reason_code) = ack {
+        log::error!("Subscription rejected: {}", reason_code.description());
+        return Ok(());
+    }
+
+    // Each publish returns after the delivery flow of its QoS completes.
+    for (i, qos) in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactOnce]
+        .into_iter()
+        .enumerate()
+    {
+        let topic = format!("hello/{i}");
+        client.publish(&topic, qos, b"Hello, world").await?;
+    }
+
+    for _i in 0..3 {
+        let message = client.next_message().await?;
+        log::info!(
+            "Got message, topic: {}, qos: {:?}, payload: {:?}",
+            message.topic,
+            message.qos,
+            String::from_utf8_lossy(&message.payload)
+        );
+    }
+
+    client.unsubscribe("hello/#").await
+}
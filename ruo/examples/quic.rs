@@ -7,6 +7,7 @@ use ruo::client::Client;
 use ruo::connect_options::{ConnectOptions, ConnectType, QuicConnect, SelfSignedTls, TlsType};
 use std::path::PathBuf;
 
+#[allow(dead_code)]
 async fn on_connect(client: &mut Client) {
     log::info!(
         "[on_connect] client id: {}",
@@ -77,6 +77,8 @@ impl Client {
 
     /// Publish packet.
     ///
+    /// Retain flag is set to [`ConnectOptions::default_retain()`].
+    ///
     /// # Errors
     ///
     /// Returns error if:
@@ -84,9 +86,42 @@ impl Client {
     /// - `payload` is too large
     /// - Socket stream error
     pub fn publish(&mut self, topic: &str, qos: QoS, payload: &[u8]) -> Result<(), Error> {
+        let retain = self.connect_options().default_retain();
+        self.publish_with_retain(topic, qos, retain, payload)
+    }
+
+    /// Publish packet with default `QoS` and retain flag in [`ConnectOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - `topic` is invalid
+    /// - `payload` is too large
+    /// - Socket stream error
+    pub fn publish_default(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let qos = self.connect_options().default_qos();
+        let retain = self.connect_options().default_retain();
+        self.publish_with_retain(topic, qos, retain, payload)
+    }
+
+    /// Publish packet with explicit `QoS` and retain flag.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - `topic` is invalid
+    /// - `payload` is too large
+    /// - Socket stream error
+    pub fn publish_with_retain(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &[u8],
+    ) -> Result<(), Error> {
         match &mut self.inner {
-            Inner::V3(inner) | Inner::V4(inner) => inner.publish(topic, qos, payload),
-            Inner::V5(inner) => inner.publish(topic, qos, payload),
+            Inner::V3(inner) | Inner::V4(inner) => inner.publish(topic, qos, retain, payload),
+            Inner::V5(inner) => inner.publish(topic, qos, retain, payload),
         }
     }
 
@@ -3,9 +3,8 @@
 // in the LICENSE file.
 
 use codec::v3::{
-    ConnectAckPacket, ConnectPacket, ConnectReturnCode, DisconnectPacket, PingRequestPacket,
-    PublishAckPacket, PublishPacket, SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket,
-    UnsubscribePacket,
+    ConnectAckPacket, ConnectReturnCode, DisconnectPacket, PingRequestPacket, PublishAckPacket,
+    PublishPacket, SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket, UnsubscribePacket,
 };
 use codec::{ByteArray, DecodePacket, EncodePacket, FixedHeader, PacketId, PacketType, QoS};
 use std::collections::HashMap;
@@ -78,7 +77,7 @@ impl ClientInnerV3 {
         assert_eq!(self.status, ClientStatus::Disconnected);
         let stream = Stream::new(self.connect_options.connect_type())?;
         self.stream = Some(stream);
-        let conn_packet = ConnectPacket::new(self.connect_options.client_id())?;
+        let conn_packet = self.connect_options.connect_packet_v3()?;
         self.status = ClientStatus::Connecting;
         self.send_packet(&conn_packet)?;
 
@@ -122,9 +121,16 @@ impl ClientInnerV3 {
     }
 
     /// Publish message to server.
-    pub fn publish(&mut self, topic: &str, qos: QoS, data: &[u8]) -> Result<(), Error> {
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        data: &[u8],
+    ) -> Result<(), Error> {
         assert_eq!(self.status, ClientStatus::Connected);
         let mut packet = PublishPacket::new(topic, qos, data)?;
+        packet.set_retain(retain);
         let packet_id = self.next_packet_id();
         packet.set_packet_id(packet_id);
         match packet.qos() {
@@ -221,14 +227,10 @@ impl ClientInnerV3 {
     }
 
     #[allow(clippy::unused_self)]
-    fn on_publish_message(&mut self, ba: &mut ByteArray) -> Result<PublishMessage, Error> {
+    fn on_publish_message(&self, ba: &mut ByteArray) -> Result<PublishMessage, Error> {
         // TODO(Shaohua): Support QoS1 / QoS2.
         let packet = PublishPacket::decode(ba)?;
-        Ok(PublishMessage {
-            topic: packet.topic().to_owned(),
-            qos: packet.qos(),
-            payload: packet.message().into(),
-        })
+        Ok(PublishMessage::from(&packet))
     }
 
     #[allow(clippy::unused_self)]
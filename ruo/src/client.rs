@@ -7,14 +7,36 @@
 use codec::{ProtocolLevel, QoS};
 use std::fmt;
 use std::future::Future;
+use std::time::Duration;
 
 use crate::connect_options::ConnectOptions;
-use crate::error::Error;
-use crate::{ClientInnerV3, ClientInnerV4, ClientInnerV5, ClientStatus};
+use crate::error::{Error, ErrorKind};
+use crate::{
+    ClientInnerV3, ClientInnerV4, ClientInnerV5, ClientStatus, PublishMessage, SubscribeAck,
+};
 
 type FutureConnectCb = dyn Fn(&mut Client) -> dyn Future<Output = ()>;
 
 /// Asynchronous mqtt client.
+///
+/// Methods return after the server acknowledges the request, and messages from
+/// server are read with [`Self::next_message()`]:
+///
+/// ```no_run
+/// # async fn run() -> Result<(), ruo::error::Error> {
+/// use codec::QoS;
+/// use ruo::client::Client;
+/// use ruo::connect_options::ConnectOptions;
+///
+/// let mut client = Client::new(ConnectOptions::new());
+/// client.connect().await?;
+/// client.subscribe("hello", QoS::AtLeastOnce).await?;
+/// client.publish("hello", QoS::ExactOnce, b"world").await?;
+/// let message = client.next_message().await?;
+/// assert_eq!(message.payload, b"world");
+/// # Ok(())
+/// # }
+/// ```
 pub struct Client {
     inner: Inner,
     connect_cb: Option<Box<FutureConnectCb>>,
@@ -56,6 +78,29 @@ impl Client {
         self.connect_cb = Some(callback);
     }
 
+    /// Handle messages from server with `callback`, instead of queuing them for
+    /// [`Self::next_message()`].
+    ///
+    /// This is useful if messages are handled in [`Self::run_loop()`].
+    pub fn set_message_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(&PublishMessage) + Send + 'static,
+    {
+        let callback = Some(Box::new(callback) as _);
+        match &mut self.inner {
+            Inner::V3(inner) | Inner::V4(inner) => inner.set_message_callback(callback),
+            Inner::V5(inner) => inner.set_message_callback(callback),
+        }
+    }
+
+    /// Remove message callback, messages from server are queued again.
+    pub fn clear_message_callback(&mut self) {
+        match &mut self.inner {
+            Inner::V3(inner) | Inner::V4(inner) => inner.set_message_callback(None),
+            Inner::V5(inner) => inner.set_message_callback(None),
+        }
+    }
+
     /// Get mqtt connection options.
     #[must_use]
     pub const fn connect_options(&self) -> &ConnectOptions {
@@ -74,11 +119,17 @@ impl Client {
         }
     }
 
-    /// Connect to server.
+    /// Connect to server and wait for connect ack packet.
+    ///
+    /// If connection is rejected with a retryable reason and reconnect is enabled,
+    /// returns Ok and [`Self::run_loop()`] will reconnect later.
     ///
     /// # Errors
     ///
-    /// Returns error if server is unreachable or connection is rejected.
+    /// Returns error if:
+    /// - Server is unreachable
+    /// - Connection is rejected
+    /// - No connect ack in [`ConnectOptions::connect_timeout()`]
     pub async fn connect(&mut self) -> Result<(), Error> {
         match &mut self.inner {
             Inner::V3(inner) | Inner::V4(inner) => inner.connect().await,
@@ -96,6 +147,11 @@ impl Client {
 
     /// Send a message to server.
     ///
+    /// Retain flag is set to [`ConnectOptions::default_retain()`].
+    ///
+    /// `QoS` 0 messages complete once sent, `QoS` 1 messages complete when publish ack
+    /// is received, and `QoS` 2 messages complete when publish complete is received.
+    ///
     /// # Errors
     ///
     /// Returns error if:
@@ -103,27 +159,61 @@ impl Client {
     /// - `payload` is too large
     /// - Socket stream error
     pub async fn publish(&mut self, topic: &str, qos: QoS, payload: &[u8]) -> Result<(), Error> {
+        let retain = self.connect_options().default_retain();
+        self.publish_with_retain(topic, qos, retain, payload).await
+    }
+
+    /// Send a message to server with default `QoS` and retain flag in [`ConnectOptions`].
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - `topic` is invalid
+    /// - `payload` is too large
+    /// - Socket stream error
+    pub async fn publish_default(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let qos = self.connect_options().default_qos();
+        let retain = self.connect_options().default_retain();
+        self.publish_with_retain(topic, qos, retain, payload).await
+    }
+
+    /// Send a message to server with explicit `QoS` and retain flag.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - `topic` is invalid
+    /// - `payload` is too large
+    /// - Socket stream error
+    /// - Message is rejected by mqtt 5.0 server
+    pub async fn publish_with_retain(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: &[u8],
+    ) -> Result<(), Error> {
         match &mut self.inner {
-            Inner::V3(inner) | Inner::V4(inner) => inner.publish(topic, qos, payload).await,
-            Inner::V5(inner) => inner.publish(topic, qos, payload).await,
+            Inner::V3(inner) | Inner::V4(inner) => inner.publish(topic, qos, retain, payload).await,
+            Inner::V5(inner) => inner.publish(topic, qos, retain, payload).await,
         }
     }
 
-    /// Subscribe to a specific `topic`.
+    /// Subscribe to a specific `topic`, and returns result in subscribe ack packet.
     ///
     /// # Errors
     ///
     /// Returns error if:
     /// - `topic` pattern is invalid
     /// - Socket stream returns error
-    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), Error> {
+    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<SubscribeAck, Error> {
         match &mut self.inner {
             Inner::V3(inner) | Inner::V4(inner) => inner.subscribe(topic, qos).await,
             Inner::V5(inner) => inner.subscribe(topic, qos).await,
         }
     }
 
-    /// Unsubscribe specific `topic` pattern.
+    /// Unsubscribe specific `topic` pattern, and wait for unsubscribe ack packet.
     ///
     /// # Errors
     ///
@@ -137,13 +227,75 @@ impl Client {
         }
     }
 
-    /// Send ping packet to server explicitly.
+    /// Wait for next message from server.
+    ///
+    /// Messages received while waiting for other acks are queued and returned first.
+    /// Never returns if message callback is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if socket stream returns error or is closed by server.
+    pub async fn next_message(&mut self) -> Result<PublishMessage, Error> {
+        match &mut self.inner {
+            Inner::V3(inner) | Inner::V4(inner) => inner.next_message().await,
+            Inner::V5(inner) => inner.next_message().await,
+        }
+    }
+
+    /// Publish a request to `topic`, and wait for the response with same
+    /// correlation data, only supported by v5 clients.
+    ///
+    /// A temporary response topic is subscribed while waiting for response.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - Protocol level is not v5
+    /// - Request or response topic is rejected by server
+    /// - No response in `timeout`
+    /// - Socket stream returns error
+    pub async fn request(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<PublishMessage, Error> {
+        match &mut self.inner {
+            Inner::V3(_) | Inner::V4(_) => Err(Error::new(
+                ErrorKind::ConfigError,
+                "Request/response requires MQTT v5",
+            )),
+            Inner::V5(inner) => inner.request(topic, payload, timeout).await,
+        }
+    }
+
+    /// Publish `payload` to response topic of `request` message, with its
+    /// correlation data, only supported by v5 clients.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - Protocol level is not v5
+    /// - `request` has no response topic
+    /// - Socket stream returns error
+    pub async fn respond(&mut self, request: &PublishMessage, payload: &[u8]) -> Result<(), Error> {
+        match &mut self.inner {
+            Inner::V3(_) | Inner::V4(_) => Err(Error::new(
+                ErrorKind::ConfigError,
+                "Request/response requires MQTT v5",
+            )),
+            Inner::V5(inner) => inner.respond(request, payload).await,
+        }
+    }
+
+    /// Send ping packet to server explicitly, and wait for ping response.
     ///
     /// # Errors
     ///
     /// Returns error if:
     /// - Client status is invalid
     /// - Socket stream returns error
+    /// - No ping response from server in [`ConnectOptions::ping_timeout()`]
     pub async fn ping(&mut self) -> Result<(), Error> {
         match &mut self.inner {
             Inner::V3(inner) | Inner::V4(inner) => inner.ping().await,
@@ -151,3 +303,286 @@ impl Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use codec::v3::{ConnectAckPacket, ConnectPacket, ConnectReturnCode, PublishPacket};
+    use codec::{
+        v3, v5, ByteArray, DecodePacket, EncodePacket, FixedHeader, PacketId, PacketType,
+        ProtocolLevel, QoS,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::Client;
+    use crate::connect_options::{ConnectOptions, ConnectType, MqttConnect};
+    use crate::error::ErrorKind;
+    use crate::reconnect::ReconnectOptions;
+    use crate::SubscribeAck;
+
+    /// Run a fake server which rejects every connection with `reason_code`,
+    /// and returns number of accepted connections after client runs for a while.
+    async fn count_connections(reason_code: v5::ReasonCode) -> usize {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let accepted_clone = accepted.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _address) = listener.accept().await.unwrap();
+                accepted_clone.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    stream.read_buf(&mut buf).await.unwrap();
+                    let mut ack_buf = Vec::new();
+                    v5::ConnectAckPacket::new(false, reason_code)
+                        .encode(&mut ack_buf)
+                        .unwrap();
+                    stream.write_all(&ack_buf).await.unwrap();
+                    // Keep socket open until client closes it.
+                    while let Ok(n_recv) = stream.read_buf(&mut buf).await {
+                        if n_recv == 0 {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut reconnect = ReconnectOptions::new();
+        reconnect
+            .set_enabled(true)
+            .set_retry_interval(Duration::from_millis(50))
+            .set_maximum_retry_interval(Duration::from_millis(100));
+        let mut options = ConnectOptions::new();
+        options
+            .set_connect_type(ConnectType::Mqtt(MqttConnect { address }))
+            .set_protocol_level(ProtocolLevel::V5)
+            .set_reconnect(reconnect);
+        let mut client = Client::new(options);
+        let ret = client.connect().await;
+        if reason_code == v5::ReasonCode::ServerBusy {
+            assert!(ret.is_ok());
+        } else {
+            assert!(matches!(
+                ret.unwrap_err().kind(),
+                ErrorKind::ConnectionRefused
+            ));
+        }
+        let _ret = tokio::time::timeout(Duration::from_millis(600), client.run_loop()).await;
+        accepted.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_server_busy() {
+        assert!(count_connections(v5::ReasonCode::ServerBusy).await > 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_reconnect_bad_credentials() {
+        assert_eq!(
+            count_connections(v5::ReasonCode::BadUserNameOrPassword).await,
+            1
+        );
+    }
+
+    /// Read next packet from client, skipping ping requests.
+    async fn read_packet<P: DecodePacket>(stream: &mut TcpStream, buf: &mut Vec<u8>) -> P {
+        loop {
+            let mut ba = ByteArray::new(buf);
+            if let Ok(fixed_header) = FixedHeader::decode(&mut ba) {
+                let len = fixed_header.bytes() + fixed_header.remaining_length();
+                if buf.len() >= len {
+                    let packet_buf: Vec<u8> = buf.drain(..len).collect();
+                    if fixed_header.packet_type() == PacketType::PingRequest {
+                        continue;
+                    }
+                    let mut ba = ByteArray::new(&packet_buf);
+                    return P::decode(&mut ba).unwrap();
+                }
+            }
+            assert!(stream.read_buf(buf).await.unwrap() > 0);
+        }
+    }
+
+    async fn write_packet<P: EncodePacket>(stream: &mut TcpStream, packet: &P) {
+        let mut buf = Vec::new();
+        packet.encode(&mut buf).unwrap();
+        stream.write_all(&buf).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_publish_default_qos() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _address) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let _packet: ConnectPacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(
+                &mut stream,
+                &ConnectAckPacket::new(false, ConnectReturnCode::Accepted),
+            )
+            .await;
+            let packet: PublishPacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(&mut stream, &v3::PublishAckPacket::new(packet.packet_id())).await;
+            packet
+        });
+
+        let mut options = ConnectOptions::new();
+        options
+            .set_connect_type(ConnectType::Mqtt(MqttConnect { address }))
+            .set_default_qos(QoS::AtLeastOnce);
+        let mut client = Client::new(options);
+        client.connect().await.unwrap();
+        // Returns after publish ack is received.
+        client.publish_default("hello", b"world").await.unwrap();
+
+        let packet = server.await.unwrap();
+        assert_eq!(packet.topic(), "hello");
+        assert_eq!(packet.qos(), QoS::AtLeastOnce);
+        assert!(!packet.retain());
+        assert_eq!(packet.message(), b"world");
+    }
+
+    #[tokio::test]
+    async fn test_exactly_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _address) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let _packet: ConnectPacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(
+                &mut stream,
+                &ConnectAckPacket::new(false, ConnectReturnCode::Accepted),
+            )
+            .await;
+
+            let packet: v3::SubscribePacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(
+                &mut stream,
+                &v3::SubscribeAckPacket::new(
+                    packet.packet_id(),
+                    v3::SubscribeAck::QoS(QoS::ExactOnce),
+                ),
+            )
+            .await;
+
+            // Outgoing QoS 2 flow.
+            let packet: PublishPacket = read_packet(&mut stream, &mut buf).await;
+            let packet_id = packet.packet_id();
+            write_packet(&mut stream, &v3::PublishReceivedPacket::new(packet_id)).await;
+            let _packet: v3::PublishReleasePacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(&mut stream, &v3::PublishCompletePacket::new(packet_id)).await;
+
+            // Incoming QoS 2 flow, message is sent twice before released.
+            let mut packet = PublishPacket::new("hello", QoS::ExactOnce, b"server").unwrap();
+            packet.set_packet_id(PacketId::new(7));
+            write_packet(&mut stream, &packet).await;
+            let _packet: v3::PublishReceivedPacket = read_packet(&mut stream, &mut buf).await;
+            packet.set_dup(true).unwrap();
+            write_packet(&mut stream, &packet).await;
+            let _packet: v3::PublishReceivedPacket = read_packet(&mut stream, &mut buf).await;
+            write_packet(
+                &mut stream,
+                &v3::PublishReleasePacket::new(PacketId::new(7)),
+            )
+            .await;
+            let packet: v3::PublishCompletePacket = read_packet(&mut stream, &mut buf).await;
+            assert_eq!(packet.packet_id(), PacketId::new(7));
+
+            let packet = PublishPacket::new("hello", QoS::AtMostOnce, b"done").unwrap();
+            write_packet(&mut stream, &packet).await;
+            // Keep socket open until client closes it.
+            while let Ok(n_recv) = stream.read_buf(&mut buf).await {
+                if n_recv == 0 {
+                    break;
+                }
+            }
+        });
+
+        let mut options = ConnectOptions::new();
+        options.set_connect_type(ConnectType::Mqtt(MqttConnect { address }));
+        let mut client = Client::new(options);
+        client.connect().await.unwrap();
+        let ack = client.subscribe("hello", QoS::ExactOnce).await.unwrap();
+        assert_eq!(ack, SubscribeAck::Granted(QoS::ExactOnce));
+        client
+            .publish("hello", QoS::ExactOnce, b"client")
+            .await
+            .unwrap();
+
+        let message = client.next_message().await.unwrap();
+        assert_eq!(message.topic, "hello");
+        assert_eq!(message.qos, QoS::ExactOnce);
+        assert_eq!(message.payload, b"server");
+        // Duplicated message is not delivered again.
+        let message = client.next_message().await.unwrap();
+        assert_eq!(message.payload, b"done");
+        drop(client);
+        server.await.unwrap();
+    }
+
+    /// Run a fake server which accepts connection, sends a publish packet and
+    /// an unsolicited ping response, then replies ping requests if `reply_ping` is true.
+    async fn ping_server(reply_ping: bool) -> ConnectOptions {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _address) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            stream.read_buf(&mut buf).await.unwrap();
+            let mut out_buf = Vec::new();
+            ConnectAckPacket::new(false, ConnectReturnCode::Accepted)
+                .encode(&mut out_buf)
+                .unwrap();
+            PublishPacket::new("hello", QoS::AtMostOnce, b"world")
+                .unwrap()
+                .encode(&mut out_buf)
+                .unwrap();
+            v3::PingResponsePacket::new().encode(&mut out_buf).unwrap();
+            stream.write_all(&out_buf).await.unwrap();
+
+            let mut ping_buf = Vec::new();
+            v3::PingResponsePacket::new().encode(&mut ping_buf).unwrap();
+            buf.clear();
+            while let Ok(n_recv) = stream.read_buf(&mut buf).await {
+                if n_recv == 0 {
+                    break;
+                }
+                if reply_ping {
+                    stream.write_all(&ping_buf).await.unwrap();
+                }
+            }
+        });
+
+        let mut options = ConnectOptions::new();
+        options
+            .set_connect_type(ConnectType::Mqtt(MqttConnect { address }))
+            .set_ping_timeout(Duration::from_millis(500));
+        options
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let options = ping_server(true).await;
+        let mut client = Client::new(options);
+        client.connect().await.unwrap();
+        client.ping().await.unwrap();
+        client.ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ping_timeout() {
+        let options = ping_server(false).await;
+        let mut client = Client::new(options);
+        client.connect().await.unwrap();
+        // Unsolicited ping response does not resolve ping request.
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TimeoutError));
+    }
+}
@@ -5,24 +5,27 @@
 #![allow(clippy::unused_async)]
 
 use codec::v3::{
-    ConnectAckPacket, ConnectPacket, ConnectReturnCode, DisconnectPacket, PingRequestPacket,
-    PublishAckPacket, PublishPacket, SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket,
-    UnsubscribePacket,
+    ConnectAckPacket, ConnectReturnCode, DisconnectPacket, PingRequestPacket, PublishAckPacket,
+    PublishCompletePacket, PublishPacket, PublishReceivedPacket, PublishReleasePacket,
+    SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket, UnsubscribePacket,
 };
 use codec::{
-    ByteArray, DecodePacket, EncodePacket, FixedHeader, Packet, PacketId, PacketType, QoS,
+    AnyPacket, EncodePacket, Packet, PacketDecoder, PacketId, ProtocolLevel, QoS, V3Packet,
 };
-use std::collections::HashMap;
-use tokio::time::interval;
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::time::{interval, sleep_until, timeout, Instant};
 
 use crate::connect_options::ConnectOptions;
 use crate::error::{Error, ErrorKind};
+use crate::publish::MessageCallback;
+use crate::reconnect::{is_retryable_return_code, reconnect_timer, Backoff};
 use crate::stream::Stream;
-use crate::ClientStatus;
+use crate::{ClientStatus, PublishMessage, SubscribeAck};
 
 pub struct ClientInnerV3 {
     connect_options: ConnectOptions,
     stream: Stream,
+    decoder: PacketDecoder,
     status: ClientStatus,
     topics: HashMap<String, PacketId>,
     packet_id: PacketId,
@@ -30,6 +33,21 @@ pub struct ClientInnerV3 {
     unsubscribing_packets: HashMap<PacketId, UnsubscribePacket>,
     publishing_qos1_packets: HashMap<PacketId, PublishPacket>,
     publishing_qos2_packets: HashMap<PacketId, PublishPacket>,
+    backoff: Backoff,
+    reconnect_at: Option<Instant>,
+    /// Number of ping requests sent in current connection.
+    pings_sent: u64,
+    /// Number of ping responses received in current connection.
+    pings_received: u64,
+    /// Subscribe ack of each subscribe packet, taken by `subscribe()`.
+    subscribe_acks: HashMap<PacketId, SubscribeAck>,
+    /// Packet ids of `QoS` 2 messages received but not released by server yet.
+    receiving_qos2_packets: HashSet<PacketId>,
+    /// Messages from server not taken by `next_message()` yet.
+    messages: VecDeque<PublishMessage>,
+    on_message_cb: Option<MessageCallback>,
+    /// Time of last packet sent to server, used to send keep alive pings.
+    last_sent: Instant,
 }
 
 impl Drop for ClientInnerV3 {
@@ -46,6 +64,7 @@ impl ClientInnerV3 {
         Self {
             connect_options,
             stream: Stream::None,
+            decoder: PacketDecoder::new(),
             status: ClientStatus::Disconnected,
             topics: HashMap::new(),
             packet_id: PacketId::new(1),
@@ -53,6 +72,15 @@ impl ClientInnerV3 {
             unsubscribing_packets: HashMap::new(),
             publishing_qos1_packets: HashMap::new(),
             publishing_qos2_packets: HashMap::new(),
+            backoff: Backoff::new(),
+            reconnect_at: None,
+            pings_sent: 0,
+            pings_received: 0,
+            subscribe_acks: HashMap::new(),
+            receiving_qos2_packets: HashSet::new(),
+            messages: VecDeque::new(),
+            on_message_cb: None,
+            last_sent: Instant::now(),
         }
     }
 
@@ -66,27 +94,32 @@ impl ClientInnerV3 {
         &self.connect_options
     }
 
+    pub fn set_message_callback(&mut self, callback: Option<MessageCallback>) {
+        self.on_message_cb = callback;
+    }
+
     pub async fn run_loop(&mut self) -> ! {
         log::info!("client.start()");
 
-        let mut buf: Vec<u8> = Vec::with_capacity(1024);
         log::info!("reader loop");
         // FIXME(Shaohua): Fix panic when keep_alive is 0
         let mut timer = interval(*self.connect_options.keep_alive());
 
         loop {
             tokio::select! {
-                Ok(n_recv) = self.stream.read_buf(&mut buf) => {
+                Ok(n_recv) = self.stream.read_buf(self.decoder.buffer_mut()), if self.reconnect_at.is_none() => {
                     if n_recv > 0 {
-                        if let Err(err) = self.handle_session_packet(&buf).await {
+                        if let Err(err) = self.handle_buffered_packets().await {
                             log::error!("err: {:?}", err);
                         }
-                        buf.clear();
                     }
                 }
+                () = reconnect_timer(self.reconnect_at) => {
+                    self.reconnect().await;
+                }
                 _ = timer.tick() => {
                     log::info!("tick()");
-                    if let Err(err) = self.ping().await {
+                    if let Err(err) = self.send_ping().await {
                         log::error!("Ping failed: {:?}", err);
                     }
                 },
@@ -94,18 +127,61 @@ impl ClientInnerV3 {
         }
     }
 
-    async fn handle_session_packet(&mut self, buf: &[u8]) -> Result<(), Error> {
-        let mut ba = ByteArray::new(buf);
-        let fixed_header = FixedHeader::decode(&mut ba)?;
-        match fixed_header.packet_type() {
-            PacketType::ConnectAck => self.connect_ack(buf).await,
-            PacketType::Publish { .. } => self.on_message(buf).await,
-            PacketType::PublishAck => self.publish_ack(buf),
-            PacketType::SubscribeAck => self.subscribe_ack(buf),
-            PacketType::UnsubscribeAck => self.unsubscribe_ack(buf),
-            PacketType::PingResponse => self.on_ping_resp().await,
-            t => {
-                log::info!("Unhandled msg: {:?}", t);
+    /// Handle all complete packets in read buffer.
+    async fn handle_buffered_packets(&mut self) -> Result<(), Error> {
+        while let Some(packet) = self.decoder.next_packet(ProtocolLevel::V4)? {
+            let AnyPacket::V3(packet) = packet else {
+                unreachable!("V3 packets are decoded with protocol level V4")
+            };
+            self.handle_session_packet(packet).await?;
+        }
+        Ok(())
+    }
+
+    /// Read and handle packets from server until `done` returns true.
+    async fn wait_until<F>(&mut self, done: F) -> Result<(), Error>
+    where
+        F: Fn(&Self) -> bool,
+    {
+        loop {
+            self.handle_buffered_packets().await?;
+            if done(self) {
+                return Ok(());
+            }
+            if self.stream.read_buf(self.decoder.buffer_mut()).await? == 0 {
+                return Err(Error::new(
+                    ErrorKind::SocketError,
+                    "Connection closed by server",
+                ));
+            }
+        }
+    }
+
+    async fn handle_session_packet(&mut self, packet: V3Packet) -> Result<(), Error> {
+        match packet {
+            V3Packet::ConnectAck(packet) => self.connect_ack(packet).await,
+            V3Packet::Publish(packet) => self.on_message(packet).await,
+            V3Packet::PublishAck(packet) => {
+                self.publish_ack(packet);
+                Ok(())
+            }
+            V3Packet::PublishReceived(packet) => self.publish_received(packet).await,
+            V3Packet::PublishRelease(packet) => self.publish_release(packet).await,
+            V3Packet::PublishComplete(packet) => {
+                self.publish_complete(packet);
+                Ok(())
+            }
+            V3Packet::SubscribeAck(packet) => {
+                self.subscribe_ack(&packet);
+                Ok(())
+            }
+            V3Packet::UnsubscribeAck(packet) => {
+                self.unsubscribe_ack(packet);
+                Ok(())
+            }
+            V3Packet::PingResponse(_packet) => self.on_ping_resp().await,
+            packet => {
+                log::info!("Unhandled msg: {:?}", packet);
                 Ok(())
             }
         }
@@ -114,13 +190,15 @@ impl ClientInnerV3 {
     async fn send<P: EncodePacket + Packet>(&mut self, packet: P) -> Result<(), Error> {
         let mut buf = Vec::new();
         packet.encode(&mut buf)?;
-        match &mut self.stream {
-            Stream::None => Err(Error::new(
+        if matches!(self.stream, Stream::None) {
+            return Err(Error::new(
                 ErrorKind::SocketError,
                 "Socket is uninitialized",
-            )),
-            _ => self.stream.write(&buf).await.map(drop),
+            ));
         }
+        self.stream.write(&buf).await?;
+        self.last_sent = Instant::now();
+        Ok(())
     }
 
     /// Connect to server.
@@ -142,9 +220,21 @@ impl ClientInnerV3 {
         }
 
         self.stream = Stream::connect(self.connect_options.connect_type()).await?;
-        let conn_packet = ConnectPacket::new(self.connect_options.client_id())?;
+        self.decoder.clear();
+        self.pings_sent = 0;
+        self.pings_received = 0;
+        let conn_packet = self.connect_options.connect_packet_v3()?;
         log::info!("send conn packet");
-        self.send(conn_packet).await
+        self.send(conn_packet).await?;
+        self.status = ClientStatus::Connecting;
+
+        let connect_timeout = *self.connect_options.connect_timeout();
+        timeout(
+            connect_timeout,
+            self.wait_until(|client| client.status != ClientStatus::Connecting),
+        )
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::TimeoutError, "No connect ack from server"))?
     }
 
     /// Send a message to server.
@@ -155,25 +245,37 @@ impl ClientInnerV3 {
     /// - `topic` is invalid
     /// - `data` is too large
     /// - Socket stream error
-    pub async fn publish(&mut self, topic: &str, qos: QoS, data: &[u8]) -> Result<(), Error> {
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        data: &[u8],
+    ) -> Result<(), Error> {
         let mut packet = PublishPacket::new(topic, qos, data)?;
-        match qos {
-            QoS::AtLeastOnce => {
-                let packet_id = self.next_packet_id();
-                packet.set_packet_id(packet_id);
-                // TODO(Shaohua): Tuning memory usage.
-                self.publishing_qos1_packets
-                    .insert(packet_id, packet.clone());
-            }
-            QoS::ExactOnce => {
-                let packet_id = self.next_packet_id();
-                packet.set_packet_id(packet_id);
-                self.publishing_qos2_packets
-                    .insert(packet_id, packet.clone());
-            }
-            QoS::AtMostOnce => (),
+        packet.set_retain(retain);
+        if qos == QoS::AtMostOnce {
+            return self.send(packet).await;
         }
-        self.send(packet).await
+
+        let packet_id = self.next_packet_id();
+        packet.set_packet_id(packet_id);
+        // TODO(Shaohua): Tuning memory usage.
+        if qos == QoS::AtLeastOnce {
+            self.publishing_qos1_packets
+                .insert(packet_id, packet.clone());
+        } else {
+            self.publishing_qos2_packets
+                .insert(packet_id, packet.clone());
+        }
+        self.send(packet).await?;
+
+        // Wait for publish ack of QoS 1 message, or publish complete of QoS 2 message.
+        self.wait_until(|client| {
+            !client.publishing_qos1_packets.contains_key(&packet_id)
+                && !client.publishing_qos2_packets.contains_key(&packet_id)
+        })
+        .await
     }
 
     /// Subscribe to a specific `topic`.
@@ -183,13 +285,22 @@ impl ClientInnerV3 {
     /// Returns error if:
     /// - `topic` pattern is invalid
     /// - Socket stream returns error
-    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), Error> {
+    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<SubscribeAck, Error> {
         log::info!("subscribe to: {}", topic);
         let packet_id = self.next_packet_id();
         self.topics.insert(topic.to_string(), packet_id);
         let packet = SubscribePacket::new(topic, qos, packet_id)?;
         self.subscribing_packets.insert(packet_id, packet.clone());
-        self.send(packet).await
+        self.send(packet).await?;
+
+        self.wait_until(|client| !client.subscribing_packets.contains_key(&packet_id))
+            .await?;
+        self.subscribe_acks.remove(&packet_id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::PacketError,
+                "No acknowledgement in subscribe ack packet",
+            )
+        })
     }
 
     /// Unsubscribe specific `topic` pattern.
@@ -204,7 +315,10 @@ impl ClientInnerV3 {
         let packet_id = self.next_packet_id();
         let packet = UnsubscribePacket::new(topic, packet_id)?;
         self.unsubscribing_packets.insert(packet_id, packet.clone());
-        self.send(packet).await
+        self.send(packet).await?;
+
+        self.wait_until(|client| !client.unsubscribing_packets.contains_key(&packet_id))
+            .await
     }
 
     pub async fn disconnect(&mut self) -> Result<(), Error> {
@@ -217,19 +331,74 @@ impl ClientInnerV3 {
         self.on_disconnect()
     }
 
-    /// Send ping packet to server explicitly.
+    /// Send ping packet to server and wait for its response.
+    ///
+    /// If connect ack packet is not received yet, wait for it first.
     ///
     /// # Errors
     ///
     /// Returns error if:
     /// - Client status is invalid
     /// - Socket stream returns error
+    /// - No response in [`ConnectOptions::ping_timeout()`]
     pub async fn ping(&mut self) -> Result<(), Error> {
         log::info!("ping()");
+        let ping_timeout = *self.connect_options.ping_timeout();
+        timeout(ping_timeout, async {
+            if self.status == ClientStatus::Connecting {
+                self.wait_until(|client| client.status != ClientStatus::Connecting)
+                    .await?;
+            }
+            self.send_ping().await?;
+            // Responses of previous ping requests, sent by keep alive timer, may arrive first.
+            let pings_sent = self.pings_sent;
+            self.wait_until(|client| client.pings_received >= pings_sent)
+                .await
+        })
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::TimeoutError, "No ping response from server"))?
+    }
+
+    /// Wait for next message from server.
+    ///
+    /// Ping packets are sent if no packet is sent to server in keep alive interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if socket stream returns error or is closed by server.
+    pub async fn next_message(&mut self) -> Result<PublishMessage, Error> {
+        loop {
+            if let Some(message) = self.messages.pop_front() {
+                return Ok(message);
+            }
+
+            let keep_alive = *self.connect_options.keep_alive();
+            let ping_at = self.last_sent + keep_alive;
+            tokio::select! {
+                n_recv = self.stream.read_buf(self.decoder.buffer_mut()) => {
+                    if n_recv? == 0 {
+                        return Err(Error::new(
+                            ErrorKind::SocketError,
+                            "Connection closed by server",
+                        ));
+                    }
+                    self.handle_buffered_packets().await?;
+                }
+                () = sleep_until(ping_at), if !keep_alive.is_zero() => {
+                    self.send_ping().await?;
+                }
+            }
+        }
+    }
+
+    /// Send ping packet without waiting for response.
+    async fn send_ping(&mut self) -> Result<(), Error> {
         if self.status == ClientStatus::Connected {
             log::info!("Send ping packet");
             let packet = PingRequestPacket::new();
-            self.send(packet).await
+            self.send(packet).await?;
+            self.pings_sent += 1;
+            Ok(())
         } else {
             Err(Error::new(
                 ErrorKind::InvalidClientStatus,
@@ -238,51 +407,96 @@ impl ClientInnerV3 {
         }
     }
 
+    // `&self` would require client to be `Sync` for the future to be `Send`.
+    #[allow(clippy::needless_pass_by_ref_mut)]
     async fn on_connect(&mut self) -> Result<(), Error> {
         log::info!("on_connect()");
-        todo!()
+        Ok(())
     }
 
-    fn on_disconnect(&mut self) -> Result<(), Error> {
+    fn on_disconnect(&self) -> Result<(), Error> {
         log::info!("on_disconnect()");
         todo!()
     }
 
-    async fn on_message(&self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_message(&mut self, packet: PublishPacket) -> Result<(), Error> {
         log::info!("on_message()");
-        let mut ba = ByteArray::new(buf);
-        let packet = PublishPacket::decode(&mut ba)?;
         log::info!("packet: {:?}", packet);
-        //if let Some(cb) = &self.on_message_cb {
-        //    cb(self, &packet);
-        //}
-        todo!()
+        match packet.qos() {
+            QoS::AtMostOnce => (),
+            QoS::AtLeastOnce => {
+                self.send(PublishAckPacket::new(packet.packet_id())).await?;
+            }
+            QoS::ExactOnce => {
+                let is_new = self.receiving_qos2_packets.insert(packet.packet_id());
+                self.send(PublishReceivedPacket::new(packet.packet_id()))
+                    .await?;
+                if !is_new {
+                    // Resent by server before release, message is already delivered.
+                    return Ok(());
+                }
+            }
+        }
+
+        let message = PublishMessage::from(&packet);
+        if let Some(cb) = &mut self.on_message_cb {
+            cb(&message);
+        } else {
+            self.messages.push_back(message);
+        }
+        Ok(())
     }
 
-    async fn on_ping_resp(&self) -> Result<(), Error> {
+    async fn on_ping_resp(&mut self) -> Result<(), Error> {
         log::info!("on ping resp");
         // TODO(Shaohua): Reset reconnect timer.
+        if self.pings_received < self.pings_sent {
+            self.pings_received += 1;
+        } else {
+            log::warn!("Got ping response without request");
+        }
         Ok(())
     }
 
-    async fn connect_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn connect_ack(&mut self, packet: ConnectAckPacket) -> Result<(), Error> {
         log::info!("connect_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = ConnectAckPacket::decode(&mut ba)?;
-        if packet.return_code() == ConnectReturnCode::Accepted {
+        let return_code = packet.return_code();
+        if return_code == ConnectReturnCode::Accepted {
             self.status = ClientStatus::Connected;
+            self.backoff.reset();
             self.on_connect().await?;
+            return Ok(());
+        }
+
+        log::warn!("Failed to connect to server, {:?}", return_code);
+        self.status = ClientStatus::Disconnected;
+        let reconnect = self.connect_options.reconnect();
+        if reconnect.enabled() && is_retryable_return_code(return_code) {
+            self.reconnect_at = Some(self.backoff.next_reconnect(reconnect));
+            Ok(())
         } else {
-            log::warn!("Failed to connect to server, {:?}", packet.return_code());
-            self.status = ClientStatus::Disconnected;
+            Err(Error::from_string(
+                ErrorKind::ConnectionRefused,
+                format!("Connection refused by server, {return_code:?}"),
+            ))
+        }
+    }
+
+    /// Reconnect to server after connection is rejected with a retryable reason.
+    async fn reconnect(&mut self) {
+        self.reconnect_at = None;
+        log::info!("Reconnect to server");
+        if let Err(err) = self.connect().await {
+            log::warn!("Failed to reconnect to server, err: {:?}", err);
+            self.reconnect_at = Some(
+                self.backoff
+                    .next_reconnect(self.connect_options.reconnect()),
+            );
         }
-        Ok(())
     }
 
-    fn publish_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn publish_ack(&mut self, packet: PublishAckPacket) {
         log::info!("publish_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = PublishAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
         if let Some(p) = self.publishing_qos1_packets.get(&packet_id) {
             log::info!("Topic `{}` publish confirmed!", p.topic());
@@ -290,28 +504,55 @@ impl ClientInnerV3 {
         } else {
             log::warn!("Failed to find PublishAckPacket: {}", packet_id);
         }
-        Ok(())
+    }
+
+    async fn publish_received(&mut self, packet: PublishReceivedPacket) -> Result<(), Error> {
+        log::info!("publish_received()");
+        let packet_id = packet.packet_id();
+        if self.publishing_qos2_packets.contains_key(&packet_id) {
+            self.send(PublishReleasePacket::new(packet_id)).await
+        } else {
+            log::warn!("Failed to find PublishReceivedPacket: {}", packet_id);
+            Ok(())
+        }
+    }
+
+    async fn publish_release(&mut self, packet: PublishReleasePacket) -> Result<(), Error> {
+        log::info!("publish_release()");
+        let packet_id = packet.packet_id();
+        if !self.receiving_qos2_packets.remove(&packet_id) {
+            log::warn!("Failed to find PublishReleasePacket: {}", packet_id);
+        }
+        self.send(PublishCompletePacket::new(packet_id)).await
+    }
+
+    fn publish_complete(&mut self, packet: PublishCompletePacket) {
+        log::info!("publish_complete()");
+        let packet_id = packet.packet_id();
+        if let Some(p) = self.publishing_qos2_packets.remove(&packet_id) {
+            log::info!("Topic `{}` publish completed!", p.topic());
+        } else {
+            log::warn!("Failed to find PublishCompletePacket: {}", packet_id);
+        }
     }
 
     /// Parse `packet_id` and remove from vector.
-    fn subscribe_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn subscribe_ack(&mut self, packet: &SubscribeAckPacket) {
         log::info!("subscribe_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = SubscribeAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
         if let Some(p) = self.subscribing_packets.get(&packet_id) {
             log::info!("Subscription {:?} confirmed!", p.topics());
             self.subscribing_packets.remove(&packet.packet_id());
+            if let Some(ack) = packet.acknowledgements().first() {
+                self.subscribe_acks.insert(packet_id, (*ack).into());
+            }
         } else {
             log::warn!("Failed to find SubscribeAckPacket: {}", packet_id);
         }
-        Ok(())
     }
 
-    fn unsubscribe_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn unsubscribe_ack(&mut self, packet: UnsubscribeAckPacket) {
         log::info!("unsubscribe_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = UnsubscribeAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
         if let Some(p) = self.unsubscribing_packets.get(&packet_id) {
             log::info!("Topics {:?} unsubscribe confirmed!", p);
@@ -319,7 +560,6 @@ impl ClientInnerV3 {
         } else {
             log::warn!("Failed to find UnsubscribeAckPacket: {}", packet_id);
         }
-        Ok(())
     }
 
     fn next_packet_id(&mut self) -> PacketId {
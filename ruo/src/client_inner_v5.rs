@@ -4,25 +4,31 @@
 
 #![allow(clippy::unused_async)]
 
+use codec::utils::random_string;
 use codec::v5::{
-    ConnectAckPacket, ConnectPacket, DisconnectPacket, PingRequestPacket, PublishAckPacket,
-    PublishPacket, ReasonCode, SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket,
-    UnsubscribePacket,
+    ConnectAckPacket, DisconnectPacket, PingRequestPacket, Property, PublishAckPacket,
+    PublishCompletePacket, PublishPacket, PublishReceivedPacket, PublishReleasePacket, ReasonCode,
+    SubscribeAckPacket, SubscribePacket, UnsubscribeAckPacket, UnsubscribePacket,
 };
 use codec::{
-    ByteArray, DecodePacket, EncodePacket, FixedHeader, Packet, PacketId, PacketType, QoS,
+    AnyPacket, BinaryData, EncodeError, EncodePacket, Packet, PacketDecoder, PacketId,
+    ProtocolLevel, PubTopic, QoS, V5Packet,
 };
-use std::collections::HashMap;
-use tokio::time::interval;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use tokio::time::{interval, sleep_until, timeout, Instant};
 
 use crate::connect_options::ConnectOptions;
 use crate::error::{Error, ErrorKind};
+use crate::publish::MessageCallback;
+use crate::reconnect::{is_retryable_reason, reconnect_timer, Backoff};
 use crate::stream::Stream;
-use crate::ClientStatus;
+use crate::{ClientStatus, PublishMessage, SubscribeAck};
 
 pub struct ClientInnerV5 {
     connect_options: ConnectOptions,
     stream: Stream,
+    decoder: PacketDecoder,
     status: ClientStatus,
     topics: HashMap<String, PacketId>,
     packet_id: PacketId,
@@ -30,6 +36,27 @@ pub struct ClientInnerV5 {
     unsubscribing_packets: HashMap<PacketId, UnsubscribePacket>,
     publishing_qos1_packets: HashMap<PacketId, PublishPacket>,
     publishing_qos2_packets: HashMap<PacketId, PublishPacket>,
+    backoff: Backoff,
+    reconnect_at: Option<Instant>,
+    /// Number of ping requests sent in current connection.
+    pings_sent: u64,
+    /// Number of ping responses received in current connection.
+    pings_received: u64,
+    /// Subscribe ack of each subscribe packet, taken by `subscribe()`.
+    subscribe_acks: HashMap<PacketId, SubscribeAck>,
+    /// Reason codes of rejected publish packets, taken by `publish()`.
+    failed_publishes: HashMap<PacketId, ReasonCode>,
+    /// Packet ids of `QoS` 2 messages received but not released by server yet.
+    receiving_qos2_packets: HashSet<PacketId>,
+    /// Messages from server not taken by `next_message()` yet.
+    messages: VecDeque<PublishMessage>,
+    on_message_cb: Option<MessageCallback>,
+    /// Time of last packet sent to server, used to send keep alive pings.
+    last_sent: Instant,
+    /// `(response_topic, correlation_data)` of request waiting for response.
+    pending_request: Option<(String, Vec<u8>)>,
+    /// Response of pending request, taken by `request()`.
+    response: Option<PublishMessage>,
 }
 
 impl Drop for ClientInnerV5 {
@@ -46,6 +73,7 @@ impl ClientInnerV5 {
         Self {
             connect_options,
             stream: Stream::None,
+            decoder: PacketDecoder::new(),
             status: ClientStatus::Disconnected,
             topics: HashMap::new(),
             packet_id: PacketId::new(1),
@@ -53,6 +81,18 @@ impl ClientInnerV5 {
             unsubscribing_packets: HashMap::new(),
             publishing_qos1_packets: HashMap::new(),
             publishing_qos2_packets: HashMap::new(),
+            backoff: Backoff::new(),
+            reconnect_at: None,
+            pings_sent: 0,
+            pings_received: 0,
+            subscribe_acks: HashMap::new(),
+            failed_publishes: HashMap::new(),
+            receiving_qos2_packets: HashSet::new(),
+            messages: VecDeque::new(),
+            on_message_cb: None,
+            last_sent: Instant::now(),
+            pending_request: None,
+            response: None,
         }
     }
 
@@ -66,27 +106,32 @@ impl ClientInnerV5 {
         &self.connect_options
     }
 
+    pub fn set_message_callback(&mut self, callback: Option<MessageCallback>) {
+        self.on_message_cb = callback;
+    }
+
     pub async fn run_loop(&mut self) -> ! {
         log::info!("client.start()");
 
-        let mut buf: Vec<u8> = Vec::with_capacity(1024);
         log::info!("reader loop");
         // FIXME(Shaohua): Fix panic when keep_alive is 0
         let mut timer = interval(*self.connect_options.keep_alive());
 
         loop {
             tokio::select! {
-                Ok(n_recv) = self.stream.read_buf(&mut buf) => {
+                Ok(n_recv) = self.stream.read_buf(self.decoder.buffer_mut()), if self.reconnect_at.is_none() => {
                     if n_recv > 0 {
-                        if let Err(err) = self.handle_session_packet(&buf).await {
+                        if let Err(err) = self.handle_buffered_packets().await {
                             log::error!("err: {:?}", err);
                         }
-                        buf.clear();
                     }
                 }
+                () = reconnect_timer(self.reconnect_at) => {
+                    self.reconnect().await;
+                }
                 _ = timer.tick() => {
                     log::info!("tick()");
-                    if let Err(err) = self.ping().await {
+                    if let Err(err) = self.send_ping().await {
                         log::error!("Ping failed: {:?}", err);
                     }
                 },
@@ -94,18 +139,61 @@ impl ClientInnerV5 {
         }
     }
 
-    async fn handle_session_packet(&mut self, buf: &[u8]) -> Result<(), Error> {
-        let mut ba = ByteArray::new(buf);
-        let fixed_header = FixedHeader::decode(&mut ba)?;
-        match fixed_header.packet_type() {
-            PacketType::ConnectAck => self.connect_ack(buf).await,
-            PacketType::Publish { .. } => self.on_message(buf).await,
-            PacketType::PublishAck => self.publish_ack(buf),
-            PacketType::SubscribeAck => self.subscribe_ack(buf),
-            PacketType::UnsubscribeAck => self.unsubscribe_ack(buf),
-            PacketType::PingResponse => self.on_ping_resp().await,
-            t => {
-                log::info!("Unhandled msg: {:?}", t);
+    /// Handle all complete packets in read buffer.
+    async fn handle_buffered_packets(&mut self) -> Result<(), Error> {
+        while let Some(packet) = self.decoder.next_packet(ProtocolLevel::V5)? {
+            let AnyPacket::V5(packet) = packet else {
+                unreachable!("V5 packets are decoded with protocol level V5")
+            };
+            self.handle_session_packet(packet).await?;
+        }
+        Ok(())
+    }
+
+    /// Read and handle packets from server until `done` returns true.
+    async fn wait_until<F>(&mut self, done: F) -> Result<(), Error>
+    where
+        F: Fn(&Self) -> bool,
+    {
+        loop {
+            self.handle_buffered_packets().await?;
+            if done(self) {
+                return Ok(());
+            }
+            if self.stream.read_buf(self.decoder.buffer_mut()).await? == 0 {
+                return Err(Error::new(
+                    ErrorKind::SocketError,
+                    "Connection closed by server",
+                ));
+            }
+        }
+    }
+
+    async fn handle_session_packet(&mut self, packet: V5Packet) -> Result<(), Error> {
+        match packet {
+            V5Packet::ConnectAck(packet) => self.connect_ack(packet).await,
+            V5Packet::Publish(packet) => self.on_message(packet).await,
+            V5Packet::PublishAck(packet) => {
+                self.publish_ack(&packet);
+                Ok(())
+            }
+            V5Packet::PublishReceived(packet) => self.publish_received(packet).await,
+            V5Packet::PublishRelease(packet) => self.publish_release(packet).await,
+            V5Packet::PublishComplete(packet) => {
+                self.publish_complete(&packet);
+                Ok(())
+            }
+            V5Packet::SubscribeAck(packet) => {
+                self.subscribe_ack(&packet);
+                Ok(())
+            }
+            V5Packet::UnsubscribeAck(packet) => {
+                self.unsubscribe_ack(&packet);
+                Ok(())
+            }
+            V5Packet::PingResponse(_packet) => self.on_ping_resp().await,
+            packet => {
+                log::info!("Unhandled msg: {:?}", packet);
                 Ok(())
             }
         }
@@ -114,13 +202,15 @@ impl ClientInnerV5 {
     async fn send<P: EncodePacket + Packet>(&mut self, packet: P) -> Result<(), Error> {
         let mut buf = Vec::new();
         packet.encode(&mut buf)?;
-        match &mut self.stream {
-            Stream::None => Err(Error::new(
+        if matches!(self.stream, Stream::None) {
+            return Err(Error::new(
                 ErrorKind::SocketError,
                 "Socket is uninitialized",
-            )),
-            _ => self.stream.write(&buf).await.map(drop),
+            ));
         }
+        self.stream.write(&buf).await?;
+        self.last_sent = Instant::now();
+        Ok(())
     }
 
     pub async fn connect(&mut self) -> Result<(), Error> {
@@ -138,39 +228,162 @@ impl ClientInnerV5 {
         }
 
         self.stream = Stream::connect(self.connect_options.connect_type()).await?;
-        let conn_packet = ConnectPacket::new(self.connect_options.client_id())?;
+        self.decoder.clear();
+        self.pings_sent = 0;
+        self.pings_received = 0;
+        let conn_packet = self.connect_options.connect_packet_v5()?;
         log::info!("send conn packet");
-        self.send(conn_packet).await
+        self.send(conn_packet).await?;
+        self.status = ClientStatus::Connecting;
+
+        let connect_timeout = *self.connect_options.connect_timeout();
+        timeout(
+            connect_timeout,
+            self.wait_until(|client| client.status != ClientStatus::Connecting),
+        )
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::TimeoutError, "No connect ack from server"))?
     }
 
-    pub async fn publish(&mut self, topic: &str, qos: QoS, data: &[u8]) -> Result<(), Error> {
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        data: &[u8],
+    ) -> Result<(), Error> {
         let mut packet = PublishPacket::new(topic, qos, data)?;
-        match qos {
-            QoS::AtLeastOnce => {
-                let packet_id = self.next_packet_id();
-                packet.set_packet_id(packet_id);
-                // TODO(Shaohua): Tuning memory usage.
-                self.publishing_qos1_packets
-                    .insert(packet_id, packet.clone());
-            }
-            QoS::ExactOnce => {
-                let packet_id = self.next_packet_id();
-                packet.set_packet_id(packet_id);
-                self.publishing_qos2_packets
-                    .insert(packet_id, packet.clone());
-            }
-            QoS::AtMostOnce => (),
+        packet.set_retain(retain);
+        self.publish_packet(packet).await
+    }
+
+    /// Send publish packet and wait for its acknowledgement.
+    async fn publish_packet(&mut self, mut packet: PublishPacket) -> Result<(), Error> {
+        let qos = packet.qos();
+        if qos == QoS::AtMostOnce {
+            return self.send(packet).await;
+        }
+
+        let packet_id = self.next_packet_id();
+        packet.set_packet_id(packet_id);
+        // TODO(Shaohua): Tuning memory usage.
+        if qos == QoS::AtLeastOnce {
+            self.publishing_qos1_packets
+                .insert(packet_id, packet.clone());
+        } else {
+            self.publishing_qos2_packets
+                .insert(packet_id, packet.clone());
         }
-        self.send(packet).await
+        self.send(packet).await?;
+
+        // Wait for publish ack of QoS 1 message, or publish complete of QoS 2 message.
+        self.wait_until(|client| {
+            !client.publishing_qos1_packets.contains_key(&packet_id)
+                && !client.publishing_qos2_packets.contains_key(&packet_id)
+        })
+        .await?;
+        self.failed_publishes
+            .remove(&packet_id)
+            .map_or(Ok(()), |reason_code| {
+                Err(Error::from_string(
+                    ErrorKind::PublishRejected,
+                    format!("Publish rejected by server, {}", reason_code.description()),
+                ))
+            })
     }
 
-    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), Error> {
+    /// Publish a request to `topic` and wait for its response.
+    ///
+    /// A temporary response topic is subscribed until response is received or
+    /// `timeout_duration` elapsed. Both request and response use `QoS` 1.
+    pub async fn request(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        timeout_duration: Duration,
+    ) -> Result<PublishMessage, Error> {
+        let correlation_id = random_string(16);
+        let response_topic = format!(
+            "ruo/response/{}/{correlation_id}",
+            self.connect_options.client_id()
+        );
+        if let SubscribeAck::Rejected(reason_code) =
+            self.subscribe(&response_topic, QoS::AtLeastOnce).await?
+        {
+            return Err(Error::from_string(
+                ErrorKind::SubscribeRejected,
+                format!(
+                    "Response topic rejected by server, {}",
+                    reason_code.description()
+                ),
+            ));
+        }
+
+        let correlation_data = correlation_id.into_bytes();
+        self.response = None;
+        self.pending_request = Some((response_topic.clone(), correlation_data.clone()));
+        let mut packet = PublishPacket::new(topic, QoS::AtLeastOnce, payload)?;
+        let properties = packet.properties_mut();
+        let response_topic_property = PubTopic::new(&response_topic).map_err(EncodeError::from)?;
+        properties.push(Property::ResponseTopic(response_topic_property))?;
+        properties.push(Property::CorrelationData(BinaryData::from_slice(
+            &correlation_data,
+        )?))?;
+        let ret = timeout(timeout_duration, async {
+            self.publish_packet(packet).await?;
+            self.wait_until(|client| client.response.is_some()).await
+        })
+        .await;
+
+        // Responses arriving after this are dropped.
+        let unsubscribed = self.unsubscribe(&response_topic).await;
+        self.pending_request = None;
+        let response = self.response.take();
+        ret.map_err(|_elapsed| {
+            Error::from_string(
+                ErrorKind::TimeoutError,
+                format!("No response to request on {topic}"),
+            )
+        })??;
+        unsubscribed?;
+        response.ok_or_else(|| Error::new(ErrorKind::PacketError, "No response to request"))
+    }
+
+    /// Publish `payload` as response to `request` message.
+    pub async fn respond(&mut self, request: &PublishMessage, payload: &[u8]) -> Result<(), Error> {
+        let Some(response_topic) = &request.response_topic else {
+            return Err(Error::new(
+                ErrorKind::PacketError,
+                "No response topic in request message",
+            ));
+        };
+        let mut packet = PublishPacket::new(response_topic, request.qos, payload)?;
+        if let Some(correlation_data) = &request.correlation_data {
+            packet
+                .properties_mut()
+                .push(Property::CorrelationData(BinaryData::from_slice(
+                    correlation_data,
+                )?))?;
+        }
+        self.publish_packet(packet).await
+    }
+
+    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<SubscribeAck, Error> {
         log::info!("subscribe to: {}", topic);
         let packet_id = self.next_packet_id();
         self.topics.insert(topic.to_string(), packet_id);
         let packet = SubscribePacket::new(topic, qos, packet_id)?;
         self.subscribing_packets.insert(packet_id, packet.clone());
-        self.send(packet).await
+        self.send(packet).await?;
+
+        self.wait_until(|client| !client.subscribing_packets.contains_key(&packet_id))
+            .await?;
+        self.subscribe_acks.remove(&packet_id).ok_or_else(|| {
+            Error::new(
+                ErrorKind::PacketError,
+                "No acknowledgement in subscribe ack packet",
+            )
+        })
     }
 
     pub async fn unsubscribe(&mut self, topic: &str) -> Result<(), Error> {
@@ -178,7 +391,10 @@ impl ClientInnerV5 {
         let packet_id = self.next_packet_id();
         let packet = UnsubscribePacket::new(topic, packet_id)?;
         self.unsubscribing_packets.insert(packet_id, packet.clone());
-        self.send(packet).await
+        self.send(packet).await?;
+
+        self.wait_until(|client| !client.unsubscribing_packets.contains_key(&packet_id))
+            .await
     }
 
     pub async fn disconnect(&mut self) -> Result<(), Error> {
@@ -191,12 +407,74 @@ impl ClientInnerV5 {
         self.on_disconnect()
     }
 
+    /// Send ping packet to server and wait for its response.
+    ///
+    /// If connect ack packet is not received yet, wait for it first.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - Client status is invalid
+    /// - Socket stream returns error
+    /// - No response in [`ConnectOptions::ping_timeout()`]
     pub async fn ping(&mut self) -> Result<(), Error> {
         log::info!("ping()");
+        let ping_timeout = *self.connect_options.ping_timeout();
+        timeout(ping_timeout, async {
+            if self.status == ClientStatus::Connecting {
+                self.wait_until(|client| client.status != ClientStatus::Connecting)
+                    .await?;
+            }
+            self.send_ping().await?;
+            // Responses of previous ping requests, sent by keep alive timer, may arrive first.
+            let pings_sent = self.pings_sent;
+            self.wait_until(|client| client.pings_received >= pings_sent)
+                .await
+        })
+        .await
+        .map_err(|_elapsed| Error::new(ErrorKind::TimeoutError, "No ping response from server"))?
+    }
+
+    /// Wait for next message from server.
+    ///
+    /// Ping packets are sent if no packet is sent to server in keep alive interval.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if socket stream returns error or is closed by server.
+    pub async fn next_message(&mut self) -> Result<PublishMessage, Error> {
+        loop {
+            if let Some(message) = self.messages.pop_front() {
+                return Ok(message);
+            }
+
+            let keep_alive = *self.connect_options.keep_alive();
+            let ping_at = self.last_sent + keep_alive;
+            tokio::select! {
+                n_recv = self.stream.read_buf(self.decoder.buffer_mut()) => {
+                    if n_recv? == 0 {
+                        return Err(Error::new(
+                            ErrorKind::SocketError,
+                            "Connection closed by server",
+                        ));
+                    }
+                    self.handle_buffered_packets().await?;
+                }
+                () = sleep_until(ping_at), if !keep_alive.is_zero() => {
+                    self.send_ping().await?;
+                }
+            }
+        }
+    }
+
+    /// Send ping packet without waiting for response.
+    async fn send_ping(&mut self) -> Result<(), Error> {
         if self.status == ClientStatus::Connected {
             log::info!("Send ping packet");
             let packet = PingRequestPacket::new();
-            self.send(packet).await
+            self.send(packet).await?;
+            self.pings_sent += 1;
+            Ok(())
         } else {
             Err(Error::new(
                 ErrorKind::InvalidClientStatus,
@@ -205,80 +483,175 @@ impl ClientInnerV5 {
         }
     }
 
+    // `&self` would require client to be `Sync` for the future to be `Send`.
+    #[allow(clippy::needless_pass_by_ref_mut)]
     async fn on_connect(&mut self) -> Result<(), Error> {
         log::info!("on_connect()");
-        todo!()
+        Ok(())
     }
 
-    fn on_disconnect(&mut self) -> Result<(), Error> {
+    fn on_disconnect(&self) -> Result<(), Error> {
         log::info!("on_disconnect()");
         todo!()
     }
 
-    async fn on_message(&self, buf: &[u8]) -> Result<(), Error> {
+    async fn on_message(&mut self, packet: PublishPacket) -> Result<(), Error> {
         log::info!("on_message()");
-        let mut ba = ByteArray::new(buf);
-        let packet = PublishPacket::decode(&mut ba)?;
         log::info!("packet: {:?}", packet);
-        //if let Some(cb) = &self.on_message_cb {
-        //    cb(self, &packet);
-        //}
+        match packet.qos() {
+            QoS::AtMostOnce => (),
+            QoS::AtLeastOnce => {
+                self.send(PublishAckPacket::new(packet.packet_id())).await?;
+            }
+            QoS::ExactOnce => {
+                let is_new = self.receiving_qos2_packets.insert(packet.packet_id());
+                self.send(PublishReceivedPacket::new(packet.packet_id()))
+                    .await?;
+                if !is_new {
+                    // Resent by server before release, message is already delivered.
+                    return Ok(());
+                }
+            }
+        }
+
+        let message = PublishMessage::from(&packet);
+        if let Some((response_topic, correlation_data)) = &self.pending_request {
+            if message.topic == *response_topic
+                && message.correlation_data.as_ref() == Some(correlation_data)
+            {
+                self.response = Some(message);
+                return Ok(());
+            }
+        }
+        if let Some(cb) = &mut self.on_message_cb {
+            cb(&message);
+        } else {
+            self.messages.push_back(message);
+        }
         Ok(())
     }
 
-    async fn on_ping_resp(&self) -> Result<(), Error> {
+    async fn on_ping_resp(&mut self) -> Result<(), Error> {
         log::info!("on ping resp");
         // TODO(Shaohua): Reset reconnect timer.
+        if self.pings_received < self.pings_sent {
+            self.pings_received += 1;
+        } else {
+            log::warn!("Got ping response without request");
+        }
         Ok(())
     }
 
-    async fn connect_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    async fn connect_ack(&mut self, packet: ConnectAckPacket) -> Result<(), Error> {
         log::info!("connect_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = ConnectAckPacket::decode(&mut ba)?;
-        if packet.reason_code() == ReasonCode::Success {
+        let reason_code = packet.reason_code();
+        if reason_code == ReasonCode::Success {
             self.status = ClientStatus::Connected;
+            self.backoff.reset();
             self.on_connect().await?;
+            return Ok(());
+        }
+
+        log::warn!("Failed to connect to server, {}", reason_code.description());
+        self.status = ClientStatus::Disconnected;
+        let reconnect = self.connect_options.reconnect();
+        if reconnect.enabled() && is_retryable_reason(reason_code) {
+            self.reconnect_at = Some(self.backoff.next_reconnect(reconnect));
+            Ok(())
         } else {
-            log::warn!("Failed to connect to server, {:?}", packet.reason_code());
-            self.status = ClientStatus::Disconnected;
+            Err(Error::from_string(
+                ErrorKind::ConnectionRefused,
+                format!(
+                    "Connection refused by server, {}",
+                    reason_code.description()
+                ),
+            ))
+        }
+    }
+
+    /// Reconnect to server after connection is rejected with a retryable reason.
+    async fn reconnect(&mut self) {
+        self.reconnect_at = None;
+        log::info!("Reconnect to server");
+        if let Err(err) = self.connect().await {
+            log::warn!("Failed to reconnect to server, err: {:?}", err);
+            self.reconnect_at = Some(
+                self.backoff
+                    .next_reconnect(self.connect_options.reconnect()),
+            );
         }
-        Ok(())
     }
 
-    fn publish_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn publish_ack(&mut self, packet: &PublishAckPacket) {
         log::info!("publish_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = PublishAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
-        if let Some(p) = self.publishing_qos1_packets.get(&packet_id) {
-            log::info!("Topic `{}` publish confirmed!", p.topic());
-            self.publishing_qos1_packets.remove(&packet.packet_id());
+        if let Some(p) = self.publishing_qos1_packets.remove(&packet_id) {
+            if packet.reason_code().is_error() {
+                log::warn!("Topic `{}` publish rejected!", p.topic());
+                self.failed_publishes
+                    .insert(packet_id, packet.reason_code());
+            } else {
+                log::info!("Topic `{}` publish confirmed!", p.topic());
+            }
         } else {
             log::warn!("Failed to find PublishAckPacket: {}", packet_id);
         }
-        Ok(())
+    }
+
+    async fn publish_received(&mut self, packet: PublishReceivedPacket) -> Result<(), Error> {
+        log::info!("publish_received()");
+        let packet_id = packet.packet_id();
+        if !self.publishing_qos2_packets.contains_key(&packet_id) {
+            log::warn!("Failed to find PublishReceivedPacket: {}", packet_id);
+            return Ok(());
+        }
+        if packet.reason_code().is_error() {
+            // Publish flow ends here, without release packet.
+            self.publishing_qos2_packets.remove(&packet_id);
+            self.failed_publishes
+                .insert(packet_id, packet.reason_code());
+            Ok(())
+        } else {
+            self.send(PublishReleasePacket::new(packet_id)).await
+        }
+    }
+
+    async fn publish_release(&mut self, packet: PublishReleasePacket) -> Result<(), Error> {
+        log::info!("publish_release()");
+        let packet_id = packet.packet_id();
+        if !self.receiving_qos2_packets.remove(&packet_id) {
+            log::warn!("Failed to find PublishReleasePacket: {}", packet_id);
+        }
+        self.send(PublishCompletePacket::new(packet_id)).await
+    }
+
+    fn publish_complete(&mut self, packet: &PublishCompletePacket) {
+        log::info!("publish_complete()");
+        let packet_id = packet.packet_id();
+        if let Some(p) = self.publishing_qos2_packets.remove(&packet_id) {
+            log::info!("Topic `{}` publish completed!", p.topic());
+        } else {
+            log::warn!("Failed to find PublishCompletePacket: {}", packet_id);
+        }
     }
 
     /// Parse `packet_id` and remove from vector.
-    fn subscribe_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn subscribe_ack(&mut self, packet: &SubscribeAckPacket) {
         log::info!("subscribe_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = SubscribeAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
         if let Some(p) = self.subscribing_packets.get(&packet_id) {
             log::info!("Subscription {:?} confirmed!", p.topics());
             self.subscribing_packets.remove(&packet.packet_id());
+            if let Some(reason_code) = packet.reasons().first() {
+                self.subscribe_acks.insert(packet_id, (*reason_code).into());
+            }
         } else {
             log::warn!("Failed to find SubscribeAckPacket: {}", packet_id);
         }
-        Ok(())
     }
 
-    fn unsubscribe_ack(&mut self, buf: &[u8]) -> Result<(), Error> {
+    fn unsubscribe_ack(&mut self, packet: &UnsubscribeAckPacket) {
         log::info!("unsubscribe_ack()");
-        let mut ba = ByteArray::new(buf);
-        let packet = UnsubscribeAckPacket::decode(&mut ba)?;
         let packet_id = packet.packet_id();
         if let Some(p) = self.unsubscribing_packets.get(&packet_id) {
             log::info!("Topics {:?} unsubscribe confirmed!", p);
@@ -286,7 +659,6 @@ impl ClientInnerV5 {
         } else {
             log::warn!("Failed to find UnsubscribeAckPacket: {}", packet_id);
         }
-        Ok(())
     }
 
     fn next_packet_id(&mut self) -> PacketId {
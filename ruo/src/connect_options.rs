@@ -3,11 +3,15 @@
 // in the LICENSE file.
 
 use codec::utils::random_string;
-use codec::ProtocolLevel;
-use std::net::SocketAddr;
+use codec::{v3, v5, ProtocolLevel, QoS, U32Data};
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
+use crate::error::{Error, ErrorKind};
+use crate::reconnect::ReconnectOptions;
+
 #[derive(Clone, Debug)]
 pub struct HttpProxy {
     pub hostname: String,
@@ -42,13 +46,38 @@ pub struct SelfSignedTls {
     pub cert: PathBuf,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum TlsType {
     /// Signed by Root CA, like `Let's Encrypt`.
+    #[default]
     CASigned,
 
     /// Generated self signed ca file with `openssl` or other tools.
     SelfSigned(SelfSignedTls),
+
+    /// Do not verify server certificate, only used for testing.
+    Insecure,
+}
+
+/// Client certificate and its private key in PEM format, sent to servers which
+/// require client authentication.
+#[derive(Clone, Debug)]
+pub struct ClientCert {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Last will message of client.
+#[derive(Clone, Debug)]
+pub struct Will {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: QoS,
+    pub retain: bool,
+
+    /// Delay of publishing will message after network connection is closed,
+    /// only sent to v5 servers.
+    pub delay_interval: Duration,
 }
 
 /// Connect to tcp server.
@@ -63,6 +92,7 @@ pub struct MqttsConnect {
     pub address: SocketAddr,
     pub domain: String,
     pub tls_type: TlsType,
+    pub client_cert: Option<ClientCert>,
 }
 
 /// Connect to websocket server.
@@ -78,6 +108,7 @@ pub struct WssConnect {
     pub address: SocketAddr,
     pub domain: String,
     pub tls_type: TlsType,
+    pub client_cert: Option<ClientCert>,
     pub path: String,
 }
 
@@ -111,6 +142,106 @@ pub enum ConnectType {
     Quic(QuicConnect),
 }
 
+impl ConnectType {
+    /// Split `host:port` into host and port, brackets around ipv6 address are removed.
+    fn parse_authority(authority: &str, default_port: u16) -> Result<(&str, u16), Error> {
+        let invalid_port = || {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid port in url: {authority}"),
+            )
+        };
+        if let Some(rest) = authority.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(|| {
+                Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("Invalid ipv6 address in url: {authority}"),
+                )
+            })?;
+            let port = match rest.strip_prefix(':') {
+                Some(port) => port.parse().map_err(|_err| invalid_port())?,
+                None if rest.is_empty() => default_port,
+                None => return Err(invalid_port()),
+            };
+            return Ok((host, port));
+        }
+        match authority.split_once(':') {
+            Some((host, port)) => Ok((host, port.parse().map_err(|_err| invalid_port())?)),
+            None => Ok((authority, default_port)),
+        }
+    }
+}
+
+impl FromStr for ConnectType {
+    type Err = Error;
+
+    /// Parse url like `mqtts://broker.example.com:8883` or `ws://127.0.0.1:8083/mqtt`.
+    ///
+    /// Supported schemes are `mqtt`, `mqtts`, `ws` and `wss`, default ports are
+    /// 1883, 8883, 80 and 443. TLS connections verify server with root CAs,
+    /// see [`ConnectOptions::set_tls()`] to change it.
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = url.split_once("://").ok_or_else(|| {
+            Error::from_string(ErrorKind::ConfigError, format!("No scheme in url: {url}"))
+        })?;
+        let default_port = match scheme {
+            "mqtt" => 1883,
+            "mqtts" => 8883,
+            "ws" => 80,
+            "wss" => 443,
+            _ => {
+                return Err(Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("Unsupported scheme in url: {url}"),
+                ))
+            }
+        };
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, "/"), |index| rest.split_at(index));
+        let (host, port) = Self::parse_authority(authority, default_port)?;
+        if host.is_empty() {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("No host in url: {url}"),
+            ));
+        }
+        let address = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Failed to resolve host in url: {url}"),
+            )
+        })?;
+
+        let is_ws = matches!(scheme, "ws" | "wss");
+        if !is_ws && path != "/" {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Path is only supported by ws and wss urls: {url}"),
+            ));
+        }
+        let domain = host.to_string();
+        let path = path.to_string();
+        Ok(match scheme {
+            "mqtt" => Self::Mqtt(MqttConnect { address }),
+            "mqtts" => Self::Mqtts(MqttsConnect {
+                address,
+                domain,
+                tls_type: TlsType::default(),
+                client_cert: None,
+            }),
+            "ws" => Self::Ws(WsConnect { address, path }),
+            _ => Self::Wss(WssConnect {
+                address,
+                domain,
+                tls_type: TlsType::default(),
+                client_cert: None,
+                path,
+            }),
+        })
+    }
+}
+
 /// Options for mqtt connection.
 #[derive(Clone, Debug)]
 pub struct ConnectOptions {
@@ -149,10 +280,35 @@ pub struct ConnectOptions {
     /// Default is 10 seconds.
     connect_timeout: Duration,
 
+    /// Maximum time to wait for ping response in [`crate::client::Client::ping()`].
+    ///
+    /// Default is 10 seconds.
+    ping_timeout: Duration,
+
     /// Speicfy network proxy.
     ///
     /// Default is None.
     proxy: Proxy,
+
+    /// `QoS` of messages published without specifying `QoS` explicitly.
+    ///
+    /// Default is `QoS` 0.
+    default_qos: QoS,
+
+    /// Retain flag of messages published without specifying it explicitly.
+    ///
+    /// Default is false.
+    default_retain: bool,
+
+    /// Reconnect if connection is rejected by server with a retryable reason.
+    ///
+    /// Default is disabled.
+    reconnect: ReconnectOptions,
+
+    /// Message published by server when client is disconnected abnormally.
+    ///
+    /// Default is None.
+    will: Option<Will>,
 }
 
 impl Default for ConnectOptions {
@@ -165,8 +321,13 @@ impl Default for ConnectOptions {
             }),
             client_id,
             connect_timeout: Duration::from_secs(10),
+            ping_timeout: Duration::from_secs(10),
             keep_alive: Duration::from_secs(60),
             proxy: Proxy::None,
+            default_qos: QoS::AtMostOnce,
+            default_retain: false,
+            reconnect: ReconnectOptions::default(),
+            will: None,
         }
     }
 }
@@ -196,6 +357,35 @@ impl ConnectOptions {
         self
     }
 
+    /// Update connection type with server url, like `mqtts://broker.example.com`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if url is invalid or its host can not be resolved.
+    pub fn set_url(&mut self, url: &str) -> Result<&mut Self, Error> {
+        self.connect_type = url.parse()?;
+        Ok(self)
+    }
+
+    /// Update how to verify server and client certificate of mqtts and wss connections.
+    ///
+    /// Other connection types are not affected, so call this after updating
+    /// connection type.
+    pub fn set_tls(&mut self, tls_type: TlsType, client_cert: Option<ClientCert>) -> &mut Self {
+        match &mut self.connect_type {
+            ConnectType::Mqtts(connect) => {
+                connect.tls_type = tls_type;
+                connect.client_cert = client_cert;
+            }
+            ConnectType::Wss(connect) => {
+                connect.tls_type = tls_type;
+                connect.client_cert = client_cert;
+            }
+            _ => log::warn!("TLS options are ignored by {:?}", self.connect_type),
+        }
+        self
+    }
+
     /// Get current connection type.
     #[must_use]
     pub const fn connect_type(&self) -> &ConnectType {
@@ -226,6 +416,18 @@ impl ConnectOptions {
         &self.connect_timeout
     }
 
+    /// Update timeout of ping response.
+    pub fn set_ping_timeout(&mut self, ping_timeout: Duration) -> &mut Self {
+        self.ping_timeout = ping_timeout;
+        self
+    }
+
+    /// Get current timeout of ping response.
+    #[must_use]
+    pub const fn ping_timeout(&self) -> &Duration {
+        &self.ping_timeout
+    }
+
     /// Update keep alive value of network connection.
     pub fn set_keepalive(&mut self, keep_alive: Duration) -> &mut Self {
         self.keep_alive = keep_alive;
@@ -250,5 +452,216 @@ impl ConnectOptions {
         &self.proxy
     }
 
+    /// Update default `QoS` of published messages.
+    pub fn set_default_qos(&mut self, qos: QoS) -> &mut Self {
+        self.default_qos = qos;
+        self
+    }
+
+    /// Get current default `QoS` of published messages.
+    #[must_use]
+    pub const fn default_qos(&self) -> QoS {
+        self.default_qos
+    }
+
+    /// Update default retain flag of published messages.
+    pub fn set_default_retain(&mut self, retain: bool) -> &mut Self {
+        self.default_retain = retain;
+        self
+    }
+
+    /// Get current default retain flag of published messages.
+    #[must_use]
+    pub const fn default_retain(&self) -> bool {
+        self.default_retain
+    }
+
+    /// Update reconnect options.
+    pub fn set_reconnect(&mut self, reconnect: ReconnectOptions) -> &mut Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Get current reconnect options.
+    #[must_use]
+    pub const fn reconnect(&self) -> &ReconnectOptions {
+        &self.reconnect
+    }
+
+    /// Update last will message, which is published by server if client is
+    /// disconnected without sending a disconnect packet.
+    pub fn set_will(&mut self, topic: &str, payload: &[u8], qos: QoS, retain: bool) -> &mut Self {
+        self.will = Some(Will {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            qos,
+            retain,
+            delay_interval: Duration::ZERO,
+        });
+        self
+    }
+
+    /// Update delay of publishing last will message, only sent to v5 servers.
+    ///
+    /// It is ignored if last will message is not set.
+    pub fn set_will_delay_interval(&mut self, delay_interval: Duration) -> &mut Self {
+        if let Some(will) = &mut self.will {
+            will.delay_interval = delay_interval;
+        } else {
+            log::warn!("Will delay interval is ignored as no will message is set");
+        }
+        self
+    }
+
+    /// Remove last will message.
+    pub fn clear_will(&mut self) -> &mut Self {
+        self.will = None;
+        self
+    }
+
+    /// Get current last will message.
+    #[must_use]
+    pub const fn will(&self) -> Option<&Will> {
+        self.will.as_ref()
+    }
+
+    /// Create v3 connect packet with client id and will message.
+    pub(crate) fn connect_packet_v3(&self) -> Result<v3::ConnectPacket, Error> {
+        let mut packet = v3::ConnectPacket::new(&self.client_id)?;
+        if let Some(will) = &self.will {
+            let mut flags = packet.connect_flags().clone();
+            flags
+                .set_will(true)
+                .set_will_qos(will.qos)
+                .set_will_retain(will.retain);
+            packet.set_connect_flags(flags);
+            packet
+                .set_will_topic(&will.topic)?
+                .set_will_message(&will.payload)?;
+        }
+        Ok(packet)
+    }
+
+    /// Create v5 connect packet with client id and will message.
+    pub(crate) fn connect_packet_v5(&self) -> Result<v5::ConnectPacket, Error> {
+        let mut packet = v5::ConnectPacket::new(&self.client_id)?;
+        if let Some(will) = &self.will {
+            packet
+                .set_will(true)
+                .set_will_qos(will.qos)
+                .set_will_retain(will.retain);
+            packet
+                .set_will_topic(&will.topic)?
+                .set_will_message(&will.payload)?;
+            if !will.delay_interval.is_zero() {
+                let delay_interval =
+                    u32::try_from(will.delay_interval.as_secs()).unwrap_or(u32::MAX);
+                packet
+                    .will_properties_mut()
+                    .push(v5::Property::WillDelayInterval(U32Data::new(
+                        delay_interval,
+                    )))?;
+            }
+        }
+        Ok(packet)
+    }
+
     // TODO(Shaohua): Add authentication options
 }
+
+#[cfg(test)]
+mod tests {
+    use codec::{v5, QoS, U32Data};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use super::{ConnectOptions, ConnectType, TlsType};
+
+    fn address(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let connect_type: ConnectType = "mqtt://127.0.0.1".parse().unwrap();
+        assert!(
+            matches!(connect_type, ConnectType::Mqtt(c) if c.address == address("127.0.0.1:1883"))
+        );
+
+        let connect_type: ConnectType = "mqtts://localhost:18883".parse().unwrap();
+        let ConnectType::Mqtts(connect) = connect_type else {
+            panic!("Expected mqtts");
+        };
+        assert_eq!(connect.address.port(), 18883);
+        assert!(connect.address.ip().is_loopback());
+        assert_eq!(connect.domain, "localhost");
+        assert!(matches!(connect.tls_type, TlsType::CASigned));
+        assert!(connect.client_cert.is_none());
+
+        let connect_type: ConnectType = "ws://[::1]:8083/mqtt".parse().unwrap();
+        assert!(matches!(connect_type, ConnectType::Ws(c)
+            if c.address == address("[::1]:8083") && c.path == "/mqtt"));
+
+        let connect_type: ConnectType = "wss://127.0.0.1".parse().unwrap();
+        let ConnectType::Wss(connect) = connect_type else {
+            panic!("Expected wss");
+        };
+        assert_eq!(connect.address, address("127.0.0.1:443"));
+        assert_eq!(connect.domain, "127.0.0.1");
+        assert_eq!(connect.path, "/");
+
+        for url in [
+            "127.0.0.1:1883",
+            "tcp://127.0.0.1:1883",
+            "mqtt://127.0.0.1:port",
+            "mqtt://:1883",
+            "mqtt://[::1:1883",
+            "mqtt://127.0.0.1:1883/mqtt",
+        ] {
+            assert!(url.parse::<ConnectType>().is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_set_tls() {
+        let mut options = ConnectOptions::new();
+        options
+            .set_url("wss://127.0.0.1:8084/mqtt")
+            .unwrap()
+            .set_tls(TlsType::Insecure, None);
+        assert!(matches!(
+            options.connect_type(),
+            ConnectType::Wss(c) if matches!(c.tls_type, TlsType::Insecure)
+        ));
+    }
+
+    #[test]
+    fn test_will() {
+        let mut options = ConnectOptions::new();
+        let packet = options.connect_packet_v3().unwrap();
+        assert!(!packet.connect_flags().will());
+
+        options
+            .set_will("will/ruo", b"offline", QoS::AtLeastOnce, true)
+            .set_will_delay_interval(Duration::from_secs(5));
+        let packet = options.connect_packet_v3().unwrap();
+        assert!(packet.connect_flags().will());
+        assert_eq!(packet.connect_flags().will_qos(), QoS::AtLeastOnce);
+        assert!(packet.connect_flags().will_retain());
+        assert_eq!(packet.will_topic(), Some("will/ruo"));
+        assert_eq!(packet.will_message(), b"offline");
+
+        let packet = options.connect_packet_v5().unwrap();
+        assert!(packet.will());
+        assert_eq!(packet.will_topic(), Some("will/ruo"));
+        assert_eq!(
+            packet.will_properties().props(),
+            &[v5::Property::WillDelayInterval(U32Data::new(5))]
+        );
+
+        options.set_will("will/ruo/#", b"offline", QoS::AtMostOnce, false);
+        assert!(options.connect_packet_v3().is_err());
+        options.clear_will();
+        assert!(options.connect_packet_v5().is_ok());
+    }
+}
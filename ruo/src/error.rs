@@ -41,6 +41,18 @@ pub enum ErrorKind {
 
     /// Auth failed while connecting to server.
     AuthFailed,
+
+    /// Connection is refused by server with a reason which is not retryable.
+    ConnectionRefused,
+
+    /// Publish packet is rejected by server with an error reason code.
+    PublishRejected,
+
+    /// Subscription is rejected by server.
+    SubscribeRejected,
+
+    /// No response from server in time.
+    TimeoutError,
 }
 
 #[derive(Debug, Clone)]
@@ -65,6 +77,11 @@ impl Error {
     pub const fn from_string(kind: ErrorKind, message: String) -> Self {
         Self { kind, message }
     }
+
+    #[must_use]
+    pub const fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
 }
 
 impl Display for Error {
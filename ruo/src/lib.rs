@@ -18,14 +18,17 @@ mod client_inner_v5;
 pub mod connect_options;
 pub mod error;
 mod publish;
+pub mod reconnect;
 mod status;
 pub mod stream;
+mod subscribe_ack;
 
 #[cfg(feature = "blocking")]
 pub mod blocking;
 
 pub use publish::PublishMessage;
 pub use status::ClientStatus;
+pub use subscribe_ack::SubscribeAck;
 
 pub(crate) use client_inner_v3::ClientInnerV3;
 pub(crate) type ClientInnerV4 = ClientInnerV3;
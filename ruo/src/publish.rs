@@ -2,7 +2,7 @@
 // Use of this source is governed by Lesser General Public License that can be found
 // in the LICENSE file.
 
-use codec::QoS;
+use codec::{v3, v5, QoS};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone)]
@@ -10,4 +10,50 @@ pub struct PublishMessage {
     pub topic: String,
     pub qos: QoS,
     pub payload: Vec<u8>,
+
+    /// Topic to publish response to, only sent by v5 clients.
+    pub response_topic: Option<String>,
+
+    /// Identify which request the response is for, only sent by v5 clients.
+    pub correlation_data: Option<Vec<u8>>,
+}
+
+impl From<&v3::PublishPacket> for PublishMessage {
+    fn from(packet: &v3::PublishPacket) -> Self {
+        Self {
+            topic: packet.topic().to_string(),
+            qos: packet.qos(),
+            payload: packet.message().to_vec(),
+            response_topic: None,
+            correlation_data: None,
+        }
+    }
 }
+
+impl From<&v5::PublishPacket> for PublishMessage {
+    fn from(packet: &v5::PublishPacket) -> Self {
+        let mut response_topic = None;
+        let mut correlation_data = None;
+        for property in packet.properties().props() {
+            match property {
+                v5::Property::ResponseTopic(topic) => {
+                    response_topic = Some(topic.as_ref().to_string());
+                }
+                v5::Property::CorrelationData(data) => {
+                    correlation_data = Some(data.as_ref().to_vec());
+                }
+                _ => (),
+            }
+        }
+        Self {
+            topic: packet.topic().to_string(),
+            qos: packet.qos(),
+            payload: packet.message().to_vec(),
+            response_topic,
+            correlation_data,
+        }
+    }
+}
+
+/// Called on each message from server, instead of queuing it for `next_message()`.
+pub type MessageCallback = Box<dyn FnMut(&PublishMessage) + Send>;
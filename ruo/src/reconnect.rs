@@ -0,0 +1,164 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by Lesser General Public License that can be found
+// in the LICENSE file.
+
+//! Reconnect after connection is rejected by server.
+
+use codec::{v3, v5};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Options to reconnect when server rejects connection with a retryable reason,
+/// like server busy or quota exceeded.
+///
+/// Connection rejected with other reasons, like bad user name or password,
+/// is never retried.
+#[derive(Clone, Debug)]
+pub struct ReconnectOptions {
+    /// Reconnect automatically.
+    ///
+    /// Default is false.
+    enabled: bool,
+
+    /// Delay before first reconnection, doubled on each consecutive failure.
+    ///
+    /// Default is 1 second.
+    retry_interval: Duration,
+
+    /// Maximum delay before reconnection.
+    ///
+    /// Default is 60 seconds.
+    maximum_retry_interval: Duration,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            retry_interval: Duration::from_secs(1),
+            maximum_retry_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectOptions {
+    /// Create a `ReconnectOptions` object with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable reconnection.
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Get whether reconnection is enabled.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Update delay before first reconnection.
+    pub fn set_retry_interval(&mut self, retry_interval: Duration) -> &mut Self {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    /// Get delay before first reconnection.
+    #[must_use]
+    pub const fn retry_interval(&self) -> Duration {
+        self.retry_interval
+    }
+
+    /// Update maximum delay before reconnection.
+    pub fn set_maximum_retry_interval(&mut self, maximum_retry_interval: Duration) -> &mut Self {
+        self.maximum_retry_interval = maximum_retry_interval;
+        self
+    }
+
+    /// Get maximum delay before reconnection.
+    #[must_use]
+    pub const fn maximum_retry_interval(&self) -> Duration {
+        self.maximum_retry_interval
+    }
+}
+
+/// Returns true if server may accept the same connect request later.
+#[must_use]
+pub const fn is_retryable_reason(reason_code: v5::ReasonCode) -> bool {
+    matches!(
+        reason_code,
+        v5::ReasonCode::ServerUnavailable
+            | v5::ReasonCode::ServerBusy
+            | v5::ReasonCode::QuotaExceeded
+            | v5::ReasonCode::ConnectionRateExceeded
+    )
+}
+
+/// Returns true if server may accept the same connect request later.
+#[must_use]
+pub const fn is_retryable_return_code(return_code: v3::ConnectReturnCode) -> bool {
+    matches!(return_code, v3::ConnectReturnCode::ServerUnavailable)
+}
+
+/// Exponential delay between reconnections.
+#[derive(Debug, Clone)]
+pub(crate) struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self { failures: 0 }
+    }
+
+    /// Reset delay after connection is accepted.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Get instant of next reconnection.
+    pub fn next_reconnect(&mut self, options: &ReconnectOptions) -> Instant {
+        let delay = options
+            .retry_interval
+            .saturating_mul(2_u32.saturating_pow(self.failures))
+            .min(options.maximum_retry_interval);
+        self.failures = self.failures.saturating_add(1);
+        Instant::now() + delay
+    }
+}
+
+/// Wait until `reconnect_at`, or forever if no reconnection is scheduled.
+pub(crate) async fn reconnect_timer(reconnect_at: Option<Instant>) {
+    match reconnect_at {
+        Some(instant) => tokio::time::sleep_until(instant).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::{v3, v5};
+
+    use super::{is_retryable_reason, is_retryable_return_code};
+
+    #[test]
+    fn test_retryable_reasons() {
+        assert!(is_retryable_reason(v5::ReasonCode::ServerBusy));
+        assert!(is_retryable_reason(v5::ReasonCode::QuotaExceeded));
+        assert!(!is_retryable_reason(
+            v5::ReasonCode::ClientIdentifierNotValid
+        ));
+        assert!(!is_retryable_reason(v5::ReasonCode::BadUserNameOrPassword));
+        assert!(!is_retryable_reason(v5::ReasonCode::NotAuthorized));
+
+        assert!(is_retryable_return_code(
+            v3::ConnectReturnCode::ServerUnavailable
+        ));
+        assert!(!is_retryable_return_code(
+            v3::ConnectReturnCode::MalformedUsernamePassword
+        ));
+    }
+}
@@ -7,21 +7,43 @@ use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 #[cfg(unix)]
 use tokio::net::UnixStream;
 use tokio_rustls::client::TlsStream;
-use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use tokio_rustls::rustls::{self, Certificate, PrivateKey, ServerName};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
 use tokio_tungstenite::{self, tungstenite::protocol::Message, WebSocketStream};
 
 #[cfg(unix)]
 use crate::connect_options::UdsConnect;
 use crate::connect_options::{
-    ConnectType, MqttConnect, MqttsConnect, QuicConnect, TlsType, WsConnect, WssConnect,
+    ClientCert, ConnectType, MqttConnect, MqttsConnect, QuicConnect, TlsType, WsConnect, WssConnect,
 };
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
+
+/// Accept any server certificate, used by `TlsType::Insecure`.
+struct NoServerVerifier;
+
+impl ServerCertVerifier for NoServerVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
 
 pub enum Stream {
     Mqtt(TcpStream),
@@ -72,17 +94,69 @@ impl Stream {
         Ok(Self::Mqtt(tcp_stream))
     }
 
-    async fn new_tls_stream(
+    fn load_pem_items(path: &Path) -> Result<Vec<rustls_pemfile::Item>, Error> {
+        let mut pem_buf = BufReader::new(File::open(path)?);
+        rustls_pemfile::read_all(&mut pem_buf).map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid PEM file {}, err: {err:?}", path.display()),
+            )
+        })
+    }
+
+    fn load_certs(path: &Path) -> Result<Vec<Certificate>, Error> {
+        let certs: Vec<Certificate> = Self::load_pem_items(path)?
+            .into_iter()
+            .filter_map(|item| match item {
+                rustls_pemfile::Item::X509Certificate(cert) => Some(Certificate(cert)),
+                _ => None,
+            })
+            .collect();
+        if certs.is_empty() {
+            return Err(Error::from_string(
+                ErrorKind::ConfigError,
+                format!("No certificate found in {}", path.display()),
+            ));
+        }
+        Ok(certs)
+    }
+
+    fn load_key(path: &Path) -> Result<PrivateKey, Error> {
+        Self::load_pem_items(path)?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                Error::from_string(
+                    ErrorKind::ConfigError,
+                    format!("No private key found in {}", path.display()),
+                )
+            })
+    }
+
+    fn new_tls_config(
         tls_type: &TlsType,
-        server_address: &SocketAddr,
-        server_domain: &str,
-    ) -> Result<TlsStream<TcpStream>, Error> {
+        client_cert: Option<&ClientCert>,
+    ) -> Result<rustls::ClientConfig, Error> {
         let mut root_store = rustls::RootCertStore::empty();
         match tls_type {
             TlsType::SelfSigned(self_signed) => {
-                let mut pem_buf = BufReader::new(File::open(&self_signed.cert)?);
-                let pem_data = rustls_pemfile::certs(&mut pem_buf).unwrap();
-                root_store.add_parsable_certificates(&pem_data);
+                let certs = Self::load_certs(&self_signed.cert)?;
+                for cert in &certs {
+                    root_store.add(cert).map_err(|err| {
+                        Error::from_string(
+                            ErrorKind::ConfigError,
+                            format!(
+                                "Invalid CA file {}, err: {err:?}",
+                                self_signed.cert.display()
+                            ),
+                        )
+                    })?;
+                }
             }
             TlsType::CASigned => {
                 root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
@@ -93,29 +167,68 @@ impl Stream {
                     )
                 }));
             }
+            TlsType::Insecure => (),
         }
+        let verifier: Arc<dyn ServerCertVerifier> = if matches!(tls_type, TlsType::Insecure) {
+            Arc::new(NoServerVerifier)
+        } else {
+            Arc::new(WebPkiVerifier::new(root_store, None))
+        };
         let config_builder = rustls::ClientConfig::builder()
             .with_safe_defaults()
-            .with_root_certificates(root_store);
-        let client_config: rustls::ClientConfig = config_builder.with_no_client_auth();
-        let rc_client_config = Arc::new(client_config);
-        let connector = tokio_rustls::TlsConnector::from(rc_client_config);
-        let tcp_stream = TcpStream::connect(server_address).await?;
-        // TODO(Shaohua): Convert error type.
-        let domain = rustls::ServerName::try_from(server_domain).unwrap();
+            .with_custom_certificate_verifier(verifier);
 
-        // TODO(Shaohua): Convert error type.
-        let tls_stream = connector
-            .connect(domain, tcp_stream)
-            .await
-            .expect("Invalid connector");
+        match client_cert {
+            Some(client_cert) => {
+                let certs = Self::load_certs(&client_cert.cert)?;
+                let key = Self::load_key(&client_cert.key)?;
+                config_builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|err| {
+                        Error::from_string(
+                            ErrorKind::ConfigError,
+                            format!("Invalid client certificate, err: {err:?}"),
+                        )
+                    })
+            }
+            None => Ok(config_builder.with_no_client_auth()),
+        }
+    }
 
+    async fn new_tls_stream(
+        tls_type: &TlsType,
+        client_cert: Option<&ClientCert>,
+        server_address: &SocketAddr,
+        server_domain: &str,
+    ) -> Result<TlsStream<TcpStream>, Error> {
+        let client_config = Self::new_tls_config(tls_type, client_cert)?;
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let domain = ServerName::try_from(server_domain).map_err(|err| {
+            Error::from_string(
+                ErrorKind::ConfigError,
+                format!("Invalid server domain {server_domain:?}, err: {err:?}"),
+            )
+        })?;
+        let tcp_stream = TcpStream::connect(server_address).await?;
+        let tls_stream = connector.connect(domain, tcp_stream).await?;
         Ok(tls_stream)
     }
 
+    /// Websocket handshake request with mqtt subprotocol.
+    fn new_ws_request(
+        ws_url: &str,
+    ) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request, Error> {
+        let mut request = ws_url.into_client_request()?;
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("mqtt"));
+        Ok(request)
+    }
+
     async fn new_mqtts(mqtts_connect: &MqttsConnect) -> Result<Self, Error> {
         let tls_stream = Self::new_tls_stream(
             &mqtts_connect.tls_type,
+            mqtts_connect.client_cert.as_ref(),
             &mqtts_connect.address,
             &mqtts_connect.domain,
         )
@@ -125,20 +238,23 @@ impl Stream {
 
     async fn new_ws(ws_connect: &WsConnect) -> Result<Self, Error> {
         let ws_url = format!("ws://{}{}", ws_connect.address, &ws_connect.path);
+        let request = Self::new_ws_request(&ws_url)?;
         let tcp_stream = TcpStream::connect(ws_connect.address).await?;
-        let (ws_stream, _) = tokio_tungstenite::client_async(ws_url, tcp_stream).await?;
+        let (ws_stream, _) = tokio_tungstenite::client_async(request, tcp_stream).await?;
         Ok(Self::Ws(Box::new(ws_stream)))
     }
 
     async fn new_wss(wss_connect: &WssConnect) -> Result<Self, Error> {
         let tls_stream = Self::new_tls_stream(
             &wss_connect.tls_type,
+            wss_connect.client_cert.as_ref(),
             &wss_connect.address,
             &wss_connect.domain,
         )
         .await?;
-        let ws_url = format!("ws://{}{}", wss_connect.address, &wss_connect.path);
-        let (ws_stream, _) = tokio_tungstenite::client_async(ws_url, tls_stream).await?;
+        let ws_url = format!("wss://{}{}", wss_connect.domain, &wss_connect.path);
+        let request = Self::new_ws_request(&ws_url)?;
+        let (ws_stream, _) = tokio_tungstenite::client_async(request, tls_stream).await?;
         Ok(Self::Wss(Box::new(ws_stream)))
     }
 
@@ -183,7 +299,7 @@ impl Stream {
     pub async fn read_buf(&mut self, buf: &mut Vec<u8>) -> Result<usize, Error> {
         match self {
             Self::Mqtt(tcp_stream) => Ok(tcp_stream.read_buf(buf).await?),
-            Self::Mqtts(tls_stream) => Ok(tls_stream.read(buf).await?),
+            Self::Mqtts(tls_stream) => Ok(tls_stream.read_buf(buf).await?),
             Self::Ws(ref mut ws_stream) => {
                 if let Some(msg) = ws_stream.next().await {
                     let msg = msg?;
@@ -0,0 +1,40 @@
+// Copyright (c) 2024 Xu Shaohua <shaohua@biofan.org>. All rights reserved.
+// Use of this source is governed by Lesser General Public License that can be found
+// in the LICENSE file.
+
+use codec::v5::ReasonCode;
+use codec::{v3, QoS};
+
+/// Result of a subscription, sent by server in subscribe ack packet.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscribeAck {
+    /// Subscription is accepted, with maximum `QoS` granted by server.
+    Granted(QoS),
+
+    /// Subscription is rejected.
+    ///
+    /// Servers of mqtt 3.1 and 3.1.1 do not send the reason, which is reported
+    /// as `ReasonCode::UnspecifiedError`.
+    Rejected(ReasonCode),
+}
+
+impl From<v3::SubscribeAck> for SubscribeAck {
+    fn from(ack: v3::SubscribeAck) -> Self {
+        match ack {
+            v3::SubscribeAck::QoS(qos) => Self::Granted(qos),
+            v3::SubscribeAck::Failed => Self::Rejected(ReasonCode::UnspecifiedError),
+        }
+    }
+}
+
+impl From<ReasonCode> for SubscribeAck {
+    fn from(reason_code: ReasonCode) -> Self {
+        match reason_code {
+            ReasonCode::Success => Self::Granted(QoS::AtMostOnce),
+            ReasonCode::GrantedQoS1 => Self::Granted(QoS::AtLeastOnce),
+            ReasonCode::GrantedQoS2 => Self::Granted(QoS::ExactOnce),
+            _ => Self::Rejected(reason_code),
+        }
+    }
+}